@@ -0,0 +1,48 @@
+
+//! Solve a problem built in Rust, without going through SMT-LIB text.
+//!
+//! `f(a) = b`, `f(b) = a`, `f(f(f(a))) != b`, `a = b` is unsat.
+//!
+//! Run with `cargo run --example embed`.
+
+use batsmt::{prelude::*, ast::HManager};
+
+fn main() {
+    let mut d = Driver::new(DriverConfig::default());
+    let (b, ty, f, a, b_) = {
+        let c = d.ctx_mut();
+        let b = c.b.clone();
+        let ty = c.m.mk_str("U", None);
+        let f = c.m.mk_str("f", None);
+        let a = c.m.mk_str("a", Some(ty));
+        let b_ = c.m.mk_str("b", Some(ty));
+        (b, ty, f, a, b_)
+    };
+
+    let assert = |d: &mut Driver, t: AST| {
+        match d.execute(Statement::Assert(t)) {
+            Response::Success => (),
+            r => panic!("cannot assert {:?}: {:?}", t, r),
+        }
+    };
+
+    let c = d.ctx_mut();
+    let app = |m: &mut HManager<_>, t: AST| m.mk_app(f, &[t], Some(ty));
+    let fa = app(&mut c.m, a);
+    let fb = app(&mut c.m, b_);
+    let ffa = app(&mut c.m, fa);
+    let fffa = app(&mut c.m, ffa);
+    let eqs = [(fa, b_), (fb, a), (a, b_)].iter()
+        .map(|&(t, u)| c.m.mk_app(b.eq, &[t, u], Some(b.bool_)))
+        .collect::<Vec<_>>();
+    let eq_fffa_b = c.m.mk_app(b.eq, &[fffa, b_], Some(b.bool_));
+    let goal = c.m.mk_app(b.not_, &[eq_fffa_b], Some(b.bool_));
+
+    for t in eqs.into_iter().chain(Some(goal)) {
+        assert(&mut d, t);
+    }
+    match d.execute(Statement::CheckSat) {
+        Response::Unsat => println!("unsat"),
+        r => panic!("expected unsat, got {:?}", r),
+    }
+}
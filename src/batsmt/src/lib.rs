@@ -0,0 +1,81 @@
+
+//! Batsmt, as a library.
+//!
+//! This crate re-exports what is needed to embed the solver, so that
+//! users depend on it rather than on the individual `batsmt-*` crates,
+//! whose organization may change.
+//!
+//! - `ast`: terms, and the manager that allocates them;
+//! - `solve`: the SAT solver, the theory of equality, the clausifier,
+//!   and (with the `driver` feature) the driver that glues them together;
+//! - `parse` (with the `parser` feature): the SMT-LIB parser;
+//! - `prelude`: the most common items, to be imported with `*`.
+//!
+//! See `examples/embed.rs` for a problem built without SMT-LIB text.
+//!
+//! ```
+//! use batsmt::prelude::*;
+//!
+//! let mut d = Driver::new(DriverConfig::default());
+//! let r = d.execute_script("(declare-fun p () Bool) (assert p) (check-sat)".as_bytes());
+//! assert_eq!(r.last(), Some(&Response::Sat));
+//! ```
+
+/// Terms, and the manager that allocates them.
+pub mod ast {
+    pub use batsmt_core::{
+        ast::{Manager, HasManager, View, iter_dag, map_dag, AstMap, AstSet},
+        ast_u32::{AST, HashMap, HashSet},
+        attr::{AttrKey, AttrValue},
+        match_term,
+    };
+    pub use batsmt_hast::{HManager, StrSymbolManager};
+
+    #[cfg(feature="driver")]
+    pub use batsmt_run::{M, Ctx, Builtins};
+}
+
+/// Solving: the SAT solver, theories, and the Tseitin transformation.
+pub mod solve {
+    pub use batsmt_solver::{
+        Solver, Res, BLit, ReasonUnknown, RestartPolicy, CancelToken, ClauseKind,
+    };
+    pub use batsmt_theory::{Theory, Actions, BoolLit, Ctx as TheoryCtx};
+    pub use batsmt_cc::{CCTheory, CC, Ctx as CCCtx};
+    pub use batsmt_tseitin::{Tseitin, Ctx as FormulaCtx, View as FormulaView};
+
+    #[cfg(feature="driver")]
+    pub use batsmt_run::{
+        Driver, DriverConfig, Response, Model, Preprocessor,
+        driver::Th as DefaultTheory,
+    };
+}
+
+/// Parsing SMT-LIB.
+#[cfg(feature="parser")]
+pub mod parse {
+    pub use batsmt_parser::{
+        parse, parse_str, parse_with_spans, Statement, Span, Atom,
+        TermBuilder, SortBuilder, Error, Result, simple_ast,
+    };
+}
+
+/// The most common items.
+pub mod prelude {
+    pub use crate::{
+        Error,
+        ast::{AST, Manager},
+        solve::Res,
+    };
+
+    #[cfg(feature="driver")]
+    pub use crate::{
+        ast::Ctx,
+        solve::{Driver, DriverConfig, Response},
+    };
+
+    #[cfg(feature="parser")]
+    pub use crate::parse::Statement;
+}
+
+pub use batsmt_core::Error;
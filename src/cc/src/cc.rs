@@ -7,18 +7,31 @@
 // TODO(perf): backtrackable array allocator for signatures
 
 use {
-    std::{ u32, ptr, hash::Hash, fmt::Debug, marker::PhantomData, },
-    batsmt_core::{backtrack, },
-    fxhash::FxHashMap,
+    std::{ u32, ptr, io, fmt, hash::Hash, fmt::Debug, marker::PhantomData, time::Duration, },
+    batsmt_core::{backtrack, mem::{self, MemTree, ReportMemory}, debug_assert_levels_in_sync, },
+    fxhash::{FxHashMap, FxHashSet},
     batsmt_pretty as pp,
-    crate::{ Ctx, Actions, CCInterface, CCView, SVec, pp_t, },
+    batsmt_theory::{BoolLit, TheoryLit, ClauseKind},
+    crate::{
+        Ctx, Actions, CCInterface, CCView, CcError, SVec, ExplVec, PropVec, ConflVec,
+        pp_t, view_cc, view_apply_chain, check_sorts, },
 };
 
 enum TraverseTask<AST> {
     Enter(AST),
-    Exit(AST,NodeID)
+    Exit(AST)
 }
 
+// `guard => a1=b1 ∨ … ∨ an=bn`, or just the disjunction if there is no `guard`
+struct EqClause {
+    guard: Option<NodeID>,
+    eqs: Vec<(NodeID,NodeID)>,
+    watch: [u32; 2], // indices of the watched equalities (the same one if `n=1`)
+}
+
+/// Watch on the class of the guard of an `EqClause`, rather than on an equality.
+const WATCH_GUARD: u32 = u32::MAX;
+
 /// The congruence closure.
 pub struct CC<C:Ctx, Th: MicroTheory<C> = ()> {
     n_true: NodeID,
@@ -26,14 +39,35 @@ pub struct CC<C:Ctx, Th: MicroTheory<C> = ()> {
     th: Th,
     pending: Vec<NodeID>, // update signatures
     combine: Vec<(NodeID,NodeID,Expl<C::B>)>, // merge
-    undo: backtrack::Stack<UndoOp>,
+    undo: backtrack::Stack<UndoOp<C::B>>,
     expl_st: Vec<Expl<C::B>>, // to expand explanations
     tmp_sig: Signature<C::Fun>, // for computing signatures
     traverse: Vec<TraverseTask<C::AST>>, // for adding terms
+    traverse_open: FxHashSet<C::AST>, // terms entered but not exited yet, to detect cycles
     sig_tbl: backtrack::HashMap<Signature<C::Fun>, NodeID>,
+    propagate: bool, // propagate literals?
+    props: backtrack::Stack<Propagation<C::B>>, // propagations so far
+    props_sent: usize, // prefix of `props` already given to the SAT solver
+    prop_expl: backtrack::HashMap<C::B, (NodeID,NodeID)>, // `lit -> (t, true/false)`
+    priority: Option<Box<MergePriority<C>>>, // choose representatives
+    ext: Option<Box<MkEq<C>>>, // build equations for extensionality lemmas
+    ext_done: FxHashSet<C::AST>, // equations of extensionality lemmas added so far
+    antisym: FxHashSet<C::AST>, // antisymmetric predicates
+    antisym_apps: backtrack::Stack<NodeID>, // binary applications of antisymmetric predicates
+    eq_clauses: backtrack::Stack<EqClause>, // disjunctions of equalities
+    eq_moves: backtrack::Stack<(u32,usize,u32)>, // `(clause, slot, old watch)`
+    eq_woken: Vec<(u32,u32)>, // `(clause, watch)` whose classes changed
     cc1: CC1<C>,
 }
 
+/// User-provided priority of a term for becoming the representative of its class.
+pub type MergePriority<C> = dyn Fn(&C, &<C as batsmt_core::ast::Manager>::AST) -> i32;
+
+/// User-provided builder of the equation `a = b`, for extensionality lemmas.
+pub type MkEq<C> = dyn Fn(
+    &mut C, &<C as batsmt_core::ast::Manager>::AST, &<C as batsmt_core::ast::Manager>::AST
+) -> <C as batsmt_core::ast::Manager>::AST;
+
 /// Argument passed to micro theories
 pub struct MicroTheoryArg<'a, C:Ctx> {
     pub n_true: NodeID,
@@ -61,6 +95,11 @@ pub trait MicroTheory<C:Ctx> : backtrack::Backtrackable<C> {
 
     /// Called with all terms whose signature is to be updated
     fn on_sig_update(&mut self, c: &mut C, acts: &mut MicroTheoryArg<C>, t: &C::AST, n: NodeID) {}
+
+    /// Does this theory never merge anything nor raise conflicts?
+    ///
+    /// Conflicts of `CC` can then be checked with `NaiveCC`.
+    fn is_empty() -> bool { false }
 }
 
 /// Implement `MicroTheory` for a tuple of types themselves micro-theories.
@@ -102,6 +141,8 @@ macro_rules! impl_micro_theory_tuple {
                 let ($( $t ,)*) = self;
                 $( $t.on_sig_update(c, acts, t, n); )*
             }
+
+            fn is_empty() -> bool { $( $t::is_empty() )&&* }
         }
 
         impl_micro_theory_tuple_peel!{ $($t,)* }
@@ -116,6 +157,7 @@ impl_micro_theory_tuple! { T0, T1, T2, T3, T4, T5, T6, T7, T8, }
 
 impl<C:Ctx> MicroTheory<C> for () {
     fn init(_m: &mut C) -> Self { () }
+    fn is_empty() -> bool { true }
 }
 
 /// internal state, with just the core structure for nodes and parent sets
@@ -123,9 +165,12 @@ pub struct CC1<C:Ctx> {
     ok: bool, // no conflict?
     alloc_parent_list: ListAlloc<NodeID>,
     alloc_lit_list: ListAlloc<(NodeID,C::B)>,
+    alloc_watch_list: ListAlloc<(u32,u32)>,
     nodes: Nodes<C>,
     confl: Vec<C::B>, // local for conflict
     tmp_expl: Vec<NodeID>,
+    apply: Option<C::AST>, // application symbol of curried terms
+    stats: Stats, // time per region, with `metrics`
 }
 
 /// Unique Node ID.
@@ -151,6 +196,7 @@ pub type Node<C:Ctx> = NodeDef<C::AST, C::B>;
 /// - a direct pointer to the class' root
 /// - pointer to next element of the class (circular list)
 /// - a linked list of parent terms
+/// - a linked list of watches of eq-clauses on the class
 /// - the proof forest pointer
 #[derive(Clone)]
 pub struct NodeDef<AST, B> where AST : Sized, B : Sized {
@@ -160,6 +206,8 @@ pub struct NodeDef<AST, B> where AST : Sized, B : Sized {
     expl: Option<(NodeID, Expl<B>)>, // proof forest //TODO: use allocator?
     root: NodeID, // current representative (initially, itself)
     parents: List<NodeID>,
+    watches: List<(u32,u32)>, // `(clause, watch)` of eq-clauses
+    lit: Option<B>, // literal for this term, if any
     flags: u8, // boolean flags
 }
 
@@ -183,20 +231,210 @@ pub enum Expl<B> {
     Axiom, // trivial explanation
     Lit(B), // because literal was asserted
     Congruence(NodeID, NodeID), // because terms are congruent
+    ApplyChain(NodeID, NodeID), // because chains of `apply` are congruent
     AreEq(NodeID, NodeID), // because terms are equal
     Conj(Vec<Expl<B>>), // conjunction of explanations
 }
 
+/// Why a literal was propagated by the congruence closure.
+///
+/// This is only meant for diagnostics, the actual explanation
+/// is computed by `explain_prop`.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum PropReason {
+    /// The term is congruent to a term equal to `true` or `false`.
+    Congruence,
+    /// The term is equal to `true` or `false` by a chain of equalities.
+    Transitivity,
+    /// Evaluation of a boolean connective or constant (e.g. `not true`).
+    BoolConstant,
+}
+
+/// A literal propagated by the congruence closure.
+#[derive(Clone,Debug)]
+pub struct Propagation<B> {
+    /// The propagated literal.
+    pub lit: B,
+    /// Why it was propagated.
+    pub reason: PropReason,
+}
+
+/// An atom bound to a literal, whose class is that of `true` or `false`.
+#[derive(Clone,Debug)]
+pub struct AssignedAtom<AST, B> {
+    /// The atom, as given to `add_literal`.
+    pub atom: AST,
+    /// The literal bound to the atom.
+    pub lit: B,
+    /// Value of the atom in the congruence closure.
+    pub value: bool,
+    /// Explanation of the value, if the congruence closure propagated it.
+    pub reason: Option<Vec<B>>,
+}
+
+/// A certificate that the congruence closure is consistent, see `CC::sat_witness`.
+///
+/// `check` verifies it without the congruence closure. It does not
+/// cover congruence itself, only that the classes respect disequalities.
+#[derive(Clone,Debug)]
+pub struct SatWitness<AST> {
+    /// Each term of the congruence closure, with the representative of its class.
+    pub repr: Vec<(AST, AST)>,
+    /// Pairs of terms that must be in distinct classes: `true` and `false`,
+    /// and the sides of each equation in the class of `false`.
+    pub diseqs: Vec<(AST, AST)>,
+}
+
+impl<AST:Copy+Eq+Hash+Debug> SatWitness<AST> {
+    /// Check that representatives are their own representative,
+    /// and that no disequality holds between terms of the same class.
+    pub fn check(&self) -> Result<(), String> {
+        let repr: FxHashMap<AST, AST> = self.repr.iter().cloned().collect();
+        for (t, r) in self.repr.iter() {
+            if repr.get(r) != Some(r) {
+                return Err(format!("representative {:?} of {:?} is not a representative", r, t))
+            }
+        }
+        for (a, b) in self.diseqs.iter() {
+            match (repr.get(a), repr.get(b)) {
+                (Some(ra), Some(rb)) if ra == rb =>
+                    return Err(format!("{:?} and {:?} are distinct, but in the class of {:?}", a, b, ra)),
+                (Some(_), Some(_)) => (),
+                _ => return Err(format!("{:?} or {:?} has no representative", a, b)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Output format of `CC::dump_egraph`.
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub enum EgraphFormat {
+    /// A graphviz `digraph`, with one cluster per class.
+    Dot,
+    /// A JSON object on a single line, `{"nodes": […], "edges": […]}`.
+    Json,
+}
+
+/// Time spent in one region of the congruence closure, see `Stats`.
+#[derive(Copy,Clone,Debug,Default)]
+pub struct RegionStats {
+    /// Number of timed calls.
+    pub calls: u64,
+    /// Total time of these calls.
+    pub time: Duration,
+    /// Longest single call.
+    pub max: Duration,
+}
+
+/// Where the congruence closure spends its time.
+///
+/// Only measured with the `metrics` feature; otherwise everything stays at 0,
+/// and the timers compile to nothing.
+#[derive(Clone,Debug,Default)]
+pub struct Stats {
+    /// Checks and explanations of propagations, as seen by the SAT solver.
+    ///
+    /// The regions below are parts of this.
+    pub total: RegionStats,
+    /// Merges of the queue, one call per merge.
+    ///
+    /// It includes the explanation of conflicts.
+    pub queue: RegionStats,
+    /// Signature updates, one call per term.
+    pub rehash: RegionStats,
+    /// Explanations, of conflicts, propagations and lemmas.
+    pub explain: RegionStats,
+    /// Propagations given to the SAT solver, one call per check.
+    pub emit: RegionStats,
+}
+
+// start of a timed region, nothing without `metrics`
+#[derive(Copy,Clone)]
+struct Timer(#[cfg(feature="metrics")] std::time::Instant);
+
+impl Timer {
+    #[inline(always)]
+    fn start() -> Self {
+        Timer(#[cfg(feature="metrics")] std::time::Instant::now())
+    }
+
+    #[inline(always)]
+    fn stop(self, _r: &mut RegionStats) {
+        #[cfg(feature="metrics")]
+        {
+            let d = self.0.elapsed();
+            _r.calls += 1;
+            _r.time += d;
+            _r.max = _r.max.max(d);
+        }
+    }
+}
+
+impl Stats {
+    /// The regions, with their names.
+    pub fn regions(&self) -> [(&'static str, &RegionStats); 5] {
+        [("total", &self.total), ("queue", &self.queue), ("rehash", &self.rehash),
+         ("explain", &self.explain), ("emit", &self.emit)]
+    }
+
+    /// Counters, with times in microseconds, as `cc_<region>_{calls,us,max_us}`.
+    ///
+    /// Empty without the `metrics` feature, since nothing is measured.
+    pub fn counters(&self) -> Vec<(&'static str, u64)> {
+        if !cfg!(feature="metrics") { return vec!() }
+        let names: [[&'static str; 3]; 5] = [
+            ["cc_total_calls", "cc_total_us", "cc_total_max_us"],
+            ["cc_queue_calls", "cc_queue_us", "cc_queue_max_us"],
+            ["cc_rehash_calls", "cc_rehash_us", "cc_rehash_max_us"],
+            ["cc_explain_calls", "cc_explain_us", "cc_explain_max_us"],
+            ["cc_emit_calls", "cc_emit_us", "cc_emit_max_us"],
+        ];
+        let mut v = vec!();
+        for (n, (_, r)) in names.iter().zip(self.regions().iter()) {
+            v.push((n[0], r.calls));
+            v.push((n[1], r.time.as_micros() as u64));
+            v.push((n[2], r.max.as_micros() as u64));
+        }
+        v
+    }
+}
+
+/// One line per region, with its number of calls, total and max time.
+impl fmt::Display for Stats {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(out, "{:<10}{:>12}{:>14}{:>14}", "cc", "calls", "time (ms)", "max (ms)")?;
+        for (name, r) in self.regions().iter() {
+            writeln!(out, "{:<10}{:>12}{:>14.3}{:>14.3}",
+                name, r.calls, r.time.as_secs_f64() * 1e3, r.max.as_secs_f64() * 1e3)?;
+        }
+        Ok(())
+    }
+}
+
+// element of the conjunction in `CC::conflict_as_smtlib`
+enum LemmaLit<AST, B> {
+    Atom(AST, bool),
+    Unbound(B),
+}
+
+struct SmtLemma<'a, C: Ctx> {
+    m: &'a C,
+    lits: Vec<LemmaLit<C::AST, C::B>>,
+}
+
 /// Undo operations on the congruence closure
 #[derive(Debug)]
-enum UndoOp {
+enum UndoOp<B> {
     SetOk,
+    SetLit(NodeID, Option<B>), // restore the literal of this node
     RemoveNode(NodeID),
     Unmerge {
         root: NodeID, // the new repr
         old_root: NodeID, // merged into `a`
     }, // unmerge these two reprs
     RemoveExplLink(NodeID,NodeID), // remove explanation link connecting these
+    RemoveWatch(NodeID), // remove the last watch added to this class
 }
 
 /// A signature for a term, obtained by replacing its subterms with their repr.
@@ -211,16 +449,18 @@ pub struct Signature<F> {
 
 // implement main interface
 impl<C:Ctx, Th: MicroTheory<C>> CCInterface<C> for CC<C, Th> {
-    fn merge(&mut self, m: &mut C, t1: C::AST, t2: C::AST, lit: C::B) {
+    fn merge(&mut self, m: &mut C, t1: C::AST, t2: C::AST, lit: C::B) -> Result<(), CcError<C::AST>> {
         debug!("merge {} and {} (expl {:?})", pp_t(m,&t1), pp_t(m,&t2), lit);
+        check_sorts(m, &t1, &t2)?;
         let n1 = self.add_term(m, t1);
         let n2 = self.add_term(m, t2);
         let expl = Expl::Lit(lit);
         self.combine.push((n1,n2,expl));
+        Ok(())
     }
 
-    fn distinct(&mut self, _m: &mut C, _ts: &[C::AST], _lit: C::B) {
-        unimplemented!("distinct is not supported yet")
+    fn distinct(&mut self, _m: &mut C, _ts: &[C::AST], _lit: C::B) -> Result<(), CcError<C::AST>> {
+        Err(CcError::Unsupported{feature: "distinct"})
     }
 
     fn add_literal(&mut self, m: &mut C, t: C::AST, lit: C::B) {
@@ -242,15 +482,30 @@ impl<C:Ctx, Th: MicroTheory<C>> CCInterface<C> for CC<C, Th> {
         self.check_internal(m, acts)
     }
 
-    fn explain_prop(&mut self, _m: &C, _p: C::B) -> &[C::B] {
-        unimplemented!("no propagation here sir")
+    fn explain_prop(&mut self, m: &C, p: C::B) -> &[C::B] {
+        let (t, u) = match self.prop_expl.get(&p) {
+            Some(pair) => *pair,
+            None => panic!("cc: cannot explain {:?}, it was not propagated", p),
+        };
+        trace!("explain-prop {:?} by {} = {}", p, pp::pp2(&self.cc1,m,&t), pp::pp2(&self.cc1,m,&u));
+        let timer = Timer::start();
+        let mut er = ExplResolve::new(&mut self.cc1, &mut self.expl_st);
+        er.explain_eq(m, t, u);
+        er.fixpoint(m);
+        timer.stop(&mut self.cc1.stats.total);
+        &self.cc1.confl
     }
 
     fn has_partial_check() -> bool { true }
 
-    fn enable_propagation(&mut self, _b: bool) {}
+    fn enable_propagation(&mut self, b: bool) { self.propagate = b }
 
     fn impl_descr() -> &'static str { "fast congruence closure"}
+
+    fn declare_apply_symbol(&mut self, apply: C::AST) {
+        assert!(self.cc1.apply.map_or(true, |f| f == apply), "cc: apply symbol declared twice");
+        self.cc1.apply = Some(apply);
+    }
 }
 
 impl<C:Ctx, Th: MicroTheory<C>> CC<C, Th> {
@@ -258,65 +513,354 @@ impl<C:Ctx, Th: MicroTheory<C>> CC<C, Th> {
     fn check_internal<A>(&mut self, m: &mut C, acts: &mut A)
         where A: Actions<C>
     {
-        debug!("check-internal (pending: {}, combine: {})",
-            self.pending.len(), self.combine.len());
-        self.fixpoint(m, Some(acts));
+        self.check_fuel(m, acts, usize::MAX);
+    }
+
+    /// Like `partial_check`, but performs at most `fuel` congruence steps.
+    ///
+    /// A step is either a signature update or a merge.
+    /// Returns `true` if the fixpoint was reached (possibly with a conflict);
+    /// otherwise the remaining work is kept, and the next check resumes it.
+    pub fn partial_check_fuel<A>(&mut self, m: &mut C, acts: &mut A, fuel: usize) -> bool
+        where A: Actions<C>
+    {
+        self.check_fuel(m, acts, fuel)
+    }
+
+    fn check_fuel<A>(&mut self, m: &mut C, acts: &mut A, fuel: usize) -> bool
+        where A: Actions<C>
+    {
+        debug!("check-internal (pending: {}, combine: {}, fuel: {})",
+            self.pending.len(), self.combine.len(), fuel);
+        let timer = Timer::start();
+        let mut done = self.fixpoint(m, Some(&mut *acts), fuel);
+        // clauses may imply new merges, which may refute more equalities
+        while done && self.cc1.ok && self.check_eq_clauses(m) {
+            done = self.fixpoint(m, Some(&mut *acts), fuel);
+        }
         if ! self.cc1.ok {
             debug_assert!(self.cc1.confl.len() >= 1); // must have some conflict
             let costly = true;
             acts.raise_conflict(&self.cc1.confl, costly)
+        } else {
+            // give new propagations to the SAT solver
+            let emit = Timer::start();
+            for p in self.props.as_slice()[self.props_sent..].iter() {
+                trace!("propagate {:?} (reason: {:?})", p.lit, p.reason);
+                acts.propagate(p.lit);
+            }
+            self.props_sent = self.props.as_slice().len();
+            emit.stop(&mut self.cc1.stats.emit);
+        }
+        #[cfg(feature="expensive-checks")]
+        {
+            if done { self.assert_invariants(m) }
+        }
+        timer.stop(&mut self.cc1.stats.total);
+        done
+    }
+
+    /// Where time was spent so far, see `Stats`.
+    pub fn stats(&self) -> &Stats { &self.cc1.stats }
+
+    /// Literals propagated so far, along with the reason for their propagation.
+    ///
+    /// They are in the order they were found, which is also the order
+    /// they are given to `Actions::propagate`.
+    /// Propagations are removed upon backtracking.
+    pub fn propagations(&self) -> &[Propagation<C::B>] { self.props.as_slice() }
+
+    /// Terms bound to a literal by `add_literal`, along with their literal.
+    ///
+    /// Bindings made after a `push_level` are removed by the matching `pop_levels`.
+    pub fn literal_bindings<'a>(&'a self) -> impl Iterator<Item=(C::AST, &'a C::B)> + 'a {
+        self.cc1.nodes.nodes.iter().filter_map(|n| n.lit.as_ref().map(|lit| (n.ast, lit)))
+    }
+
+    /// Atoms bound to a literal whose value is known, with the reason
+    /// for their value if it was propagated.
+    ///
+    /// Atoms that were only assigned by the SAT solver have no reason.
+    /// This explains every propagation, so it is meant for debugging.
+    pub fn iter_assigned_atoms_with_reason(
+        &mut self, m: &C
+    ) -> impl Iterator<Item=AssignedAtom<C::AST, C::B>> {
+        let (r_true, r_false) = (self.cc1.find(self.n_true), self.cc1.find(self.n_false));
+        let bound: Vec<(C::AST, C::B)> =
+            self.literal_bindings().map(|(t, lit)| (t, *lit)).collect();
+        let mut atoms = vec!();
+        let mut propagated: PropVec<(usize, C::B)> = PropVec::new();
+        for (atom, lit) in bound {
+            let r = self.cc1.find_t(&atom);
+            let value = if r == r_true { true } else if r == r_false { false } else { continue };
+            let p = lit.apply_sign(value);
+            if self.prop_expl.get(&p).is_some() {
+                propagated.push((atoms.len(), p));
+            }
+            atoms.push(AssignedAtom{atom, lit, value, reason: None});
+        }
+        let lits: PropVec<C::B> = propagated.iter().map(|(_, p)| *p).collect();
+        crate::svec_sites::PROPAGATIONS.record(lits.len());
+        for ((i, p), e) in propagated.iter().zip(self.explain_props(m, &lits)) {
+            let e: Vec<C::B> = e.into_iter().collect();
+            // an atom merged by its own literal comes from the trail
+            if e.as_slice() != [*p] {
+                atoms[*i].reason = Some(e);
+            }
+        }
+        atoms.into_iter()
+    }
+
+    /// Representatives and disequalities of the current state, see `SatWitness`.
+    ///
+    /// This is meant for a state without conflict, such as after a
+    /// `final_check` that succeeded; otherwise, `check` fails on it.
+    pub fn sat_witness(&self, m: &C) -> SatWitness<C::AST> {
+        let cc1 = &self.cc1;
+        let r_false = cc1.nodes.root_of(self.n_false);
+        let mut repr = Vec::with_capacity(cc1.nodes.nodes.len());
+        let mut diseqs = vec![(cc1[self.n_true].ast, cc1[self.n_false].ast)];
+        for i in 0 .. cc1.nodes.nodes.len() {
+            let id = NodeID(i as u32);
+            let r = cc1.nodes.root_of(id);
+            repr.push((cc1[id].ast, cc1[r].ast));
+            if r == r_false {
+                if let CCView::Eq(a, b) = view_cc(m, &cc1[id].ast) {
+                    diseqs.push((*a, *b));
+                }
+            }
+        }
+        SatWitness{repr, diseqs}
+    }
+
+    /// Classes of the congruence closure, each with its representative first.
+    pub fn classes(&mut self) -> Vec<Vec<C::AST>> {
+        let mut by_root: FxHashMap<NodeID, usize> = FxHashMap::default();
+        let mut res: Vec<Vec<C::AST>> = vec!();
+        for i in 0 .. self.cc1.nodes.nodes.len() {
+            let id = NodeID(i as u32);
+            let r = self.cc1.find(id);
+            let cc1 = &self.cc1;
+            let k = *by_root.entry(r).or_insert_with(|| {
+                res.push(vec![cc1[r].ast]);
+                res.len() - 1
+            });
+            if r != id { res[k].push(cc1[id].ast) }
+        }
+        res
+    }
+
+    /// Render the conflict clause `confl` as an SMT-LIB lemma, `(assert (not (and …)))`.
+    ///
+    /// The conjunction contains the negation of each literal of `confl`,
+    /// printed as the atom it is bound to by `add_literal`, or as `(not atom)`.
+    /// A literal bound to no atom is printed as a quoted symbol, using `Debug`,
+    /// so the lemma only makes sense elsewhere if every literal is bound.
+    pub fn conflict_as_smtlib(&self, m: &C, confl: &[C::B]) -> String {
+        let atoms: FxHashMap<C::B, C::AST> =
+            self.literal_bindings().map(|(t, lit)| (*lit, t)).collect();
+        let lits = confl.iter().map(|lit| {
+            let p = ! *lit;
+            match (atoms.get(&p), atoms.get(&!p)) {
+                (Some(t), _) => LemmaLit::Atom(*t, true),
+                (None, Some(t)) => LemmaLit::Atom(*t, false),
+                (None, None) => LemmaLit::Unbound(p),
+            }
+        }).collect();
+        pp::display(SmtLemma{m, lits}).to_string()
+    }
+
+    /// Explain several propagated literals at once.
+    ///
+    /// `cc.explain_props(m, lits)[i]` has the same literals as
+    /// `cc.explain_prop(m, lits[i])`, sorted and without duplicates.
+    /// The links of the proof forest that occur in the explanation of
+    /// several literals of the batch are expanded only once; the other
+    /// links are explained directly, as `explain_prop` would.
+    ///
+    /// Panics if one of the literals was not propagated.
+    pub fn explain_props(&mut self, m: &C, lits: &[C::B]) -> Vec<ExplVec<C::B>> {
+        let timer = Timer::start();
+        // links of each literal, in `links[offsets[i] .. offsets[i+1]]`,
+        // and how many literals of the batch each link occurs in
+        let mut links = vec!();
+        let mut offsets = Vec::with_capacity(lits.len()+1);
+        let mut n_occ: FxHashMap<NodeID, u32> = FxHashMap::default();
+        offsets.push(0);
+        for p in lits {
+            let (t, u) = match self.prop_expl.get(p) {
+                Some(pair) => *pair,
+                None => panic!("cc: cannot explain {:?}, it was not propagated", p),
+            };
+            trace!("explain-props {:?} by {} = {}",
+                   p, pp::pp2(&self.cc1,m,&t), pp::pp2(&self.cc1,m,&u));
+            let start = links.len();
+            let mut er = ExplResolve::new_links(&mut self.cc1, &mut self.expl_st, &mut links);
+            er.explain_eq(m, t, u);
+            er.fixpoint(m);
+            for n in &links[start..] {
+                *n_occ.entry(*n).or_insert(0) += 1;
+            }
+            offsets.push(links.len());
+        }
+
+        // literals of each shared link, expanded once
+        let mut link_lits: FxHashMap<NodeID, Vec<C::B>> = FxHashMap::default();
+        let mut res: Vec<ExplVec<C::B>> = Vec::with_capacity(lits.len());
+        for i in 0 .. lits.len() {
+            let l_i = &links[offsets[i] .. offsets[i+1]];
+            for n in l_i.iter().filter(|n| n_occ[n] > 1) {
+                if !link_lits.contains_key(n) {
+                    self.expand_links(m, &[*n], &mut link_lits);
+                }
+            }
+            // the links are explained in the same order as in `explain_prop`
+            let mut er = ExplResolve::new(&mut self.cc1, &mut self.expl_st);
+            for n in l_i.iter() {
+                if n_occ[n] > 1 {
+                    for lit in link_lits[n].iter().rev() { er.add_expl(Expl::Lit(*lit)) }
+                } else {
+                    match &er.cc1[*n].expl {
+                        Some((_, expl)) => { let e = expl.clone(); er.add_expl(e) },
+                        None => panic!("cc: no link from {:?}", n),
+                    }
+                }
+            }
+            er.fixpoint(m);
+            let e = &mut self.cc1.confl;
+            e.sort_unstable();
+            e.dedup();
+            crate::svec_sites::EXPLANATION.record(e.len());
+            res.push(ExplVec::from_slice(e));
+        }
+        timer.stop(&mut self.cc1.stats.total);
+        res
+    }
+
+    // expand the proof forest links from `links` (and the compound links
+    // their explanations depend on) that are not in `link_lits` yet
+    fn expand_links(
+        &mut self, m: &C, links: &[NodeID], link_lits: &mut FxHashMap<NodeID, Vec<C::B>>
+    ) {
+        // a link, and once visited, its own literals and the links it depends on
+        let mut todo: Vec<_> = links.iter().map(|n| (*n, None)).collect();
+        while let Some((n, visited)) = todo.pop() {
+            if link_lits.contains_key(&n) { continue }
+            match visited {
+                None => {
+                    let expl = match &self.cc1[n].expl {
+                        Some((_, expl)) => expl.clone(),
+                        None => panic!("cc: no link from {:?}", n),
+                    };
+                    let mut deps = vec!();
+                    let mut er = ExplResolve::new_links(&mut self.cc1, &mut self.expl_st, &mut deps);
+                    er.add_expl(expl);
+                    let mut lits = er.fixpoint(m).clone();
+                    // a link justified by a literal is not worth caching
+                    let cc1 = &self.cc1;
+                    deps.retain(|d| match &cc1[*d].expl {
+                        Some((_, Expl::Lit(lit))) => { lits.push(*lit); false },
+                        Some((_, Expl::Axiom)) => false,
+                        _ => true,
+                    });
+                    // links form a DAG: the dependencies are done before `n`
+                    let deps_todo: Vec<_> =
+                        deps.iter().filter(|d| !link_lits.contains_key(d)).map(|d| (*d, None)).collect();
+                    todo.push((n, Some((lits, deps))));
+                    todo.extend(deps_todo);
+                },
+                Some((mut lits, deps)) => {
+                    for d in deps.iter() {
+                        lits.extend(link_lits[d].iter().cloned());
+                    }
+                    lits.sort_unstable();
+                    lits.dedup();
+                    link_lits.insert(n, lits);
+                },
+            }
         }
     }
 
     /// Main CC algorithm.
-    fn fixpoint(&mut self, m: &mut C, mut acts: Option<&mut dyn Actions<C>>) {
+    ///
+    /// Stops after `fuel` steps, or when `acts` is canceled;
+    /// returns `true` if the fixpoint is reached.
+    fn fixpoint(
+        &mut self, m: &mut C, mut acts: Option<&mut dyn Actions<C>>, mut fuel: usize
+    ) -> bool {
         let CC{
             combine,cc1,pending,th,expl_st,undo,tmp_sig,
-            sig_tbl,n_true,n_false,..} = self;
+            sig_tbl,n_true,n_false,props,prop_expl,propagate,priority,ext,ext_done,antisym_apps,
+            eq_clauses,eq_woken,..} = self;
         let mut combine2 = vec!();
         loop {
             if !cc1.ok {
                 combine.clear();
                 pending.clear();
                 break
+            } else if is_canceled(&acts) {
+                trace!("cc: canceled (pending: {}, combine: {})", pending.len(), combine.len());
+                return false
             }
 
             {
                 let mut updsig =
                     UpdateSigPhase{cc1,combine,sig_tbl,tmp_sig,
                     n_true: *n_true,n_false: *n_false};
-                for &t in pending.iter() {
+                let n = pending.len().min(fuel);
+                for &t in pending[..n].iter() {
                     if updsig.cc1[t].needs_sig() {
+                        let timer = Timer::start();
                         updsig.update_signature(m, th, t);
+                        timer.stop(&mut updsig.cc1.stats.rehash);
                     }
                 }
-                pending.clear();
+                fuel -= n;
+                pending.drain(..n);
             }
 
             {
                 let mut merger = MergePhase{
                     cc1,pending,expl_st,undo,acts: &mut acts,
                     combine2: &mut combine2,
+                    props, prop_expl, propagate: *propagate,
+                    priority: priority.as_ref().map(|p| &**p),
+                    ext: ext.as_ref().map(|e| &**e), ext_done,
+                    antisym_apps: antisym_apps.as_slice(),
+                    eq_woken, watching: !eq_clauses.as_slice().is_empty(),
                     n_true: *n_true,n_false: *n_false};
-                while combine.len() > 0 {
-                    for (t,u,expl) in combine.iter() {
-                        merger.merge(m,th,*t,*u,expl.clone())
+                while !combine.is_empty() && fuel > 0 && !is_canceled(merger.acts) {
+                    let n = combine.len().min(fuel);
+                    for (t,u,expl) in combine[..n].iter() {
+                        let timer = Timer::start();
+                        merger.merge(m,th,*t,*u,expl.clone());
+                        timer.stop(&mut merger.cc1.stats.queue);
                     }
-                    combine.clear();
+                    fuel -= n;
+                    combine.drain(..n);
                     // micro theories may have more things to propagate
                     combine.extend_from_slice(merger.combine2);
                     merger.combine2.clear();
                 }
             }
-            debug_assert!(combine.is_empty());
 
-            if pending.is_empty() {
+            if pending.is_empty() && combine.is_empty() {
                 break; // done
+            } else if fuel == 0 && cc1.ok {
+                trace!("cc: out of fuel (pending: {}, combine: {})", pending.len(), combine.len());
+                return false
             }
         }
+        true
     }
 }
 
+// remaining work is kept, so a canceled check can be resumed later
+#[inline]
+fn is_canceled<C:Ctx>(acts: &Option<&mut dyn Actions<C>>) -> bool {
+    acts.as_ref().map_or(false, |a| a.is_canceled())
+}
+
 // main congruence closure operations
 impl<C:Ctx, Th:MicroTheory<C>> CC<C, Th> {
     /// Create a new congruence closure.
@@ -338,13 +882,218 @@ impl<C:Ctx, Th:MicroTheory<C>> CC<C, Th> {
             combine: vec!(),
             undo: backtrack::Stack::new(),
             traverse: vec!(),
+            traverse_open: FxHashSet::default(),
             tmp_sig: Signature::new(),
             sig_tbl: backtrack::HashMap::new(),
             expl_st: vec!(),
+            propagate: false,
+            props: backtrack::Stack::new(),
+            props_sent: 0,
+            prop_expl: backtrack::HashMap::new(),
+            priority: None,
+            ext: None,
+            ext_done: FxHashSet::default(),
+            antisym: FxHashSet::default(),
+            antisym_apps: backtrack::Stack::new(),
+            eq_clauses: backtrack::Stack::new(),
+            eq_moves: backtrack::Stack::new(),
+            eq_woken: vec!(),
             cc1,
         }
     }
 
+    /// Choose representatives using the given priority function.
+    ///
+    /// When two classes are merged, the root with the highest priority
+    /// becomes the representative of the new class; in case of a tie,
+    /// the bigger class wins. `true` and `false` are always representatives.
+    pub fn set_merge_priority<F>(&mut self, f: F)
+        where F: Fn(&C, &C::AST) -> i32 + 'static
+    {
+        self.priority = Some(Box::new(f))
+    }
+
+    /// Add extensionality lemmas for the symbol given to `declare_apply_symbol`.
+    ///
+    /// When two classes that both contain functions (terms `f` occurring
+    /// in some `apply(f,x)`) are merged into `f = g`, the lemma
+    /// `f = g ⇒ apply(f,x) = apply(g,x)` is added for each `apply(f,x)`
+    /// where `apply(g,x)` is not a term yet, and symmetrically.
+    /// `mk_eq(m,a,b)` builds the equation `a = b`.
+    ///
+    /// Lemmas are only added during `partial_check` and `final_check`.
+    pub fn enable_extensionality<F>(&mut self, mk_eq: F)
+        where F: Fn(&mut C, &C::AST, &C::AST) -> C::AST + 'static
+    {
+        self.ext = Some(Box::new(mk_eq))
+    }
+
+    /// Declare that the binary predicate `pred` is antisymmetric.
+    ///
+    /// Once both `pred(a,b)` and `pred(b,a)` are equal to `true`, `a` and `b`
+    /// are merged, with both atoms as explanation.
+    /// Only applications of `pred` added to the congruence closure
+    /// after this call are concerned.
+    pub fn register_antisymmetric(&mut self, pred: C::AST) {
+        self.antisym.insert(pred);
+    }
+
+    /// Assert `guard => a1=b1 ∨ … ∨ an=bn`, where `eqs` is `[(a1,b1), …, (an,bn)]`.
+    ///
+    /// The clause does nothing until the boolean term `guard` is merged
+    /// with `true`, e.g. by asserting its literal.
+    ///
+    /// An equality `ai=bi` is refuted once some equation between the classes
+    /// of `ai` and `bi` is merged with `false`. When all equalities but one
+    /// are refuted, the last one is merged, explained by `guard` and the
+    /// refutations; when all of them are, this is a conflict.
+    /// Clauses are only examined by `partial_check`, `final_check` and
+    /// `push_level`, and are removed upon backtracking.
+    ///
+    /// An equality between a term and `true` or `false` is also refuted
+    /// once the term is merged with the other boolean.
+    ///
+    /// Two equalities of the clause are watched, and the clause is only
+    /// examined again once one of them can have been refuted, i.e. when
+    /// the class of one of their terms is merged with `true` or `false`,
+    /// or with a class that has equations merged with `false`.
+    ///
+    /// Fails, without doing anything, if some `ai` and `bi` have distinct sorts,
+    /// or if `guard` is not a boolean.
+    pub fn add_eq_clause(
+        &mut self, m: &mut C, eqs: &[(C::AST,C::AST)], guard: C::AST
+    ) -> Result<(), CcError<C::AST>> {
+        debug!("add-eq-clause {} => {} equalities", pp_t(m,&guard), eqs.len());
+        check_sorts(m, &guard, &m.get_bool_term(true))?;
+        self.push_eq_clause(m, eqs, Some(guard))
+    }
+
+    /// Assert `a1=b1 ∨ … ∨ an=bn`, like `add_eq_clause` but without a guard.
+    ///
+    /// The clause is a valid lemma: it does not appear in explanations.
+    /// It is still removed upon backtracking.
+    pub fn add_eq_lemma(
+        &mut self, m: &mut C, eqs: &[(C::AST,C::AST)]
+    ) -> Result<(), CcError<C::AST>> {
+        debug!("add-eq-lemma of {} equalities", eqs.len());
+        self.push_eq_clause(m, eqs, None)
+    }
+
+    fn push_eq_clause(
+        &mut self, m: &mut C, eqs: &[(C::AST,C::AST)], guard: Option<C::AST>
+    ) -> Result<(), CcError<C::AST>> {
+        for (a,b) in eqs { check_sorts(m, a, b)?; }
+        let guard = guard.map(|g| self.add_term(m, g));
+        let eqs: Vec<_> =
+            eqs.iter().map(|(a,b)| (self.add_term(m, *a), self.add_term(m, *b))).collect();
+        let c = self.eq_clauses.as_slice().len() as u32;
+        let watch = [0, if eqs.len() > 1 { 1 } else { 0 }];
+        let CC{cc1, undo, ..} = self;
+        if let Some(g) = guard {
+            cc1.add_watch(undo, g, (c, WATCH_GUARD));
+        }
+        for &w in watch[..eqs.len().min(2)].iter() {
+            let (a, b) = eqs[w as usize];
+            cc1.add_watch(undo, a, (c, w));
+            cc1.add_watch(undo, b, (c, w));
+        }
+        self.eq_clauses.push(EqClause{guard, eqs, watch});
+        // examine it once, in case it is already unit
+        self.eq_woken.push((c, WATCH_GUARD));
+        Ok(())
+    }
+
+    /// Current representative of `t`'s class, if `t` is in the congruence closure.
+    ///
+    /// Merges are only processed by `partial_check`, `final_check` and `push_level`.
+    pub fn find(&mut self, t: &C::AST) -> Option<C::AST> {
+        if self.cc1.nodes.contains(t) {
+            let r = self.cc1.find_t(t);
+            Some(self.cc1[r].ast)
+        } else {
+            None
+        }
+    }
+
+    /// Size of the undo trail when level `lvl` (starting at 0) was pushed,
+    /// or its current size if `lvl` is the current number of levels.
+    ///
+    /// For diagnostics, e.g. to see which level grows the most.
+    pub fn trail_size_at_level(&self, lvl: usize) -> usize {
+        self.undo.size_at_level(lvl)
+    }
+
+    /// Tentatively merge `t1` and `t2` with explanation `lit`.
+    ///
+    /// Returns `Ok(Some(c))` if the merge is inconsistent with the current
+    /// state, where `c` is a valid conflict clause (containing `¬lit`),
+    /// and `Ok(None)` otherwise.
+    /// Either way the merge, and everything it implied, is undone before
+    /// returning. Pending merges are processed first, as in `push_level`.
+    ///
+    /// Fails, without doing anything, if `t1` and `t2` have distinct sorts.
+    pub fn probe(
+        &mut self, m: &mut C, t1: C::AST, t2: C::AST, lit: C::B
+    ) -> Result<Option<ConflVec<C::B>>, CcError<C::AST>> {
+        debug!("probe {} = {} (expl {:?})", pp_t(m,&t1), pp_t(m,&t2), lit);
+        check_sorts(m, &t1, &t2)?;
+        backtrack::Backtrackable::push_level(self, m);
+        let n1 = self.add_term(m, t1);
+        let n2 = self.add_term(m, t2);
+        self.combine.push((n1,n2,Expl::Lit(lit)));
+        self.fixpoint(m, None, usize::MAX);
+        let res = if self.cc1.ok { None } else {
+            crate::svec_sites::CONFLICT.record(self.cc1.confl.len());
+            Some(ConflVec::from_slice(&self.cc1.confl))
+        };
+        backtrack::Backtrackable::pop_levels(self, m, 1);
+        Ok(res)
+    }
+
+    /// Representative that `t` has once added to the congruence closure.
+    ///
+    /// `t`, and those of its subterms that are not in the congruence closure
+    /// yet, are added and closed under congruence, without any new merge;
+    /// a term congruent to a known term gets its representative (or another
+    /// known term of its class), and a new boolean term can be `true` or `false`.
+    /// Everything is undone before returning, as in `probe`, so the
+    /// result may be `t` itself. Queries do not count in `stats`.
+    pub fn eval_new_term(&mut self, m: &mut C, t: C::AST) -> C::AST {
+        self.eval_new_term_in(m, &[], t)
+    }
+
+    /// Like `eval_new_term`, once the terms of each class of `classes`
+    /// are merged as well, e.g. to restore a state the SAT solver has
+    /// backtracked from.
+    pub(crate) fn eval_new_term_in(
+        &mut self, m: &mut C, classes: &[Vec<C::AST>], t: C::AST
+    ) -> C::AST {
+        let stats = self.cc1.stats.clone();
+        backtrack::Backtrackable::push_level(self, m);
+        for cl in classes {
+            let n0 = self.add_term(m, cl[0]);
+            for u in &cl[1..] {
+                let n = self.add_term(m, *u);
+                self.combine.push((n0, n, Expl::Axiom));
+            }
+        }
+        let n_known = self.cc1.nodes.nodes.len();
+        let n = self.add_term(m, t);
+        self.fixpoint(m, None, usize::MAX);
+        // the representative, or a term of its class that was known before
+        let r = self.cc1.find(n);
+        let mut u = r;
+        let res = loop {
+            if (u.0 as usize) < n_known { break self.cc1[u].ast }
+            u = self.cc1[u].next;
+            if u == r { break self.cc1[r].ast }
+        };
+        trace!("eval-new-term {}: {}", pp_t(m,&t), pp_t(m,&res));
+        backtrack::Backtrackable::pop_levels(self, m, 1);
+        self.cc1.stats = stats;
+        res
+    }
+
     /// Add this term to the congruence closure, if not present already.
     #[inline]
     fn add_term(&mut self, m: &mut C, t0: C::AST) -> NodeID {
@@ -356,8 +1105,9 @@ impl<C:Ctx, Th:MicroTheory<C>> CC<C, Th> {
 
     fn add_term_rec(&mut self, m: &mut C, t0: C::AST) -> NodeID {
         self.traverse.clear();
+        self.traverse_open.clear();
 
-        let CC {traverse, undo, cc1, pending, th, ..} = self;
+        let CC {traverse, traverse_open, undo, cc1, pending, th, antisym, antisym_apps, ..} = self;
         // traverse in postfix order (shared context: `cc1`)
 
         traverse.push(TraverseTask::Enter(t0));
@@ -366,26 +1116,42 @@ impl<C:Ctx, Th:MicroTheory<C>> CC<C, Th> {
             match task {
                 TraverseTask::Enter(t) => {
                     if ! cc1.nodes.contains(&t) {
-                        let n = cc1.nodes.insert(t);
-
-                        if t == t0 { n0 = Some(n) } // first node
-
-                        traverse.push(TraverseTask::Exit(t,n));
+                        // `t` is below itself: only hand-built ids can do that,
+                        // and the traversal would never end
+                        if ! traverse_open.insert(t) {
+                            panic!("cc: term {:?} is its own subterm (cyclic AST, added from {:?})", t, t0);
+                        }
+                        traverse.push(TraverseTask::Exit(t));
                         // add subterms
-                        m.view_as_cc_term(&t).iter_subterms(|u| {
+                        view_cc(m, &t).iter_subterms(|u| {
                             traverse.push(TraverseTask::Enter(*u))
                         });
                     }
                 },
-                TraverseTask::Exit(t,n) => {
+                TraverseTask::Exit(t) => {
+                    // allocate nodes in postfix order, so that undoing
+                    // `RemoveNode` always removes the last node
+                    traverse_open.remove(&t);
+                    let n = cc1.nodes.insert(t);
+                    if t == t0 { n0 = Some(n) } // last node
+
                     // now add itself to its children's list of parents.
-                    let view = m.view_as_cc_term(&t);
+                    let view = view_cc(m, &t);
                     view.iter_subterms(|u| {
                         debug_assert!(cc1.nodes.contains(&u)); // postfix order
                         let ur = cc1.find_t(u);
                         let parents = cc1.nodes.parents_mut(ur);
                         parents.add(&mut cc1.alloc_parent_list, n);
                     });
+                    // a chain of `apply` must also be checked again when
+                    // its head or one of its arguments changes class
+                    if let Some((f, args)) = cc1.apply.and_then(|a| view_apply_chain(m, &a, &t)) {
+                        cc1.nodes[n].set_apply_chain();
+                        for u in Some(&f).into_iter().chain(&args[.. args.len()-1]) {
+                            let ur = cc1.find_t(u);
+                            cc1.nodes.parents_mut(ur).add(&mut cc1.alloc_parent_list, n);
+                        }
+                    }
                     // remove `t` before its children
                     undo.push_if_nonzero(UndoOp::RemoveNode(n));
 
@@ -393,6 +1159,13 @@ impl<C:Ctx, Th:MicroTheory<C>> CC<C, Th> {
                         cc1.nodes[n].set_needs_sig();
                         pending.push(n);
                     }
+                    match view {
+                        CCView::ApplyHO(f, args) if args.len() == 2 && antisym.contains(f) => {
+                            cc1.nodes[n].set_antisym();
+                            antisym_apps.push(n);
+                        },
+                        _ => (),
+                    }
 
                     th.on_new_term(m, cc1, &t, n);
                 },
@@ -402,8 +1175,13 @@ impl<C:Ctx, Th:MicroTheory<C>> CC<C, Th> {
         n0.unwrap()
     }
 
-    // we do not care
-    fn map_to_lit(&mut self, _m: &C, _t: NodeID, _lit: C::B) {}
+    // remember that `t` is the term for `lit`, for propagations
+    fn map_to_lit(&mut self, _m: &C, t: NodeID, lit: C::B) {
+        let old = std::mem::replace(&mut self.cc1[t].lit, Some(lit));
+        if old != Some(lit) {
+            self.undo.push_if_nonzero(UndoOp::SetLit(t, old));
+        }
+    }
 }
 
 /// Internal structure used during merging of newly equivalent classes.
@@ -414,8 +1192,17 @@ pub struct MergePhase<'a,'b:'a, C:Ctx> {
     pub(crate) pending: &'a mut Vec<NodeID>,
     pub(crate) combine2: &'a mut Vec<(NodeID, NodeID, Expl<C::B>)>, // temporary
     pub(crate) expl_st: &'a mut Vec<Expl<C::B>>,
-    undo: &'a mut backtrack::Stack<UndoOp>,
+    undo: &'a mut backtrack::Stack<UndoOp<C::B>>,
     acts: &'a mut Option<&'b mut dyn Actions<C>>,
+    propagate: bool,
+    priority: Option<&'a MergePriority<C>>,
+    ext: Option<&'a MkEq<C>>,
+    ext_done: &'a mut FxHashSet<C::AST>,
+    antisym_apps: &'a [NodeID],
+    props: &'a mut backtrack::Stack<Propagation<C::B>>,
+    prop_expl: &'a mut backtrack::HashMap<C::B, (NodeID,NodeID)>,
+    eq_woken: &'a mut Vec<(u32,u32)>,
+    watching: bool, // any eq-clause?
 }
 
 /// Internal structure used during update of term signatures.
@@ -486,7 +1273,14 @@ impl<'a, 'b:'a, C:Ctx> MergePhase<'a,'b,C> {
             }
         } else if ra == self.n_true || ra == self.n_false {
             // `ra` must be repr
-        } else if na.len() < nb.len() {
+        } else if {
+            // does `rb` win? Compare priorities, then sizes
+            let prio = self.priority.map(|p| (p(m, &na.ast), p(m, &nb.ast)));
+            match prio {
+                Some((pa, pb)) if pa != pb => pa < pb,
+                _ => na.len() < nb.len(),
+            }
+        } {
             // swap a and b
             std::mem::swap(&mut ra, &mut rb);
             std::mem::swap(&mut a, &mut b);
@@ -496,6 +1290,9 @@ impl<'a, 'b:'a, C:Ctx> MergePhase<'a,'b,C> {
 
         trace!("merge {} into {}", pp::pp2(self.cc1,m,&rb), pp::pp2(self.cc1,m,&ra));
 
+        // applications that need an extensionality lemma, before the parents are merged
+        let ext_apps = self.ext_apps(m, ra, rb);
+
         // call micro theories
         {
             let mut acts = MicroTheoryArg{
@@ -531,6 +1328,23 @@ impl<'a, 'b:'a, C:Ctx> MergePhase<'a,'b,C> {
             });
         }
 
+        // propagate literals of the class of `rb`, which becomes true/false
+        if self.propagate && (ra == self.n_true || ra == self.n_false) {
+            self.propagate_class(m, ra, rb, a, b, &expl);
+        }
+
+        // applications of antisymmetric predicates that become true
+        let mut antisym_new = vec!();
+        if ra == self.n_true && !self.antisym_apps.is_empty() {
+            self.cc1.nodes.iter_class_mut(rb, |n| {
+                if n.is_antisym() { antisym_new.push(n.id) }
+            });
+        }
+
+        if self.watching {
+            self.wake_eq_clauses(m, ra, rb);
+        }
+
         let MergePhase{cc1, acts:_, n_true, n_false, combine2, ..} = self;
 
         // set `rb.root` to `ra`
@@ -551,8 +1365,9 @@ impl<'a, 'b:'a, C:Ctx> MergePhase<'a,'b,C> {
             na.next = next_b;
             nb.next = next_a;
 
-            // also merge parent/lit lists
+            // also merge parent/watch lists
             na.parents.append(&mut nb.parents);
+            na.watches.append(&mut nb.watches);
         }
 
         // call micro theories
@@ -562,6 +1377,389 @@ impl<'a, 'b:'a, C:Ctx> MergePhase<'a,'b,C> {
                 combine: combine2};
             th.after_merge(m, &mut acts, ra, rb);
         }
+
+        if self.cc1.ok && !ext_apps.is_empty() {
+            self.add_ext_lemmas(m, ext_apps);
+        }
+        if self.cc1.ok && antisym_new.len() > 0 {
+            self.merge_antisym(m, &antisym_new);
+        }
+    }
+}
+
+// export of the proof forest
+impl<C:Ctx, Th:MicroTheory<C>> CC<C, Th> {
+    /// Write the equality graph into `out`, for visualization.
+    ///
+    /// Nodes are the terms, grouped by class, with the representative of
+    /// each class in bold. Edges are the links of the proof forest that
+    /// `explain_prop` follows, from the term that was merged to the term
+    /// it was merged with: merges by congruence are dashed, and labelled
+    /// with the congruent terms; other merges are solid, and labelled with
+    /// their literal (or explanation, for merges the CC derived itself).
+    /// A literal is printed as the term bound to it by `add_literal`,
+    /// or else as the equality of the edge's terms that it asserts.
+    ///
+    /// Merges still pending are not part of the graph.
+    pub fn dump_egraph<W: io::Write>(
+        &self, m: &C, out: &mut W, format: EgraphFormat
+    ) -> io::Result<()> {
+        let nodes = &self.cc1.nodes;
+        let mut classes: Vec<(NodeID, Vec<NodeID>)> = vec!();
+        let mut class_idx: FxHashMap<NodeID, usize> = FxHashMap::default();
+        for n in nodes.nodes.iter() {
+            let r = nodes.root_of(n.id);
+            let k = *class_idx.entry(r).or_insert_with(|| {
+                classes.push((r, vec!()));
+                classes.len() - 1
+            });
+            classes[k].1.push(n.id);
+        }
+        // terms bound to literals, to print the literals of edges
+        let atoms: FxHashMap<C::B, C::AST> =
+            self.literal_bindings().map(|(t, lit)| (*lit, t)).collect();
+        // `(from, to, congruence?, label)`
+        let edges: Vec<_> = nodes.nodes.iter().filter_map(|n| {
+            let (to, expl) = n.expl.as_ref()?;
+            let (dashed, label) = match expl {
+                Expl::Lit(lit) => {
+                    let label = match (atoms.get(lit), atoms.get(&!*lit)) {
+                        (Some(t), _) => pp_t(m,t).to_string(),
+                        (None, Some(t)) => format!("(not {})", pp_t(m,t)),
+                        (None, None) => {
+                            let (a, b) = (nodes[n.id].ast, nodes[*to].ast);
+                            format!("{} = {}", pp_t(m,&a), pp_t(m,&b))
+                        },
+                    };
+                    (false, label)
+                },
+                Expl::Congruence(a,b) | Expl::ApplyChain(a,b) => {
+                    let (a, b) = (nodes[*a].ast, nodes[*b].ast);
+                    (true, format!("{}, {}", pp_t(m,&a), pp_t(m,&b)))
+                },
+                e => (false, pp::pp2(nodes, m, e).to_string()),
+            };
+            Some((n.id, *to, dashed, label))
+        }).collect();
+
+        let term = |n: NodeID| escape(&pp_t(m, &nodes[n].ast).to_string());
+        match format {
+            EgraphFormat::Dot => {
+                writeln!(out, "digraph egraph {{")?;
+                for (k, (r, class)) in classes.iter().enumerate() {
+                    writeln!(out, "  subgraph cluster_{} {{", k)?;
+                    for &n in class.iter() {
+                        let style = if n == *r { ", style=bold" } else { "" };
+                        writeln!(out, "    n{} [label=\"{}\"{}];", n.0, term(n), style)?;
+                    }
+                    writeln!(out, "  }}")?;
+                }
+                for (a, b, dashed, label) in edges.iter() {
+                    let style = if *dashed { "style=dashed, " } else { "" };
+                    writeln!(out, "  n{} -> n{} [{}label=\"{}\"];", a.0, b.0, style, escape(label))?;
+                }
+                writeln!(out, "}}")
+            },
+            EgraphFormat::Json => {
+                write!(out, "{{\"nodes\": [")?;
+                let mut first = true;
+                for (k, (r, class)) in classes.iter().enumerate() {
+                    for &n in class.iter() {
+                        if !first { write!(out, ", ")? }
+                        first = false;
+                        write!(out, "{{\"id\": {}, \"term\": \"{}\", \"class\": {}, \"repr\": {}}}",
+                            n.0, term(n), k, n == *r)?;
+                    }
+                }
+                write!(out, "], \"edges\": [")?;
+                for (i, (a, b, dashed, label)) in edges.iter().enumerate() {
+                    if i > 0 { write!(out, ", ")? }
+                    let kind = if *dashed { "congruence" } else { "asserted" };
+                    write!(out, "{{\"from\": {}, \"to\": {}, \"kind\": \"{}\", \"label\": \"{}\"}}",
+                        a.0, b.0, kind, escape(label))?;
+                }
+                writeln!(out, "]}}")
+            },
+        }
+    }
+}
+
+// escape a label, for both DOT and JSON strings; control characters
+// become `\uXXXX`
+fn escape(s: &str) -> String {
+    use std::fmt::Write;
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if (c as u32) < 0x20 => { write!(res, "\\u{:04x}", c as u32).unwrap(); },
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+// disjunctions of equalities
+impl<C:Ctx, Th:MicroTheory<C>> CC<C, Th> {
+    /// Examine clauses of `add_eq_clause` whose watched classes changed,
+    /// adding the merges they imply to `combine`.
+    /// Returns `true` if some merge was added.
+    fn check_eq_clauses(&mut self, m: &C) -> bool {
+        let mut added = false;
+        while let Some((c, w)) = self.eq_woken.pop() {
+            // the clause may have been removed, or may not watch `w` anymore
+            let live = match self.eq_clauses.as_slice().get(c as usize) {
+                Some(cl) => w == WATCH_GUARD || cl.watch.contains(&w),
+                None => false,
+            };
+            if live && self.visit_eq_clause(m, c as usize) {
+                added = true;
+            }
+        }
+        added
+    }
+
+    /// Examine the clause `c`, now that one of its watches was woken.
+    ///
+    /// A refuted watched equality is replaced with one that is not refuted;
+    /// if there is none left, the other watched equality is merged,
+    /// or this is a conflict if it is refuted too. The explanation is only
+    /// built in these last two cases.
+    fn visit_eq_clause(&mut self, m: &C, c: usize) -> bool {
+        let CC{eq_clauses, eq_moves, cc1, combine, undo, n_true, n_false, ..} = self;
+        let (n_true, n_false) = (*n_true, *n_false);
+        let clause = &mut eq_clauses.as_mut_slice()[c];
+        if let Some(g) = clause.guard {
+            if cc1.find(g) != n_true { return false } // not enabled (yet)
+        }
+
+        let survivor = if clause.eqs.is_empty() { None } else {
+            if clause.watch.iter().any(|&w| {
+                let (a, b) = clause.eqs[w as usize];
+                cc1.is_eq(a, b)
+            }) {
+                return false; // satisfied
+            }
+            let mut refuted = [false; 2];
+            for (slot, refuted_slot) in refuted.iter_mut().enumerate() {
+                let (w, other) = (clause.watch[slot], clause.watch[1-slot]);
+                let (a, b) = clause.eqs[w as usize];
+                if !cc1.refutes_eq(m, a, b, None) { continue }
+                // find another equality to watch
+                let next = (0..clause.eqs.len() as u32).find(|&i| {
+                    let (a, b) = clause.eqs[i as usize];
+                    i != w && i != other && !cc1.refutes_eq(m, a, b, None)
+                });
+                match next {
+                    Some(i) => {
+                        eq_moves.push_if_nonzero((c as u32, slot, w));
+                        clause.watch[slot] = i;
+                        let (a, b) = clause.eqs[i as usize];
+                        cc1.add_watch(undo, a, (c as u32, i));
+                        cc1.add_watch(undo, b, (c as u32, i));
+                    },
+                    None => *refuted_slot = true,
+                }
+            }
+            let [w0, w1] = clause.watch;
+            match refuted {
+                [false, false] if w0 != w1 => return false, // two equalities still possible
+                [false, _] => Some(w0),
+                [true, false] => Some(w1),
+                [true, true] => None,
+            }
+        };
+
+        // explain why the others are refuted
+        let mut expl: Vec<_> = clause.guard.iter().map(|&g| Expl::AreEq(g, n_true)).collect();
+        for (i, &(a,b)) in clause.eqs.iter().enumerate() {
+            if survivor != Some(i as u32) {
+                let _ok = cc1.refutes_eq(m, a, b, Some(&mut expl));
+                debug_assert!(_ok, "equality {} of eq-clause is not refuted", i);
+            }
+        }
+        let (a, b) = survivor.map_or((n_true, n_false), |i| clause.eqs[i as usize]);
+        trace!("eq-clause {}: merge {} and {}",
+            c, pp::pp2(&*cc1,m,&a), pp::pp2(&*cc1,m,&b));
+        combine.push((a, b, Expl::Conj(expl)));
+        true
+    }
+}
+
+// disjunctions of equalities
+impl<'a, 'b:'a, C:Ctx> MergePhase<'a,'b,C> {
+    /// Before `rb` is merged into `ra`, wake up the watches of eq-clauses
+    /// on equalities that this merge can refute.
+    fn wake_eq_clauses(&mut self, m: &C, ra: NodeID, rb: NodeID) {
+        let MergePhase{cc1, eq_woken, n_true, n_false, ..} = self;
+        let (n_true, n_false) = (*n_true, *n_false);
+        let ra_bool = ra == n_true || ra == n_false;
+        // terms of `rb` become booleans, or join equations merged with `false`
+        if cc1[rb].watches.len() > 0 && (ra_bool || cc1.has_false_eq_parent(m, ra)) {
+            cc1.wake_watches(rb, eq_woken);
+        }
+        if cc1[ra].watches.len() > 0 && cc1.has_false_eq_parent(m, rb) {
+            cc1.wake_watches(ra, eq_woken);
+        }
+        // equations of `rb` become false: their sides are now distinct
+        if ra == n_false {
+            let mut eqs = vec!();
+            cc1.nodes.iter_class_mut(rb, |n| eqs.push(n.ast));
+            for t in eqs {
+                if let CCView::Eq(x, y) = view_cc(m, &t) {
+                    for u in [x, y].iter() {
+                        let r = cc1.find_t(u);
+                        cc1.wake_watches(r, eq_woken);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// antisymmetric predicates
+impl<'a, 'b:'a, C:Ctx> MergePhase<'a,'b,C> {
+    /// For each `p = pred(x,y)` of `apps`, now true, merge `x` and `y`
+    /// if some `q = pred(y,x)` is also true.
+    fn merge_antisym(&mut self, m: &C, apps: &[NodeID]) {
+        let n_true = self.n_true;
+        for &p in apps {
+            let (f, x, y) = match view_cc(m, &self.cc1[p].ast) {
+                CCView::ApplyHO(f, args) => (*f, args[0], args[1]),
+                _ => unreachable!(),
+            };
+            let (nx, ny) = (self.cc1.get_term_id(&x), self.cc1.get_term_id(&y));
+            if self.cc1.is_eq(nx, ny) { continue }
+            for &q in self.antisym_apps {
+                if !self.cc1.is_eq(q, n_true) { continue }
+                let (x2, y2) = match view_cc(m, &self.cc1[q].ast) {
+                    CCView::ApplyHO(f2, args) if *f2 == f => (args[0], args[1]),
+                    _ => continue,
+                };
+                let (nx2, ny2) = (self.cc1.get_term_id(&x2), self.cc1.get_term_id(&y2));
+                if self.cc1.is_eq(nx, ny2) && self.cc1.is_eq(ny, nx2) {
+                    trace!("antisymmetry: merge {} and {}", pp_t(m,&x), pp_t(m,&y));
+                    // `x=y <== p=true, q=true, x=y2, y=x2`
+                    let mut v = vec![Expl::AreEq(p, n_true), Expl::AreEq(q, n_true)];
+                    if nx != ny2 { v.push(Expl::AreEq(nx, ny2)) }
+                    if ny != nx2 { v.push(Expl::AreEq(ny, nx2)) }
+                    self.combine2.push((nx, ny, Expl::Conj(v)));
+                    break
+                }
+            }
+        }
+    }
+}
+
+// extensionality
+impl<'a, 'b:'a, C:Ctx> MergePhase<'a,'b,C> {
+    /// `(apply(f,x), f, x)` for each parent `apply(f,x)` of the class of `r`
+    /// such that `f` is in the class.
+    fn apps_of_class(&mut self, m: &C, apply: &C::AST, r: NodeID) -> Vec<(C::AST, C::AST, C::AST)> {
+        let mut parents = vec!();
+        self.cc1.nodes.iter_parents(r, |p| parents.push(*p));
+        let mut res = vec!();
+        for p in parents {
+            let t = self.cc1[p].ast;
+            if let CCView::ApplyHO(g, args) = view_cc(m, &t) {
+                if g == apply && args.len() == 2 && self.cc1.find_t(&args[0]) == r {
+                    res.push((t, args[0], args[1]))
+                }
+            }
+        }
+        res
+    }
+
+    /// `(apply(f,x), f, g, x)` for each `apply(f,x)` that needs the lemma
+    /// `f = g ⇒ apply(f,x) = apply(g,x)` once `ra` and `rb` are merged.
+    ///
+    /// Both classes must contain functions, so that the new terms
+    /// are never functions themselves.
+    fn ext_apps(
+        &mut self, m: &C, ra: NodeID, rb: NodeID
+    ) -> Vec<(C::AST, C::AST, C::AST, C::AST)> {
+        let apply = match self.cc1.apply {
+            Some(apply) if self.ext.is_some() && self.acts.is_some() => apply,
+            _ => return vec!(),
+        };
+        let apps_a = self.apps_of_class(m, &apply, ra);
+        let apps_b = self.apps_of_class(m, &apply, rb);
+        match (apps_a.first(), apps_b.first()) {
+            (Some((_, fa, _)), Some((_, fb, _))) => {
+                let (fa, fb) = (*fa, *fb);
+                apps_a.iter().map(|(t, f, x)| (*t, *f, fb, *x))
+                    .chain(apps_b.iter().map(|(t, f, x)| (*t, *f, fa, *x)))
+                    .collect()
+            },
+            _ => vec!(),
+        }
+    }
+
+    /// Add the extensionality lemmas for `apps`, now that the classes are merged.
+    fn add_ext_lemmas(&mut self, m: &mut C, apps: Vec<(C::AST, C::AST, C::AST, C::AST)>) {
+        let (apply, mk_eq) = match (self.cc1.apply, self.ext) {
+            (Some(apply), Some(mk_eq)) => (apply, mk_eq),
+            _ => return,
+        };
+        for (t, f, g, x) in apps {
+            let ty = m.ty(&t);
+            let u = m.mk_app(apply, &[g, x], ty);
+            if self.cc1.nodes.contains(&u) {
+                continue; // congruence already applies
+            }
+            let eq = mk_eq(m, &t, &u);
+            if !self.ext_done.insert(eq) {
+                continue;
+            }
+            trace!("extensionality lemma for {} and {}", pp_t(m,&t), pp_t(m,&u));
+            let mut c: ConflVec<C::B> = {
+                let mut er = ExplResolve::new(&mut self.cc1, &mut self.expl_st);
+                er.explain_eq_t(m, &f, &g);
+                er.fixpoint(m).iter().map(|lit| ! *lit).collect()
+            };
+            let acts = self.acts.as_mut().expect("extensionality needs actions");
+            c.push(acts.map_lit(m, TheoryLit::T(eq, true)));
+            crate::svec_sites::CONFLICT.record(c.len());
+            acts.add_lemma_with_kind(&c, ClauseKind::TheoryLemmaPermanent);
+        }
+    }
+}
+
+impl<'a, 'b:'a, C:Ctx> MergePhase<'a,'b,C> {
+    /// `rb`'s class is about to be merged into `r_bool`, which is `true` or `false`.
+    ///
+    /// The merge was done between `a` and `b` because of `expl`.
+    fn propagate_class(
+        &mut self, m: &C, r_bool: NodeID, rb: NodeID,
+        a: NodeID, b: NodeID, expl: &Expl<C::B>
+    ) {
+        let sign = r_bool == self.n_true;
+        // the node of `{a,b}` that is in the class of `rb`
+        let merged = if self.cc1.find(a) == rb { a } else { b };
+        let reason_merged = match expl {
+            Expl::Congruence(..) | Expl::ApplyChain(..) => PropReason::Congruence,
+            Expl::Axiom => PropReason::BoolConstant,
+            Expl::AreEq(..) => match view_cc(m, &self.cc1[merged].ast) {
+                CCView::Not(..) => PropReason::BoolConstant,
+                _ => PropReason::Transitivity,
+            },
+            Expl::Lit(..) | Expl::Conj(..) => PropReason::Transitivity,
+        };
+        let MergePhase{cc1, props, prop_expl, ..} = self;
+        cc1.nodes.iter_class_mut(rb, |n| {
+            if let Some(lit) = n.lit {
+                let lit = if sign { lit } else { ! lit };
+                if prop_expl.contains_key(&lit) {
+                    return; // already propagated
+                }
+                let reason =
+                    if n.id == merged { reason_merged } else { PropReason::Transitivity };
+                trace!("cc.propagate {:?} (reason: {:?})", lit, reason);
+                prop_expl.insert(lit, (n.id, r_bool));
+                props.push(Propagation{lit, reason});
+            }
+        });
     }
 }
 
@@ -574,7 +1772,7 @@ impl<'a, C:Ctx> UpdateSigPhase<'a,C> {
         let has_sig =
             m.is_app(&t) // shortcut &&
             &&
-            match m.view_as_cc_term(&t) {
+            match view_cc(m, &t) {
             CCView::Bool(_) | CCView::Opaque(_) | CCView::Distinct(_) => false,
             CCView::Eq(a,b) => {
                 // do not compute a signature, but check if `args[0]==args[1]`
@@ -601,26 +1799,30 @@ impl<'a, C:Ctx> UpdateSigPhase<'a,C> {
             },
             CCView::Apply(f, args) => {
                 // compute signature and look for collisions
-                sig.compute_app(cc1, &f, args);
+                sig.compute_app(cc1, &f, args, m.is_commutative(f));
                 true
             },
             CCView::ApplyHO(f, args) => {
-                sig.compute_app_ho(cc1, f, args);
+                sig.compute_app_ho(cc1, f, args, m.is_commutative_term(f));
                 true
             },
         };
         if has_sig {
-            match sig_tbl.get(sig) {
-                None => {
-                    // insert into signature table
-                    sig_tbl.insert(sig.clone(), n);
-                },
-                Some(u) if n == *u => (), // same node
-                Some(u) => {
-                    // collision, merge `t` and `u` as they are congruent
-                    trace!("merge by congruence: {} and {}", pp_t(m,&t), pp::pp2(*cc1,m,u));
-                    let expl = Expl::Congruence(n, *u);
-                    combine.push((n, *u, expl))
+            if let Some(u) = sig.find_or_insert(sig_tbl, n) {
+                // collision, merge `t` and `u` as they are congruent
+                trace!("merge by congruence: {} and {}", pp_t(m,&t), pp::pp2(*cc1,m,&u));
+                let expl = Expl::Congruence(n, u);
+                combine.push((n, u, expl))
+            }
+        }
+        // chains of `apply` are also compared with each other as a whole
+        if cc1[n].is_apply_chain() {
+            if let Some((f, args)) = cc1.apply.and_then(|a| view_apply_chain(m, &a, &t)) {
+                sig.compute_apply_chain(cc1, &f, &args);
+                if let Some(u) = sig.find_or_insert(sig_tbl, n) {
+                    trace!("merge by apply-chain congruence: {} and {}", pp_t(m,&t), pp::pp2(*cc1,m,&u));
+                    let expl = Expl::ApplyChain(n, u);
+                    combine.push((n, u, expl))
                 }
             }
         }
@@ -642,8 +1844,11 @@ impl<C:Ctx> CC1<C> {
             ok: true,
             alloc_parent_list: backtrack::Alloc::new(),
             alloc_lit_list: backtrack::Alloc::new(),
+            alloc_watch_list: backtrack::Alloc::new(),
             tmp_expl: vec!(),
             confl: vec!(),
+            apply: None,
+            stats: Stats::default(),
         }
     }
 
@@ -667,14 +1872,98 @@ impl<C:Ctx> CC1<C> {
         self.nodes.get_term_id(t)
     }
 
+    /// If some equation `x=y` is merged with `false`, where `x=a` and `y=b`
+    /// (or symmetrically), explain why `a≠b`.
+    fn explain_diseq(
+        &mut self, m: &C, a: NodeID, b: NodeID, n_false: NodeID
+    ) -> Option<[Expl<C::B>; 3]> {
+        let (ra, rb) = (self.find(a), self.find(b));
+        let mut eqs = vec!();
+        self.nodes.iter_parents(ra, |p| eqs.push(*p));
+        for p in eqs {
+            let (x, y) = match view_cc(m, &self[p].ast) {
+                CCView::Eq(x, y) => (self.get_term_id(x), self.get_term_id(y)),
+                _ => continue,
+            };
+            if !self.is_eq(p, n_false) { continue }
+            let (rx, ry) = (self.find(x), self.find(y));
+            if rx == ra && ry == rb {
+                return Some([Expl::AreEq(p, n_false), Expl::AreEq(a, x), Expl::AreEq(b, y)])
+            } else if rx == rb && ry == ra {
+                return Some([Expl::AreEq(p, n_false), Expl::AreEq(a, y), Expl::AreEq(b, x)])
+            }
+        }
+        None
+    }
+
+    /// If `a` and `b` are merged with distinct booleans, explain why `a≠b`.
+    fn explain_bool_diseq(
+        &mut self, a: NodeID, b: NodeID, n_true: NodeID, n_false: NodeID
+    ) -> Option<[Expl<C::B>; 2]> {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra == n_true && rb == n_false {
+            Some([Expl::AreEq(a, n_true), Expl::AreEq(b, n_false)])
+        } else if ra == n_false && rb == n_true {
+            Some([Expl::AreEq(a, n_false), Expl::AreEq(b, n_true)])
+        } else {
+            None
+        }
+    }
+
+    /// Is `a≠b` known, by `explain_diseq` or `explain_bool_diseq`?
+    ///
+    /// If so, the explanation is pushed into `expl`, if provided.
+    fn refutes_eq(
+        &mut self, m: &C, a: NodeID, b: NodeID, expl: Option<&mut Vec<Expl<C::B>>>
+    ) -> bool {
+        let (n_true, n_false) = (self.nodes.n_true, self.nodes.n_false);
+        if let Some(e) = self.explain_diseq(m, a, b, n_false) {
+            if let Some(expl) = expl { expl.extend(e.iter().cloned()) }
+            true
+        } else if let Some(e) = self.explain_bool_diseq(a, b, n_true, n_false) {
+            if let Some(expl) = expl { expl.extend(e.iter().cloned()) }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Is some equation between a term of the class of `r` and another term
+    /// merged with `false`?
+    fn has_false_eq_parent(&mut self, m: &C, r: NodeID) -> bool {
+        let mut eqs = vec!();
+        self.nodes.iter_parents(r, |p| eqs.push(*p));
+        let n_false = self.nodes.n_false;
+        eqs.into_iter().any(|p| match view_cc(m, &self[p].ast) {
+            CCView::Eq(..) => self.is_eq(p, n_false),
+            _ => false,
+        })
+    }
+
+    /// Watch the class of `t` for `w`, a `(clause, watch)` of an eq-clause.
+    fn add_watch(&mut self, undo: &mut backtrack::Stack<UndoOp<C::B>>, t: NodeID, w: (u32,u32)) {
+        let r = self.find(t);
+        let CC1{nodes, alloc_watch_list, ..} = self;
+        nodes[r].watches.add(alloc_watch_list, w);
+        undo.push_if_nonzero(UndoOp::RemoveWatch(r));
+    }
+
+    /// Wake up the watches on the class of `r`.
+    fn wake_watches(&self, r: NodeID, woken: &mut Vec<(u32,u32)>) {
+        woken.extend(self.nodes[r].watches.iter().cloned())
+    }
+
     /// Undo one change.
-    fn perform_undo(&mut self, m: &C, op: UndoOp) {
+    fn perform_undo(&mut self, m: &C, op: UndoOp<C::B>) {
         trace!("perform-undo {}", pp::pp2(&self.nodes,m,&op));
         match op {
             UndoOp::SetOk => {
                 self.ok = true;
                 self.confl.clear();
             },
+            UndoOp::SetLit(n, lit) => {
+                self.nodes[n].lit = lit;
+            },
             UndoOp::Unmerge {root: a, old_root: b} => {
                 assert_ne!(a,b); // crucial invariant
 
@@ -689,6 +1978,7 @@ impl<C:Ctx> CC1<C> {
                     nb.next = next_a;
 
                     na.parents.un_append(&mut nb.parents);
+                    na.watches.un_append(&mut nb.watches);
                 }
 
                 // reset `root` pointer for `nb`
@@ -714,13 +2004,26 @@ impl<C:Ctx> CC1<C> {
                     }
                 }
             },
+            UndoOp::RemoveWatch(r) => {
+                let _w = self.nodes[r].watches.remove();
+            },
             UndoOp::RemoveNode(n) => {
                 debug_assert_eq!(0, self.nodes[n].parents.len(), "remove term with parents");
                 let t = self.nodes[n].ast;
+                let is_chain = self.nodes[n].is_apply_chain();
                 self.nodes.remove(n);
 
+                if is_chain {
+                    let (f, args) = self.apply.and_then(|a| view_apply_chain(m, &a, &t)).unwrap();
+                    for u in Some(&f).into_iter().chain(&args[.. args.len()-1]) {
+                        let ur = self.find_t(u);
+                        let _n = self.nodes.parents_mut(ur).remove();
+                        debug_assert_eq!(_n, n);
+                    }
+                }
+
                 // remove from children's parents' lists
-                m.view_as_cc_term(&t).iter_subterms(|u| {
+                view_cc(m, &t).iter_subterms(|u| {
                     let ur = self.find_t(u);
                     let parents = self.nodes.parents_mut(ur);
                     let _n = parents.remove();
@@ -821,29 +2124,279 @@ impl<C:Ctx> CC1<C> {
 impl<C:Ctx, Th: MicroTheory<C>> backtrack::Backtrackable<C> for CC<C, Th> {
     fn push_level(&mut self, m: &mut C) {
         trace!("push-level");
-        self.fixpoint(m, None); // be sure to commit changes before saving
+        self.fixpoint(m, None, usize::MAX); // be sure to commit changes before saving
+        // merges implied by eq-clauses belong to this level, too
+        while self.cc1.ok && self.check_eq_clauses(m) {
+            self.fixpoint(m, None, usize::MAX);
+        }
         self.undo.push_level();
         self.sig_tbl.push_level();
+        self.props.push_level();
+        self.prop_expl.push_level();
+        self.antisym_apps.push_level();
+        self.eq_clauses.push_level();
+        self.eq_moves.push_level();
         self.cc1.alloc_parent_list.push_level();
         self.cc1.alloc_lit_list.push_level();
+        self.cc1.alloc_watch_list.push_level();
         self.th.push_level(m);
     }
 
     fn pop_levels(&mut self, m: &mut C, n: usize) {
-        debug_assert_eq!(self.undo.n_levels(), self.sig_tbl.n_levels());
-        debug_assert_eq!(self.undo.n_levels(), self.cc1.alloc_parent_list.n_levels());
+        debug_assert_levels_in_sync!(
+            "undo" => self.undo.n_levels(),
+            "sig-tbl" => self.sig_tbl.n_levels(),
+            "parent-lists" => self.cc1.alloc_parent_list.n_levels());
         if n > 0 {
             trace!("pop-levels {}", n);
             let cc1 = &mut self.cc1;
             self.undo.pop_levels(n, |op| cc1.perform_undo(m, op));
             self.sig_tbl.pop_levels(n);
+            self.props.pop_levels(n, |_| ());
+            self.prop_expl.pop_levels(n);
+            self.props_sent = self.props_sent.min(self.props.as_slice().len());
+            self.antisym_apps.pop_levels(n, |_| ());
+            {
+                let clauses = self.eq_clauses.as_mut_slice();
+                self.eq_moves.pop_levels(n, |(c, slot, w)| clauses[c as usize].watch[slot] = w);
+            }
+            self.eq_clauses.pop_levels(n, |_| ());
             cc1.alloc_parent_list.pop_levels(n);
             cc1.alloc_lit_list.pop_levels(n);
+            cc1.alloc_watch_list.pop_levels(n);
             self.th.pop_levels(m, n);
 
             self.pending.clear();
             self.combine.clear();
         }
+        #[cfg(feature="expensive-checks")]
+        self.assert_invariants(m);
+    }
+
+    fn n_levels(&self) -> usize { self.undo.n_levels() }
+}
+
+/// A way to break the invariants of a `CC`, to test `CC::check_invariants`.
+#[doc(hidden)]
+#[derive(Clone,Debug)]
+pub enum Corruption<AST> {
+    /// Make the representatives of the two terms point to each other.
+    RootCycle(AST, AST),
+    /// Remove the term from the list of members of its class.
+    UnlinkFromClass(AST),
+    /// Replace the key of a signature of the term with a wrong one.
+    WrongSignature(AST),
+    /// Leave the term in the queue of signatures to update.
+    LeakPending(AST),
+}
+
+mod invariants {
+    use super::*;
+
+    impl<C:Ctx, Th: MicroTheory<C>> CC<C, Th> {
+        /// Check the internal invariants of the congruence closure, and
+        /// describe the first one that is broken.
+        ///
+        /// This is meant for debugging, after a check or `pop_levels`,
+        /// when no work is pending. It checks that:
+        ///
+        /// - chains of representatives are acyclic;
+        /// - the classes partition the known terms;
+        /// - the key of each signature matches the signature of its term,
+        ///   up to the merges made since it was computed;
+        /// - the backtracking points of the trails are monotone;
+        /// - no merge or signature update is pending.
+        ///
+        /// With the `expensive-checks` feature, it runs after each
+        /// `pop_levels` and each complete check, and panics on failure.
+        pub fn check_invariants(&self, m: &C) -> Result<(), String> {
+            let nodes = &self.cc1.nodes;
+            let n_nodes = nodes.nodes.len();
+            let pp_n = |n: NodeID| pp::pp2(nodes, m, &n).to_string();
+
+            if nodes.map.len() != n_nodes {
+                return Err(format!("{} terms for {} nodes", nodes.map.len(), n_nodes))
+            }
+            for (i, n) in nodes.nodes.iter().enumerate() {
+                if n.id.0 as usize != i || nodes.map.get(&n.ast) != Some(&n.id) {
+                    return Err(format!("node {} is not registered under its term", i))
+                }
+            }
+
+            // representatives
+            let mut roots = Vec::with_capacity(n_nodes);
+            for n in nodes.nodes.iter() {
+                match nodes.find_no_compress(n.id) {
+                    Some(r) => roots.push(r),
+                    None => return Err(format!("cyclic representatives from {}", pp_n(n.id))),
+                }
+            }
+
+            // classes, as circular lists starting from their representative
+            let mut seen = vec![false; n_nodes];
+            let mut n_seen = 0;
+            for n in nodes.nodes.iter().filter(|n| roots[n.id.0 as usize] == n.id) {
+                let r = n.id;
+                let mut t = r;
+                loop {
+                    if seen[t.0 as usize] {
+                        return Err(format!("{} is twice in the class of {}", pp_n(t), pp_n(r)))
+                    } else if roots[t.0 as usize] != r {
+                        return Err(format!("{} is in the class of {}, but its representative is {}",
+                            pp_n(t), pp_n(r), pp_n(roots[t.0 as usize])))
+                    }
+                    seen[t.0 as usize] = true;
+                    n_seen += 1;
+                    t = nodes[t].next;
+                    if t.0 as usize >= n_nodes {
+                        return Err(format!("class of {} links to unknown node {:?}", pp_n(r), t))
+                    } else if t == r {
+                        break
+                    }
+                }
+            }
+            if n_seen != n_nodes {
+                let t = NodeID(seen.iter().position(|b| !b).unwrap() as u32);
+                return Err(format!("{} is in no class", pp_n(t)))
+            }
+
+            // signatures, whose subterms may have been merged since
+            let find = |t: NodeID| if t == NodeID::UNDEF { t } else { roots[t.0 as usize] };
+            for (sig, n) in self.sig_tbl.iter() {
+                if n.0 as usize >= n_nodes {
+                    return Err(format!("signature of unknown node {:?}", n))
+                }
+                let t = nodes[*n].ast;
+                let mut key = sig.clone();
+                for u in key.subs.iter_mut() { *u = find(*u) }
+                let is_chain = key.subs.first() == Some(&NodeID::UNDEF);
+                let expected = if is_chain {
+                    match self.cc1.apply.and_then(|a| view_apply_chain(m, &a, &t)) {
+                        Some((f, args)) if nodes[*n].is_apply_chain() => {
+                            let mut subs: SVec<NodeID> = SVec::new();
+                            subs.push(NodeID::UNDEF);
+                            subs.push(find(nodes.get_term_id(&f)));
+                            subs.extend(args.iter().map(|u| find(nodes.get_term_id(u))));
+                            Some(Signature{f: None, subs})
+                        },
+                        _ => None,
+                    }
+                } else {
+                    match view_cc(m, &t) {
+                        CCView::Apply(f, args) => {
+                            let mut subs: SVec<NodeID> =
+                                args.iter().map(|u| find(nodes.get_term_id(u))).collect();
+                            if m.is_commutative(f) && args.len() == 2 {
+                                subs.sort_unstable();
+                                key.subs.sort_unstable();
+                            }
+                            Some(Signature{f: Some(f.clone()), subs})
+                        },
+                        CCView::ApplyHO(f, args) => {
+                            let mut subs: SVec<NodeID> = SVec::new();
+                            subs.push(find(nodes.get_term_id(f)));
+                            subs.extend(args.iter().map(|u| find(nodes.get_term_id(u))));
+                            if m.is_commutative_term(f) && args.len() == 2 {
+                                subs[1..].sort_unstable();
+                                if key.subs.len() > 1 { key.subs[1..].sort_unstable() }
+                            }
+                            Some(Signature{f: None, subs})
+                        },
+                        _ => None,
+                    }
+                };
+                if expected.as_ref() != Some(&key) {
+                    return Err(format!("signature {:?} of {} does not match the term",
+                        sig, pp_n(*n)))
+                }
+            }
+
+            // trails
+            let n_levels = self.undo.n_levels();
+            let levels_ok =
+                self.sig_tbl.n_levels() == n_levels
+                && self.props.n_levels() == n_levels
+                && self.prop_expl.n_levels() == n_levels
+                && self.antisym_apps.n_levels() == n_levels
+                && self.eq_clauses.n_levels() == n_levels
+                && self.eq_moves.n_levels() == n_levels
+                && self.cc1.alloc_parent_list.n_levels() == n_levels
+                && self.cc1.alloc_lit_list.n_levels() == n_levels
+                && self.cc1.alloc_watch_list.n_levels() == n_levels;
+            if !levels_ok {
+                return Err(format!("trails do not have {} levels", n_levels))
+            } else if !self.undo.check_levels() || !self.sig_tbl.check_levels()
+                || !self.props.check_levels() || !self.prop_expl.check_levels()
+                || !self.antisym_apps.check_levels() || !self.eq_clauses.check_levels()
+                || !self.eq_moves.check_levels()
+            {
+                return Err("backtracking points are not monotone".to_string())
+            } else if self.props_sent > self.props.as_slice().len() {
+                return Err(format!("{} propagations sent, out of {}",
+                    self.props_sent, self.props.as_slice().len()))
+            } else if !self.cc1.ok && self.cc1.confl.is_empty() {
+                return Err("conflict without explanation".to_string())
+            }
+
+            // pending work
+            if let Some(t) = self.pending.first() {
+                return Err(format!("{} signature updates are pending, e.g. {}",
+                    self.pending.len(), pp_n(*t)))
+            } else if let Some((a, b, _)) = self.combine.first() {
+                return Err(format!("{} merges are pending, e.g. {} and {}",
+                    self.combine.len(), pp_n(*a), pp_n(*b)))
+            }
+            Ok(())
+        }
+
+        // panic if an invariant is broken
+        #[cfg(feature="expensive-checks")]
+        pub(crate) fn assert_invariants(&self, m: &C) {
+            if let Err(e) = self.check_invariants(m) {
+                panic!("cc: broken invariant: {}", e)
+            }
+        }
+
+        /// Break an invariant on purpose, see `Corruption`.
+        #[doc(hidden)]
+        pub fn corrupt(&mut self, c: Corruption<C::AST>) {
+            let nodes = &mut self.cc1.nodes;
+            match c {
+                Corruption::RootCycle(a, b) => {
+                    let (ra, rb) = (nodes.find_t(&a), nodes.find_t(&b));
+                    nodes[ra].root = rb;
+                    nodes[rb].root = ra;
+                },
+                Corruption::UnlinkFromClass(t) => {
+                    let n = nodes.get_term_id(&t);
+                    nodes[n].next = n;
+                },
+                Corruption::WrongSignature(t) => {
+                    let n = nodes.get_term_id(&t);
+                    let sig = self.sig_tbl.iter().find(|(_, u)| **u == n).map(|(sig, _)| sig.clone());
+                    let mut sig = sig.expect("term has no signature");
+                    sig.subs.push(nodes.n_true);
+                    self.sig_tbl.insert(sig, n);
+                },
+                Corruption::LeakPending(t) => {
+                    let n = nodes.get_term_id(&t);
+                    self.pending.push(n);
+                },
+            }
+        }
+    }
+
+    impl<C:Ctx> Nodes<C> {
+        // representative of `t`, without path compression;
+        // `None` if the chain of representatives has a cycle
+        fn find_no_compress(&self, mut t: NodeID) -> Option<NodeID> {
+            for _ in 0 ..= self.nodes.len() {
+                let root = self.nodes.get(t.0 as usize)?.root;
+                if root == t { return Some(t) }
+                t = root;
+            }
+            None
+        }
     }
 }
 
@@ -851,6 +2404,8 @@ impl<C:Ctx, Th: MicroTheory<C>> backtrack::Backtrackable<C> for CC<C, Th> {
 struct ExplResolve<'a,C:Ctx> {
     cc1: &'a mut CC1<C>,
     expl_st: &'a mut Vec<Expl<C::B>>, // set of explanations to unfold
+    links: Option<&'a mut Vec<NodeID>>, // if present, links to collect instead of unfolding them
+    timer: Timer, // until the end of `fixpoint`
 }
 
 impl<'a,C:Ctx> ExplResolve<'a,C> {
@@ -858,7 +2413,17 @@ impl<'a,C:Ctx> ExplResolve<'a,C> {
     fn new(cc1: &'a mut CC1<C>, expl_st: &'a mut Vec<Expl<C::B>>) -> Self {
         expl_st.clear();
         cc1.confl.clear();
-        ExplResolve { cc1, expl_st }
+        ExplResolve { cc1, expl_st, links: None, timer: Timer::start() }
+    }
+
+    /// Like `new`, but the links of the proof forest that are reached
+    /// are pushed onto `links`, instead of being explained.
+    fn new_links(
+        cc1: &'a mut CC1<C>, expl_st: &'a mut Vec<Expl<C::B>>, links: &'a mut Vec<NodeID>
+    ) -> Self {
+        let mut er = Self::new(cc1, expl_st);
+        er.links = Some(links);
+        er
     }
 
     #[inline]
@@ -888,27 +2453,39 @@ impl<'a,C:Ctx> ExplResolve<'a,C> {
                     // explain why arguments are pairwise equal
                     let a = self.cc1[a].ast;
                     let b = self.cc1[b].ast;
-                    match (m.view_as_cc_term(&a), m.view_as_cc_term(&b)) {
+                    match (view_cc(m, &a), view_cc(m, &b)) {
                         (CCView::Apply(f1, args1), CCView::Apply(f2, args2)) =>
                         {
                             debug_assert_eq!(f1, f2);
-                            for i in 0 .. args1.len() {
-                                self.explain_eq_t(m, &args1[i], &args2[i]);
-                            }
+                            self.explain_args(m, args1, args2, m.is_commutative(f1));
                         },
                         (CCView::ApplyHO(f1, args1), CCView::ApplyHO(f2, args2)) =>
                         {
                             debug_assert_eq!(args1.len(), args2.len());
                             self.explain_eq_t(m, f1, f2);
-                            for i in 0 .. args1.len() {
-                                self.explain_eq_t(m, &args1[i], &args2[i]);
-                            }
+                            let comm = m.is_commutative_term(f1) && m.is_commutative_term(f2);
+                            self.explain_args(m, args1, args2, comm);
                         },
                         _ => unreachable!(),
                     }
-                }
+                },
+                Expl::ApplyChain(a,b) => {
+                    // explain why heads and arguments are pairwise equal
+                    let apply = self.cc1.apply.expect("apply-chain without apply symbol");
+                    let a = self.cc1[a].ast;
+                    let b = self.cc1[b].ast;
+                    match (view_apply_chain(m, &apply, &a), view_apply_chain(m, &apply, &b)) {
+                        (Some((f1, args1)), Some((f2, args2))) => {
+                            debug_assert_eq!(args1.len(), args2.len());
+                            self.explain_eq_t(m, &f1, &f2);
+                            self.explain_args(m, &args1, &args2, false);
+                        },
+                        _ => unreachable!(),
+                    }
+                },
             }
         }
+        self.timer.stop(&mut self.cc1.stats.explain);
         &self.cc1.confl
     }
 
@@ -924,6 +2501,23 @@ impl<'a,C:Ctx> ExplResolve<'a,C> {
         self.explain_along_path(b, common_ancestor);
     }
 
+    /// Explain why the arguments of two congruent applications are equal.
+    ///
+    /// For commutative functions, the arguments may have been swapped.
+    fn explain_args(&mut self, m: &C, args1: &[C::AST], args2: &[C::AST], comm: bool) {
+        let swapped =
+            comm && args1.len() == 2 &&
+            self.cc1.find_t(&args1[0]) != self.cc1.find_t(&args2[0]);
+        if swapped {
+            self.explain_eq_t(m, &args1[0], &args2[1]);
+            self.explain_eq_t(m, &args1[1], &args2[0]);
+        } else {
+            for i in 0 .. args1.len() {
+                self.explain_eq_t(m, &args1[i], &args2[i]);
+            }
+        }
+    }
+
     fn explain_eq_t(&mut self, m: &C, a: &C::AST, b: &C::AST) {
         if *a == *b { return }
         let na = self.cc1.nodes.get_term_id(a);
@@ -935,7 +2529,10 @@ impl<'a,C:Ctx> ExplResolve<'a,C> {
     fn explain_along_path(&mut self, mut cur: NodeID, ancestor: NodeID) {
         while cur != ancestor {
             if let Some((next, expl)) = &self.cc1[cur].expl {
-                self.expl_st.push(expl.clone()); // need to explain this link
+                match &mut self.links {
+                    Some(links) => links.push(cur),
+                    None => self.expl_st.push(expl.clone()), // need to explain this link
+                }
                 cur = *next;
             } else {
                 panic!()
@@ -1000,6 +2597,12 @@ impl<C:Ctx> Nodes<C> {
         }
     }
 
+    /// Representative of `t`, without path compression.
+    pub(crate) fn root_of(&self, mut t: NodeID) -> NodeID {
+        while self[t].root != t { t = self[t].root }
+        t
+    }
+
     // find + path compression, using recursion up to `limit`
     fn find_rec(&mut self, t: NodeID, limit: usize) -> NodeID {
         let root = self[t].root;
@@ -1144,6 +2747,77 @@ mod cc {
             self.cc1.pp2_into(m,n,ctx)
         }
     }
+
+    // micro theories and explanations of the proof forest are not counted
+    impl<C:Ctx, Th: MicroTheory<C>> ReportMemory for CC<C, Th> {
+        fn memory_breakdown(&self, out: &mut MemTree) {
+            let CC1{nodes, alloc_parent_list, alloc_lit_list, alloc_watch_list, confl, tmp_expl, ..} = &self.cc1;
+            {
+                let out = out.child("union-find");
+                out.leaf("nodes", mem::vec_bytes(&nodes.nodes) + mem::vec_bytes(&nodes.find_stack));
+                out.leaf("term-map", mem::hashmap_bytes(&nodes.map));
+                out.add("parents", alloc_parent_list);
+                out.add("lits", alloc_lit_list);
+                out.add("watches", alloc_watch_list);
+            }
+            out.add("sig-table", &self.sig_tbl);
+            out.add("trail", &self.undo);
+            {
+                let out = out.child("propagation");
+                out.add("props", &self.props);
+                out.add("prop-expl", &self.prop_expl);
+                out.leaf("ext-done", mem::hashset_bytes(&self.ext_done));
+                out.add("antisym", &self.antisym_apps);
+                out.add("eq-clauses", &self.eq_clauses);
+                out.add("eq-moves", &self.eq_moves);
+            }
+            out.leaf("buffers",
+                mem::vec_bytes(&self.pending) + mem::vec_bytes(&self.combine)
+                + mem::vec_bytes(&self.expl_st) + mem::vec_bytes(&self.traverse)
+                + mem::vec_bytes(&self.eq_woken)
+                + mem::hashset_bytes(&self.traverse_open)
+                + mem::vec_bytes(confl) + mem::vec_bytes(tmp_expl));
+        }
+    }
+}
+
+mod smt_lemma {
+    use super::*;
+
+    impl<'a, C: Ctx> SmtLemma<'a, C> {
+        fn pp_lit(&self, lit: &LemmaLit<C::AST, C::B>, ctx: &mut pp::Ctx) {
+            match lit {
+                LemmaLit::Atom(t, true) => { ctx.pp(&pp_t(self.m, t)); },
+                LemmaLit::Atom(t, false) => {
+                    ctx.sexp(|ctx| { ctx.str("not").space().pp(&pp_t(self.m, t)); });
+                },
+                LemmaLit::Unbound(p) => { ctx.string(format!("|{:?}|", p)); },
+            }
+        }
+    }
+
+    impl<'a, C: Ctx> pp::Pretty for SmtLemma<'a, C> {
+        fn pp_into(&self, ctx: &mut pp::Ctx) {
+            ctx.sexp(|ctx| {
+                ctx.str("assert").space().sexp(|ctx| {
+                    ctx.str("not").space();
+                    match self.lits.as_slice() {
+                        [] => { ctx.str("true"); },
+                        [lit] => self.pp_lit(lit, ctx),
+                        lits => {
+                            ctx.sexp(|ctx| {
+                                ctx.str("and");
+                                for lit in lits {
+                                    ctx.space();
+                                    self.pp_lit(lit, ctx);
+                                }
+                            });
+                        },
+                    }
+                });
+            });
+        }
+    }
 }
 
 // manipulating nodes
@@ -1160,6 +2834,8 @@ mod node {
 
     const FLG_NEEDS_SIG : u8 = 0b1;
     const FLG_MARKED : u8 = 0b10;
+    const FLG_APPLY_CHAIN : u8 = 0b100;
+    const FLG_ANTISYM : u8 = 0b1000;
 
     impl<AST, B:Clone> NodeDef<AST, B> {
         /// Create a new node with the given ID and AST.
@@ -1167,7 +2843,7 @@ mod node {
             let parents = List::new();
             NodeDef {
                 id, ast, next: id, expl: None,
-                root: id, parents, flags: 0,
+                root: id, parents, watches: List::new(), lit: None, flags: 0,
             }
         }
 
@@ -1190,6 +2866,20 @@ mod node {
 
         #[inline(always)]
         pub fn unmark(&mut self) { self.flags &= !FLG_MARKED }
+
+        /// Is this a chain of `apply`, with the head and arguments as parents?
+        #[inline]
+        pub fn is_apply_chain(&self) -> bool { (self.flags & FLG_APPLY_CHAIN) != 0 }
+
+        #[inline]
+        pub fn set_apply_chain(&mut self) { self.flags |= FLG_APPLY_CHAIN }
+
+        /// Is this an application of an antisymmetric predicate?
+        #[inline]
+        pub fn is_antisym(&self) -> bool { (self.flags & FLG_ANTISYM) != 0 }
+
+        #[inline]
+        pub fn set_antisym(&mut self) { self.flags |= FLG_ANTISYM }
     }
 }
 
@@ -1218,14 +2908,23 @@ mod expl {
                     let b = self[*b].ast;
                     ctx.str("congruence(").pp(&pp_t(m,&a)).str(", ").pp(&pp_t(m,&b)).str(")");
                 }
+                Expl::ApplyChain(a,b) => {
+                    let a = self[*a].ast;
+                    let b = self[*b].ast;
+                    ctx.str("apply-chain(").pp(&pp_t(m,&a)).str(", ").pp(&pp_t(m,&b)).str(")");
+                }
             }
         }
     }
 
-    impl<C:Ctx> pp::Pretty2<C, UndoOp> for Nodes<C> {
-        fn pp2_into(&self, m: &C, op: &UndoOp, ctx: &mut pp::Ctx) {
+    impl<C:Ctx> pp::Pretty2<C, UndoOp<C::B>> for Nodes<C> {
+        fn pp2_into(&self, m: &C, op: &UndoOp<C::B>, ctx: &mut pp::Ctx) {
             match op {
                 UndoOp::SetOk => { ctx.str("set-ok"); },
+                UndoOp::SetLit(t, lit) => {
+                    let t = self[*t].ast;
+                    ctx.str("set-lit(").pp(&pp_t(m,&t)).string(format!(", {:?})", lit));
+                },
                 UndoOp::Unmerge{root:a,old_root:b} => {
                     let a = self[*a].ast;
                     let b = self[*b].ast;
@@ -1240,6 +2939,10 @@ mod expl {
                     let t = self[*t].ast;
                     ctx.str("remove-term(").pp(&pp_t(m,&t)).str(")");
                 },
+                UndoOp::RemoveWatch(t) => {
+                    let t = self[*t].ast;
+                    ctx.str("remove-watch(").pp(&pp_t(m,&t)).str(")");
+                },
             }
         }
     }
@@ -1373,24 +3076,61 @@ impl<F: Eq+Hash+Clone+Debug> Signature<F> {
     }
 
     /// Compute the signature of `f(args)`.
+    ///
+    /// If `comm` is true and there are two arguments, their order is normalized.
     fn compute_app<C>(
-        &mut self, cc1: &mut CC1<C>, f: &C::Fun, args: &[C::AST]
+        &mut self, cc1: &mut CC1<C>, f: &C::Fun, args: &[C::AST], comm: bool
     ) where C: Ctx<Fun=F> {
         self.clear();
         self.f = Some(f.clone());
         for u in args {
             self.subs.push(cc1.find_t(u));
         }
+        if comm && args.len() == 2 {
+            self.subs.sort_unstable();
+        }
+        crate::svec_sites::SIGNATURE.record(self.subs.len());
     }
 
     /// Compute the signature of `f(args)`.
     fn compute_app_ho<C>(
+        &mut self, cc1: &mut CC1<C>, f: &C::AST, args: &[C::AST], comm: bool
+    ) where C: Ctx {
+        self.clear();
+        self.subs.push(cc1.find_t(f));
+        for u in args {
+            self.subs.push(cc1.find_t(u));
+        }
+        if comm && args.len() == 2 {
+            self.subs[1..].sort_unstable();
+        }
+        crate::svec_sites::SIGNATURE.record(self.subs.len());
+    }
+
+    /// Compute the signature of the chain `apply(…apply(f,a1)…,an)`,
+    /// which does not depend on the inner applications.
+    fn compute_apply_chain<C>(
         &mut self, cc1: &mut CC1<C>, f: &C::AST, args: &[C::AST]
     ) where C: Ctx {
         self.clear();
+        self.subs.push(NodeID::UNDEF); // distinct from `apply(f,args)`
         self.subs.push(cc1.find_t(f));
         for u in args {
             self.subs.push(cc1.find_t(u));
         }
+        crate::svec_sites::SIGNATURE.record(self.subs.len());
+    }
+
+    /// Node with the same signature in `tbl`, or `None` after
+    /// inserting this signature for `n`.
+    fn find_or_insert(&self, tbl: &mut backtrack::HashMap<Signature<F>, NodeID>, n: NodeID) -> Option<NodeID> {
+        match tbl.get(self) {
+            None => {
+                tbl.insert(self.clone(), n);
+                None
+            },
+            Some(u) if n == *u => None, // same node
+            Some(u) => Some(*u),
+        }
     }
 }
@@ -3,16 +3,24 @@
 
 #[allow(unused_imports)]
 use {
-    batsmt_core::{ast, backtrack, },
-    batsmt_theory as theory,
+    std::{hash::Hash, io},
+    fxhash::FxHashMap,
+    batsmt_core::{ast, backtrack, Manager, Error, mem::{self, MemTree, ReportMemory}, },
+    batsmt_theory::{self as theory, Actions, TheoryError, ClauseKind, },
     batsmt_pretty as pp,
-    crate::{CCInterface, CCView, Ctx, theories, pp_t},
+    crate::{CCInterface, CCView, CcError, Ctx, theories, pp_t, check_sorts},
 };
 
 #[allow(unused_imports)]
-use crate::{naive_cc::NaiveCC,cc::{CC, MicroTheory}};
+use crate::{naive_cc::NaiveCC,cc::{self, CC, MicroTheory}};
 
-type SVec8<T> = smallvec::SmallVec<[T;8]>;
+/// Inline capacity for the local copy of `distinct` arguments.
+#[cfg(not(feature="svec-large"))]
+pub const DISTINCT_SVEC_SIZE: usize = 8;
+#[cfg(feature="svec-large")]
+pub const DISTINCT_SVEC_SIZE: usize = 16;
+
+type SVec8<T> = smallvec::SmallVec<[T; DISTINCT_SVEC_SIZE]>;
 
 //#[cfg(feature="naive")]
 //type CCI<M> = NaiveCC<M>;
@@ -21,9 +29,33 @@ type SVec8<T> = smallvec::SmallVec<[T;8]>;
 type CCI<M, Th> = CC<M, Th>;
 
 /// A theory built on top of a congruence closure.
-#[repr(transparent)]
 pub struct CCTheory<C:Ctx, Th: MicroTheory<C> = (theories::Ite, )>{
     cc: CCI<C, Th>,
+    lemma_kind: ClauseKind,
+    record_final_state: bool,
+    final_state: Option<FinalState<C::AST, C::B>>,
+    egraph_dump: Option<(Box<dyn io::Write>, cc::EgraphFormat)>, // dump at each conflict
+    atoms: FxHashMap<C::B, C::AST>, // literals of `add_literal`, for `assert_theory_clause`
+    #[cfg(feature="expensive-checks")]
+    cross_check: cross_check::CrossCheck<C>,
+}
+
+/// Snapshot of the congruence closure after a `final_check` that found no conflict.
+///
+/// The SAT solver backtracks before returning `sat`, and the
+/// congruence closure with it; this keeps what it looked like for the model.
+#[derive(Clone,Debug)]
+pub struct FinalState<AST:Eq+Hash, B> {
+    /// Atoms with a value, see `CC::iter_assigned_atoms_with_reason`.
+    pub atoms: Vec<cc::AssignedAtom<AST, B>>,
+    /// Classes, each with its representative first.
+    pub classes: Vec<Vec<AST>>,
+    repr: FxHashMap<AST, AST>,
+}
+
+impl<AST:Eq+Hash+Clone, B> FinalState<AST, B> {
+    /// Representative of `t`, if it was in the congruence closure.
+    pub fn repr(&self, t: &AST) -> Option<&AST> { self.repr.get(t) }
 }
 
 impl<C:Ctx, Th: MicroTheory<C>> CCTheory<C, Th> {
@@ -31,51 +63,251 @@ impl<C:Ctx, Th: MicroTheory<C>> CCTheory<C, Th> {
     pub fn new(m: &mut C) -> Self {
         let cc = CCI::new(m);
         debug!("use {}", CCI::<C,Th>::impl_descr());
-        Self { cc }
+        Self {
+            cc, lemma_kind: ClauseKind::TheoryLemmaPermanent,
+            record_final_state: false, final_state: None, egraph_dump: None,
+            atoms: FxHashMap::default(),
+            #[cfg(feature="expensive-checks")]
+            cross_check: cross_check::CrossCheck::new(),
+        }
+    }
+
+    /// Compare every `n`-th final check with `NaiveCC`, never if `n` is 0.
+    ///
+    /// Defaults to the value of `$BATSMT_CC_CROSS_CHECK`, or 16.
+    /// See `cross_check` for what is compared.
+    #[cfg(feature="expensive-checks")]
+    pub fn set_cross_check_period(&mut self, n: usize) { self.cross_check.period = n }
+
+    /// Keep a snapshot of the congruence closure after each `final_check`
+    /// that finds no conflict, see `final_state`.
+    ///
+    /// This explains every propagation of the model, so it is costly;
+    /// use it for debugging. Disabled by default.
+    pub fn set_record_final_state(&mut self, b: bool) {
+        self.record_final_state = b;
+        if !b { self.final_state = None }
+    }
+
+    /// Snapshot taken at the last successful `final_check`, if recording is enabled.
+    pub fn final_state(&self) -> Option<&FinalState<C::AST, C::B>> { self.final_state.as_ref() }
+
+    /// Atoms with a value in the current state, with the reason for their value.
+    ///
+    /// See `CC::iter_assigned_atoms_with_reason`.
+    pub fn iter_assigned_atoms_with_reason(
+        &mut self, m: &C
+    ) -> impl Iterator<Item=cc::AssignedAtom<C::AST, C::B>> {
+        self.cc.iter_assigned_atoms_with_reason(m)
+    }
+
+    /// Representatives and disequalities of the current state.
+    ///
+    /// Call it before the SAT solver backtracks, see `CC::sat_witness`.
+    pub fn sat_witness(&self, m: &C) -> cc::SatWitness<C::AST> { self.cc.sat_witness(m) }
+
+    /// Representative of `t`, possibly a new term, in the model.
+    ///
+    /// `t` is evaluated as by `CC::eval_new_term`, in the state of the
+    /// last successful `final_check` if it was recorded (see
+    /// `set_record_final_state`), in the current state otherwise:
+    /// once the SAT solver has backtracked after `sat`, the current
+    /// state is not the one of the model.
+    pub fn eval_new_term(&mut self, m: &mut C, t: C::AST) -> C::AST {
+        match &self.final_state {
+            Some(st) => self.cc.eval_new_term_in(m, &st.classes, t),
+            None => self.cc.eval_new_term(m, t),
+        }
+    }
+
+    fn snapshot_final_state(&mut self, m: &C) {
+        let atoms = self.cc.iter_assigned_atoms_with_reason(m).collect();
+        let classes = self.cc.classes();
+        let repr =
+            classes.iter()
+            .flat_map(|cl| cl.iter().map(move |t| (*t, cl[0])))
+            .collect();
+        self.final_state = Some(FinalState{atoms, classes, repr});
+    }
+
+    /// Write the equality graph into `out` at each conflict, see `CC::dump_egraph`.
+    ///
+    /// If writing fails, the error is logged and dumping stops.
+    pub fn set_egraph_dump(&mut self, out: Box<dyn io::Write>, format: cc::EgraphFormat) {
+        self.egraph_dump = Some((out, format));
+    }
+
+    fn dump_egraph(&mut self, m: &C) {
+        if let Some((out, format)) = &mut self.egraph_dump {
+            debug!("cc: dump egraph of conflict");
+            if let Err(e) = self.cc.dump_egraph(m, out, *format).and_then(|_| out.flush()) {
+                error!("cc: cannot dump egraph: {}", e);
+                self.egraph_dump = None;
+            }
+        }
     }
 
-    /// Add trail to the congruence closure, returns `true` if anything was added
-    fn add_trail_to_cc(&mut self, m: &mut C, trail: &theory::Trail<C>) -> bool {
+    /// Kind of the lemmas this theory adds (e.g. for `distinct` or `ite`).
+    ///
+    /// Defaults to `TheoryLemmaPermanent`: such lemmas are cheap to keep,
+    /// and costly to find again.
+    pub fn set_lemma_kind(&mut self, kind: ClauseKind) {
+        assert!(kind != ClauseKind::Learned && kind != ClauseKind::Input);
+        self.lemma_kind = kind;
+    }
+
+    /// Kind of the lemmas this theory adds.
+    pub fn lemma_kind(&self) -> ClauseKind { self.lemma_kind }
+
+    /// Where the congruence closure spent its time, see `cc::Stats`.
+    ///
+    /// Only measured with the `metrics` feature.
+    pub fn stats(&self) -> &cc::Stats { self.cc.stats() }
+
+    /// Literals propagated so far (in the current trail), with their reason.
+    pub fn propagations(&self) -> &[cc::Propagation<C::B>] { self.cc.propagations() }
+
+    /// Declare the application symbol of curried terms, see `CCInterface::declare_apply_symbol`.
+    pub fn declare_apply_symbol(&mut self, apply: C::AST) {
+        self.cc.declare_apply_symbol(apply);
+        #[cfg(feature="expensive-checks")]
+        { self.cross_check.apply = Some(apply) }
+    }
+
+    /// Add extensionality lemmas, see `CC::enable_extensionality`.
+    pub fn enable_extensionality<F>(&mut self, mk_eq: F)
+        where F: Fn(&mut C, &C::AST, &C::AST) -> C::AST + 'static
+    {
+        self.cc.enable_extensionality(mk_eq)
+    }
+
+    /// Assert the clause `lits` directly in the congruence closure,
+    /// without going through the SAT solver.
+    ///
+    /// Each literal must be given to `add_literal` first (possibly negated).
+    /// The clause is handled by `CC::add_eq_lemma`: once all its literals
+    /// but one are false, the last one is made true by a merge
+    /// (and possibly propagated), and if all of them are false
+    /// the next check raises a conflict. Like any merge, it is removed
+    /// upon backtracking, so lemmas that must stay are to be asserted at level 0.
+    ///
+    /// Panics if some literal was not given to `add_literal`.
+    pub fn assert_theory_clause(
+        &mut self, m: &mut C, lits: &[C::B]
+    ) -> Result<(), CcError<C::AST>> {
+        let eqs: Vec<_> = lits.iter().map(|&lit| {
+            let (t, sign) = match (self.atoms.get(&lit), self.atoms.get(&!lit)) {
+                (Some(t), _) => (*t, true),
+                (None, Some(t)) => (*t, false),
+                (None, None) => panic!("cc: unknown literal {:?} in theory clause", lit),
+            };
+            match m.view_as_cc_term(&t) {
+                CCView::Eq(a, b) if sign => (*a, *b),
+                _ => (t, m.get_bool_term(sign)),
+            }
+        }).collect();
+        self.cc.add_eq_lemma(m, &eqs)
+    }
+
+    /// Merge `a` and `b` once `pred(a,b)` and `pred(b,a)` hold, see `CC::register_antisymmetric`.
+    pub fn register_antisymmetric(&mut self, pred: C::AST) { self.cc.register_antisymmetric(pred) }
+
+    /// Add trail to the congruence closure, returns `true` if anything was added.
+    ///
+    /// Fails on the first ill-sorted literal of the trail.
+    fn add_trail_to_cc<A>(
+        &mut self, m: &mut C, acts: &mut A, trail: &theory::Trail<C>
+    ) -> Result<bool, TheoryError<C::B>>
+        where A: theory::Actions<C>
+    {
         let mut done_sth = false;
 
         // update congruence closure
         for (ast,sign,lit) in trail.iter() {
             // convert `ast is {true,false}` into merge ops
-            match m.view_as_cc_term(&ast) {
+            let res = match m.view_as_cc_term(&ast) {
                 CCView::Eq(a,b) => {
                     if sign {
                         // `a=b`
-                        self.cc.merge(m, *a, *b, lit);
+                        self.cc.merge(m, *a, *b, lit)
                     } else {
-                        // `(a=b)=false`
-                        self.cc.merge(m, ast, m.get_bool_term(false), lit);
+                        // `(a=b)=false`, where `a` and `b` must still be comparable
+                        let (a, b) = (*a, *b);
+                        check_sorts(m, &a, &b)
+                            .and_then(|()| self.cc.merge(m, ast, m.get_bool_term(false), lit))
                     }
                 },
                 CCView::Distinct(args) => {
                     if !sign {
-                        panic!("cannot handle negative `distinct`")
+                        // not handled: ignore it, the trail might be T-unsat
+                        debug!("cc: ignore negative `distinct` {}", pp_t(m,&ast));
+                        acts.mark_incomplete("distinct");
+                        continue
                     };
                     // copy `args` locally
                     let args = SVec8::from_slice(args);
+                    crate::svec_sites::DISTINCT.record(args.len());
                     self.cc.distinct(m, &args, lit)
                 },
                 _ => {
                     let u = m.get_bool_term(sign);
                     self.cc.merge(m, ast, u, lit)
                 },
-            }
+            };
 
+            if let Err(e) = res {
+                return Err(error_of_cc(m, e, lit))
+            }
             done_sth = true;
         }
-        done_sth
+        Ok(done_sth)
     }
 }
 
+/// Turn an error of the congruence closure into a theory error.
+fn error_of_cc<C:Ctx>(m: &C, e: CcError<C::AST>, lit: C::B) -> TheoryError<C::B> {
+    let err = match e {
+        CcError::SortMismatch{t1, t2} => {
+            let pp_ty = |t: &C::AST| match m.ty(t) {
+                Some(ty) => pp_t(m, &ty).to_string(),
+                None => "?".to_string(),
+            };
+            // `t2` should have the sort of `t1`
+            Error::Sort{expected: pp_ty(&t1), found: pp_ty(&t2), term: pp_t(m, &t2).to_string()}
+        },
+        CcError::Unsupported{feature} => Error::unsupported(feature),
+    };
+    TheoryError{err, lit: Some(lit)}
+}
+
 impl<C:Ctx, Th:MicroTheory<C>> backtrack::Backtrackable<C> for CCTheory<C,Th> {
     #[inline]
-    fn push_level(&mut self, c: &mut C) { self.cc.push_level(c) }
+    fn push_level(&mut self, c: &mut C) {
+        self.cc.push_level(c);
+        #[cfg(feature="expensive-checks")]
+        self.cross_check.trail.push_level();
+    }
+    #[inline]
+    fn pop_levels(&mut self, c: &mut C, n:usize) {
+        self.cc.pop_levels(c, n);
+        #[cfg(feature="expensive-checks")]
+        self.cross_check.trail.pop_levels(n, |_| ());
+    }
     #[inline]
-    fn pop_levels(&mut self, c: &mut C, n:usize) { self.cc.pop_levels(c, n) }
+    fn n_levels(&self) -> usize { self.cc.n_levels() }
+}
+
+impl<C:Ctx, Th:MicroTheory<C>> ReportMemory for CCTheory<C,Th> {
+    fn memory_breakdown(&self, out: &mut MemTree) {
+        out.add("cc", &self.cc);
+        out.leaf("atoms", mem::hashmap_bytes(&self.atoms));
+        if let Some(st) = &self.final_state {
+            let classes: usize = st.classes.iter().map(mem::vec_bytes).sum();
+            out.leaf("final-state",
+                mem::vec_bytes(&st.atoms) + mem::vec_bytes(&st.classes) + classes
+                + mem::hashmap_bytes(&st.repr));
+        }
+    }
 }
 
 impl<C:Ctx, Th:MicroTheory<C>> theory::Theory<C> for CCTheory<C, Th> {
@@ -85,8 +317,20 @@ impl<C:Ctx, Th:MicroTheory<C>> theory::Theory<C> for CCTheory<C, Th> {
     ) where A: theory::Actions<C>
     {
         debug!("cc.final-check");
-        self.add_trail_to_cc(ctx, trail);
-        self.cc.final_check(ctx, acts);
+        #[cfg(feature="expensive-checks")]
+        self.cross_check.record(trail);
+        #[cfg(feature="expensive-checks")]
+        let acts = &mut cross_check::Spy::new(acts);
+        match self.add_trail_to_cc(ctx, acts, trail) {
+            Ok(_) => self.cc.final_check(ctx, acts),
+            Err(e) => return acts.raise_error(e),
+        }
+        if self.record_final_state && !acts.has_conflict() && !acts.is_canceled() {
+            self.snapshot_final_state(ctx);
+        }
+        if acts.has_conflict() { self.dump_egraph(ctx) }
+        #[cfg(feature="expensive-checks")]
+        self.cross_check.check(ctx, acts, Th::is_empty());
     }
 
     fn partial_check<A>(
@@ -99,13 +343,16 @@ impl<C:Ctx, Th:MicroTheory<C>> theory::Theory<C> for CCTheory<C, Th> {
         }
         debug!("cc.partial-check");
         trace!("trail: {:?}", trail.as_slice());
+        #[cfg(feature="expensive-checks")]
+        self.cross_check.record(trail);
 
         // TODO: shouldn't this shortcut be done in main solver already?
-        let do_sth = self.add_trail_to_cc(ctx, trail);
-        if !do_sth {
-            return; // nothing new
+        match self.add_trail_to_cc(ctx, acts, trail) {
+            Ok(false) => (), // nothing new
+            Ok(true) => self.cc.partial_check(ctx, acts),
+            Err(e) => acts.raise_error(e),
         }
-        self.cc.partial_check(ctx, acts);
+        if acts.has_conflict() { self.dump_egraph(ctx) }
     }
 
     #[inline(always)]
@@ -117,6 +364,7 @@ impl<C:Ctx, Th:MicroTheory<C>> theory::Theory<C> for CCTheory<C, Th> {
 
     #[inline]
     fn add_literal(&mut self, ctx: &mut C, t: C::AST, lit: C::B) {
+        self.atoms.insert(lit, t);
         self.cc.add_literal(ctx, t,lit);
     }
 
@@ -127,3 +375,161 @@ impl<C:Ctx, Th:MicroTheory<C>> theory::Theory<C> for CCTheory<C, Th> {
         self.cc.explain_prop(m, p)
     }
 }
+
+/// Differential check of `CCTheory` against `NaiveCC`, on real problems.
+///
+/// Every `period`-th final check, the literals asserted so far are replayed
+/// into a fresh `NaiveCC`. Unless `CC` also found a conflict, the naive
+/// one must find none. A conflict of `CC` must consist of asserted literals,
+/// and, if there are no micro theories, be a conflict for `NaiveCC` too.
+/// On divergence, this panics with the asserted literals as a replay log
+/// (see `batsmt_theory::replay`), to be added to `tests/logs/`.
+#[cfg(feature="expensive-checks")]
+pub mod cross_check {
+    use {
+        std::sync::atomic::{AtomicUsize, Ordering},
+        fxhash::FxHashSet,
+        batsmt_theory::{TheoryLit, replay},
+        super::*,
+    };
+
+    /// Environment variable with the default period of cross-checks.
+    pub const PERIOD_ENV: &str = "BATSMT_CC_CROSS_CHECK";
+
+    static N_CROSS_CHECKS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Number of cross-checks done so far, by all theories.
+    pub fn n_cross_checks() -> usize { N_CROSS_CHECKS.load(Ordering::Relaxed) }
+
+    pub(super) struct CrossCheck<C:Ctx> {
+        pub(super) trail: backtrack::Stack<(C::AST, bool, C::B)>, // asserted literals, by level
+        pub(super) period: usize,
+        pub(super) apply: Option<C::AST>,
+        n_final_checks: usize,
+    }
+
+    /// Forwards to `acts`, keeping the conflict.
+    pub(super) struct Spy<'a, C:Ctx, A> {
+        acts: &'a mut A,
+        confl: Option<Vec<C::B>>,
+        skip: bool, // error or incomplete check, nothing to compare
+    }
+
+    impl<C:Ctx> CrossCheck<C> {
+        pub(super) fn new() -> Self {
+            let period =
+                std::env::var(PERIOD_ENV).ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(16);
+            CrossCheck{trail: backtrack::Stack::new(), period, apply: None, n_final_checks: 0}
+        }
+
+        pub(super) fn record(&mut self, trail: &theory::Trail<C>) {
+            for (t, sign, lit) in trail.iter() {
+                self.trail.push((t, sign, lit))
+            }
+        }
+
+        pub(super) fn check<A:Actions<C>>(&mut self, m: &mut C, spy: &Spy<C,A>, th_empty: bool) {
+            self.n_final_checks += 1;
+            if self.period == 0 || self.n_final_checks % self.period != 0
+                || spy.skip || spy.acts.is_canceled()
+            {
+                return
+            }
+            let trail = self.trail.as_slice();
+            if trail.iter().any(|(t,_,_)| matches!(m.view_as_cc_term(t), CCView::Distinct(_))) {
+                return // not supported by `NaiveCC`
+            }
+            N_CROSS_CHECKS.fetch_add(1, Ordering::Relaxed);
+            debug!("cc.cross-check ({} literals)", trail.len());
+            match &spy.confl {
+                None if !naive_sat(m, self.apply, trail) => {
+                    self.diverge(m, "NaiveCC found a conflict, CC did not")
+                },
+                None => (),
+                Some(c) => {
+                    let lits: FxHashSet<C::B> = c.iter().map(|lit| !*lit).collect();
+                    if lits.iter().any(|lit| !trail.iter().any(|(_,_,b)| b == lit)) {
+                        self.diverge(m, &format!("conflict {:?} is not false in the trail", c))
+                    }
+                    let core: Vec<_> = trail.iter().filter(|(_,_,b)| lits.contains(b)).cloned().collect();
+                    if th_empty && naive_sat(m, self.apply, &core) {
+                        self.diverge(m, &format!("NaiveCC finds no conflict in {:?}", c))
+                    }
+                },
+            }
+        }
+
+        fn diverge(&self, m: &C, msg: &str) -> ! {
+            let mut out = vec!();
+            self.log(m).write(&mut out).expect("write to a vec");
+            let log = String::from_utf8_lossy(&out);
+            error!("cc.cross-check: {}\n{}", msg, log);
+            panic!("cc.cross-check: {}\n{}", msg, log)
+        }
+
+        // the asserted literals, one partial check per level
+        fn log(&self, m: &C) -> replay::Log {
+            let mut b = replay::LogBuilder::new(false);
+            let mut start = 0;
+            for lvl in 0 ..= self.trail.n_levels() {
+                if lvl > 0 { b.push(replay::Event::PushLevel) }
+                let end = self.trail.size_at_level(lvl);
+                let trail = b.trail(m, &self.trail.as_slice()[start..end]);
+                b.push(replay::Event::PartialCheck(trail, replay::Outcome::default()));
+                start = end;
+            }
+            b.push(replay::Event::FinalCheck(vec!(), replay::Outcome::default()));
+            b.into_log()
+        }
+    }
+
+    // is `trail` satisfiable for a fresh `NaiveCC`?
+    fn naive_sat<C:Ctx>(m: &mut C, apply: Option<C::AST>, trail: &[(C::AST, bool, C::B)]) -> bool {
+        let mut ncc = NaiveCC::new(m);
+        if let Some(f) = apply { ncc.declare_apply_symbol(f) }
+        for &(t, sign, lit) in trail {
+            let res = match m.view_as_cc_term(&t) {
+                CCView::Eq(a, b) if sign => {
+                    let (a, b) = (*a, *b);
+                    ncc.merge(m, a, b, lit)
+                },
+                _ => {
+                    let u = m.get_bool_term(sign);
+                    ncc.merge(m, t, u, lit)
+                },
+            };
+            if res.is_err() { return true } // `CC` would have failed too
+        }
+        let mut acts = theory::SimpleActions::new(|| unreachable!("NaiveCC makes no literal"));
+        ncc.final_check(m, &mut acts);
+        acts.get().is_ok()
+    }
+
+    impl<'a, C:Ctx, A:Actions<C>> Spy<'a, C, A> {
+        pub(super) fn new(acts: &'a mut A) -> Self { Spy{acts, confl: None, skip: false} }
+    }
+
+    impl<'a, C:Ctx, A:Actions<C>> Actions<C> for Spy<'a, C, A> {
+        fn add_lemma_with_kind(&mut self, c: &[C::B], kind: ClauseKind) {
+            self.acts.add_lemma_with_kind(c, kind)
+        }
+        fn propagate(&mut self, p: C::B) -> bool { self.acts.propagate(p) }
+        fn raise_conflict(&mut self, c: &[C::B], costly: bool) {
+            if self.confl.is_none() { self.confl = Some(c.to_vec()) }
+            self.acts.raise_conflict(c, costly)
+        }
+        fn map_lit(&mut self, m: &C, lit: TheoryLit<C>) -> C::B { self.acts.map_lit(m, lit) }
+        fn has_conflict(&self) -> bool { self.acts.has_conflict() }
+        fn mark_incomplete(&mut self, feature: &'static str) {
+            self.skip = true;
+            self.acts.mark_incomplete(feature)
+        }
+        fn raise_error(&mut self, err: TheoryError<C::B>) {
+            self.skip = true;
+            self.acts.raise_error(err)
+        }
+        fn is_canceled(&self) -> bool { self.acts.is_canceled() }
+    }
+}
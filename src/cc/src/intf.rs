@@ -4,6 +4,7 @@ use {
     batsmt_theory::{self as theory, Actions},
     batsmt_core::{backtrack, },
     batsmt_pretty as pp,
+    crate::SVec,
 };
 
 /// A view of terms adapted for the congruence closure.
@@ -56,10 +57,87 @@ pub trait Ctx : theory::Ctx {
     type Fun : Eq + Hash + Clone + fmt::Debug;
 
     /// View a term as an equality or function application.
+    ///
+    /// This is how `CC` and `NaiveCC` classify terms, they do not know
+    /// the builtin symbols. For example, several equality symbols
+    /// can all be viewed as `CCView::Eq`.
     fn view_as_cc_term<'a>(&'a self, t: &'a Self::AST) -> CCView<'a, Self::Fun, Self::AST>;
 
     /// Obtain true/false terms.
     fn get_bool_term(&self, b: bool) -> Self::AST;
+
+    /// Is `f` the boolean negation?
+    ///
+    /// Override this if `view_as_cc_term` returns `not(x)` as an
+    /// application rather than as `CCView::Not`.
+    fn is_not_fun(&self, _f: &Self::Fun) -> bool { false }
+
+    /// Same as `is_not_fun`, for the head of a `CCView::ApplyHO`.
+    fn is_not_term(&self, _f: &Self::AST) -> bool { false }
+
+    /// Is `f` commutative, i.e. is `f(a,b) = f(b,a)`?
+    ///
+    /// Only binary applications are concerned.
+    fn is_commutative(&self, _f: &Self::Fun) -> bool { false }
+
+    /// Same as `is_commutative`, for the head of a `CCView::ApplyHO`.
+    fn is_commutative_term(&self, _f: &Self::AST) -> bool { false }
+}
+
+/// View `t` for the congruence closure, with unary applications of
+/// the negation turned into `CCView::Not`.
+#[inline]
+pub(crate) fn view_cc<'a,C:Ctx>(m: &'a C, t: &'a C::AST) -> CCView<'a,C::Fun,C::AST> {
+    match m.view_as_cc_term(t) {
+        CCView::Apply(f, args) if args.len() == 1 && m.is_not_fun(f) => CCView::Not(&args[0]),
+        CCView::ApplyHO(f, args) if args.len() == 1 && m.is_not_term(f) => CCView::Not(&args[0]),
+        v => v,
+    }
+}
+
+/// View `t` as a maximal chain `apply(…apply(apply(head,a1),a2)…,an)`
+/// of binary applications of `apply`, returning `(head, [a1…an])`.
+///
+/// Returns `None` unless there are at least two nested applications,
+/// or if `apply` is commutative.
+pub(crate) fn view_apply_chain<C:Ctx>(
+    m: &C, apply: &C::AST, t: &C::AST
+) -> Option<(C::AST, SVec<C::AST>)> {
+    if m.is_commutative_term(apply) { return None }
+    let mut args = SVec::new();
+    let mut head = *t;
+    loop {
+        match m.view_as_cc_term(&head) {
+            CCView::ApplyHO(f, fargs) if f == apply && fargs.len() == 2 => {
+                args.push(fargs[1]);
+                head = fargs[0];
+            },
+            _ => break,
+        }
+    }
+    if args.len() < 2 { return None }
+    args.reverse();
+    Some((head, args))
+}
+
+/// Error in the input of the congruence closure.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum CcError<AST> {
+    /// `t1` and `t2` have distinct sorts and cannot be equated.
+    SortMismatch { t1: AST, t2: AST },
+    /// The implementation does not handle this kind of constraint.
+    Unsupported { feature: &'static str },
+}
+
+/// Check that `t1` and `t2` can be equated.
+///
+/// Only terms for which the manager knows a sort are checked.
+pub(crate) fn check_sorts<C:Ctx>(m: &C, t1: &C::AST, t2: &C::AST) -> Result<(), CcError<C::AST>> {
+    match (m.ty(t1), m.ty(t2)) {
+        (Some(ty1), Some(ty2)) if ty1 != ty2 =>
+            Err(CcError::SortMismatch{t1: t1.clone(), t2: t2.clone()}),
+        _ => Ok(()),
+    }
 }
 
 /// An empty type, convenient when there is no notion of `Fun` in terms.
@@ -76,10 +154,14 @@ pub(crate) fn pp_t<'a,C:Ctx>(
 /// Interface satisfied by implementations of the congruence closure.
 pub trait CC<C: Ctx> : backtrack::Backtrackable<C> {
     /// `cc.merge(t1,t2,lit)` merges `t1` and `t2` with explanation `lit`.
-    fn merge(&mut self, m: &mut C, t1: C::AST, t2: C::AST, lit: C::B);
+    ///
+    /// Fails, without merging anything, if `t1` and `t2` have distinct sorts.
+    fn merge(&mut self, m: &mut C, t1: C::AST, t2: C::AST, lit: C::B) -> Result<(), CcError<C::AST>>;
 
     /// `cc.distinct(terms,lit)` asserts that all elements of `terms` are disjoint
-    fn distinct(&mut self, m: &mut C, ts: &[C::AST], lit: C::B);
+    ///
+    /// Fails if the implementation does not support `distinct`.
+    fn distinct(&mut self, m: &mut C, ts: &[C::AST], lit: C::B) -> Result<(), CcError<C::AST>>;
 
     /// Add a binding term<=>literal to the congruence closure.
     ///
@@ -109,6 +191,14 @@ pub trait CC<C: Ctx> : backtrack::Backtrackable<C> {
 
     /// Explain why `p` was propagated
     fn explain_prop(&mut self, m: &C, p: C::B) -> &[C::B];
+
+    /// Declare `apply` as the binary application symbol of a curried encoding,
+    /// where `f(a,b)` is written `apply(apply(f,a),b)`.
+    ///
+    /// Chains of `apply` are then compared in one step. This does not change
+    /// which equalities are entailed, so implementations may ignore it.
+    /// Only terms added after this call are concerned.
+    fn declare_apply_symbol(&mut self, _apply: C::AST) {}
 }
 
 /// A term adapted for if-then-else.
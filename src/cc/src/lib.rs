@@ -14,19 +14,87 @@ pub mod theories;
 pub use {
     crate::{
         intf::{
-            CC as CCInterface, CCView, Ctx, IteView, HasIte,
+            CC as CCInterface, CCView, CcError, Ctx, IteView, HasIte,
             InjectiveView, HasInjectivity, HasDisjointness,
             SelectorView, HasSelector, ConstructorView, HasConstructor,
             ConstructorSelectView, HasConstructorSelect,
         },
-        cc::{CC, MicroTheory},
+        cc::{
+            CC, MicroTheory, MergePriority, MkEq, Propagation, PropReason, AssignedAtom, Corruption,
+            EgraphFormat, Stats, RegionStats, SatWitness,
+        },
         naive_cc::NaiveCC,
-        cc_theory::{CCTheory},
+        cc_theory::{CCTheory, FinalState},
         theories::{Ite as ThIte, }
     },
     batsmt_theory::Actions,
 };
 
+/// Inline capacity of `SVec`, used for signatures and parent lists.
+///
+/// Binary applications have signatures of length 3 (`f` is included),
+/// and 4 inline `u32` take no more room than 3. See `benchs/svec_report.md`.
+/// The `svec-large` feature raises it for problems with high arities.
+#[cfg(not(feature="svec-large"))]
+pub const SVEC_SIZE: usize = 4;
+#[cfg(feature="svec-large")]
+pub const SVEC_SIZE: usize = 8;
+
 /// a small vector of `T`.
-pub(crate) type SVec<T> = smallvec::SmallVec<[T; 3]>;
-pub(crate) use crate::intf::pp_t;
+pub(crate) type SVec<T> = smallvec::SmallVec<[T; SVEC_SIZE]>;
+
+/// Inline capacity of `ExplVec`.
+///
+/// Most propagations are explained by one or two literals.
+pub const EXPL_SVEC_SIZE: usize = 2;
+
+/// Inline capacity of `PropVec`.
+///
+/// A batch holds every propagated literal at once; only small batches
+/// are expected to stay inline.
+#[cfg(not(feature="svec-large"))]
+pub const PROP_SVEC_SIZE: usize = 16;
+#[cfg(feature="svec-large")]
+pub const PROP_SVEC_SIZE: usize = 64;
+
+/// Inline capacity of `ConflVec`.
+///
+/// Probe conflicts on `benchs/` have a single literal; extensionality
+/// lemmas have one more literal than the explanation of `f = g`.
+#[cfg(not(feature="svec-large"))]
+pub const CONFL_SVEC_SIZE: usize = 8;
+#[cfg(feature="svec-large")]
+pub const CONFL_SVEC_SIZE: usize = 16;
+
+/// The explanation of one propagated literal.
+pub type ExplVec<T> = smallvec::SmallVec<[T; EXPL_SVEC_SIZE]>;
+
+/// A batch of propagated literals.
+pub type PropVec<T> = smallvec::SmallVec<[T; PROP_SVEC_SIZE]>;
+
+/// A conflict clause, or a lemma, built outside of the solver's buffers.
+pub type ConflVec<T> = smallvec::SmallVec<[T; CONFL_SVEC_SIZE]>;
+
+/// Spill counters for the small vectors of this crate.
+///
+/// Only counted with the `svec-stats` feature.
+pub mod svec_sites {
+    use batsmt_core::svec_stats::Site;
+
+    /// Arguments of a congruence signature (`f` included for HO applications).
+    pub static SIGNATURE: Site = Site::new("cc.signature", crate::SVEC_SIZE);
+    /// Selector terms attached to a class.
+    pub static SELECTORS: Site = Site::new("cc.theories.selectors", crate::theories::SVEC_SIZE);
+    /// Arguments of a `distinct` literal.
+    pub static DISTINCT: Site = Site::new("cc.distinct", crate::cc_theory::DISTINCT_SVEC_SIZE);
+    /// Explanation of a propagated literal.
+    pub static EXPLANATION: Site = Site::new("cc.explanation", crate::EXPL_SVEC_SIZE);
+    /// Literals explained together by `CC::iter_assigned_atoms_with_reason`.
+    pub static PROPAGATIONS: Site = Site::new("cc.propagations", crate::PROP_SVEC_SIZE);
+    /// Conflicts returned by `CC::probe`, and extensionality lemmas.
+    pub static CONFLICT: Site = Site::new("cc.conflict", crate::CONFL_SVEC_SIZE);
+
+    /// All the sites of this crate.
+    pub static ALL: [&Site; 6] = [&SIGNATURE, &SELECTORS, &DISTINCT, &EXPLANATION, &PROPAGATIONS, &CONFLICT];
+}
+pub(crate) use crate::intf::{pp_t, view_cc, view_apply_chain, check_sorts};
@@ -14,12 +14,14 @@ use {
 
 enum Op<C:Ctx> {
     Merge(C::AST, C::AST, C::B),
+    Neq(C::AST, C::AST, C::B),
 }
 
 /// A naive implementation of the congruence closure
 pub struct NaiveCC<C:Ctx>{
     confl: Vec<C::B>,
     ops: backtrack::Stack<Op<C>>, // just keep the set of operations to do here
+    apply: Option<C::AST>, // application symbol of curried terms
 }
 
 /// A class representative
@@ -32,10 +34,12 @@ struct Repr<AST>(AST);
 // literals used so far.
 struct Solve<'a, C:Ctx> {
     m: &'a C,
+    apply: Option<C::AST>,
     true_: C::AST,
     false_: C::AST,
     confl: &'a mut Vec<C::B>,
     all_lits: FxHashSet<C::B>, // all literals used in ops so far
+    neqs: Vec<(C::AST, C::AST)>, // pairs of terms that must remain distinct
     root: FxHashMap<C::AST, Repr<C::AST>>, // term -> its root + expl
     //root: FxHashMap<AST, (Repr,Option<Expl<B>>)>, // term -> its root + expl
     parents: FxHashMap<Repr<C::AST>, SVec<C::AST>>, // term -> its direct superterms
@@ -49,12 +53,14 @@ enum Task<AST> {
 }
 
 impl<C:Ctx> CCInterface<C> for NaiveCC<C> {
-    fn merge(&mut self, _m: &mut C, t1: C::AST, t2: C::AST, lit: C::B) {
-        self.ops.push(Op::Merge(t1,t2,lit))
+    fn merge(&mut self, m: &mut C, t1: C::AST, t2: C::AST, lit: C::B) -> Result<(), CcError<C::AST>> {
+        check_sorts(m, &t1, &t2)?;
+        self.ops.push(Op::Merge(t1,t2,lit));
+        Ok(())
     }
 
-    fn distinct(&mut self, _m: &mut C, _ts: &[C::AST], _lit: C::B) {
-        unimplemented!("no handling of `distinct` in naiveCC")
+    fn distinct(&mut self, _m: &mut C, _ts: &[C::AST], _lit: C::B) -> Result<(), CcError<C::AST>> {
+        Err(CcError::Unsupported{feature: "distinct"})
     }
 
     fn final_check<A>(&mut self, m: &mut C, acts: &mut A)
@@ -63,9 +69,9 @@ impl<C:Ctx> CCInterface<C> for NaiveCC<C> {
         debug!("cc.check()");
         // create local solver
         self.confl.clear();
-        let mut solve = Solve::new(m, &mut self.confl);
+        let mut solve = Solve::new(m, self.apply, &mut self.confl);
         // here is where we do all the work
-        let ok = solve.check_internal(self.ops.as_slice());
+        let ok = solve.check_internal(self.ops.as_slice(), || acts.is_canceled());
         if !ok {
             let costly = true;
             acts.raise_conflict(&self.confl, costly);
@@ -76,6 +82,8 @@ impl<C:Ctx> CCInterface<C> for NaiveCC<C> {
 
     fn impl_descr() -> &'static str { "naive congruence closure"}
 
+    fn declare_apply_symbol(&mut self, apply: C::AST) { self.apply = Some(apply) }
+
     fn explain_prop(&mut self, _m: &C, _p: C::B) -> &[C::B] {
         unreachable!() // never propagated anything
     }
@@ -87,8 +95,20 @@ impl<C:Ctx> NaiveCC<C> {
         NaiveCC {
             confl: vec!(),
             ops: backtrack::Stack::new(),
+            apply: None,
         }
     }
+
+    /// Assert `t1 ≠ t2`, justified by `lit`.
+    ///
+    /// Unlike merging the term `t1=t2` with `false`, this does not depend
+    /// on how equality terms are handled: the classes of `t1` and `t2`
+    /// must remain distinct, which is checked after each closure.
+    pub fn assert_neq(&mut self, m: &mut C, t1: C::AST, t2: C::AST, lit: C::B) -> Result<(), CcError<C::AST>> {
+        check_sorts(m, &t1, &t2)?;
+        self.ops.push(Op::Neq(t1,t2,lit));
+        Ok(())
+    }
 }
 
 // just backtrack the set of operations we'll have to perform
@@ -97,20 +117,22 @@ impl<C:Ctx> backtrack::Backtrackable<C> for NaiveCC<C> {
     fn pop_levels(&mut self, _: &mut C, n: usize) {
         self.ops.pop_levels(n, |_| ()) // we didn't do anything to cancel
     }
+    fn n_levels(&self) -> usize { self.ops.n_levels() }
 }
 
 // main algorithm
 impl<'a, C:Ctx> Solve<'a, C> {
-    fn new(m: &'a C, confl: &'a mut Vec<C::B>) -> Self {
+    fn new(m: &'a C, apply: Option<C::AST>, confl: &'a mut Vec<C::B>) -> Self {
         let true_ = m.get_bool_term(true);
         let false_ = m.get_bool_term(false);
         let mut s = Solve {
-            m,
+            m, apply,
             true_, false_,
             confl,
             root: FxHashMap::default(),
             parents: FxHashMap::default(),
             all_lits: FxHashSet::default(),
+            neqs: vec!(),
             tasks: VecDeque::new(),
         };
         // be sure to add true and false
@@ -121,9 +143,17 @@ impl<'a, C:Ctx> Solve<'a, C> {
     }
 
     /// entry point
-    pub fn check_internal(&mut self, ops: &[Op<C>]) -> bool {
+    ///
+    /// Stops early, without conflict, if `canceled()` becomes true.
+    pub fn check_internal<F>(&mut self, ops: &[Op<C>], canceled: F) -> bool
+        where F: Fn() -> bool
+    {
         trace!("naive-cc.check (ops: {:?})", ops);
         for op in ops.iter() {
+            if canceled() {
+                trace!("naive-cc: canceled");
+                return true
+            }
             let ok = self.perform_op(op);
             if !ok {
                 // build conflict (all literals used so far, negated)
@@ -142,7 +172,15 @@ impl<'a, C:Ctx> Solve<'a, C> {
             Op::Merge(a,b,lit) => {
                 // add terms, then merge
                 self.merge(*a,*b,*lit)
-            }
+            },
+            Op::Neq(a,b,lit) => {
+                self.add_term(*a);
+                self.add_term(*b);
+                self.fixpoint();
+                self.all_lits.insert(*lit);
+                self.neqs.push((*a,*b));
+                self.is_consistent()
+            },
         }
     }
 
@@ -162,21 +200,25 @@ impl<'a, C:Ctx> Solve<'a, C> {
     fn merge(&mut self, a: C::AST, b: C::AST, lit: C::B) -> bool {
         self.add_term(a);
         self.add_term(b);
+        self.fixpoint(); // new terms might be congruent to existing ones
 
         let ra = self.find(a);
         let rb = self.find(b);
 
-        if ra == rb {
-            true
-        } else {
+        if ra != rb {
             trace!("merge {:?} and {:?}", pp_t(self.m,&ra.0), pp_t(self.m,&rb.0));
             self.all_lits.insert(lit); // may be involved in conflict
 
             self.tasks.push_back(Task::Merge(a,b));
             self.fixpoint();
-            let ok = ! self.is_eq(&self.true_, &self.false_);
-            ok
         }
+        self.is_consistent()
+    }
+
+    // are `true` and `false`, and each pair of `neqs`, in distinct classes?
+    fn is_consistent(&self) -> bool {
+        ! self.is_eq(&self.true_, &self.false_) &&
+            self.neqs.iter().all(|(a,b)| ! self.is_eq(a,b))
     }
 
     // are `a` and `b` equal?
@@ -197,7 +239,7 @@ impl<'a, C:Ctx> Solve<'a, C> {
             self.tasks.push_back(Task::UpdateTerm(t));
 
             // add arguments to CC, and add `t` to its arguments' parents lists
-            match self.m.view_as_cc_term(&t) {
+            match view_cc(self.m, &t) {
                 CCView::Apply(_, args) | CCView::Distinct(args) => {
                     for &u in args.iter() {
                         self.add_term(u);
@@ -212,6 +254,12 @@ impl<'a, C:Ctx> Solve<'a, C> {
                         self.add_term(u);
                         self.parents.get_mut(&self.find(u)).unwrap().push(t);
                     }
+                    // a chain of `apply` is also a parent of its head and arguments
+                    if let Some((f, args)) = self.apply_chain(&t) {
+                        for &u in Some(&f).into_iter().chain(args.iter()) {
+                            self.parents.get_mut(&self.find(u)).unwrap().push(t);
+                        }
+                    }
                     self.update_term(&t);
                 },
                 CCView::Not(&a) => {
@@ -255,11 +303,24 @@ impl<'a, C:Ctx> Solve<'a, C> {
         self.parents.get(&r).unwrap().len()
     }
 
+    // `(head, args)` if `t` is a chain of `apply`
+    fn apply_chain(&self, t: &C::AST) -> Option<(C::AST, SVec<C::AST>)> {
+        self.apply.and_then(|a| view_apply_chain(self.m, &a, t))
+    }
+
     // are t and u congruent?
     fn congruent(&self, t: &C::AST, u: &C::AST) -> bool {
         if *t == *u { return true }
 
-        match (self.m.view_as_cc_term(t), self.m.view_as_cc_term(u)) {
+        if let (Some((f1, args1)), Some((f2, args2))) = (self.apply_chain(t), self.apply_chain(u)) {
+            let eq_chain =
+                args1.len() == args2.len() &&
+                self.is_eq(&f1, &f2) &&
+                args1.iter().zip(args2.iter()).all(|(u1,u2)| self.is_eq(u1,u2));
+            if eq_chain { return true }
+        }
+
+        match (view_cc(self.m, t), view_cc(self.m, u)) {
             (CCView::Apply(f1, args1), CCView::Apply(f2, args2)) => {
                 args1.len() == args2.len() &&
                     f1 == f2 &&
@@ -309,7 +370,7 @@ impl<'a, C:Ctx> Solve<'a, C> {
         }
 
         for &t in parents_a.iter().chain(parents_b.iter()) {
-            match self.m.view_as_cc_term(&t) {
+            match view_cc(self.m, &t) {
                 CCView::Eq(a,b) if self.is_eq(a,b) => {
                     // `a=b` where a and b are merged --> merge with true
                     new_congr.push((t, self.m.get_bool_term(true)));
@@ -359,14 +420,18 @@ impl<'a, C:Ctx> Solve<'a, C> {
     //
     // Look for these based on t's arguments' parents
     fn update_term(&mut self, t: &C::AST) {
-        match self.m.view_as_cc_term(t) {
+        match view_cc(self.m, t) {
             CCView::Bool(_) | CCView::Opaque(_) => (),
             CCView::Apply(_, args) | CCView::Distinct(args) => {
                 self.update_term_with_args(t, args)
             },
             CCView::ApplyHO(f, args) => {
                 self.update_term_with_args(t, &[*f]);
-                self.update_term_with_args(t, args)
+                self.update_term_with_args(t, args);
+                if let Some((f, args)) = self.apply_chain(t) {
+                    self.update_term_with_args(t, &[f]);
+                    self.update_term_with_args(t, &args)
+                }
             },
             CCView::Not(a) => {
                 if self.is_eq(a, &self.true_) { self.push_congruence(*t, self.false_) }
@@ -387,6 +452,7 @@ impl<C:Ctx> fmt::Debug for Op<C> {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Op::Merge(a,b,lit) => write!(out, "merge({:?},{:?},{:?})",a,b,lit),
+            Op::Neq(a,b,lit) => write!(out, "neq({:?},{:?},{:?})",a,b,lit),
         }
     }
 }
@@ -395,15 +461,16 @@ impl<C:Ctx> Clone for Op<C> {
     fn clone(&self) -> Self {
         match self {
             Op::Merge(a,b,c) => Op::Merge(*a,*b,*c),
+            Op::Neq(a,b,c) => Op::Neq(*a,*b,*c),
         }
     }
 }
 
 impl<C:Ctx> Clone for NaiveCC<C> {
     fn clone(&self) -> Self {
-        let NaiveCC {confl, ops} = self;
+        let NaiveCC {confl, ops, apply} = self;
         NaiveCC {
-            confl: confl.clone(), ops: ops.clone()
+            confl: confl.clone(), ops: ops.clone(), apply: *apply,
         }
     }
 }
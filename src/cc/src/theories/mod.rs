@@ -17,5 +17,11 @@ pub use {
     th_constructor_select::ConstructorSelect,
 };
 
+/// Inline capacity of the theories' `SVec`.
+#[cfg(not(feature="svec-large"))]
+pub const SVEC_SIZE: usize = 4;
+#[cfg(feature="svec-large")]
+pub const SVEC_SIZE: usize = 8;
+
 /// A local small-vec
-pub(crate) type SVec<T> = smallvec::SmallVec<[T; 4]>;
+pub(crate) type SVec<T> = smallvec::SmallVec<[T; SVEC_SIZE]>;
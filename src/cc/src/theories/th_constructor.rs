@@ -27,6 +27,7 @@ pub struct Constructor<F:Eq+Clone> {
 impl<C, F:Eq+Hash+Clone> Backtrackable<C> for Constructor<F> {
     fn push_level(&mut self, _: &mut C) { self.repr.push_level() }
     fn pop_levels(&mut self, _: &mut C, n: usize) { self.repr.pop_levels(n) }
+    fn n_levels(&self) -> usize { self.repr.n_levels() }
 }
 
 impl<C> MicroTheory<C> for Constructor<<C as HasConstructor<AST>>::F>
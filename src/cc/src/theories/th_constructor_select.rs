@@ -29,6 +29,7 @@ pub struct ConstructorSelect<F:Eq+Clone> {
 impl<C, F:Eq+Hash+Clone> Backtrackable<C> for ConstructorSelect<F> {
     fn push_level(&mut self, _: &mut C) { self.repr.push_level() }
     fn pop_levels(&mut self, _: &mut C, n: usize) { self.repr.pop_levels(n) }
+    fn n_levels(&self) -> usize { self.repr.n_levels() }
 }
 
 impl<F: Eq+Hash+Clone> ConstructorSelect<F> {
@@ -66,6 +67,7 @@ impl<C> MicroTheory<C> for ConstructorSelect<<C as HasConstructorSelect<AST>>::F
                         Some(st) => {
                             let mut st = st.clone();
                             st.sel.push((f.clone(), t.clone()));
+                            crate::svec_sites::SELECTORS.record(st.sel.len());
                             st
                         },
                         None => {
@@ -224,6 +226,7 @@ impl<C> MicroTheory<C> for ConstructorSelect<<C as HasConstructorSelect<AST>>::F
                     self.repr.update(&n1, move |_,v1_opt| {
                         let mut s = v1_opt.unwrap().clone();
                         s.sel.extend(st2_sel.iter().cloned());
+                        crate::svec_sites::SELECTORS.record(s.sel.len());
                         if let Some(new_cstor) = new_cstor_opt {
                             s.cstor = Some(new_cstor);
                         }
@@ -15,6 +15,7 @@ pub struct Disjointness<F:Clone+Eq> {
 impl<C, F:Eq+Clone> Backtrackable<C> for Disjointness<F> {
     fn push_level(&mut self, _: &mut C) { self.label.push_level() }
     fn pop_levels(&mut self, _: &mut C, n: usize) { self.label.pop_levels(n) }
+    fn n_levels(&self) -> usize { self.label.n_levels() }
 }
 
 impl<C> MicroTheory<C> for Disjointness<<C as HasDisjointness<AST>>::F>
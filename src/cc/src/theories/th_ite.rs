@@ -7,15 +7,18 @@ use {
 };
 
 /// Theory of `if then else`.
-pub struct Ite;
+pub struct Ite {
+    levels: usize, // no other state
+}
 
 impl<C> Backtrackable<C> for Ite {
-    fn push_level(&mut self, _: &mut C) {}
-    fn pop_levels(&mut self, _: &mut C, _: usize) {}
+    fn push_level(&mut self, _: &mut C) { self.levels += 1 }
+    fn pop_levels(&mut self, _: &mut C, n: usize) { self.levels -= n }
+    fn n_levels(&self) -> usize { self.levels }
 }
 
 impl<C> MicroTheory<C> for Ite where C: Ctx + HasIte<AST> {
-    fn init(_m: &mut C) -> Self { Ite }
+    fn init(_m: &mut C) -> Self { Ite{levels: 0} }
 
     fn on_sig_update(&mut self, c: &mut C, acts: &mut MicroTheoryArg<C>, t: &AST, n_t: NodeID)
     {
@@ -36,6 +36,7 @@ impl<C:Ctx, F:Eq+Hash+Clone> Backtrackable<C> for Selector<F> {
         self.inj.pop_levels(c, n);
         self.sel.pop_levels(n);
     }
+    fn n_levels(&self) -> usize { self.sel.n_levels() }
 }
 
 impl<C> MicroTheory<C> for Selector<<C as HasInjectivity<AST>>::F>
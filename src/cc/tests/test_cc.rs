@@ -2,9 +2,8 @@
 #[macro_use] extern crate proptest;
 
 use {
-    std::{rc::Rc, fmt},
-    fxhash::FxHashMap,
-    batsmt_core::{ast::{self, HasManager, },AstView,backtrack::*, ast_u32::{self, AST}},
+    std::fmt,
+    batsmt_core::{ast::HasManager,AstView,backtrack::*, ast_u32::{self, AST}, AttrKey, AttrValue},
     batsmt_cc::{*, Ctx as CC_ctx},
     batsmt_hast::*,
     batsmt_pretty as pp,
@@ -52,6 +51,9 @@ struct Builtins {
     true_: AST,
     false_: AST,
     eq: AST,
+    eq_alt: Option<AST>, // another equality symbol, with the same meaning
+    not_: AST,
+    distinct: AST,
     ty_u: AST, // default type for everyone
 }
 
@@ -103,6 +105,10 @@ mod ctx {
             if b { self.b().true_ } else { self.b().false_ }
         }
 
+        fn is_not_term(&self, f: &AST) -> bool { *f == self.b().not_ }
+
+        fn is_commutative_term(&self, f: &AST) -> bool { self.m.has_attr(f, AttrKey::COMMUTATIVE) }
+
         fn view_as_cc_term<'a>(&'a self, t: &'a AST) -> CCView<'a,Self::Fun,AST> {
             if *t == self.b().true_ {
                 CCView::Bool(true)
@@ -111,10 +117,11 @@ mod ctx {
             } else {
                 match self.m.view(t) {
                     AstView::Const(_) | AstView::Index(..) => CCView::Opaque(t),
-                    AstView::App{f, args} if *f == self.b().eq => {
+                    AstView::App{f, args} if *f == self.b().eq || Some(*f) == self.b().eq_alt => {
                         debug_assert_eq!(args.len(), 2);
                         CCView::Eq(&args[0], &args[1])
                     },
+                    AstView::App{f, args} if *f == self.b().distinct => CCView::Distinct(args),
                     AstView::App{f,args} => CCView::ApplyHO(f,args),
                 }
             }
@@ -125,102 +132,68 @@ mod ctx {
 type CC0 = CC<Ctx, ()>;
 type NaiveCC0 = NaiveCC<Ctx>;
 
+// a context with builtins, for unit tests
+fn mk_ctx() -> Ctx {
+    let mut m = M::new();
+    let bool_ = m.mk_str("Bool", None);
+    let b = Builtins{
+        true_: m.mk_str("true", Some(bool_)),
+        false_: m.mk_str("false", Some(bool_)),
+        eq: m.mk_str("=", None),
+        eq_alt: None,
+        not_: m.mk_str("not", None),
+        distinct: m.mk_str("distinct", None),
+        ty_u: m.mk_str("ty_u", None),
+        bool_,
+    };
+    Ctx{m, b: Some(b)}
+}
+
 // generate a series of operations for the congruence closure
 mod prop_cc {
-    use {super::*, batsmt_core::ast::Manager, proptest::{prelude::*,test_runner::Config}};
-
-    /// Context for generating terms
-    #[derive(Clone)]
-    struct AstGen(Rc<std::cell::RefCell<AstGenCell>>);
-
-    struct AstGenCell {
-        m: Ctx,
-        b: Option<Builtins>,
-        consts: FxHashMap<String, AST>,
-    }
-
-    impl AstGenCell {
-        fn string(&mut self, s: String, ty: Option<AST>) -> AST {
-            let c = &self.consts;
-            match c.get(&s) {
-                Some(t) => *t,
-                None => {
-                    let t = self.m.m_mut().mk_string(s.clone(), ty);
-                    drop(c); // before the borrow
-                    self.consts.insert(s, t);
-                    t
-                }
-            }
-        }
-        fn str(&mut self, s: &str, ty: Option<AST>) -> AST { self.string(s.to_string(), ty) }
-        fn b(&self) -> Builtins { self.b.unwrap() }
-    }
-
-    impl AstGen {
-        fn new(m: M) -> Self {
-            let consts = FxHashMap::default();
-            let m = Ctx{m, b:None};
-            let mut cell = AstGenCell { m, consts, b: None };
-            // make builtins
-            let bool_ = cell.str("Bool", None);
-            let b = Builtins{
-                true_: cell.str("true", Some(bool_)),
-                false_: cell.str("false", Some(bool_)),
-                eq: cell.str("=", None),
-                ty_u: cell.str("ty_u", None),
-                bool_,
-            };
-            cell.m.b = Some(b);
-            cell.b = Some(b);
-            AstGen(Rc::new(std::cell::RefCell::new(cell)))
-        }
-        fn app(&self, f: AST, args: &[AST]) -> AST {
-            let mut m = self.0.borrow_mut();
-            let ty_u = m.b().ty_u;
-            m.m.mk_app(f, args, Some(ty_u))
-        }
-    }
+    use {
+        super::*, std::{env, fs, panic}, batsmt_core::ast::Manager, batsmt_theory::replay,
+        batsmt_test_utils as test_utils,
+        proptest::{prelude::*,test_runner::Config},
+    };
 
-    impl ast::HasManager for AstGenCell {
-        type M = Ctx;
-        fn m(&self) -> &Self::M { &self.m }
-        fn m_mut(&mut self) -> &mut Self::M { &mut self.m }
-    }
+    type AstGen = test_utils::AstGen<Ctx>;
+    type AstGenCell = test_utils::AstGenCell<Ctx>;
 
-    // just so we can `prop_map` on it
-    impl fmt::Debug for AstGen {
-        fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result { write!(out, "astgen") }
+    // a generator whose context has its builtins
+    fn new_astgen() -> AstGen {
+        let g = AstGen::new(Ctx{m: M::new(), b: None});
+        let bool_ = g.str("Bool", None);
+        let b = Builtins{
+            true_: g.str("true", Some(bool_)),
+            false_: g.str("false", Some(bool_)),
+            eq: g.str("=", None),
+            eq_alt: None,
+            not_: g.str("not", None),
+            distinct: g.str("distinct", None),
+            ty_u: g.str("ty_u", None),
+            bool_,
+        };
+        g.0.borrow_mut().m.b = Some(b);
+        g
     }
 
-    fn with_astgen<F,T>(mut f: F) -> BoxedStrategy<(AstGen,T)>
+    fn with_astgen<F,T>(f: F) -> BoxedStrategy<(AstGen,T)>
         where F: FnMut(&AstGen) -> BoxedStrategy<T>, T: 'static+fmt::Debug
     {
-        let m = AstGen::new(HManager::new());
-        f(&m)
-            .prop_map(move |t| (m.clone(), t))
-            .boxed()
+        test_utils::with_astgen(new_astgen(), f)
     }
 
     /// Random generator of terms
-    fn gen_term(m: &AstGen) -> BoxedStrategy<AST> {
-        let m = m.clone();
-        let ty_u = m.0.borrow_mut().b().ty_u;
-        let leaf = {
-            let m2 = m.clone();
-            "f|g|a|b|c|d".prop_map(move |s| m2.0.borrow_mut().string(s, Some(ty_u)))
-        };
-        // see https://docs.rs/proptest/*/proptest/#generating-recursive-data
-        leaf.prop_recursive(
-            8, 512, 10,
-            move |inner| {
-                let m2 = m.clone();
-                (inner.clone(),prop::collection::vec(inner.clone(), 0..6)).
-                    prop_map(move |(f,args)| m2.app(f,&args))
-            }).boxed()
+    ///
+    /// If `apply` is true, terms also contain curried applications `apply(t,u)`.
+    fn gen_term(m: &AstGen, apply: bool) -> BoxedStrategy<AST> {
+        let apply = if apply { Some("apply".to_string()) } else { None };
+        test_utils::gen_term(m, &test_utils::GenConfig{apply, ..Default::default()})
     }
 
     prop_compose! {
-        fn gen_term2(m: &AstGen)(t1 in gen_term(m), t2 in gen_term(m)) -> (AST,AST) {
+        fn gen_term2(m: &AstGen, apply: bool)(t1 in gen_term(m, apply), t2 in gen_term(m, apply)) -> (AST,AST) {
             (t1,t2)
         }
     }
@@ -247,12 +220,12 @@ mod prop_cc {
     }
 
     // FIXME: first, allocate a vec of terms, then use `prop_flat_map` to sample terms
-    fn cc_op(m: &AstGen) -> BoxedStrategy<Op> {
+    fn cc_op(m: &AstGen, apply: bool) -> BoxedStrategy<Op> {
         prop_oneof![
             2 => Just(Op::PushLevel),
             1 => (1..5usize).prop_map(Op::PopLevels),
-            7 => gen_term2(m).prop_map(|(t1,t2)| Op::AssertEq(t1,t2)),
-            3 => gen_term2(m).prop_map(|(t1,t2)| Op::AssertNeq(t1,t2)),
+            7 => gen_term2(m, apply).prop_map(|(t1,t2)| Op::AssertEq(t1,t2)),
+            3 => gen_term2(m, apply).prop_map(|(t1,t2)| Op::AssertNeq(t1,t2)),
             1 => Just(Op::PartialCheck),
             1 => Just(Op::FinalCheck),
         ].boxed()
@@ -260,7 +233,12 @@ mod prop_cc {
 
     // generates a vector of ops (size `i`)
     fn cc_ops(m: &AstGen, len: usize) -> BoxedStrategy<Vec<Op>> {
-        prop::collection::vec(cc_op(m), 0..len)
+        cc_ops_with(m, len, false)
+    }
+
+    // same as `cc_ops`, with curried applications if `apply` is true
+    fn cc_ops_with(m: &AstGen, len: usize, apply: bool) -> BoxedStrategy<Vec<Op>> {
+        prop::collection::vec(cc_op(m, apply), 0..len)
             .prop_filter("invalid sequence of CC operations".to_string(), |v| ops_valid(&v))
             .boxed()
     }
@@ -277,10 +255,9 @@ mod prop_cc {
             let lit = lit.into();
             let TermLit(sign,t1,t2) = lit;
             if sign {
-                ncc.merge(ctx,t1,t2,lit)
+                ncc.merge(ctx,t1,t2,lit).unwrap()
             } else {
-                let eqn = ctx.mk_app(ctx.b().eq, &[t1,t2], Some(ctx.b().bool_)); // `t1=t2`
-                ncc.merge(ctx,eqn, ctx.b().false_, lit)
+                ncc.assert_neq(ctx,t1,t2,lit).unwrap()
             }
         }
 
@@ -292,6 +269,43 @@ mod prop_cc {
         ! check_lits_sat(m, cube.iter().cloned())
     }
 
+    // the oracle itself must classify these cubes correctly
+    #[test]
+    fn test_oracle_known_cubes() {
+        let g = new_astgen();
+        let m = &mut g.0.borrow_mut();
+        let b = m.m.b();
+        let (a, b_, c, d, f) = (
+            m.str("a", Some(b.ty_u)), m.str("b", Some(b.ty_u)), m.str("c", Some(b.ty_u)),
+            m.str("d", Some(b.ty_u)), m.str("f", Some(b.ty_u)));
+        let ctx = &mut m.m;
+        let mut app = |f, args: &[AST], ty| ctx.mk_app(f, args, Some(ty));
+        let (fa, fb, fc) = (app(f, &[a], b.ty_u), app(f, &[b_], b.ty_u), app(f, &[c], b.ty_u));
+        let (eq_ab, eq_cd) = (app(b.eq, &[a, b_], b.bool_), app(b.eq, &[c, d], b.bool_));
+        let eq_eq = app(b.eq, &[eq_ab, eq_cd], b.bool_);
+        let (eq, neq) = (TermLit::mk_eq, TermLit::mk_neq);
+
+        let cubes = vec![
+            (true, vec![eq(a,b_), neq(fa,fc)]),
+            (false, vec![eq(a,b_), neq(fa,fb)]),
+            (false, vec![eq(a,b_), eq(b_,c), neq(a,c)]),
+            (true, vec![neq(a,b_), neq(b_,c), eq(a,c)]),
+            (false, vec![eq(fa,c), eq(a,b_), neq(fb,c)]),
+            (false, vec![neq(a,a)]),
+            // nested equality terms
+            (false, vec![eq(a,b_), neq(eq_ab,b.true_)]),
+            (true, vec![neq(eq_ab,eq_cd)]),
+            (false, vec![eq(a,b_), eq(c,d), neq(eq_ab,eq_cd)]),
+            (true, vec![eq(a,b_), neq(eq_eq,b.true_)]),
+            (false, vec![eq(a,b_), eq(c,d), neq(eq_eq,b.true_)]),
+            (false, vec![eq(a,b_), eq(eq_ab,eq_cd), neq(c,d), eq(eq_cd,b.false_)]),
+        ];
+        for (sat, cube) in cubes {
+            assert_eq!(check_lits_sat(m, cube.iter().cloned()), sat,
+                "cube {}", pp::display(pp::sexp_iter(cube.iter().map(|x| pp::pp1(&m.m,x)))));
+        }
+    }
+
     // test that NaiveCC's backtracking behavior is consistent
     proptest! {
         #![proptest_config(Config::with_cases(100))]
@@ -323,15 +337,13 @@ mod prop_cc {
                         let ctx = &mut m.m;
                         let lit = TermLit::mk_eq(t1,t2);
                         st.push((t1,t2,lit));
-                        ncc.merge(ctx, t1,t2,lit);
+                        ncc.merge(ctx, t1,t2,lit).unwrap();
                     },
                     Op::AssertNeq(t1,t2) => {
                         let ctx = &mut m.m;
                         let lit = TermLit::mk_neq(t1,t2);
-                        let eqn = ctx.mk_app(ctx.b().eq, &[t1,t2], Some(ctx.b().bool_)); // term `t1=t2`
-                        st.push((eqn, ctx.b().false_, lit));
-
-                        ncc.merge(ctx, eqn, ctx.b().false_, lit);
+                        st.push((t1,t2,lit));
+                        ncc.assert_neq(ctx, t1,t2,lit).unwrap();
                     },
                     Op::PartialCheck => (), // do nothing
                     Op::FinalCheck => {
@@ -353,9 +365,8 @@ mod prop_cc {
                         prop_assert_eq!(sat1, sat2, "ncc-incremental.sat: {}, ncc-fresh.sat: {}", sat1, sat2);
 
                         // conflict returned by `ncc`, if any, must be valid
-                        if let Err(confl) = r_ncc {
-                            let lits = confl.iter().map(|lit| ! *lit);
-                            let confl_sat = check_lits_sat(m, lits);
+                        if r_ncc.is_err() {
+                            let confl_sat = check_lits_sat(m, acts.negated_conflict());
 
                             prop_assert!(! confl_sat, "ncc-incremental.conflict is sat");
                         }
@@ -373,125 +384,261 @@ mod prop_cc {
         fn proptest_cc_is_correct(ref tup in with_astgen(|m| cc_ops(m, 120))) {
             let (m, ops) = tup;
             let m = &mut m.0.borrow_mut();
-            let mut stack = Stack::new(); // keep current set of ops
+            check_or_export(m, ops)?;
+        }
+    }
 
-            //println!("ops: {:?}", ops);
+    // same, with chains of `apply` compared as a whole by both implementations
+    proptest! {
+        #![proptest_config(Config::with_cases(80))]
+        #[test]
+        fn proptest_cc_apply_is_correct(ref tup in with_astgen(|m| cc_ops_with(m, 120, true))) {
+            let (m, ops) = tup;
+            let m = &mut m.0.borrow_mut();
+            let apply = {
+                let ty_u = m.m.b().ty_u;
+                m.str("apply", Some(ty_u))
+            };
+            check_cc_against_naive(m, ops, Some(apply))?;
+        }
+    }
 
-            let mut cc = CC0::new(&mut m.m);
-            let mut ncc = NaiveCC0::new(&mut m.m);
-            let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
-            let mut nacts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+    // `check_cc_against_naive`, but if it fails and `BATSMT_CC_LOG` is set,
+    // also write `ops` to this file as a replay log (see `test_replay.rs`).
+    // Proptest overwrites the file with each smaller failing case.
+    fn check_or_export(m: &mut AstGenCell, ops: &[Op]) -> Result<(), TestCaseError> {
+        let res = panic::catch_unwind(panic::AssertUnwindSafe(|| check_cc_against_naive(m, ops, None)));
+        if res.as_ref().map_or(true, |r| r.is_err()) {
+            if let Ok(path) = env::var("BATSMT_CC_LOG") {
+                let mut out = vec!();
+                log_of_ops(m, ops).write(&mut out).unwrap();
+                fs::write(&path, out).unwrap();
+            }
+        }
+        match res {
+            Ok(r) => r,
+            Err(e) => panic::resume_unwind(e),
+        }
+    }
 
-            // add literals, for propagations
-            for &op in ops.iter() {
-                match op {
-                    Op::AssertEq(t1,t2) | Op::AssertNeq(t1,t2) => {
+    // the calls of `ops` into a `CCTheory`, one check per asserted literal
+    fn log_of_ops(m: &mut AstGenCell, ops: &[Op]) -> replay::Log {
+        let ctx = &mut m.m;
+        let mut b = replay::LogBuilder::new(false);
+        let mut atoms = vec!();
+        for &op in ops.iter() {
+            let ((t1,t2), sign) = match op {
+                Op::AssertEq(t1,t2) => ((t1,t2), true),
+                Op::AssertNeq(t1,t2) => ((t1,t2), false),
+                _ => continue,
+            };
+            let eqn = ctx.mk_app(ctx.b().eq, &[t1,t2], Some(ctx.b().bool_));
+            let lit = TermLit::mk_eq(t1,t2);
+            let ev = replay::Event::AddLiteral(b.term(ctx, eqn), b.lit(lit));
+            b.push(ev);
+            atoms.push((eqn, sign, lit.apply_sign(sign)));
+        }
+        let mut atoms = atoms.into_iter();
+        for &op in ops.iter() {
+            let ev = match op {
+                Op::PushLevel => replay::Event::PushLevel,
+                Op::PopLevels(n) => replay::Event::PopLevels(n),
+                Op::AssertEq(..) | Op::AssertNeq(..) => {
+                    let trail = b.trail(ctx, &[atoms.next().unwrap()]);
+                    replay::Event::PartialCheck(trail, replay::Outcome::default())
+                },
+                Op::PartialCheck => replay::Event::PartialCheck(vec!(), replay::Outcome::default()),
+                Op::FinalCheck => replay::Event::FinalCheck(vec!(), replay::Outcome::default()),
+            };
+            b.push(ev);
+        }
+        b.into_log()
+    }
+
+    // run `ops` on CC and NaiveCC, check they agree, and check the
+    // conflicts and propagations of CC using a fresh NaiveCC
+    fn check_cc_against_naive(
+        m: &mut AstGenCell, ops: &[Op], apply: Option<AST>
+    ) -> Result<(), TestCaseError> {
+        let mut stack = Stack::new(); // keep current set of ops
+
+        //println!("ops: {:?}", ops);
+
+        let mut cc = CC0::new(&mut m.m);
+        let mut ncc = NaiveCC0::new(&mut m.m);
+        if let Some(apply) = apply {
+            cc.declare_apply_symbol(apply);
+            ncc.declare_apply_symbol(apply);
+        }
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        let mut nacts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+
+        // add literals, for propagations
+        for &op in ops.iter() {
+            match op {
+                Op::AssertEq(t1,t2) | Op::AssertNeq(t1,t2) => {
+                    let ctx = &mut m.m;
+                    let lit = TermLit::mk_eq(t1,t2);
+                    let eqn = ctx.mk_app(ctx.b().eq, &[t1,t2], Some(ctx.b().bool_));
+                    cc.add_literal(ctx, eqn, lit);
+                },
+                _ => (),
+            }
+        }
+
+        for &op in ops.iter() {
+            match op {
+                Op::PushLevel => {
+                    let ctx = &mut m.m;
+                    cc.push_level(ctx);
+                    ncc.push_level(ctx);
+                    stack.push_level();
+                },
+                Op::PopLevels(n) => {
+                    let ctx = &mut m.m;
+                    acts.clear();
+                    nacts.clear();
+                    cc.pop_levels(ctx,n);
+                    ncc.pop_levels(ctx,n);
+                    stack.pop_levels(n, |_| ());
+                },
+                Op::AssertEq(t1,t2) => {
+                    let ctx = &mut m.m;
+                    let lit = TermLit::mk_eq(t1,t2);
+                    cc.merge(ctx,t1,t2,lit).unwrap();
+                    ncc.merge(ctx,t1,t2,lit).unwrap();
+                    stack.push(lit);
+                },
+                Op::AssertNeq(t1,t2) => {
+                    let ctx = &mut m.m;
+                    let lit = TermLit::mk_neq(t1,t2);
+                    let eqn = ctx.mk_app(ctx.b().eq, &[t1,t2], Some(ctx.b().bool_)); // term `t1=t2`
+                    cc.merge(ctx,eqn, ctx.b().false_, lit).unwrap();
+                    ncc.assert_neq(ctx,t1,t2,lit).unwrap();
+                    stack.push(lit);
+                },
+                Op::PartialCheck => {
+                    let sat1 = {
                         let ctx = &mut m.m;
-                        let lit = TermLit::mk_eq(t1,t2);
-                        let eqn = ctx.mk_app(ctx.b().eq, &[t1,t2], Some(ctx.b().bool_));
-                        cc.add_literal(ctx, eqn, lit);
-                    },
-                    _ => (),
+                        cc.partial_check(ctx, &mut acts);
+                        acts.get().is_ok()
+                    };
+
+                    if sat1 {
+                        // check each propagation using a copy of `ncc`
+                        for lit in acts.drain_props() {
+                            check_propagation(m, lit, stack.as_slice());
+                            let expl = {
+                                let ctx = &mut m.m;
+                                let r = cc.explain_prop(ctx, lit);
+                                drop(ctx);
+                                r
+                            };
+                            check_propagation_expl(m, lit, expl);
+                        }
+                    } else {
+                        // check conflict, using a fresh new naiveCC
+                        check_confl(m, acts.get().unwrap_err());
+                    }
+                },
+                Op::FinalCheck => {
+                    // here be the main check
+                    let (sat1,sat2) = {
+                        let ctx = &mut m.m;
+                        cc.final_check(ctx, &mut acts);
+                        ncc.final_check(ctx, &mut nacts);
+                        (acts.get().is_ok(),nacts.get().is_ok())
+                    };
+
+                    // must agree on satisfiability
+                    prop_assert_eq!(sat1, sat2, "cc.sat: {}, ncc.sat: {}", sat1, sat2);
+
+                    if sat1 {
+                        // check each propagation using a copy of `ncc`
+                        for lit in acts.drain_props() {
+                            check_propagation(m, lit, &stack.as_slice());
+                            let expl = {
+                                let ctx = &mut m.m;
+                                let r = cc.explain_prop(ctx, lit);
+                                drop(ctx);
+                                r
+                            };
+                            check_propagation_expl(m, lit, expl);
+                        }
+                    } else {
+                        // check conflict, using a fresh new naiveCC
+                        check_confl(m, acts.get().unwrap_err());
+                    }
                 }
+            };
+
+            batsmt_core::debug_assert_levels_in_sync!(
+                "cc" => cc.n_levels(), "naive-cc" => ncc.n_levels(), "ops" => stack.n_levels());
+
+            // assertions leave merges pending, other operations complete them
+            match op {
+                Op::AssertEq(..) | Op::AssertNeq(..) => (),
+                _ => {
+                    if let Err(e) = cc.check_invariants(&m.m) {
+                        return Err(TestCaseError::fail(format!("after {:?}: {}", op, e)))
+                    }
+                },
             }
+        }
+        Ok(())
+    }
+
+    // repeated fuel-limited checks reach the same state as one unbounded check
+    proptest! {
+        #![proptest_config(Config::with_cases(50))]
+        #[test]
+        fn proptest_cc_fuel(ref tup in with_astgen(|m| cc_ops(m, 80)), fuel in 1..5usize) {
+            let (m, ops) = tup;
+            let m = &mut m.0.borrow_mut();
+            let mut terms = vec!();
 
+            let ctx = &mut m.m;
+            let mut cc = CC0::new(ctx);
+            let mut cc_fuel = CC0::new(ctx);
             for &op in ops.iter() {
-                match op {
-                    Op::PushLevel => {
-                        let ctx = &mut m.m;
-                        cc.push_level(ctx);
-                        ncc.push_level(ctx);
-                        stack.push_level();
-                    },
-                    Op::PopLevels(n) => {
-                        let ctx = &mut m.m;
-                        acts.clear();
-                        nacts.clear();
-                        cc.pop_levels(ctx,n);
-                        ncc.pop_levels(ctx,n);
-                        stack.pop_levels(n, |_| ());
-                    },
-                    Op::AssertEq(t1,t2) => {
-                        let ctx = &mut m.m;
-                        let lit = TermLit::mk_eq(t1,t2);
-                        cc.merge(ctx,t1,t2,lit);
-                        ncc.merge(ctx,t1,t2,lit);
-                        stack.push(lit);
-                    },
+                let (t1, t2, lit) = match op {
+                    Op::AssertEq(t1,t2) => (t1, t2, TermLit::mk_eq(t1,t2)),
                     Op::AssertNeq(t1,t2) => {
-                        let ctx = &mut m.m;
-                        let lit = TermLit::mk_neq(t1,t2);
-                        let eqn = ctx.mk_app(ctx.b().eq, &[t1,t2], Some(ctx.b().bool_)); // term `t1=t2`
-                        cc.merge(ctx,eqn, ctx.b().false_, lit);
-                        ncc.merge(ctx,eqn, ctx.b().false_, lit);
-                        stack.push(lit);
+                        let eqn = ctx.mk_app(ctx.b().eq, &[t1,t2], Some(ctx.b().bool_));
+                        (eqn, ctx.b().false_, TermLit::mk_neq(t1,t2))
                     },
-                    Op::PartialCheck => {
-                        let r1 = {
-                            let ctx = &mut m.m;
-                            cc.partial_check(ctx, &mut acts);
-                            acts.get()
-                        };
+                    _ => continue,
+                };
+                terms.push(t1);
+                terms.push(t2);
+                cc.merge(ctx,t1,t2,lit).unwrap();
+                cc_fuel.merge(ctx,t1,t2,lit).unwrap();
+            }
 
-                        match r1 {
-                            Ok((props,_)) => {
-                                let props = props.clone();
-                                drop(r1);
-                                // check each propagation using a copy of `ncc`
-                                for lit in props.iter().cloned() {
-                                    check_propagation(m, lit, stack.as_slice());
-                                    let expl = {
-                                        let ctx = &mut m.m;
-                                        let r = cc.explain_prop(ctx, lit);
-                                        drop(ctx);
-                                        r
-                                    };
-                                    check_propagation_expl(m, lit, expl);
-                                }
-                            },
-                            Err(confl) => {
-                                // check conflict, using a fresh new naiveCC
-                                check_confl(m, &confl);
-                            }
-                        }
-                    },
-                    Op::FinalCheck => {
-                        // here be the main check
-                        let (r1,r2) = {
-                            let ctx = &mut m.m;
-                            cc.final_check(ctx, &mut acts);
-                            ncc.final_check(ctx, &mut nacts);
-                            (acts.get(),nacts.get())
-                        };
+            let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+            cc.final_check(ctx, &mut acts);
+            let mut acts_fuel = theory::SimpleActions::new(|| unimplemented!("new lit"));
+            let mut n_calls = 0;
+            while !cc_fuel.partial_check_fuel(ctx, &mut acts_fuel, fuel) {
+                n_calls += 1;
+                prop_assert!(n_calls < 100_000, "no progress");
+            }
 
-                        // must agree on satisfiability
-                        let sat1 = r1.is_ok();
-                        let sat2 = r2.is_ok();
-                        prop_assert_eq!(sat1, sat2, "cc.sat: {}, ncc.sat: {}", sat1, sat2);
-
-                        match r1 {
-                            Ok((props,_)) => {
-                                let props = props.clone();
-                                drop(r1);
-                                // check each propagation using a copy of `ncc`
-                                for lit in props.iter().cloned() {
-                                    check_propagation(m, lit, &stack.as_slice());
-                                    let expl = {
-                                        let ctx = &mut m.m;
-                                        let r = cc.explain_prop(ctx, lit);
-                                        drop(ctx);
-                                        r
-                                    };
-                                    check_propagation_expl(m, lit, expl);
-                                }
-                            },
-                            Err(confl) => {
-                                // check conflict, using a fresh new naiveCC
-                                check_confl(m, &confl);
-                            }
+            match acts_fuel.get() {
+                Err(confl) => {
+                    prop_assert!(acts.get().is_err(), "unbounded check is sat");
+                    let confl = confl.to_vec();
+                    check_confl(m, &confl);
+                },
+                Ok(..) => {
+                    prop_assert!(acts.get().is_ok(), "unbounded check is unsat");
+                    for &t1 in terms.iter() {
+                        for &t2 in terms.iter() {
+                            prop_assert_eq!(cc.find(&t1) == cc.find(&t2),
+                                            cc_fuel.find(&t1) == cc_fuel.find(&t2));
                         }
                     }
-                };
+                },
             }
         }
     }
@@ -545,3 +692,1896 @@ mod prop_cc {
             pp::debug(pp::sexp_iter(confl.iter().map(|x| pp::pp1(ctx,x)))));
     }
 }
+
+mod propagation {
+    use {super::*, batsmt_core::ast::Manager};
+
+    #[test]
+    fn test_prop_reason_congruence() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let x = c.m.mk_str("x", Some(b.ty_u));
+        let y = c.m.mk_str("y", Some(b.ty_u));
+        let fx = c.m.mk_app(f, &[x], Some(b.bool_));
+        let fy = c.m.mk_app(f, &[y], Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        cc.enable_propagation(true);
+        let lit_fx = TermLit::mk_eq(fx, b.true_);
+        cc.add_literal(&mut c, fx, lit_fx);
+
+        // `f(y)=true`, `x=y` ==> `f(x)=true` by congruence
+        let lit_fy = TermLit::mk_eq(fy, b.true_);
+        let lit_xy = TermLit::mk_eq(x, y);
+        cc.merge(&mut c, fy, b.true_, lit_fy).unwrap();
+        cc.merge(&mut c, x, y, lit_xy).unwrap();
+
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        let props = acts.get().expect("must be satisfiable").0.to_vec();
+        assert_eq!(&props, &[lit_fx]);
+
+        assert_eq!(cc.propagations().len(), 1);
+        assert_eq!(cc.propagations()[0].lit, lit_fx);
+        assert_eq!(cc.propagations()[0].reason, PropReason::Congruence);
+
+        let mut expl = cc.explain_prop(&c, lit_fx).to_vec();
+        expl.sort();
+        let mut expected = vec![lit_fy, lit_xy];
+        expected.sort();
+        assert_eq!(expl, expected);
+    }
+
+    #[test]
+    fn test_no_prop_if_disabled() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let p = c.m.mk_str("p", Some(b.bool_));
+        let q = c.m.mk_str("q", Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        let lit_p = TermLit::mk_eq(p, b.true_);
+        cc.add_literal(&mut c, p, lit_p);
+        cc.merge(&mut c, q, b.true_, TermLit::mk_eq(q, b.true_)).unwrap();
+        cc.merge(&mut c, p, q, TermLit::mk_eq(p, q)).unwrap();
+
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        let props = acts.get().expect("must be satisfiable").0.to_vec();
+        assert!(props.is_empty(), "{:?}", props);
+    }
+
+    // without propagation, the same merges propagate nothing but still conflict
+    #[test]
+    fn test_congruence_only() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let x = c.m.mk_str("x", Some(b.ty_u));
+        let y = c.m.mk_str("y", Some(b.ty_u));
+        let fx = c.m.mk_app(f, &[x], Some(b.bool_));
+        let fy = c.m.mk_app(f, &[y], Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        cc.enable_propagation(false);
+        let lit_fx = TermLit::mk_eq(fx, b.true_);
+        cc.add_literal(&mut c, fx, lit_fx);
+        let lit_fy = TermLit::mk_eq(fy, b.true_);
+        let lit_xy = TermLit::mk_eq(x, y);
+        cc.merge(&mut c, fy, b.true_, lit_fy).unwrap();
+        cc.merge(&mut c, x, y, lit_xy).unwrap();
+
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        assert!(acts.get().expect("must be satisfiable").0.is_empty());
+        assert!(cc.propagations().is_empty());
+
+        // the solver decides `f(x)=false` instead
+        cc.merge(&mut c, fx, b.false_, !lit_fx).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        let mut confl = acts.get().expect_err("f(x)=f(y)").to_vec();
+        confl.sort();
+        let mut expected = vec![lit_fx, !lit_fy, !lit_xy];
+        expected.sort();
+        assert_eq!(confl, expected);
+    }
+
+    // `f(y)=true`, `x=y` ==> `f(x)=true`, moved out of the actions
+    #[test]
+    fn test_drain_props() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let x = c.m.mk_str("x", Some(b.ty_u));
+        let y = c.m.mk_str("y", Some(b.ty_u));
+        let fx = c.m.mk_app(f, &[x], Some(b.bool_));
+        let fy = c.m.mk_app(f, &[y], Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        cc.enable_propagation(true);
+        let lit_fx = TermLit::mk_eq(fx, b.true_);
+        cc.add_literal(&mut c, fx, lit_fx);
+        cc.merge(&mut c, fy, b.true_, TermLit::mk_eq(fy, b.true_)).unwrap();
+        cc.merge(&mut c, x, y, TermLit::mk_eq(x, y)).unwrap();
+
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        let expected = acts.get().expect("must be satisfiable").0.to_vec();
+        let props: Vec<_> = acts.drain_props().collect();
+        assert_eq!(&props, &[lit_fx]);
+        assert_eq!(props, expected);
+        assert!(acts.get().expect("still satisfiable").0.is_empty());
+    }
+
+    // `f(x_0)=true`, then `x_i=x_{i+1}` in a scrambled order: the propagations
+    // of `f(x_i)=true` are the same, in the same order, at each run
+    #[test]
+    fn test_props_order_is_stable() {
+        let n = 50;
+        let run = || {
+            let mut c = mk_ctx();
+            let b = c.b();
+            let f = c.m.mk_str("f", None);
+            let xs: Vec<AST> =
+                (0..n).map(|i| c.m.mk_str(&format!("x{}", i), Some(b.ty_u))).collect();
+            let fxs: Vec<AST> =
+                xs.iter().map(|x| c.m.mk_app(f, &[*x], Some(b.bool_))).collect();
+
+            let mut cc = CC0::new(&mut c);
+            cc.enable_propagation(true);
+            for &fx in &fxs[1..] {
+                cc.add_literal(&mut c, fx, TermLit::mk_eq(fx, b.true_));
+            }
+            cc.merge(&mut c, fxs[0], b.true_, TermLit::mk_eq(fxs[0], b.true_)).unwrap();
+            for k in 0 .. n-1 {
+                let i = (k * 11) % (n-1);
+                cc.merge(&mut c, xs[i], xs[i+1], TermLit::mk_eq(xs[i], xs[i+1])).unwrap();
+            }
+            let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+            cc.final_check(&mut c, &mut acts);
+            let props = acts.get().expect("must be satisfiable").0.to_vec();
+            let found: Vec<TermLit> = cc.propagations().iter().map(|p| p.lit).collect();
+            assert_eq!(props, found, "given to the solver in the order found");
+            props
+        };
+        let props = run();
+        assert_eq!(props.len(), n-1);
+        for _ in 0 .. 5 {
+            assert_eq!(run(), props);
+        }
+    }
+
+    // `f(x_0)=true`, `x_0=x_1=…=x_n` ==> `f(x_i)=true` for each `i`
+    fn chain_props(n: usize) -> (Ctx, CC0, Vec<TermLit>) {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let xs: Vec<AST> =
+            (0..n).map(|i| c.m.mk_str(&format!("x{}", i), Some(b.ty_u))).collect();
+        let fxs: Vec<AST> =
+            xs.iter().map(|x| c.m.mk_app(f, &[*x], Some(b.bool_))).collect();
+
+        let mut cc = CC0::new(&mut c);
+        cc.enable_propagation(true);
+        for &fx in &fxs[1..] {
+            cc.add_literal(&mut c, fx, TermLit::mk_eq(fx, b.true_));
+        }
+        cc.merge(&mut c, fxs[0], b.true_, TermLit::mk_eq(fxs[0], b.true_)).unwrap();
+        for i in 0 .. n-1 {
+            cc.merge(&mut c, xs[i], xs[i+1], TermLit::mk_eq(xs[i], xs[i+1])).unwrap();
+        }
+
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        let props = acts.get().expect("must be satisfiable").0.to_vec();
+        assert_eq!(props.len(), n-1);
+        (c, cc, props)
+    }
+
+    #[test]
+    fn test_explain_props_batch() {
+        let (c, mut cc, props) = chain_props(1_000);
+        let batch = cc.explain_props(&c, &props);
+        assert_eq!(batch.len(), props.len());
+        for (e1, p) in batch.iter().zip(props.iter()) {
+            let mut e2 = cc.explain_prop(&c, *p).to_vec();
+            e2.sort();
+            e2.dedup();
+            assert_eq!(&e1[..], &e2[..]);
+        }
+    }
+
+    // `p(f(x_0))`, `q(f(x_0))`, `x_0=x_1=…=x_n`: the explanations of
+    // `p(f(x_i))` and `q(f(x_i))` share the links between the `f(x_i)`
+    #[test]
+    fn test_explain_props_shared_links() {
+        let n = 50;
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let p = c.m.mk_str("p", None);
+        let q = c.m.mk_str("q", None);
+        let xs: Vec<AST> =
+            (0..n).map(|i| c.m.mk_str(&format!("x{}", i), Some(b.ty_u))).collect();
+        let fxs: Vec<AST> =
+            xs.iter().map(|x| c.m.mk_app(f, &[*x], Some(b.ty_u))).collect();
+        let atoms: Vec<AST> = fxs.iter().flat_map(|fx| vec![
+            c.m.mk_app(p, &[*fx], Some(b.bool_)), c.m.mk_app(q, &[*fx], Some(b.bool_)),
+        ]).collect();
+
+        let mut cc = CC0::new(&mut c);
+        cc.enable_propagation(true);
+        for &a in &atoms[2..] {
+            cc.add_literal(&mut c, a, TermLit::mk_eq(a, b.true_));
+        }
+        for &a in &atoms[..2] {
+            cc.merge(&mut c, a, b.true_, TermLit::mk_eq(a, b.true_)).unwrap();
+        }
+        // merge in a balanced way, so the links are not all on one path
+        let mut step = 1;
+        while step < n {
+            for i in (0 .. n-step).step_by(2*step) {
+                cc.merge(&mut c, xs[i], xs[i+step], TermLit::mk_eq(xs[i], xs[i+step])).unwrap();
+            }
+            step *= 2;
+        }
+
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        let props = acts.get().expect("must be satisfiable").0.to_vec();
+        assert_eq!(props.len(), 2*(n-1));
+        let batch = cc.explain_props(&c, &props);
+        for (e1, p) in batch.iter().zip(props.iter()) {
+            let mut e2 = cc.explain_prop(&c, *p).to_vec();
+            e2.sort();
+            e2.dedup();
+            assert_eq!(&e1[..], &e2[..]);
+        }
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture explain_props_timing`.
+    //
+    // The loop builds the same sorted explanations as the batch;
+    // each is timed several times, keeping the fastest run.
+    // Nothing is shared between the explanations of a chain, so the batch
+    // can at best match the loop: allow 10% of timer noise.
+    #[test]
+    #[ignore]
+    fn test_explain_props_timing() {
+        use std::time::{Duration, Instant};
+
+        let (c, mut cc, props) = chain_props(1_001);
+        assert_eq!(props.len(), 1_000);
+
+        let (mut t_batch, mut t_loop) = (Duration::MAX, Duration::MAX);
+        let (mut n_batch, mut n_loop) = (0, 0);
+        for _ in 0 .. 5 {
+            let start = Instant::now();
+            let batch = cc.explain_props(&c, &props);
+            t_batch = t_batch.min(Instant::now() - start);
+            n_batch = batch.iter().map(|e| e.len()).sum();
+
+            let start = Instant::now();
+            let looped: Vec<Vec<TermLit>> = props.iter().map(|p| {
+                let mut e = cc.explain_prop(&c, *p).to_vec();
+                e.sort_unstable();
+                e.dedup();
+                e
+            }).collect();
+            t_loop = t_loop.min(Instant::now() - start);
+            n_loop = looped.iter().map(|e| e.len()).sum();
+        }
+
+        eprintln!("explained {} propagations: batch {:?} ({} lits), loop {:?} ({} lits)",
+                  props.len(), t_batch, n_batch, t_loop, n_loop);
+        assert_eq!(n_batch, n_loop);
+        assert!(t_batch <= t_loop + t_loop / 10, "batch {:?} slower than loop {:?}", t_batch, t_loop);
+    }
+}
+
+mod assigned_atoms {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // the atoms bound to literals, with their value and reason, and the classes
+    #[test]
+    fn test_assigned_atoms_and_classes() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let x = c.m.mk_str("x", Some(b.ty_u));
+        let y = c.m.mk_str("y", Some(b.ty_u));
+        let z = c.m.mk_str("z", Some(b.ty_u));
+        let fx = c.m.mk_app(f, &[x], Some(b.bool_));
+        let fy = c.m.mk_app(f, &[y], Some(b.bool_));
+        let fz = c.m.mk_app(f, &[z], Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        cc.enable_propagation(true);
+        let lit_fx = TermLit::mk_eq(fx, b.true_);
+        let lit_fy = TermLit::mk_eq(fy, b.true_);
+        let lit_fz = TermLit::mk_eq(fz, b.true_);
+        cc.add_literal(&mut c, fx, lit_fx);
+        cc.add_literal(&mut c, fy, lit_fy);
+        cc.add_literal(&mut c, fz, lit_fz);
+
+        // `f(y)=true`, `x=y` ==> `f(x)=true`; `f(z)` has no value
+        let lit_xy = TermLit::mk_eq(x, y);
+        cc.merge(&mut c, fy, b.true_, lit_fy).unwrap();
+        cc.merge(&mut c, x, y, lit_xy).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        assert!(acts.get().is_ok());
+
+        let mut atoms: Vec<_> = cc.iter_assigned_atoms_with_reason(&c).collect();
+        atoms.sort_by_key(|a| a.atom);
+        assert_eq!(2, atoms.len(), "atoms: {:?}", atoms);
+        let (a_fx, a_fy) = if atoms[0].atom == fx { (&atoms[0], &atoms[1]) } else { (&atoms[1], &atoms[0]) };
+        assert_eq!((fx, lit_fx, true), (a_fx.atom, a_fx.lit, a_fx.value));
+        let mut expl = a_fx.reason.clone().expect("f(x) was propagated");
+        expl.sort();
+        let mut expected = vec![lit_fy, lit_xy];
+        expected.sort();
+        assert_eq!(expected, expl);
+        assert_eq!((fy, true, None), (a_fy.atom, a_fy.value, a_fy.reason.clone()));
+
+        let classes = cc.classes();
+        let class_of = |t: AST| classes.iter().find(|cl| cl.contains(&t)).expect("no class");
+        let cl_true = class_of(b.true_);
+        assert!(cl_true.contains(&fx) && cl_true.contains(&fy) && !cl_true.contains(&fz));
+        assert!(class_of(x).contains(&y));
+        assert!(!class_of(x).contains(&z));
+        // each term is in exactly one class
+        let n: usize = classes.iter().map(|cl| cl.len()).sum();
+        let mut all: Vec<AST> = classes.into_iter().flatten().collect();
+        all.sort(); all.dedup();
+        assert_eq!(n, all.len());
+    }
+}
+
+mod sat_witness {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // `x=y`, `z=w`, `f(x)=f(z)` false: the witness agrees with the classes
+    #[test]
+    fn test_sat_witness_consistent() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let x = c.m.mk_str("x", Some(b.ty_u));
+        let y = c.m.mk_str("y", Some(b.ty_u));
+        let z = c.m.mk_str("z", Some(b.ty_u));
+        let w = c.m.mk_str("w", Some(b.ty_u));
+        let fx = c.m.mk_app(f, &[x], Some(b.ty_u));
+        let fz = c.m.mk_app(f, &[z], Some(b.ty_u));
+        let eq_fxfz = c.m.mk_app(b.eq, &[fx, fz], Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        cc.merge(&mut c, x, y, TermLit::mk_eq(x, y)).unwrap();
+        cc.merge(&mut c, z, w, TermLit::mk_eq(z, w)).unwrap();
+        cc.merge(&mut c, eq_fxfz, b.false_, TermLit::mk_neq(fx, fz)).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        assert!(acts.get().is_ok());
+
+        let mut wit = cc.sat_witness(&c);
+        assert_eq!(Ok(()), wit.check());
+        let repr = |t: AST| wit.repr.iter().find(|(u,_)| *u == t).map(|(_,r)| *r).expect("no repr");
+        assert_eq!(repr(x), repr(y));
+        assert_eq!(repr(z), repr(w));
+        assert_ne!(repr(x), repr(z));
+        assert_eq!(repr(eq_fxfz), repr(b.false_));
+        assert!(wit.diseqs.contains(&(b.true_, b.false_)));
+        assert!(wit.diseqs.contains(&(fx, fz)));
+        for (t, r) in wit.repr.iter() {
+            assert_eq!(Some(*r), cc.find(t), "repr of {:?}", t);
+        }
+
+        // a disequality inside a class is caught
+        wit.diseqs.push((y, x));
+        assert!(wit.check().is_err());
+    }
+}
+
+mod smtlib {
+    use {super::*, batsmt_core::ast::Manager, batsmt_parser::{self as parser, simple_ast, Statement}};
+
+    // `a=b`, `b=c`, `a!=c`: the lemma parses back as the negation of the conjunction
+    #[test]
+    fn test_conflict_as_smtlib() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let ta = c.m.mk_str("a", Some(b.ty_u));
+        let tb = c.m.mk_str("b", Some(b.ty_u));
+        let tc = c.m.mk_str("c", Some(b.ty_u));
+        let eq_ab = c.m.mk_app(b.eq, &[ta, tb], Some(b.bool_));
+        let eq_bc = c.m.mk_app(b.eq, &[tb, tc], Some(b.bool_));
+        let eq_ac = c.m.mk_app(b.eq, &[ta, tc], Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        cc.add_literal(&mut c, eq_ab, TermLit::mk_eq(ta, tb));
+        cc.add_literal(&mut c, eq_bc, TermLit::mk_eq(tb, tc));
+        cc.add_literal(&mut c, eq_ac, TermLit::mk_eq(ta, tc));
+        cc.merge(&mut c, ta, tb, TermLit::mk_eq(ta, tb)).unwrap();
+        cc.merge(&mut c, tb, tc, TermLit::mk_eq(tb, tc)).unwrap();
+        cc.merge(&mut c, eq_ac, b.false_, TermLit::mk_neq(ta, tc)).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        let confl = acts.get().expect_err("must conflict").to_vec();
+
+        let lemma = cc.conflict_as_smtlib(&c, &confl);
+        let script = format!(
+            "(declare-sort U 0) (declare-fun a () U) (declare-fun b () U) (declare-fun c () U) {}",
+            lemma);
+        let stmts = parser::parse_str(&mut simple_ast::Builder::new(), &script)
+            .unwrap_or_else(|e| panic!("cannot parse {:?}: {}", lemma, e));
+        let t = match stmts.last() {
+            Some(Statement::Assert(t)) => t.clone(),
+            st => panic!("expected an assertion, got {:?}", st),
+        };
+        // printing the parsed term gives the lemma back
+        assert_eq!(lemma, format!("(assert {})", t));
+
+        // the order of literals in the conflict is unspecified
+        assert!(lemma.starts_with("(assert (not (and "), "lemma: {}", lemma);
+        for lit in &["(= a b)", "(= b c)", "(not (= a c))"] {
+            assert_eq!(1, lemma.matches(lit).count(), "{} in lemma: {}", lit, lemma);
+        }
+    }
+}
+
+mod commutative {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // `g(x,y)=true`, `y=z` ==> `g(z,x)=true`, only if `g` is commutative
+    #[test]
+    fn test_commutative_attr() {
+        for &comm in &[true, false] {
+            let mut c = mk_ctx();
+            let b = c.b();
+            let g = c.m.mk_str("g", None);
+            if comm {
+                c.m.set_attr(g, AttrKey::COMMUTATIVE, AttrValue::Flag);
+            }
+            let x = c.m.mk_str("x", Some(b.ty_u));
+            let y = c.m.mk_str("y", Some(b.ty_u));
+            let z = c.m.mk_str("z", Some(b.ty_u));
+            let gxy = c.m.mk_app(g, &[x, y], Some(b.bool_));
+            let gzx = c.m.mk_app(g, &[z, x], Some(b.bool_));
+
+            let mut cc = CC0::new(&mut c);
+            cc.enable_propagation(true);
+            let lit_gzx = TermLit::mk_eq(gzx, b.true_);
+            cc.add_literal(&mut c, gzx, lit_gzx);
+
+            let lit_gxy = TermLit::mk_eq(gxy, b.true_);
+            let lit_yz = TermLit::mk_eq(y, z);
+            cc.merge(&mut c, gxy, b.true_, lit_gxy).unwrap();
+            cc.merge(&mut c, y, z, lit_yz).unwrap();
+
+            let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+            cc.final_check(&mut c, &mut acts);
+            let props = acts.get().expect("must be satisfiable").0.to_vec();
+            if !comm {
+                assert_eq!(props.len(), 0, "props: {:?}", props);
+                continue
+            }
+            assert_eq!(&props, &[lit_gzx]);
+
+            let mut expl = cc.explain_prop(&c, lit_gzx).to_vec();
+            expl.sort();
+            let mut expected = vec![lit_gxy, lit_yz];
+            expected.sort();
+            assert_eq!(expl, expected);
+        }
+    }
+}
+
+mod apply_chain {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // `apply(apply(f,a),b)`, `apply(apply(g,c),d)`
+    fn setup(c: &mut Ctx) -> (AST, [AST; 6], AST, AST) {
+        let ty_u = c.b().ty_u;
+        let apply = c.m.mk_str("apply", Some(ty_u));
+        let xs = [
+            c.m.mk_str("f", Some(ty_u)), c.m.mk_str("a", Some(ty_u)), c.m.mk_str("b", Some(ty_u)),
+            c.m.mk_str("g", Some(ty_u)), c.m.mk_str("c", Some(ty_u)), c.m.mk_str("d", Some(ty_u)),
+        ];
+        let fa = c.m.mk_app(apply, &[xs[0], xs[1]], Some(ty_u));
+        let fab = c.m.mk_app(apply, &[fa, xs[2]], Some(ty_u));
+        let gc = c.m.mk_app(apply, &[xs[3], xs[4]], Some(ty_u));
+        let gcd = c.m.mk_app(apply, &[gc, xs[5]], Some(ty_u));
+        (apply, xs, fab, gcd)
+    }
+
+    // `f=g`, `a=c`, `b=d` ==> `apply(apply(f,a),b) = apply(apply(g,c),d)`
+    #[test]
+    fn test_apply_chain_congruence() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let (apply, [f, a, b_, g, c_, d], fab, gcd) = setup(&mut c);
+        let eqn = c.m.mk_app(b.eq, &[fab, gcd], Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        cc.declare_apply_symbol(apply);
+        cc.enable_propagation(true);
+        let lit = TermLit::mk_eq(fab, gcd);
+        cc.add_literal(&mut c, eqn, lit);
+
+        cc.push_level(&mut c);
+        let lits = [TermLit::mk_eq(f, g), TermLit::mk_eq(a, c_), TermLit::mk_eq(b_, d)];
+        for l in lits.iter() {
+            cc.merge(&mut c, l.1, l.2, *l).unwrap();
+        }
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        let props = acts.get().expect("must be satisfiable").0.to_vec();
+        assert_eq!(&props, &[lit]);
+
+        let mut expl = cc.explain_prop(&c, lit).to_vec();
+        expl.sort();
+        let mut expected = lits.to_vec();
+        expected.sort();
+        assert_eq!(expl, expected);
+
+        // the chains are removed from the parents of their head and arguments
+        cc.pop_levels(&mut c, 1);
+        assert_ne!(cc.find(&fab), cc.find(&gcd));
+    }
+
+    // `SimpleActions`, with theory literals mapped to `TermLit`
+    struct MapEq(theory::SimpleActions<Ctx>);
+
+    impl Actions<Ctx> for MapEq {
+        fn add_lemma_with_kind(&mut self, c: &[TermLit], kind: theory::ClauseKind) {
+            self.0.add_lemma_with_kind(c, kind)
+        }
+        fn propagate(&mut self, p: TermLit) -> bool { self.0.propagate(p) }
+        fn raise_conflict(&mut self, c: &[TermLit], costly: bool) { self.0.raise_conflict(c, costly) }
+        fn map_lit(&mut self, m: &Ctx, lit: theory::TheoryLit<Ctx>) -> TermLit {
+            match lit {
+                theory::TheoryLit::T(t, sign) => match m.view_as_cc_term(&t) {
+                    CCView::Eq(a, b) => TermLit::new(*a, *b, sign),
+                    _ => panic!("not an equation"),
+                },
+                _ => panic!("not a theory literal"),
+            }
+        }
+        fn has_conflict(&self) -> bool { self.0.has_conflict() }
+        fn mark_incomplete(&mut self, feature: &'static str) { self.0.mark_incomplete(feature) }
+        fn raise_error(&mut self, err: theory::TheoryError<TermLit>) { self.0.raise_error(err) }
+    }
+
+    // `f=g` ==> `apply(f,a) = apply(g,a)` and `apply(g,c) = apply(f,c)`
+    #[test]
+    fn test_extensionality_lemmas() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let (apply, [f, a, _, g, c_, _], _, _) = setup(&mut c);
+        let ty_u = b.ty_u;
+        let fa = c.m.mk_app(apply, &[f, a], Some(ty_u));
+        let gc = c.m.mk_app(apply, &[g, c_], Some(ty_u));
+        let ga = c.m.mk_app(apply, &[g, a], Some(ty_u));
+        let fc = c.m.mk_app(apply, &[f, c_], Some(ty_u));
+
+        let mut cc = CC0::new(&mut c);
+        cc.declare_apply_symbol(apply);
+        cc.enable_extensionality(move |m: &mut Ctx, t, u| {
+            let eq = m.b().eq;
+            m.m.mk_app(eq, &[*t, *u], Some(m.b().bool_))
+        });
+        cc.add_literal(&mut c, fa, TermLit::mk_eq(fa, b.true_));
+        cc.add_literal(&mut c, gc, TermLit::mk_eq(gc, b.true_));
+        let lit_fg = TermLit::mk_eq(f, g);
+        cc.merge(&mut c, f, g, lit_fg).unwrap();
+
+        let mut acts = MapEq(theory::SimpleActions::new(|| unimplemented!("new lit")));
+        cc.final_check(&mut c, &mut acts);
+        let mut lemmas = acts.0.get().expect("must be satisfiable").1.to_vec();
+        lemmas.sort();
+        let mut expected = vec![
+            vec![!lit_fg, TermLit::mk_eq(fa, ga)],
+            vec![!lit_fg, TermLit::mk_eq(gc, fc)],
+        ];
+        expected.sort();
+        assert_eq!(lemmas, expected);
+        assert!(acts.0.lemma_kinds().iter().all(|k| *k == theory::ClauseKind::TheoryLemmaPermanent));
+
+        // lemmas are only added once
+        let mut acts = MapEq(theory::SimpleActions::new(|| unimplemented!("new lit")));
+        cc.push_level(&mut c);
+        cc.pop_levels(&mut c, 1);
+        cc.final_check(&mut c, &mut acts);
+        assert_eq!(0, acts.0.get().expect("must be satisfiable").1.len());
+    }
+}
+
+mod probe {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // `a=b`, `a!=c`: probing `b=c` fails, probing `b=d` succeeds,
+    // and neither changes the congruence closure.
+    #[test]
+    fn test_probe_restores_state() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let ta = c.m.mk_str("a", Some(b.ty_u));
+        let tb = c.m.mk_str("b", Some(b.ty_u));
+        let tc = c.m.mk_str("c", Some(b.ty_u));
+        let td = c.m.mk_str("d", Some(b.ty_u));
+        let eq_ac = c.m.mk_app(b.eq, &[ta, tc], Some(b.bool_));
+        let fb = c.m.mk_app(f, &[tb], Some(b.bool_));
+        let fd = c.m.mk_app(f, &[td], Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        cc.enable_propagation(true);
+        let lit_ab = TermLit::mk_eq(ta, tb);
+        let lit_ac = TermLit::mk_neq(ta, tc);
+        cc.merge(&mut c, ta, tb, lit_ab).unwrap();
+        cc.merge(&mut c, eq_ac, b.false_, lit_ac).unwrap();
+        cc.merge(&mut c, fb, b.true_, TermLit::mk_eq(fb, b.true_)).unwrap();
+        cc.add_literal(&mut c, fd, TermLit::mk_eq(fd, b.true_));
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        assert!(acts.get().is_ok());
+
+        let terms = [ta, tb, tc, td, eq_ac, fb, fd, b.true_, b.false_];
+        let snapshot = |cc: &mut CC0| -> Vec<Option<AST>> {
+            terms.iter().map(|t| cc.find(t)).collect()
+        };
+        let before = snapshot(&mut cc);
+        let n_props = cc.propagations().len();
+
+        let lit_bc = TermLit::mk_eq(tb, tc);
+        let mut confl = cc.probe(&mut c, tb, tc, lit_bc).unwrap().expect("must conflict");
+        confl.sort();
+        let mut expected = vec![!lit_ab, !lit_ac, !lit_bc];
+        expected.sort();
+        assert_eq!(&confl[..], &expected[..]);
+        assert_eq!(before, snapshot(&mut cc));
+
+        // `b=d` is consistent, and would propagate `f(d)`
+        let lit_bd = TermLit::mk_eq(tb, td);
+        assert_eq!(None, cc.probe(&mut c, tb, td, lit_bd).unwrap());
+        assert_eq!(before, snapshot(&mut cc));
+        assert_eq!(n_props, cc.propagations().len());
+
+        // a term seen only by `probe` is removed afterwards
+        let te = c.m.mk_str("e", Some(b.ty_u));
+        assert_eq!(None, cc.probe(&mut c, te, ta, TermLit::mk_eq(te, ta)).unwrap());
+        assert_eq!(None, cc.find(&te));
+
+        // the state is still usable
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.merge(&mut c, tb, tc, lit_bc).unwrap();
+        cc.final_check(&mut c, &mut acts);
+        let mut confl2 = acts.get().expect_err("must conflict").to_vec();
+        confl2.sort();
+        assert_eq!(confl2, expected);
+    }
+}
+
+mod eval_new_term {
+    use {
+        super::*, batsmt_core::{ast::Manager, backtrack::Backtrackable, mem},
+        batsmt_cc::CCTheory, theory::Theory,
+    };
+
+    // `a=b`, `f(b)=c`, `g(f(b))=d`, `p(b)`: unseen terms congruent
+    // to these get their value, and the state is left unchanged.
+    #[test]
+    fn test_eval_new_term_congruent() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let g = c.m.mk_str("g", None);
+        let p = c.m.mk_str("p", None);
+        let ta = c.m.mk_str("a", Some(b.ty_u));
+        let tb = c.m.mk_str("b", Some(b.ty_u));
+        let tc = c.m.mk_str("c", Some(b.ty_u));
+        let td = c.m.mk_str("d", Some(b.ty_u));
+        let fb = c.m.mk_app(f, &[tb], Some(b.ty_u));
+        let gfb = c.m.mk_app(g, &[fb], Some(b.ty_u));
+        let pb = c.m.mk_app(p, &[tb], Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        cc.enable_propagation(true);
+        cc.merge(&mut c, ta, tb, TermLit::mk_eq(ta, tb)).unwrap();
+        cc.merge(&mut c, fb, tc, TermLit::mk_eq(fb, tc)).unwrap();
+        cc.merge(&mut c, gfb, td, TermLit::mk_eq(gfb, td)).unwrap();
+        cc.merge(&mut c, pb, b.true_, TermLit::mk_eq(pb, b.true_)).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        assert!(acts.get().is_ok());
+
+        let fa = c.m.mk_app(f, &[ta], Some(b.ty_u));
+        let gfa = c.m.mk_app(g, &[fa], Some(b.ty_u));
+        let pa = c.m.mk_app(p, &[ta], Some(b.bool_));
+        let te = c.m.mk_str("e", Some(b.ty_u));
+        let fe = c.m.mk_app(f, &[te], Some(b.ty_u));
+        let pe = c.m.mk_app(p, &[te], Some(b.bool_));
+
+        let terms = [ta, tb, tc, td, fb, gfb, pb, b.true_, b.false_];
+        let snapshot = |cc: &mut CC0| -> Vec<Option<AST>> {
+            terms.iter().map(|t| cc.find(t)).collect()
+        };
+        let before = snapshot(&mut cc);
+        let n_props = cc.propagations().len();
+        let n_levels = cc.n_levels();
+
+        let eval = |c: &mut Ctx, cc: &mut CC0| -> [AST; 5] {
+            [cc.eval_new_term(c, fa), cc.eval_new_term(c, gfa), cc.eval_new_term(c, pa),
+             cc.eval_new_term(c, fe), cc.eval_new_term(c, pe)]
+        };
+        let res = eval(&mut c, &mut cc);
+        assert_eq!(cc.find(&tc), Some(res[0]), "f(a) = f(b) = c");
+        assert_eq!(cc.find(&td), Some(res[1]), "g(f(a)) = g(f(b)) = d");
+        assert_eq!(b.true_, res[2], "p(a) = p(b) = true");
+        assert_eq!([fe, pe], [res[3], res[4]], "no value for f(e), p(e)");
+
+        // nothing remains of the queries
+        let mem0 = mem::report("", &cc).total();
+        for _ in 0 .. 100 {
+            assert_eq!(res, eval(&mut c, &mut cc));
+        }
+        assert_eq!(before, snapshot(&mut cc));
+        for t in &[fa, gfa, pa, te, fe, pe] {
+            assert_eq!(None, cc.find(t), "{:?} was removed", t);
+        }
+        assert_eq!(n_props, cc.propagations().len());
+        assert_eq!(n_levels, cc.n_levels());
+        assert_eq!(mem0, mem::report("", &cc).total());
+    }
+
+    // same problem, solved by a `CCTheory` at level 1: once it has
+    // backtracked, queries are evaluated in the recorded final state
+    #[test]
+    fn test_eval_new_term_final_state() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let p = c.m.mk_str("p", None);
+        let ta = c.m.mk_str("a", Some(b.ty_u));
+        let tb = c.m.mk_str("b", Some(b.ty_u));
+        let tc = c.m.mk_str("c", Some(b.ty_u));
+        let fb = c.m.mk_app(f, &[tb], Some(b.ty_u));
+        let pb = c.m.mk_app(p, &[tb], Some(b.bool_));
+        let eq_ab = c.m.mk_app(b.eq, &[ta, tb], Some(b.bool_));
+        let eq_fbc = c.m.mk_app(b.eq, &[fb, tc], Some(b.bool_));
+        let trail = [
+            (eq_ab, true, TermLit::mk_eq(ta, tb)),
+            (eq_fbc, true, TermLit::mk_eq(fb, tc)),
+            (pb, true, TermLit::mk_eq(pb, b.true_)),
+        ];
+
+        let mut th: CCTheory<Ctx, ()> = CCTheory::new(&mut c);
+        th.set_record_final_state(true);
+        th.push_level(&mut c);
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        th.final_check(&mut c, &mut acts, &theory::Trail::from_slice(&trail));
+        assert!(acts.get().is_ok());
+        th.pop_levels(&mut c, 1);
+
+        let fa = c.m.mk_app(f, &[ta], Some(b.ty_u));
+        let pa = c.m.mk_app(p, &[ta], Some(b.bool_));
+        let te = c.m.mk_str("e", Some(b.ty_u));
+        let fe = c.m.mk_app(f, &[te], Some(b.ty_u));
+        let n_levels = th.n_levels();
+        let stats = format!("{:?}", th.stats());
+        let mem0 = mem::report("", &th).total();
+
+        let eval = |c: &mut Ctx, th: &mut CCTheory<Ctx, ()>| {
+            let res = [th.eval_new_term(c, fa), th.eval_new_term(c, pa), th.eval_new_term(c, fe)];
+            let st = th.final_state().unwrap();
+            assert_eq!(st.repr(&tc), st.repr(&res[0]), "f(a) = f(b) = c");
+            assert_eq!(st.repr(&b.true_), st.repr(&res[1]), "p(a) = p(b) = true");
+            assert_eq!(fe, res[2], "no value for f(e)");
+            res
+        };
+        let res = eval(&mut c, &mut th);
+        for _ in 0 .. 100 {
+            assert_eq!(res, eval(&mut c, &mut th));
+        }
+        assert_eq!(n_levels, th.n_levels());
+        assert_eq!(stats, format!("{:?}", th.stats()));
+        assert_eq!(mem0, mem::report("", &th).total());
+    }
+}
+
+mod cancel {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // `a=b`, `b=c`, `a!=c`: a canceled check stops without conflict,
+    // and the next one finds the conflict.
+    fn check_cancel_then_resume<Th>(c: &mut Ctx, cc: &mut Th)
+        where Th: CCInterface<Ctx>
+    {
+        let b = c.b();
+        let ta = c.m.mk_str("a", Some(b.ty_u));
+        let tb = c.m.mk_str("b", Some(b.ty_u));
+        let tc = c.m.mk_str("c", Some(b.ty_u));
+        let eq_ac = c.m.mk_app(b.eq, &[ta, tc], Some(b.bool_));
+        cc.merge(c, ta, tb, TermLit::mk_eq(ta, tb)).unwrap();
+        cc.merge(c, tb, tc, TermLit::mk_eq(tb, tc)).unwrap();
+        cc.merge(c, eq_ac, b.false_, TermLit::mk_neq(ta, tc)).unwrap();
+
+        let tok = theory::CancelToken::new();
+        tok.cancel();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        acts.set_cancel_token(tok.clone());
+        cc.final_check(c, &mut acts);
+        assert!(acts.get().is_ok(), "canceled check must not conflict");
+
+        tok.reset();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        acts.set_cancel_token(tok.clone());
+        cc.final_check(c, &mut acts);
+        assert_eq!(3, acts.get().expect_err("must conflict").len());
+    }
+
+    #[test]
+    fn test_cancel_cc() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        check_cancel_then_resume(&mut c, &mut cc);
+    }
+
+    #[test]
+    fn test_cancel_naive_cc() {
+        let mut c = mk_ctx();
+        let mut cc = NaiveCC0::new(&mut c);
+        check_cancel_then_resume(&mut c, &mut cc);
+    }
+}
+
+mod not_app {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // `not(x)` is an application of the `not` symbol, not a `CCView::Not`
+    #[test]
+    fn test_not_app_true() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        let b = c.b();
+        let x = c.m.mk_str("x", Some(b.bool_));
+        let not_x = c.m.mk_app(b.not_, &[x], Some(b.bool_));
+        cc.add_literal(&mut c, not_x, TermLit::mk_eq(not_x, b.true_));
+        cc.merge(&mut c, x, b.true_, TermLit::mk_eq(x, b.true_)).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        assert!(acts.get().is_ok());
+        assert_eq!(cc.find(&not_x), cc.find(&b.false_));
+    }
+}
+
+mod eq_symbols {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // a context where `==` is another equality symbol; only these tests
+    // declare it, so the other tests (and golden files) keep their terms
+    fn mk_ctx() -> Ctx {
+        let mut c = super::mk_ctx();
+        let eq_alt = c.m.mk_str("==", None);
+        c.b.as_mut().unwrap().eq_alt = Some(eq_alt);
+        c
+    }
+
+    // `=` and `==` are both viewed as `CCView::Eq`
+    #[test]
+    fn test_two_eq_symbols_cc() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let x = c.m.mk_str("x", Some(b.ty_u));
+        let y = c.m.mk_str("y", Some(b.ty_u));
+        let eq1 = c.m.mk_app(b.eq, &[x, y], Some(b.bool_));
+        let eq2 = c.m.mk_app(b.eq_alt.unwrap(), &[y, x], Some(b.bool_));
+
+        let mut cc = CC0::new(&mut c);
+        cc.enable_propagation(true);
+        cc.add_literal(&mut c, eq1, TermLit::mk_eq(eq1, b.true_));
+        cc.add_literal(&mut c, eq2, TermLit::mk_eq(eq2, b.true_));
+        cc.merge(&mut c, x, y, TermLit::mk_eq(x, y)).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        assert!(acts.get().is_ok());
+        assert_eq!(cc.find(&eq1), cc.find(&b.true_));
+        assert_eq!(cc.find(&eq2), cc.find(&b.true_));
+    }
+
+    #[test]
+    fn test_two_eq_symbols_naive_cc() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let x = c.m.mk_str("x", Some(b.ty_u));
+        let y = c.m.mk_str("y", Some(b.ty_u));
+        let eq2 = c.m.mk_app(b.eq_alt.unwrap(), &[x, y], Some(b.bool_));
+
+        let mut cc = NaiveCC0::new(&mut c);
+        let lit_eq2 = TermLit::mk_eq(eq2, b.false_);
+        let lit_xy = TermLit::mk_eq(x, y);
+        cc.merge(&mut c, eq2, b.false_, lit_eq2).unwrap();
+        cc.merge(&mut c, x, y, lit_xy).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        let mut confl = acts.get().expect_err("x==y is true").to_vec();
+        confl.sort();
+        let mut expected = vec![!lit_eq2, !lit_xy];
+        expected.sort();
+        assert_eq!(confl, expected);
+    }
+}
+
+mod incomplete {
+    use {super::*, batsmt_core::ast::Manager, batsmt_cc::CCTheory, theory::Theory};
+
+    // `¬distinct(a,b)` is not supported, the theory must say so
+    #[test]
+    fn test_neg_distinct_incomplete() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let a1 = c.m.mk_str("a", Some(b.ty_u));
+        let a2 = c.m.mk_str("b", Some(b.ty_u));
+        let d = c.m.mk_app(b.distinct, &[a1, a2], Some(b.bool_));
+        let lit = TermLit::mk_eq(d, b.false_);
+
+        let mut th: CCTheory<Ctx, ()> = CCTheory::new(&mut c);
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        let trail = vec![(d, false, lit)];
+        th.final_check(&mut c, &mut acts, &theory::Trail::from_slice(&trail));
+        assert!(acts.get().is_ok());
+        assert_eq!(acts.incomplete(), Some("distinct"));
+    }
+
+    #[test]
+    fn test_complete_by_default() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let x = c.m.mk_str("x", Some(b.ty_u));
+        let y = c.m.mk_str("y", Some(b.ty_u));
+        let eqn = c.m.mk_app(b.eq, &[x, y], Some(b.bool_));
+        let lit = TermLit::mk_eq(x, y);
+
+        let mut th: CCTheory<Ctx, ()> = CCTheory::new(&mut c);
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        let trail = vec![(eqn, true, lit)];
+        th.final_check(&mut c, &mut acts, &theory::Trail::from_slice(&trail));
+        assert!(acts.get().is_ok());
+        assert_eq!(acts.incomplete(), None);
+    }
+}
+
+mod sorts {
+    use {super::*, batsmt_core::{ast::Manager, Error}, batsmt_cc::{CCTheory, CcError}, theory::Theory};
+
+    #[test]
+    fn test_merge_sort_mismatch() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let a = c.m.mk_str("a", Some(b.ty_u));
+        let p = c.m.mk_str("p", Some(b.bool_));
+        let mut cc = CC0::new(&mut c);
+        let r = cc.merge(&mut c, a, p, TermLit::mk_eq(a, p));
+        assert_eq!(r, Err(CcError::SortMismatch{t1: a, t2: p}));
+
+        // untyped terms are not checked
+        let f = c.m.mk_str("f", None);
+        assert!(cc.merge(&mut c, f, a, TermLit::mk_eq(f, a)).is_ok());
+    }
+
+    // `a=p` and `a!=p` are both rejected, and the theory keeps working afterwards
+    #[test]
+    fn test_theory_sort_error() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let a = c.m.mk_str("a", Some(b.ty_u));
+        let a2 = c.m.mk_str("a2", Some(b.ty_u));
+        let p = c.m.mk_str("p", Some(b.bool_));
+        let bad = c.m.mk_app(b.eq, &[a, p], Some(b.bool_));
+        let good = c.m.mk_app(b.eq, &[a, a2], Some(b.bool_));
+
+        let mut th: CCTheory<Ctx, ()> = CCTheory::new(&mut c);
+        for &sign in &[true, false] {
+            let lit = TermLit::new(a, p, sign);
+            let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+            th.push_level(&mut c);
+            th.final_check(&mut c, &mut acts, &theory::Trail::from_slice(&[(bad, sign, lit)]));
+            let err = acts.error().expect("must fail");
+            match &err.err {
+                Error::Sort{expected, found, term} => {
+                    let mut sorts = [expected.as_str(), found.as_str()];
+                    sorts.sort();
+                    assert_eq!(sorts, ["Bool", "ty_u"], "bad error {}", err);
+                    assert!(term == "a" || term == "p", "bad error {}", err);
+                },
+                e => panic!("expected a sort error, got {}", e),
+            }
+            assert_eq!(err.lit, Some(lit));
+            th.pop_levels(&mut c, 1);
+        }
+
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        let trail = vec![(good, true, TermLit::mk_eq(a, a2))];
+        th.final_check(&mut c, &mut acts, &theory::Trail::from_slice(&trail));
+        assert!(acts.error().is_none());
+        assert!(acts.get().is_ok());
+    }
+}
+
+mod bool_clash {
+    use {super::*, batsmt_core::ast::Manager, batsmt_cc::CCTheory, theory::Theory};
+
+    // `a=true`, `a=b`, `b=false`
+    fn chain(c: &mut Ctx) -> [(AST, bool, TermLit); 3] {
+        let b = c.b();
+        let a1 = c.m.mk_str("a", Some(b.bool_));
+        let a2 = c.m.mk_str("b", Some(b.bool_));
+        let eqn = c.m.mk_app(b.eq, &[a1, a2], Some(b.bool_));
+        [(a1, true, TermLit::mk_eq(a1, b.true_)),
+         (eqn, true, TermLit::mk_eq(a1, a2)),
+         (a2, false, TermLit::mk_eq(a2, b.false_))]
+    }
+
+    fn check_confl(confl: &[TermLit], trail: &[(AST, bool, TermLit)]) {
+        let mut confl = confl.to_vec();
+        confl.sort();
+        let mut expected: Vec<_> = trail.iter().map(|(_,_,lit)| !*lit).collect();
+        expected.sort();
+        assert_eq!(confl, expected);
+    }
+
+    #[test]
+    fn test_negated_conflict() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let trail = chain(&mut c);
+        let [(a1,_,l1), (_,_,l2), (a2,_,l3)] = trail;
+        let mut cc = CC0::new(&mut c);
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        assert_eq!(acts.negated_conflict().count(), 0);
+        cc.merge(&mut c, a1, b.true_, l1).unwrap();
+        cc.merge(&mut c, a1, a2, l2).unwrap();
+        cc.merge(&mut c, a2, b.false_, l3).unwrap();
+        cc.final_check(&mut c, &mut acts);
+        let manual: Vec<_> = acts.get().unwrap_err().iter().map(|lit| ! *lit).collect();
+        let mut negated: Vec<_> = acts.negated_conflict().collect();
+        assert_eq!(negated, manual);
+        // the conflict is explained by the trail
+        negated.sort();
+        let mut trail_lits = vec![l1, l2, l3];
+        trail_lits.sort();
+        assert_eq!(negated, trail_lits);
+    }
+
+    fn check_cc<CC: CCInterface<Ctx>>(c: &mut Ctx, mut cc: CC) {
+        let b = c.b();
+        let trail = chain(c);
+        let [(a1,_,l1), (_,_,l2), (a2,_,l3)] = trail;
+        cc.merge(c, a1, b.true_, l1).unwrap();
+        cc.merge(c, a1, a2, l2).unwrap();
+        cc.merge(c, a2, b.false_, l3).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(c, &mut acts);
+        check_confl(acts.get().expect_err("must be unsat"), &trail);
+    }
+
+    #[test]
+    fn test_true_eq_false_cc() {
+        let mut c = mk_ctx();
+        let cc = CC0::new(&mut c);
+        check_cc(&mut c, cc);
+    }
+
+    #[test]
+    fn test_true_eq_false_naive_cc() {
+        let mut c = mk_ctx();
+        let cc = NaiveCC0::new(&mut c);
+        check_cc(&mut c, cc);
+    }
+
+    #[test]
+    fn test_true_eq_false_theory() {
+        let mut c = mk_ctx();
+        let trail = chain(&mut c);
+        let mut th: CCTheory<Ctx, ()> = CCTheory::new(&mut c);
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        th.final_check(&mut c, &mut acts, &theory::Trail::from_slice(&trail));
+        assert!(acts.error().is_none());
+        check_confl(acts.get().expect_err("must be unsat"), &trail);
+    }
+}
+
+mod literal_bindings {
+    use {super::*, batsmt_core::ast::Manager};
+
+    fn bindings(cc: &CC0) -> Vec<(AST, TermLit)> {
+        let mut v: Vec<_> = cc.literal_bindings().map(|(t,lit)| (t,*lit)).collect();
+        v.sort();
+        v
+    }
+
+    #[test]
+    fn test_bindings_push_pop() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let x = c.m.mk_str("x", Some(b.ty_u));
+        let y = c.m.mk_str("y", Some(b.ty_u));
+        let z = c.m.mk_str("z", Some(b.ty_u));
+        let exy = c.m.mk_app(b.eq, &[x, y], Some(b.bool_));
+        let eyz = c.m.mk_app(b.eq, &[y, z], Some(b.bool_));
+        let (l_xy, l_yz) = (TermLit::mk_eq(x, y), TermLit::mk_eq(y, z));
+
+        let mut cc = CC0::new(&mut c);
+        assert_eq!(bindings(&cc), vec![]);
+        cc.add_literal(&mut c, exy, l_xy);
+        assert_eq!(bindings(&cc), vec![(exy, l_xy)]);
+
+        cc.push_level(&mut c);
+        cc.add_literal(&mut c, eyz, l_yz);
+        // rebinding an existing term is undone as well
+        cc.add_literal(&mut c, exy, !l_xy);
+        let mut expected = vec![(exy, !l_xy), (eyz, l_yz)];
+        expected.sort();
+        assert_eq!(bindings(&cc), expected);
+
+        cc.pop_levels(&mut c, 1);
+        assert_eq!(bindings(&cc), vec![(exy, l_xy)]);
+    }
+}
+
+mod merge_priority {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // `f(a)` has more parents than `a`, so it would be the representative by default
+    fn setup(c: &mut Ctx, cc: &mut CC0) -> (AST, AST) {
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let g = c.m.mk_str("g", None);
+        let h = c.m.mk_str("h", None);
+        let a = c.m.mk_str("a", Some(b.ty_u));
+        let fa = c.m.mk_app(f, &[a], Some(b.ty_u));
+        let gfa = c.m.mk_app(g, &[fa], Some(b.ty_u));
+        let hfa = c.m.mk_app(h, &[fa], Some(b.ty_u));
+        let eqn = c.m.mk_app(b.eq, &[gfa, hfa], Some(b.bool_));
+        cc.add_literal(c, eqn, TermLit::mk_eq(gfa, hfa));
+        (a, fa)
+    }
+
+    fn merge_and_find(c: &mut Ctx, cc: &mut CC0, a: AST, fa: AST) -> AST {
+        cc.merge(c, fa, a, TermLit::mk_eq(fa, a)).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(c, &mut acts);
+        assert!(acts.get().is_ok());
+        assert_eq!(cc.find(&a), cc.find(&fa));
+        cc.find(&fa).unwrap()
+    }
+
+    #[test]
+    fn test_default_repr() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        let (a, fa) = setup(&mut c, &mut cc);
+        assert_eq!(merge_and_find(&mut c, &mut cc, a, fa), fa);
+    }
+
+    #[test]
+    fn test_const_has_priority() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        cc.set_merge_priority(|c: &Ctx, t: &AST| if c.m.is_const(t) { 1 } else { 0 });
+        let (a, fa) = setup(&mut c, &mut cc);
+        assert_eq!(merge_and_find(&mut c, &mut cc, a, fa), a);
+    }
+}
+
+mod antisymmetric {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // `le(a,b)`, `le(b,a)`
+    fn setup(c: &mut Ctx, cc: &mut CC0) -> (AST, AST, AST, AST) {
+        let b = c.b();
+        let le = c.m.mk_str("le", None);
+        cc.register_antisymmetric(le);
+        let a = c.m.mk_str("a", Some(b.ty_u));
+        let b_ = c.m.mk_str("b", Some(b.ty_u));
+        let ab = c.m.mk_app(le, &[a, b_], Some(b.bool_));
+        let ba = c.m.mk_app(le, &[b_, a], Some(b.bool_));
+        (a, b_, ab, ba)
+    }
+
+    fn check(c: &mut Ctx, cc: &mut CC0) -> Result<(), Vec<TermLit>> {
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(c, &mut acts);
+        acts.get().map(|_| ()).map_err(|confl| confl.to_vec())
+    }
+
+    #[test]
+    fn test_both_directions() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        let (a, b, ab, ba) = setup(&mut c, &mut cc);
+        let t = c.b().true_;
+        cc.merge(&mut c, ab, t, TermLit::mk_eq(ab, t)).unwrap();
+        check(&mut c, &mut cc).unwrap();
+        assert_ne!(cc.find(&a), cc.find(&b), "only one direction holds");
+
+        cc.merge(&mut c, ba, t, TermLit::mk_eq(ba, t)).unwrap();
+        check(&mut c, &mut cc).unwrap();
+        assert_eq!(cc.find(&a), cc.find(&b));
+        cc.check_invariants(&c).unwrap();
+    }
+
+    // the conflict with `a≠b` cites both atoms
+    #[test]
+    fn test_explanation() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        let (a, b, ab, ba) = setup(&mut c, &mut cc);
+        let (t, f) = (c.b().true_, c.b().false_);
+        let eq_ab = c.m.mk_app(c.b().eq, &[a, b], Some(c.b().bool_));
+        let lits = [TermLit::mk_eq(ab, t), TermLit::mk_eq(ba, t), TermLit::mk_neq(a, b)];
+        cc.merge(&mut c, ab, t, lits[0]).unwrap();
+        cc.merge(&mut c, eq_ab, f, lits[2]).unwrap();
+        check(&mut c, &mut cc).unwrap();
+
+        cc.push_level(&mut c);
+        cc.merge(&mut c, ba, t, lits[1]).unwrap();
+        let mut confl = check(&mut c, &mut cc).expect_err("a=b and a≠b");
+        confl.sort();
+        let mut expected: Vec<_> = lits.iter().map(|l| !*l).collect();
+        expected.sort();
+        assert_eq!(confl, expected);
+
+        // the merge is undone along with `le(b,a)`
+        cc.pop_levels(&mut c, 1);
+        check(&mut c, &mut cc).unwrap();
+        assert_ne!(cc.find(&a), cc.find(&b));
+    }
+}
+
+mod eq_clause {
+    use {super::*, batsmt_core::ast::Manager};
+
+    fn check(c: &mut Ctx, cc: &mut CC0) -> Result<(), Vec<TermLit>> {
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(c, &mut acts);
+        acts.get().map(|_| ()).map_err(|confl| confl.to_vec())
+    }
+
+    // `p => a=b ∨ a=c ∨ a=d`, then `a≠b`, `c≠a`
+    #[test]
+    fn test_last_equality() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        let b = c.b();
+        let p = c.m.mk_str("p", Some(b.bool_));
+        let [a, b_, c_, d] = ["a", "b", "c", "d"].map(|s| c.m.mk_str(s, Some(b.ty_u)));
+        let (t, f) = (b.true_, b.false_);
+        let lit = TermLit::mk_eq(p, t);
+        cc.add_eq_clause(&mut c, &[(a,b_), (a,c_), (a,d)], p).unwrap();
+        cc.merge(&mut c, p, t, lit).unwrap();
+
+        let eq_ab = c.m.mk_app(b.eq, &[a, b_], Some(b.bool_));
+        let eq_ca = c.m.mk_app(b.eq, &[c_, a], Some(b.bool_));
+        let eq_ad = c.m.mk_app(b.eq, &[a, d], Some(b.bool_));
+        let lits = [TermLit::mk_neq(a, b_), TermLit::mk_neq(c_, a), TermLit::mk_neq(a, d)];
+        cc.merge(&mut c, eq_ab, f, lits[0]).unwrap();
+        check(&mut c, &mut cc).unwrap();
+        assert_ne!(cc.find(&a), cc.find(&d), "two equalities are still possible");
+
+        cc.push_level(&mut c);
+        cc.merge(&mut c, eq_ca, f, lits[1]).unwrap();
+        check(&mut c, &mut cc).unwrap();
+        assert_eq!(cc.find(&a), cc.find(&d));
+        cc.check_invariants(&c).unwrap();
+
+        // the merge is explained by `p` and both refutations
+        cc.merge(&mut c, eq_ad, f, lits[2]).unwrap();
+        let mut confl = check(&mut c, &mut cc).expect_err("a=d and a≠d");
+        confl.sort();
+        let mut expected: Vec<_> = lits.iter().chain(Some(&lit)).map(|l| !*l).collect();
+        expected.sort();
+        assert_eq!(confl, expected);
+
+        // the merge is undone along with `c≠a`
+        cc.pop_levels(&mut c, 1);
+        check(&mut c, &mut cc).unwrap();
+        assert_ne!(cc.find(&a), cc.find(&d));
+    }
+
+    // a clause whose equalities are all refuted is a conflict
+    #[test]
+    fn test_all_refuted() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        let b = c.b();
+        let p = c.m.mk_str("p", Some(b.bool_));
+        let a = c.m.mk_str("a", Some(b.ty_u));
+        let b_ = c.m.mk_str("b", Some(b.ty_u));
+        let eq_ab = c.m.mk_app(b.eq, &[a, b_], Some(b.bool_));
+        let lits = [TermLit::mk_eq(p, b.true_), TermLit::mk_neq(a, b_)];
+        cc.merge(&mut c, eq_ab, b.false_, lits[1]).unwrap();
+        cc.merge(&mut c, p, b.true_, lits[0]).unwrap();
+        cc.add_eq_clause(&mut c, &[(b_,a)], p).unwrap();
+        let mut confl = check(&mut c, &mut cc).expect_err("p and a≠b");
+        confl.sort();
+        let mut expected: Vec<_> = lits.iter().map(|l| !*l).collect();
+        expected.sort();
+        assert_eq!(confl, expected);
+    }
+
+    // `p => a=b ∨ a=c` does nothing until `p` is asserted
+    #[test]
+    fn test_guard_not_asserted() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        let b = c.b();
+        let p = c.m.mk_str("p", Some(b.bool_));
+        let [a, b_, c_] = ["a", "b", "c"].map(|s| c.m.mk_str(s, Some(b.ty_u)));
+        cc.add_eq_clause(&mut c, &[(a,b_), (a,c_)], p).unwrap();
+
+        let eq_ab = c.m.mk_app(b.eq, &[a, b_], Some(b.bool_));
+        let eq_ac = c.m.mk_app(b.eq, &[a, c_], Some(b.bool_));
+        cc.merge(&mut c, eq_ab, b.false_, TermLit::mk_neq(a, b_)).unwrap();
+        check(&mut c, &mut cc).unwrap();
+        assert_ne!(cc.find(&a), cc.find(&c_), "p is not asserted");
+
+        // all equalities are refuted, still no conflict without `p`
+        cc.push_level(&mut c);
+        cc.merge(&mut c, eq_ac, b.false_, TermLit::mk_neq(a, c_)).unwrap();
+        check(&mut c, &mut cc).unwrap();
+        cc.pop_levels(&mut c, 1);
+
+        // `¬p` does not enable the clause either
+        cc.push_level(&mut c);
+        cc.merge(&mut c, p, b.false_, TermLit::mk_neq(p, b.true_)).unwrap();
+        check(&mut c, &mut cc).unwrap();
+        assert_ne!(cc.find(&a), cc.find(&c_));
+        cc.pop_levels(&mut c, 1);
+
+        // once `p` holds, `a=c` is merged
+        let lit_p = TermLit::mk_eq(p, b.true_);
+        cc.merge(&mut c, p, b.true_, lit_p).unwrap();
+        check(&mut c, &mut cc).unwrap();
+        assert_eq!(cc.find(&a), cc.find(&c_));
+        cc.check_invariants(&c).unwrap();
+    }
+
+    // equalities refuted by booleans, with watches moved and restored on backtracking
+    #[test]
+    fn test_watches_backtrack() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        let b = c.b();
+        let [x, y, z] = ["x", "y", "z"].map(|s| c.m.mk_str(s, Some(b.bool_)));
+        let (t, f) = (b.true_, b.false_);
+        // `x=true ∨ y=true ∨ z=true`
+        cc.add_eq_lemma(&mut c, &[(x,t), (y,t), (z,t)]).unwrap();
+        check(&mut c, &mut cc).unwrap();
+
+        for _ in 0..2 {
+            cc.push_level(&mut c);
+            cc.merge(&mut c, x, f, TermLit::mk_neq(x, t)).unwrap();
+            cc.push_level(&mut c);
+            cc.merge(&mut c, y, f, TermLit::mk_neq(y, t)).unwrap();
+            check(&mut c, &mut cc).unwrap();
+            assert_eq!(cc.find(&z), Some(t));
+            cc.check_invariants(&c).unwrap();
+            cc.pop_levels(&mut c, 1);
+            check(&mut c, &mut cc).unwrap();
+            assert_eq!(cc.find(&z), Some(z), "z is not propagated anymore");
+            // refute the other two instead
+            cc.merge(&mut c, z, f, TermLit::mk_neq(z, t)).unwrap();
+            check(&mut c, &mut cc).unwrap();
+            assert_eq!(cc.find(&y), Some(t));
+            cc.pop_levels(&mut c, 1);
+            check(&mut c, &mut cc).unwrap();
+        }
+    }
+
+    // `x=y ∨ x=z` and `p ⇒ x=y`, given to the theory rather than the SAT solver
+    #[test]
+    fn test_theory_clause() {
+        use {batsmt_cc::CCTheory, theory::Theory};
+        let mut c = mk_ctx();
+        let b = c.b();
+        let p = c.m.mk_str("p", Some(b.bool_));
+        let [x, y, z] = ["x", "y", "z"].map(|s| c.m.mk_str(s, Some(b.ty_u)));
+        let eq_xy = c.m.mk_app(b.eq, &[x, y], Some(b.bool_));
+        let eq_xz = c.m.mk_app(b.eq, &[x, z], Some(b.bool_));
+        let (lit_p, lit_xy, lit_xz) = (TermLit::mk_eq(p, b.true_), TermLit::mk_eq(x, y), TermLit::mk_eq(x, z));
+
+        let mut th: CCTheory<Ctx, ()> = CCTheory::new(&mut c);
+        th.enable_propagation(true);
+        th.add_literal(&mut c, p, lit_p);
+        th.add_literal(&mut c, eq_xy, lit_xy);
+        th.add_literal(&mut c, eq_xz, lit_xz);
+        th.assert_theory_clause(&mut c, &[lit_xy, lit_xz]).unwrap();
+        th.assert_theory_clause(&mut c, &[!lit_p, lit_xy]).unwrap();
+
+        // `x≠y` leaves `x=z`
+        th.push_level(&mut c);
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        let trail = vec![(eq_xy, false, !lit_xy)];
+        th.partial_check(&mut c, &mut acts, &theory::Trail::from_slice(&trail));
+        let props = acts.get().expect("must be satisfiable").0.to_vec();
+        assert!(props.contains(&lit_xz), "props: {:?}", props);
+        assert!(props.contains(&!lit_p), "p ⇒ x=y, so ¬p");
+        assert_eq!(th.explain_propagation(&mut c, eq_xz, true, lit_xz), &[!lit_xy]);
+        th.pop_levels(&mut c, 1);
+
+        // `p` implies `x=y`
+        th.push_level(&mut c);
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        let trail = vec![(p, true, lit_p)];
+        th.partial_check(&mut c, &mut acts, &theory::Trail::from_slice(&trail));
+        let props = acts.get().expect("must be satisfiable").0.to_vec();
+        assert!(props.contains(&lit_xy), "props: {:?}", props);
+        assert_eq!(th.explain_propagation(&mut c, eq_xy, true, lit_xy), &[lit_p]);
+
+        // and `x≠y` is now a conflict
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        let trail = vec![(eq_xy, false, !lit_xy)];
+        th.partial_check(&mut c, &mut acts, &theory::Trail::from_slice(&trail));
+        let mut confl = acts.get().expect_err("p and x≠y").to_vec();
+        confl.sort();
+        let mut expected = vec![!lit_p, lit_xy];
+        expected.sort();
+        assert_eq!(confl, expected);
+    }
+}
+
+mod egraph {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // `a=b`, `f(a)=c`, `f(b)=d`, hence `f(a)=f(b)` by congruence
+    fn setup(c: &mut Ctx) -> CC0 {
+        let mut cc = CC0::new(c);
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let [a, b_, c_, d] = ["a", "b", "c", "d"].map(|s| c.m.mk_str(s, Some(b.ty_u)));
+        let fa = c.m.mk_app(f, &[a], Some(b.ty_u));
+        let fb = c.m.mk_app(f, &[b_], Some(b.ty_u));
+        for &(t, u) in [(a, b_), (fa, c_), (fb, d)].iter() {
+            cc.merge(c, t, u, TermLit::mk_eq(t, u)).unwrap();
+        }
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(c, &mut acts);
+        assert!(acts.get().is_ok());
+        assert_eq!(cc.find(&c_), cc.find(&d));
+        cc
+    }
+
+    #[test]
+    fn test_dot_golden() {
+        let mut c = mk_ctx();
+        let cc = setup(&mut c);
+        let mut out = vec!();
+        cc.dump_egraph(&c, &mut out, EgraphFormat::Dot).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, include_str!("golden/egraph.dot"), "got:\n{}", out);
+    }
+
+    #[test]
+    fn test_json() {
+        let mut c = mk_ctx();
+        let cc = setup(&mut c);
+        let mut out = vec!();
+        cc.dump_egraph(&c, &mut out, EgraphFormat::Json).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 1);
+        assert!(out.starts_with("{\"nodes\": [{\"id\": 0, \"term\": \"true\""), "{}", out);
+        assert_eq!(out.matches("\"kind\": \"asserted\"").count(), 3);
+        assert_eq!(out.matches("\"kind\": \"congruence\"").count(), 1);
+        assert_eq!(out.matches("\"repr\": true").count(), 5); // true, false, {a,b}, {f}, {f(a),f(b),c,d}
+    }
+
+    // literals bound to atoms are printed as the atom; names are escaped
+    #[test]
+    fn test_lit_labels() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let p = c.m.mk_str("p", Some(b.bool_));
+        let q = c.m.mk_str("q\t\"r\"", Some(b.bool_));
+        let mut cc = CC0::new(&mut c);
+        let (lit_p, lit_q) = (TermLit::mk_eq(p, b.true_), TermLit::mk_eq(q, b.true_));
+        cc.add_literal(&mut c, p, lit_p);
+        cc.add_literal(&mut c, q, lit_q);
+        cc.merge(&mut c, p, b.true_, lit_p).unwrap();
+        cc.merge(&mut c, q, b.false_, !lit_q).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        assert!(acts.get().is_ok());
+
+        let mut out = vec!();
+        cc.dump_egraph(&c, &mut out, EgraphFormat::Json).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\"term\": \"q\\u0009\\\"r\\\"\""), "{}", out);
+        assert!(out.contains("\"kind\": \"asserted\", \"label\": \"p\""), "{}", out);
+        assert!(out.contains("\"label\": \"(not q\\u0009\\\"r\\\")\""), "{}", out);
+    }
+}
+
+mod levels {
+    use super::*;
+
+    #[test]
+    fn test_trail_size_at_level() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        let ty = c.b().ty_u;
+        let (a, b, d) = (c.m.mk_str("a", Some(ty)), c.m.mk_str("b", Some(ty)), c.m.mk_str("d", Some(ty)));
+        assert_eq!(cc.n_levels(), 0);
+        let size0 = cc.trail_size_at_level(0);
+
+        cc.push_level(&mut c);
+        assert_eq!(cc.n_levels(), 1);
+        assert_eq!(cc.trail_size_at_level(1), size0);
+        cc.merge(&mut c, a, b, TermLit::mk_eq(a, b)).unwrap();
+        cc.merge(&mut c, b, d, TermLit::mk_eq(b, d)).unwrap();
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+        assert!(acts.get().is_ok());
+        assert_eq!(cc.trail_size_at_level(0), size0);
+        assert!(cc.trail_size_at_level(1) > size0, "merges are on the trail");
+
+        cc.pop_levels(&mut c, 1);
+        assert_eq!(cc.n_levels(), 0);
+        assert_eq!(cc.trail_size_at_level(0), size0);
+    }
+}
+
+/// Run with `cargo test --features svec-stats -- --nocapture` to see
+/// how often small vectors spill on terms of growing arity.
+mod invariants {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // `a, b, c, f(a), f(c)`
+    fn terms(c: &mut Ctx) -> [AST; 5] {
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let x = c.m.mk_str("a", Some(b.ty_u));
+        let y = c.m.mk_str("b", Some(b.ty_u));
+        let z = c.m.mk_str("c", Some(b.ty_u));
+        let fx = c.m.mk_app(f, &[x], Some(b.ty_u));
+        let fz = c.m.mk_app(f, &[z], Some(b.ty_u));
+        [x, y, z, fx, fz]
+    }
+
+    // `f(a)=b`, then `a=c` at level 1, with `f(c)` known
+    fn setup(c: &mut Ctx, [x, y, z, fx, fz]: [AST; 5]) -> CC0 {
+        let mut cc = CC0::new(c);
+        cc.merge(c, fx, y, TermLit::mk_eq(fx, y)).unwrap();
+        cc.push_level(c);
+        cc.merge(c, x, z, TermLit::mk_eq(x, z)).unwrap();
+        cc.add_literal(c, fz, TermLit::mk_eq(fz, y));
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(c, &mut acts);
+        assert!(acts.get().is_ok());
+        cc
+    }
+
+    #[test]
+    fn test_invariants_hold() {
+        let mut c = mk_ctx();
+        let ts = terms(&mut c);
+        let [x, _, z, fx, fz] = ts;
+        let mut cc = setup(&mut c, ts);
+        assert_eq!(cc.check_invariants(&c), Ok(()));
+        assert_eq!(cc.find(&fx), cc.find(&fz));
+        cc.pop_levels(&mut c, 1);
+        assert_eq!(cc.check_invariants(&c), Ok(()));
+        assert_ne!(cc.find(&x), cc.find(&z));
+    }
+
+    // each corruption is caught, with a message about it
+    #[test]
+    fn test_corruption_is_caught() {
+        let mut c = mk_ctx();
+        let ts = terms(&mut c);
+        let [x, y, _, fx, fz] = ts;
+        let cases = vec![
+            (Corruption::RootCycle(x, y), "cyclic"),
+            (Corruption::UnlinkFromClass(fz), "class"),
+            (Corruption::WrongSignature(fx), "signature"),
+            (Corruption::LeakPending(fx), "pending"),
+        ];
+        for (corruption, msg) in cases {
+            let mut cc = setup(&mut c, ts);
+            cc.corrupt(corruption.clone());
+            match cc.check_invariants(&c) {
+                Ok(()) => panic!("{:?} is not caught", corruption),
+                Err(e) => assert!(e.contains(msg), "{:?}: unexpected error {:?}", corruption, e),
+            }
+        }
+    }
+}
+
+mod svec_aliases {
+    use batsmt_cc::{ExplVec, PropVec, ConflVec, EXPL_SVEC_SIZE, PROP_SVEC_SIZE, CONFL_SVEC_SIZE};
+
+    // push past the inline capacity, then check the usual vector operations
+    fn check_vec<V>(cap: usize) where V: Default + Extend<u32> + std::ops::DerefMut<Target=[u32]> {
+        let mut v = V::default();
+        assert!(v.is_empty());
+        v.extend((0 .. cap as u32 + 3).rev());
+        assert_eq!(v.len(), cap + 3);
+        v.sort();
+        assert_eq!(v[0], 0);
+        assert_eq!(v.iter().cloned().collect::<Vec<_>>(), (0 .. cap as u32 + 3).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_aliases_are_vectors() {
+        check_vec::<ExplVec<u32>>(EXPL_SVEC_SIZE);
+        check_vec::<PropVec<u32>>(PROP_SVEC_SIZE);
+        check_vec::<ConflVec<u32>>(CONFL_SVEC_SIZE);
+
+        let mut e: ExplVec<u32> = ExplVec::new();
+        e.push(1);
+        assert!(!e.spilled());
+        e.extend(2 .. EXPL_SVEC_SIZE as u32 + 2);
+        assert!(e.spilled());
+        e.truncate(1);
+        assert_eq!(&e[..], &[1]);
+    }
+}
+
+#[cfg(feature="svec-stats")]
+mod svec_report {
+    use {super::*, batsmt_core::ast::Manager, batsmt_cc::svec_sites};
+
+    #[test]
+    fn report_spills() {
+        let mut c = mk_ctx();
+        let mut cc = CC0::new(&mut c);
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let args: Vec<AST> =
+            (0..8).map(|i| c.m.mk_str(&format!("a{}", i), Some(b.ty_u))).collect();
+        for n in 1 .. args.len() {
+            let t = c.m.mk_app(f, &args[..n], Some(b.ty_u));
+            let u = args[n];
+            let eqn = c.m.mk_app(b.eq, &[t, u], Some(b.bool_));
+            cc.add_literal(&mut c, eqn, TermLit::mk_eq(t, u));
+        }
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        cc.final_check(&mut c, &mut acts);
+
+        for s in svec_sites::ALL.iter() {
+            println!("{}", s);
+        }
+        let sig = &svec_sites::SIGNATURE;
+        assert!(sig.uses() > 0);
+        assert!(sig.max_len() >= 7);
+        assert_eq!(sig.spills() > 0, sig.max_len() > sig.inline_cap());
+    }
+
+    // candidate inline capacities, for the report
+    const CAPS: [usize; 8] = [1, 2, 3, 4, 5, 6, 8, 16];
+
+    /// Terms of the problems in `benchs/`, each assertion as a set of atoms.
+    mod bench {
+        use {
+            super::*, std::{fs, collections::HashMap},
+            batsmt_parser::{Statement, simple_ast::{self, TermCell}},
+        };
+
+        /// Atoms of each problem in `benchs/`, by file name.
+        pub fn atoms(c: &mut Ctx) -> Vec<(String, Vec<AST>)> {
+            let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../../benchs");
+            let mut files: Vec<_> = ["sat", "unsat"].iter()
+                .flat_map(|d| fs::read_dir(format!("{}/{}", dir, d)).unwrap())
+                .map(|e| e.unwrap().path())
+                .filter(|p| p.extension().map_or(false, |e| e == "smt2"))
+                .collect();
+            files.sort();
+            files.iter().map(|file| {
+                let s = fs::read_to_string(file).unwrap();
+                let stmts = simple_ast::parse_str(&s)
+                    .unwrap_or_else(|e| panic!("cannot parse {:?}: {}", file, e));
+                let mut conv = Conv{c: &mut *c, terms: HashMap::new(), atoms: vec!()};
+                for st in stmts.iter() {
+                    if let Statement::Assert(t) = st { conv.formula(t) }
+                }
+                let name = file.file_name().unwrap().to_string_lossy().to_string();
+                (name, conv.atoms)
+            }).collect()
+        }
+
+        // convert terms into `Ctx`, sharing them by address
+        struct Conv<'a> {
+            c: &'a mut Ctx,
+            terms: HashMap<*const TermCell, AST>,
+            atoms: Vec<AST>,
+        }
+
+        impl<'a> Conv<'a> {
+            // collect atoms under boolean connectives
+            fn formula(&mut self, t: &simple_ast::Term) {
+                match t.view() {
+                    TermCell::App(f, args) if ["and", "or", "=>", "not"].contains(&f.name()) => {
+                        for u in args.iter() { self.formula(u) }
+                    },
+                    TermCell::App(f, _) if ["true", "false"].contains(&f.name()) => (),
+                    _ => {
+                        let u = self.term(t);
+                        if !self.atoms.contains(&u) { self.atoms.push(u) }
+                    },
+                }
+            }
+
+            fn sort(&mut self, s: &simple_ast::Sort) -> AST {
+                if s.name() == "Bool" { self.c.b().bool_ } else { self.c.m.mk_str(s.name(), None) }
+            }
+
+            fn term(&mut self, t: &simple_ast::Term) -> AST {
+                let key = t.view() as *const TermCell;
+                if let Some(u) = self.terms.get(&key) { return *u }
+                let b = self.c.b();
+                let u = match t.view() {
+                    TermCell::App(f, args) => {
+                        let args: Vec<AST> = args.iter().map(|u| self.term(u)).collect();
+                        let ty = Some(self.sort(&f.ret()));
+                        match f.name() {
+                            "true" => b.true_,
+                            "false" => b.false_,
+                            "=" => self.c.m.mk_app(b.eq, &args, ty),
+                            "not" => self.c.m.mk_app(b.not_, &args, ty),
+                            "distinct" => self.c.m.mk_app(b.distinct, &args, ty),
+                            name if args.is_empty() => self.c.m.mk_str(name, ty),
+                            name => {
+                                let f = self.c.m.mk_str(name, None);
+                                self.c.m.mk_app(f, &args, ty)
+                            },
+                        }
+                    },
+                    TermCell::Ite(a, b_, c_) => {
+                        let args = [self.term(a), self.term(b_), self.term(c_)];
+                        let ite = self.c.m.mk_str("ite", None);
+                        self.c.m.mk_app(ite, &args, None)
+                    },
+                    TermCell::Rational(num, den) => self.c.m.mk_str(&format!("{}/{}", num, den), None),
+                };
+                self.terms.insert(key, u);
+                u
+            }
+        }
+    }
+
+    // Run with `cargo test --release --features svec-stats -- --ignored --nocapture`.
+    //
+    // Each atom of each problem is added to a fresh `CC`, then given a value
+    // in order, the other value if `probe` finds the first one conflicting.
+    // Once all atoms have a value, the assignment is explained as for a model.
+    #[test]
+    #[ignore]
+    fn report_benchs() {
+        for s in svec_sites::ALL.iter() { s.reset() }
+        let mut c = mk_ctx();
+        let b = c.b();
+        let mut seed: u32 = 42;
+        for (name, atoms) in bench::atoms(&mut c) {
+            let mut cc = CC0::new(&mut c);
+            cc.enable_propagation(true);
+            for &t in atoms.iter() {
+                cc.add_literal(&mut c, t, TermLit::mk_eq(t, b.true_));
+            }
+            let mut n_confl = 0;
+            for &t in atoms.iter() {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                let lit = TermLit::mk_eq(t, b.true_);
+                let (lit, v) = if (seed >> 16) & 1 == 0 { (lit, b.true_) } else { (!lit, b.false_) };
+                let (lit, v) = match cc.probe(&mut c, t, v, lit).unwrap() {
+                    None => (lit, v),
+                    Some(_) => {
+                        n_confl += 1;
+                        (!lit, if v == b.true_ { b.false_ } else { b.true_ })
+                    },
+                };
+                match cc.probe(&mut c, t, v, lit).unwrap() {
+                    None => cc.merge(&mut c, t, v, lit).unwrap(),
+                    Some(_) => break, // both values conflict
+                }
+            }
+            let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+            cc.final_check(&mut c, &mut acts);
+            let n_atoms = cc.iter_assigned_atoms_with_reason(&c).count();
+            let props: Vec<_> = cc.propagations().iter().map(|p| p.lit).collect();
+            cc.explain_props(&c, &props);
+            println!("{}: {} atoms, {} with a value, {} conflicting probes",
+                name, atoms.len(), n_atoms, n_confl);
+        }
+        let mut header = format!("{:24} {:>8} {:>4} {:>4}", "site", "uses", "max", "cap");
+        for cap in CAPS.iter() { header.push_str(&format!(" {:>8}", format!("≤{}", cap))) }
+        println!("\nspills per inline capacity\n{}", header);
+        for s in svec_sites::ALL.iter().filter(|s| s.uses() > 0) {
+            let mut line = format!("{:24} {:>8} {:>4} {:>4}", s.name(), s.uses(), s.max_len(), s.inline_cap());
+            for &cap in CAPS.iter() { line.push_str(&format!(" {:>8}", s.spills_at(cap))) }
+            println!("{}", line);
+        }
+    }
+}
+
+mod cyclic {
+    use {super::*, batsmt_core::ast::Manager};
+
+    // a hand-built id makes `t = f(t)`; adding it must not loop forever
+    #[test]
+    #[should_panic(expected = "is its own subterm")]
+    fn test_self_argument() {
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let x = c.m.mk_str("x", Some(b.ty_u));
+        let fx = c.m.mk_app(f, &[x], Some(b.ty_u));
+        // id of the next application
+        let next = mk_ast_app(ast_idx(fx) + 1);
+        let t = c.m.mk_app(f, &[next], Some(b.ty_u));
+        assert_eq!(t, next);
+
+        let mut cc = CC0::new(&mut c);
+        let _ = cc.merge(&mut c, t, x, TermLit::mk_eq(t, x));
+    }
+}
+
+#[cfg(feature="metrics")]
+mod metrics {
+    use {super::*, batsmt_core::ast::Manager, batsmt_cc::CCTheory, theory::Theory};
+
+    // `f(x_0)`, `x_0=x_1=…=x_n`: merges, signature updates of `f(x_i)` and `g(g(x_i))`,
+    // propagations of `f(x_i)=true`, and their explanations
+    #[test]
+    fn test_regions_are_timed() {
+        let n = 2_000;
+        let mut c = mk_ctx();
+        let b = c.b();
+        let f = c.m.mk_str("f", None);
+        let g = c.m.mk_str("g", None);
+        let xs: Vec<AST> =
+            (0..n).map(|i| c.m.mk_str(&format!("x{}", i), Some(b.ty_u))).collect();
+        let fxs: Vec<AST> =
+            xs.iter().map(|x| c.m.mk_app(f, &[*x], Some(b.bool_))).collect();
+
+        let mut th: CCTheory<Ctx, ()> = CCTheory::new(&mut c);
+        th.enable_propagation(true);
+        for (x, fx) in xs.iter().zip(fxs.iter()) {
+            th.add_literal(&mut c, *fx, TermLit::mk_eq(*fx, b.true_));
+            let gx = c.m.mk_app(g, &[*x], Some(b.ty_u));
+            let ggx = c.m.mk_app(g, &[gx], Some(b.ty_u));
+            let eqn = c.m.mk_app(b.eq, &[ggx, *x], Some(b.bool_));
+            th.add_literal(&mut c, eqn, TermLit::mk_eq(ggx, *x));
+        }
+        let mut trail = vec![(fxs[0], true, TermLit::mk_eq(fxs[0], b.true_))];
+        for i in 0 .. n-1 {
+            let eqn = c.m.mk_app(b.eq, &[xs[i], xs[i+1]], Some(b.bool_));
+            trail.push((eqn, true, TermLit::mk_eq(xs[i], xs[i+1])));
+        }
+        let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+        th.partial_check(&mut c, &mut acts, &theory::Trail::from_slice(&trail));
+        let props = acts.get().expect("must be satisfiable").0.to_vec();
+        assert!(props.len() >= n-1);
+        for p in props.iter() {
+            let &TermLit(_, t, u) = p;
+            let fx = if t == b.true_ { u } else { t };
+            assert!(!th.explain_propagation(&mut c, fx, true, *p).is_empty());
+        }
+
+        let st = th.stats();
+        print!("{}", st);
+        let regions = [&st.queue, &st.rehash, &st.explain, &st.emit];
+        for r in regions.iter() {
+            assert!(r.calls > 0);
+            assert!(r.time > std::time::Duration::default());
+            assert!(r.max <= r.time);
+        }
+        assert_eq!(st.emit.calls, 1);
+        // explanations of conflicts are counted twice, there are none here
+        let sum: std::time::Duration = regions.iter().map(|r| r.time).sum();
+        assert!(sum <= st.total.time, "{:?} > {:?}", sum, st.total.time);
+        assert!(sum * 4 >= st.total.time, "{:?} is not most of {:?}", sum, st.total.time);
+        assert!(st.counters().iter().any(|&(name, n)| name == "cc_queue_calls" && n == st.queue.calls));
+    }
+}
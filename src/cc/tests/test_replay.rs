@@ -0,0 +1,258 @@
+
+use {
+    std::fs,
+    batsmt_core::{ast::{HasManager, Manager}, AstView, ast_u32::AST, backtrack::Backtrackable},
+    batsmt_cc::{CCTheory, CCView, Ctx as CCCtx},
+    batsmt_hast::{HManager, StrSymbolManager},
+    batsmt_pretty as pp,
+    batsmt_theory::{self as theory, IntLit, Actions, BoolLit, Theory, replay::{self, Recorder, ReplayError}},
+};
+
+type M = HManager<StrSymbolManager>;
+
+/// A context whose literals are integers, so that logs can be replayed in it.
+struct Ctx {
+    m: M,
+    bool_: AST,
+    true_: AST,
+    false_: AST,
+    eq: AST,
+}
+
+type Th = CCTheory<Ctx, ()>;
+
+mod ctx {
+    use super::*;
+
+    impl Ctx {
+        pub fn new() -> Self {
+            let mut m = M::new();
+            let bool_ = m.mk_str("Bool", None);
+            let true_ = m.mk_str("true", Some(bool_));
+            let false_ = m.mk_str("false", Some(bool_));
+            let eq = m.mk_str("=", None);
+            Ctx{m, bool_, true_, false_, eq}
+        }
+
+        pub fn mk_eq(&mut self, a: AST, b: AST) -> AST {
+            let (eq, bool_) = (self.eq, self.bool_);
+            self.m.mk_app(eq, &[a,b], Some(bool_))
+        }
+    }
+
+    impl HasManager for Ctx {
+        type M = M;
+        fn m(&self) -> &M { &self.m }
+        fn m_mut(&mut self) -> &mut M { &mut self.m }
+    }
+
+    impl theory::BoolLitCtx for Ctx {
+        type B = IntLit;
+    }
+
+    impl theory::Ctx for Ctx {
+        fn pp_ast(&self, t: &AST, ctx: &mut pp::Ctx) { pp::Pretty1::pp1_into(&self.m, t, ctx) }
+    }
+
+    impl CCCtx for Ctx {
+        type Fun = String;
+
+        fn get_bool_term(&self, b: bool) -> AST { if b { self.true_ } else { self.false_ } }
+
+        fn view_as_cc_term<'a>(&'a self, t: &'a AST) -> CCView<'a,Self::Fun,AST> {
+            if *t == self.true_ {
+                CCView::Bool(true)
+            } else if *t == self.false_ {
+                CCView::Bool(false)
+            } else {
+                match self.m.view(t) {
+                    AstView::App{f, args} if *f == self.eq => CCView::Eq(&args[0], &args[1]),
+                    AstView::App{f, args} => CCView::ApplyHO(f, args),
+                    _ => CCView::Opaque(t),
+                }
+            }
+        }
+    }
+
+    impl replay::ReplayCtx for Ctx {
+        fn replay_const(&mut self, name: &str, ty: Option<AST>) -> AST {
+            match name {
+                "Bool" => self.bool_,
+                "true" => self.true_,
+                "false" => self.false_,
+                "=" => self.eq,
+                _ => self.m.mk_string(name.to_string(), ty),
+            }
+        }
+
+        fn replay_lit(&self, i: i32) -> IntLit { IntLit::new(i) }
+    }
+}
+
+/// `th`, except that it never sees the literal `forget` in a trail.
+struct Forgetful {
+    th: Th,
+    forget: IntLit,
+    trail: Vec<(AST, bool, IntLit)>,
+}
+
+impl Forgetful {
+    fn filter(&mut self, trail: &theory::Trail<Ctx>) {
+        let forget = self.forget;
+        self.trail.clear();
+        self.trail.extend(trail.as_slice().iter().filter(|(_, _, lit)| lit.abs() != forget).cloned());
+    }
+}
+
+impl Backtrackable<Ctx> for Forgetful {
+    fn push_level(&mut self, c: &mut Ctx) { self.th.push_level(c) }
+    fn pop_levels(&mut self, c: &mut Ctx, n: usize) { self.th.pop_levels(c, n) }
+    fn n_levels(&self) -> usize { self.th.n_levels() }
+}
+
+impl Theory<Ctx> for Forgetful {
+    fn final_check<A:Actions<Ctx>>(&mut self, c: &mut Ctx, acts: &mut A, trail: &theory::Trail<Ctx>) {
+        self.filter(trail);
+        self.th.final_check(c, acts, &theory::Trail::from_slice(&self.trail))
+    }
+
+    fn partial_check<A:Actions<Ctx>>(&mut self, c: &mut Ctx, acts: &mut A, trail: &theory::Trail<Ctx>) {
+        self.filter(trail);
+        self.th.partial_check(c, acts, &theory::Trail::from_slice(&self.trail))
+    }
+
+    fn has_partial_check() -> bool { Th::has_partial_check() }
+
+    fn add_literal(&mut self, c: &mut Ctx, t: AST, lit: IntLit) { self.th.add_literal(c, t, lit) }
+
+    fn enable_propagation(&mut self, on: bool) { self.th.enable_propagation(on) }
+
+    fn explain_propagation(&mut self, c: &mut Ctx, t: AST, sign: bool, p: IntLit) -> &[IntLit] {
+        self.th.explain_propagation(c, t, sign, p)
+    }
+}
+
+// `a=b`, `b=c` propagate `f(a)=f(c)`, which is then contradicted after a backtrack.
+// Returns the log, and the event of the check that propagates.
+fn record_scripted() -> (replay::Log, usize) {
+    let mut c = Ctx::new();
+    let u = c.m.mk_str("U", None);
+    let f = c.m.mk_str("f", None);
+    let (a, b, d) = (c.m.mk_str("a", Some(u)), c.m.mk_str("b", Some(u)), c.m.mk_str("c", Some(u)));
+    let fa = c.m.mk_app(f, &[a], Some(u));
+    let fc = c.m.mk_app(f, &[d], Some(u));
+    let atoms = [c.mk_eq(a, b), c.mk_eq(b, d), c.mk_eq(fa, fc)];
+    let lits: Vec<IntLit> = (1..=3).map(IntLit::new).collect();
+
+    let th = Th::new(&mut c);
+    let mut rec = Recorder::new(th);
+    rec.enable_propagation(true);
+    for (t, lit) in atoms.iter().zip(lits.iter()) {
+        rec.add_literal(&mut c, *t, *lit);
+    }
+    let mut acts = theory::SimpleActions::new(|| unimplemented!("new lit"));
+
+    rec.push_level(&mut c);
+    rec.partial_check(&mut c, &mut acts, &theory::Trail::from_slice(&[(atoms[0], true, lits[0])]));
+    rec.push_level(&mut c);
+    acts.clear();
+    rec.partial_check(&mut c, &mut acts, &theory::Trail::from_slice(&[(atoms[1], true, lits[1])]));
+    let i_prop = rec.log().events.len() - 1;
+    assert!(acts.get().unwrap().0.contains(&lits[2]));
+    let expl = rec.explain_propagation(&mut c, atoms[2], true, lits[2]).to_vec();
+    assert_eq!(2, expl.len());
+
+    acts.clear();
+    rec.partial_check(&mut c, &mut acts, &theory::Trail::from_slice(&[(atoms[2], false, !lits[2])]));
+    assert!(acts.get().is_err(), "conflict expected");
+    rec.pop_levels(&mut c, 1);
+    acts.clear();
+    rec.final_check(&mut c, &mut acts, &theory::Trail::empty());
+    assert!(acts.get().is_ok());
+    rec.pop_levels(&mut c, 1);
+    (rec.into_log(), i_prop)
+}
+
+#[test]
+fn test_record_and_replay() {
+    let (log, _) = record_scripted();
+    assert!(log.checked);
+    assert_eq!(13, log.events.len(), "{:?}", log.events);
+
+    // the text format round-trips
+    let mut out = vec!();
+    log.write(&mut out).unwrap();
+    let s = String::from_utf8(out).unwrap();
+    let log2 = replay::Log::parse(&s).unwrap();
+    assert_eq!(log, log2, "{}", s);
+    assert!(s.contains("\n> propagate 3\n") && s.contains("\n> conflict "), "{}", s);
+
+    let mut c = Ctx::new();
+    let res = replay::run(&log2, &mut c, |c| Th::new(c));
+    assert!(res.is_ok(), "{}", res.unwrap_err());
+}
+
+#[test]
+fn test_replay_detects_divergence() {
+    let (log, i_prop) = record_scripted();
+
+    // a theory that does not see `b=c` does not propagate `f(a)=f(c)`
+    let mut c = Ctx::new();
+    let res = replay::run(&log, &mut c, |c| {
+        Forgetful{th: Th::new(c), forget: IntLit::new(2), trail: vec!()}
+    });
+    match res {
+        Err(ReplayError::Divergence{event, expected, found}) => {
+            assert_eq!(i_prop, event, "{}\n{}", expected, found);
+            assert!(expected.contains("> propagate 3\n"), "{}", expected);
+            assert!(!found.contains("> propagate 3\n"), "{}", found);
+        },
+        r => panic!("expected a divergence, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(replay::Log::parse("").is_err());
+    assert!(replay::Log::parse("batsmt-theory-log 1\nlit 0 1").is_err()); // unknown term
+    assert!(replay::Log::parse("batsmt-theory-log 1\n> propagate 1").is_err()); // no check
+    let err = replay::Log::parse("batsmt-theory-log 1\nt 0 _ c a\nfinal +0/0").unwrap_err();
+    assert_eq!(Some(3), err.pos().map(|p| p.line));
+}
+
+// logs checked in `tests/logs/`, such as minimized failures of `test_cc`
+#[test]
+fn test_replay_regression_logs() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/logs");
+    let mut n = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(true, |e| e != "log") { continue }
+        let log = replay::Log::parse(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        let mut c = Ctx::new();
+        if let Err(e) = replay::run(&log, &mut c, |c| Th::new(c)) {
+            panic!("{}: {}", path.display(), e)
+        }
+        n += 1;
+    }
+    assert!(n > 0, "no log in {}", dir);
+}
+
+// the regression logs, with every final check compared against `NaiveCC`
+#[cfg(feature="expensive-checks")]
+#[test]
+fn test_cross_check_regression_logs() {
+    use batsmt_cc::cc_theory::cross_check;
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/logs/push_pop_congruence.log");
+    let log = replay::Log::parse(&fs::read_to_string(path).unwrap()).unwrap();
+    let n = cross_check::n_cross_checks();
+    let mut c = Ctx::new();
+    let res = replay::run(&log, &mut c, |c| {
+        let mut th = Th::new(c);
+        th.set_cross_check_period(1);
+        th
+    });
+    assert!(res.is_ok(), "{}", res.unwrap_err());
+    assert!(cross_check::n_cross_checks() > n, "no cross-check was done");
+}
@@ -6,8 +6,7 @@
 
 use {
     std::{ hash::Hash, fmt::{self, Debug}, },
-    crate::{ gc, GC, },
-    fxhash::{FxHashMap, FxHashSet, },
+    crate::{ gc, GC, hash, mem::{MemTree, ReportMemory}, attr::{Attrs, AttrKey, AttrValue}, },
     batsmt_pretty as pp,
 };
 
@@ -64,6 +63,36 @@ pub trait Manager : Sized {
 
     /// Special AST node that should not be confused with anything else.
     fn sentinel(&mut self) -> Self::AST;
+
+    /// Table of attributes, if this manager stores any.
+    fn attrs(&self) -> Option<&Attrs<Self::AST>> { None }
+
+    /// Mutable access to the table of attributes, if any.
+    fn attrs_mut(&mut self) -> Option<&mut Attrs<Self::AST>> { None }
+
+    /// Key for the attribute `name` (e.g. `":named"`).
+    ///
+    /// Panics if the manager does not store attributes.
+    fn attr_key(&mut self, name: &str) -> AttrKey {
+        self.attrs_mut().expect("manager without attributes").intern(name)
+    }
+
+    /// Set attribute `k` of `t` to `v`.
+    ///
+    /// Panics if the manager does not store attributes.
+    fn set_attr(&mut self, t: Self::AST, k: AttrKey, v: AttrValue) {
+        self.attrs_mut().expect("manager without attributes").set(t, k, v)
+    }
+
+    /// Value of attribute `k` of `t`, if any.
+    #[inline]
+    fn get_attr(&self, t: &Self::AST, k: AttrKey) -> Option<&AttrValue> {
+        self.attrs().and_then(|a| a.get(t, k))
+    }
+
+    /// Does `t` have the attribute `k`?
+    #[inline]
+    fn has_attr(&self, t: &Self::AST, k: AttrKey) -> bool { self.get_attr(t, k).is_some() }
 }
 
 /// An object that may have a unique integer ID.
@@ -113,6 +142,12 @@ mod manager {
 
         #[inline(always)]
         fn sentinel(&mut self) -> Self::AST { self.m_mut().sentinel() }
+
+        #[inline(always)]
+        fn attrs(&self) -> Option<&Attrs<Self::AST>> { self.m().attrs() }
+
+        #[inline(always)]
+        fn attrs_mut(&mut self) -> Option<&mut Attrs<Self::AST>> { self.m_mut().attrs_mut() }
     }
 
     // auto impl for ref
@@ -223,6 +258,117 @@ pub fn pp_ast<M, F>(m: &M, t: &M::AST, f: &mut F, ctx: &mut pp::Ctx)
     }
 }
 
+/// Match a term against a list of patterns.
+///
+/// `match_term!(m, t, { pat => expr, … , _ => expr })` tries the patterns
+/// in order, where `m` is a `Manager` (the trait must be in scope) and each
+/// `pat` is one of:
+///
+/// - `(head; ?x, ?y)`: an application of `head` (an expression, such as
+///   `b.eq`) to exactly these arguments;
+/// - `(head; ..?args)` or `(head; ..)`: an application of `head` to any
+///   arguments, bound as a `Vec`;
+/// - `(?f; …)`: same as above, with any head symbol, bound to `f`;
+/// - `const ?c`: a constant, bound to `c`;
+/// - `_`: anything, must be the last pattern.
+///
+/// Any pattern but `_` can be followed by a guard `if cond`.
+///
+/// Subterms are bound by value, and `m` is only borrowed while matching,
+/// so the arms and guards are free to modify `m`.
+///
+/// ```
+/// use batsmt_core::{match_term, ast_u32::AST, Manager};
+///
+/// // `t` is `(not (= a b))`
+/// fn as_diseq<M: Manager<AST=AST>>(m: &M, not_: AST, eq: AST, t: AST) -> Option<(AST, AST)> {
+///     match_term!(m, t, {
+///         (not_; ?u) => match_term!(m, u, {
+///             (eq; ?a, ?b) if a != b => Some((a, b)),
+///             _ => None,
+///         }),
+///         _ => None,
+///     })
+/// }
+/// ```
+#[macro_export]
+macro_rules! match_term {
+    ($m:expr, $t:expr, { $($arms:tt)* }) => {{
+        let __t = $t.clone();
+        $crate::match_term!(@arms ($m) __t; $($arms)*)
+    }};
+    (@arms $m:tt $t:ident; _ => $body:expr $(,)?) => { $body };
+    (@arms $m:tt $t:ident; const ? $c:ident $(if $guard:expr)? => $body:expr, $($rest:tt)*) => {{
+        let __r = if $m.is_const(&$t) { Some($t.clone()) } else { None };
+        match __r {
+            Some($c) $(if $guard)? => $body,
+            _ => $crate::match_term!(@arms $m $t; $($rest)*),
+        }
+    }};
+    (@arms $m:tt $t:ident; ( $($pat:tt)* ) $(if $guard:expr)? => $body:expr, $($rest:tt)*) => {
+        $crate::match_term!(@app $m $t ($($pat)*) ($($guard)?) ($body) ($($rest)*))
+    };
+    (@arms $m:tt $t:ident;) => {
+        compile_error!("match_term!: the last pattern must be `_`")
+    };
+
+    // applications, any head symbol
+    (@app $m:tt $t:ident (? $f:ident ; $(? $x:ident),* $(,)?) $g:tt $body:tt $rest:tt) => {
+        $crate::match_term!(@app_fin $m $t $g $body $rest ($f, $($x),*) {
+            match $m.view(&$t) {
+                $crate::ast::View::App{f: __f, args: __args} => match __args {
+                    [$($x),*] => Some((__f.clone(), $($x.clone()),*)),
+                    _ => None,
+                },
+                _ => None,
+            }
+        })
+    };
+    (@app $m:tt $t:ident (? $f:ident ; .. $(? $args:ident)?) $g:tt $body:tt $rest:tt) => {
+        $crate::match_term!(@app_fin $m $t $g $body $rest ($f, $($args)?) {
+            match $m.view(&$t) {
+                $crate::ast::View::App{f: __f, args: __args} => {
+                    let _ = &__args;
+                    Some((__f.clone(), $($crate::match_term!(@vec __args $args))?))
+                },
+                _ => None,
+            }
+        })
+    };
+    // applications of a given head symbol
+    (@app $m:tt $t:ident ($head:expr ; $(? $x:ident),* $(,)?) $g:tt $body:tt $rest:tt) => {
+        $crate::match_term!(@app_fin $m $t $g $body $rest ($($x),*) {
+            match $m.view(&$t) {
+                $crate::ast::View::App{f: __f, args: __args} if *__f == $head => match __args {
+                    [$($x),*] => Some(($($x.clone()),*)),
+                    _ => None,
+                },
+                _ => None,
+            }
+        })
+    };
+    (@app $m:tt $t:ident ($head:expr ; .. $(? $args:ident)?) $g:tt $body:tt $rest:tt) => {
+        $crate::match_term!(@app_fin $m $t $g $body $rest ($($args)?) {
+            match $m.view(&$t) {
+                $crate::ast::View::App{f: __f, args: __args} if *__f == $head => {
+                    let _ = &__args;
+                    Some(($($crate::match_term!(@vec __args $args))?))
+                },
+                _ => None,
+            }
+        })
+    };
+    (@vec $args:ident $name:ident) => { $args.to_vec() };
+    (@app_fin $m:tt $t:ident ($($guard:expr)?) ($body:expr) ($($rest:tt)*) $binds:tt $matcher:block) => {{
+        // the view is dropped here, before the guard and the arm run
+        let __r = $matcher;
+        match __r {
+            Some($binds) $(if $guard)? => $body,
+            _ => $crate::match_term!(@arms $m $t; $($rest)*),
+        }
+    }};
+}
+
 /// Abstraction over sets of ASTs.
 pub trait AstSet<AST:Clone> {
     /// Create a new set.
@@ -311,14 +457,14 @@ pub trait AstMap<AST, V> {
 
 /// A hashset whose elements are AST nodes.
 #[derive(Clone,Debug,Default)]
-pub struct HashSet<AST:Hash+Eq>(FxHashSet<AST>);
+pub struct HashSet<AST:Hash+Eq>(hash::HashSet<AST>);
 
 pub mod hash_set {
     use super::*;
 
     impl<AST:Hash+Eq> HashSet<AST> {
         /// New sparse set.
-        pub fn new() -> Self { HashSet(FxHashSet::default()) }
+        pub fn new() -> Self { HashSet(hash::HashSet::default()) }
     }
 
     impl<AST:Clone+Hash+Eq> AstSet<AST> for HashSet<AST> {
@@ -348,11 +494,15 @@ pub mod hash_set {
     impl<AST:Hash+Eq> gc::HasInternalMemory for HashSet<AST> {
         fn reclaim_unused_memory(&mut self) { self.0.shrink_to_fit() }
     }
+
+    impl<AST:Hash+Eq> ReportMemory for HashSet<AST> {
+        fn memory_breakdown(&self, out: &mut MemTree) { self.0.memory_breakdown(out) }
+    }
 }
 
 /// A hashmap whose keys are AST nodes.
 #[derive(Clone,Debug,Default)]
-pub struct HashMap<AST:Eq+Hash, V>(FxHashMap<AST,V>);
+pub struct HashMap<AST:Eq+Hash, V>(hash::HashMap<AST,V>);
 
 pub mod hash_map {
     use super::*;
@@ -394,19 +544,27 @@ pub mod hash_map {
 
     impl<AST:Eq+Hash, V> HashMap<AST,V> {
         /// New hashmap
-        pub fn new() -> Self { HashMap(FxHashMap::default()) }
+        pub fn new() -> Self { HashMap(hash::HashMap::default()) }
+
+        /// Iterate over the entries, in no particular order.
+        pub fn iter(&self) -> impl Iterator<Item=(&AST,&V)> { self.0.iter() }
     }
 
     impl<AST:Hash+Eq,V> gc::HasInternalMemory for HashMap<AST,V> {
         fn reclaim_unused_memory(&mut self) { self.0.shrink_to_fit() }
     }
+
+    impl<AST:Hash+Eq,V> ReportMemory for HashMap<AST,V> {
+        fn memory_breakdown(&self, out: &mut MemTree) { self.0.memory_breakdown(out) }
+    }
 }
 
 /// Iterate over sub-terms, with an immutable borrow.
 ///
 /// Iteration over sub-terms, without repetition (sharing means a common
 /// subterm will be traversed only once).
-/// The order in which terms are traversed is not specified.
+/// `iter` visits terms in `Order::PreOrder`; `iter_ordered` and
+/// `iter_by_key` let the caller choose.
 pub mod iter_dag {
     use super::*;
 
@@ -415,6 +573,28 @@ pub mod iter_dag {
     pub struct State<AST, Set> {
         st: Vec<AST>,
         seen: Set,
+        buf: Vec<AST>, // terms collected by `iter_ordered`, in order
+        post: Vec<(AST, bool)>, // for `PostOrder`; `true` once the subterms are pushed
+    }
+
+    /// Order in which `State::iter_ordered` visits subterms.
+    ///
+    /// In every order, each subterm not seen yet is visited exactly once.
+    #[derive(Clone,Copy,Debug,PartialEq,Eq)]
+    pub enum Order {
+        /// Depth-first, each term before its subterms: the arguments
+        /// from right to left, then the function symbol.
+        PreOrder,
+        /// Depth-first, each term after its subterms: the function
+        /// symbol, then the arguments from left to right.
+        PostOrder,
+        /// By increasing distance from the root, each term before its
+        /// subterms: the function symbol, then the arguments from left to right.
+        BreadthFirst,
+    }
+
+    impl Default for Order {
+        fn default() -> Self { Order::PreOrder }
     }
 
     /// New state for iterating through subterms.
@@ -424,7 +604,7 @@ pub mod iter_dag {
     pub fn new<AST>() -> State<AST, HashSet<AST>>
         where AST: Clone+Eq+Hash
     {
-        State {seen: HashSet::new(), st: Vec::new(), }
+        State {seen: HashSet::new(), st: Vec::new(), buf: Vec::new(), post: Vec::new(), }
     }
 
     /// New state for iterating through subterms, using the given set.
@@ -434,7 +614,7 @@ pub mod iter_dag {
     pub fn new_with<AST, S>(seen: S) -> State<AST, S>
         where AST: Clone, S: AstSet<AST>
     {
-        State {seen, st: Vec::new(), }
+        State {seen, st: Vec::new(), buf: Vec::new(), post: Vec::new(), }
     }
 
     impl<AST,S> State<AST,S>
@@ -451,8 +631,76 @@ pub mod iter_dag {
         /// Clear state, forgetting all the subterms seen so far.
         pub fn clear(&mut self) {
             self.st.clear();
+            self.buf.clear();
+            self.post.clear();
             self.seen.clear();
         }
+
+        // fill `self.buf` with the subterms of `t` not seen yet, in `order`,
+        // and mark them as seen
+        fn collect<M>(&mut self, m: &M, t: &AST, order: Order) where M: Manager<AST=AST> {
+            self.buf.clear();
+            if self.seen.contains(t) { return }
+            match order {
+                Order::PreOrder => {
+                    self.st.push(t.clone());
+                    while let Some(t) = self.st.pop() {
+                        if self.seen.contains(&t) { continue }
+                        self.seen.add(t.clone());
+                        if let View::App{f,args} = m.view(&t) {
+                            self.st.push(f.clone());
+                            self.st.extend(args.iter().cloned());
+                        }
+                        self.buf.push(t);
+                    }
+                },
+                Order::PostOrder => {
+                    self.post.push((t.clone(), false));
+                    while let Some((t, expanded)) = self.post.pop() {
+                        if self.seen.contains(&t) {
+                            continue
+                        } else if expanded {
+                            self.seen.add(t.clone());
+                            self.buf.push(t);
+                            continue
+                        }
+                        self.post.push((t.clone(), true));
+                        if let View::App{f,args} = m.view(&t) {
+                            // pushed in reverse, to be visited in order
+                            for a in args.iter().rev() {
+                                if !self.seen.contains(a) { self.post.push((a.clone(), false)) }
+                            }
+                            if !self.seen.contains(f) { self.post.push((f.clone(), false)) }
+                        }
+                    }
+                },
+                Order::BreadthFirst => {
+                    // `self.st` is the queue, starting at `i`
+                    self.st.push(t.clone());
+                    let mut i = 0;
+                    while i < self.st.len() {
+                        let t = self.st[i].clone();
+                        i += 1;
+                        if self.seen.contains(&t) { continue }
+                        self.seen.add(t.clone());
+                        if let View::App{f,args} = m.view(&t) {
+                            self.st.push(f.clone());
+                            self.st.extend(args.iter().cloned());
+                        }
+                        self.buf.push(t);
+                    }
+                    self.st.clear();
+                },
+            }
+        }
+
+        // same as `collect`, sorted by `key`; ties are in `PostOrder`
+        fn collect_by_key<M,K>(&mut self, m: &M, t: &AST, mut key: K)
+            where M: Manager<AST=AST>, K: FnMut(&M, &AST) -> u64
+        {
+            self.collect(m, t, Order::PostOrder);
+            self.buf.sort_by_key(|u| key(m, u)); // stable
+        }
     }
 
     macro_rules! iter_impl {
@@ -509,16 +757,77 @@ pub mod iter_dag {
         {
             iter_impl!(self, m, t, f);
         }
+
+        /// Same as `iter`, visiting the subterms in the given order.
+        pub fn iter_ordered<M, F>(&mut self, m: &M, t: &M::AST, order: Order, mut f: F)
+            where F: FnMut(&M, &M::AST),
+                  M: Manager<AST=AST>
+        {
+            self.collect(m, t, order);
+            for u in self.buf.iter() { f(m, u) }
+            self.buf.clear();
+        }
+
+        /// Same as `iter_mut`, visiting the subterms in the given order.
+        ///
+        /// The subterms are collected before `f` is called on any of them.
+        pub fn iter_mut_ordered<M, F>(&mut self, m: &mut M, t: &M::AST, order: Order, mut f: F)
+            where F: FnMut(&mut M, &M::AST),
+                  M: Manager<AST=AST>
+        {
+            self.collect(m, t, order);
+            let buf = std::mem::replace(&mut self.buf, vec!());
+            for u in buf.iter() { f(m, u) }
+            self.buf = buf;
+            self.buf.clear();
+        }
+
+        /// Same as `iter`, visiting the subterms by increasing `key`.
+        ///
+        /// Subterms with the same key are visited in `Order::PostOrder`.
+        pub fn iter_by_key<M, K, F>(&mut self, m: &M, t: &M::AST, key: K, mut f: F)
+            where F: FnMut(&M, &M::AST),
+                  K: FnMut(&M, &M::AST) -> u64,
+                  M: Manager<AST=AST>
+        {
+            self.collect_by_key(m, t, key);
+            for u in self.buf.iter() { f(m, u) }
+            self.buf.clear();
+        }
+
+        /// Same as `iter_mut`, visiting the subterms by increasing `key`.
+        pub fn iter_mut_by_key<M, K, F>(&mut self, m: &mut M, t: &M::AST, key: K, mut f: F)
+            where F: FnMut(&mut M, &M::AST),
+                  K: FnMut(&M, &M::AST) -> u64,
+                  M: Manager<AST=AST>
+        {
+            self.collect_by_key(m, t, key);
+            let buf = std::mem::replace(&mut self.buf, vec!());
+            for u in buf.iter() { f(m, u) }
+            self.buf = buf;
+            self.buf.clear();
+        }
     }
 
     impl<AST, S> gc::HasInternalMemory for State<AST, S>
         where AST:Clone, S: AstSet<AST> + gc::HasInternalMemory {
         fn reclaim_unused_memory(&mut self) {
             self.st.shrink_to_fit();
+            self.buf.shrink_to_fit();
+            self.post.shrink_to_fit();
             self.seen.reclaim_unused_memory();
         }
     }
 
+    impl<AST, S> ReportMemory for State<AST, S> where S: ReportMemory {
+        fn memory_breakdown(&self, out: &mut MemTree) {
+            self.st.memory_breakdown(out);
+            self.buf.memory_breakdown(out);
+            self.post.memory_breakdown(out);
+            self.seen.memory_breakdown(out);
+        }
+    }
+
     impl<AST,Set> Debug for State<AST, Set> {
         fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
             write!(out, "iter_dag.state")
@@ -538,6 +847,14 @@ pub fn iter_dag<M, F>(m: &M, t: &M::AST, f: F)
     st.iter(m, t, f)
 }
 
+/// Same as `iter_dag`, visiting the subterms in the given order.
+pub fn iter_dag_ordered<M, F>(m: &M, t: &M::AST, order: iter_dag::Order, f: F)
+    where M: Manager, F: FnMut(&M, &M::AST)
+{
+    let mut st = iter_dag::new();
+    st.iter_ordered(m, t, order, f)
+}
+
 /// Compute size of the term, seen as a DAG.
 ///
 /// Each unique subterm is counted only once.
@@ -655,6 +972,13 @@ pub mod iter_suffix {
             self.seen.reclaim_unused_memory();
         }
     }
+
+    impl<AST, S> ReportMemory for State<AST, S> where S: ReportMemory {
+        fn memory_breakdown(&self, out: &mut MemTree) {
+            self.st.memory_breakdown(out);
+            self.seen.memory_breakdown(out);
+        }
+    }
 }
 
 /// Iterate over the given AST `t`,
@@ -833,3 +1157,453 @@ pub fn map_dag<M, RSym, FSym, F, R>(m: &mut M, t: &M::AST, fs: FSym, f: F) -> R
     let mut st = map_dag::new();
     st.map(m, &t, fs, f)
 }
+
+/// Translate terms from one manager into another.
+///
+/// Terms are rebuilt bottom-up in the destination manager, types included.
+pub mod translate {
+    use super::*;
+
+    /// State for translation, with the translation of every term met so far.
+    ///
+    /// Reuse the state to translate several terms: constants of the
+    /// source are then translated into the same constants every time,
+    /// whereas `Manager::mk_const` would build fresh ones.
+    #[derive(Clone)]
+    pub struct State<A:Eq+Hash, B> {
+        tasks: Vec<Task<A>>,
+        res: Vec<B>, // intermediate results
+        args: Vec<B>, // temporary arguments
+        cache: HashMap<A, B>,
+    }
+
+    #[derive(Clone)]
+    enum Task<A> {
+        Enter(A), // will push one value onto `res`
+        Exit(A), // pops the translations of the type, head and arguments
+    }
+
+    /// New state.
+    pub fn new<A:Eq+Hash, B>() -> State<A, B> {
+        State { tasks: vec!(), res: vec!(), args: vec!(), cache: HashMap::new() }
+    }
+
+    impl<A, B> State<A, B> where A: HasID+Debug+Clone+Eq+Hash, B: Clone {
+        /// Translation of `t`, from `src` into `dst`.
+        ///
+        /// Each constant becomes a constant of `dst` built from
+        /// `sym_map` applied to its symbol. Subterms shared in `t` are
+        /// translated once, so the result has the same sharing.
+        ///
+        /// Panics on `Index` nodes, which only make sense in their manager.
+        pub fn translate<Src, Dst, U, F>(
+            &mut self, src: &Src, dst: &mut Dst, t: &A, mut sym_map: F
+        ) -> B
+            where Src: Manager<AST=A>, Dst: Manager<AST=B>,
+                  F: FnMut(&Src::SymView) -> U,
+                  U: std::borrow::Borrow<Dst::SymView> + Into<Dst::SymBuilder>
+        {
+            let State{tasks, res, args, cache} = self;
+            tasks.clear();
+            res.clear();
+
+            tasks.push(Task::Enter(t.clone()));
+            while let Some(task) = tasks.pop() {
+                match task {
+                    Task::Enter(u) => {
+                        if let Some(v) = cache.get(&u) {
+                            res.push(v.clone());
+                            continue
+                        }
+                        // arguments are translated first, then the head, then the type
+                        tasks.push(Task::Exit(u.clone()));
+                        if let Some(ty) = src.ty(&u) {
+                            tasks.push(Task::Enter(ty));
+                        }
+                        match src.view(&u) {
+                            View::Const(..) => (),
+                            View::Index(i) => panic!("translate: cannot translate index {}", i),
+                            View::App{f, args} => {
+                                tasks.push(Task::Enter(f.clone()));
+                                for a in args.iter() {
+                                    tasks.push(Task::Enter(a.clone()))
+                                }
+                            },
+                        }
+                    },
+                    Task::Exit(u) => {
+                        let ty = src.ty(&u).map(|_| res.pop().unwrap());
+                        let view_u = src.view(&u);
+                        let head = match view_u {
+                            View::App{args: args0, ..} => {
+                                let head = res.pop().unwrap();
+                                args.clear();
+                                for _i in 0 .. args0.len() { args.push(res.pop().unwrap()) }
+                                Some(head)
+                            },
+                            _ => None,
+                        };
+                        // `u` might have been translated since it was entered
+                        let r = match (cache.get(&u), head) {
+                            (Some(v), _) => v.clone(),
+                            (None, Some(head)) => dst.mk_app(head, &args[..], ty),
+                            (None, None) => match view_u {
+                                View::Const(s) => dst.mk_const(sym_map(s), ty),
+                                _ => unreachable!(),
+                            },
+                        };
+                        cache.insert(u, r.clone());
+                        res.push(r);
+                    },
+                }
+            }
+            debug_assert_eq!(res.len(), 1);
+            res.pop().unwrap()
+        }
+
+        /// Forget all translations.
+        pub fn clear(&mut self) { self.cache.clear() }
+    }
+
+    impl<A:Eq+Hash, B> gc::HasInternalMemory for State<A, B> {
+        fn reclaim_unused_memory(&mut self) {
+            self.tasks.shrink_to_fit();
+            self.res.shrink_to_fit();
+            self.args.shrink_to_fit();
+            self.cache.reclaim_unused_memory();
+        }
+    }
+}
+
+/// Translate `t` from `src` into `dst`, mapping symbols with `sym_map`.
+///
+/// See `translate::State::translate`; use a `translate::State` directly
+/// to translate several terms consistently.
+pub fn translate<Src, Dst, U, F>(src: &Src, dst: &mut Dst, t: &Src::AST, sym_map: F) -> Dst::AST
+    where Src: Manager, Dst: Manager,
+          F: FnMut(&Src::SymView) -> U,
+          U: std::borrow::Borrow<Dst::SymView> + Into<Dst::SymBuilder>
+{
+    translate::new().translate(src, dst, t, sym_map)
+}
+
+/// Structural algorithms on terms seen as DAGs.
+///
+/// All the algorithms are iterative, and their temporary state lives in
+/// an `AlgoCache` that can be reused across calls.
+pub mod algo {
+    use super::*;
+
+    /// Reusable scratch state for the algorithms in `algo`.
+    #[derive(Clone)]
+    pub struct AlgoCache<AST:Eq+Hash> {
+        st: Vec<AST>,
+        seen: HashSet<AST>,
+        seen2: HashSet<AST>,
+        tasks: Vec<Task<AST>>,
+        res: Vec<AST>, // intermediate results of `substitute`
+        args: Vec<AST>, // temporary arguments
+        cache: HashMap<AST,AST>, // cache for `substitute`
+        subst: HashMap<AST,AST>, // for `replace_subterm`
+    }
+
+    #[derive(Clone)]
+    enum Task<AST> {
+        Enter(AST),
+        Exit(AST),
+    }
+
+    impl<AST> AlgoCache<AST> where AST: HasID+Debug+Eq+Hash+Clone {
+        /// New cache.
+        pub fn new() -> Self {
+            AlgoCache {
+                st: vec!(), seen: HashSet::new(), seen2: HashSet::new(),
+                tasks: vec!(), res: vec!(), args: vec!(),
+                cache: HashMap::new(), subst: HashMap::new(),
+            }
+        }
+
+        /// Does `needle` occur in `haystack`?
+        ///
+        /// Stops as soon as `needle` is found.
+        pub fn occurs_in<M>(&mut self, m: &M, needle: &AST, haystack: &AST) -> bool
+            where M: Manager<AST=AST>
+        {
+            let AlgoCache{st, seen, ..} = self;
+            st.clear();
+            seen.clear();
+
+            st.push(haystack.clone());
+            while let Some(t) = st.pop() {
+                if t == *needle {
+                    return true
+                } else if seen.contains(&t) {
+                    continue
+                }
+                seen.add(t.clone());
+                if let View::App{f, args} = m.view(&t) {
+                    st.push(f.clone());
+                    st.extend(args.iter().cloned());
+                }
+            }
+            false
+        }
+
+        /// All distinct terms that are subterms of both `t` and `u`.
+        pub fn common_subterms<M>(&mut self, m: &M, t: &AST, u: &AST) -> Vec<AST>
+            where M: Manager<AST=AST>
+        {
+            let AlgoCache{st, seen, seen2, ..} = self;
+            st.clear();
+            seen.clear();
+            seen2.clear();
+
+            // mark subterms of `t`
+            st.push(t.clone());
+            while let Some(t) = st.pop() {
+                if seen.contains(&t) { continue }
+                seen.add(t.clone());
+                if let View::App{f, args} = m.view(&t) {
+                    st.push(f.clone());
+                    st.extend(args.iter().cloned());
+                }
+            }
+
+            // traverse `u`, keeping the marked subterms
+            let mut res = vec!();
+            st.push(u.clone());
+            while let Some(u) = st.pop() {
+                if seen2.contains(&u) { continue }
+                seen2.add(u.clone());
+                if seen.contains(&u) { res.push(u.clone()) }
+                if let View::App{f, args} = m.view(&u) {
+                    st.push(f.clone());
+                    st.extend(args.iter().cloned());
+                }
+            }
+            res
+        }
+
+        /// Simultaneous substitution of the keys of `subst` by their values in `t`.
+        ///
+        /// Values of `subst` are not themselves rewritten. Terms that are
+        /// not affected by the substitution are returned unchanged.
+        pub fn substitute<M>(&mut self, m: &mut M, t: &AST, subst: &HashMap<AST,AST>) -> AST
+            where M: Manager<AST=AST>
+        {
+            let AlgoCache{tasks, res, args, cache, ..} = self;
+            tasks.clear();
+            res.clear();
+            cache.clear();
+
+            tasks.push(Task::Enter(t.clone()));
+            while let Some(task) = tasks.pop() {
+                match task {
+                    Task::Enter(u) => {
+                        if let Some(v) = subst.get(&u).or_else(|| cache.get(&u)) {
+                            res.push(v.clone());
+                            continue
+                        }
+                        match m.view(&u) {
+                            View::Const(..) | View::Index(..) => {
+                                res.push(u.clone());
+                            },
+                            View::App{f, args} => {
+                                tasks.push(Task::Exit(u.clone()));
+                                tasks.push(Task::Enter(f.clone()));
+                                for a in args.iter() {
+                                    tasks.push(Task::Enter(a.clone()))
+                                }
+                            },
+                        }
+                    },
+                    Task::Exit(u) => {
+                        let head = res.pop().unwrap();
+                        let changed = match m.view(&u) {
+                            View::App{f, args: args0} => {
+                                args.clear();
+                                for _i in 0 .. args0.len() { args.push(res.pop().unwrap()) }
+                                head != *f || &args[..] != args0
+                            },
+                            View::Const(..) | View::Index(..) => unreachable!(),
+                        };
+                        let r = if changed {
+                            let ty = m.ty(&u);
+                            m.mk_app(head, &args, ty)
+                        } else {
+                            u.clone()
+                        };
+                        cache.insert(u, r.clone());
+                        res.push(r);
+                    },
+                }
+            }
+            debug_assert_eq!(res.len(), 1);
+            res.pop().unwrap()
+        }
+
+        /// Replace every occurrence of `from` in `t` by `to`.
+        pub fn replace_subterm<M>(&mut self, m: &mut M, t: &AST, from: &AST, to: &AST) -> AST
+            where M: Manager<AST=AST>
+        {
+            let mut subst = std::mem::replace(&mut self.subst, HashMap::new());
+            subst.clear();
+            subst.insert(from.clone(), to.clone());
+            let r = self.substitute(m, t, &subst);
+            self.subst = subst;
+            r
+        }
+    }
+
+    impl<AST:Eq+Hash> gc::HasInternalMemory for AlgoCache<AST> {
+        fn reclaim_unused_memory(&mut self) {
+            self.st.shrink_to_fit();
+            self.seen.reclaim_unused_memory();
+            self.seen2.reclaim_unused_memory();
+            self.tasks.shrink_to_fit();
+            self.res.shrink_to_fit();
+            self.args.shrink_to_fit();
+            self.cache.reclaim_unused_memory();
+            self.subst.reclaim_unused_memory();
+        }
+    }
+
+    impl<AST:Eq+Hash> ReportMemory for AlgoCache<AST> {
+        fn memory_breakdown(&self, out: &mut MemTree) {
+            self.st.memory_breakdown(out);
+            self.seen.memory_breakdown(out);
+            self.seen2.memory_breakdown(out);
+            self.tasks.memory_breakdown(out);
+            self.res.memory_breakdown(out);
+            self.args.memory_breakdown(out);
+            self.cache.memory_breakdown(out);
+            self.subst.memory_breakdown(out);
+        }
+    }
+
+    impl<AST:Eq+Hash> Debug for AlgoCache<AST> {
+        fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+            write!(out, "algo.cache")
+        }
+    }
+
+    /// Does `needle` occur in `haystack`? See `AlgoCache::occurs_in`.
+    pub fn occurs_in<M:Manager>(m: &M, needle: &M::AST, haystack: &M::AST) -> bool {
+        AlgoCache::new().occurs_in(m, needle, haystack)
+    }
+
+    /// Shared subterms of `t` and `u`. See `AlgoCache::common_subterms`.
+    pub fn common_subterms<M:Manager>(m: &M, t: &M::AST, u: &M::AST) -> Vec<M::AST> {
+        AlgoCache::new().common_subterms(m, t, u)
+    }
+
+    /// Replace `from` by `to` in `t`. See `AlgoCache::replace_subterm`.
+    pub fn replace_subterm<M:Manager>(
+        m: &mut M, t: &M::AST, from: &M::AST, to: &M::AST
+    ) -> M::AST {
+        AlgoCache::new().replace_subterm(m, t, from, to)
+    }
+    /// Inline boolean `let` bindings in `t`.
+    ///
+    /// A `let` is encoded as the application `let_(x1, d1, …, xn, dn, body)`
+    /// where each `xi` is a constant, bound to `di` in `body` (the bindings
+    /// are parallel). Bindings whose definition has type `bool_` are
+    /// substituted in `body`; the other ones are kept in a smaller `let`,
+    /// or the `let` disappears if all its bindings are boolean.
+    ///
+    /// Each definition is normalized once and the result is shared by all
+    /// occurrences of its variable, so the output is a DAG of size linear
+    /// in the input.
+    pub fn inline_bool_lets<M:Manager>(
+        m: &mut M, let_: &M::AST, bool_: &M::AST, t: &M::AST
+    ) -> M::AST {
+        let mut st = InlineLets {
+            let_: let_.clone(), bool_: bool_.clone(),
+            subst: HashMap::new(), caches: vec!(HashMap::new()),
+        };
+        st.inline(m, t)
+    }
+
+    struct InlineLets<AST:Eq+Hash> {
+        let_: AST,
+        bool_: AST,
+        subst: HashMap<AST,AST>, // bound boolean variable -> inlined definition
+        caches: Vec<HashMap<AST,AST>>, // one per scope
+    }
+
+    impl<AST> InlineLets<AST> where AST: HasID+Debug+Eq+Hash+Clone {
+        fn inline<M>(&mut self, m: &mut M, t: &AST) -> AST where M: Manager<AST=AST> {
+            if let Some(u) = self.subst.get(t) {
+                return u.clone()
+            } else if let Some(u) = self.caches.last().unwrap().get(t) {
+                return u.clone()
+            }
+
+            let (f, args) = match m.view(t) {
+                View::Const(..) | View::Index(..) => return t.clone(),
+                View::App{f, args} => (f.clone(), args.to_vec()),
+            };
+
+            let res = if f == self.let_ {
+                assert!(args.len() % 2 == 1, "ill-formed `let`");
+                let body = &args[args.len()-1];
+                let bindings = &args[.. args.len()-1];
+
+                // definitions live in the outer scope
+                let mut kept = vec!();
+                let mut undo = vec!();
+                let mut new_subst = vec!();
+                for pair in bindings.chunks(2) {
+                    let (x, d) = (&pair[0], &pair[1]);
+                    let d = self.inline(m, d);
+                    if m.ty(&d).as_ref() == Some(&self.bool_) {
+                        new_subst.push((x.clone(), d));
+                    } else {
+                        kept.push(x.clone());
+                        kept.push(d);
+                    }
+                }
+
+                // `body` lives in a new scope, where non-boolean variables
+                // shadow outer bindings
+                for i in 0 .. kept.len()/2 {
+                    let x = &kept[2*i];
+                    undo.push((x.clone(), self.subst.get(x).cloned()));
+                    self.subst.remove(x);
+                }
+                for (x, d) in new_subst {
+                    undo.push((x.clone(), self.subst.get(&x).cloned()));
+                    self.subst.insert(x, d);
+                }
+                self.caches.push(HashMap::new());
+                let body = self.inline(m, body);
+                self.caches.pop();
+                for (x, old) in undo.into_iter().rev() {
+                    match old {
+                        Some(d) => self.subst.insert(x, d),
+                        None => self.subst.remove(&x),
+                    }
+                }
+
+                if kept.len() == 0 {
+                    body
+                } else {
+                    kept.push(body);
+                    let ty = m.ty(t);
+                    m.mk_app(f, &kept, ty)
+                }
+            } else {
+                let f2 = self.inline(m, &f);
+                let args2: Vec<AST> = args.iter().map(|u| self.inline(m, u)).collect();
+                if f2 == f && args2 == args {
+                    t.clone()
+                } else {
+                    let ty = m.ty(t);
+                    m.mk_app(f2, &args2, ty)
+                }
+            };
+            self.caches.last_mut().unwrap().insert(t.clone(), res.clone());
+            res
+        }
+    }
+}
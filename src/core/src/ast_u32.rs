@@ -5,7 +5,7 @@
 //! and allows us to define common types for Sets and Maps.
 
 use {
-    std::u32, crate::ast::{self, View, },
+    std::u32, crate::{ast::{self, View, AstSet, AstMap, }, gc, mem::{MemTree, ReportMemory}, },
 };
 
 /// The unique identifier of an AST node.
@@ -48,6 +48,220 @@ pub type HashMap<V> = ast::HashMap<AST, V>;
 /// A hashset whose keys are AST nodes
 pub type HashSet = ast::HashSet<AST>;
 
+/// A dense set of AST nodes, cleared in O(1) by bumping an epoch.
+///
+/// Each slot stores the epoch at which the corresponding AST was added;
+/// membership means the stamp is the current epoch. `clear()` only
+/// increments the epoch, stale stamps are ignored and only physically
+/// erased in `reclaim_unused_memory` (or when the epoch counter wraps).
+///
+/// The stamp type `E` is `u32` by default.
+#[derive(Clone,Debug)]
+pub struct EpochSet<E: Epoch = u32> {
+    stamps: Vec<E>,
+    epoch: E,
+    len: usize,
+}
+
+/// Integer type used to stamp the elements of an `EpochSet`.
+pub trait Epoch : Copy + Eq + std::fmt::Debug {
+    /// Stamp of a slot that is not in any epoch.
+    const ZERO: Self;
+
+    /// The first epoch.
+    const ONE: Self;
+
+    /// The epoch after `self`, or `None` if the counter would wrap.
+    fn next(self) -> Option<Self>;
+}
+
+pub mod epoch_set {
+    use super::*;
+
+    macro_rules! impl_epoch {
+        ($t: ty) => {
+            impl Epoch for $t {
+                const ZERO: $t = 0;
+                const ONE: $t = 1;
+                #[inline(always)]
+                fn next(self) -> Option<$t> { self.checked_add(1) }
+            }
+        }
+    }
+
+    impl_epoch!(u8);
+    impl_epoch!(u16);
+    impl_epoch!(u32);
+
+    impl<E: Epoch> EpochSet<E> {
+        /// New empty set.
+        pub fn new() -> Self {
+            EpochSet { stamps: Vec::new(), epoch: E::ONE, len: 0, }
+        }
+
+        /// Number of times the set was cleared since the last wrap.
+        #[inline(always)]
+        pub fn epoch(&self) -> E { self.epoch }
+
+        // physically reset every slot
+        fn clear_stamps(&mut self) {
+            for s in self.stamps.iter_mut() { *s = E::ZERO }
+            self.epoch = E::ONE;
+        }
+    }
+
+    impl<E: Epoch> Default for EpochSet<E> {
+        fn default() -> Self { EpochSet::new() }
+    }
+
+    impl<E: Epoch> AstSet<AST> for EpochSet<E> {
+        fn new() -> Self { EpochSet::new() }
+
+        /// Start a new epoch; O(1) unless the epoch counter wraps.
+        fn clear(&mut self) {
+            self.len = 0;
+            match self.epoch.next() {
+                Some(e) => self.epoch = e,
+                None => self.clear_stamps(), // wrap: stale stamps could match again
+            }
+        }
+
+        #[inline(always)]
+        fn len(&self) -> usize { self.len }
+
+        #[inline(always)]
+        fn contains(&self, t: &AST) -> bool {
+            self.stamps.get(t.0 as usize) == Some(&self.epoch)
+        }
+
+        #[inline]
+        fn add(&mut self, t: AST) {
+            let i = t.0 as usize;
+            if i >= self.stamps.len() {
+                self.stamps.resize(i+1, E::ZERO);
+            }
+            if self.stamps[i] != self.epoch {
+                self.stamps[i] = self.epoch;
+                self.len += 1;
+            }
+        }
+
+        #[inline]
+        fn remove(&mut self, t: &AST) {
+            let i = t.0 as usize;
+            if i < self.stamps.len() && self.stamps[i] == self.epoch {
+                self.stamps[i] = E::ZERO;
+                self.len -= 1;
+            }
+        }
+    }
+
+    impl<E: Epoch> gc::HasInternalMemory for EpochSet<E> {
+        fn reclaim_unused_memory(&mut self) {
+            if self.len == 0 {
+                self.stamps.clear();
+                self.epoch = E::ONE;
+            } else {
+                // erase stale stamps, then drop the trailing unused slots
+                let epoch = self.epoch;
+                for s in self.stamps.iter_mut() {
+                    if *s != epoch { *s = E::ZERO }
+                }
+                while self.stamps.last() == Some(&E::ZERO) {
+                    self.stamps.pop();
+                }
+            }
+            self.stamps.shrink_to_fit();
+        }
+    }
+
+    impl<E: Epoch> ReportMemory for EpochSet<E> {
+        fn memory_breakdown(&self, out: &mut MemTree) { self.stamps.memory_breakdown(out) }
+    }
+}
+
+/// A map from AST nodes, stored in a vector indexed by the AST's integer.
+///
+/// Lookups are a bound check and an index, which makes it a good fit for
+/// caches keyed by most of the terms of a manager.
+#[derive(Clone,Debug)]
+pub struct AstDenseMap<V> {
+    vals: Vec<Option<V>>,
+    len: usize,
+}
+
+pub mod dense_map {
+    use super::*;
+
+    impl<V> AstDenseMap<V> {
+        /// New empty map.
+        pub fn new() -> Self {
+            AstDenseMap { vals: Vec::new(), len: 0, }
+        }
+    }
+
+    impl<V> Default for AstDenseMap<V> {
+        fn default() -> Self { AstDenseMap::new() }
+    }
+
+    impl<V> AstMap<AST, V> for AstDenseMap<V> {
+        #[inline(always)]
+        fn get(&self, t: &AST) -> Option<&V> {
+            self.vals.get(t.0 as usize).and_then(|v| v.as_ref())
+        }
+
+        #[inline(always)]
+        fn get_mut(&mut self, t: &AST) -> Option<&mut V> {
+            self.vals.get_mut(t.0 as usize).and_then(|v| v.as_mut())
+        }
+
+        #[inline(always)]
+        fn contains(&self, t: &AST) -> bool { self.get(t).is_some() }
+
+        #[inline]
+        fn insert(&mut self, t: AST, v: V) {
+            let i = t.0 as usize;
+            if i >= self.vals.len() {
+                self.vals.resize_with(i+1, || None);
+            }
+            if self.vals[i].is_none() {
+                self.len += 1;
+            }
+            self.vals[i] = Some(v);
+        }
+
+        #[inline(always)]
+        fn len(&self) -> usize { self.len }
+
+        #[inline]
+        fn remove(&mut self, t: &AST) {
+            if let Some(v) = self.vals.get_mut(t.0 as usize) {
+                if v.take().is_some() {
+                    self.len -= 1;
+                }
+            }
+        }
+
+        fn clear(&mut self) {
+            self.vals.clear();
+            self.len = 0;
+        }
+    }
+
+    impl<V> gc::HasInternalMemory for AstDenseMap<V> {
+        fn reclaim_unused_memory(&mut self) {
+            while let Some(None) = self.vals.last() {
+                self.vals.pop();
+            }
+            self.vals.shrink_to_fit();
+        }
+    }
+
+    impl<V> ReportMemory for AstDenseMap<V> {
+        fn memory_breakdown(&self, out: &mut MemTree) { self.vals.memory_breakdown(out) }
+    }
+}
+
 /// Compute size of the term, seen as a tree.
 pub fn ast_size_tree<M:ManagerU32>(m: &mut M, t: &AST) -> usize {
     ast::map_dag(
@@ -0,0 +1,199 @@
+
+//! Attributes attached to AST nodes.
+//!
+//! Frontends use attributes to record metadata about terms and symbols
+//! (the SMT-LIB `:named` annotation, user flags such as `:commutative`…).
+//! They live in a side table, so that terms without attributes cost
+//! nothing but a bit test.
+
+use {
+    std::{fmt, cell::Cell, hash::Hash},
+    fxhash::FxHashMap,
+    bit_set::BitSet,
+    crate::{ast::HasID, mem::{self, MemTree, ReportMemory}},
+};
+
+/// Name of an attribute, interned in `Attrs`.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash,Ord,PartialOrd)]
+pub struct AttrKey(u32);
+
+/// Value of an attribute.
+#[derive(Clone,Debug,Eq,PartialEq,Hash)]
+pub enum AttrValue {
+    /// The attribute is present, without a value.
+    Flag,
+    Int(i64),
+    Str(String),
+}
+
+/// Attributes of AST nodes, indexed by `AttrKey`.
+pub struct Attrs<AST:Eq+Hash> {
+    keys: Vec<String>, // name of each key
+    has_attrs: BitSet, // IDs of terms with at least one attribute
+    tbl: FxHashMap<AST, Vec<(AttrKey, AttrValue)>>,
+    n_fast_path: Cell<u64>,
+}
+
+// keys known in advance, in the order of the `AttrKey` constants
+const BUILTIN_KEYS: &[&str] = &[":named", ":commutative", ":injective"];
+
+impl AttrKey {
+    /// Name given to a term by `(! t :named name)`.
+    pub const NAMED: AttrKey = AttrKey(0);
+    /// The function symbol is commutative in its two arguments.
+    pub const COMMUTATIVE: AttrKey = AttrKey(1);
+    /// The function symbol is injective in all its arguments.
+    pub const INJECTIVE: AttrKey = AttrKey(2);
+}
+
+impl<AST:Clone+Eq+Hash+HasID> Attrs<AST> {
+    /// New table, with only the builtin keys.
+    pub fn new() -> Self {
+        Attrs {
+            keys: BUILTIN_KEYS.iter().map(|s| s.to_string()).collect(),
+            has_attrs: BitSet::new(),
+            tbl: FxHashMap::default(),
+            n_fast_path: Cell::new(0),
+        }
+    }
+
+    /// Key for the attribute `name`, allocated if needed.
+    pub fn intern(&mut self, name: &str) -> AttrKey {
+        match self.key(name) {
+            Some(k) => k,
+            None => {
+                self.keys.push(name.to_string());
+                AttrKey((self.keys.len() - 1) as u32)
+            }
+        }
+    }
+
+    /// Key for the attribute `name`, if it was interned already.
+    pub fn key(&self, name: &str) -> Option<AttrKey> {
+        self.keys.iter().position(|s| s == name).map(|i| AttrKey(i as u32))
+    }
+
+    /// Name of the attribute `k`.
+    pub fn key_name(&self, k: AttrKey) -> &str { &self.keys[k.0 as usize] }
+
+    // cheap check that `t` has no attribute at all
+    #[inline]
+    fn surely_absent(&self, t: &AST) -> bool {
+        let absent = match t.get_id() {
+            Some(i) => !self.has_attrs.contains(i),
+            None => self.tbl.is_empty(),
+        };
+        if absent {
+            self.n_fast_path.set(self.n_fast_path.get() + 1);
+        }
+        absent
+    }
+
+    /// Set attribute `k` of `t` to `v`, replacing its previous value.
+    pub fn set(&mut self, t: AST, k: AttrKey, v: AttrValue) {
+        debug_assert!((k.0 as usize) < self.keys.len(), "unknown attribute key");
+        if let Some(i) = t.get_id() {
+            self.has_attrs.insert(i);
+        }
+        let v0 = self.tbl.entry(t).or_insert_with(|| vec!());
+        match v0.iter_mut().find(|(k2,_)| *k2 == k) {
+            Some(pair) => pair.1 = v,
+            None => v0.push((k,v)),
+        }
+    }
+
+    /// Value of the attribute `k` of `t`, if any.
+    #[inline]
+    pub fn get(&self, t: &AST, k: AttrKey) -> Option<&AttrValue> {
+        if self.surely_absent(t) { return None }
+        self.tbl.get(t)
+            .and_then(|v| v.iter().find(|(k2,_)| *k2 == k))
+            .map(|(_,v)| v)
+    }
+
+    /// All the attributes of `t`.
+    pub fn get_all(&self, t: &AST) -> &[(AttrKey, AttrValue)] {
+        if self.surely_absent(t) { return &[] }
+        self.tbl.get(t).map(|v| &v[..]).unwrap_or(&[])
+    }
+
+    /// Remove the attribute `k` of `t`, if present.
+    pub fn remove(&mut self, t: &AST, k: AttrKey) {
+        if self.surely_absent(t) { return }
+        let empty = match self.tbl.get_mut(t) {
+            Some(v) => {
+                v.retain(|(k2,_)| *k2 != k);
+                v.is_empty()
+            },
+            None => false,
+        };
+        if empty { self.remove_all(t) }
+    }
+
+    /// Remove all the attributes of `t`.
+    pub fn remove_all(&mut self, t: &AST) {
+        if self.tbl.remove(t).is_some() {
+            if let Some(i) = t.get_id() { self.has_attrs.remove(i); }
+        }
+    }
+
+    /// Terms with attribute `k`, along with its value, in no particular order.
+    pub fn iter_key<'a>(&'a self, k: AttrKey) -> impl Iterator<Item=(&'a AST, &'a AttrValue)> + 'a {
+        self.tbl.iter().filter_map(move |(t,v)| {
+            v.iter().find(|(k2,_)| *k2 == k).map(|(_,v)| (t,v))
+        })
+    }
+
+    /// Only keep the attributes of terms satisfying `f`.
+    ///
+    /// Used by managers when their terms are collected.
+    pub fn retain<F>(&mut self, mut f: F) where F: FnMut(&AST) -> bool {
+        let Attrs{tbl, has_attrs, ..} = self;
+        tbl.retain(|t,_| {
+            let keep = f(t);
+            if !keep {
+                if let Some(i) = t.get_id() { has_attrs.remove(i); }
+            }
+            keep
+        })
+    }
+
+    /// Number of terms with at least one attribute.
+    pub fn len(&self) -> usize { self.tbl.len() }
+
+    /// Number of lookups answered without touching the table,
+    /// because the term had no attribute.
+    pub fn n_fast_path(&self) -> u64 { self.n_fast_path.get() }
+
+    /// Shrink internal tables.
+    pub fn reclaim_unused_memory(&mut self) {
+        self.tbl.shrink_to_fit();
+        self.has_attrs.shrink_to_fit();
+    }
+}
+
+impl<AST:Eq+Hash> ReportMemory for Attrs<AST> {
+    fn memory_breakdown(&self, out: &mut MemTree) {
+        // values are small, only count their vectors
+        let vals: usize = self.tbl.values().map(mem::vec_bytes).sum();
+        out.add_bytes(mem::hashmap_bytes(&self.tbl) + vals + mem::bitset_bytes(&self.has_attrs));
+        out.add_bytes(mem::vec_bytes(&self.keys));
+    }
+}
+
+impl AttrValue {
+    /// The string value, if any.
+    pub fn as_str(&self) -> Option<&str> {
+        match self { AttrValue::Str(s) => Some(s), _ => None }
+    }
+}
+
+impl fmt::Display for AttrValue {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttrValue::Flag => Ok(()),
+            AttrValue::Int(i) => write!(out, "{}", i),
+            AttrValue::Str(s) => write!(out, "{}", s),
+        }
+    }
+}
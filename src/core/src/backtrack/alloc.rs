@@ -2,8 +2,9 @@
 //! Backtrackable allocator.
 
 use {
-    std::{default::Default},
+    std::{default::Default, mem::size_of},
     super::{Ref, },
+    crate::mem::{MemTree, ReportMemory, vec_bytes},
 };
 
 /// A backtrackable allocator.
@@ -113,3 +114,10 @@ impl<T:Clone> Slice<T> {
 impl Offset {
     fn new() -> Self { Offset {sl_i: 0, i: 0} }
 }
+
+impl<T:Clone> ReportMemory for Alloc<T> {
+    fn memory_breakdown(&self, out: &mut MemTree) {
+        let slices: usize = self.slices.iter().map(|s| s.len() * size_of::<T>()).sum();
+        out.add_bytes(vec_bytes(&self.slices) + slices)
+    }
+}
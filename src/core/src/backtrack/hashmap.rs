@@ -1,7 +1,7 @@
 
 use {
     std::{hash::Hash, u32, },
-    fxhash::FxHashMap,
+    crate::{mem::ReportMemory, hash},
 };
 
 /// Backtrackable hashmap.
@@ -10,7 +10,7 @@ use {
 /// because we will store old versions of them in an undo stack.
 #[derive(Clone)]
 pub struct HashMap<K:Eq+Hash,V> {
-    map: FxHashMap<K,V>,
+    map: hash::HashMap<K,V>,
     undo: Vec<Undo<K,V>>,
     levels: Vec<u32>,
 }
@@ -28,7 +28,7 @@ impl<K:Eq+Hash+Clone,V> HashMap<K,V> {
     /// Create a new backtrackable hashmap.
     pub fn new() -> Self {
         HashMap {
-            map: FxHashMap::default(),
+            map: hash::HashMap::default(),
             undo: vec!(), levels: vec!(),
         }
     }
@@ -109,6 +109,12 @@ impl<K:Eq+Hash+Clone,V> HashMap<K,V> {
     #[inline(always)]
     pub fn n_levels(&self) -> usize { self.levels.len() }
 
+    /// Are the backtracking points in order, and within the undo stack?
+    pub fn check_levels(&self) -> bool {
+        self.levels.windows(2).all(|w| w[0] <= w[1])
+            && self.levels.last().map_or(true, |n| *n as usize <= self.undo.len())
+    }
+
     /// Push a backtracking point.
     pub fn push_level(&mut self) {
         let cur_size = self.undo.len();
@@ -144,6 +150,14 @@ impl<K:Eq+Hash+Clone,V> HashMap<K,V> {
 }
 
 impl<K:Eq+Hash,V> std::ops::Deref for HashMap<K,V> {
-    type Target = FxHashMap<K,V>;
+    type Target = hash::HashMap<K,V>;
     fn deref(&self) -> &Self::Target { &self.map }
 }
+
+impl<K:Eq+Hash,V> ReportMemory for HashMap<K,V> {
+    fn memory_breakdown(&self, out: &mut crate::mem::MemTree) {
+        self.map.memory_breakdown(out);
+        self.undo.memory_breakdown(out);
+        self.levels.memory_breakdown(out);
+    }
+}
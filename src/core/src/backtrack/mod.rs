@@ -14,6 +14,39 @@ pub trait Backtrackable<Ctx> {
 
     /// Backtrack `n` levels, using `ctx` to undo changes
     fn pop_levels(&mut self, _c: &mut Ctx, n: usize);
+
+    /// Number of levels currently pushed.
+    ///
+    /// Components that are pushed and popped together must agree on it,
+    /// see `debug_assert_levels_in_sync!`.
+    fn n_levels(&self) -> usize;
+}
+
+/// Check that components have the same number of levels.
+///
+/// `levels` contains the name of each component, and its number of levels.
+/// On mismatch, the error lists all the components.
+pub fn levels_in_sync(levels: &[(&str, usize)]) -> Result<(), String> {
+    if levels.windows(2).all(|w| w[0].1 == w[1].1) {
+        return Ok(())
+    }
+    let report: Vec<_> = levels.iter().map(|(name, n)| format!("{}: {}", name, n)).collect();
+    Err(format!("backtracking levels out of sync ({})", report.join(", ")))
+}
+
+/// `debug_assert_levels_in_sync!(name1 => n1, name2 => n2, …)` panics
+/// if the numbers of levels `n1, n2, …` differ, with a report of each component.
+///
+/// Only checked if debug assertions are enabled.
+#[macro_export]
+macro_rules! debug_assert_levels_in_sync {
+    ($( $name:expr => $n:expr ),+ $(,)?) => {
+        if cfg!(debug_assertions) {
+            if let Err(msg) = $crate::backtrack::levels_in_sync(&[ $( ($name, $n) ),+ ]) {
+                panic!("{}", msg)
+            }
+        }
+    }
 }
 
 pub use {
@@ -24,9 +57,11 @@ pub use {
     self::alloc::Alloc,
 };
 
+// no state, hence no levels
 impl<C> Backtrackable<C> for () {
     fn push_level(&mut self, _: &mut C) { }
     fn pop_levels(&mut self, _: &mut C, _n: usize) {}
+    fn n_levels(&self) -> usize { 0 }
 }
 
 /// Implement `Backtrackable` for a tuple of types themselves backtrackable.
@@ -50,6 +85,14 @@ macro_rules! impl_micro_theory_tuple {
                     $t.pop_levels(c, n);
                 )*
             }
+
+            // components are pushed together, they all have the same number of levels
+            fn n_levels(&self) -> usize {
+                let ($( $t ,)*) = self;
+                let levels = [ $( $t.n_levels() ,)* ];
+                debug_assert!(levels.iter().all(|n| *n == levels[0]), "levels out of sync: {:?}", levels);
+                levels[0]
+            }
         }
 
         impl_micro_theory_tuple_peel!{ $($t,)* }
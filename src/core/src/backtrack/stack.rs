@@ -24,6 +24,21 @@ impl<T> Stack<T> {
     #[inline(always)]
     pub fn n_levels(&self) -> usize { self.levels.len() }
 
+    /// Size of the stack when level `lvl` (starting at 0) was pushed,
+    /// or its current size if `lvl == self.n_levels()`.
+    pub fn size_at_level(&self, lvl: usize) -> usize {
+        assert!(lvl <= self.levels.len(), "level {} is not pushed", lvl);
+        self.levels.get(lvl).map_or(self.st.len(), |n| *n as usize)
+    }
+
+    /// Are the backtracking points in order, and within the stack?
+    ///
+    /// This always holds, unless the stack was corrupted.
+    pub fn check_levels(&self) -> bool {
+        self.levels.windows(2).all(|w| w[0] <= w[1])
+            && self.levels.last().map_or(true, |n| *n as usize <= self.st.len())
+    }
+
     /// Push a backtracking point.
     ///
     /// This is useful for implementing `Backtrackable` by deferring undo
@@ -99,6 +114,14 @@ impl<T> Stack<T> {
         &self.st
     }
 
+    /// Mutable view into the internal operations.
+    ///
+    /// Modifications are not undone upon backtracking.
+    #[inline(always)]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.st
+    }
+
     /// Pop all levels, consume all elements.
     ///
     /// After this operation, the stack is empty, as if new.
@@ -114,3 +137,9 @@ impl<T> crate::gc::HasInternalMemory for Stack<T> {
         self.levels.shrink_to_fit();
     }
 }
+
+impl<T> crate::mem::ReportMemory for Stack<T> {
+    fn memory_breakdown(&self, out: &mut crate::mem::MemTree) {
+        out.add_bytes(crate::mem::vec_bytes(&self.st) + crate::mem::vec_bytes(&self.levels))
+    }
+}
@@ -2,7 +2,7 @@
 //! Backtrackable vector.
 
 use {
-    crate::gc::HasInternalMemory,
+    crate::{gc::HasInternalMemory, mem::{MemTree, ReportMemory}},
     super::Stack as BStack,
 };
 
@@ -138,3 +138,10 @@ impl<T:Clone> HasInternalMemory for BVec<T> {
         self.undo.reclaim_unused_memory();
     }
 }
+
+impl<T:Clone> ReportMemory for BVec<T> {
+    fn memory_breakdown(&self, out: &mut MemTree) {
+        self.v.memory_breakdown(out);
+        self.undo.memory_breakdown(out);
+    }
+}
@@ -0,0 +1,118 @@
+
+//! Errors shared by the crates of the workspace.
+//!
+//! Errors come from the input (syntax, sorts, unsupported constructs) or
+//! from the environment (I/O, resource limits). Violations of internal
+//! invariants are still panics; `Error::Internal` is for the few cases
+//! where a caller can reasonably carry on.
+
+use {
+    std::{fmt, io, error, sync::Arc},
+    batsmt_pretty as pp,
+};
+
+/// Position in the input.
+#[derive(Clone,Copy,Debug,Eq,PartialEq)]
+pub struct Pos {
+    pub line: u32,
+    pub col: u32,
+}
+
+/// An error, in any part of the solver.
+#[derive(Clone,Debug)]
+pub enum Error {
+    /// The input is ill-formed.
+    Parse { pos: Option<Pos>, msg: String },
+    /// `term` has sort `found`, where `expected` was expected.
+    Sort { expected: String, found: String, term: String },
+    /// The input is well-formed, but uses a construct that is not supported.
+    Unsupported { feature: String, pos: Option<Pos> },
+    /// Some resource (time, memory, nodes…) was exhausted.
+    ResourceOut,
+    /// The AST manager cannot allocate more than `limit` terms of a kind.
+    TermCapacity { limit: usize },
+    /// Something went wrong inside the solver.
+    Internal { msg: String },
+    Io(Arc<io::Error>),
+}
+
+/// Result using `Error`.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Quote `s` as a SMT-LIB string literal, on a single line.
+pub fn quote(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\"\""),
+            '\n' | '\r' => res.push(' '),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+impl Error {
+    /// Error for an unsupported feature, at an unknown position.
+    pub fn unsupported(feature: impl Into<String>) -> Self {
+        Error::Unsupported{feature: feature.into(), pos: None}
+    }
+
+    /// Position of the error in the input, if known.
+    pub fn pos(&self) -> Option<Pos> {
+        match self {
+            Error::Parse{pos, ..} | Error::Unsupported{pos, ..} => *pos,
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self { Error::Io(Arc::new(e)) }
+}
+
+impl fmt::Display for Pos {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "line {}, col {}", self.line, self.col)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse{pos: Some(pos), msg} => write!(out, "parse error: {} ({})", msg, pos),
+            Error::Parse{pos: None, msg} => write!(out, "parse error: {}", msg),
+            Error::Sort{expected, found, term} => {
+                write!(out, "`{}` has sort `{}`, expected `{}`", term, found, expected)
+            },
+            Error::Unsupported{feature, pos: Some(pos)} => {
+                write!(out, "unsupported: {} ({})", feature, pos)
+            },
+            Error::Unsupported{feature, pos: None} => write!(out, "unsupported: {}", feature),
+            Error::ResourceOut => write!(out, "resource limit reached"),
+            Error::TermCapacity{limit} => {
+                write!(out, "too many terms (the manager is limited to {})", limit)
+            },
+            Error::Internal{msg} => write!(out, "internal error: {}", msg),
+            Error::Io(e) => write!(out, "I/O error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+
+// printed as a SMT-LIB response
+impl pp::Pretty for Error {
+    fn pp_into(&self, ctx: &mut pp::Ctx) {
+        ctx.string(format!("(error {})", quote(&self.to_string())));
+    }
+}
@@ -0,0 +1,89 @@
+
+//! Hashers of the hash tables that decide internal orderings.
+//!
+//! By default, these tables use `FxHasher`, whose results depend on the
+//! word size of the platform. With the `deterministic` feature, they
+//! use SipHash with fixed keys instead: slower, but the iteration order
+//! of a table only depends on the sequence of operations on it.
+
+use {
+    std::{collections, hash::Hash, slice},
+    crate::{gc, mem::{self, MemTree, ReportMemory}},
+};
+
+/// Builder of the hashers, see the module documentation.
+#[cfg(not(feature="deterministic"))]
+pub type BuildHasher = fxhash::FxBuildHasher;
+
+/// Builder of the hashers, see the module documentation.
+#[cfg(feature="deterministic")]
+pub type BuildHasher = std::hash::BuildHasherDefault<collections::hash_map::DefaultHasher>;
+
+/// A hashmap using `BuildHasher`. Build it with `HashMap::default()`.
+pub type HashMap<K,V> = collections::HashMap<K,V,BuildHasher>;
+
+/// A hashset using `BuildHasher`. Build it with `HashSet::default()`.
+pub type HashSet<K> = collections::HashSet<K,BuildHasher>;
+
+/// A set that remembers the order in which elements were first inserted.
+///
+/// Iteration follows this order, whatever the hasher, so it can be
+/// part of an API without leaking the hash table's order.
+#[derive(Clone,Debug)]
+pub struct IndexSet<K> {
+    set: HashSet<K>,
+    order: Vec<K>,
+}
+
+impl<K:Eq+Hash+Clone> IndexSet<K> {
+    /// New empty set.
+    pub fn new() -> Self { IndexSet{set: HashSet::default(), order: vec!()} }
+
+    /// Add `k`, returns `true` if it was not already present.
+    pub fn insert(&mut self, k: K) -> bool {
+        let new = self.set.insert(k.clone());
+        if new { self.order.push(k) }
+        new
+    }
+
+    /// Is `k` in the set?
+    pub fn contains(&self, k: &K) -> bool { self.set.contains(k) }
+
+    /// Number of elements.
+    pub fn len(&self) -> usize { self.order.len() }
+
+    /// Is the set empty?
+    pub fn is_empty(&self) -> bool { self.order.is_empty() }
+
+    /// Remove all elements, keeping the memory.
+    pub fn clear(&mut self) {
+        self.set.clear();
+        self.order.clear();
+    }
+
+    /// Elements, in the order of their first insertion.
+    pub fn as_slice(&self) -> &[K] { &self.order }
+
+    /// Iterate on elements, in the order of their first insertion.
+    pub fn iter(&self) -> slice::Iter<'_, K> { self.order.iter() }
+}
+
+impl<K:Eq+Hash+Clone> Default for IndexSet<K> {
+    fn default() -> Self { IndexSet::new() }
+}
+
+impl<K:Eq+Hash> gc::HasInternalMemory for IndexSet<K> {
+    fn reclaim_unused_memory(&mut self) {
+        self.set.shrink_to_fit();
+        self.order.shrink_to_fit();
+    }
+}
+
+impl<K:Eq+Hash> ReportMemory for IndexSet<K> {
+    fn memory_breakdown(&self, out: &mut MemTree) {
+        out.add_bytes(mem::hashset_bytes(&self.set) + mem::vec_bytes(&self.order))
+    }
+}
+
+/// Is the `deterministic` feature enabled?
+pub const DETERMINISTIC: bool = cfg!(feature="deterministic");
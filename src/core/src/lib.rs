@@ -4,16 +4,24 @@ extern crate fxhash;
 
 pub mod ast;
 pub mod gc;
+pub mod mem;
 pub mod backtrack;
 pub mod shared;
 pub mod ast_u32;
 pub mod chrono;
+pub mod svec_stats;
+pub mod error;
+pub mod attr;
+pub mod hash;
 
 pub use crate::{
   backtrack::{Stack as BacktrackStack,Backtrackable},
   ast::{Manager, View as AstView, AstSet, AstMap, },
   gc::GC,
+  mem::{MemTree, ReportMemory},
   shared::{Shared,SharedRef,SharedRefMut},
   chrono::Chrono,
+  error::Error,
+  attr::{AttrKey, AttrValue},
 };
 
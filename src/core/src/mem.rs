@@ -0,0 +1,178 @@
+
+//! Memory usage reports.
+//!
+//! Components implement `ReportMemory` to describe what they hold on to,
+//! as a tree of named byte counts (`MemTree`). Counts are estimates
+//! computed from the capacity of internal containers
+//! (`capacity * size_of::<T>()`): they ignore the allocator's overhead,
+//! and heap data owned by the elements themselves unless the component
+//! accounts for it explicitly.
+
+use {
+    std::{fmt, mem::size_of, hash::Hash, collections::{HashMap, HashSet}},
+    bit_set::BitSet,
+};
+
+/// A named tree of byte counts.
+///
+/// Each node has its own byte count, and children; its total is the
+/// sum of both. A node can also have a capacity, if what it stores is bounded.
+#[derive(Clone,Debug,Default)]
+pub struct MemTree {
+    name: String,
+    bytes: usize, // not counting children
+    capacity: Option<usize>, // number of items, not bytes
+    children: Vec<MemTree>,
+}
+
+/// Something that can describe its memory usage.
+pub trait ReportMemory {
+    /// Add the memory used by `self` to `out`, either to its own
+    /// byte count or as children.
+    fn memory_breakdown(&self, out: &mut MemTree);
+}
+
+/// Report of `r`, at the root of a new tree named `name`.
+pub fn report<R:ReportMemory+?Sized>(name: impl Into<String>, r: &R) -> MemTree {
+    let mut t = MemTree::new(name);
+    r.memory_breakdown(&mut t);
+    t
+}
+
+/// Estimated size of the buffer of `v`.
+#[inline]
+pub fn vec_bytes<T>(v: &Vec<T>) -> usize { v.capacity() * size_of::<T>() }
+
+/// Estimated size of the table of `m` (one control byte per bucket).
+#[inline]
+pub fn hashmap_bytes<K,V,S>(m: &HashMap<K,V,S>) -> usize {
+    m.capacity() * (size_of::<(K,V)>() + 1)
+}
+
+/// Estimated size of the table of `s` (one control byte per bucket).
+#[inline]
+pub fn hashset_bytes<K,S>(s: &HashSet<K,S>) -> usize {
+    s.capacity() * (size_of::<K>() + 1)
+}
+
+/// Size of the blocks of `s`.
+#[inline]
+pub fn bitset_bytes(s: &BitSet) -> usize { s.capacity() / 8 }
+
+impl MemTree {
+    /// New tree, with no bytes and no children.
+    pub fn new(name: impl Into<String>) -> Self {
+        MemTree { name: name.into(), bytes: 0, capacity: None, children: vec!() }
+    }
+
+    /// Name of this node.
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Bytes of this node, not counting children.
+    pub fn bytes(&self) -> usize { self.bytes }
+
+    /// Bytes of this node and all its descendants.
+    pub fn total(&self) -> usize {
+        self.bytes + self.children.iter().map(|c| c.total()).sum::<usize>()
+    }
+
+    /// How many items this node can hold at most, if it is bounded.
+    ///
+    /// This is a number of items (e.g. terms), not of bytes.
+    pub fn capacity(&self) -> Option<usize> { self.capacity }
+
+    /// Bound the number of items this node can hold, see `capacity`.
+    pub fn set_capacity(&mut self, n: usize) { self.capacity = Some(n) }
+
+    /// Children of this node.
+    pub fn children(&self) -> &[MemTree] { &self.children }
+
+    /// Add `n` bytes to this node.
+    #[inline]
+    pub fn add_bytes(&mut self, n: usize) { self.bytes += n }
+
+    /// Add a child with `n` bytes.
+    pub fn leaf(&mut self, name: impl Into<String>, n: usize) {
+        self.child(name).bytes += n
+    }
+
+    /// Add a child filled by `r`.
+    pub fn add<R:ReportMemory+?Sized>(&mut self, name: impl Into<String>, r: &R) {
+        r.memory_breakdown(self.child(name))
+    }
+
+    /// Add `t` as a child.
+    pub fn push(&mut self, t: MemTree) { self.children.push(t) }
+
+    /// New, empty child; fill it using the returned reference.
+    pub fn child(&mut self, name: impl Into<String>) -> &mut MemTree {
+        self.children.push(MemTree::new(name));
+        self.children.last_mut().unwrap()
+    }
+
+    /// Descendant at the given `/`-separated path of names, if any.
+    ///
+    /// For example `t.get("cc/sig-table")`.
+    pub fn get(&self, path: &str) -> Option<&MemTree> {
+        let mut t = self;
+        for name in path.split('/').filter(|s| !s.is_empty()) {
+            t = t.children.iter().find(|c| c.name == name)?;
+        }
+        Some(t)
+    }
+
+    fn fmt_indent(&self, indent: usize, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{:indent$}{}: {}", "", self.name, Bytes(self.total()), indent=indent)?;
+        match self.capacity {
+            Some(n) => writeln!(out, " (capacity {})", n)?,
+            None => writeln!(out)?,
+        }
+        for c in self.children.iter() {
+            c.fmt_indent(indent+2, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Display a byte count with a binary unit.
+#[derive(Copy,Clone,Debug)]
+pub struct Bytes(pub usize);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+        if self.0 < 1024 {
+            return write!(out, "{}B", self.0)
+        }
+        let mut x = self.0 as f64 / 1024.;
+        let mut i = 0;
+        while x >= 1024. && i+1 < UNITS.len() {
+            x /= 1024.;
+            i += 1;
+        }
+        write!(out, "{:.1}{}", x, UNITS[i])
+    }
+}
+
+/// One line per node, children indented below their parent.
+impl fmt::Display for MemTree {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indent(0, out)
+    }
+}
+
+impl<T> ReportMemory for Vec<T> {
+    fn memory_breakdown(&self, out: &mut MemTree) { out.add_bytes(vec_bytes(self)) }
+}
+
+impl<K:Eq+Hash,V,S> ReportMemory for HashMap<K,V,S> {
+    fn memory_breakdown(&self, out: &mut MemTree) { out.add_bytes(hashmap_bytes(self)) }
+}
+
+impl<K:Eq+Hash,S> ReportMemory for HashSet<K,S> {
+    fn memory_breakdown(&self, out: &mut MemTree) { out.add_bytes(hashset_bytes(self)) }
+}
+
+impl ReportMemory for BitSet {
+    fn memory_breakdown(&self, out: &mut MemTree) { out.add_bytes(bitset_bytes(self)) }
+}
@@ -0,0 +1,117 @@
+
+//! Spill counters for small vectors.
+//!
+//! Each use site of a `SmallVec` can declare a static `Site` and call
+//! `Site::record` once the vector is filled. With the `svec-stats`
+//! feature enabled, this counts how often the vector outgrew its inline
+//! capacity; without it, recording compiles to nothing.
+//!
+//! The counts are meant to pick inline capacities (see the `SVEC_SIZE`
+//! constants in each crate), not for general profiling.
+
+use {
+    std::{fmt, sync::atomic::{AtomicUsize, Ordering}},
+};
+
+/// Lengths up to this one are counted one by one, see `Site::spills_at`.
+pub const MAX_TRACKED_LEN: usize = 64;
+
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+
+/// Counters for one small-vector use site.
+pub struct Site {
+    name: &'static str,
+    inline_cap: usize,
+    uses: AtomicUsize,
+    spills: AtomicUsize,
+    max_len: AtomicUsize,
+    lens: [AtomicUsize; MAX_TRACKED_LEN+2], // uses per length, the last one for longer ones
+}
+
+/// Whether spill counting is compiled in.
+pub const ENABLED: bool = cfg!(feature="svec-stats");
+
+impl Site {
+    /// New site, for a vector with the given inline capacity.
+    pub const fn new(name: &'static str, inline_cap: usize) -> Self {
+        Site {
+            name, inline_cap,
+            uses: AtomicUsize::new(0),
+            spills: AtomicUsize::new(0),
+            max_len: AtomicUsize::new(0),
+            lens: [ZERO; MAX_TRACKED_LEN+2],
+        }
+    }
+
+    /// Record one use of a vector of length `len`.
+    ///
+    /// No-op unless the `svec-stats` feature is enabled.
+    #[inline(always)]
+    pub fn record(&self, len: usize) {
+        if ENABLED {
+            self.uses.fetch_add(1, Ordering::Relaxed);
+            if len > self.inline_cap {
+                self.spills.fetch_add(1, Ordering::Relaxed);
+            }
+            self.lens[len.min(MAX_TRACKED_LEN+1)].fetch_add(1, Ordering::Relaxed);
+            let mut cur = self.max_len.load(Ordering::Relaxed);
+            while len > cur {
+                match self.max_len.compare_exchange_weak(
+                    cur, len, Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => break,
+                    Err(x) => cur = x,
+                }
+            }
+        }
+    }
+
+    /// Name of the site.
+    pub fn name(&self) -> &'static str { self.name }
+
+    /// Inline capacity of the vector at this site.
+    pub fn inline_cap(&self) -> usize { self.inline_cap }
+
+    /// Number of recorded uses.
+    pub fn uses(&self) -> usize { self.uses.load(Ordering::Relaxed) }
+
+    /// Number of uses that spilled to the heap.
+    pub fn spills(&self) -> usize { self.spills.load(Ordering::Relaxed) }
+
+    /// Largest recorded length.
+    pub fn max_len(&self) -> usize { self.max_len.load(Ordering::Relaxed) }
+
+    /// Number of uses that would have spilled with inline capacity `cap`.
+    ///
+    /// This is how other capacities compare on the same run.
+    /// Panics if `cap > MAX_TRACKED_LEN`.
+    pub fn spills_at(&self, cap: usize) -> usize {
+        assert!(cap <= MAX_TRACKED_LEN, "capacity {} is not tracked", cap);
+        self.lens[cap+1..].iter().map(|n| n.load(Ordering::Relaxed)).sum()
+    }
+
+    /// Reset counters.
+    pub fn reset(&self) {
+        self.uses.store(0, Ordering::Relaxed);
+        self.spills.store(0, Ordering::Relaxed);
+        self.max_len.store(0, Ordering::Relaxed);
+        for n in self.lens.iter() { n.store(0, Ordering::Relaxed) }
+    }
+}
+
+impl fmt::Display for Site {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}: cap {}, {} uses, {} spills, max len {}",
+               self.name, self.inline_cap, self.uses(), self.spills(), self.max_len())
+    }
+}
+
+/// Print the counters of `sites` on stderr, if enabled.
+pub fn dump(sites: &[&Site]) {
+    if ENABLED {
+        for s in sites {
+            if s.uses() > 0 {
+                eprintln!("svec-stats: {}", s);
+            }
+        }
+    }
+}
@@ -102,6 +102,61 @@ mod backtrack {
         assert_eq!(*s, 1);
     }
 
+    #[test]
+    fn test_stack_size_at_level() {
+        let mut s = Stack::new();
+        s.push(0);
+        s.push_level();
+        s.push(1);
+        s.push(2);
+        s.push_level();
+        s.push(3);
+        assert_eq!(s.size_at_level(0), 1);
+        assert_eq!(s.size_at_level(1), 3);
+        assert_eq!(s.size_at_level(2), 4);
+        s.pop_levels(1, |_| ());
+        assert_eq!(s.size_at_level(1), 3);
+    }
+
+    // a component that counts its levels, but forgets to push some of them
+    struct Forgetful { levels: usize, forget_at: usize }
+
+    impl Backtrackable<()> for Forgetful {
+        fn push_level(&mut self, _: &mut ()) {
+            if self.levels + 1 != self.forget_at { self.levels += 1 }
+        }
+        fn pop_levels(&mut self, _: &mut (), n: usize) {
+            self.levels -= n.min(self.levels)
+        }
+        fn n_levels(&self) -> usize { self.levels }
+    }
+
+    #[test]
+    fn test_levels_in_sync_report() {
+        assert_eq!(levels_in_sync(&[]), Ok(()));
+        assert_eq!(levels_in_sync(&[("a", 2), ("b", 2), ("c", 2)]), Ok(()));
+        assert_eq!(levels_in_sync(&[("a", 2), ("b", 1), ("c", 2)]),
+            Err("backtracking levels out of sync (a: 2, b: 1, c: 2)".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "backtracking levels out of sync (stack: 2, forgetful: 1)")]
+    fn test_levels_out_of_sync() {
+        let mut st: Stack<u32> = Stack::new();
+        let mut f = Forgetful{levels: 0, forget_at: 2};
+        for _ in 0..2 {
+            st.push_level();
+            f.push_level(&mut ());
+            if cfg!(not(debug_assertions)) {
+                // the macro is a no-op in release mode
+                levels_in_sync(&[("stack", st.n_levels()), ("forgetful", f.n_levels())])
+                    .unwrap_or_else(|msg| panic!("{}", msg));
+            }
+            batsmt_core::debug_assert_levels_in_sync!(
+                "stack" => st.n_levels(), "forgetful" => f.n_levels());
+        }
+    }
+
     // ##### random tests #####
 
     #[derive(Clone,Debug)]
@@ -168,3 +223,155 @@ mod backtrack {
         }
     }
 }
+
+mod epoch_set {
+    use super::*;
+    use {
+        std::collections::HashSet,
+        batsmt_core::{
+            gc::HasInternalMemory, AstSet,
+            ast_u32::{AST, EpochSet, manager_util::ast_from_u32}
+        },
+    };
+
+    #[test]
+    fn test_clear_many_epochs() {
+        let mut s: EpochSet = EpochSet::new();
+        for i in 0..5_000u32 {
+            let t = ast_from_u32(i % 37);
+            assert!(! s.contains(&t));
+            s.add(t);
+            s.add(ast_from_u32(100 + i % 5));
+            assert!(s.contains(&t));
+            assert_eq!(s.len(), 2);
+            s.clear();
+            assert_eq!(s.len(), 0);
+            assert!(! s.contains(&t));
+        }
+    }
+
+    #[test]
+    fn test_wrap_no_stale() {
+        // `u8` stamps wrap every 255 clears
+        let mut s: EpochSet<u8> = EpochSet::new();
+        let old = ast_from_u32(3);
+        s.add(old);
+        for i in 0..2_000u32 {
+            s.clear();
+            assert!(! s.contains(&old), "stale entry seen after {} clears", i+1);
+            // touch other slots so stamps of all epochs are around
+            s.add(ast_from_u32(i % 11 + 4));
+        }
+        s.clear();
+        s.reclaim_unused_memory();
+        assert_eq!(s.len(), 0);
+        assert!(! s.contains(&old));
+    }
+
+    #[derive(Clone,Debug)]
+    enum Op {
+        Add(u32),
+        Remove(u32),
+        Clear,
+        Reclaim,
+    }
+
+    fn set_op() -> BoxedStrategy<Op> {
+        prop_oneof![
+            6 => (0..64u32).prop_map(Op::Add),
+            2 => (0..64u32).prop_map(Op::Remove),
+            3 => Just(Op::Clear),
+            1 => Just(Op::Reclaim),
+        ].boxed()
+    }
+
+    // compare against a real set that is physically cleared
+    proptest! {
+        #![proptest_config(Config::with_cases(500))]
+        #[test]
+        fn proptest_epoch_set_eq_hashset(ref ops in prop::collection::vec(set_op(), 0..1_500)) {
+            let mut s: EpochSet<u8> = EpochSet::new();
+            let mut s_ref: HashSet<AST> = HashSet::new();
+
+            for o in ops.iter() {
+                match o {
+                    Op::Add(i) => { s.add(ast_from_u32(*i)); s_ref.insert(ast_from_u32(*i)); },
+                    Op::Remove(i) => { s.remove(&ast_from_u32(*i)); s_ref.remove(&ast_from_u32(*i)); },
+                    Op::Clear => { s.clear(); s_ref.clear(); },
+                    Op::Reclaim => s.reclaim_unused_memory(),
+                }
+                prop_assert_eq!(s.len(), s_ref.len());
+                for i in 0..64 {
+                    let t = ast_from_u32(i);
+                    prop_assert_eq!(s.contains(&t), s_ref.contains(&t));
+                }
+            }
+        }
+    }
+}
+
+mod svec_stats {
+    use batsmt_core::svec_stats::{self, Site};
+
+    #[test]
+    fn test_record() {
+        static SITE: Site = Site::new("test", 2);
+        for len in &[0, 1, 2, 3, 5] { SITE.record(*len) }
+        if svec_stats::ENABLED {
+            assert_eq!(SITE.uses(), 5);
+            assert_eq!(SITE.spills(), 2);
+            assert_eq!(SITE.max_len(), 5);
+            assert_eq!(SITE.spills_at(2), SITE.spills());
+            assert_eq!((SITE.spills_at(0), SITE.spills_at(3), SITE.spills_at(5)), (4, 1, 0));
+        } else {
+            assert_eq!(SITE.uses(), 0);
+        }
+        SITE.reset();
+        assert_eq!(SITE.uses(), 0);
+    }
+}
+
+mod mem {
+    use batsmt_core::mem::{self, MemTree, ReportMemory, Bytes};
+
+    struct Two(Vec<u64>, Vec<u8>);
+
+    impl ReportMemory for Two {
+        fn memory_breakdown(&self, out: &mut MemTree) {
+            out.add("a", &self.0);
+            out.leaf("b", mem::vec_bytes(&self.1));
+        }
+    }
+
+    #[test]
+    fn test_tree() {
+        let x = Two(Vec::with_capacity(10), Vec::with_capacity(100));
+        let mut t = MemTree::new("root");
+        t.add_bytes(1);
+        t.add("x", &x);
+        assert_eq!(1 + 80 + 100, t.total());
+        assert_eq!(1, t.bytes());
+        assert_eq!(Some(80), t.get("x/a").map(|t| t.total()));
+        assert_eq!(Some(180), t.get("x").map(|t| t.total()));
+        assert!(t.get("x/c").is_none());
+        assert_eq!("root: 181B\n  x: 180B\n    a: 80B\n    b: 100B\n", t.to_string());
+
+        // a capacity is not a number of bytes
+        let mut t = MemTree::new("root");
+        t.child("terms").set_capacity(4);
+        assert_eq!(0, t.total());
+        assert_eq!(Some(4), t.get("terms").and_then(|t| t.capacity()));
+        assert_eq!("root: 0B\n  terms: 0B (capacity 4)\n", t.to_string());
+
+        // shrinking is visible
+        let x = Two(vec!(), Vec::with_capacity(100));
+        assert_eq!(100, mem::report("x", &x).total());
+    }
+
+    #[test]
+    fn test_bytes() {
+        assert_eq!("1023B", Bytes(1023).to_string());
+        assert_eq!("1.5KiB", Bytes(1536).to_string());
+        assert_eq!("2.0MiB", Bytes(2 << 20).to_string());
+    }
+}
@@ -10,10 +10,12 @@ pub mod symbol;
 
 use {
     std::{
-        slice, u32, marker::PhantomData, fmt,
+        slice, u32, marker::PhantomData, fmt, mem::size_of,
+    },
+    batsmt_core::{
+        ast::{self,Manager}, ast_u32, gc, AstView, attr::Attrs, Error, hash,
+        mem::{self, MemTree, ReportMemory},
     },
-    batsmt_core::{ ast::{self,Manager}, ast_u32, gc, AstView, },
-    fxhash::{FxHashMap},
     bit_set::BitSet,
     batsmt_pretty as pp,
 };
@@ -204,6 +206,7 @@ const AST_MAX_IDX : u32 = (u32::MAX >> 2);
 /// A vector with some additional metadata.
 struct ManagedVec<T> {
     sentinel: T,
+    limit: u32, // maximum number of slots, at most `AST_MAX_IDX+1`
     vec: Vec<T>,
     gc_alive: BitSet,
     recycle: Vec<u32>, // slots in `nodes` that are available
@@ -221,30 +224,33 @@ impl<T> std::ops::DerefMut for ManagedVec<T> {
 }
 
 impl<T: Clone> ManagedVec<T> {
-    /// New managed vec.
-    fn new(sentinel: T) -> Self {
+    /// New managed vec, with at most `limit` slots.
+    fn new(sentinel: T, limit: u32) -> Self {
+        debug_assert!(limit <= AST_MAX_IDX + 1);
         ManagedVec {
-            sentinel,
+            sentinel, limit,
             vec: vec!(), gc_alive: BitSet::new(),
             recycle: vec!(), }
     }
 
-    /// allocate a new AST ID, and return the slot it should live in
-    fn allocate_id(&mut self) -> (u32, &mut T) {
+    /// allocate a new AST ID, and return the slot it should live in.
+    ///
+    /// Fails if all the slots are used; slots freed by the GC are reused.
+    fn allocate_id(&mut self) -> Result<(u32, &mut T), Error> {
         match self.recycle.pop() {
             Some(n) => {
                 let slot = &mut self.vec[n as usize];
-                (n,slot)
+                Ok((n,slot))
             },
             None => {
                 let n = self.vec.len();
                 // does `n` fit in an AST?
-                if n > AST_MAX_IDX as usize {
-                    panic!("cannot allocate more AST nodes")
+                if n >= self.limit as usize {
+                    return Err(Error::TermCapacity{limit: self.limit as usize})
                 }
                 self.vec.push(self.sentinel.clone());
                 let slot = &mut self.vec[n];
-                (n as u32, slot)
+                Ok((n as u32, slot))
             }
         }
     }
@@ -278,6 +284,11 @@ impl<T: Clone> ManagedVec<T> {
         self.recycle.shrink_to_fit();
     }
 
+    // the vector, and GC metadata
+    fn bytes(&self) -> usize {
+        mem::vec_bytes(&self.vec) + mem::bitset_bytes(&self.gc_alive) + mem::vec_bytes(&self.recycle)
+    }
+
     /// Recycle element at index `i`, using `f` to free its content if needed.
     fn recycle<F>(&mut self, i: u32, f: F)
         where F: FnOnce(T)
@@ -319,8 +330,9 @@ struct ConstStored<S:Clone> {
 pub struct HManager<S:SymbolManager> {
     apps: ManagedVec<AppStored<'static>>,
     consts: ManagedVec<ConstStored<S::Ref>>,
-    tbl_app: FxHashMap<AppStored<'static>, AST>, // hashconsing of applications
+    tbl_app: hash::HashMap<AppStored<'static>, AST>, // hashconsing of applications
     sym_m: S,
+    attrs: Attrs<AST>,
     gc_stack: Vec<AST>, // temporary vector for GC marking
 }
 
@@ -364,29 +376,10 @@ impl<S:SymbolManager> Manager for HManager<S> {
     /// If the term is structurally equal to an existing term, then this
     /// ensures the exact same AST is returned ("hashconsing").
     /// If `args` is empty, return `f`.
+    ///
+    /// Panics if the manager is full, see `try_mk_app`.
     fn mk_app(&mut self, f: AST, args: &[AST], ty: Option<AST>) -> AST {
-        if args.len() == 0 { return f }
-
-        let ty = ty.unwrap_or(AST::SENTINEL);
-        let k = AppStored::mk_ref(f, args, ty);
-
-        // borrow multiple fields
-        let HManager {apps, tbl_app, ..} = self;
-
-        match tbl_app.get(&k) {
-            Some(&a) => a, // fast path
-            None => {
-                // insert
-                let (n, slot) = apps.allocate_id();
-                let ast = mk_ast_app(n);
-                // make 2 owned copies of the key
-                let k = k.to_owned();
-                *slot = k.clone();
-                tbl_app.insert(k, ast);
-                // return AST
-                ast
-            }
-        }
+        self.try_mk_app(f, args, ty).unwrap_or_else(|e| panic!("mk_app: {}", e))
     }
 
     /// Make a term from a symbol.
@@ -395,34 +388,93 @@ impl<S:SymbolManager> Manager for HManager<S> {
     /// will result in two distinct ASTs (as if the second one
     /// was shadowing the first). Use an auxiliary hashtable if
     /// you want sharing.
+    ///
+    /// Panics if the manager is full, see `try_mk_const`.
     fn mk_const<U>(&mut self, s: U, ty: Option<AST>) -> AST
         where U: std::borrow::Borrow<Self::SymView> + Into<Self::SymBuilder>
     {
-        let ty = ty.unwrap_or(AST::SENTINEL);
-        let r = self.sym_m.build(s);
-        let (n, slot) = self.consts.allocate_id();
-        *slot = ConstStored{sym: r, ty};
-        mk_ast_const(n)
+        self.try_mk_const(s, ty).unwrap_or_else(|e| panic!("mk_const: {}", e))
     }
 
     fn sentinel(&mut self) -> AST { AST::SENTINEL }
+
+    #[inline(always)]
+    fn attrs(&self) -> Option<&Attrs<AST>> { Some(&self.attrs) }
+
+    #[inline(always)]
+    fn attrs_mut(&mut self) -> Option<&mut Attrs<AST>> { Some(&mut self.attrs) }
 }
 
 impl<S:SymbolManager> HManager<S> {
     /// Create a new AST manager
-    pub fn new() -> Self {
-        let mut tbl_app = FxHashMap::default();
-        tbl_app.reserve(1_024);
+    pub fn new() -> Self { Self::with_term_limit(AST_MAX_IDX + 1) }
+
+    /// New AST manager, that holds at most `limit` applications and
+    /// `limit` constants at a time.
+    ///
+    /// This is mostly useful to test how running out of terms is handled.
+    /// `limit` is capped to what fits in an `AST`.
+    pub fn with_term_limit(limit: u32) -> Self {
+        let limit = limit.min(AST_MAX_IDX + 1);
+        let mut tbl_app = hash::HashMap::default();
+        tbl_app.reserve(1_024.min(limit as usize));
         let sym_m = S::new();
         HManager {
-            apps: ManagedVec::new(AppStored::SENTINEL),
-            consts: ManagedVec::new(ConstStored{sym: sym_m.sentinel(), ty: AST::SENTINEL}),
+            apps: ManagedVec::new(AppStored::SENTINEL, limit),
+            consts: ManagedVec::new(ConstStored{sym: sym_m.sentinel(), ty: AST::SENTINEL}, limit),
             tbl_app,
             sym_m,
+            attrs: Attrs::new(),
             gc_stack: Vec::new(),
         }
     }
 
+    /// Maximum number of applications, and of constants, alive at a time.
+    pub fn term_limit(&self) -> usize { self.apps.limit as usize }
+
+    /// Like `mk_app`, but fails with `Error::TermCapacity` if there is no
+    /// room for a new term.
+    ///
+    /// The manager is still usable afterwards: terms that are already
+    /// built can be returned, and collecting dead terms makes room.
+    pub fn try_mk_app(&mut self, f: AST, args: &[AST], ty: Option<AST>) -> Result<AST, Error> {
+        if args.len() == 0 { return Ok(f) }
+
+        let ty = ty.unwrap_or(AST::SENTINEL);
+        let k = AppStored::mk_ref(f, args, ty);
+
+        // borrow multiple fields
+        let HManager {apps, tbl_app, ..} = self;
+
+        match tbl_app.get(&k) {
+            Some(&a) => Ok(a), // fast path
+            None => {
+                // insert
+                let (n, slot) = apps.allocate_id()?;
+                let ast = mk_ast_app(n);
+                // make 2 owned copies of the key
+                let k = k.to_owned();
+                *slot = k.clone();
+                tbl_app.insert(k, ast);
+                // return AST
+                Ok(ast)
+            }
+        }
+    }
+
+    /// Like `mk_const`, but fails with `Error::TermCapacity` if there is no
+    /// room for a new term.
+    pub fn try_mk_const<U>(&mut self, s: U, ty: Option<AST>) -> Result<AST, Error>
+        where U: std::borrow::Borrow<S::View> + Into<S::Builder>
+    {
+        let ty = ty.unwrap_or(AST::SENTINEL);
+        // check for room before building the symbol
+        let (n, slot) = self.consts.allocate_id()?;
+        let r = self.sym_m.build(s);
+        *slot = ConstStored{sym: r, ty};
+        Ok(mk_ast_const(n))
+    }
+
     /// Create an "index" term from the given symbol.
     pub fn mk_idx(&mut self, i: u32) -> AST {
         if i > AST_MAX_IDX {
@@ -482,7 +534,14 @@ impl<S:SymbolManager> HManager<S> {
     fn gc_retain_roots(&mut self) -> usize {
         let mut count = 0;
 
-        let HManager{apps, tbl_app, consts, sym_m, ..} = self;
+        let HManager{apps, tbl_app, consts, sym_m, attrs, ..} = self;
+
+        // attributes of dead terms go away with them
+        attrs.retain(|&t| {
+            if ast_is_app(t) { apps.alive(ast_idx(t)) }
+            else if ast_is_const(t) { consts.alive(ast_idx(t)) }
+            else { true }
+        });
 
         count += apps.gc_retain_roots(|mut app| { 
             // remove from table
@@ -539,11 +598,33 @@ impl<S> gc::HasInternalMemory for HManager<S> where S: SymbolManager {
         self.apps.reclaim_unused_memory();
         self.consts.reclaim_unused_memory();
         self.tbl_app.shrink_to_fit();
+        self.attrs.reclaim_unused_memory();
         self.gc_stack.shrink_to_fit();
     }
 }
 
 
+/// Nodes (with GC metadata), hashconsing table, arguments of large
+/// applications (shared by the nodes and the table), symbols and attributes.
+impl<S> ReportMemory for HManager<S> where S: SymbolManager {
+    fn memory_breakdown(&self, out: &mut MemTree) {
+        let args: usize = self.apps.iter()
+            .filter(|app| app.len as usize > N_SMALL_APP)
+            .map(|app| app.len as usize * size_of::<AST>())
+            .sum();
+        let nodes = out.child("nodes");
+        nodes.add_bytes(self.apps.bytes() + self.consts.bytes() + mem::vec_bytes(&self.gc_stack));
+        // applications, and as many constants, see `term_limit`
+        if self.apps.limit <= AST_MAX_IDX {
+            nodes.set_capacity(self.apps.limit as usize);
+        }
+        out.leaf("app-table", mem::hashmap_bytes(&self.tbl_app));
+        out.leaf("args", args);
+        out.add("symbols", &self.sym_m);
+        out.add("attrs", &self.attrs);
+    }
+}
+
 /// GC for a manager's internal nodes
 impl<S> gc::GC for HManager<S> where S: SymbolManager {
     type Element = AST;
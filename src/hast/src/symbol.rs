@@ -1,6 +1,6 @@
 
 use {
-    batsmt_core::{gc, },
+    batsmt_core::{gc, mem::ReportMemory, },
     batsmt_pretty as pp,
 };
 
@@ -25,7 +25,7 @@ pub trait SymbolCtx {
 /// to the logic signature, set of sorts, custom domain elements
 /// (such as arithmetic constants, datatype constructors, etc.).
 pub trait SymbolManager
-    : Sized + gc::HasInternalMemory
+    : Sized + gc::HasInternalMemory + ReportMemory
     + SymbolCtx<SymM=Self>
     + for<'a> pp::Pretty1<&'a <Self as SymbolCtx>::View>
 {
@@ -53,7 +53,8 @@ pub mod str {
     use {
         super::*,
         fxhash::FxHashMap,
-        std::{u32, rc::Rc},
+        std::{u32, rc::Rc, mem::size_of},
+        batsmt_core::mem::{self, MemTree},
     };
 
     /// Manager for string symbols.
@@ -103,7 +104,11 @@ pub mod str {
                     let owned: Self::Builder = s.into();
                     let r = self.allocate_new_id();
                     self.tbl.insert(owned.clone(), r);
-                    self.syms.push(owned);
+                    if (r as usize) < self.syms.len() {
+                        self.syms[r as usize] = owned; // recycled ID
+                    } else {
+                        self.syms.push(owned);
+                    }
                     r
                 }
             }
@@ -125,7 +130,7 @@ pub mod str {
     impl<'a> pp::Pretty1<&'a str> for StrManager {
         // SMTLIB printing
         fn pp1_into(&self, s: &&'a str, ctx: &mut pp::Ctx) {
-            let escape = s.contains(|c| {c == ' ' || c == '\n'});
+            let escape = s.contains(|c: char| {c == ' ' || c == '\n' || !c.is_ascii()});
             if escape { ctx.str("|"); }
             ctx.string(s.to_string());
             if escape { ctx.str("|"); }
@@ -140,6 +145,17 @@ pub mod str {
         }
     }
 
+    impl ReportMemory for StrManager {
+        fn memory_breakdown(&self, out: &mut MemTree) {
+            // each string is shared by `tbl` and `syms`, next to two refcounts
+            let strings: usize =
+                self.syms.iter().map(|s| s.len() + 2 * size_of::<usize>()).sum();
+            out.leaf("table", mem::hashmap_bytes(&self.tbl));
+            out.leaf("ids", mem::vec_bytes(&self.syms) + mem::vec_bytes(&self.recycle));
+            out.leaf("strings", strings);
+        }
+    }
+
     impl StrManager {
         fn allocate_new_id(&mut self) -> u32 {
             if let Some(r) = self.recycle.pop() {
@@ -238,7 +254,11 @@ pub mod str_id {
                     let owned: Self::Builder = s.into();
                     let r = self.allocate_new_id();
                     self.tbl.insert(owned.clone(), r);
-                    self.syms.push(owned);
+                    if (r as usize) < self.syms.len() {
+                        self.syms[r as usize] = owned; // recycled ID
+                    } else {
+                        self.syms.push(owned);
+                    }
                     r
                 }
             }
@@ -260,7 +280,7 @@ pub mod str_id {
     impl<'a> pp::Pretty1<&'a Sym> for ScopedManager {
         // SMTLIB printing
         fn pp1_into(&self, s: &&'a Sym, ctx: &mut pp::Ctx) {
-            let escape = s.name.contains(|c| {c == ' ' || c == '\n'});
+            let escape = s.name.contains(|c: char| {c == ' ' || c == '\n' || !c.is_ascii()});
             if escape { ctx.str("|"); }
             ctx.string(s.to_string());
             if escape { ctx.str("|"); }
@@ -7,7 +7,7 @@ use {
     std::{fmt, rc::Rc}, 
     batsmt_core::{
         ast_u32::{self, AST, },
-        ast::{self, View}, },
+        ast::{self, View}, AttrKey, AttrValue, },
     fxhash::FxHashMap,
     batsmt_hast::{HManager, StrSymbolManager,}
 };
@@ -256,6 +256,95 @@ mod test_ast {
         }
     }
 
+    // a collected symbol's ID is reused by the next one, with its own name
+    #[test]
+    fn test_gc_recycle_symbol() {
+        let mut m = M::new();
+        let a = m.mk_str("a", None);
+        m.mk_str("b", None);
+        m.mark_root(&a);
+        assert_eq!(m.collect(), 1);
+        let c = m.mk_str("c", None);
+        let d = m.mk_str("d", None);
+        for (t, name) in [(a, "a"), (c, "c"), (d, "d")].iter() {
+            match m.view(t) {
+                View::Const(s) => assert_eq!(s, *name),
+                _ => panic!("{:?} must be a constant", t),
+            }
+        }
+    }
+
+    #[test]
+    fn test_term_limit() {
+        use batsmt_core::{Error, mem};
+
+        let mut m = M::with_term_limit(4);
+        assert_eq!(m.term_limit(), 4);
+        let f = m.mk_str("f", None);
+        let a = m.mk_str("a", None);
+        let b = m.mk_str("b", None);
+        let c = m.mk_str("c", None);
+        match m.try_mk_const("d", None) {
+            Err(Error::TermCapacity{limit: 4}) => (),
+            r => panic!("expected the manager to be full, got {:?}", r),
+        }
+        let apps: Vec<AST> = [a, b, c, f].iter().map(|&x| m.mk_app(f, &[x], None)).collect();
+        let err = m.try_mk_app(f, &[a, a], None).unwrap_err();
+        assert!(err.to_string().contains("limited to 4"), "{}", err);
+        // existing terms can still be built
+        assert_eq!(m.try_mk_app(f, &[a], None).unwrap(), apps[0]);
+        let r = mem::report("manager", &m);
+        assert_eq!(r.get("nodes").and_then(|n| n.capacity()), Some(4), "{}", r);
+        assert!(r.to_string().contains("(capacity 4)"), "{}", r);
+        assert_eq!(mem::report("manager", &M::new()).get("nodes").unwrap().capacity(), None);
+
+        // free the applications, except `f(a)`
+        for t in [f, a, b, c, apps[0]].iter() {
+            m.mark_root(t);
+        }
+        assert_eq!(m.collect(), 3);
+        let t = m.try_mk_app(f, &[a, a], None).unwrap();
+        assert_eq!(m.mk_app(f, &[a, a], None), t);
+        assert!(m.try_mk_app(f, &[b], None).is_ok());
+        assert!(m.try_mk_app(f, &[c], None).is_ok());
+        assert!(m.try_mk_app(f, &[f], None).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "too many terms")]
+    fn test_term_limit_panic() {
+        let mut m = M::with_term_limit(1);
+        let a = m.mk_str("a", None);
+        m.mk_app(a, &[a], None);
+        m.mk_app(a, &[a, a], None);
+    }
+
+    #[test]
+    fn test_memory_report() {
+        use batsmt_core::{gc::HasInternalMemory, mem};
+
+        let mut s = StressApp::new(1000).verbose(false).long_apps(true);
+        s.run();
+        let r1 = mem::report("manager", &s.m);
+        for name in &["nodes", "app-table", "args", "symbols"] {
+            assert!(r1.get(name).unwrap().total() > 0, "{}: empty\n{}", name, r1);
+        }
+
+        // collect all but a few terms, then shrink tables
+        let m = &mut s.m;
+        for t in s.terms[..10].iter() {
+            m.mark_root(t);
+        }
+        m.collect();
+        m.reclaim_unused_memory();
+        let r2 = mem::report("manager", &s.m);
+        assert!(r2.total() < r1.total(), "before:\n{}after:\n{}", r1, r2);
+        for name in &["app-table", "args"] {
+            assert!(r2.get(name).unwrap().total() < r1.get(name).unwrap().total(),
+                "{}: before:\n{}after:\n{}", name, r1, r2);
+        }
+    }
+
     #[test]
     fn test_iter_dag() {
         // create a bunch of terms
@@ -275,6 +364,116 @@ mod test_ast {
         }
     }
 
+    // `f(g(a), h(g(a), b))`, with `g(a)` shared
+    #[test]
+    fn test_iter_dag_orders() {
+        use batsmt_core::ast::iter_dag::{self, Order};
+
+        let mut m = M::new();
+        let (f, g, h) = (m.mk_str("f", None), m.mk_str("g", None), m.mk_str("h", None));
+        let (a, b) = (m.mk_str("a", None), m.mk_str("b", None));
+        let ga = m.mk_app(g, &[a], None);
+        let hgab = m.mk_app(h, &[ga, b], None);
+        let t = m.mk_app(f, &[ga, hgab], None);
+
+        let visit = |order| {
+            let mut v = vec!();
+            ast::iter_dag_ordered(&m, &t, order, |_, u| v.push(*u));
+            v
+        };
+        let mut pre = vec!();
+        ast::iter_dag(&m, &t, |_, u| pre.push(*u));
+        assert_eq!(pre, vec![t, hgab, b, ga, a, g, h, f]);
+        assert_eq!(visit(Order::PreOrder), pre);
+        assert_eq!(visit(Order::PostOrder), vec![f, g, a, ga, h, b, hgab, t]);
+        assert_eq!(visit(Order::BreadthFirst), vec![t, f, ga, hgab, g, a, h, b]);
+
+        // by number of arguments, ties in post-order
+        let mut by_arity = vec!();
+        iter_dag::new().iter_by_key(&m, &t, |m, u| match m.view(u) {
+            View::App{args, ..} => args.len() as u64,
+            _ => 0,
+        }, |_, u| by_arity.push(*u));
+        assert_eq!(by_arity, vec![f, g, a, h, b, ga, hgab, t]);
+
+        // subterms seen by an earlier call are skipped
+        let mut st = iter_dag::new();
+        st.iter(&m, &hgab, |_, _| ());
+        let mut rest = vec!();
+        st.iter_ordered(&m, &t, Order::PostOrder, |_, u| rest.push(*u));
+        assert_eq!(rest, vec![f, t]);
+    }
+
+    #[test]
+    fn test_attr_roundtrip() {
+        let mut m = M::new();
+        let f = m.mk_str("f", None);
+        let a = m.mk_str("a", None);
+        let fa = m.mk_app(f, &[a], None);
+        let k = m.attr_key(":weight");
+        assert_eq!(k, m.attr_key(":weight"));
+        assert_eq!(Some(":weight"), m.attrs().map(|at| at.key_name(k)));
+
+        m.set_attr(f, AttrKey::COMMUTATIVE, AttrValue::Flag);
+        m.set_attr(fa, AttrKey::NAMED, AttrValue::Str("foo".to_string()));
+        m.set_attr(fa, k, AttrValue::Int(3));
+        m.set_attr(fa, k, AttrValue::Int(4)); // replaces the old value
+
+        assert!(m.has_attr(&f, AttrKey::COMMUTATIVE));
+        assert!(!m.has_attr(&f, AttrKey::NAMED));
+        assert_eq!(Some(&AttrValue::Str("foo".to_string())), m.get_attr(&fa, AttrKey::NAMED));
+        assert_eq!(Some(&AttrValue::Int(4)), m.get_attr(&fa, k));
+        assert_eq!(2, m.attrs().unwrap().get_all(&fa).len());
+
+        m.attrs_mut().unwrap().remove(&fa, k);
+        assert_eq!(None, m.get_attr(&fa, k));
+        assert!(m.has_attr(&fa, AttrKey::NAMED));
+    }
+
+    // lookups on terms without attributes do not touch the table
+    #[test]
+    fn test_attr_absent_fast_path() {
+        let mut m = M::new();
+        let f = m.mk_str("f", None);
+        m.set_attr(f, AttrKey::INJECTIVE, AttrValue::Flag);
+        let terms: Vec<AST> = (0 .. 100).map(|i| m.mk_string(format!("a{}", i), None)).collect();
+
+        let n0 = m.attrs().unwrap().n_fast_path();
+        for t in terms.iter() {
+            assert!(!m.has_attr(t, AttrKey::INJECTIVE));
+        }
+        assert_eq!(n0 + 100, m.attrs().unwrap().n_fast_path());
+        // terms with some attribute take the slow path
+        assert!(!m.has_attr(&f, AttrKey::NAMED));
+        assert_eq!(n0 + 100, m.attrs().unwrap().n_fast_path());
+    }
+
+    #[test]
+    fn test_attr_gc() {
+        let mut m = M::new();
+        let f = m.mk_str("f", None);
+        let a = m.mk_str("a", None);
+        let b = m.mk_str("b", None);
+        let fa = m.mk_app(f, &[a], None);
+        let fb = m.mk_app(f, &[b], None);
+        for &t in &[f, fa, fb] {
+            m.set_attr(t, AttrKey::NAMED, AttrValue::Str(format!("{:?}", t)));
+        }
+        assert_eq!(3, m.attrs().unwrap().len());
+
+        m.mark_root(&fa);
+        m.collect();
+        // `fb` is dead, `f` is alive as a subterm of `fa`
+        assert_eq!(2, m.attrs().unwrap().len());
+        assert!(m.has_attr(&f, AttrKey::NAMED));
+        assert!(m.has_attr(&fa, AttrKey::NAMED));
+
+        // a new term reusing the slot of `fb` has no attribute
+        let c = m.mk_str("c", None);
+        let fc = m.mk_app(f, &[c], None);
+        assert!(!m.has_attr(&fc, AttrKey::NAMED));
+    }
+
     /* FIXME:
     // test that `t.map(id) == t`
     #[test]
@@ -324,9 +523,9 @@ mod ast_prop {
     };
 
     #[derive(Clone)]
-    struct AstGen(Rc<std::cell::RefCell<AstGenCell>>);
-    struct AstGenCell {
-        m: M,
+    pub(super) struct AstGen(pub(super) Rc<std::cell::RefCell<AstGenCell>>);
+    pub(super) struct AstGenCell {
+        pub(super) m: M,
         consts: FxHashMap<String, AST>,
     }
 
@@ -362,7 +561,7 @@ mod ast_prop {
         fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result { write!(out, "astgen") }
     }
 
-    fn with_astgen<F,T>(mut f: F) -> BoxedStrategy<(AstGen,T)>
+    pub(super) fn with_astgen<F,T>(mut f: F) -> BoxedStrategy<(AstGen,T)>
         where F: FnMut(&AstGen) -> BoxedStrategy<T>, T: 'static+fmt::Debug
     {
         let m = AstGen::new(HManager::new());
@@ -370,7 +569,7 @@ mod ast_prop {
     }
 
     /// Random generator of terms
-    fn gen_term(m: &AstGen) -> BoxedStrategy<AST> {
+    pub(super) fn gen_term(m: &AstGen) -> BoxedStrategy<AST> {
         let m = m.clone();
         let leaf = {
             let m2 = m.clone();
@@ -498,3 +697,307 @@ mod ast_prop {
         }
     }
 }
+
+/// Reference implementations for `ast::algo`, on trees
+mod algo_ref {
+    use {super::*, batsmt_core::ast::Manager, std::collections::BTreeSet};
+
+    pub(super) fn occurs_in(m: &M, needle: AST, t: AST) -> bool {
+        t == needle ||
+        match m.view(&t) {
+            View::Const(_) | View::Index(..) => false,
+            View::App{f, args} => {
+                occurs_in(m, needle, *f) || args.iter().any(|u| occurs_in(m, needle, *u))
+            },
+        }
+    }
+
+    pub(super) fn subterms(m: &M, t: AST, set: &mut BTreeSet<AST>) {
+        set.insert(t);
+        if let View::App{f, args} = m.view(&t) {
+            subterms(m, *f, set);
+            for u in args.iter() { subterms(m, *u, set) }
+        }
+    }
+
+    pub(super) fn replace(m: &mut M, t: AST, from: AST, to: AST) -> AST {
+        if t == from { return to }
+        let (f, args) = match m.view(&t) {
+            View::Const(_) | View::Index(..) => return t,
+            View::App{f, args} => (*f, args.to_vec()),
+        };
+        let f = replace(m, f, from, to);
+        let args: Vec<_> = args.iter().map(|u| replace(m, *u, from, to)).collect();
+        let ty = m.ty(&t);
+        m.mk_app(f, &args, ty)
+    }
+}
+
+mod algo_prop {
+    use {
+        super::*, batsmt_pretty::Pretty1,
+        batsmt_core::ast::{Manager, algo::AlgoCache},
+        std::collections::BTreeSet,
+        proptest::prelude::*,
+    };
+
+    #[test]
+    fn test_replace_subterm() {
+        let mut m = M::new();
+        let f = m.mk_str("f", None);
+        let a = m.mk_str("a", None);
+        let b = m.mk_str("b", None);
+        let c = m.mk_str("c", None);
+        let fab = m.mk_app(f, &[a,b], None);
+        let t = m.mk_app(f, &[fab,a], None);
+        let fcb = m.mk_app(f, &[c,b], None);
+        let t2 = m.mk_app(f, &[fcb,c], None);
+        assert_eq!(t2, ast::algo::replace_subterm(&mut m, &t, &a, &c));
+        assert_eq!(t, ast::algo::replace_subterm(&mut m, &t, &c, &a));
+        assert!(ast::algo::occurs_in(&m, &b, &t));
+        assert!(! ast::algo::occurs_in(&m, &c, &t));
+        let mut common = ast::algo::common_subterms(&m, &t, &t2);
+        common.sort();
+        let mut expected = vec![f, b];
+        expected.sort();
+        assert_eq!(common, expected);
+    }
+
+    #[test]
+    fn test_inline_bool_lets() {
+        let mut m = M::new();
+        let bool_ = m.mk_str("Bool", None);
+        let u = m.mk_str("U", None);
+        let let_ = m.mk_str("let", None);
+        let and_ = m.mk_str("and", None);
+        let f = m.mk_str("f", None);
+        let p = m.mk_str("p", Some(bool_));
+        let q = m.mk_str("q", Some(bool_));
+        let a = m.mk_str("a", Some(u));
+        let x = m.mk_str("x", Some(bool_));
+        let y = m.mk_str("y", Some(u));
+
+        // `let x = p∧q, y = a in f(x, y, x)`
+        let p_and_q = m.mk_app(and_, &[p,q], Some(bool_));
+        let fxyx = m.mk_app(f, &[x,y,x], Some(bool_));
+        let t = m.mk_app(let_, &[x, p_and_q, y, a, fxyx], Some(bool_));
+
+        let t2 = ast::algo::inline_bool_lets(&mut m, &let_, &bool_, &t);
+
+        // only `y` remains bound, and `p∧q` is shared by both occurrences of `x`
+        let (args, body) = match m.view(&t2) {
+            View::App{f: f2, args} => {
+                assert_eq!(*f2, let_);
+                assert_eq!(&args[..2], &[y, a]);
+                (args.to_vec(), args[2])
+            },
+            _ => panic!("expected a `let`"),
+        };
+        assert_eq!(args.len(), 3);
+        match m.view(&body) {
+            View::App{f: f2, args} => {
+                assert_eq!(*f2, f);
+                assert_eq!(args[0], p_and_q);
+                assert_eq!(args[1], y);
+                assert_eq!(args[0], args[2]);
+            },
+            _ => panic!("expected `f(…)`"),
+        }
+        let mut n_and = 0;
+        ast::iter_dag(&m, &t2, |m,v| if let View::App{f,..} = m.view(v) { if *f == and_ { n_and += 1 } });
+        assert_eq!(n_and, 1);
+
+        // without lets, the term is unchanged
+        assert_eq!(fxyx, ast::algo::inline_bool_lets(&mut m, &let_, &bool_, &fxyx));
+    }
+
+    #[test]
+    fn test_translate() {
+        let mut src = M::new();
+        let u = src.mk_str("U", None);
+        let f = src.mk_str("f", None);
+        let g = src.mk_str("g", None);
+        let a = src.mk_str("a", Some(u));
+        let b = src.mk_str("b", Some(u));
+        // `g(f(a), f(a), b)`, where `f(a)` is shared
+        let fa = src.mk_app(f, &[a], Some(u));
+        let t = src.mk_app(g, &[fa, fa, b], Some(u));
+
+        let mut dst = M::new();
+        let rename = |s: &str| format!("c_{}", s);
+        let t2 = ast::translate(&src, &mut dst, &t, rename);
+        assert_eq!(ast::size_dag(&src, &t), ast::size_dag(&dst, &t2));
+        assert_eq!("(c_g (c_f c_a) (c_f c_a) c_b)", format!("{}", dst.pp(&t2)));
+        let (fa2, b2) = match dst.view(&t2) {
+            View::App{args, ..} => {
+                assert_eq!(args[0], args[1]);
+                (args[0], args[2])
+            },
+            _ => panic!("expected an application"),
+        };
+        // types are translated too
+        let u2 = dst.ty(&t2).unwrap();
+        assert_eq!(Some(u2), dst.ty(&fa2));
+        assert_eq!(Some(u2), dst.ty(&b2));
+        assert_eq!("c_U", format!("{}", dst.pp(&u2)));
+
+        // a reused state translates constants consistently, unlike separate calls
+        let mut st = ast::translate::new();
+        let t3 = st.translate(&src, &mut dst, &t, rename);
+        assert_ne!(t2, t3);
+        assert_eq!(t3, st.translate(&src, &mut dst, &t, rename));
+        let fa3 = st.translate(&src, &mut dst, &fa, rename);
+        assert!(ast::algo::occurs_in(&dst, &fa3, &t3));
+    }
+
+    // pick a subterm of `t` (or `u` itself if `i` is even)
+    fn pick(m: &M, t: AST, u: AST, i: usize) -> AST {
+        if i % 2 == 0 { return u }
+        let mut subs = vec!();
+        ast::iter_dag(m, &t, |_,v| subs.push(*v));
+        subs[(i/2) % subs.len()]
+    }
+
+    fn gen_3(m: &super::ast_prop::AstGen) -> BoxedStrategy<(AST,AST,usize)> {
+        let m = m.clone();
+        (super::ast_prop::gen_term(&m), super::ast_prop::gen_term(&m), any::<usize>()).boxed()
+    }
+
+    proptest! {
+        #[test]
+        fn prop_occurs_in(ref tup in super::ast_prop::with_astgen(gen_3)) {
+            let (m,(t,u,i)) = tup;
+            let m = &m.0.borrow().m;
+            let needle = pick(m, *t, *u, *i);
+            let mut cache = AlgoCache::new();
+            let r1 = cache.occurs_in(m, &needle, t);
+            let r2 = algo_ref::occurs_in(m, needle, *t);
+            prop_assert_eq!(r1, r2, "needle {:?}, t: {:?}", m.pp(&needle), m.pp(t));
+            // cache can be reused
+            prop_assert_eq!(cache.occurs_in(m, t, t), true);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_common_subterms(ref tup in super::ast_prop::with_astgen(gen_3)) {
+            let (m,(t,u,_)) = tup;
+            let m = &m.0.borrow().m;
+            let mut cache = AlgoCache::new();
+            let v = cache.common_subterms(m, t, u);
+            let r1: BTreeSet<AST> = v.iter().cloned().collect();
+            prop_assert_eq!(r1.len(), v.len(), "no duplicates");
+
+            let mut s1 = BTreeSet::new();
+            let mut s2 = BTreeSet::new();
+            algo_ref::subterms(m, *t, &mut s1);
+            algo_ref::subterms(m, *u, &mut s2);
+            let r2: BTreeSet<AST> = s1.intersection(&s2).cloned().collect();
+            prop_assert_eq!(r1, r2, "t: {:?}, u: {:?}", m.pp(t), m.pp(u));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_replace_subterm(ref tup in super::ast_prop::with_astgen(gen_3)) {
+            let (m,(t,u,i)) = tup;
+            let m = &mut m.0.borrow_mut().m;
+            let from = pick(m, *t, *u, *i);
+            let to = *u;
+            let mut cache = AlgoCache::new();
+            let r1 = cache.replace_subterm(m, t, &from, &to);
+            let r2 = algo_ref::replace(m, *t, from, to);
+            prop_assert_eq!(r1, r2, "from {:?}, to {:?}, t: {:?}", m.pp(&from), m.pp(&to), m.pp(t));
+            // replacing back is the identity if `to` did not occur in `t`
+            if ! cache.occurs_in(m, &to, t) && ! cache.occurs_in(m, &from, &to) {
+                let r3 = cache.replace_subterm(m, &r1, &to, &from);
+                prop_assert_eq!(*t, r3);
+            }
+        }
+    }
+}
+
+mod match_term {
+    use {super::*, batsmt_core::{ast::Manager, match_term}};
+
+    struct B { f: AST, g: AST, a: AST, b: AST }
+
+    fn setup(m: &mut M) -> B {
+        B { f: m.mk_str("f", None), g: m.mk_str("g", None),
+            a: m.mk_str("a", None), b: m.mk_str("b", None) }
+    }
+
+    #[test]
+    fn test_head() {
+        let mut m = M::new();
+        let b = setup(&mut m);
+        let t = m.mk_app(b.f, &[b.a, b.b], None);
+        let r = match_term!(m, t, {
+            (b.g; ?x, ?y) => Some((b.g, x, y)),
+            (b.f; ?x) => Some((b.f, x, x)),
+            (b.f; ?x, ?y) => Some((b.f, x, y)),
+            _ => None,
+        });
+        assert_eq!(r, Some((b.f, b.a, b.b)));
+        let r = match_term!(m, b.a, { (b.f; ..) => true, _ => false });
+        assert!(!r);
+    }
+
+    #[test]
+    fn test_any_head_and_rest() {
+        let mut m = M::new();
+        let b = setup(&mut m);
+        let t = m.mk_app(b.g, &[b.a, b.b, b.a], None);
+        let r = match_term!(m, t, { (?h; ..?args) => (h, args), _ => panic!() });
+        assert_eq!(r, (b.g, vec![b.a, b.b, b.a]));
+        let r = match_term!(m, t, { (b.g; ..?args) => args.len(), _ => 0 });
+        assert_eq!(r, 3);
+        let r = match_term!(m, t, { (?h; ..) => Some(h), _ => None });
+        assert_eq!(r, Some(b.g));
+        let r = match_term!(m, t, { (?h; ?x) => Some((h, x)), _ => None });
+        assert_eq!(r, None, "arity mismatch");
+    }
+
+    #[test]
+    fn test_const_and_guard() {
+        let mut m = M::new();
+        let b = setup(&mut m);
+        let t = m.mk_app(b.f, &[b.a, b.a], None);
+        let kind = |m: &M, t: AST| match_term!(m, t, {
+            const ?c if c == b.a => "a",
+            const ?_c => "const",
+            (b.f; ?x, ?y) if x == y => "diag",
+            (b.f; ?_x, ?_y) => "f",
+            _ => "other",
+        });
+        assert_eq!(kind(&m, b.a), "a");
+        assert_eq!(kind(&m, b.b), "const");
+        assert_eq!(kind(&m, t), "diag");
+        let u = m.mk_app(b.f, &[b.a, b.b], None);
+        assert_eq!(kind(&m, u), "f");
+        let v = m.mk_app(b.g, &[b.a], None);
+        assert_eq!(kind(&m, v), "other");
+    }
+
+    // guards and arms can build terms: the view is not alive anymore
+    #[test]
+    fn test_mutate_in_arm() {
+        let mut m = M::new();
+        let b = setup(&mut m);
+        let t = m.mk_app(b.f, &[b.a, b.b], None);
+        let swapped = match_term!(m, t, {
+            (b.f; ?x, ?y) if m.mk_app(b.g, &[x], None) != x => m.mk_app(b.f, &[y, x], None),
+            _ => t,
+        });
+        assert_eq!(swapped, m.mk_app(b.f, &[b.b, b.a], None));
+        // nested, on a subterm
+        let r = match_term!(m, swapped, {
+            (b.f; ?x, ?_y) => match_term!(m, x, {
+                const ?c => m.mk_app(b.g, &[c], None),
+                _ => x,
+            }),
+            _ => t,
+        });
+        assert_eq!(r, m.mk_app(b.g, &[b.b], None));
+    }
+}
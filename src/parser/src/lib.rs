@@ -6,7 +6,7 @@ pub mod parser;
 pub mod simple_ast;
 
 pub use crate::{
-    types::{Atom,Statement,TermBuilder,SortBuilder,Op as BuiltinOp,pp_stmt},
-    parser::{parse,parse_stdin,parse_str,Error,Result},
+    types::{Atom,Statement,Span,TermBuilder,SortBuilder,Op as BuiltinOp,pp_stmt,write_smtlib},
+    parser::{parse,parse_with_spans,parse_stdin,parse_str,Batches,Error,Result},
 };
 
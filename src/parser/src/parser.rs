@@ -1,31 +1,63 @@
 
 use {
-    std::{error, result, fmt::{self,Display}, io, ops::Deref},
+    std::{error, result, fmt, io, ops::Deref},
     fxhash::FxHashMap,
+    batsmt_core::error::{self as core_error, Pos},
     crate::types::*,
 };
 
 /// Error messages
 #[derive(Debug)]
-pub struct Error(String);
+pub enum Error {
+    /// The input is ill-formed.
+    Syntax { pos: Pos, msg: String },
+    /// A SMT-LIB construct that the parser does not support.
+    Unsupported { pos: Pos, feature: String },
+    Io(io::Error),
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        Display::fmt(&self.0, fmt)
+        match self {
+            Error::Syntax{pos, msg} => write!(fmt, "{} ({})", msg, pos),
+            Error::Unsupported{pos, feature} => write!(fmt, "unsupported {} ({})", feature, pos),
+            Error::Io(e) => fmt::Display::fmt(e, fmt),
+        }
     }
 }
 
 impl error::Error for Error {
-  fn description(&self) -> &str { &self.0 }
-  fn cause(&self) -> Option<&error::Error> { None }
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
-pub type Result<T> = result::Result<T, Box<error::Error>>;
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self { Error::Io(e) }
+}
 
-fn mk_err(s: String) -> Box<error::Error> {
-    Box::new(Error(s))
+impl From<Error> for core_error::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Syntax{pos, msg} => core_error::Error::Parse{pos: Some(pos), msg},
+            Error::Unsupported{pos, feature} => core_error::Error::Unsupported{feature, pos: Some(pos)},
+            Error::Io(e) => e.into(),
+        }
+    }
 }
 
+pub type Result<T> = result::Result<T, Error>;
+
+// SMT-LIB commands that are recognized, but not supported
+const UNSUPPORTED_DIRECTIVES: &[&str] = &[
+    "push", "pop", "define-fun", "define-fun-rec",
+    "define-sort", "declare-datatype", "declare-datatypes",
+    "get-assignment", "get-proof",
+];
+
 // parser's buffer size
 const BUF_SIZE : usize = 1_024 * 16;
 
@@ -41,7 +73,7 @@ struct LetBinding<Var, Term>{
 struct ParserState<'a, R : io::Read, B : TermBuilder + 'a> {
     io: ParserIO<R>,
     build: &'a mut B,
-    sorts: FxHashMap<Atom, B::Sort>,
+    sorts: FxHashMap<Atom, (B::Sort, u8)>, // with their arity
     funs: FxHashMap<Atom, B::Fun>,
     vars: FxHashMap<Atom, B::Var>, // let-bindings
     atom_buf: Vec<u8>,
@@ -58,6 +90,13 @@ struct ParserIO<R : io::Read> {
 }
 
 impl<R : io::Read> ParserIO<R> {
+    fn new(r: R) -> Self {
+        ParserIO {
+            r, eof: false, buf: [0; BUF_SIZE], len: 0,
+            i: 0, line: 1, col: 1,
+        }
+    }
+
     // refill internal buffer
     fn refill(&mut self) -> Result<()> {
         trace!("refill internal buffer (size {})", BUF_SIZE);
@@ -70,9 +109,23 @@ impl<R : io::Read> ParserIO<R> {
         Ok(())
     }
 
+    fn pos(&self) -> Pos { Pos{line: self.line, col: self.col} }
+
     fn err_with<T>(&self, s: impl Deref<Target=str>) -> Result<T> {
-        let s: &str = &*s;
-        Err(mk_err(format!("{} (line {}, col {})", s, self.line, self.col)))
+        Err(Error::Syntax{pos: self.pos(), msg: s.to_string()})
+    }
+
+    fn err_unsupported<T>(&self, feature: impl Deref<Target=str>) -> Result<T> {
+        Err(Error::Unsupported{pos: self.pos(), feature: feature.to_string()})
+    }
+
+    // a failure of the builder, located at the current position
+    fn err_build<T>(&self, e: core_error::Error) -> Result<T> {
+        match e {
+            core_error::Error::Parse{msg, ..} => self.err_with(msg),
+            core_error::Error::Unsupported{feature, ..} => self.err_unsupported(feature),
+            e => self.err_with(e.to_string()),
+        }
     }
 
     fn err_eof<T>(&self) -> Result<T> {
@@ -149,16 +202,17 @@ impl<R : io::Read> ParserIO<R> {
 impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
     // allocate new parser
     fn new(build: &'a mut B, r: R) -> Self {
+        Self::with_io(build, ParserIO::new(r))
+    }
+
+    // parser that resumes reading from `io`
+    fn with_io(build: &'a mut B, io: ParserIO<R>) -> Self {
         ParserState {
             funs: FxHashMap::default(),
             sorts: FxHashMap::default(),
             vars: FxHashMap::default(),
             atom_buf: vec!(),
-            build, 
-            io: ParserIO {
-                r, eof: false, buf: [0; BUF_SIZE], len: 0,
-                i: 0, line: 1, col: 1,
-            },
+            build, io,
         }
     }
 
@@ -207,7 +261,10 @@ impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
         }
 
         // convert to utf8 and onto the heap
-        let str = std::str::from_utf8(&s)?;
+        let str = match std::str::from_utf8(&s) {
+            Ok(str) => str,
+            Err(e) => return self.io.err_with(e.to_string()),
+        };
         let s: Atom = str.into();; // now convert to utf8
         Ok(s)
     }
@@ -243,14 +300,131 @@ impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
 
     // parse a sort
     fn sort(&mut self) -> Result<B::Sort> {
+        self.io.skip_spaces()?;
+        if self.io.get()? == b'(' {
+            return self.compound_sort()
+        }
         let a = self.atom()?;
-        if &*a == "Bool" { return Ok(self.build.get_bool()) }; // builtin
-        match self.sorts.get(&a) {
-            Some(s) => Ok(s.clone()),
-            None => self.io.err_with(format!("{} is not a known sort", &a).to_string()),
+        self.atomic_sort(&a)
+    }
+
+    // the sort named `a`, with its arity, declared in this call or before
+    fn find_sort(&self, a: &str) -> Option<(B::Sort, u8)> {
+        match self.sorts.get(a) {
+            Some((s, n)) => Some((s.clone(), *n)),
+            None => self.build.lookup_sort(a),
+        }
+    }
+
+    // the function named `a`, declared in this call or before
+    fn find_fun(&self, a: &str) -> Option<B::Fun> {
+        match self.funs.get(a) {
+            Some(f) => Some(f.clone()),
+            None => self.build.lookup_fun(a),
+        }
+    }
+
+    // the sort named `a`
+    fn atomic_sort(&mut self, a: &Atom) -> Result<B::Sort> {
+        if &**a == "Bool" { return Ok(self.build.get_bool()) }; // builtin
+        match self.find_sort(a) {
+            Some((s, _)) => Ok(s),
+            None => self.io.err_with(format!("{} is not a known sort", a).to_string()),
+        }
+    }
+
+    // parse the signature `(args…) ret` of `declare-fun`, or
+    // `(par (vars…) (args…) ret)`, along with its type variables
+    fn fun_signature(&mut self) -> Result<(Vec<(Atom, B::Sort)>, Vec<B::Sort>, B::Sort)> {
+        self.io.skip_spaces()?;
+        self.expect_char(b'(')?;
+        self.io.skip_spaces()?;
+        let mut tys = vec!();
+        match self.io.get()? {
+            b'(' | b')' => (),
+            _ => {
+                let a = self.atom()?;
+                if &*a == "par" {
+                    return self.poly_signature()
+                }
+                tys.push(self.atomic_sort(&a)?);
+            },
+        }
+        tys.extend(self.many_until_paren(|m| m.sort())?);
+        self.expect_char(b')')?;
+        let ret = self.sort()?;
+        Ok((vec!(), tys, ret))
+    }
+
+    // parse `(vars…) (args…) ret)`, after `(par`.
+    // Type variables shadow sorts with the same name.
+    fn poly_signature(&mut self) -> Result<(Vec<(Atom, B::Sort)>, Vec<B::Sort>, B::Sort)> {
+        let names = self.within_parens(|m| m.atom())?;
+        let mut vars = vec!();
+        let mut shadowed = vec!();
+        for v in names {
+            let s = match self.build.sort_var(v.clone()) {
+                Some(s) => s,
+                None => return self.io.err_unsupported("polymorphic declarations"),
+            };
+            shadowed.push((v.clone(), self.sorts.insert(v.clone(), (s.clone(), 0))));
+            vars.push((v, s));
+        }
+        let res = self.within_parens(|m| m.sort()).and_then(|tys| {
+            let ret = self.sort()?;
+            self.io.skip_spaces()?;
+            self.expect_char(b')')?;
+            Ok((tys, ret))
+        });
+        for (v, old) in shadowed.into_iter().rev() {
+            match old {
+                Some(old) => self.sorts.insert(v, old),
+                None => self.sorts.remove(&v),
+            };
+        }
+        let (tys, ret) = res?;
+        Ok((vars, tys, ret))
+    }
+
+    // parse a sort `(head sort+)`
+    fn compound_sort(&mut self) -> Result<B::Sort> {
+        self.expect_char(b'(')?;
+        let head = self.atom()?;
+        let args = self.many_until_paren(|m| m.sort())?;
+        let ok = match (&*head, self.find_sort(&head)) {
+            ("->", _) => args.len() >= 2,
+            (_, Some((_, n))) => n as usize == args.len() && args.len() > 0,
+            (_, None) => return self.io.err_with(format!("{} is not a known sort", &head)),
+        };
+        if !ok {
+            return self.io.err_with(
+                format!("sort {} cannot be applied to {} argument(s)", &head, args.len()))
+        }
+        self.expect_char(b')')?;
+        Ok(self.build.app_sort(head, &args))
+    }
+
+    // build the rational `num/den`, if the builder supports it
+    fn rational(&mut self, num: Atom, den: Atom) -> Result<B::Term> {
+        match self.build.mk_rational(num, den) {
+            Some(t) => Ok(t),
+            None => self.io.err_unsupported("numeric literals"),
         }
     }
 
+    // parse a numeral, for `(/ num den)`
+    fn numeral(&mut self) -> Result<Atom> {
+        self.io.skip_spaces()?;
+        if self.io.get()? == b'(' {
+            return self.io.err_unsupported("division of non-literal terms")
+        }
+        let a = self.atom()?;
+        if !a.bytes().all(|c| c.is_ascii_digit()) {
+            return self.io.err_unsupported(format!("division of non-numeral {}", &a))
+        }
+        Ok(a)
+    }
+
     // find function with this name
     fn find_fun_apply(&mut self, s: &str, args: &[B::Term]) -> Result<B::Term> {
         match s {
@@ -263,11 +437,10 @@ impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
             "=" => Ok(self.build.app_op(Op::Eq, args)),
             "distinct" => Ok(self.build.app_op(Op::Distinct, args)),
             _ => {
-                let f =
-                    self.funs.get(s).ok_or_else(|| {
-                    mk_err(format!("{} is not a known function", &s))
-                })?;
-                Ok(self.build.app_fun(f.clone(), args))
+                match self.find_fun(s) {
+                    Some(f) => Ok(self.build.app_fun(f, args)),
+                    None => self.io.err_with(format!("{} is not a known function", &s)),
+                }
             }
         }
     }
@@ -325,6 +498,24 @@ impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
         Ok(t)
     }
 
+    // parse an attribute `:key` or `:key value`, where `value` is an atom
+    fn attribute(&mut self) -> Result<(Atom, Option<Atom>)> {
+        let key = self.atom()?;
+        if !key.starts_with(':') {
+            return self.io.err_with(format!("expected attribute, got '{}'", key))
+        }
+        self.io.skip_spaces()?;
+        let value = match self.io.get()? {
+            b')' | b':' => None,
+            b'(' => return self.io.err_unsupported("compound attribute values"),
+            _ => Some(self.atom()?),
+        };
+        if &*key == ":named" && value.is_none() {
+            return self.io.err_with("expected ':named atom'")
+        }
+        Ok((key, value))
+    }
+
     // parse a term
     fn term(&mut self) -> Result<B::Term> {
         self.io.skip_spaces()?;
@@ -334,15 +525,16 @@ impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
                 let a = self.atom()?;
                 match &*a {
                     "!" => {
-                        let t = self.term()?;
-                        let a = self.atom()?;
-                        if *a == *":named" {
-                            let _ = self.atom()?;
-                            self.expect_char(b')')?;
-                            Ok(t)
-                        } else {
-                            self.io.err_with(format!("expected ':named atom', got '{}'", a))
+                        let mut t = self.term()?;
+                        let attrs = self.many_until_paren(|p| p.attribute())?;
+                        self.expect_char(b')')?;
+                        if attrs.len() == 0 {
+                            return self.io.err_with("expected at least one attribute")
+                        }
+                        for (key, value) in attrs {
+                            t = self.build.annotate(t, key, value);
                         }
+                        Ok(t)
                     },
                     "ite" => {
                         let t1 = self.term()?;
@@ -354,6 +546,18 @@ impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
                     "let" => {
                         self.parse_let()
                     },
+                    "forall" | "exists" => self.io.err_unsupported("quantifiers"),
+                    "/" if self.find_fun(&a).is_none() => {
+                        // rational literal
+                        let num = self.numeral()?;
+                        let den = self.numeral()?;
+                        self.io.skip_spaces()?;
+                        self.expect_char(b')')?;
+                        if den.bytes().all(|c| c == b'0') {
+                            return self.io.err_with(format!("zero denominator in (/ {} {})", num, den))
+                        }
+                        self.rational(num, den)
+                    },
                     _ => {
                         // function application
                         let args = self.terms()?;
@@ -362,6 +566,13 @@ impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
                     }
                 }
             },
+            c if c.is_ascii_digit() => {
+                let a = self.atom()?;
+                match parse_decimal(&a) {
+                    Some((num, den)) => self.rational(num.into(), den.into()),
+                    None => self.io.err_with(format!("invalid numeric literal {}", &a)),
+                }
+            },
             _ => {
                 let a = self.atom()?;
                 match self.vars.get(&a) {
@@ -396,45 +607,106 @@ impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
                     let b = self.atom()?;
                     Statement::SetInfo(a,b)
                 },
+                "set-option" => {
+                    let a = self.atom()?;
+                    let b = self.atom()?;
+                    Statement::SetOption(a,b)
+                },
                 "set-logic" => {
                     let a = self.atom()?;
                     Statement::SetLogic(a)
                 },
                 "declare-sort" => {
                     let a = self.atom()?;
-                    let n = self.atom()?.parse::<u8>()?;
+                    if self.find_sort(&a).is_some() {
+                        return self.io.err_with(format!("sort {} is already declared", &a))
+                    }
+                    let n = match self.atom()?.parse::<u8>() {
+                        Ok(n) => n,
+                        Err(e) => return self.io.err_with(format!("invalid arity: {}", e)),
+                    };
                     // make a sort and store it
-                    let sort = self.build.declare_sort(a.clone(), n);
-                    self.sorts.insert(a.clone(), sort);
+                    let sort = match self.build.declare_sort(a.clone(), n) {
+                        Ok(sort) => sort,
+                        Err(e) => return self.io.err_build(e),
+                    };
+                    self.sorts.insert(a.clone(), (sort, n));
                     Statement::DeclareSort(a, n)
                 },
                 "declare-fun" | "declare-cstor" => {
                     let a = self.atom()?;
-                    let tys = self.within_parens(|m| m.sort())?;
-                    let ret = self.sort()?;
+                    if self.find_fun(&a).is_some() {
+                        return self.io.err_with(format!("function {} is already declared", &a))
+                    }
+                    let (vars, tys, ret) = self.fun_signature()?;
                     // store function
                     let f = {
                         let a = a.clone();
                         let ret = ret.clone();
-                        if &*dir == "declare-fun" {
-                            self.build.declare_fun(a, &tys, ret)
+                        if !vars.is_empty() {
+                            if &*dir != "declare-fun" {
+                                return self.io.err_unsupported("polymorphic constructors")
+                            }
+                            let var_sorts: Vec<_> = vars.iter().map(|(_, s)| s.clone()).collect();
+                            match self.build.declare_poly_fun(a, &var_sorts, &tys, ret) {
+                                Some(f) => f,
+                                None => return self.io.err_unsupported("polymorphic declarations"),
+                            }
                         } else {
-                            self.build.declare_cstor(a, &tys, ret)
+                            let res = if &*dir == "declare-fun" {
+                                self.build.declare_fun(a, &tys, ret)
+                            } else {
+                                self.build.declare_cstor(a, &tys, ret)
+                            };
+                            match res {
+                                Ok(f) => f,
+                                Err(e) => return self.io.err_build(e),
+                            }
                         }
                     };
                     self.funs.insert(a.clone(), f);
-                    Statement::DeclareFun(a, tys, ret)
+                    if vars.is_empty() {
+                        Statement::DeclareFun(a, tys, ret)
+                    } else {
+                        let vars = vars.into_iter().map(|(v, _)| v).collect();
+                        Statement::DeclarePolyFun(a, vars, tys, ret)
+                    }
                 },
                 "assert" => {
                     let t = self.term()?;
                     Statement::Assert(t)
                 },
+                "assert-lemma" => {
+                    let t = self.term()?;
+                    Statement::AssertLemma(t)
+                },
                 "check-sat" => Statement::CheckSat,
                 "check-sat-assumptions" => {
                     let v = self.terms()?;
                     Statement::CheckSatAssumptions(v)
                 },
+                "get-info" => {
+                    let a = self.atom()?;
+                    Statement::GetInfo(a)
+                },
+                "get-model" => Statement::GetModel,
+                "get-value" => {
+                    let v = self.within_parens(|m| m.term())?;
+                    Statement::GetValue(v)
+                },
+                "get-next-model" => Statement::GetNextModel,
+                "get-unsat-core" => Statement::GetUnsatCore,
+                "reset-assertions" => Statement::ResetAssertions,
+                "reset" => {
+                    // declarations are forgotten, names can be declared again
+                    self.sorts.clear();
+                    self.funs.clear();
+                    Statement::Reset
+                },
                 "exit" => Statement::Exit,
+                d if UNSUPPORTED_DIRECTIVES.contains(&d) => {
+                    self.io.err_unsupported(format!("directive {:?}", d))?
+                },
                 _ => {
                     self.io.err_with(format!("unknown directive {:?}", dir))?
                 }
@@ -445,7 +717,8 @@ impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
         }
     }
 
-    // parse a bunch of statements until the EOF
+    // parse a bunch of statements until the EOF, or until `(exit)`:
+    // what follows it is ignored, even if it does not parse
     fn statements(&mut self) -> Result<Vec<Statement<B::Term, B::Sort>>> {
         let mut res = Vec::new();
 
@@ -453,16 +726,83 @@ impl<'a, R : io::Read, B : TermBuilder> ParserState<'a, R, B> {
             match self.statement() {
                 Ok(None) => break,
                 Ok(Some(st)) => {
+                    let exit = is_exit(&st);
                     res.push(st);
+                    if exit { break }
                 },
                 Err(e) => return Err(e),
             }
         }
         Ok(res)
     }
+
+    // same as `statements`, but also return where each statement is;
+    // if `batch` is true, also stop after `(reset)`
+    fn statements_with_spans(&mut self, batch: bool) -> Result<Vec<(Span, Statement<B::Term, B::Sort>)>> {
+        let mut res = Vec::new();
+
+        loop {
+            self.io.skip_spaces()?;
+            let start_line = self.io.line;
+            match self.statement()? {
+                None => break,
+                Some(st) => {
+                    let span = Span{start_line, end_line: self.io.line};
+                    let stop = is_exit(&st) || (batch && is_reset(&st));
+                    res.push((span, st));
+                    if stop { break }
+                },
+            }
+        }
+        Ok(res)
+    }
+}
+
+fn is_exit<T,S>(st: &Statement<T,S>) -> bool {
+    match st { Statement::Exit => true, _ => false }
+}
+
+fn is_reset<T,S>(st: &Statement<T,S>) -> bool {
+    match st { Statement::Reset => true, _ => false }
+}
+
+// largest exponent accepted in a decimal, to bound the size of numerals
+const MAX_EXPONENT: i64 = 4096;
+
+/// Parse a decimal `int[.frac][e[+|-]exp]` into the fraction `(num, den)`,
+/// where both are numerals.
+fn parse_decimal(s: &str) -> Option<(String, String)> {
+    let (mantissa, exp) = match s.find(|c| c == 'e' || c == 'E') {
+        Some(i) => (&s[..i], s[i+1..].parse::<i64>().ok()?),
+        None => (s, 0),
+    };
+    let (int, frac) = match mantissa.find('.') {
+        Some(i) if i+1 < mantissa.len() => (&mantissa[..i], &mantissa[i+1..]),
+        Some(_) => return None, // `1.`
+        None => (mantissa, ""),
+    };
+    let is_numeral = |x: &str| x.bytes().all(|c| c.is_ascii_digit());
+    if int.is_empty() || !is_numeral(int) || !is_numeral(frac) || exp.abs() > MAX_EXPONENT {
+        return None
+    }
+    let exp = exp - frac.len() as i64;
+    let mut num = format!("{}{}", int, frac);
+    let mut den = "1".to_string();
+    if exp >= 0 {
+        num.extend(std::iter::repeat('0').take(exp as usize));
+    } else {
+        den.extend(std::iter::repeat('0').take((-exp) as usize));
+    }
+    let num = match num.trim_start_matches('0') {
+        "" => "0".to_string(),
+        n => n.to_string(),
+    };
+    Some((num, den))
 }
 
 /// Parse a set of statements from `r`, allocating terms in `m`
+///
+/// Parsing stops after `(exit)`, whatever follows it.
 pub fn parse<R,B>(b: &mut B, r: R) -> Result<Vec<Statement<B::Term, B::Sort>>>
     where R : io::Read, B: TermBuilder
 {
@@ -470,6 +810,50 @@ pub fn parse<R,B>(b: &mut B, r: R) -> Result<Vec<Statement<B::Term, B::Sort>>>
     p.statements()
 }
 
+/// Parse a set of statements from `r`, along with their location in `r`
+pub fn parse_with_spans<R,B>(b: &mut B, r: R) -> Result<Vec<(Span, Statement<B::Term, B::Sort>)>>
+    where R : io::Read, B: TermBuilder
+{
+    let mut p = ParserState::new(b, r);
+    p.statements_with_spans(false)
+}
+
+/// Statements of a reader, parsed in batches that end with `(reset)`.
+///
+/// After `(reset)`, the builder may forget its declarations and terms, so
+/// each batch can be executed before the next one is parsed.
+/// Parsing stops after `(exit)`, or at the first error.
+pub struct Batches<R: io::Read> {
+    io: Option<ParserIO<R>>, // `None` once done
+}
+
+impl<R: io::Read> Batches<R> {
+    /// Batches of the statements of `r`.
+    pub fn new(r: R) -> Self {
+        Batches { io: Some(ParserIO::new(r)) }
+    }
+
+    /// Parse the next batch with `b`, along with locations in `r`.
+    ///
+    /// Returns `None` once all the statements are parsed.
+    pub fn next_with_spans<B>(&mut self, b: &mut B) -> Result<Option<Vec<(Span, Statement<B::Term, B::Sort>)>>>
+        where B: TermBuilder
+    {
+        let io = match self.io.take() {
+            Some(io) => io,
+            None => return Ok(None),
+        };
+        let mut p = ParserState::with_io(b, io);
+        let stmts = p.statements_with_spans(true)?;
+        match stmts.last() {
+            None => return Ok(None),
+            Some((_, st)) if is_reset(st) => self.io = Some(p.io), // more to parse
+            Some(_) => (), // `(exit)` or EOF
+        }
+        Ok(Some(stmts))
+    }
+}
+
 /// Parse from given string
 pub fn parse_str<B>(b: &mut B, s: &str) -> Result<Vec<Statement<B::Term, B::Sort>>>
     where B: TermBuilder
@@ -1,9 +1,34 @@
 
 //! Simple representation of terms, sorts, etc.
+//!
+//! `Builder` implements `TermBuilder` without any term manager, which is
+//! handy to parse small problems in tests and examples.
+//!
+//! ```
+//! use batsmt_parser::{Statement, simple_ast::{self, TermCell}};
+//!
+//! let stmts = simple_ast::parse_str(r#"
+//!     (declare-sort U 0)
+//!     (declare-fun f (U) U)
+//!     (declare-fun a () U)
+//!     (assert (= (f a) a))"#).unwrap();
+//! match &stmts[3] {
+//!     Statement::Assert(t) => match t.view() {
+//!         TermCell::App(eq, args) => {
+//!             assert_eq!(eq.name(), "=");
+//!             assert_eq!(args.len(), 2);
+//!             assert_eq!(args[1].to_string(), "a");
+//!         },
+//!         _ => panic!("expected an application"),
+//!     },
+//!     st => panic!("expected an assertion, got {}", st),
+//! }
+//! ```
 
 use {
-    std::{ops::Deref,rc::Rc, fmt, ptr},
-    crate::types::{self,Op,Atom},
+    std::{ops::Deref,rc::Rc, fmt},
+    crate::types::{self,Op,Atom,Statement},
+    batsmt_core::error,
     batsmt_pretty as pp,
 };
 
@@ -14,6 +39,7 @@ pub use batsmt_pretty::Pretty;
 struct SortCell {
     name: Atom,
     arity: u8,
+    args: Vec<Sort>, // for compound sorts
 }
 
 /// A sort
@@ -29,8 +55,14 @@ impl fmt::Debug for Sort {
 impl Sort {
     /// New sort
     fn new(name: Atom, arity: u8) -> Self {
-        Sort(Rc::new(SortCell{name, arity}))
+        Sort(Rc::new(SortCell{name, arity, args: vec!()}))
     }
+
+    /// Name of the sort, or head of a compound sort.
+    pub fn name(&self) -> &str { &self.0.name }
+
+    /// Arguments of a compound sort, empty for atomic sorts.
+    pub fn args(&self) -> &[Sort] { &self.0.args }
 }
 
 #[derive(Eq,PartialEq,Hash)]
@@ -55,6 +87,12 @@ impl Fun {
     }
     pub fn ret(&self) -> Sort { self.0.ret.clone() }
     pub fn name(&self) -> &str { &self.0.name }
+
+    /// Sorts of the arguments, `None` for variadic builtins.
+    pub fn args(&self) -> Option<&[Sort]> { self.0.args.as_ref().map(|v| v.as_slice()) }
+
+    /// Is this a datatype constructor?
+    pub fn is_cstor(&self) -> bool { self.0.cstor }
 }
 
 impl fmt::Debug for Fun {
@@ -67,6 +105,7 @@ impl fmt::Debug for Fun {
 pub enum TermCell {
     App(Fun, Vec<Term>),
     Ite(Term,Term,Term),
+    Rational(Atom, Atom), // numerator, denominator
 }
 
 /// A term
@@ -76,7 +115,7 @@ pub struct Term(Rc<TermCell>);
 impl Eq for Term {}
 impl PartialEq for Term {
     fn eq(&self, other: &Term) -> bool {
-        ptr::eq(&self.0, &other.0)
+        Rc::ptr_eq(&self.0, &other.0)
     }
 }
 
@@ -88,6 +127,7 @@ impl fmt::Debug for Term {
                 fmt.debug_list().entries(&[a,b,c]).finish()?;
                 write!(fmt, ")")
             },
+            TermCell::Rational(num, den) => write!(fmt, "{}/{}", num, den),
             TermCell::App(f, args) => {
                 if args.len () == 0 {
                     f.fmt(fmt)
@@ -112,6 +152,18 @@ impl Term {
     pub fn ite(a: Term, b: Term, c: Term) -> Self {
         Term(Rc::new(TermCell::Ite(a,b,c)))
     }
+    pub fn rational(num: Atom, den: Atom) -> Self {
+        Term(Rc::new(TermCell::Rational(num, den)))
+    }
+    pub fn view(&self) -> &TermCell { &self.0 }
+
+    /// Head symbol and arguments, if this is an application.
+    pub fn as_app(&self) -> Option<(&Fun, &[Term])> {
+        match self.view() {
+            TermCell::App(f, args) => Some((f, args)),
+            _ => None,
+        }
+    }
 }
 
 /// The builder used for holding context and parsing
@@ -145,12 +197,26 @@ impl Builder {
     }
 }
 
+impl Default for Builder {
+    fn default() -> Self { Builder::new() }
+}
+
+/// Parse `s` using a fresh `Builder`.
+pub fn parse_str(s: &str) -> crate::Result<Vec<Statement<Term, Sort>>> {
+    let mut b = Builder::new();
+    crate::parse_str(&mut b, s)
+}
+
 impl types::SortBuilder for Builder {
     type Sort = Sort;
     fn get_bool(&self) -> Sort { self.bool_.clone() }
-    fn declare_sort(&mut self, s: Atom, n: u8) -> Sort {
-        Sort::new(s,n)
+    fn declare_sort(&mut self, s: Atom, n: u8) -> error::Result<Sort> {
+        Ok(Sort::new(s,n))
+    }
+    fn app_sort(&mut self, head: Atom, args: &[Sort]) -> Sort {
+        Sort(Rc::new(SortCell{name: head, arity: 0, args: args.to_vec()}))
     }
+    fn sort_var(&mut self, name: Atom) -> Option<Sort> { Some(Sort::new(name, 0)) }
 }
 
 impl types::TermBuilder for Builder {
@@ -172,14 +238,19 @@ impl types::TermBuilder for Builder {
         self.app_fun(f, args)
     }
 
-    fn declare_fun(&mut self, name: Atom, args: &[Sort], ret: Sort) -> Fun {
+    fn declare_fun(&mut self, name: Atom, args: &[Sort], ret: Sort) -> error::Result<Fun> {
         let args = Some(args.iter().map(|s| s.clone()).collect());
-        Fun::new(name, args, ret)
+        Ok(Fun::new(name, args, ret))
     }
 
-    fn declare_cstor(&mut self, name: Atom, args: &[Sort], ret: Sort) -> Fun {
+    // type variables are kept as they are, in `args` and `ret`
+    fn declare_poly_fun(&mut self, name: Atom, _vars: &[Sort], args: &[Sort], ret: Sort) -> Option<Fun> {
+        self.declare_fun(name, args, ret).ok()
+    }
+
+    fn declare_cstor(&mut self, name: Atom, args: &[Sort], ret: Sort) -> error::Result<Fun> {
         let args = Some(args.iter().map(|s| s.clone()).collect());
-        Fun::new_cstor(name, args, ret)
+        Ok(Fun::new_cstor(name, args, ret))
     }
 
     fn var(&mut self, v: Self::Var) -> Term { v }
@@ -196,11 +267,22 @@ impl types::TermBuilder for Builder {
 
     // ignore bindings, they've been expanded already
     fn let_(&mut self, _bs: &[(Self::Var,Term)], body: Self::Term) -> Self::Term { body }
+
+    fn mk_rational(&mut self, num: Atom, den: Atom) -> Option<Term> {
+        Some(Term::rational(num, den))
+    }
 }
 
 impl pp::Pretty for Sort {
     fn pp_into(&self, ctx: &mut pp::Ctx) {
-        ctx.string(self.0.name.to_string());
+        if self.0.args.is_empty() {
+            ctx.string(self.0.name.to_string());
+        } else {
+            ctx.sexp(|ctx| {
+                ctx.string(self.0.name.to_string());
+                ctx.space().array(pp::space(), &self.0.args);
+            });
+        }
     }
 }
 
@@ -218,6 +300,12 @@ impl pp::Pretty for Term {
                     ctx.str("ite ").array(pp::space(), &[a,b,c]);
                 });
             },
+            TermCell::Rational(num, den) if &**den == "1" => { ctx.string(num.to_string()); },
+            TermCell::Rational(num, den) => {
+                ctx.sexp(|ctx| {
+                    ctx.str("/ ").string(num.to_string()).space().string(den.to_string());
+                });
+            },
             TermCell::App(f, args) => {
                 if args.len() == 0 {
                     f.pp_into(ctx);
@@ -232,6 +320,18 @@ impl pp::Pretty for Term {
     }
 }
 
+/// Prints terms and sorts, e.g. for `Statement::pp_with` or `write_smtlib`.
+#[derive(Copy,Clone,Debug,Default)]
+pub struct Printer;
+
+impl pp::Pretty1<Term> for Printer {
+    fn pp1_into(&self, t: &Term, ctx: &mut pp::Ctx) { t.pp_into(ctx) }
+}
+
+impl pp::Pretty1<Sort> for Printer {
+    fn pp1_into(&self, s: &Sort, ctx: &mut pp::Ctx) { s.pp_into(ctx) }
+}
+
 macro_rules! pretty_display {
     ($t:ty) => {
         impl fmt::Display for $t {
@@ -1,6 +1,7 @@
 
 use {
-    std::{fmt::{self,Debug}, rc::Rc},
+    std::{fmt::{self,Debug}, io, rc::Rc},
+    batsmt_core::error,
     batsmt_pretty as pp,
 };
 pub use self::pp::Pretty;
@@ -13,8 +14,26 @@ pub trait SortBuilder {
 
     fn get_bool(&self) -> Self::Sort;
 
-    /// Declare a sort of the given arity
-    fn declare_sort(&mut self, name: Atom, arity: u8) -> Self::Sort;
+    /// Declare a sort of the given arity.
+    ///
+    /// Fails if the builder rejects the declaration, e.g. if `name` is already declared.
+    fn declare_sort(&mut self, name: Atom, arity: u8) -> error::Result<Self::Sort>;
+
+    /// Compound sort `(head args…)`.
+    ///
+    /// `head` is either `->`, for function sorts (the last argument is
+    /// the codomain), or a sort declared with arity `args.len()`.
+    fn app_sort(&mut self, head: Atom, args: &[Self::Sort]) -> Self::Sort;
+
+    /// Type variable `name`, bound by `par` in a polymorphic declaration.
+    ///
+    /// Returns `None` if polymorphism is not supported, which is the default.
+    fn sort_var(&mut self, _name: Atom) -> Option<Self::Sort> { None }
+
+    /// The sort `name`, with its arity, if it was declared before parsing started.
+    ///
+    /// This lets declarations outlive a call to the parser. Defaults to `None`.
+    fn lookup_sort(&self, _name: &str) -> Option<(Self::Sort, u8)> { None }
 }
 
 /// The builtins recognized by the parser
@@ -29,11 +48,29 @@ pub trait TermBuilder : SortBuilder {
     /// Term from a bound variable
     fn var(&mut self, v: Self::Var) -> Self::Term;
 
-    /// Declare a function
-    fn declare_fun(&mut self, name: Atom, args: &[Self::Sort], ret: Self::Sort) -> Self::Fun;
+    /// Declare a function.
+    ///
+    /// Fails like `SortBuilder::declare_sort`.
+    fn declare_fun(&mut self, name: Atom, args: &[Self::Sort], ret: Self::Sort) -> error::Result<Self::Fun>;
+
+    /// Declare a polymorphic function `(declare-fun name (par (vars…) (args…) ret))`.
+    ///
+    /// `vars` come from `sort_var`, and `args` and `ret` may contain them;
+    /// instantiating them is left to the builder. Returns `None` if
+    /// polymorphism is not supported, which is the default.
+    fn declare_poly_fun(
+        &mut self, _name: Atom, _vars: &[Self::Sort], _args: &[Self::Sort], _ret: Self::Sort
+    ) -> Option<Self::Fun> { None }
+
+    /// The function `name`, if it was declared before parsing started.
+    ///
+    /// Same as `SortBuilder::lookup_sort`, for functions. Defaults to `None`.
+    fn lookup_fun(&self, _name: &str) -> Option<Self::Fun> { None }
 
-    /// Declare a constructor
-    fn declare_cstor(&mut self, name: Atom, args: &[Self::Sort], ret: Self::Sort) -> Self::Fun;
+    /// Declare a constructor.
+    ///
+    /// Fails like `SortBuilder::declare_sort`.
+    fn declare_cstor(&mut self, name: Atom, args: &[Self::Sort], ret: Self::Sort) -> error::Result<Self::Fun>;
 
     /// Build a term by function application
     fn app_fun(&mut self, f: Self::Fun, args: &[Self::Term]) -> Self::Term;
@@ -49,23 +86,102 @@ pub trait TermBuilder : SortBuilder {
 
     /// Build a let binding. The variables may be called from now on.
     fn let_(&mut self, bs: &[(Self::Var, Self::Term)], body: Self::Term) -> Self::Term;
+
+    /// Annotate `t` with the attribute `key` (such as `:named`), and its value if any.
+    ///
+    /// This comes from `(! t key value)`. Attributes are ignored by default.
+    fn annotate(&mut self, t: Self::Term, _key: Atom, _value: Option<Atom>) -> Self::Term { t }
+
+    /// Build the rational constant `num/den`, from a numeral, a decimal
+    /// such as `1.5e3`, or `(/ num den)`.
+    ///
+    /// `num` and `den` are numerals (strings of digits), so that no precision
+    /// is lost; the fraction is not reduced, e.g. `1.5` is `15/10`.
+    /// Returns `None` if numbers are not supported, which is the default.
+    fn mk_rational(&mut self, _num: Atom, _den: Atom) -> Option<Self::Term> { None }
+}
+
+
+/// Lines of the input covered by a toplevel statement (1-based, inclusive).
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub struct Span {
+    pub start_line: u32,
+    pub end_line: u32,
 }
 
+impl fmt::Display for Span {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        if self.start_line == self.end_line {
+            write!(out, "line {}", self.start_line)
+        } else {
+            write!(out, "lines {}-{}", self.start_line, self.end_line)
+        }
+    }
+}
 
 /// A toplevel statement
 #[derive(Debug,Clone)]
 pub enum Statement<Term, Sort> {
     SetInfo(Atom,Atom),
+    SetOption(Atom,Atom),
     SetLogic(Atom),
     DeclareSort(Atom,u8),
     DeclareFun(Atom,Vec<Sort>,Sort),
+    /// `(declare-fun f (par (vars…) (args…) ret))`.
+    DeclarePolyFun(Atom,Vec<Atom>,Vec<Sort>,Sort),
     Assert(Term),
+    /// Non-standard: assert a lemma, for debugging; see the driver.
+    AssertLemma(Term),
     CheckSat,
     CheckSatAssumptions(Vec<Term>),
+    GetInfo(Atom),
+    GetModel,
+    /// `(get-value (t1 … tn))`.
+    GetValue(Vec<Term>),
+    /// Non-standard: block the current model, and look for another one.
+    GetNextModel,
+    GetUnsatCore,
+    /// Remove all assertions, but keep declarations.
+    ResetAssertions,
+    /// Forget everything, including declarations and options.
+    Reset,
     Exit,
 }
 
 impl<T,S> Statement<T,S> {
+    /// Name of the command, e.g. `"check-sat"`.
+    pub fn name(&self) -> &'static str {
+        use super::Statement::*;
+        match self {
+            SetInfo(..) => "set-info",
+            SetOption(..) => "set-option",
+            SetLogic(..) => "set-logic",
+            DeclareSort(..) => "declare-sort",
+            DeclareFun(..) | DeclarePolyFun(..) => "declare-fun",
+            Assert(..) => "assert",
+            AssertLemma(..) => "assert-lemma",
+            CheckSat => "check-sat",
+            CheckSatAssumptions(..) => "check-sat-assumptions",
+            GetInfo(..) => "get-info",
+            GetModel => "get-model",
+            GetValue(..) => "get-value",
+            GetNextModel => "get-next-model",
+            GetUnsatCore => "get-unsat-core",
+            ResetAssertions => "reset-assertions",
+            Reset => "reset",
+            Exit => "exit",
+        }
+    }
+
+    /// Print in SMT-LIB syntax, with terms and sorts printed by `p`.
+    ///
+    /// The result implements `Pretty`, `Display` and `Debug`.
+    pub fn pp_with<'a, P>(&'a self, p: &'a P) -> impl 'a + Pretty + fmt::Display + fmt::Debug
+        where P: pp::Pretty1<T> + pp::Pretty1<S>
+    {
+        StatementWith{st: self, p}
+    }
+
     /// Tranform terms and sorts
     pub fn map<T2,S2,FT,FS>(self, mut ft: FT, mut fs: FS) -> Statement<T2,S2>
         where FT: FnMut(T)->T2, FS: FnMut(S) -> S2
@@ -73,6 +189,7 @@ impl<T,S> Statement<T,S> {
         use super::Statement::*;
         match self {
             SetInfo(a,b) => SetInfo(a,b),
+            SetOption(a,b) => SetOption(a,b),
             SetLogic(a) => SetLogic(a),
             DeclareSort(s,n) => DeclareSort(s,n),
             DeclareFun(s,args,ret) => {
@@ -80,12 +197,25 @@ impl<T,S> Statement<T,S> {
                 let ret = fs(ret);
                 DeclareFun(s,args,ret)
             },
+            DeclarePolyFun(s,vars,args,ret) => {
+                let args = args.into_iter().map(|s| fs(s)).collect();
+                let ret = fs(ret);
+                DeclarePolyFun(s,vars,args,ret)
+            },
             Assert(t) => Assert(ft(t)),
+            AssertLemma(t) => AssertLemma(ft(t)),
             CheckSat => CheckSat,
             CheckSatAssumptions(v) => {
                 let v = v.into_iter().map(|x| ft(x)).collect();
                 CheckSatAssumptions(v)
             },
+            GetInfo(a) => GetInfo(a),
+            GetModel => GetModel,
+            GetValue(v) => GetValue(v.into_iter().map(&mut ft).collect()),
+            GetNextModel => GetNextModel,
+            GetUnsatCore => GetUnsatCore,
+            ResetAssertions => ResetAssertions,
+            Reset => Reset,
             Exit => Exit,
         }
     }
@@ -101,6 +231,11 @@ pub fn pp_stmt<T,S,FT,FS>(st: &Statement<T,S>, mut ft: FT, mut fs: FS, ctx: &mut
                 ctx.str("set-info").space().pp(&a).space().pp(&b);
             });
         },
+        &Statement::SetOption(ref a, ref b) => {
+            ctx.sexp(|ctx| {
+                ctx.str("set-option").space().pp(&a).space().pp(&b);
+            });
+        },
         &Statement::SetLogic(ref a) => {
             ctx.sexp(|ctx| {
                 ctx.str("set-logic").space().pp(&a);
@@ -115,14 +250,26 @@ pub fn pp_stmt<T,S,FT,FS>(st: &Statement<T,S>, mut ft: FT, mut fs: FS, ctx: &mut
             ctx.sexp(|ctx| {
                 ctx.str("declare-fun").space().pp(&f).space().
                     sexp(|ctx| {
-                        ctx.sexp(|ctx| {
-                            for (i,u) in args.iter().enumerate() {
-                                if i>0 { ctx.space(); }
-                                fs(u,ctx);
-                            }}).space();
+                        for (i,u) in args.iter().enumerate() {
+                            if i>0 { ctx.space(); }
+                            fs(u,ctx);
+                        }}).space();
+                fs(&ret, ctx);
+            });
+        },
+        &Statement::DeclarePolyFun(ref f, ref vars, ref args, ref ret) => {
+            ctx.sexp(|ctx| {
+                ctx.str("declare-fun").space().pp(&f).space().
+                    sexp(|ctx| {
+                        ctx.str("par").space()
+                            .sexp(|ctx| { ctx.array(pp::space(), vars); }).space()
+                            .sexp(|ctx| {
+                                for (i,u) in args.iter().enumerate() {
+                                    if i>0 { ctx.space(); }
+                                    fs(u,ctx);
+                                }}).space();
                         fs(&ret, ctx);
-                        });
-
+                    });
             });
         },
         &Statement::Assert(ref t) => {
@@ -131,6 +278,12 @@ pub fn pp_stmt<T,S,FT,FS>(st: &Statement<T,S>, mut ft: FT, mut fs: FS, ctx: &mut
                 ft(t, ctx);
             });
         },
+        &Statement::AssertLemma(ref t) => {
+            ctx.sexp(|ctx| {
+                ctx.str("assert-lemma").space();
+                ft(t, ctx);
+            });
+        },
         &Statement::CheckSat => { ctx.str("(check-sat)"); },
         &Statement::CheckSatAssumptions(ref v) => {
             ctx.sexp(|ctx| {
@@ -138,6 +291,26 @@ pub fn pp_stmt<T,S,FT,FS>(st: &Statement<T,S>, mut ft: FT, mut fs: FS, ctx: &mut
                 for t in v { ctx.space(); ft(t,ctx); }
             });
         },
+        &Statement::GetInfo(ref a) => {
+            ctx.sexp(|ctx| {
+                ctx.str("get-info").space().pp(&a);
+            });
+        },
+        &Statement::GetModel => { ctx.str("(get-model)"); },
+        Statement::GetValue(v) => {
+            ctx.sexp(|ctx| {
+                ctx.str("get-value").space().sexp(|ctx| {
+                    for (i, t) in v.iter().enumerate() {
+                        if i > 0 { ctx.space(); }
+                        ft(t,ctx);
+                    }
+                });
+            });
+        },
+        &Statement::GetNextModel => { ctx.str("(get-next-model)"); },
+        &Statement::GetUnsatCore => { ctx.str("(get-unsat-core)"); },
+        &Statement::ResetAssertions => { ctx.str("(reset-assertions)"); },
+        &Statement::Reset => { ctx.str("(reset)"); },
         &Statement::Exit => { ctx.str("(exit)"); },
     }
 }
@@ -154,6 +327,47 @@ impl<T:Pretty,S:Pretty> fmt::Display for Statement<T,S> {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result { Pretty::pp_fmt(&self,out,true) }
 }
 
+/// Write `statements` to `out` in SMT-LIB syntax, in order, one per line.
+///
+/// Terms and sorts are printed by `p`, as in `Statement::pp_with`.
+/// Symbols are written as they are, without `|…|` quotes, so a symbol
+/// such as `|a b|` must be quoted by `p` to be parsed back.
+pub fn write_smtlib<T,S,P,W>(statements: &[Statement<T,S>], p: &P, mut out: W) -> io::Result<()>
+    where P: pp::Pretty1<T> + pp::Pretty1<S>, W: io::Write
+{
+    for st in statements {
+        writeln!(out, "{}", st.pp_with(p))?;
+    }
+    out.flush()
+}
+
+// result of `Statement::pp_with`
+struct StatementWith<'a, T, S, P> {
+    st: &'a Statement<T,S>,
+    p: &'a P,
+}
+
+impl<'a,T,S,P> pp::Pretty for StatementWith<'a,T,S,P>
+    where P: pp::Pretty1<T> + pp::Pretty1<S>
+{
+    fn pp_into(&self, ctx: &mut pp::Ctx) {
+        let p = self.p;
+        pp_stmt(self.st, |t,ctx| p.pp1_into(t,ctx), |s,ctx| p.pp1_into(s,ctx), ctx);
+    }
+}
+
+impl<'a,T,S,P> fmt::Display for StatementWith<'a,T,S,P>
+    where P: pp::Pretty1<T> + pp::Pretty1<S>
+{
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result { Pretty::pp_fmt(&self,out,false) }
+}
+
+impl<'a,T,S,P> fmt::Debug for StatementWith<'a,T,S,P>
+    where P: pp::Pretty1<T> + pp::Pretty1<S>
+{
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result { Pretty::pp_fmt(&self,out,true) }
+}
+
 #[test]
 fn test_pp() {
     use crate::simple_ast as a;
@@ -161,3 +375,349 @@ fn test_pp() {
     let s = format!("{}", &st);
     assert_eq!("(exit)", s);
 }
+
+#[test]
+fn test_pp_with() {
+    // terms are strings, printed between `|`
+    struct Quote;
+    impl pp::Pretty1<String> for Quote {
+        fn pp1_into(&self, s: &String, ctx: &mut pp::Ctx) { ctx.string(format!("|{}|", s)); }
+    }
+    let st: Statement<String, String> = Statement::Assert("p q".to_string());
+    assert_eq!("(assert |p q|)", st.pp_with(&Quote).to_string());
+    let st: Statement<String, String> = Statement::CheckSat;
+    assert_eq!("(check-sat)", st.pp_with(&Quote).to_string());
+}
+
+#[test]
+fn test_parse_get_info() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_str(&mut b, "(check-sat) (get-info :reason-unknown)").unwrap();
+    assert_eq!(stmts.len(), 2);
+    match &stmts[1] {
+        Statement::GetInfo(a) => assert_eq!(&**a, ":reason-unknown"),
+        st => panic!("expected get-info, got {:?}", st),
+    }
+    assert_eq!("(get-info :reason-unknown)", format!("{}", &stmts[1]));
+}
+
+#[test]
+fn test_parse_set_option() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_str(&mut b, "(set-option :produce-models true) (get-model)").unwrap();
+    assert_eq!(stmts.len(), 2);
+    match &stmts[0] {
+        Statement::SetOption(a, b) => {
+            assert_eq!(&**a, ":produce-models");
+            assert_eq!(&**b, "true");
+        },
+        st => panic!("expected set-option, got {:?}", st),
+    }
+    assert_eq!("(set-option :produce-models true)", format!("{}", &stmts[0]));
+    assert_eq!("(get-model)", format!("{}", &stmts[1]));
+}
+
+#[test]
+fn test_parse_get_value() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let input = "(declare-sort U 0) (declare-fun a () U) (declare-fun f (U) U) (get-value (a (f a)))";
+    let stmts = crate::parse_str(&mut b, input).unwrap();
+    assert_eq!(stmts.len(), 4);
+    match &stmts[3] {
+        Statement::GetValue(v) => assert_eq!(v.len(), 2),
+        st => panic!("expected get-value, got {:?}", st),
+    }
+    assert_eq!("(get-value (a (f a)))", format!("{}", &stmts[3]));
+}
+
+#[test]
+fn test_parse_get_next_model() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_str(&mut b, "(check-sat) (get-next-model)").unwrap();
+    assert_eq!(stmts.len(), 2);
+    match &stmts[1] {
+        Statement::GetNextModel => (),
+        st => panic!("expected get-next-model, got {:?}", st),
+    }
+    assert_eq!("(get-next-model)", format!("{}", &stmts[1]));
+}
+
+#[test]
+fn test_parse_reset_assertions() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_str(&mut b, "(declare-fun p () Bool) (reset-assertions) (assert p)").unwrap();
+    assert_eq!(stmts.len(), 3);
+    match &stmts[1] {
+        Statement::ResetAssertions => (),
+        st => panic!("expected reset-assertions, got {:?}", st),
+    }
+    assert_eq!("(reset-assertions)", format!("{}", &stmts[1]));
+}
+
+#[test]
+fn test_parse_reset() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    // `p` can be declared again after `(reset)`
+    let input = "(declare-fun p () Bool) (reset) (declare-fun p () Bool) (assert p)";
+    let stmts = crate::parse_str(&mut b, input).unwrap();
+    assert_eq!(stmts.len(), 4);
+    match &stmts[1] {
+        Statement::Reset => (),
+        st => panic!("expected reset, got {:?}", st),
+    }
+    assert_eq!("(reset)", format!("{}", &stmts[1]));
+}
+
+#[test]
+fn test_parse_batches() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let input = "(declare-fun p () Bool)\n(reset)\n(declare-fun p () Bool)\n(assert p)\n(reset)\n";
+    let mut batches = crate::Batches::new(input.as_bytes());
+    let mut lens = vec!();
+    while let Some(stmts) = batches.next_with_spans(&mut b).unwrap() {
+        assert_eq!(Some("reset"), stmts.last().map(|(_, st)| st.name()));
+        lens.push(stmts.iter().map(|(span, _)| span.start_line).collect::<Vec<_>>());
+    }
+    // lines are counted from the start of the input
+    assert_eq!(lens, vec![vec![1, 2], vec![3, 4, 5]]);
+
+    let input = "(assert true) (exit) (reset) (assert false)";
+    let mut batches = crate::Batches::new(input.as_bytes());
+    let stmts = batches.next_with_spans(&mut b).unwrap().unwrap();
+    assert_eq!(stmts.len(), 2);
+    assert!(batches.next_with_spans(&mut b).unwrap().is_none(), "stops at exit");
+}
+
+#[test]
+fn test_parse_stops_at_exit() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let input = "(declare-fun p () Bool) (assert p) (exit) (assert q) ) (garbage";
+    let stmts = crate::parse_str(&mut b, input).unwrap();
+    assert_eq!(stmts.len(), 3);
+    match stmts.last() {
+        Some(Statement::Exit) => (),
+        st => panic!("expected exit, got {:?}", st),
+    }
+
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_with_spans(&mut b, input.as_bytes()).unwrap();
+    assert_eq!(stmts.len(), 3);
+}
+
+#[test]
+fn test_parse_unicode_symbol() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_str(&mut b, "(declare-fun |αβγ δ| () Bool) (assert |αβγ δ|)").unwrap();
+    assert_eq!(stmts.len(), 2);
+    match &stmts[0] {
+        Statement::DeclareFun(f, args, _) => {
+            assert_eq!(&**f, "αβγ δ");
+            assert!(args.is_empty());
+        },
+        st => panic!("expected declare-fun, got {:?}", st),
+    }
+}
+
+#[test]
+fn test_parse_assert_lemma() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_str(&mut b, "(declare-fun p () Bool) (assert-lemma (not p))").unwrap();
+    assert_eq!(stmts.len(), 2);
+    match &stmts[1] {
+        Statement::AssertLemma(_) => (),
+        st => panic!("expected assert-lemma, got {:?}", st),
+    }
+    assert_eq!("(assert-lemma (not p))", format!("{}", &stmts[1]));
+}
+
+#[test]
+fn test_parse_compound_sort() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_str(&mut b, r#"
+        (declare-sort U 0)
+        (declare-sort Pair 2)
+        (declare-fun f ((-> U (Pair U Bool)) U) Bool)
+        (declare-fun g (U) U)
+    "#).unwrap();
+    assert_eq!(stmts.len(), 4);
+    match &stmts[2] {
+        Statement::DeclareFun(f, args, ret) => {
+            assert_eq!(&**f, "f");
+            assert_eq!(args.len(), 2);
+            assert_eq!(args[0].args().len(), 2);
+            assert_eq!("(-> U (Pair U Bool))", format!("{}", args[0]));
+            assert_eq!("Bool", format!("{}", ret));
+        },
+        st => panic!("expected declare-fun, got {:?}", st),
+    }
+    // first-order declarations are unchanged
+    match &stmts[3] {
+        Statement::DeclareFun(_, args, ret) => {
+            assert_eq!(args.len(), 1);
+            assert!(args[0].args().is_empty() && ret.args().is_empty());
+        },
+        st => panic!("expected declare-fun, got {:?}", st),
+    }
+
+    let mut b = a::Builder::new();
+    let res = crate::parse_str(&mut b, "(declare-sort U 0) (declare-fun f ((U U)) U)");
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_parse_annotations() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_str(&mut b, r#"
+        (declare-sort U 0)
+        (declare-fun f (U U) U)
+        (declare-fun a () U)
+        (assert (= (! (f a a) :commutative :named fa) a))
+    "#).unwrap();
+    assert_eq!(stmts.len(), 4);
+    // annotations are dropped by the simple AST
+    assert_eq!("(assert (= (f a a) a))", format!("{}", &stmts[3]));
+
+    for bad in &["(! a)", "(! a :named)", "(! a named)", "(! a :pattern (a))"] {
+        let mut b = a::Builder::new();
+        let pb = format!("(declare-sort U 0) (declare-fun a () U) (assert (= {} a))", bad);
+        assert!(crate::parse_str(&mut b, &pb).is_err(), "{} should not parse", bad);
+    }
+}
+
+#[test]
+fn test_parse_rationals() {
+    use crate::simple_ast::{self as a, TermCell};
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_str(&mut b, r#"
+        (assert (= (/ 1 3) 1.5e3))
+        (assert (distinct 1.5 0.25e-2 12 007E+1))
+    "#).unwrap();
+    assert_eq!(stmts.len(), 2);
+    let rationals = |st: &Statement<a::Term, a::Sort>| -> Vec<(String, String)> {
+        match st {
+            Statement::Assert(t) => match t.view() {
+                TermCell::App(_, args) => args.iter().map(|u| match u.view() {
+                    TermCell::Rational(num, den) => (num.to_string(), den.to_string()),
+                    _ => panic!("expected a rational, got {:?}", u),
+                }).collect(),
+                _ => panic!("expected an application, got {:?}", t),
+            },
+            st => panic!("expected assert, got {:?}", st),
+        }
+    };
+    let pair = |n: &str, d: &str| (n.to_string(), d.to_string());
+    assert_eq!(rationals(&stmts[0]), vec![pair("1", "3"), pair("1500", "1")]);
+    assert_eq!(rationals(&stmts[1]),
+        vec![pair("15", "10"), pair("25", "10000"), pair("12", "1"), pair("70", "1")]);
+    assert_eq!("(assert (= (/ 1 3) 1500))", format!("{}", &stmts[0]));
+
+    for bad in &["1.", "1.5e", "1e3.5", "(/ 1 0)", "(/ 1.5 2)", "(/ 1 (f 2))", "12ab"] {
+        let mut b = a::Builder::new();
+        let pb = format!("(declare-fun f (Bool) Bool) (assert (= {} 1))", bad);
+        assert!(crate::parse_str(&mut b, &pb).is_err(), "{} should not parse", bad);
+    }
+}
+
+#[test]
+fn test_parse_poly_fun() {
+    use crate::simple_ast as a;
+    let mut b = a::Builder::new();
+    let stmts = crate::parse_str(&mut b, r#"
+        (declare-sort A 0)
+        (declare-fun f (par (A) (A A) Bool))
+        (declare-fun g (par (X Y) ((-> X Y) X) Y))
+        (declare-fun a () A)
+        (assert (f a a))
+    "#).unwrap();
+    assert_eq!(stmts.len(), 5);
+    match &stmts[2] {
+        Statement::DeclarePolyFun(g, vars, args, ret) => {
+            assert_eq!(&**g, "g");
+            assert_eq!(vars.iter().map(|v| &**v).collect::<Vec<_>>(), vec!["X", "Y"]);
+            assert_eq!(args.len(), 2);
+            assert_eq!("Y", format!("{}", ret));
+        },
+        st => panic!("expected declare-fun, got {:?}", st),
+    }
+    assert_eq!("(declare-fun f (par (A) (A A) Bool))", format!("{}", &stmts[1]));
+    assert_eq!("(declare-fun g (par (X Y) ((-> X Y) X) Y))", format!("{}", &stmts[2]));
+    assert_eq!("declare-fun", stmts[2].name());
+
+    // type variables are only in scope in their declaration
+    let mut b = a::Builder::new();
+    let res = crate::parse_str(&mut b, "(declare-fun f (par (X) (X) Bool)) (declare-fun a () X)");
+    assert!(res.is_err());
+}
+
+#[test]
+fn test_simple_ast_inspect() {
+    use crate::simple_ast::{self as a, TermCell};
+    let stmts = a::parse_str(r#"
+        (declare-sort U 0)
+        (declare-fun f (U U) U)
+        (declare-fun a () U)
+        (assert (let ((x (f a a))) (not (= x a))))
+    "#).unwrap();
+    let t = match stmts.last() {
+        Some(Statement::Assert(t)) => t.clone(),
+        st => panic!("expected assert, got {:?}", st),
+    };
+    let (not_, args) = t.as_app().expect("not");
+    assert_eq!(not_.name(), "not");
+    let (eq, args) = args[0].as_app().expect("=");
+    assert_eq!(eq.name(), "=");
+    assert_eq!(eq.args(), None, "`=` is variadic");
+    let (f, f_args) = args[0].as_app().expect("f a a");
+    assert_eq!(f.name(), "f");
+    assert_eq!(f.args().map(|s| s.len()), Some(2));
+    assert_eq!(f.ret().name(), "U");
+    assert_eq!(f_args.len(), 2);
+    assert_eq!(f_args[0], f_args[0].clone(), "terms are shared, not copied");
+    assert_eq!("(not (= (f a a) a))", t.to_string());
+    match args[1].view() {
+        TermCell::App(a, a_args) => assert!(a.name() == "a" && a_args.is_empty()),
+        _ => panic!("expected a constant"),
+    }
+}
+
+#[test]
+fn test_write_smtlib_roundtrip() {
+    use crate::simple_ast as a;
+    let input = r#"
+        (set-logic QF_UF)
+        (set-info :status unsat)
+        (declare-sort U 0)
+        (declare-fun f (U U) U)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun p (U) Bool)
+        (assert (let ((x (f a b))) (and (= x a) (p x))))
+        (assert (or (not (p a)) (ite (p b) (distinct a b) (=> (p a) false))))
+        (check-sat)
+        (exit)
+    "#;
+    let stmts = a::parse_str(input).unwrap();
+    let mut buf = vec!();
+    write_smtlib(&stmts, &a::Printer, &mut buf).unwrap();
+    let output = String::from_utf8(buf).unwrap();
+    assert_eq!(output.lines().count(), stmts.len());
+
+    let stmts2 = a::parse_str(&output).unwrap();
+    let show = |v: &[Statement<a::Term,a::Sort>]| -> Vec<String> {
+        v.iter().map(|st| st.to_string()).collect()
+    };
+    assert_eq!(show(&stmts), show(&stmts2));
+    assert_eq!("(assert (and (= (f a b) a) (p (f a b))))", show(&stmts2)[7]);
+}
@@ -11,13 +11,14 @@ use {
         fmt, borrow::{Borrow,ToOwned},
         collections::VecDeque,
     },
-    pretty::{DocAllocator,DocBuilder,Doc,Arena},
+    pretty::{DocAllocator,DocBuilder,Arena,Render,RenderAnnotated},
 };
 
 // operations
 #[derive(Debug,Clone)]
 enum Op {
     Open(usize),
+    OpenHang(usize),
     Close,
     Newline,
     Space,
@@ -31,12 +32,20 @@ pub struct Ctx {
     ops: VecDeque<Op>,
 }
 
-type StackItem<'a> = DocBuilder<'a, Arena<'a,()>>;
+// annotated with the index of the wide text it stands for, if any
+type StackItem<'a> = DocBuilder<'a, Arena<'a,usize>, usize>;
+
+// how a box indents its lines
+#[derive(Debug,Clone,Copy)]
+enum Indent {
+    Nest(usize), // relative to the enclosing indentation
+    Hang(usize), // relative to the column where the box starts
+}
 
 // a stack of document builders
 struct Stack<'a> {
     pub st: Vec<StackItem<'a>>, // queue of operations
-    pub boxes: Vec<usize>, // indentation levels
+    pub boxes: Vec<Indent>, // indentation levels
 }
 
 impl<'a> Stack<'a> {
@@ -44,11 +53,11 @@ impl<'a> Stack<'a> {
         Stack { st: Vec::new(), boxes: Vec::new(), }
     }
 
-    fn enter_box(&mut self, n: usize, start: StackItem<'a>) {
+    fn enter_box(&mut self, n: Indent, start: StackItem<'a>) {
         self.boxes.push(n);
         self.st.push(start); // to be combined with the rest
     }
-    fn exit_box(&mut self) -> usize {
+    fn exit_box(&mut self) -> Indent {
         debug_assert!(self.boxes.len() > 0);
         self.boxes.pop().expect("no box to exit")
     }
@@ -70,6 +79,18 @@ impl<'a> Stack<'a> {
         self.st.pop().expect("cannot pop from empty stack")
     }
 
+    // `pretty` measures text in bytes: a non-ASCII text is laid out as
+    // a placeholder with one byte per character, stored in `wide`
+    fn push_text(&mut self, arena: &'a Arena<'a,usize>, wide: &mut Vec<String>, s: String) {
+        if s.is_ascii() {
+            self.push(arena.text(s))
+        } else {
+            let placeholder = "_".repeat(s.chars().count());
+            self.push(arena.text(placeholder).annotate(wide.len()));
+            wide.push(s);
+        }
+    }
+
     // assuming there's only one element remaining, pop it
     fn pop_last(&mut self) -> StackItem<'a> {
         debug_assert!(self.boxes.len() == 0, "all boxes should be closed");
@@ -103,43 +124,85 @@ impl Ctx {
 
         // temporary docs
         let mut stack = Stack::new();
+        let mut wide = vec!();
 
         while let Some(op) = self.ops.pop_front() {
             //println!("process op {:?} (stack len {} nboxes {})", op, stack.st.len(), stack.boxes.len());
 
             match op {
                 Op::Open(n) => {
-                    stack.enter_box(n, arena.nil());
+                    stack.enter_box(Indent::Nest(n), arena.nil());
+                },
+                Op::OpenHang(n) => {
+                    stack.enter_box(Indent::Hang(n), arena.nil());
                 },
                 Op::Newline => {
-                    stack.push(arena.newline());
+                    stack.push(arena.hardline());
                 },
                 Op::Space => {
-                    stack.push(arena.space());
+                    stack.push(arena.line());
                 },
                 Op::Close => {
-                    let n = stack.exit_box();
                     let mut d = stack.pop();
-                    if n > 0 { d = d.nest(n) }
+                    match stack.exit_box() {
+                        Indent::Nest(0) => (),
+                        Indent::Nest(n) => d = d.nest(n as isize),
+                        Indent::Hang(n) => d = d.hang(n as isize),
+                    }
                     d = d.group();
                     stack.push(d) // might combine with previous box
                 },
-                Op::SStatic(str) => {
+                Op::SStatic(str) if str.is_ascii() => {
                     stack.push(arena.text(str));
                 },
+                Op::SStatic(str) => {
+                    stack.push_text(&arena, &mut wide, str.to_string());
+                },
                 Op::Text(s) => {
-                    stack.push(arena.text(s));
+                    stack.push_text(&arena, &mut wide, s);
                 },
             }
         }
 
         // extract top doc
-        let d : Doc<_> = stack.pop_last().into();
+        let d = stack.pop_last();
 
         // render to a string
-        let mut s = Vec::new();
-        d.render(width, &mut s).unwrap();
-        String::from_utf8(s).unwrap()
+        let mut out = Out{s: String::new(), wide: &wide, cur: None};
+        d.1.render_raw(width, &mut out).unwrap();
+        out.s
+    }
+}
+
+// renders into a string, replacing placeholders with their wide text
+struct Out<'w> {
+    s: String,
+    wide: &'w [String],
+    cur: Option<usize>, // placeholder being rendered
+}
+
+impl<'w> Render for Out<'w> {
+    type Error = ();
+
+    fn write_str(&mut self, s: &str) -> Result<usize, ()> {
+        match self.cur {
+            None => self.s.push_str(s),
+            Some(i) => self.s.push_str(&self.wide[i]),
+        }
+        Ok(s.len())
+    }
+
+    fn fail_doc(&self) -> Self::Error {}
+}
+
+impl<'a, 'w> RenderAnnotated<'a, usize> for Out<'w> {
+    fn push_annotation(&mut self, i: &'a usize) -> Result<(), ()> {
+        self.cur = Some(*i);
+        Ok(())
+    }
+    fn pop_annotation(&mut self) -> Result<(), ()> {
+        self.cur = None;
+        Ok(())
     }
 }
 
@@ -154,6 +217,7 @@ impl Ctx {
     pub fn newline(&mut self) -> &mut Self { self.push_(Op::Newline) }
     pub fn space(&mut self) -> &mut Self { self.push_(Op::Space) }
     fn open_indent(&mut self, u: usize) -> &mut Self { self.push_(Op::Open(u)); self }
+    fn open_hang(&mut self, u: usize) -> &mut Self { self.push_(Op::OpenHang(u)); self }
     fn close(&mut self) -> &mut Self { self.push_(Op::Close); self }
 
     pub fn pp<T:Pretty>(&mut self, x: &T) -> &mut Self { x.pp_into(self); self }
@@ -170,8 +234,31 @@ impl Ctx {
         self
     }
 
+    /// Call `f` in a box whose lines hang `n` columns to the right of
+    /// the column where the box starts.
+    ///
+    /// Unlike `with_indent`, which nests relative to the enclosing
+    /// indentation, this follows the cursor (`pretty`'s `hang`).
+    pub fn hang<F,U>(&mut self, n: usize, f: F) -> &mut Self
+        where F: FnOnce(&mut Ctx) -> U
+    {
+        self.open_hang(n);
+        f(self);
+        self.close();
+        self
+    }
+
     pub fn with_box<F>(&mut self, f: F) -> &mut Self where F: FnOnce(&mut Ctx) { self.with_indent(0,f) }
 
+    /// Call `f` in a box if `cond` is true, otherwise directly.
+    ///
+    /// A box is printed on one line if it fits, otherwise all its spaces
+    /// become newlines. Without the box, spaces printed by `f` break
+    /// along with the enclosing box, which suits small subtrees.
+    pub fn with_group_if<F>(&mut self, cond: bool, f: F) -> &mut Self where F: FnOnce(&mut Ctx) {
+        if cond { self.with_box(f) } else { f(self); self }
+    }
+
     pub fn sexp<F,U>(&mut self, f: F) -> &mut Self
         where F: FnOnce(&mut Ctx) -> U
     { self.str("("); self.with_indent(1,f); self.str(")"); self }
@@ -385,6 +472,46 @@ pub fn from_display<T:fmt::Display>(x: T) -> impl Pretty {
     Dis(x)
 }
 
+/// A table, see `table`.
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+/// Print `rows` as a table, below the column names in `header`.
+///
+/// Each row ends with a newline. Columns are separated by two spaces;
+/// the first column is aligned on the left, the others on the right,
+/// as they usually hold numbers.
+pub fn table<S:Into<String>>(header: Vec<S>, rows: Vec<Vec<String>>) -> Table {
+    let header = header.into_iter().map(|s| s.into()).collect();
+    Table{header, rows}
+}
+
+impl Pretty for Table {
+    fn pp_into(&self, ctx: &mut Ctx) {
+        let n_cols = self.rows.iter().map(|r| r.len()).fold(self.header.len(), usize::max);
+        let mut widths = vec![0; n_cols];
+        for row in Some(&self.header).into_iter().chain(self.rows.iter()) {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+        for row in Some(&self.header).into_iter().chain(self.rows.iter()) {
+            let mut line = String::new();
+            for (i, w) in widths.iter().enumerate() {
+                let cell = row.get(i).map_or("", |s| s.as_str());
+                if i == 0 {
+                    line.push_str(&format!("{:<w$}", cell, w=w));
+                } else {
+                    line.push_str(&format!("  {:>w$}", cell, w=w));
+                }
+            }
+            ctx.string(line.trim_end().to_string()).newline();
+        }
+    }
+}
+
 /// Temporary holder of `T`.
 struct Tmp<T>(T);
 
@@ -459,3 +586,70 @@ fn test_display() {
     let s3 = format!("{}", display(sexp!(&Foo(1), &Foo(23), &Foo(105))));
     assert_eq!("(1 23 105)", s3);
 }
+
+#[test]
+fn test_group_if() {
+    // `aaa bbb (c d)`, too large for its width
+    struct T(bool);
+    impl Pretty for T {
+        fn pp_into(&self, ctx: &mut Ctx) {
+            ctx.str("aaa").space().str("bbb").space();
+            ctx.with_group_if(self.0, |ctx| { ctx.str("c").space().str("d"); });
+        }
+        fn width(&self) -> usize { 10 }
+    }
+
+    // the group fits on one line, even though the enclosing box breaks
+    assert_eq!("aaa\nbbb\nc d", format!("{}", display(T(true))));
+    // without the group, its space breaks with the enclosing box
+    assert_eq!("aaa\nbbb\nc\nd", format!("{}", display(T(false))));
+}
+
+#[test]
+fn test_table() {
+    let rows = vec![
+        vec!["a".to_string(), "1".to_string(), "200".to_string()],
+        vec!["bcd".to_string(), "30".to_string()],
+    ];
+    let s = format!("{}", display(table(vec!["name", "x", "y"], rows)));
+    assert_eq!("name   x    y\na      1  200\nbcd   30\n", s);
+}
+
+#[test]
+fn test_hang() {
+    // `items: alpha beta gamma delta`, too large for its width
+    struct L(bool);
+    impl Pretty for L {
+        fn pp_into(&self, ctx: &mut Ctx) {
+            let items = ["alpha", "beta", "gamma", "delta"];
+            ctx.str("items: ");
+            if self.0 {
+                ctx.hang(2, |ctx| { ctx.array(space(), &items); });
+            } else {
+                ctx.with_indent(2, |ctx| { ctx.array(space(), &items); });
+            }
+        }
+        fn width(&self) -> usize { 20 }
+    }
+
+    // lines hang 2 columns right of `alpha`
+    assert_eq!("items: alpha\n         beta\n         gamma\n         delta", format!("{}", display(L(true))));
+    // lines are nested from the enclosing box
+    assert_eq!("items: alpha\n  beta\n  gamma\n  delta", format!("{}", display(L(false))));
+}
+
+#[test]
+fn test_wide_text() {
+    // 4 symbols of 3 characters (6 bytes) each
+    struct W(usize);
+    impl Pretty for W {
+        fn pp_into(&self, ctx: &mut Ctx) {
+            ctx.sexp(|ctx| { ctx.array(space(), &["αβγ", "δεζ", "ηθι", "κλμ"]); });
+        }
+        fn width(&self) -> usize { self.0 }
+    }
+
+    // 17 characters, but 29 bytes
+    assert_eq!("(αβγ δεζ ηθι κλμ)", format!("{}", display(W(17))));
+    assert_eq!("(αβγ\n δεζ\n ηθι\n κλμ)", format!("{}", display(W(16))));
+}
@@ -1,32 +1,129 @@
 
 use {
     batsmt_parser as parser,
-    batsmt_core::{ast_u32::AST, },
-    fxhash::FxHashMap,
+    batsmt_core::{ast_u32::AST, AstView, AttrKey, AttrValue, backtrack, error::{self, Error}, },
+    fxhash::{FxHashMap, FxHashSet},
     crate::{parser::Atom, Ctx, },
 };
 
+/// Declared sorts and functions, kept across calls to the parser.
+///
+/// Declarations made in a scope (see `push_scope`) are forgotten when it is popped.
+pub struct Symbols {
+    sorts: FxHashMap<Atom, (AST, u8)>,
+    funs: FxHashMap<Atom, (AST, Vec<AST>, AST)>, // sort
+    used: FxHashSet<AST>, // subterms, and their sorts, of `record_uses`
+    scoped: backtrack::Stack<Decl>, // declarations made in the current scopes
+}
+
+// a name to forget when its scope is popped
+enum Decl {
+    Sort(Atom),
+    Fun(Atom),
+}
+
 /// AST builder for the parser
 pub struct AstBuilder<'a> {
     m: &'a mut Ctx,
     b: crate::Builtins,
-    sorts: FxHashMap<Atom, (AST, u8)>,
-    funs: FxHashMap<Atom, (AST, Vec<AST>, AST)>, // sort
+    syms: &'a mut Symbols,
 }
 
 mod ast_builder {
-    use {super::*, batsmt_core::Manager};
+    use {super::*, batsmt_core::{Manager, match_term}};
 
     impl<'a> AstBuilder<'a> {
-        /// Create an AST builder that uses the given manager.
-        pub fn new(m: &'a mut Ctx) -> Self {
+        /// Create an AST builder that uses the given manager and symbol table.
+        pub fn new(m: &'a mut Ctx, syms: &'a mut Symbols) -> Self {
             let b = m.builtins();
-            Self {
-                m, b,
-                funs: FxHashMap::default(),
-                sorts: FxHashMap::default(),
+            Self { m, b, syms, }
+        }
+    }
+
+    impl Symbols {
+        /// Empty symbol table.
+        pub fn new() -> Self {
+            Symbols {
+                sorts: FxHashMap::default(), funs: FxHashMap::default(),
+                used: FxHashSet::default(), scoped: backtrack::Stack::new(),
             }
         }
+
+        /// Number of scopes that are not popped yet.
+        pub fn n_scopes(&self) -> usize { self.scoped.n_levels() }
+
+        /// Open a scope: the declarations made from now on are
+        /// forgotten by the matching `pop_scope`.
+        pub fn push_scope(&mut self) {
+            self.scoped.push_level();
+        }
+
+        /// Forget the declarations made since the last `push_scope`.
+        ///
+        /// Their names can be declared again, which makes new terms:
+        /// the terms built before remain valid, and distinct from the new ones.
+        /// Panics if there is no scope, see `n_scopes`.
+        pub fn pop_scope(&mut self) {
+            let Symbols{sorts, funs, scoped, ..} = self;
+            scoped.pop_levels(1, |d| match d {
+                Decl::Sort(s) => { sorts.remove(&s); },
+                Decl::Fun(f) => { funs.remove(&f); },
+            });
+        }
+
+        /// Declared constants of type `bool_`, by name.
+        pub fn bool_consts(&self, bool_: AST) -> Vec<(Atom, AST)> {
+            let mut v: Vec<_> =
+                self.funs.iter()
+                .filter(|(_, (_, args, ret))| args.len() == 0 && *ret == bool_)
+                .map(|(name, (f, _, _))| (name.clone(), *f))
+                .collect();
+            v.sort_by(|a, b| a.0.cmp(&b.0));
+            v
+        }
+
+        /// Declared functions and constants, by name, with the sorts
+        /// of their arguments and their sort.
+        pub fn funs(&self) -> Vec<(Atom, AST, &[AST], AST)> {
+            let mut v: Vec<_> =
+                self.funs.iter()
+                .map(|(name, (f, args, ret))| (name.clone(), *f, &args[..], *ret))
+                .collect();
+            v.sort_by(|a, b| a.0.cmp(&b.0));
+            v
+        }
+
+        /// Record that the symbols and sorts of `t` are used.
+        pub fn record_uses(&mut self, m: &crate::M, t: AST) {
+            let mut todo = vec!(t);
+            while let Some(t) = todo.pop() {
+                if !self.used.insert(t) { continue }
+                if let Some(ty) = m.ty(&t) {
+                    todo.push(ty);
+                }
+                if let AstView::App{f, args} = m.view(&t) {
+                    todo.push(*f);
+                    todo.extend_from_slice(args);
+                }
+            }
+        }
+
+        /// Declared sorts and functions that no call to `record_uses` saw,
+        /// as `("sort", name)` or `("function", name)`, by name.
+        pub fn unused(&self) -> Vec<(&'static str, Atom)> {
+            // `->` is not declared by the user
+            let sorts =
+                self.sorts.iter()
+                .filter(|(name, (s, _))| &***name != "->" && !self.used.contains(s))
+                .map(|(name, _)| ("sort", name.clone()));
+            let funs =
+                self.funs.iter()
+                .filter(|(_, (f, _, _))| !self.used.contains(f))
+                .map(|(name, _)| ("function", name.clone()));
+            let mut v: Vec<_> = sorts.chain(funs).collect();
+            v.sort_by(|a, b| a.1.cmp(&b.1));
+            v
+        }
     }
 
     impl<'a> parser::SortBuilder for AstBuilder<'a> {
@@ -34,16 +131,29 @@ mod ast_builder {
 
         fn get_bool(&self) -> AST { self.b.bool_ }
 
-        fn declare_sort(&mut self, s: Atom, arity: u8) -> AST {
+        fn declare_sort(&mut self, s: Atom, arity: u8) -> error::Result<AST> {
             debug!("declare sort {:?} arity {}", &s, arity);
-            if self.sorts.contains_key(&s) {
-                panic!("sort {:?} already declared", &s);
+            if self.syms.sorts.contains_key(&s) {
+                Err(Error::Parse{pos: None, msg: format!("sort {} is already declared", &s)})
             } else {
                 let ast = self.m.m.mk_str(&s, None);
-                self.sorts.insert(s, (ast, arity));
-                ast
+                self.syms.scoped.push_if_nonzero(Decl::Sort(s.clone()));
+                self.syms.sorts.insert(s, (ast, arity));
+                Ok(ast)
             }
         }
+
+        fn app_sort(&mut self, head: Atom, args: &[AST]) -> AST {
+            // `->` is declared on first use
+            let m = &mut self.m.m;
+            let (f, _) = *self.syms.sorts.entry(head.clone())
+                .or_insert_with(|| (m.mk_str(&head, None), 0));
+            m.mk_app(f, args, None)
+        }
+
+        fn lookup_sort(&self, s: &str) -> Option<(AST, u8)> {
+            self.syms.sorts.get(s).cloned()
+        }
     }
 
     #[derive(Clone,Debug)]
@@ -58,6 +168,12 @@ mod ast_builder {
 
         fn app_op(&mut self, op: parser::BuiltinOp, args: &[AST]) -> AST {
             use crate::parser::BuiltinOp::*;
+            // `mk_app` returns the symbol itself when there are no arguments
+            match op {
+                And if args.is_empty() => return self.b.true_,
+                Or if args.is_empty() => return self.b.false_,
+                _ => (),
+            }
             let f = match op {
                 True => self.b.true_,
                 False => self.b.false_,
@@ -71,22 +187,28 @@ mod ast_builder {
             self.m.m.mk_app(f, args, Some(self.b.bool_))
         }
 
-        fn declare_fun(&mut self, f: Atom, args: &[AST], ret: AST) -> Self::Fun {
-            if self.funs.contains_key(&f) {
-                panic!("fun {:?} already declared", &f);
+        fn declare_fun(&mut self, f: Atom, args: &[AST], ret: AST) -> error::Result<Self::Fun> {
+            if self.syms.funs.contains_key(&f) {
+                Err(Error::Parse{pos: None, msg: format!("function {} is already declared", &f)})
             } else {
                 let ty = if args.len() == 0 { Some(ret) } else { None };
+                // a new constant, even if `f` was declared in a popped scope
                 let ast = self.m.m.mk_str(&*f, ty);
                 let args = args.iter().map(|t| t.clone()).collect();
-                self.funs.insert(f, (ast, args, ret));
-                Fun {f: ast, ty_ret: ret}
+                self.syms.scoped.push_if_nonzero(Decl::Fun(f.clone()));
+                self.syms.funs.insert(f, (ast, args, ret));
+                Ok(Fun {f: ast, ty_ret: ret})
             }
         }
 
-        fn declare_cstor(&mut self, f: Atom, args: &[AST], ret: AST) -> Self::Fun {
-            let f = self.declare_fun(f, args, ret);
+        fn lookup_fun(&self, f: &str) -> Option<Self::Fun> {
+            self.syms.funs.get(f).map(|(f, _, ret)| Fun {f: *f, ty_ret: *ret})
+        }
+
+        fn declare_cstor(&mut self, f: Atom, args: &[AST], ret: AST) -> error::Result<Self::Fun> {
+            let f = self.declare_fun(f, args, ret)?;
             self.m.set_cstor(&f.f);
-            f
+            Ok(f)
         }
 
         fn ite(&mut self, a: AST, b: AST, c: AST) -> AST {
@@ -101,5 +223,22 @@ mod ast_builder {
         fn bind(&mut self, _v: Atom, t: AST) -> AST { t }
 
         fn let_(&mut self, _: &[(AST,AST)], body: AST) -> AST { body }
+
+        fn annotate(&mut self, t: AST, key: Atom, value: Option<Atom>) -> AST {
+            let m = &mut self.m.m;
+            let k = m.attr_key(&key);
+            // flags on function symbols are written on one of their applications
+            let target = match_term!(m, t, {
+                (?f; ..) if k == AttrKey::COMMUTATIVE || k == AttrKey::INJECTIVE => f,
+                _ => t,
+            });
+            let v = match value {
+                Some(v) => AttrValue::Str(v.to_string()),
+                None => AttrValue::Flag,
+            };
+            debug!("set attribute {} of {:?}", &key, target);
+            m.set_attr(target, k, v);
+            t
+        }
     }
 }
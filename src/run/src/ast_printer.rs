@@ -1,14 +1,121 @@
 
 use {
+    fxhash::FxHashSet,
     batsmt_parser as parser,
-    batsmt_pretty::{self as pp, Pretty1},
-    batsmt_core::{ast_u32::AST, },
+    batsmt_pretty::{self as pp, Pretty, Pretty1},
+    batsmt_core::{ast_u32::AST, AstView, Manager, match_term, },
     crate::ctx::{Ctx, },
 };
 
-// print a statement by mapping its terms/sorts into `PP`
+// print a statement, with its terms and sorts printed by the context
 impl Pretty1<parser::Statement<AST,AST>> for Ctx {
     fn pp1_into(&self, st: &parser::Statement<AST,AST>, out: &mut pp::Ctx) {
-        parser::pp_stmt(st, |ast,ctx| self.pp1_into(ast,ctx), |ast,ctx| self.pp1_into(ast,ctx), out)
+        st.pp_with(self).pp_into(out)
     }
 }
+
+impl Ctx {
+    /// Print `(distinct args)`, see `PpDistinct`.
+    pub(crate) fn pp_distinct_into(&self, args: &[AST], out: &mut pp::Ctx) {
+        let compact = self.pp_distinct().compact;
+        out.sexp(|out| {
+            self.pp1_into(&self.b.distinct, out);
+            for u in args.iter() {
+                // in compact mode, each argument only goes to the next line if it must
+                out.with_group_if(compact, |out| {
+                    out.space();
+                    self.pp1_into(u, out);
+                });
+            }
+        });
+    }
+
+    /// If `and(args)` is the expansion of `distinct(t1…tn)`, with `n >= 3`,
+    /// into pairwise disequations, return `t1…tn` (in order of appearance).
+    pub(crate) fn refold_distinct(&self, args: &[AST]) -> Option<Vec<AST>> {
+        let mut terms: Vec<AST> = vec!();
+        let mut pairs = FxHashSet::default();
+        for u in args.iter() {
+            let (a, b) = self.as_diseq(u)?;
+            if a == b { return None }
+            let mut idx = |t: AST| match terms.iter().position(|u| *u == t) {
+                Some(i) => i,
+                None => { terms.push(t); terms.len() - 1 },
+            };
+            let (i, j) = (idx(a), idx(b));
+            if !pairs.insert((i.min(j), i.max(j))) {
+                return None // duplicate
+            }
+        }
+        // each pair occurs once, so all of them occur
+        let n = terms.len();
+        if n >= 3 && pairs.len() == n * (n-1) / 2 { Some(terms) } else { None }
+    }
+
+    // `t` is `(not (= a b))`
+    fn as_diseq(&self, t: &AST) -> Option<(AST, AST)> {
+        match_term!(self.m, t, {
+            (self.b.not_; ?u) => match_term!(self.m, u, {
+                (self.b.eq; ?a, ?b) => Some((a, b)),
+                _ => None,
+            }),
+            _ => None,
+        })
+    }
+
+    /// Render `t` as JSON.
+    ///
+    /// Constants are `{"const": {"name": …}}`, applications are
+    /// `{"app": {"f": …, "args": […]}}`, and both carry a `"sort"` field
+    /// if they have a sort.
+    pub fn to_json(&self, t: &AST) -> String {
+        let mut out = String::new();
+        self.json_into(t, &mut out);
+        out
+    }
+
+    fn json_into(&self, t: &AST, out: &mut String) {
+        match self.m.view(t) {
+            AstView::Const(s) => {
+                out.push_str("{\"const\":{\"name\":");
+                json_string(&s.to_string(), out);
+            },
+            AstView::Index(i) => {
+                out.push_str(&format!("{{\"index\":{}}}", i));
+                return
+            },
+            AstView::App{f, args} => {
+                out.push_str("{\"app\":{\"f\":");
+                self.json_into(f, out);
+                out.push_str(",\"args\":[");
+                for (i, u) in args.iter().enumerate() {
+                    if i > 0 { out.push(',') }
+                    self.json_into(u, out);
+                }
+                out.push(']');
+            },
+        }
+        if let Some(ty) = self.m.ty(t) {
+            out.push_str(",\"sort\":");
+            self.json_into(&ty, out);
+        }
+        out.push_str("}}");
+    }
+}
+
+// `s` as a JSON string literal
+pub(crate) fn json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -1,9 +1,9 @@
 
 use {
-    batsmt_core::{ast, AstView},
+    batsmt_core::{ast, AstView, AttrKey},
     batsmt_hast::{HManager, StrSymbolManager},
     batsmt_theory::{self as theory, LitMapBuiltins},
-    batsmt_cc::{self as cc, CCView, HasConstructor, ConstructorView as CView, },
+    batsmt_cc::{self as cc, CCView, HasConstructor, ConstructorView as CView, HasInjectivity, InjectiveView, },
     batsmt_solver as solver,
     batsmt_pretty as pp,
     batsmt_tseitin::{self as tseitin, View as FView, },
@@ -29,16 +29,29 @@ pub struct Builtins {
     pub ite: AST,
 }
 
+/// How `distinct` is printed, see `Ctx::set_pp_distinct`.
+#[derive(Copy,Clone,Debug,Default,Eq,PartialEq)]
+pub struct PpDistinct {
+    /// Fill lines with the arguments, rather than putting each
+    /// argument on its own line when they do not fit on one line.
+    pub compact: bool,
+    /// Print the pairwise disequations `(and (not (= a b)) (not (= a c)) (not (= b c)))`
+    /// as `(distinct a b c)`.
+    pub refold: bool,
+}
+
 /// The main context.
 pub struct Ctx {
     pub m: M,
     pub lmb: LitMapBuiltins,
     pub b: Builtins,
     cstor: BitSet,
+    infix: BitSet,
+    pp_distinct: PpDistinct,
 }
 
 pub mod ctx {
-    use {super::*, batsmt_core::Manager};
+    use {super::*, batsmt_core::{Manager, gc::GC}};
 
     impl Ctx {
         /// New context.
@@ -46,7 +59,30 @@ pub mod ctx {
             let mut m = HManager::new();
             let b = Builtins::new(&mut m);
             let lmb = b.clone().into();
-            Ctx {m, b, lmb, cstor: BitSet::new(), }
+            Ctx {
+                m, b, lmb, cstor: BitSet::new(), infix: BitSet::new(),
+                pp_distinct: PpDistinct::default(),
+            }
+        }
+
+        /// Forget every term but the builtins, as if the context was new.
+        ///
+        /// The manager is collected rather than rebuilt, so its memory is
+        /// reused by the next terms. Terms built before (other than builtins)
+        /// must not be used afterwards. Returns the number of collected terms.
+        pub fn reset(&mut self) -> usize {
+            let Builtins{bool_, true_, false_, not_, eq, distinct, and_, or_, imply_, ite} = self.b;
+            for t in &[bool_, true_, false_, not_, eq, distinct, and_, or_, imply_, ite] {
+                self.m.mark_root(t);
+            }
+            let n = self.m.collect();
+            if let Some(attrs) = self.m.attrs_mut() {
+                attrs.retain(|_| false); // e.g. `:commutative` on a builtin
+            }
+            self.cstor.clear();
+            self.infix.clear();
+            self.pp_distinct = PpDistinct::default();
+            n
         }
 
         pub fn is_cstor(&self, t: &AST) -> bool { self.cstor.contains(t.idx() as usize) }
@@ -54,6 +90,20 @@ pub mod ctx {
             self.cstor.insert(t.idx() as usize);
         }
 
+        /// Is `f` printed infix when applied to two arguments?
+        pub fn is_infix(&self, f: &AST) -> bool { self.infix.contains(f.idx() as usize) }
+
+        /// Print binary applications of `f` as `(a f b)` rather than `(f a b)`.
+        pub fn set_infix(&mut self, f: &AST) {
+            self.infix.insert(f.idx() as usize);
+        }
+
+        /// How `distinct` is printed.
+        pub fn pp_distinct(&self) -> PpDistinct { self.pp_distinct }
+
+        /// Change how `distinct` is printed. This only affects printing.
+        pub fn set_pp_distinct(&mut self, o: PpDistinct) { self.pp_distinct = o }
+
         /// Copy of builtins
         pub fn builtins<U>(&self) -> U
             where Builtins: Into<U>
@@ -148,7 +198,43 @@ pub mod ctx {
 
     impl pp::Pretty1<AST> for Ctx {
         fn pp1_into(&self, t: &AST, ctx: &mut pp::Ctx) {
-            ast::pp_ast(self, t, &mut |s,ctx| { ctx.display(s); }, ctx);
+            match self.m.view(t) {
+                AstView::App{f, args} if args.len() == 2 && self.is_infix(f) => {
+                    ctx.sexp(|ctx| {
+                        self.pp1_into(&args[0], ctx);
+                        ctx.space();
+                        self.pp1_into(f, ctx);
+                        ctx.space();
+                        self.pp1_into(&args[1], ctx);
+                    });
+                },
+                AstView::App{f, args} if args.len() > 0 => {
+                    let refolded =
+                        if *f == self.b.and_ && self.pp_distinct.refold { self.refold_distinct(args) }
+                        else { None };
+                    match refolded {
+                        Some(args) => self.pp_distinct_into(&args, ctx),
+                        None if *f == self.b.distinct => self.pp_distinct_into(args, ctx),
+                        None => {
+                            ctx.sexp(|ctx| {
+                                self.pp1_into(f, ctx);
+                                for u in args.iter() {
+                                    ctx.space();
+                                    self.pp1_into(u, ctx);
+                                }
+                            });
+                        },
+                    }
+                },
+                _ => {
+                    // leaves are printed (with their ID) by `pp_ast`
+                    ast::pp_ast(self, t, &mut |s,ctx| { ctx.display(s); }, ctx);
+                    return
+                },
+            }
+            if ctx.alternate() {
+                ctx.string(format!("/{}", t.idx())); // print unique ID
+            }
         }
     }
 
@@ -191,6 +277,10 @@ pub mod ctx {
                 }
             }
         }
+
+        fn is_commutative_term(&self, f: &AST) -> bool {
+            self.m.has_attr(f, AttrKey::COMMUTATIVE)
+        }
     }
 
     impl cc::HasIte<AST> for Ctx {
@@ -225,10 +315,23 @@ pub mod ctx {
             }
         }
     }
+
+    impl HasInjectivity<AST> for Ctx {
+        type F = AST;
+
+        fn view_as_injective<'a>(&'a self, t: &'a AST) -> InjectiveView<'a, Self::F, AST> {
+            match self.m.view(t) {
+                AstView::App {f, args} if self.m.has_attr(f, AttrKey::INJECTIVE) => {
+                    InjectiveView::AppInjective(f, args)
+                },
+                _ => InjectiveView::Other(t),
+            }
+        }
+    }
 }
 
 mod builtins {
-    use super::*;
+    use {super::*, batsmt_core::Manager};
 
     impl Builtins {
         /// New builtins structure.
@@ -247,6 +350,42 @@ mod builtins {
                 distinct: m.mk_str("distinct", None),
             }
         }
+
+        /// Check that builtins are wired as expected in `m`.
+        ///
+        /// `true_` and `false_` must be distinct constants of sort `bool_`.
+        /// Symbols (`not_`, `eq`, …) must be distinct unsorted constants;
+        /// their arity is only known from their applications, so it is not checked.
+        pub fn validate_types(&self, m: &M) -> Result<(), String> {
+            let is_sym = |t: &AST| match m.view(t) {
+                AstView::Const(_) => m.ty(t).is_none(),
+                _ => false,
+            };
+            if !is_sym(&self.bool_) {
+                return Err(format!("Bool: {} is not an unsorted constant", pp::pp1(m, &self.bool_)))
+            }
+            for (name, t) in &[("true", self.true_), ("false", self.false_)] {
+                match m.view(t) {
+                    AstView::Const(_) if m.ty(t) == Some(self.bool_) => (),
+                    _ => return Err(format!("{}: {} is not a boolean constant", name, pp::pp1(m, t))),
+                }
+            }
+            if self.true_ == self.false_ {
+                return Err("true and false are the same term".to_string())
+            }
+            let syms = [
+                ("not", self.not_), ("=", self.eq), ("distinct", self.distinct),
+                ("and", self.and_), ("or", self.or_), ("=>", self.imply_), ("ite", self.ite),
+            ];
+            for (i, (name, f)) in syms.iter().enumerate() {
+                if !is_sym(f) {
+                    return Err(format!("{}: {} is not a function symbol", name, pp::pp1(m, f)))
+                } else if let Some((name2, _)) = syms[..i].iter().find(|(_, g)| g == f) {
+                    return Err(format!("{} and {} are the same symbol", name2, name))
+                }
+            }
+            Ok(())
+        }
     }
 
     impl Into<LitMapBuiltins> for Builtins {
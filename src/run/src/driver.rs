@@ -0,0 +1,1015 @@
+
+//! Library driver.
+//!
+//! The `Driver` owns everything needed to process SMT-LIB statements
+//! (context, symbols, preprocessor, solver with its Tseitin transformation)
+//! and answers each statement with a `Response`. The `batsmt-run` binary
+//! is a thin wrapper around it.
+
+use {
+    std::{fmt, io, fs, path::PathBuf, time::Instant, },
+    batsmt_core::{
+        ast_u32::AST, ast::algo, gc::HasInternalMemory, error::{self, Error},
+        mem::{MemTree, Bytes}, Manager, AttrKey,
+    },
+    batsmt_cc as cc,
+    batsmt_parser::{self as parser, Statement, Span},
+    batsmt_solver::{self as solver, Res, ReasonUnknown, RestartPolicy, BLit, CancelToken, ClauseKind, solver::lbool, },
+    batsmt_theory::LitMap,
+    batsmt_pretty as pp,
+    crate::{
+        Ctx,
+        ast_builder::{AstBuilder, Symbols},
+        options::{Options, OptError},
+        output::ResponseWriter,
+        preprocess::Preprocessor,
+        model::{Model, ModelBuilder, Eval, Value},
+        encoding_stats::{self, AssertionStats},
+        sat_dump, validate,
+    },
+};
+
+/// The theory used by the driver.
+pub type Th = cc::CCTheory<Ctx, (
+    cc::theories::Ite, cc::theories::Constructor<AST>, cc::theories::Injectivity<AST>,
+)>;
+
+/// Configuration of a `Driver`.
+#[derive(Clone,Debug)]
+pub struct DriverConfig {
+    /// Enable theory propagation.
+    ///
+    /// Without it, the theory only detects conflicts, and the solver relies
+    /// on decisions instead; this isolates the cost of the congruence
+    /// fixpoint (`theory_propagations` stays at 0 in `Driver::stats`).
+    pub propagate: bool,
+    /// Bound on the number of learnt clauses (see `Solver::set_max_learnts`).
+    pub max_learnts: Option<usize>,
+    /// Restart policy of the SAT solver.
+    pub restarts: RestartPolicy,
+    /// Track which assertions are used to prove unsat (see `Driver::unsat_assertions`).
+    pub explain_unsat: bool,
+    /// Accept non-standard commands, such as `(get-next-model)`
+    /// or `(assert-lemma t)`.
+    pub lenient: bool,
+    /// Answer `unsat` as usual even if lemmas were injected with `(assert-lemma t)`.
+    ///
+    /// By default, such answers are `Response::UnsatWithLemmas`.
+    pub trust_lemmas: bool,
+    /// Eliminate top-level equalities before the first `check-sat`
+    /// (see `Preprocessor`). Ignored if `explain_unsat` is set.
+    pub preprocess: bool,
+    /// After each `sat` answer, dump the state of the theory into this file
+    /// (see `Driver::write_sat_dump`).
+    pub debug_sat_dump: Option<PathBuf>,
+    /// At each conflict of the theory, append its equality graph to this file
+    /// (see `CC::dump_egraph`), as JSON if the file name ends in `.json`,
+    /// in the DOT format otherwise.
+    pub dump_egraph: Option<PathBuf>,
+    /// Record the rewrites of the simplifier (see `Driver::write_simp_trace`).
+    #[cfg(feature="simp-trace")]
+    pub trace_simp: bool,
+    /// Answer `unknown` (`resourceout`) to commands that run the solver,
+    /// once the estimated memory usage exceeds this number of bytes
+    /// (see `Driver::memory_report`).
+    pub memory_limit: Option<usize>,
+    /// Record the size of the encoding of each assertion (see `Driver::encoding_stats`).
+    pub stats_encoding: bool,
+    /// Record which declared symbols are used (see `Driver::unused_symbols`).
+    pub warn_unused: bool,
+    /// After a `sat` answer, do not clausify new assertions that already
+    /// hold in the model.
+    ///
+    /// Such assertions are deferred: each later `sat` answer evaluates them
+    /// in its model, and clausifies those that do not hold before solving again.
+    pub skip_satisfied: bool,
+    /// Check statements before executing them (see `Driver::validate`).
+    ///
+    /// `execute_script` then stops before a batch of statements (see
+    /// `Driver::parse_batch`) where some statement is ill-formed.
+    pub validate: bool,
+}
+
+/// Answer of the driver to a statement.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum Response {
+    /// The statement was processed, there is nothing to report.
+    Success,
+    Sat,
+    Unsat,
+    /// `unsat`, but lemmas were injected with `(assert-lemma t)`, so it might
+    /// not hold for the assertions alone (see `DriverConfig::trust_lemmas`).
+    UnsatWithLemmas,
+    Unknown(ReasonUnknown),
+    /// Value of the declared symbols, and of named boolean terms, after `get-model`.
+    Model(Model),
+    /// Value of each term of `get-value`, as `(term, value)`.
+    Values(Vec<(String, Value)>),
+    /// Assumptions in the unsat core, after `get-unsat-core`.
+    Core(Vec<String>),
+    /// Answer to `get-info`, as `(keyword, value)`.
+    Info(String, String),
+    /// The statement, or option, is not supported.
+    Unsupported,
+    /// The statement failed, with the given message (on a single line).
+    Error(String),
+}
+
+/// Processes statements, one at a time.
+pub struct Driver {
+    cfg: DriverConfig,
+    cancel: Option<CancelToken>,
+    c: Ctx,
+    syms: Symbols,
+    solver: solver::Solver<Ctx, Th>,
+    opts: Options,
+    explain_unsat: bool,
+    lenient: bool,
+    pre: Preprocessor,
+    pending: Option<Vec<(AST, usize)>>, // assertions waiting for preprocessing, if it did not run yet
+    assertions: Vec<AST>,
+    lemmas: Vec<AST>, // injected by `assert-lemma`; not assertions, never in cores
+    spans: Vec<Option<Span>>, // location of each assertion
+    reason_unknown: Option<ReasonUnknown>, // reason for the last `unknown` answer
+    last_res: Option<Res>, // result of the last `check-sat`
+    last_assumptions: Vec<(AST, BLit)>, // assumptions of the last `check-sat`
+    error_origin: Option<String>, // assertion or assumption the last solver error comes from
+    sat_dump: Option<PathBuf>,
+    n_sat_dumps: usize,
+    memory_limit: Option<usize>,
+    enc_stats: Option<Vec<AssertionStats>>, // size of the encoding of each assertion
+    warn_unused: bool,
+    deferred: Vec<(AST, usize)>, // assertions not clausified yet, see `DriverConfig::skip_satisfied`
+    sat_model: Option<Model>, // model of the last `sat` answer, if `skip_satisfied`
+    exited: bool,
+}
+
+impl Default for DriverConfig {
+    fn default() -> Self {
+        DriverConfig {
+            propagate: true, max_learnts: None, restarts: RestartPolicy::default(),
+            explain_unsat: false,
+            lenient: false,
+            trust_lemmas: false,
+            preprocess: true,
+            debug_sat_dump: None,
+            dump_egraph: None,
+            #[cfg(feature="simp-trace")]
+            trace_simp: false,
+            memory_limit: None,
+            stats_encoding: false,
+            warn_unused: false,
+            skip_satisfied: false,
+            validate: true,
+        }
+    }
+}
+
+impl Driver {
+    /// New driver, with an empty context.
+    pub fn new(cfg: DriverConfig) -> Self {
+        Driver::with_ctx(cfg, Ctx::new())
+    }
+
+    // new driver, whose declarations live in `c`
+    fn with_ctx(cfg: DriverConfig, mut c: Ctx) -> Self {
+        let th: Th = cc::CCTheory::new(&mut c);
+        let mut solver = solver::Solver::new(c.builtins(), th);
+        solver.enable_th_propagation(cfg.propagate);
+        solver.set_restart_policy(cfg.restarts);
+        if let Some(n) = cfg.max_learnts {
+            solver.set_max_learnts(n);
+        }
+        if cfg.debug_sat_dump.is_some() || cfg.skip_satisfied {
+            solver.theory_mut().set_record_final_state(true);
+        }
+        if let Some(path) = &cfg.dump_egraph {
+            let format = match path.extension() {
+                Some(ext) if ext == "json" => cc::EgraphFormat::Json,
+                _ => cc::EgraphFormat::Dot,
+            };
+            match fs::OpenOptions::new().create(true).append(true).open(path) {
+                Ok(f) => solver.theory_mut().set_egraph_dump(Box::new(io::BufWriter::new(f)), format),
+                Err(e) => error!("cannot open {:?} to dump egraphs: {}", path, e),
+            }
+        }
+
+        #[cfg(feature="simp-trace")]
+        solver.tseitin_mut().set_simp_trace(cfg.trace_simp);
+        Driver {
+            c, solver,
+            syms: Symbols::new(),
+            opts: Options::new(),
+            explain_unsat: cfg.explain_unsat,
+            lenient: cfg.lenient,
+            pre: Preprocessor::new(),
+            pending: if cfg.preprocess && !cfg.explain_unsat { Some(vec!()) } else { None },
+            assertions: vec!(),
+            lemmas: vec!(),
+            spans: vec!(),
+            reason_unknown: None,
+            last_res: None,
+            last_assumptions: vec!(),
+            error_origin: None,
+            sat_dump: cfg.debug_sat_dump.clone(),
+            n_sat_dumps: 0,
+            memory_limit: cfg.memory_limit,
+            enc_stats: if cfg.stats_encoding { Some(vec!()) } else { None },
+            warn_unused: cfg.warn_unused,
+            deferred: vec!(),
+            sat_model: None,
+            exited: false,
+            cancel: None,
+            cfg,
+        }
+    }
+
+    /// Access the context, e.g. to print terms.
+    pub fn ctx(&self) -> &Ctx { &self.c }
+
+    /// Mutable access to the context, e.g. to register infix symbols.
+    pub fn ctx_mut(&mut self) -> &mut Ctx { &mut self.c }
+
+    /// Access the solver, e.g. for statistics.
+    pub fn solver(&self) -> &solver::Solver<Ctx, Th> { &self.solver }
+
+    /// Mutable access to the solver, e.g. to configure it.
+    ///
+    /// The configuration is lost on `(reset)` and `(reset-assertions)`.
+    pub fn solver_mut(&mut self) -> &mut solver::Solver<Ctx, Th> { &mut self.solver }
+
+    /// Access the preprocessor, e.g. for its substitution.
+    pub fn preprocessor(&self) -> &Preprocessor { &self.pre }
+
+    /// Interrupt `check-sat` when `tok` is canceled, e.g. by a watchdog thread.
+    ///
+    /// The interrupted `check-sat` answers `unknown`, with reason `canceled`.
+    pub fn set_cancel_token(&mut self, tok: CancelToken) {
+        self.solver.set_cancel_token(tok.clone());
+        self.cancel = Some(tok);
+    }
+
+    /// Forget everything, as `(reset)`: as if the driver was just created
+    /// with the same configuration.
+    ///
+    /// Declarations, assertions, and options are lost; the cancel token is kept.
+    /// The terms of the manager are collected (see `Ctx::reset`), so that
+    /// its memory is reused: terms obtained before must not be used anymore,
+    /// including statements parsed but not executed yet.
+    pub fn reset(&mut self) {
+        debug!("reset driver");
+        // the placeholder is dropped right away, with the old solver
+        let mut c = std::mem::replace(&mut self.c, Ctx::new());
+        let n = c.reset();
+        debug!("reset: collected {} terms", n);
+        // the solver is rebuilt below, but nothing must see the collected terms
+        self.solver.tseitin_mut().on_gc();
+        self.rebuild(c);
+    }
+
+    /// Forget assertions, as `(reset-assertions)`.
+    ///
+    /// Declarations and options are kept, as well as terms: statements
+    /// parsed before can still be executed. The solver starts from scratch.
+    pub fn reset_assertions(&mut self) {
+        debug!("reset assertions");
+        let c = std::mem::replace(&mut self.c, Ctx::new());
+        let syms = std::mem::replace(&mut self.syms, Symbols::new());
+        let opts = self.opts.clone();
+        self.rebuild(c);
+        self.syms = syms;
+        if opts.produce_models {
+            self.solver.theory_mut().set_record_final_state(true);
+        }
+        self.opts = opts;
+    }
+
+    // replace everything but the configuration and cancel token
+    fn rebuild(&mut self, c: Ctx) {
+        let cancel = self.cancel.take();
+        *self = Driver::with_ctx(self.cfg.clone(), c);
+        if let Some(tok) = cancel {
+            self.set_cancel_token(tok);
+        }
+    }
+
+    /// Write the theory's view of the current model into `out`.
+    ///
+    /// Each theory atom comes with its value, the representative of its
+    /// sides, and the explanation if the theory propagated it; atoms whose
+    /// value contradicts the congruence closure are marked `:inconsistent`.
+    /// Then the classes of the congruence closure follow.
+    /// The theory state is only recorded if `debug_sat_dump` is set.
+    pub fn write_sat_dump<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        sat_dump::write(&self.c, &self.solver, self.solver.theory().final_state(), out)
+    }
+
+    /// Write the rewrites done by the simplifier into `out`, one per line, then check them.
+    ///
+    /// The result of the check is written as a comment; an invalid step
+    /// is also reported as an internal error.
+    /// Nothing is recorded unless `trace_simp` is set.
+    #[cfg(feature="simp-trace")]
+    pub fn write_simp_trace<W: io::Write>(&mut self, out: &mut W) -> error::Result<()> {
+        let Driver{c, solver, ..} = self;
+        let tr = match solver.tseitin().simp_trace() {
+            Some(tr) => tr,
+            None => return Ok(()),
+        };
+        write!(out, "{}", pp::pp1(tr, c))?;
+        match tr.check(c) {
+            Ok(n_unchecked) => {
+                writeln!(out, "; {} steps checked, {} too large to check", tr.len(), n_unchecked)?;
+                Ok(())
+            },
+            Err(e) => {
+                writeln!(out, "; {}", e)?;
+                Err(Error::Internal{msg: e.to_string()})
+            },
+        }
+    }
+
+    /// Estimated memory usage, by component: the AST manager, the Tseitin
+    /// transformation, the preprocessor, and the solver (see `Solver::memory_report`).
+    pub fn memory_report(&self) -> MemTree {
+        memory_report(&self.c, &self.pre, &self.solver)
+    }
+
+    /// Size of the encoding of each assertion so far, worst offenders first.
+    ///
+    /// Empty unless `DriverConfig::stats_encoding` is set. Assertions are
+    /// only encoded by `check-sat` if preprocessing is enabled.
+    pub fn encoding_stats(&self) -> Vec<AssertionStats> {
+        let mut v = match &self.enc_stats {
+            Some(v) => v.clone(),
+            None => return vec!(),
+        };
+        // assertions dropped by preprocessing, or not encoded yet
+        while v.len() < self.assertions.len() {
+            v.push(AssertionStats::new(v.len()));
+        }
+        for st in v.iter_mut() {
+            st.name = self.c.m.get_attr(&self.assertions[st.idx], AttrKey::NAMED).map(|n| n.to_string());
+        }
+        encoding_stats::sort(&mut v);
+        v
+    }
+
+    /// Declared sorts and functions that appear in no assertion nor
+    /// assumption so far, as `("sort", name)` or `("function", name)`.
+    ///
+    /// Often a typo in the input. Empty unless `DriverConfig::warn_unused` is set.
+    pub fn unused_symbols(&self) -> Vec<(&'static str, String)> {
+        if !self.warn_unused {
+            return vec!()
+        }
+        self.syms.unused().into_iter().map(|(kind, name)| (kind, name.to_string())).collect()
+    }
+
+    /// Open a scope for declarations, closed by `pop_scope`.
+    ///
+    /// Only declarations are scoped: assertions made in the scope remain
+    /// after it is popped.
+    pub fn push_scope(&mut self) {
+        self.syms.push_scope();
+    }
+
+    /// Forget the sorts and functions declared since the last `push_scope`,
+    /// so that their names can be declared again, possibly with other sorts.
+    ///
+    /// A name declared again denotes a new symbol, distinct from the one
+    /// of the popped scope. Returns `false`, doing nothing, if there is no scope.
+    pub fn pop_scope(&mut self) -> bool {
+        if self.syms.n_scopes() == 0 {
+            return false
+        }
+        self.syms.pop_scope();
+        true
+    }
+
+    /// Has `(exit)` been executed?
+    pub fn exited(&self) -> bool { self.exited }
+
+    /// Parse statements from `r`, in the driver's context.
+    ///
+    /// Symbols declared in previous calls remain visible, and cannot be
+    /// declared again.
+    pub fn parse<R: io::Read>(&mut self, r: R) -> parser::Result<Vec<Statement<AST,AST>>> {
+        let mut builder = AstBuilder::new(&mut self.c, &mut self.syms);
+        parser::parse(&mut builder, r)
+    }
+
+    /// Same as `parse`, but also returns the location of each statement.
+    pub fn parse_with_spans<R: io::Read>(
+        &mut self, r: R
+    ) -> parser::Result<Vec<(Span, Statement<AST,AST>)>> {
+        let mut builder = AstBuilder::new(&mut self.c, &mut self.syms);
+        parser::parse_with_spans(&mut builder, r)
+    }
+
+    /// Parse the next statements of `input`, up to `(reset)`, in the driver's context.
+    ///
+    /// The statements must be executed before the next batch is parsed,
+    /// because `(reset)` forgets declarations and collects terms.
+    /// Returns `None` once the input is consumed.
+    pub fn parse_batch<R: io::Read>(
+        &mut self, input: &mut parser::Batches<R>
+    ) -> parser::Result<Option<Vec<(Span, Statement<AST,AST>)>>> {
+        let mut builder = AstBuilder::new(&mut self.c, &mut self.syms);
+        input.next_with_spans(&mut builder)
+    }
+
+    /// Check the terms of `stmts`, before executing them.
+    ///
+    /// Finds functions applied to the wrong number of arguments, `=`
+    /// and `distinct` with fewer than two arguments, and `ite` whose
+    /// condition is not a boolean. All the problems are returned, each
+    /// located by its statement. Empty unless `DriverConfig::validate` is set.
+    pub fn validate(&self, stmts: &[(Span, Statement<AST,AST>)]) -> Vec<Error> {
+        if !self.cfg.validate {
+            return vec!()
+        }
+        validate::check(&self.c, &self.syms, stmts)
+    }
+
+    /// Parse and execute all the statements from `r`, until `(exit)`.
+    ///
+    /// Statements are parsed up to each `(reset)`, and executed before
+    /// parsing the following ones (see `parse_batch`).
+    /// If parsing fails, nothing more is executed and the last response is the error.
+    /// Likewise if validation fails (see `validate`), with one response per problem.
+    pub fn execute_script<R: io::Read>(&mut self, r: R) -> Vec<Response> {
+        let mut input = parser::Batches::new(r);
+        let mut res = vec!();
+        while !self.exited {
+            let stmts = match self.parse_batch(&mut input) {
+                Ok(Some(stmts)) => stmts,
+                Ok(None) => break,
+                Err(e) => {
+                    res.push(Response::Error(Error::from(e).to_string()));
+                    break
+                },
+            };
+            let errs = self.validate(&stmts);
+            if !errs.is_empty() {
+                res.extend(errs.iter().map(|e| Response::Error(e.to_string())));
+                break
+            }
+            for (span, st) in stmts {
+                if self.exited { break }
+                res.push(self.execute_at(st, Some(span)));
+            }
+        }
+        res
+    }
+
+    /// Execute `stmts` until `(exit)`, and report each response to `w`.
+    ///
+    /// Answers to `check-sat` come with the solver's statistics, and, if
+    /// `DriverConfig::explain_unsat` is set, the assertions used to prove `unsat`.
+    pub fn execute_all<I, W>(&mut self, stmts: I, w: &mut W) -> io::Result<()>
+        where I: IntoIterator<Item=(Span, Statement<AST,AST>)>,
+              W: ResponseWriter + ?Sized
+    {
+        for (span, st) in stmts {
+            if self.exited { break }
+            let cmd = st.name();
+            let runs_solver = match st {
+                Statement::CheckSat | Statement::CheckSatAssumptions(..)
+                    | Statement::GetNextModel => true,
+                _ => false,
+            };
+            let start = Instant::now();
+            let r = self.execute_at(st, Some(span));
+            let time = start.elapsed();
+            let stats = if runs_solver { Some(self.stats()) } else { None };
+            w.response(cmd, &r, time, stats.as_ref().map(|v| &v[..]))?;
+            if r == Response::Unsat || r == Response::UnsatWithLemmas {
+                for i in self.unsat_assertions() {
+                    let (t, span) = self.assertion(i);
+                    w.used_assertion(i, span.as_ref(), &pp::pp1(&self.c, &t).to_string())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Counters of the solver, since the driver was created.
+    ///
+    /// With the `metrics` feature, this includes the time spent in
+    /// the congruence closure (see `batsmt_cc::Stats::counters`).
+    pub fn stats(&self) -> Vec<(&'static str, u64)> {
+        let s = &self.solver;
+        let th = s.th_stats();
+        let mut v = vec![
+            ("conflicts", s.n_conflicts() as u64),
+            ("decisions", s.n_decisions() as u64),
+            ("propagations", s.n_props() as u64),
+            ("restarts", s.n_restarts() as u64),
+            ("learnts", s.n_learnts() as u64),
+            ("theory_conflicts", th.conflicts),
+            ("theory_propagations", th.propagations),
+            ("theory_lemmas", th.lemmas),
+        ];
+        v.extend(s.theory().stats().counters());
+        v
+    }
+
+    /// Time spent in the congruence closure, by region (see `batsmt_cc::Stats`).
+    #[cfg(feature="metrics")]
+    pub fn cc_stats(&self) -> &cc::Stats { self.solver.theory().stats() }
+
+    /// Execute a single statement.
+    pub fn execute(&mut self, st: Statement<AST,AST>) -> Response {
+        self.execute_at(st, None)
+    }
+
+    /// Execute a single statement, found at `span` in the input.
+    ///
+    /// Failures are turned into `Response::Error`, see `try_execute_at`.
+    pub fn execute_at(&mut self, st: Statement<AST,AST>, span: Option<Span>) -> Response {
+        match self.try_execute_at(st, span) {
+            Ok(r) => r,
+            Err(e) => {
+                debug!("statement failed: {}", e);
+                // locate the error, preferably by the assertion it comes from
+                match (self.error_origin.take(), span) {
+                    (Some(origin), _) => Response::Error(format!("{}, in {}", e, origin)),
+                    (None, Some(span)) if e.pos().is_none() => {
+                        Response::Error(format!("{} ({})", e, span))
+                    },
+                    (None, _) => Response::Error(e.to_string()),
+                }
+            },
+        }
+    }
+
+    /// Execute a single statement, found at `span` in the input.
+    ///
+    /// A statement that fails has no effect, except for `check-sat`
+    /// which forgets the previous answer.
+    pub fn try_execute_at(
+        &mut self, st: Statement<AST,AST>, span: Option<Span>
+    ) -> error::Result<Response> {
+        debug!("execute statement {}", pp::pp1(&self.c, &st));
+        self.error_origin = None;
+        match st {
+            Statement::CheckSat | Statement::CheckSatAssumptions(..) => {
+                self.last_res = None;
+                self.sat_model = None;
+                self.reason_unknown = None;
+                self.flush_pending()?
+            },
+            _ => (),
+        }
+        let limit = self.memory_limit;
+        let Driver{c, solver, opts, pre, ..} = self;
+
+        if self.warn_unused {
+            match &st {
+                Statement::Assert(t) | Statement::AssertLemma(t) => self.syms.record_uses(&c.m, *t),
+                Statement::CheckSatAssumptions(v) => {
+                    for t in v { self.syms.record_uses(&c.m, *t) }
+                },
+                _ => (),
+            }
+        }
+
+        let r = match st {
+            Statement::Assert(t) => {
+                let idx = self.assertions.len();
+                if let Some(pending) = &mut self.pending {
+                    solver.tseitin_mut().check_formula(c, &t)?;
+                    pending.push((t, idx));
+                } else {
+                    let u = pre.rewrite(c, t);
+                    let holds = match &self.sat_model {
+                        Some(model) => Eval::new(model).eval_formula(c, &u) == Some(true),
+                        None => false,
+                    };
+                    if holds {
+                        debug!("assertion {} holds in the current model, defer it", idx);
+                        self.deferred.push((u, idx));
+                    } else {
+                        self.add_assertion(u, Some(idx))?;
+                    }
+                }
+                self.assertions.push(t);
+                self.spans.push(span);
+                Response::Success
+            },
+            Statement::AssertLemma(_) if !self.lenient => Response::Unsupported,
+            Statement::AssertLemma(t) => {
+                if self.pending.is_some() {
+                    // added after the assertions, once they are preprocessed
+                    solver.tseitin_mut().check_formula(c, &t)?;
+                } else {
+                    let u = pre.rewrite(c, t);
+                    self.add_lemma(u)?;
+                }
+                self.lemmas.push(t);
+                Response::Success
+            },
+            Statement::SetOption(key, value) => {
+                match opts.set(&key, &value) {
+                    Ok(()) => {
+                        // models are built from the final state of the congruence closure
+                        solver.theory_mut().set_record_final_state(
+                            opts.produce_models || self.sat_dump.is_some() || self.cfg.skip_satisfied);
+                        Response::Success
+                    },
+                    Err(OptError::Unsupported) => Response::Unsupported,
+                    Err(OptError::BadValue(msg)) => Response::Error(msg),
+                }
+            },
+            Statement::CheckSat => {
+                solver.tseitin_mut().reclaim_unused_memory();
+                let r = if out_of_memory(limit, c, pre, solver) {
+                    Ok(Res::Unknown(ReasonUnknown::ResourceOut))
+                } else {
+                    solver.solve(c)
+                };
+                self.last_assumptions.clear();
+                let r = self.check_res(r, &[])?;
+                let r = self.check_deferred(r, |c, solver| solver.solve(c))?;
+                self.set_res(r)?
+            },
+            Statement::CheckSatAssumptions(v) => {
+                solver.tseitin_mut().reclaim_unused_memory();
+                // map assumptions to literals
+                let lits: Vec<_> =
+                    v.iter().map(|t| {
+                        let t = pre.rewrite(c, *t);
+                        solver.new_term_lit(c, t).0
+                    }).collect();
+                let r = if out_of_memory(limit, c, pre, solver) {
+                    Ok(Res::Unknown(ReasonUnknown::ResourceOut))
+                } else {
+                    solver.solve_with(c, &lits[..])
+                };
+                self.last_assumptions.clear();
+                if opts.produce_unsat_cores {
+                    self.last_assumptions.extend(
+                        v.iter().cloned().zip(lits.iter().map(|l| BLit(*l))));
+                }
+                let r = self.check_res(r, &v)?;
+                let r = self.check_deferred(r, |c, solver| solver.solve_with(c, &lits[..]))?;
+                self.set_res(r)?
+            },
+            Statement::GetModel => {
+                if !opts.produce_models {
+                    Response::Error("model generation is not enabled".to_string())
+                } else if self.last_res != Some(Res::SAT) {
+                    Response::Error("no model available".to_string())
+                } else {
+                    Response::Model(build_model(c, &self.syms, pre, solver))
+                }
+            },
+            Statement::GetValue(v) => {
+                if !opts.produce_models {
+                    Response::Error("model generation is not enabled".to_string())
+                } else if self.last_res != Some(Res::SAT) {
+                    Response::Error("no model available".to_string())
+                } else {
+                    let model = build_model(c, &self.syms, pre, solver);
+                    let mut ev = Eval::new(&model);
+                    let mut values = vec!();
+                    for t in v {
+                        let u = pre.rewrite(c, t);
+                        // a term congruent to `u` in the model has its value;
+                        // otherwise `u` gets the default values of the model
+                        let r = solver.theory_mut().eval_new_term(c, u);
+                        let name = pp::pp1(&*c, &t).to_string();
+                        match ev.eval_term(c, &r).or_else(|| ev.eval_term(c, &u)) {
+                            Some(val) => values.push((name, val)),
+                            None => return Ok(Response::Error(format!("cannot evaluate {}", name))),
+                        }
+                    }
+                    Response::Values(values)
+                }
+            },
+            Statement::GetNextModel if !self.lenient => Response::Unsupported,
+            Statement::GetNextModel => {
+                if self.last_res != Some(Res::SAT) {
+                    Response::Error("no model available".to_string())
+                } else {
+                    // look for a model that differs on the boolean constants
+                    let mut consts: Vec<AST> =
+                        self.syms.bool_consts(c.b.bool_).into_iter()
+                        .map(|(_, t)| pre.repr(&t))
+                        .filter(|t| *t != c.b.true_ && *t != c.b.false_)
+                        .collect();
+                    consts.sort_unstable();
+                    consts.dedup();
+                    let r = if out_of_memory(limit, c, pre, solver) {
+                        Ok(Res::Unknown(ReasonUnknown::ResourceOut))
+                    } else {
+                        solver.next_model(c, &consts)
+                    };
+                    self.last_assumptions.clear();
+                    self.last_res = None;
+                    self.sat_model = None;
+                    let r = self.check_res(r, &[])?;
+                    let r = self.check_deferred(r, |c, solver| solver.solve(c))?;
+                    self.set_res(r)?
+                }
+            },
+            Statement::GetUnsatCore => {
+                if !opts.produce_unsat_cores {
+                    Response::Error("unsat core generation is not enabled".to_string())
+                } else if self.last_res != Some(Res::UNSAT) {
+                    Response::Error("no unsat core available".to_string())
+                } else {
+                    let core =
+                        self.last_assumptions.iter()
+                        .filter(|(_, lit)| solver.unsat_core_contains_lit(lit.0))
+                        .map(|(t, _)| pp::pp1(&*c, t).to_string())
+                        .collect();
+                    Response::Core(core)
+                }
+            },
+            Statement::GetInfo(ref a) if &**a == ":reason-unknown" => {
+                match self.reason_unknown {
+                    Some(r) => Response::Info(a.to_string(), r.to_string()),
+                    None => Response::Error("no unknown result to explain".to_string()),
+                }
+            },
+            Statement::GetInfo(_) => Response::Unsupported,
+            Statement::ResetAssertions => {
+                self.reset_assertions();
+                Response::Success
+            },
+            Statement::Reset => {
+                self.reset();
+                Response::Success
+            },
+            Statement::Exit => {
+                self.exited = true;
+                Response::Success
+            },
+            Statement::SetInfo(..) | Statement::SetLogic(..)
+                | Statement::DeclareSort(..) | Statement::DeclareFun(..)
+                | Statement::DeclarePolyFun(..) => Response::Success,
+        };
+        Ok(r)
+    }
+
+    /// Assertions used to prove the last `unsat` answer, by index.
+    ///
+    /// Requires `DriverConfig::explain_unsat`. The set is an over-approximation,
+    /// see `Solver::unsat_core_indices`; it is empty if the last
+    /// answer was not `unsat`.
+    pub fn unsat_assertions(&mut self) -> Vec<usize> {
+        if !self.explain_unsat || self.last_res != Some(Res::UNSAT) {
+            return vec!()
+        }
+        self.solver.unsat_core_indices()
+    }
+
+    /// The `i`-th assertion, along with its location in the input if known.
+    pub fn assertion(&self, i: usize) -> (AST, Option<Span>) {
+        (self.assertions[i], self.spans[i])
+    }
+
+    // preprocess the pending assertions, if any, and add them to the solver.
+    // Assertions that cannot be clausified are dropped, and the first error is returned.
+    fn flush_pending(&mut self) -> error::Result<()> {
+        let mut ts = match self.pending.take() {
+            Some(ts) => ts,
+            None => return Ok(()),
+        };
+        let sat = self.pre.run(&mut self.c, self.solver.tseitin_mut(), &mut ts);
+        debug!("{}", self.pre.stats());
+        if !sat {
+            return self.add_assertion(self.c.b.false_, None) // the solver will answer unsat
+        }
+        let mut res = Ok(());
+        for (t, idx) in ts {
+            if let Err(e) = self.add_assertion(t, Some(idx)) {
+                res = res.and(Err(e));
+            }
+        }
+        for i in 0 .. self.lemmas.len() {
+            let u = self.pre.rewrite(&mut self.c, self.lemmas[i]);
+            if let Err(e) = self.add_lemma(u) {
+                res = res.and(Err(e));
+            }
+        }
+        res
+    }
+
+    // clausify the injected lemma `t`. Its clauses are permanent lemmas:
+    // deletable ones would be unsound, since `t`'s subformulas keep their
+    // Tseitin definitions for later assertions.
+    fn add_lemma(&mut self, t: AST) -> error::Result<()> {
+        let Driver{c, solver, ..} = self;
+        let batch = solver.clausify(c, t, None)?;
+        debug!("add injected lemma ({} clauses)", batch.len());
+        solver.add_batch_with_kind(c, batch, ClauseKind::TheoryLemmaPermanent);
+        Ok(())
+    }
+
+    // clausify `t`, which comes from the `origin`-th assertion if known
+    fn add_assertion(&mut self, t: AST, origin: Option<usize>) -> error::Result<()> {
+        let Driver{c, solver, enc_stats, explain_unsat, ..} = self;
+        let named = origin.filter(|_| *explain_unsat);
+        match (enc_stats, origin) {
+            (Some(enc_stats), Some(i)) => {
+                // measure the encoding before adding it
+                let before = solver.tseitin().stats().snapshot();
+                let batch = solver.clausify(c, t, named)?;
+                while enc_stats.len() <= i {
+                    enc_stats.push(AssertionStats::new(enc_stats.len()));
+                }
+                let max_len = batch.iter().map(|cl| cl.len()).max().unwrap_or(0);
+                enc_stats[i].add(&solver.tseitin().stats().diff(&before), max_len);
+                solver.add_batch(c, batch);
+            },
+            _ => match named {
+                Some(i) => solver.assert_formula_named(c, t, i)?,
+                None => solver.assert_formula(c, t)?,
+            },
+        }
+        Ok(())
+    }
+
+    // if the answer is `sat`, evaluate the deferred assertions in its model;
+    // those that do not hold are clausified, and `resolve` runs the solver again
+    fn check_deferred<F>(&mut self, mut r: Res, mut resolve: F) -> error::Result<Res>
+        where F: FnMut(&mut Ctx, &mut solver::Solver<Ctx, Th>) -> error::Result<Res>
+    {
+        if !self.cfg.skip_satisfied {
+            return Ok(r)
+        }
+        while r == Res::SAT {
+            let model = build_model(&mut self.c, &self.syms, &mut self.pre, &self.solver);
+            let mut ev = Eval::new(&model);
+            let c = &self.c;
+            let (holds, violated): (Vec<_>, Vec<_>) =
+                self.deferred.drain(..).partition(|(u, _)| ev.eval_formula(c, u) == Some(true));
+            self.deferred = holds;
+            if violated.is_empty() {
+                self.sat_model = Some(model);
+                break
+            }
+            debug!("{} deferred assertions do not hold in the model, solve again", violated.len());
+            for (u, idx) in violated {
+                self.add_assertion(u, Some(idx))?;
+            }
+            r = resolve(&mut self.c, &mut self.solver)?;
+        }
+        Ok(r)
+    }
+
+    // on a solver error, remember where it comes from
+    fn check_res(&mut self, r: error::Result<Res>, assumptions: &[AST]) -> error::Result<Res> {
+        if r.is_err() {
+            self.error_origin = self.error_origin(assumptions);
+        }
+        r
+    }
+
+    // remember the result of `check-sat`, and dump the model if it is `sat`
+    fn set_res(&mut self, r: Res) -> error::Result<Response> {
+        let resp = match r {
+            Res::SAT => Response::Sat,
+            Res::UNSAT if !self.lemmas.is_empty() && !self.cfg.trust_lemmas => Response::UnsatWithLemmas,
+            Res::UNSAT => Response::Unsat,
+            Res::Unknown(reason) => Response::Unknown(reason),
+        };
+        self.reason_unknown = match r {
+            Res::Unknown(reason) => Some(reason),
+            _ => None,
+        };
+        let sat = r == Res::SAT;
+        self.last_res = Some(r);
+        if sat && self.sat_dump.is_some() {
+            self.dump_sat()?;
+        }
+        Ok(resp)
+    }
+
+    // append the dump of the current model to the file given by `debug_sat_dump`
+    fn dump_sat(&mut self) -> io::Result<()> {
+        let path = self.sat_dump.as_ref().expect("no dump file");
+        // the first dump truncates the file
+        let mut f = io::BufWriter::new(
+            fs::OpenOptions::new().create(true).write(true)
+            .append(self.n_sat_dumps > 0).truncate(self.n_sat_dumps == 0)
+            .open(path)?);
+        self.n_sat_dumps += 1;
+        debug!("dump sat answer #{} into {:?}", self.n_sat_dumps, path);
+        use io::Write;
+        writeln!(f, "; sat answer #{}", self.n_sat_dumps)?;
+        self.write_sat_dump(&mut f)?;
+        f.flush()
+    }
+
+    // find the assertion or assumption the solver's last error comes from
+    fn error_origin(&self, assumptions: &[AST]) -> Option<String> {
+        let c = &self.c;
+        let lit = self.solver.last_error().and_then(|e| e.lit);
+        let atom = lit.and_then(|lit| self.solver.lit_map().map_lit(lit));
+        atom.and_then(|(t, _)| {
+            let mut cache = algo::AlgoCache::new();
+            let mut find = |kind, v: &[AST]| {
+                v.iter()
+                    .find(|u| cache.occurs_in(c, &t, u))
+                    .map(|u| format!("{} {}", kind, pp::pp1(c, u)))
+            };
+            find("assertion", &self.assertions).or_else(|| find("assumption", assumptions))
+        })
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Response::Success => Ok(()),
+            Response::Sat => write!(out, "SAT"),
+            Response::Unsat => write!(out, "UNSAT"),
+            Response::UnsatWithLemmas => write!(out, "unsat (with injected lemmas)"),
+            Response::Unknown(_) => write!(out, "unknown"),
+            Response::Model(m) => write!(out, "{}", m),
+            Response::Values(v) => {
+                let v: Vec<String> = v.iter().map(|(t, val)| format!("({} {})", t, val)).collect();
+                write!(out, "({})", v.join(" "))
+            },
+            Response::Core(v) => write!(out, "({})", v.join(" ")),
+            Response::Info(key, value) => write!(out, "({} {})", key, value),
+            Response::Unsupported => write!(out, "unsupported"),
+            Response::Error(msg) => write!(out, "(error {})", error::quote(msg)),
+        }
+    }
+}
+
+// model of the last `sat` answer, over the declared symbols and named terms
+fn build_model(
+    c: &mut Ctx, syms: &Symbols, pre: &mut Preprocessor, solver: &solver::Solver<Ctx, Th>
+) -> Model {
+    // boolean terms named with `(! t :named n)` also have a value
+    let mut named: Vec<(String, AST)> =
+        c.m.attrs().into_iter()
+        .flat_map(|a| a.iter_key(AttrKey::NAMED))
+        .filter(|(t, _)| c.m.ty(t) == Some(c.b.bool_))
+        .map(|(t, name)| (name.to_string(), *t))
+        .collect();
+    named.sort();
+    let funs = syms.funs();
+    // eliminated constants take the value of their representative
+    let reprs: Vec<AST> =
+        funs.iter().map(|(_, f, _, _)| pre.rewrite(c, *f)).collect();
+    let named: Vec<_> =
+        named.into_iter().map(|(name, t)| (name, pre.rewrite(c, t))).collect();
+
+    let c = &*c;
+    let mut mb = ModelBuilder::new(c);
+    if let Some(st) = solver.theory().final_state() {
+        for cl in st.classes.iter() {
+            mb.add_class(cl);
+        }
+    }
+    for ((name, f, args, ret), t) in funs.iter().zip(reprs) {
+        if args.is_empty() {
+            mb.declare_const(name, *f, t);
+        } else {
+            mb.declare_fun(name, *f, args, *ret);
+        }
+    }
+    for (name, t) in named.iter() {
+        mb.add_named(name, *t);
+    }
+    mb.build(|t| {
+        let lit = solver.lit_map().get_term(c, t, true)?;
+        match solver.value_in_model(lit) {
+            v if v == lbool::TRUE => Some(true),
+            v if v == lbool::FALSE => Some(false),
+            _ => None,
+        }
+    })
+}
+
+// estimated memory usage of the components of a driver
+fn memory_report(c: &Ctx, pre: &Preprocessor, solver: &solver::Solver<Ctx, Th>) -> MemTree {
+    let mut out = MemTree::new("total");
+    out.add("manager", &c.m);
+    out.add("tseitin", solver.tseitin());
+    out.add("preprocess", pre);
+    out.push(solver.memory_report());
+    out
+}
+
+// is the estimated memory usage over `limit`?
+fn out_of_memory(
+    limit: Option<usize>,
+    c: &Ctx, pre: &Preprocessor, solver: &solver::Solver<Ctx, Th>
+) -> bool {
+    match limit {
+        None => false,
+        Some(limit) => {
+            let total = memory_report(c, pre, solver).total();
+            debug!("memory: {} (limit {})", Bytes(total), Bytes(limit));
+            total > limit
+        },
+    }
+}
@@ -0,0 +1,71 @@
+
+//! Size of the encoding of each assertion.
+//!
+//! To find which assertions are responsible for a large encoding, the
+//! driver records what the Tseitin transformation produced for each of
+//! them (see `DriverConfig::stats_encoding`).
+
+use {
+    batsmt_tseitin::Stats,
+    batsmt_pretty as pp,
+};
+
+/// What the encoding of one assertion produced.
+///
+/// Subterms shared with previous assertions are only counted for the
+/// first assertion that contains them.
+#[derive(Clone,Debug,Default,Eq,PartialEq)]
+pub struct AssertionStats {
+    /// Index of the assertion, in the order of the input.
+    pub idx: usize,
+    /// Name of the assertion, from `(! t :named n)`.
+    pub name: Option<String>,
+    /// Literals introduced to define connectives.
+    pub def_lits: u64,
+    /// Clauses emitted.
+    pub clauses: u64,
+    /// Length of the longest clause.
+    pub max_clause_len: usize,
+    /// Theory atoms created.
+    pub atoms: u64,
+}
+
+impl AssertionStats {
+    /// Empty statistics for the assertion `idx`.
+    pub fn new(idx: usize) -> Self {
+        AssertionStats{idx, ..Default::default()}
+    }
+
+    /// Add one batch of clauses, whose encoding changed the statistics by `d`.
+    pub(crate) fn add(&mut self, d: &Stats, max_clause_len: usize) {
+        self.def_lits += d.def_lits;
+        self.clauses += d.clauses;
+        self.atoms += d.atoms;
+        self.max_clause_len = self.max_clause_len.max(max_clause_len);
+    }
+
+    /// Order by cost, the most expensive assertion first.
+    fn cost_key(&self) -> impl Ord {
+        use std::cmp::Reverse;
+        (Reverse(self.clauses), Reverse(self.def_lits), Reverse(self.atoms),
+         Reverse(self.max_clause_len), self.idx)
+    }
+}
+
+/// Sort `stats` so that the worst offenders come first.
+pub fn sort(stats: &mut [AssertionStats]) {
+    stats.sort_by_key(|s| s.cost_key())
+}
+
+/// A table with one row per assertion, in the order of `stats`.
+pub fn table(stats: &[AssertionStats]) -> pp::Table {
+    let rows = stats.iter().map(|s| {
+        let label = match &s.name {
+            Some(name) => format!("#{} {}", s.idx, name),
+            None => format!("#{}", s.idx),
+        };
+        vec![label, s.def_lits.to_string(), s.clauses.to_string(),
+             s.max_clause_len.to_string(), s.atoms.to_string()]
+    }).collect();
+    pp::table(vec!["assertion", "def-lits", "clauses", "max-len", "atoms"], rows)
+}
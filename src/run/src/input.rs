@@ -0,0 +1,37 @@
+
+//! Opening the inputs of the `batsmt-run` binary.
+//!
+//! An input is either `-`, for stdin, or a file; files whose name ends
+//! with `.gz` are decompressed on the fly, if the `flate2` feature is enabled.
+
+use {
+    std::{io, fs},
+    batsmt_core::Error,
+};
+
+/// Name of the input that reads stdin.
+pub const STDIN: &str = "-";
+
+/// Open the input `name`, see the module documentation.
+pub fn open(name: &str) -> Result<Box<dyn io::Read>, Error> {
+    if name == STDIN {
+        return Ok(Box::new(io::stdin()))
+    }
+    let file = io::BufReader::new(fs::File::open(name)?);
+    if name.ends_with(".gz") {
+        gunzip(name, file)
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+#[cfg(feature="flate2")]
+fn gunzip(_name: &str, r: io::BufReader<fs::File>) -> Result<Box<dyn io::Read>, Error> {
+    Ok(Box::new(flate2::bufread::GzDecoder::new(r)))
+}
+
+#[cfg(not(feature="flate2"))]
+fn gunzip(name: &str, _r: io::BufReader<fs::File>) -> Result<Box<dyn io::Read>, Error> {
+    Err(Error::unsupported(
+        format!("compressed input {:?} (build with the `flate2` feature)", name)))
+}
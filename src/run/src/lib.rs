@@ -0,0 +1,48 @@
+
+//! Library-level driver for batsmt.
+//!
+//! It glues together the AST manager, the parser, the Tseitin transformation,
+//! and the solver, so that embedders do not have to.
+//!
+//! ```
+//! use batsmt_run::{Driver, DriverConfig, Response};
+//!
+//! let mut d = Driver::new(DriverConfig::default());
+//! let r = d.execute_script(r#"
+//!     (declare-sort U 0)
+//!     (declare-fun a () U)
+//!     (declare-fun b () U)
+//!     (assert (= a b))
+//!     (assert (not (= a b)))
+//!     (check-sat)
+//!     "#.as_bytes());
+//! assert_eq!(r.last(), Some(&Response::Unsat));
+//! ```
+
+#[macro_use] extern crate log;
+
+pub mod ctx;
+pub mod driver;
+pub mod encoding_stats;
+pub mod input;
+pub mod model;
+pub mod options;
+pub mod output;
+pub mod preprocess;
+pub mod sat_dump;
+mod ast_builder;
+mod ast_printer;
+mod validate;
+
+use batsmt_parser as parser;
+
+pub use {
+    crate::{
+        ctx::{M, Ctx, Builtins, PpDistinct},
+        driver::{Driver, DriverConfig, Response},
+        encoding_stats::AssertionStats,
+        model::{Model, ModelBuilder, Eval},
+        output::{ResponseWriter, Smt2Writer, JsonWriter},
+        preprocess::Preprocessor,
+    },
+};
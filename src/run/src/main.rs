@@ -1,101 +1,225 @@
 
 // A test binary
+//
+// Usage: `batsmt-run [flags] [file…]`. Files are solved one after the
+// other, independently; `-` or no file at all reads stdin.
+// With `--parse-only`, files are only parsed, and the kinds of their
+// statements are counted. Before solving, statements are checked
+// (see `Driver::validate`), unless `--no-validate` is given.
 
 #[macro_use] extern crate log;
 
-mod ctx;
-mod ast_builder;
-mod ast_printer;
-
 use {
-    std::{env,fs,error::Error},
-    batsmt_core::{Chrono, gc::HasInternalMemory, },
-    batsmt_cc as cc,
-    batsmt_parser::{self as parser, Statement},
-    batsmt_tseitin::Tseitin,
-    batsmt_solver as solver,
+    std::{env,fs,io,process,thread,path::PathBuf,time::Duration},
+    batsmt_core::{Chrono, svec_stats, Error, },
+    batsmt_solver::{RestartPolicy, CancelToken},
     batsmt_pretty as pp,
+    batsmt_parser::Batches,
+    batsmt_run::{Driver, DriverConfig, ResponseWriter, Smt2Writer, JsonWriter, input},
 };
 
-pub use {
-    crate::ctx::{M, Ctx, Builtins},
-};
+const FLAGS: &[&str] = &["--explain-unsat", "--lenient", "--no-preprocess", "--no-validate", "--parse-only",
+    "--skip-satisfied", "--stats", "--stats-encoding", "--trust-lemmas", "--warn-unused"];
+const TIME_LIMIT: &str = "--time-limit="; // followed by a number of seconds
+const MEMORY_LIMIT: &str = "--memory-limit="; // followed by a number of MiB
 
-fn main() -> Result<(), Box<Error>> {
+fn main() {
     batsmt_logger::init();
-    let chrono = Chrono::new();
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut w: Box<dyn ResponseWriter> = match output(&mut args) {
+        Ok(w) => w,
+        Err(e) => {
+            println!("{}", pp::display(&e));
+            process::exit(1)
+        },
+    };
+    // every failure is reported as an error, in the chosen output format
+    if let Err(e) = run(args, &mut *w) {
+        let _ = w.error(&e);
+        process::exit(1)
+    }
+}
 
-    let mut c = Ctx::new();
+// remove `flag <value>` from `args`, and return the value
+fn flag_value(args: &mut Vec<String>, flag: &str, what: &str) -> Result<Option<String>, Error> {
+    match args.iter().position(|s| s == flag) {
+        Some(i) if i+1 < args.len() => {
+            let v = args.remove(i+1);
+            args.remove(i);
+            Ok(Some(v))
+        },
+        Some(_) => Err(Error::Parse{pos: None, msg: format!("{}: expected {}", flag, what)}),
+        None => Ok(None),
+    }
+}
 
-    // parse
-    let stmts: Vec<_> = {
-        let mut builder = ast_builder::AstBuilder::new(&mut c);
-        let args = env::args();
-        match args.skip(1).next() {
-            None => {
-                info!("parse stdin");
-                parser::parse_stdin(&mut builder)?
-            },
-            Some(file) => {
-                info!("parse file {:?}", file);
-                let file = fs::File::open(file)?;
-                parser::parse(&mut builder, file)?
-            },
+// remove `--output <format>` from `args`, and build the corresponding writer
+fn output(args: &mut Vec<String>) -> Result<Box<dyn ResponseWriter>, Error> {
+    let format = flag_value(args, "--output", "a format")?.unwrap_or("smt2".to_string());
+    match format.as_str() {
+        "smt2" => Ok(Box::new(Smt2Writer(io::stdout()))),
+        "json" => Ok(Box::new(JsonWriter(io::stdout()))),
+        _ => Err(Error::unsupported(format!("output format {:?}", format))),
+    }
+}
+
+// `; <n> statements (<count> <kind>, …)`, kinds in order of first appearance
+fn summary<'a>(names: impl Iterator<Item=&'a str>) -> String {
+    let mut kinds: Vec<(&str, usize)> = vec!();
+    let mut n = 0;
+    for name in names {
+        n += 1;
+        match kinds.iter_mut().find(|(k, _)| *k == name) {
+            Some((_, c)) => *c += 1,
+            None => kinds.push((name, 1)),
         }
-    };
+    }
+    let kinds: Vec<String> = kinds.iter().map(|(k, c)| format!("{} {}", c, k)).collect();
+    format!("; {} statements ({})", n, kinds.join(", "))
+}
 
-    info!("parsed {} statements (after {}s)", stmts.len(), chrono.as_f64());
+fn run(mut args: Vec<String>, w: &mut dyn ResponseWriter) -> Result<(), Error> {
+    let chrono = Chrono::new();
 
-    let th: cc::CCTheory<Ctx, (cc::theories::Ite, cc::theories::Constructor<ctx::AST>)> = cc::CCTheory::new(&mut c);
-    let mut solver = solver::Solver::new(c.builtins(), th);
+    let debug_sat_dump = flag_value(&mut args, "--debug-sat-dump", "a file")?.map(PathBuf::from);
+    let dump_egraph = flag_value(&mut args, "--dump-egraph", "a file")?.map(PathBuf::from);
+    if let Some(path) = &dump_egraph {
+        fs::File::create(path)?; // the driver appends to it
+    }
+    #[cfg(feature="simp-trace")]
+    let trace_simp = flag_value(&mut args, "--trace-simp", "a file")?.map(PathBuf::from);
 
-    {
+    let (flags, files): (Vec<String>, Vec<String>) =
+        args.into_iter().partition(|s| s.starts_with("--"));
+    let explain_unsat = flags.iter().any(|s| s == "--explain-unsat");
+    let lenient = flags.iter().any(|s| s == "--lenient");
+    let parse_only = flags.iter().any(|s| s == "--parse-only");
+    let preprocess = !flags.iter().any(|s| s == "--no-preprocess");
+    let validate = !flags.iter().any(|s| s == "--no-validate");
+    let skip_satisfied = flags.iter().any(|s| s == "--skip-satisfied");
+    let stats = flags.iter().any(|s| s == "--stats");
+    let stats_encoding = flags.iter().any(|s| s == "--stats-encoding");
+    let trust_lemmas = flags.iter().any(|s| s == "--trust-lemmas");
+    let warn_unused = flags.iter().any(|s| s == "--warn-unused");
+    if let Some(s) = flags.iter().find(|s| {
+        !FLAGS.contains(&s.as_str()) && !s.starts_with(TIME_LIMIT) && !s.starts_with(MEMORY_LIMIT)
+    }) {
+        return Err(Error::unsupported(format!("flag {:?}", s)))
+    }
+    let time_limit = match flags.iter().rev().find(|s| s.starts_with(TIME_LIMIT)) {
+        Some(s) => {
+            let secs: f64 = s[TIME_LIMIT.len()..].parse()
+                .map_err(|e| Error::Parse{pos: None, msg: format!("{}: {}", s, e)})?;
+            Some(Duration::from_millis((secs * 1000.) as u64))
+        },
+        None => None,
+    };
+    let memory_limit = match flags.iter().rev().find(|s| s.starts_with(MEMORY_LIMIT)) {
+        Some(s) => {
+            let mib: f64 = s[MEMORY_LIMIT.len()..].parse()
+                .map_err(|e| Error::Parse{pos: None, msg: format!("{}: {}", s, e)})?;
+            Some((mib * 1024. * 1024.) as usize)
+        },
+        None => None,
+    };
+
+    let cfg = {
         let propagate = match env::var("PROPAGATE") {
             Ok(ref s) if s == "1" || s == "true" => true,
             Ok(ref s) if s == "0" || s == "false" => false,
             _ => true, // default
         };
-        solver.enable_th_propagation(propagate);
+        let max_learnts = match env::var("MAX_LEARNTS") {
+            Ok(ref s) => {
+                let n = s.parse().map_err(|e| Error::Parse{pos: None, msg: format!("MAX_LEARNTS: {}", e)})?;
+                Some(n)
+            },
+            Err(_) => None,
+        };
+        let restarts = match env::var("RESTARTS") {
+            Ok(ref s) if s == "luby" => RestartPolicy::Luby{base: 100},
+            Ok(ref s) if s == "none" => RestartPolicy::None,
+            _ => RestartPolicy::default(),
+        };
+        DriverConfig {
+            propagate, max_learnts, restarts, explain_unsat, lenient, trust_lemmas, preprocess,
+            debug_sat_dump, dump_egraph,
+            #[cfg(feature="simp-trace")]
+            trace_simp: trace_simp.is_some(),
+            memory_limit, stats_encoding, warn_unused, skip_satisfied, validate,
+        }
+    };
+    let mut driver = Driver::new(cfg);
+
+    // watchdog: interrupt the solver once the time limit is reached
+    if let Some(limit) = time_limit {
+        let tok = CancelToken::new();
+        driver.set_cancel_token(tok.clone());
+        thread::spawn(move || {
+            thread::sleep(limit);
+            info!("time limit reached ({:?}), cancel", limit);
+            tok.cancel();
+        });
     }
 
-    // Tseitin transformation, to handle formulas
-    let mut tseitin = Tseitin::new();
+    // inputs are independent: the driver is reset between them
+    let files = if files.is_empty() { vec!(input::STDIN.to_string()) } else { files };
+    for (i, file) in files.iter().enumerate() {
+        if i > 0 {
+            driver.reset();
+        }
+        if files.len() > 1 {
+            w.file(file)?;
+        }
 
-    for s in &stmts {
-        debug!("parsed statement {}", pp::pp1(&c, s));
+        info!("parse {:?}", file);
+        let mut batches = Batches::new(input::open(file)?);
+        let mut names = vec!();
+        // each batch ends with `(reset)`, and runs before the next is parsed
+        while let Some(stmts) = driver.parse_batch(&mut batches)? {
+            info!("parsed {} statements (after {}s)", stmts.len(), chrono.as_f64());
 
-        // process statement
-        match s {
-            Statement::Assert(t) => {
-                let (cs, lits) = tseitin.clauses(&mut c, solver.lit_map_mut(), *t);
-                for lit in lits {
-                    trace!("add side lit {}", pp::pp1(lit, &c));
-                    solver.add_lit(&mut c, lit.clone());
-                }
-                for clause in cs {
-                    solver.add_clause(&mut c, clause);
+            if parse_only {
+                names.extend(stmts.iter().map(|(_, st)| st.name()));
+                driver.reset(); // as `(reset)` would
+                continue
+            }
+            let mut errs = driver.validate(&stmts);
+            if let Some(e) = errs.pop() {
+                // report all the problems, the last one as the failure
+                for e in &errs {
+                    w.error(e)?;
                 }
-            },
-            Statement::CheckSat => {
-                tseitin.reclaim_unused_memory();
-                let r = solver.solve(&mut c);
-                println!("{:?}", r)
-            },
-            Statement::CheckSatAssumptions(v) => {
-                tseitin.reclaim_unused_memory();
-                // map assumptions to literals
-                let lits: Vec<_> =
-                    v.iter().map(|t| solver.new_term_lit(&mut c, *t).0).collect();
-                let r = solver.solve_with(&mut c, &lits[..]);
-                println!("{:?}", r)
-            },
-            Statement::Exit => {
-                break;
+                return Err(e)
+            }
+            driver.execute_all(stmts, w)?;
+        }
+        if parse_only {
+            println!("{}", summary(names.into_iter()));
+            continue
+        }
+        #[cfg(feature="simp-trace")]
+        {
+            if let Some(path) = &trace_simp {
+                let mut out = io::BufWriter::new(fs::File::create(path)?);
+                driver.write_simp_trace(&mut out)?;
             }
-            _ => (),
+        }
+        if stats {
+            eprint!("{}", driver.memory_report());
+            #[cfg(feature="metrics")]
+            eprint!("{}", driver.cc_stats());
+        }
+        if stats_encoding {
+            eprint!("{}", pp::display(batsmt_run::encoding_stats::table(&driver.encoding_stats())));
+        }
+        for (kind, name) in driver.unused_symbols() {
+            eprintln!("warning: {} {} is declared but never used", kind, name);
         }
     }
     info!("exit (after {}s)", chrono.as_f64());
+    svec_stats::dump(&batsmt_cc::svec_sites::ALL);
+    svec_stats::dump(&batsmt_tseitin::svec_sites::ALL);
 
     Ok(())
 }
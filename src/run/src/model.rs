@@ -0,0 +1,489 @@
+
+//! Models, as answered to `get-model`.
+//!
+//! A `ModelBuilder` turns the classes of the congruence closure into
+//! values. Boolean classes are `true` or `false`; every other class
+//! gets its own abstract value `(as @U!i U)`, numbered per sort `U`.
+//! Functions are interpreted by a finite table, built from their
+//! applications in the classes, and a default value for the other arguments.
+
+use {
+    std::fmt,
+    fxhash::FxHashMap,
+    batsmt_core::{AstView, Manager},
+    batsmt_pretty as pp,
+    crate::{Ctx, ctx::AST},
+};
+
+/// Value of a term in a model.
+#[derive(Clone,Debug,Eq,PartialEq,Hash,Ord,PartialOrd)]
+pub enum Value {
+    Bool(bool),
+    /// The `idx`-th element of the sort `sort`, printed `(as @sort!idx sort)`.
+    Abstract{sort: String, idx: usize},
+}
+
+/// Interpretation of a declared symbol, or of a named term.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub struct Def {
+    pub name: String,
+    /// Sorts of the arguments, empty for constants.
+    pub args: Vec<String>,
+    /// Sort of the result.
+    pub ret: String,
+    /// Value of a function on some arguments, sorted by arguments.
+    pub table: Vec<(Vec<Value>, Value)>,
+    /// Value of a constant, or of a function outside of `table` (its `else` case).
+    pub default: Value,
+}
+
+/// A model, as a list of definitions.
+///
+/// Constants come first, then named terms, then functions.
+#[derive(Clone,Debug,Default,Eq,PartialEq)]
+pub struct Model {
+    defs: Vec<Def>,
+    syms: FxHashMap<AST, usize>, // symbol -> index of its definition
+    aliases: FxHashMap<Value, String>, // constant printed instead of the value, in tables
+}
+
+/// Builds a `Model` from the classes of the congruence closure
+/// and the declared symbols.
+pub struct ModelBuilder<'a> {
+    c: &'a Ctx,
+    classes: Vec<Class>,
+    class_of: FxHashMap<AST, usize>,
+    n_elts: FxHashMap<AST, usize>, // number of abstract values of each sort
+    consts: Vec<(String, AST, AST)>, // name, symbol, term it is equal to
+    named: Vec<(String, AST)>,
+    funs: Vec<(String, AST, Vec<AST>, AST)>, // name, symbol, sorts of arguments, sort
+}
+
+/// Evaluates terms in a `Model`, bottom-up, with a cache shared
+/// between calls.
+///
+/// Terms are evaluated without recursion, so deep terms are fine.
+/// In strict mode, applying a function to arguments that are not in its
+/// table gives `None`, rather than the `else` value of the table.
+pub struct Eval<'a> {
+    model: &'a Model,
+    strict: bool,
+    cache: FxHashMap<AST, Option<Value>>,
+}
+
+struct Class {
+    terms: Vec<AST>,
+    value: Option<Value>,
+}
+
+impl Def {
+    /// Definition of a constant.
+    pub fn constant(name: impl Into<String>, ret: impl Into<String>, value: Value) -> Self {
+        Def { name: name.into(), args: vec!(), ret: ret.into(), table: vec!(), default: value }
+    }
+
+    /// Value of the definition on `args`.
+    pub fn apply(&self, args: &[Value]) -> &Value {
+        self.lookup(args).unwrap_or(&self.default)
+    }
+
+    /// Value of the definition on `args`, if they are in the table.
+    pub fn lookup(&self, args: &[Value]) -> Option<&Value> {
+        self.table.binary_search_by(|(a, _)| a[..].cmp(args))
+            .ok().map(|i| &self.table[i].1)
+    }
+}
+
+mod model {
+    use super::*;
+
+    impl Model {
+        /// Empty model.
+        pub fn new() -> Self { Model::default() }
+
+        /// Add a definition.
+        pub fn push(&mut self, def: Def) { self.defs.push(def) }
+
+        /// Add the definition of the symbol `f`, used to evaluate terms.
+        ///
+        /// This is how models are built by hand, e.g. in tests.
+        pub fn define(&mut self, f: AST, mut def: Def) {
+            def.table.sort();
+            self.syms.insert(f, self.defs.len());
+            self.defs.push(def);
+        }
+
+        /// All the definitions.
+        pub fn defs(&self) -> &[Def] { &self.defs }
+
+        /// Definition of `name`, if any.
+        pub fn get(&self, name: &str) -> Option<&Def> {
+            self.defs.iter().find(|d| d.name == name)
+        }
+
+        /// Value of the constant (or named term) `name`, if any.
+        pub fn value(&self, name: &str) -> Option<&Value> {
+            self.get(name).filter(|d| d.args.is_empty()).map(|d| &d.default)
+        }
+
+        /// Boolean constants and named terms, with their value.
+        pub fn bools(&self) -> Vec<(String, bool)> {
+            self.defs.iter()
+                .filter(|d| d.args.is_empty())
+                .filter_map(|d| match d.default {
+                    Value::Bool(b) => Some((d.name.clone(), b)),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        /// Value of `t` in the model.
+        ///
+        /// Returns `None` if `t` contains a symbol that is not interpreted
+        /// by the model, or is ill-sorted.
+        pub fn eval(&self, c: &Ctx, t: &AST) -> Option<Value> {
+            Eval::new(self).eval_term(c, t)
+        }
+
+        // print `v`, using the name of a constant if it has one
+        fn pp_value(&self, v: &Value) -> String {
+            match self.aliases.get(v) {
+                Some(name) => name.clone(),
+                None => v.to_string(),
+            }
+        }
+
+        /// Body of the definition, as in `(define-fun f ((x!0 U)) U <body>)`.
+        ///
+        /// The arguments of functions are named `x!0`, `x!1`, …
+        pub fn pp_body(&self, d: &Def) -> String {
+            if d.args.is_empty() {
+                return d.default.to_string()
+            }
+            let mut s = String::new();
+            for (args, v) in d.table.iter() {
+                let conds: Vec<String> =
+                    args.iter().enumerate()
+                    .map(|(i, a)| format!("(= x!{} {})", i, self.pp_value(a)))
+                    .collect();
+                if conds.len() == 1 {
+                    s.push_str(&format!("(ite {} {} ", conds[0], self.pp_value(v)));
+                } else {
+                    s.push_str(&format!("(ite (and {}) {} ", conds.join(" "), self.pp_value(v)));
+                }
+            }
+            s.push_str(&self.pp_value(&d.default));
+            for _ in d.table.iter() { s.push(')') }
+            s
+        }
+    }
+
+    impl fmt::Display for Value {
+        fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Value::Bool(b) => write!(out, "{}", b),
+                Value::Abstract{sort, idx} => write!(out, "(as @{}!{} {})", sort, idx, sort),
+            }
+        }
+    }
+
+    /// SMT-LIB syntax, one `define-fun` per line.
+    impl fmt::Display for Model {
+        fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+            writeln!(out, "(model")?;
+            for d in self.defs.iter() {
+                let args: Vec<String> =
+                    d.args.iter().enumerate()
+                    .map(|(i, ty)| format!("(x!{} {})", i, ty))
+                    .collect();
+                writeln!(out, "  (define-fun {} ({}) {} {})",
+                    d.name, args.join(" "), d.ret, self.pp_body(d))?;
+            }
+            write!(out, ")")
+        }
+    }
+}
+
+mod eval {
+    use super::*;
+
+    impl<'a> Eval<'a> {
+        /// New evaluator for `model`, not strict.
+        pub fn new(model: &'a Model) -> Self {
+            Eval { model, strict: false, cache: FxHashMap::default() }
+        }
+
+        /// Use the strict mode or not.
+        pub fn strict(mut self, strict: bool) -> Self {
+            self.strict = strict;
+            self.cache.clear();
+            self
+        }
+
+        /// Value of `t` in the model, or `None` if some symbol of `t` is not
+        /// interpreted, or `t` is ill-sorted.
+        pub fn eval_term(&mut self, c: &Ctx, t: &AST) -> Option<Value> {
+            // `(u, true)` once the arguments of `u` are evaluated
+            let mut todo = vec!((*t, false));
+            while let Some((u, ready)) = todo.pop() {
+                if self.cache.contains_key(&u) {
+                    continue
+                }
+                match c.m.view(&u) {
+                    AstView::App{args, ..} if !ready => {
+                        todo.push((u, true));
+                        for a in args.iter().filter(|a| !self.cache.contains_key(a)) {
+                            todo.push((*a, false))
+                        }
+                    },
+                    _ => {
+                        let v = self.eval_node(c, &u);
+                        trace!("eval {} = {:?}", pp::pp1(c, &u), v);
+                        self.cache.insert(u, v);
+                    },
+                }
+            }
+            self.cache[t].clone()
+        }
+
+        /// Value of the formula `t`, or `None` if it cannot be evaluated
+        /// or is not a boolean.
+        pub fn eval_formula(&mut self, c: &Ctx, t: &AST) -> Option<bool> {
+            match self.eval_term(c, t)? {
+                Value::Bool(b) => Some(b),
+                Value::Abstract{..} => None,
+            }
+        }
+
+        // value of `t`, whose arguments are in the cache
+        fn eval_node(&self, c: &Ctx, t: &AST) -> Option<Value> {
+            let b = &c.b;
+            if *t == b.true_ {
+                return Some(Value::Bool(true))
+            } else if *t == b.false_ {
+                return Some(Value::Bool(false))
+            }
+            let model = self.model;
+            match c.m.view(t) {
+                AstView::Const(_) => model.syms.get(t).map(|i| model.defs[*i].default.clone()),
+                AstView::App{f, args} => {
+                    let args: Vec<Value> =
+                        args.iter().map(|u| self.cache[u].clone()).collect::<Option<_>>()?;
+                    let as_bool = |v: &Value| match v {
+                        Value::Bool(b) => Some(*b),
+                        _ => None,
+                    };
+                    let bools = || args.iter().map(as_bool).collect::<Option<Vec<bool>>>();
+                    let r = if *f == b.not_ && args.len() == 1 {
+                        !as_bool(&args[0])?
+                    } else if *f == b.and_ {
+                        bools()?.iter().all(|b| *b)
+                    } else if *f == b.or_ {
+                        bools()?.iter().any(|b| *b)
+                    } else if *f == b.imply_ && !args.is_empty() {
+                        // `(=> a b c)` is `(=> a (=> b c))`
+                        let v = bools()?;
+                        let (last, hyps) = v.split_last().unwrap();
+                        *last || hyps.iter().any(|b| !b)
+                    } else if *f == b.eq {
+                        args.iter().all(|v| *v == args[0])
+                    } else if *f == b.distinct {
+                        args.iter().enumerate().all(|(i, v)| !args[i+1..].contains(v))
+                    } else if *f == b.ite && args.len() == 3 {
+                        let i = if as_bool(&args[0])? { 1 } else { 2 };
+                        return Some(args[i].clone())
+                    } else {
+                        let d = &model.defs[*model.syms.get(f)?];
+                        return if self.strict {
+                            d.lookup(&args).cloned()
+                        } else {
+                            Some(d.apply(&args).clone())
+                        }
+                    };
+                    Some(Value::Bool(r))
+                },
+                _ => None,
+            }
+        }
+    }
+}
+
+mod builder {
+    use super::*;
+
+    impl<'a> ModelBuilder<'a> {
+        /// New builder, with no classes and no symbols.
+        pub fn new(c: &'a Ctx) -> Self {
+            ModelBuilder {
+                c, classes: vec!(), class_of: FxHashMap::default(),
+                n_elts: FxHashMap::default(),
+                consts: vec!(), named: vec!(), funs: vec!(),
+            }
+        }
+
+        /// Add a class of the congruence closure.
+        ///
+        /// Terms that are in no class are alone in their class.
+        pub fn add_class(&mut self, terms: &[AST]) {
+            let i = self.classes.len();
+            for t in terms {
+                self.class_of.insert(*t, i);
+            }
+            self.classes.push(Class{terms: terms.to_vec(), value: None});
+        }
+
+        /// Declare the constant `f`, equal to the term `t`.
+        ///
+        /// `t` is `f` itself, unless `f` was eliminated by preprocessing.
+        pub fn declare_const(&mut self, name: &str, f: AST, t: AST) {
+            self.consts.push((name.to_string(), f, t))
+        }
+
+        /// Declare the function `f`, with the given sorts.
+        pub fn declare_fun(&mut self, name: &str, f: AST, args: &[AST], ret: AST) {
+            self.funs.push((name.to_string(), f, args.to_vec(), ret))
+        }
+
+        /// Give the value of `t` under the name `name`, like a constant.
+        pub fn add_named(&mut self, name: &str, t: AST) {
+            self.named.push((name.to_string(), t))
+        }
+
+        /// Build the model.
+        ///
+        /// `bool_value` gives the value of boolean terms whose class
+        /// contains neither `true` nor `false`, e.g. from the SAT solver;
+        /// they are `false` if it returns `None`.
+        pub fn build<F>(mut self, mut bool_value: F) -> Model
+            where F: FnMut(&AST) -> Option<bool>
+        {
+            let c = self.c;
+            let mut model = Model::new();
+            // constants first, so that they get the first abstract values,
+            // and print the values in function tables
+            for (name, f, t) in std::mem::take(&mut self.consts) {
+                let v = self.value(t, &mut bool_value);
+                if let Value::Abstract{..} = v {
+                    model.aliases.entry(v.clone()).or_insert(name.clone());
+                }
+                model.syms.insert(f, model.defs.len());
+                model.push(Def::constant(name, self.pp_sort(c.m.ty(&f)), v));
+            }
+            for (name, t) in std::mem::take(&mut self.named) {
+                let v = self.value(t, &mut bool_value);
+                model.push(Def::constant(name, self.pp_sort(c.m.ty(&t)), v));
+            }
+
+            // applications of each function, in the classes
+            let mut apps: FxHashMap<AST, Vec<AST>> = FxHashMap::default();
+            for (_, f, _, _) in self.funs.iter() {
+                apps.insert(*f, vec!());
+            }
+            for cl in self.classes.iter() {
+                for t in cl.terms.iter() {
+                    if let AstView::App{f, ..} = c.m.view(t) {
+                        if let Some(v) = apps.get_mut(f) { v.push(*t) }
+                    }
+                }
+            }
+
+            for (name, f, args, ret) in std::mem::take(&mut self.funs) {
+                let mut table: Vec<(Vec<Value>, Value)> = vec!();
+                for t in apps[&f].iter() {
+                    let u_args = match c.m.view(t) {
+                        AstView::App{args: u_args, ..} if u_args.len() == args.len() => u_args,
+                        _ => continue, // partial application
+                    };
+                    let vs = u_args.iter().map(|u| self.value(*u, &mut bool_value)).collect();
+                    let v = self.value(*t, &mut bool_value);
+                    table.push((vs, v));
+                }
+                table.sort();
+                table.dedup_by(|a, b| a.0 == b.0); // same arguments, same value by congruence
+                let default = self.default_value(&table, ret);
+                // the default case covers the entries with the same value
+                table.retain(|(_, v)| *v != default);
+                let def = Def {
+                    name, table, default,
+                    args: args.iter().map(|ty| self.pp_sort(Some(*ty))).collect(),
+                    ret: self.pp_sort(Some(ret)),
+                };
+                model.syms.insert(f, model.defs.len());
+                model.push(def);
+            }
+            model
+        }
+
+        // value of the class of `t`
+        fn value<F>(&mut self, t: AST, bool_value: &mut F) -> Value
+            where F: FnMut(&AST) -> Option<bool>
+        {
+            let i = match self.class_of.get(&t) {
+                Some(i) => *i,
+                None => {
+                    self.add_class(&[t]);
+                    self.classes.len() - 1
+                },
+            };
+            if let Some(v) = &self.classes[i].value {
+                return v.clone()
+            }
+            let c = self.c;
+            let terms = &self.classes[i].terms;
+            let ty = terms.iter().filter_map(|t| c.m.ty(t)).next();
+            let v = if ty == Some(c.b.bool_)
+                || terms.contains(&c.b.true_) || terms.contains(&c.b.false_)
+            {
+                let b = if terms.contains(&c.b.true_) {
+                    true
+                } else if terms.contains(&c.b.false_) {
+                    false
+                } else {
+                    terms.iter().filter_map(&mut *bool_value).next().unwrap_or(false)
+                };
+                Value::Bool(b)
+            } else {
+                self.fresh(ty)
+            };
+            self.classes[i].value = Some(v.clone());
+            v
+        }
+
+        // new abstract value of sort `ty`
+        fn fresh(&mut self, ty: Option<AST>) -> Value {
+            let sort = self.pp_sort(ty);
+            let n = self.n_elts.entry(ty.unwrap_or(self.c.b.bool_)).or_insert(0);
+            *n += 1;
+            Value::Abstract{sort, idx: *n-1}
+        }
+
+        // the most frequent value of `table`, or some value of sort `ret` if it is empty
+        fn default_value(&mut self, table: &[(Vec<Value>, Value)], ret: AST) -> Value {
+            let mut count: FxHashMap<&Value, usize> = FxHashMap::default();
+            let mut best: Option<(&Value, usize)> = None;
+            for (_, v) in table {
+                let n = count.entry(v).or_insert(0);
+                *n += 1;
+                match best {
+                    Some((_, m)) if *n <= m => (),
+                    _ => best = Some((v, *n)),
+                }
+            }
+            match best {
+                Some((v, _)) => v.clone(),
+                None if ret == self.c.b.bool_ => Value::Bool(false),
+                None if self.n_elts.contains_key(&ret) => {
+                    Value::Abstract{sort: self.pp_sort(Some(ret)), idx: 0}
+                },
+                None => self.fresh(Some(ret)),
+            }
+        }
+
+        fn pp_sort(&self, ty: Option<AST>) -> String {
+            match ty {
+                Some(ty) => pp::pp1(self.c, &ty).to_string(),
+                None => "?".to_string(),
+            }
+        }
+    }
+}
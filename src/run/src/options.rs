@@ -0,0 +1,41 @@
+
+//! Options set by `(set-option …)`.
+
+/// Options of the solver, all disabled by default (as in SMT-LIB).
+#[derive(Clone,Debug,Default)]
+pub struct Options {
+    /// Build models after `sat`, for `get-model` and `get-value`.
+    pub produce_models: bool,
+    /// Keep track of assumptions for unsat cores.
+    pub produce_unsat_cores: bool,
+}
+
+/// Error for `Options::set`.
+#[derive(Clone,Debug)]
+pub enum OptError {
+    /// The option (or this value of the option) is not supported.
+    Unsupported,
+    /// The value has the wrong shape.
+    BadValue(String),
+}
+
+impl Options {
+    /// New options with default values.
+    pub fn new() -> Self { Options::default() }
+
+    /// Process `(set-option key value)`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), OptError> {
+        let b = match value {
+            "true" => true,
+            "false" => false,
+            _ => return Err(OptError::BadValue(format!("expected boolean for {}, got {:?}", key, value))),
+        };
+        match key {
+            ":produce-models" => self.produce_models = b,
+            ":produce-unsat-cores" => self.produce_unsat_cores = b,
+            ":produce-proofs" if !b => (),
+            _ => return Err(OptError::Unsupported),
+        }
+        Ok(())
+    }
+}
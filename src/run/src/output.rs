@@ -0,0 +1,192 @@
+
+//! Reporting responses to the user.
+//!
+//! `Smt2Writer` prints responses as SMT-LIB, like most solvers do.
+//! `JsonWriter` prints one JSON object per line, for tools that
+//! scrape the output of the solver.
+
+use {
+    std::{io, time::Duration},
+    batsmt_core::Error,
+    batsmt_parser::Span,
+    crate::{
+        driver::Response,
+        model::Value,
+        ast_printer::json_string,
+    },
+};
+
+/// Named counters reported along with the answer to `check-sat`.
+pub type Stats = [(&'static str, u64)];
+
+/// Reports the responses of a `Driver`.
+pub trait ResponseWriter {
+    /// Report the response `r` to the command `cmd`, which ran in `time`.
+    ///
+    /// `stats` are only given for commands that ran the solver.
+    fn response(
+        &mut self, cmd: &str, r: &Response, time: Duration, stats: Option<&Stats>
+    ) -> io::Result<()>;
+
+    /// Report that the `i`-th assertion, `t`, is used to prove the last `unsat`.
+    fn used_assertion(&mut self, i: usize, span: Option<&Span>, t: &str) -> io::Result<()>;
+
+    /// Report an error that is not the response to a command (e.g. a parse error).
+    fn error(&mut self, e: &Error) -> io::Result<()>;
+
+    /// Report that the following responses are for the input `name`.
+    fn file(&mut self, name: &str) -> io::Result<()>;
+}
+
+/// Prints responses as SMT-LIB.
+pub struct Smt2Writer<W: io::Write>(pub W);
+
+/// Prints each response as a JSON object, on its own line.
+///
+/// Every object has a `"cmd"` and a `"result"` field, along with `"time_ms"`.
+/// Depending on the result, it can also have `"reason"`
+/// (for `"unknown"`), `"model"`, `"core"`, `"key"` and `"value"` (for `"info"`),
+/// `"message"` (for `"error"`), `"injected_lemmas"` (for `"unsat"`,
+/// see `Response::UnsatWithLemmas`), and `"stats"`.
+pub struct JsonWriter<W: io::Write>(pub W);
+
+impl<W: io::Write> ResponseWriter for Smt2Writer<W> {
+    fn response(
+        &mut self, _cmd: &str, r: &Response, _time: Duration, _stats: Option<&Stats>
+    ) -> io::Result<()> {
+        match r {
+            Response::Success => Ok(()),
+            r => writeln!(self.0, "{}", r),
+        }
+    }
+
+    fn used_assertion(&mut self, i: usize, span: Option<&Span>, t: &str) -> io::Result<()> {
+        let span = span.map(|s| s.to_string()).unwrap_or("?".to_string());
+        writeln!(self.0, "; used assertion #{} ({}): {}", i, span, t)
+    }
+
+    fn error(&mut self, e: &Error) -> io::Result<()> {
+        writeln!(self.0, "{}", batsmt_pretty::display(e))
+    }
+
+    fn file(&mut self, name: &str) -> io::Result<()> {
+        writeln!(self.0, "; file: {}", name)
+    }
+}
+
+impl<W: io::Write> ResponseWriter for JsonWriter<W> {
+    fn response(
+        &mut self, cmd: &str, r: &Response, time: Duration, stats: Option<&Stats>
+    ) -> io::Result<()> {
+        let mut out = String::new();
+        out.push_str("{\"cmd\":");
+        json_string(cmd, &mut out);
+        out.push_str(",\"result\":");
+        match r {
+            Response::Success => out.push_str("\"success\""),
+            Response::Sat => out.push_str("\"sat\""),
+            Response::Unsat => out.push_str("\"unsat\""),
+            Response::UnsatWithLemmas => out.push_str("\"unsat\",\"injected_lemmas\":true"),
+            Response::Unknown(reason) => {
+                out.push_str("\"unknown\",\"reason\":");
+                json_string(&reason.to_string(), &mut out);
+            },
+            Response::Model(m) => {
+                out.push_str("\"model\",\"model\":[");
+                // booleans are JSON booleans, other values are in SMT-LIB syntax
+                for (i, d) in m.defs().iter().enumerate() {
+                    if i > 0 { out.push(',') }
+                    out.push_str("{\"name\":");
+                    json_string(&d.name, &mut out);
+                    if !d.args.is_empty() {
+                        out.push_str(",\"args\":[");
+                        for (j, ty) in d.args.iter().enumerate() {
+                            if j > 0 { out.push(',') }
+                            json_string(ty, &mut out);
+                        }
+                        out.push(']');
+                    }
+                    out.push_str(",\"value\":");
+                    match d.default {
+                        Value::Bool(b) if d.args.is_empty() => out.push_str(&b.to_string()),
+                        _ => json_string(&m.pp_body(d), &mut out),
+                    }
+                    out.push('}');
+                }
+                out.push(']');
+            },
+            Response::Values(v) => {
+                out.push_str("\"values\",\"values\":[");
+                for (i, (t, val)) in v.iter().enumerate() {
+                    if i > 0 { out.push(',') }
+                    out.push_str("{\"term\":");
+                    json_string(t, &mut out);
+                    out.push_str(",\"value\":");
+                    match val {
+                        Value::Bool(b) => out.push_str(&b.to_string()),
+                        _ => json_string(&val.to_string(), &mut out),
+                    }
+                    out.push('}');
+                }
+                out.push(']');
+            },
+            Response::Core(v) => {
+                out.push_str("\"core\",\"core\":[");
+                for (i, name) in v.iter().enumerate() {
+                    if i > 0 { out.push(',') }
+                    json_string(name, &mut out);
+                }
+                out.push(']');
+            },
+            Response::Info(key, value) => {
+                out.push_str("\"info\",\"key\":");
+                json_string(key, &mut out);
+                out.push_str(",\"value\":");
+                json_string(value, &mut out);
+            },
+            Response::Unsupported => out.push_str("\"unsupported\""),
+            Response::Error(msg) => {
+                out.push_str("\"error\",\"message\":");
+                json_string(msg, &mut out);
+            },
+        }
+        out.push_str(&format!(",\"time_ms\":{}", time.as_millis()));
+        if let Some(stats) = stats {
+            out.push_str(",\"stats\":{");
+            for (i, (name, n)) in stats.iter().enumerate() {
+                if i > 0 { out.push(',') }
+                json_string(name, &mut out);
+                out.push_str(&format!(":{}", n));
+            }
+            out.push('}');
+        }
+        out.push('}');
+        writeln!(self.0, "{}", out)
+    }
+
+    fn used_assertion(&mut self, i: usize, span: Option<&Span>, t: &str) -> io::Result<()> {
+        let mut out = format!("{{\"cmd\":\"explain-unsat\",\"result\":\"used-assertion\",\"index\":{}", i);
+        if let Some(span) = span {
+            out.push_str(",\"span\":");
+            json_string(&span.to_string(), &mut out);
+        }
+        out.push_str(",\"term\":");
+        json_string(t, &mut out);
+        out.push('}');
+        writeln!(self.0, "{}", out)
+    }
+
+    fn error(&mut self, e: &Error) -> io::Result<()> {
+        let mut out = String::from("{\"cmd\":null,\"result\":\"error\",\"message\":");
+        json_string(&e.to_string(), &mut out);
+        out.push('}');
+        writeln!(self.0, "{}", out)
+    }
+
+    fn file(&mut self, name: &str) -> io::Result<()> {
+        let mut out = String::from("{\"cmd\":null,\"result\":\"file\",\"name\":");
+        json_string(name, &mut out);
+        out.push('}');
+        writeln!(self.0, "{}", out)
+    }
+}
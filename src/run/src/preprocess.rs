@@ -0,0 +1,179 @@
+
+//! Preprocessing of assertions, before they are turned into clauses.
+//!
+//! The top-level literals of the assertions (equalities, boolean atoms, and
+//! their negations) are merged in a scratch congruence closure. Each constant
+//! is then replaced by the representative of its class in all the assertions,
+//! and assertions that become trivially true are dropped.
+
+use {
+    std::fmt,
+    batsmt_core::{
+        ast_u32::AST, ast::{self, algo, AstMap, iter_dag}, Manager,
+        mem::{MemTree, ReportMemory},
+    },
+    batsmt_cc::{self as cc, CCInterface},
+    batsmt_theory::SimpleActions,
+    batsmt_tseitin::{self as tseitin, Tseitin, View as FView},
+    batsmt_solver::BLit,
+    crate::Ctx,
+};
+
+/// The congruence closure used to compute the partition.
+type ScratchCC = cc::CC<Ctx, (
+    cc::theories::Ite, cc::theories::Constructor<AST>, cc::theories::Injectivity<AST>,
+)>;
+
+/// Statistics of the preprocessing.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct Stats {
+    /// Number of constants replaced by their representative.
+    pub eliminated: usize,
+    /// Number of assertions that became trivially true.
+    pub dropped: usize,
+    /// Did the congruence closure alone find the assertions unsat?
+    pub unsat: bool,
+}
+
+/// Equality propagation over top-level literals.
+///
+/// The substitution is kept, so that terms coming later (assertions,
+/// assumptions) can be rewritten the same way, and so that models can be
+/// extended to the eliminated constants (see `repr`).
+pub struct Preprocessor {
+    subst: ast::HashMap<AST,AST>, // constant -> representative
+    algo: algo::AlgoCache<AST>,
+    stats: Stats,
+}
+
+impl ReportMemory for Preprocessor {
+    fn memory_breakdown(&self, out: &mut MemTree) {
+        out.add("subst", &self.subst);
+        out.add("algo", &self.algo);
+    }
+}
+
+impl Preprocessor {
+    /// New preprocessor, with an empty substitution.
+    pub fn new() -> Self {
+        Preprocessor {
+            subst: ast::HashMap::new(), algo: algo::AlgoCache::new(),
+            stats: Stats::default(),
+        }
+    }
+
+    /// Statistics so far.
+    pub fn stats(&self) -> &Stats { &self.stats }
+
+    /// The substitution computed by `run`, mapping constants to their representative.
+    pub fn substitution(&self) -> &ast::HashMap<AST,AST> { &self.subst }
+
+    /// The term `t` stands for after preprocessing.
+    ///
+    /// For an eliminated constant, this is its representative, which is either
+    /// another constant or `true`/`false`; otherwise it is `t` itself.
+    pub fn repr(&self, t: &AST) -> AST {
+        self.subst.get(t).cloned().unwrap_or(*t)
+    }
+
+    /// Apply the substitution to `t`.
+    pub fn rewrite(&mut self, c: &mut Ctx, t: AST) -> AST {
+        if self.subst.len() == 0 { t } else { self.algo.substitute(c, &t, &self.subst) }
+    }
+
+    /// Preprocess `assertions` in place.
+    ///
+    /// Each assertion comes with its index, which stays attached to its
+    /// rewritten version. Assertions that become trivially true are removed.
+    /// Returns `false` if the assertions were found to be unsat, in which
+    /// case they are left as is.
+    pub fn run(
+        &mut self, c: &mut Ctx, tseitin: &mut Tseitin<Ctx>, assertions: &mut Vec<(AST, usize)>
+    ) -> bool {
+        let mut cc = ScratchCC::new(c);
+        // constants should be representatives, so they can be eliminated
+        cc.set_merge_priority(|c: &Ctx, t: &AST| if c.m.is_const(t) { 1 } else { 0 });
+
+        // merge the top-level literals into `cc`
+        let mut merged = vec!();
+        for &(t, _) in assertions.iter() {
+            collect_lits(c, t, true, &mut merged);
+        }
+        // explanations are never asked for, any literal will do
+        let lit = BLit::unsafe_from_int(1);
+        for &(t, u) in merged.iter() {
+            if let Err(e) = cc.merge(c, t, u, lit) {
+                // leave it to the solver, which will report it
+                debug!("preprocess: skip merge: {:?}", e);
+            }
+        }
+        let mut acts = SimpleActions::new(|| unreachable!());
+        cc.final_check(c, &mut acts);
+        if acts.get().is_err() {
+            debug!("preprocess: assertions are unsat");
+            self.stats.unsat = true;
+            return false
+        }
+
+        // map constants to their representative
+        let mut iter = iter_dag::new();
+        for &(t, u) in merged.iter() {
+            for v in [t, u].iter() {
+                iter.iter(c, v, |c, v| {
+                    if !c.m.is_const(v) { return }
+                    match cc.find(v) {
+                        Some(r) if r != *v && c.m.is_const(&r) => {
+                            self.subst.insert(*v, r);
+                            self.stats.eliminated += 1;
+                        },
+                        _ => (),
+                    }
+                });
+            }
+        }
+        debug!("preprocess: eliminated {} constants", self.stats.eliminated);
+
+        // rewrite assertions, removing the trivial ones
+        let true_ = c.b.true_;
+        let mut dropped = 0;
+        let mut res = Vec::with_capacity(assertions.len());
+        for &(t, idx) in assertions.iter() {
+            let u = self.rewrite(c, t);
+            let u = if u != t { tseitin.simplify(c, u) } else { u };
+            if u == true_ {
+                dropped += 1;
+            } else {
+                res.push((u, idx));
+            }
+        }
+        self.stats.dropped += dropped;
+        *assertions = res;
+        true
+    }
+}
+
+// find the literals `t=u` that `t` implies at the top level, if `sign` is true
+// (resp. that `¬t` implies, if `sign` is false)
+fn collect_lits(c: &Ctx, t: AST, sign: bool, out: &mut Vec<(AST,AST)>) {
+    let b = if sign { c.b.true_ } else { c.b.false_ };
+    match tseitin::Ctx::view_as_formula(c, t) {
+        FView::Bool(b2) if b2 != sign => out.push((c.b.true_, c.b.false_)),
+        FView::Not(u) => collect_lits(c, u, !sign, out),
+        FView::And(args) if sign => {
+            for &u in args.iter() { collect_lits(c, u, sign, out) }
+        },
+        FView::Or(args) if !sign => {
+            for &u in args.iter() { collect_lits(c, u, sign, out) }
+        },
+        FView::Eq(u1, u2) if sign => out.push((u1, u2)),
+        FView::Eq(..) | FView::Atom(_) => out.push((t, b)),
+        _ => (), // boolean structure
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "preprocess.eliminated {}, preprocess.dropped {}, preprocess.unsat {}",
+               self.eliminated, self.dropped, self.unsat)
+    }
+}
@@ -0,0 +1,224 @@
+
+//! Dump of the theory's view of a model, to debug wrong `sat` answers.
+//!
+//! For each theory atom, the dump gives its value in the model, the
+//! representative of each side in the congruence closure, and, if the
+//! theory propagated it, the explanation. Atoms whose value contradicts
+//! the partition are marked `:inconsistent`. Subterms shared by several
+//! terms are defined first, with `(define-term _tN …)`.
+//!
+//! The state of the theory is the one recorded by `CCTheory` at its last
+//! final check (see `CCTheory::set_record_final_state`).
+
+use {
+    std::io,
+    fxhash::{FxHashMap, FxHashSet},
+    batsmt_core::{ast_u32::AST, AstView, Manager},
+    batsmt_cc::{self as cc, CCView, Ctx as _},
+    batsmt_solver::{solver::lbool, Solver, BLit},
+    batsmt_theory::{LitMap, Theory},
+    batsmt_pretty::{self as pp, Pretty1},
+    crate::Ctx,
+};
+
+/// Names of the subterms that occur in more than one term.
+struct Sharing<'a> {
+    c: &'a Ctx,
+    names: FxHashMap<AST, usize>,
+    defs: Vec<AST>, // named terms, subterms first
+}
+
+// `t`, using the names of its shared subterms; `expand` to print a named term itself
+struct Term<'a>(&'a Sharing<'a>, AST, bool);
+
+impl<'a> Sharing<'a> {
+    fn new<I>(c: &'a Ctx, roots: I) -> Self where I: Iterator<Item=AST> {
+        // number of distinct terms `t` is an immediate subterm of
+        let mut count: FxHashMap<AST, usize> = FxHashMap::default();
+        let mut seen = FxHashSet::default();
+        let mut st = vec!();
+        for t in roots {
+            if seen.insert(t) { st.push(t) }
+        }
+        while let Some(t) = st.pop() {
+            if let AstView::App{f, args} = c.m.view(&t) {
+                for u in Some(f).into_iter().chain(args.iter()) {
+                    *count.entry(*u).or_insert(0) += 1;
+                    if seen.insert(*u) { st.push(*u) }
+                }
+            }
+        }
+        let mut defs: Vec<AST> =
+            count.into_iter()
+            .filter(|(t, n)| *n > 1 && is_compound(c, t))
+            .map(|(t, _)| t)
+            .collect();
+        // a term is built after its subterms, so it has a larger index
+        defs.sort_by_key(|t| t.idx());
+        let names = defs.iter().enumerate().map(|(i, t)| (*t, i)).collect();
+        Sharing{c, names, defs}
+    }
+
+    fn pp(&'a self, t: AST) -> impl pp::Pretty + 'a { Term(self, t, false) }
+}
+
+fn is_compound(c: &Ctx, t: &AST) -> bool {
+    match c.m.view(t) {
+        AstView::App{args, ..} => args.len() > 0,
+        _ => false,
+    }
+}
+
+impl<'a> pp::Pretty for Term<'a> {
+    fn pp_into(&self, ctx: &mut pp::Ctx) {
+        let Term(sh, t, expand) = *self;
+        match sh.names.get(&t) {
+            Some(i) if !expand => { ctx.string(format!("_t{}", i)); },
+            _ => match sh.c.m.view(&t) {
+                AstView::App{f, args} if args.len() == 2 && sh.c.is_infix(f) => {
+                    ctx.sexp(|ctx| {
+                        ctx.pp(&sh.pp(args[0])).space().pp(&sh.pp(*f)).space().pp(&sh.pp(args[1]));
+                    });
+                },
+                AstView::App{f, args} if args.len() > 0 => {
+                    ctx.sexp(|ctx| {
+                        ctx.pp(&sh.pp(*f));
+                        for u in args.iter() {
+                            ctx.space().pp(&sh.pp(*u));
+                        }
+                    });
+                },
+                _ => sh.c.pp1_into(&t, ctx),
+            },
+        }
+    }
+}
+
+// print using a closure
+struct Fun<F>(F);
+
+impl<F: Fn(&mut pp::Ctx)> pp::Pretty for Fun<F> {
+    fn pp_into(&self, ctx: &mut pp::Ctx) { (self.0)(ctx) }
+}
+
+fn fun<F: Fn(&mut pp::Ctx)>(f: F) -> Fun<F> { Fun(f) }
+
+// the literal `lit`, as a signed term if possible
+fn pp_lit<'a, Th: Theory<Ctx>>(sh: &'a Sharing<'a>, s: &Solver<Ctx, Th>, lit: BLit) -> impl pp::Pretty + 'a {
+    let pair = s.lit_map().map_lit(lit);
+    fun(move |ctx| match pair {
+        Some((t, true)) => { ctx.pp(&sh.pp(t)); },
+        Some((t, false)) => { ctx.sexp(|ctx| { ctx.str("not").space().pp(&sh.pp(t)); }); },
+        None => { ctx.debug(lit); },
+    })
+}
+
+/// Write the dump of the current model of `s`, with the theory state `st`, into `out`.
+pub fn write<Th, W>(
+    c: &Ctx, s: &Solver<Ctx, Th>, st: Option<&cc::FinalState<AST, BLit>>, out: &mut W
+) -> io::Result<()>
+    where Th: Theory<Ctx>, W: io::Write
+{
+    let mut atoms: Vec<(AST, BLit)> = s.lit_map().iter_theory_atoms().collect();
+    atoms.sort_by_key(|(t, _)| t.idx());
+
+    let assigned: FxHashMap<AST, &cc::AssignedAtom<AST, BLit>> =
+        st.iter().flat_map(|st| st.atoms.iter()).map(|a| (a.atom, a)).collect();
+    let repr = |t: &AST| st.and_then(|st| st.repr(t)).cloned();
+
+    // sides of each atom, for the congruence closure
+    let sides = |t: &AST| -> Vec<AST> {
+        match c.view_as_cc_term(t) {
+            CCView::Eq(a, b) => vec![*a, *b],
+            _ => vec![*t],
+        }
+    };
+
+    let sh = {
+        let roots =
+            atoms.iter().flat_map(|(t, _)| Some(*t).into_iter().chain(sides(t)))
+            .chain(st.iter().flat_map(|st| st.classes.iter().flat_map(|cl| cl.iter().cloned())))
+            .chain(assigned.values().flat_map(|a| a.reason.iter().flatten())
+                .filter_map(|lit| s.lit_map().map_lit(*lit).map(|(t, _)| t)));
+        Sharing::new(c, roots)
+    };
+
+    // is the value of `t` in the model inconsistent with the partition?
+    let inconsistent = |t: &AST, value: bool| -> Option<bool> {
+        let reprs: Option<Vec<AST>> = sides(t).iter().map(|u| repr(u)).collect();
+        let reprs = reprs?;
+        if reprs.len() == 2 {
+            Some((reprs[0] == reprs[1]) != value)
+        } else {
+            let r_true = repr(&c.b.true_)?;
+            let r_false = repr(&c.b.false_)?;
+            Some((value && reprs[0] == r_false) || (!value && reprs[0] == r_true))
+        }
+    };
+
+    let mut lines = vec!();
+    let mut n_inconsistent = 0;
+    for (t, lit) in atoms.iter() {
+        let value = s.value_in_model(*lit);
+        let value = if value == lbool::TRUE { Some(true) }
+            else if value == lbool::FALSE { Some(false) } else { None };
+        let bad = value.and_then(|v| inconsistent(t, v));
+        if bad == Some(true) { n_inconsistent += 1 }
+        let line = fun(|ctx| {
+            ctx.sexp(|ctx| {
+                ctx.str("atom").space().pp(&Term(&sh, *t, false));
+                match value {
+                    Some(b) => ctx.str(" :value ").display(b),
+                    None => ctx.str(" :value undef"),
+                };
+                ctx.str(" :sides ").sexp(|ctx| {
+                    for (i, u) in sides(t).iter().enumerate() {
+                        if i > 0 { ctx.space(); }
+                        ctx.sexp(|ctx| {
+                            ctx.pp(&sh.pp(*u)).space();
+                            match repr(u) {
+                                Some(r) => ctx.pp(&sh.pp(r)),
+                                None => ctx.str("?"),
+                            };
+                        });
+                    }
+                });
+                if let Some(reason) = assigned.get(t).and_then(|a| a.reason.as_ref()) {
+                    ctx.str(" :reason ").sexp(|ctx| {
+                        for (i, lit) in reason.iter().enumerate() {
+                            if i > 0 { ctx.space(); }
+                            ctx.pp(&pp_lit(&sh, s, *lit));
+                        }
+                    });
+                }
+                match bad {
+                    Some(true) => { ctx.str(" :inconsistent"); },
+                    None if st.is_some() => { ctx.str(" :unchecked"); },
+                    _ => (),
+                }
+            });
+        });
+        lines.push(pp::display(line).to_string());
+    }
+
+    writeln!(out, "; {} theory atoms, {} inconsistent with the partition", atoms.len(), n_inconsistent)?;
+    if st.is_none() {
+        writeln!(out, "; no theory state was recorded")?;
+    }
+    for (i, t) in sh.defs.iter().enumerate() {
+        writeln!(out, "(define-term _t{} {})", i, pp::display(Term(&sh, *t, true)))?;
+    }
+    for l in lines {
+        writeln!(out, "{}", l)?;
+    }
+    for cl in st.iter().flat_map(|st| st.classes.iter()) {
+        let line = fun(|ctx| {
+            ctx.sexp(|ctx| {
+                ctx.str("class");
+                for t in cl { ctx.space().pp(&sh.pp(*t)); }
+            });
+        });
+        writeln!(out, "{}", pp::display(line))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! Checks on parsed statements, before they are executed.
+//!
+//! The parser already rejects undeclared symbols. This also finds functions
+//! applied to the wrong number of arguments, `=` and `distinct` with fewer
+//! than two arguments, and `ite` whose condition is not a boolean.
+
+use {
+    batsmt_core::{ast_u32::AST, AstView, Manager, Error, error::Pos},
+    batsmt_parser::{Statement, Span},
+    batsmt_pretty as pp,
+    fxhash::{FxHashMap, FxHashSet},
+    crate::{Ctx, ast_builder::Symbols},
+};
+
+/// Problems found in the terms of `stmts`, each located by its statement.
+///
+/// All the statements are checked; a subterm shared by several statements
+/// is only reported at its first occurrence.
+pub(crate) fn check(c: &Ctx, syms: &Symbols, stmts: &[(Span, Statement<AST,AST>)]) -> Vec<Error> {
+    let arities: FxHashMap<AST, usize> =
+        syms.funs().into_iter().map(|(_, f, args, _)| (f, args.len())).collect();
+    let mut seen = FxHashSet::default();
+    let mut todo = vec!();
+    let mut errs = vec!();
+    for (span, st) in stmts {
+        match st {
+            Statement::Assert(t) | Statement::AssertLemma(t) => todo.push(*t),
+            Statement::CheckSatAssumptions(v) | Statement::GetValue(v) => todo.extend_from_slice(v),
+            _ => continue,
+        }
+        while let Some(t) = todo.pop() {
+            if !seen.insert(t) { continue }
+            let msg = match c.m.view(&t) {
+                AstView::App{f, args} => {
+                    // `f` itself is not visited: it is not used as a constant
+                    todo.extend_from_slice(args);
+                    check_app(c, &arities, t, *f, args)
+                },
+                // `mk_app` returns the symbol itself when there are no arguments
+                _ => check_app(c, &arities, t, t, &[]),
+            };
+            if let Some(msg) = msg {
+                // statements are only located by line
+                let pos = Pos{line: span.start_line, col: 1};
+                errs.push(Error::Parse{pos: Some(pos), msg});
+            }
+        }
+    }
+    errs
+}
+
+// problem with `t`, which is `f` applied to `args`
+fn check_app(
+    c: &Ctx, arities: &FxHashMap<AST, usize>, t: AST, f: AST, args: &[AST]
+) -> Option<String> {
+    let b = &c.b;
+    if f == b.eq || f == b.distinct {
+        if args.len() < 2 {
+            return Some(format!("`{}` expects at least 2 arguments, got {}, in `{}`",
+                pp::pp1(c, &f), args.len(), pp::pp1(c, &t)))
+        }
+    } else if f == b.ite {
+        match c.m.ty(&args[0]) {
+            Some(ty) if ty != b.bool_ => {
+                return Some(format!("the condition of `{}` has sort `{}`, expected `Bool`",
+                    pp::pp1(c, &t), pp::pp1(c, &ty)))
+            },
+            _ => (),
+        }
+    } else if let Some(&n) = arities.get(&f) {
+        if n != args.len() {
+            return Some(format!("`{}` expects {} argument{}, got {}, in `{}`",
+                pp::pp1(c, &f), n, if n == 1 { "" } else { "s" },
+                args.len(), pp::pp1(c, &t)))
+        }
+    }
+    None
+}
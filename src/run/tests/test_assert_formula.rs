@@ -0,0 +1,70 @@
+use {
+    batsmt_core::{ast_u32::AST, Manager},
+    batsmt_solver::{Res, Solver},
+    batsmt_run::{Ctx, driver::Th},
+};
+
+fn new_solver(c: &mut Ctx) -> Solver<Ctx, Th> {
+    let th = Th::new(c);
+    Solver::new(c.builtins(), th)
+}
+
+// `p`, `q`, `f(a)=b`, `(f(a) = b) => (p = (a = b))`
+fn setup(c: &mut Ctx) -> [AST; 4] {
+    let b = c.b.clone();
+    let p = c.m.mk_str("p", Some(b.bool_));
+    let q = c.m.mk_str("q", Some(b.bool_));
+    let ty = c.m.mk_str("U", None);
+    let f = c.m.mk_str("f", None);
+    let a = c.m.mk_str("a", Some(ty));
+    let b_ = c.m.mk_str("b", Some(ty));
+    let fa = c.m.mk_app(f, &[a], Some(ty));
+    let fa_b = c.m.mk_app(b.eq, &[fa, b_], Some(b.bool_));
+    let ab = c.m.mk_app(b.eq, &[a, b_], Some(b.bool_));
+    let p_ab = c.m.mk_app(b.eq, &[p, ab], Some(b.bool_));
+    let imp = c.m.mk_app(b.imply_, &[fa_b, p_ab], Some(b.bool_));
+    [p, q, fa_b, imp]
+}
+
+#[test]
+fn test_incremental() {
+    let mut c = Ctx::new();
+    let mut s = new_solver(&mut c);
+    let [p, q, fa_b, imp] = setup(&mut c);
+    let not_ = |c: &mut Ctx, t: AST| c.m.mk_app(c.b.not_, &[t], Some(c.b.bool_));
+    let p_or_q = c.m.mk_app(c.b.or_, &[p, q], Some(c.b.bool_));
+
+    s.assert_formula(&mut c, imp).unwrap();
+    s.assert_formula(&mut c, p_or_q).unwrap();
+    assert_eq!(Res::SAT, s.solve(&mut c).unwrap());
+
+    let not_q = not_(&mut c, q);
+    s.assert_formula(&mut c, fa_b).unwrap();
+    s.assert_formula(&mut c, not_q).unwrap();
+    assert_eq!(Res::SAT, s.solve(&mut c).unwrap());
+
+    // the encoding is shared: asserting a formula again adds nothing
+    let n_clauses = s.n_clauses();
+    s.assert_formula(&mut c, imp).unwrap();
+    assert_eq!(n_clauses, s.n_clauses());
+
+    // `p` must hold, since `q` does not
+    let not_p = not_(&mut c, p);
+    s.assert_formula(&mut c, not_p).unwrap();
+    assert_eq!(Res::UNSAT, s.solve(&mut c).unwrap());
+}
+
+#[test]
+fn test_named() {
+    let mut c = Ctx::new();
+    let mut s = new_solver(&mut c);
+    let [p, q, fa_b, imp] = setup(&mut c);
+    let not_p = c.m.mk_app(c.b.not_, &[p], Some(c.b.bool_));
+    for (i, &t) in [q, imp, fa_b, not_p].iter().enumerate() {
+        s.assert_formula_named(&mut c, t, i).unwrap();
+    }
+    assert_eq!(Res::UNSAT, s.solve(&mut c).unwrap());
+    let mut core = s.unsat_core_indices();
+    core.sort();
+    assert_eq!(core, vec![1, 2, 3]);
+}
@@ -0,0 +1,82 @@
+
+use {
+    batsmt_core::{Manager, AttrKey},
+    batsmt_run::{Driver, DriverConfig, Response},
+};
+
+// `f(a,b) = c`, `f(b,a) != c`, `b = d`, `f(d,a) != c`: unsat only if `f` is commutative,
+// which is only known from the annotation.
+#[test]
+fn test_commutative_annotation() {
+    let pb = |annot: &str| format!(r#"
+        (declare-sort U 0)
+        (declare-fun f (U U) U)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun c () U)
+        (declare-fun d () U)
+        (declare-fun p () Bool)
+        (assert (= {} c))
+        (assert (= b d))
+        (assert (or p (not (= (f d a) c))))
+        (assert (not p))
+        (check-sat)
+    "#, annot);
+    for &preprocess in &[true, false] {
+        let mut d = Driver::new(DriverConfig{preprocess, ..Default::default()});
+        let res = d.execute_script(pb("(f a b)").as_bytes());
+        assert_eq!(Some(&Response::Sat), res.last());
+
+        let mut d = Driver::new(DriverConfig{preprocess, ..Default::default()});
+        let res = d.execute_script(pb("(! (f a b) :commutative)").as_bytes());
+        assert_eq!(Some(&Response::Unsat), res.last());
+    }
+}
+
+// `g(a) = g(b)` implies `a = b` if `g` is injective
+#[test]
+fn test_injective_annotation() {
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script(r#"
+        (declare-sort U 0)
+        (declare-fun g (U) U)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun p () Bool)
+        (assert (or p (= (! (g a) :injective) (g b))))
+        (assert (not p))
+        (assert (not (= a b)))
+        (check-sat)
+    "#.as_bytes());
+    assert_eq!(Some(&Response::Unsat), res.last());
+}
+
+// named boolean terms are part of the model, if they occur in the problem
+#[test]
+fn test_named_in_model() {
+    let mut d = Driver::new(DriverConfig{preprocess: false, ..Default::default()});
+    let res = d.execute_script(r#"
+        (set-option :produce-models true)
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun p () Bool)
+        (assert (or p (! (= a b) :named a_eq_b)))
+        (assert (not p))
+        (check-sat)
+        (get-model)
+    "#.as_bytes());
+    match res.last() {
+        Some(Response::Model(m)) => {
+            assert_eq!(m.bools(), vec![("p".to_string(), false), ("a_eq_b".to_string(), true)]);
+        },
+        r => panic!("expected a model, got {:?}", r),
+    }
+
+    // the name is an attribute of the term
+    let c = d.ctx();
+    let named: Vec<_> =
+        c.m.attrs().unwrap().iter_key(AttrKey::NAMED)
+        .map(|(_, v)| v.to_string()).collect();
+    assert_eq!(named, vec!["a_eq_b".to_string()]);
+}
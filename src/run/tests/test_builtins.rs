@@ -0,0 +1,53 @@
+use {batsmt_run::Ctx, batsmt_core::Manager};
+
+#[test]
+fn test_default_builtins_valid() {
+    let c = Ctx::new();
+    c.b.validate_types(&c.m).unwrap();
+}
+
+#[test]
+fn test_true_not_bool() {
+    let mut c = Ctx::new();
+    let ty = c.m.mk_str("U", None);
+    let mut b = c.b.clone();
+    b.true_ = c.m.mk_str("true", Some(ty));
+    let e = b.validate_types(&c.m).expect_err("true is not a boolean");
+    assert!(e.starts_with("true:"), "{}", e);
+}
+
+#[test]
+fn test_symbols_distinct() {
+    let c = Ctx::new();
+    let mut b = c.b.clone();
+    b.or_ = b.and_;
+    let e = b.validate_types(&c.m).expect_err("and = or");
+    assert_eq!(e, "and and or are the same symbol");
+}
+
+// the manager is collected, not rebuilt: the same terms fit in the same memory
+#[test]
+fn test_reset_reuses_memory() {
+    use batsmt_core::mem;
+    let mut c = Ctx::new();
+    let build = |c: &mut Ctx| {
+        let bool_ = c.b.bool_;
+        let ps: Vec<_> = (0 .. 50).map(|i| c.m.mk_string(format!("p{}", i), Some(bool_))).collect();
+        for w in ps.windows(2) {
+            let t = c.m.mk_app(c.b.and_, w, Some(bool_));
+            c.m.mk_app(c.b.not_, &[t], Some(bool_));
+        }
+    };
+    build(&mut c);
+    let n_terms = c.m.n_terms();
+    let mut bytes = None; // after the first reset, which allocates the free lists
+    for i in 0 .. 100 {
+        let n = c.reset();
+        assert_eq!(n_terms, n + 10, "all but the builtins are collected");
+        c.b.validate_types(&c.m).unwrap();
+        build(&mut c);
+        assert_eq!(n_terms, c.m.n_terms());
+        let b = mem::report("manager", &c.m).total();
+        assert_eq!(*bytes.get_or_insert(b), b, "memory grew at cycle {}", i);
+    }
+}
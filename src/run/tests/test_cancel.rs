@@ -0,0 +1,57 @@
+
+use {
+    std::{thread, time::{Duration, Instant}},
+    batsmt_solver::{CancelToken, ReasonUnknown},
+    batsmt_run::{Driver, DriverConfig, Response},
+};
+
+// pigeon-hole with `n+1` pigeons and `n` holes, guarded by `g`
+fn pigeon_hole(n: usize) -> String {
+    let p = |i: usize, j: usize| format!("p_{}_{}", i, j);
+    let mut s = String::from("(declare-fun g () Bool)\n");
+    for i in 0 ..= n {
+        for j in 0 .. n {
+            s.push_str(&format!("(declare-fun {} () Bool)\n", p(i,j)));
+        }
+    }
+    for i in 0 ..= n {
+        let holes: Vec<String> = (0 .. n).map(|j| p(i,j)).collect();
+        s.push_str(&format!("(assert (or (not g) {}))\n", holes.join(" ")));
+    }
+    for j in 0 .. n {
+        for i1 in 0 ..= n {
+            for i2 in i1+1 ..= n {
+                s.push_str(&format!("(assert (or (not g) (not {}) (not {})))\n", p(i1,j), p(i2,j)));
+            }
+        }
+    }
+    s
+}
+
+#[test]
+fn test_cancel_then_solve_again() {
+    let mut d = Driver::new(DriverConfig::default());
+    let tok = CancelToken::new();
+    d.set_cancel_token(tok.clone());
+    let res = d.execute_script(pigeon_hole(12).as_bytes());
+    assert!(res.iter().all(|r| *r == Response::Success), "{:?}", res);
+
+    let watchdog = {
+        let tok = tok.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            tok.cancel();
+        })
+    };
+    let start = Instant::now();
+    let res = d.execute_script("(check-sat-assumptions g)".as_bytes());
+    let elapsed = start.elapsed();
+    watchdog.join().unwrap();
+    assert_eq!(Some(&Response::Unknown(ReasonUnknown::Canceled)), res.last());
+    assert!(elapsed < Duration::from_secs(2), "took {:?} to cancel", elapsed);
+
+    // the solver is still usable, with the declarations and assertions of the first call
+    tok.reset();
+    let res = d.execute_script("(check-sat-assumptions (not g)) (check-sat)".as_bytes());
+    assert_eq!(vec![Response::Sat, Response::Sat], res);
+}
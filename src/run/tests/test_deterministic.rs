@@ -0,0 +1,38 @@
+// Reproducibility of runs, only with the `deterministic` feature.
+#![cfg(feature="deterministic")]
+
+use {
+    std::fs,
+    batsmt_run::{Driver, DriverConfig, encoding_stats},
+    batsmt_pretty as pp,
+};
+
+const FILE: &str = "../../benchs/unsat/PEQ012_size3.smt2";
+
+// answers, what `--stats` and `--stats-encoding` print, and the clauses
+// learnt by the SAT solver
+fn run(input: &str) -> String {
+    let mut d = Driver::new(DriverConfig{stats_encoding: true, ..Default::default()});
+    d.solver_mut().set_track_state(true);
+    let res = d.execute_script(input.as_bytes());
+    let mut out = format!("{:?}\n", res);
+    for (k, v) in d.stats() {
+        out.push_str(&format!("{}: {}\n", k, v));
+    }
+    out.push_str(&format!("{}\n", d.solver().tseitin().stats()));
+    out.push_str(&d.memory_report().to_string());
+    out.push_str(&pp::display(encoding_stats::table(&d.encoding_stats())).to_string());
+    for c in d.solver().learned_clauses() {
+        out.push_str(&format!("{:?}\n", c));
+    }
+    out
+}
+
+#[test]
+fn test_same_output_twice() {
+    let input = fs::read_to_string(FILE).unwrap();
+    let out1 = run(&input);
+    let out2 = run(&input);
+    assert!(out1.contains("Unsat"), "{}", out1);
+    assert_eq!(out1, out2);
+}
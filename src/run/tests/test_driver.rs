@@ -0,0 +1,740 @@
+use {
+    batsmt_core::{ast_u32::AST, AstView, Manager},
+    batsmt_parser::Statement,
+    batsmt_run::{Driver, DriverConfig, Response},
+    batsmt_solver::{RestartPolicy, ReasonUnknown},
+};
+
+/// Pigeon-hole problem: `n+1` pigeons in `n` holes (unsat).
+fn pigeon_hole(n: usize) -> String { pigeons(n+1, n) }
+
+/// `n_pigeons` pigeons in `n_holes` holes, at most one per hole.
+fn pigeons(n_pigeons: usize, n_holes: usize) -> String {
+    let p = |i: usize, j: usize| format!("p_{}_{}", i, j);
+    let mut s = String::new();
+    for i in 0 .. n_pigeons {
+        for j in 0 .. n_holes {
+            s.push_str(&format!("(declare-fun {} () Bool)\n", p(i,j)));
+        }
+    }
+    // each pigeon has a hole
+    for i in 0 .. n_pigeons {
+        let holes: Vec<_> = (0 .. n_holes).map(|j| p(i,j)).collect();
+        s.push_str(&format!("(assert (or {}))\n", holes.join(" ")));
+    }
+    // at most one pigeon per hole
+    for j in 0 .. n_holes {
+        for i1 in 0 .. n_pigeons {
+            for i2 in i1+1 .. n_pigeons {
+                s.push_str(&format!("(assert (or (not {}) (not {})))\n", p(i1,j), p(i2,j)));
+            }
+        }
+    }
+    s.push_str("(check-sat)\n");
+    s
+}
+
+#[test]
+fn test_max_learnts_bounded() {
+    let max_learnts = 100;
+    let n = 7;
+    let mut d = Driver::new(DriverConfig{max_learnts: Some(max_learnts), ..Default::default()});
+    let res = d.execute_script(pigeon_hole(n).as_bytes());
+    assert_eq!(Some(&Response::Unsat), res.last());
+
+    let s = d.solver();
+    assert!(s.n_conflicts() > 10 * max_learnts,
+            "expected many conflicts, got {}", s.n_conflicts());
+    // reduction happens once the number of learnt clauses, minus the
+    // number of assigned literals, exceeds the limit.
+    let bound = max_learnts + s.n_lits() + 1;
+    assert!(s.n_learnts_peak() <= bound,
+            "peak of {} learnt clauses, bound is {}", s.n_learnts_peak(), bound);
+}
+
+#[test]
+fn test_ill_sorted_eq() {
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script(r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (assert (or q (= a p)))
+        (check-sat-assumptions (not q))
+    "#.as_bytes());
+    match res.last() {
+        Some(Response::Error(msg)) => {
+            assert!(msg.contains("`a`") || msg.contains("`p`"), "bad message {:?}", msg);
+            assert!(msg.contains("`Bool`") && msg.contains("`U`"), "bad message {:?}", msg);
+            assert!(msg.contains("assertion (or q (= a p))"), "bad message {:?}", msg);
+        },
+        r => panic!("expected an error, got {:?}", r),
+    }
+
+    // the solver is still usable, and reports the same error again
+    let res2 = d.execute_script("(check-sat)".as_bytes());
+    assert_eq!(res.last(), res2.last());
+}
+
+#[test]
+fn test_restart_policies_agree() {
+    let policies = [
+        RestartPolicy::default(),
+        RestartPolicy::Luby{base: 10},
+        RestartPolicy::Geometric{base: 10, factor: 1.5},
+        RestartPolicy::None,
+    ];
+    let problems = [
+        (pigeons(6, 5), Response::Unsat),
+        (pigeons(7, 6), Response::Unsat),
+        (pigeons(7, 7), Response::Sat),
+    ];
+    for (pb, expected) in problems.iter() {
+        for &restarts in &policies {
+            let mut d = Driver::new(DriverConfig{restarts, ..Default::default()});
+            let res = d.execute_script(pb.as_bytes());
+            assert_eq!(Some(expected), res.last(), "with {:?}", restarts);
+            if restarts == RestartPolicy::None {
+                assert_eq!(0, d.solver().n_restarts());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_congruence_only_agrees() {
+    let euf = |goal: &str| format!(r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun c () U)
+        (declare-fun f (U) U)
+        (declare-fun p (U) Bool)
+        (assert (or (= a b) (= a c)))
+        (assert (= (f b) (f c)))
+        (assert (p (f a)))
+        (assert {})
+        (check-sat)
+    "#, goal);
+    let problems = [
+        (euf("(not (p (f b)))"), Response::Unsat),
+        (euf("(not (p b))"), Response::Sat),
+        (pigeons(5, 4), Response::Unsat),
+        (pigeons(5, 5), Response::Sat),
+    ];
+    for (pb, expected) in problems.iter() {
+        for &propagate in &[true, false] {
+            let mut d = Driver::new(DriverConfig{propagate, preprocess: false, ..Default::default()});
+            let res = d.execute_script(pb.as_bytes());
+            assert_eq!(Some(expected), res.last(), "propagate: {}", propagate);
+            if !propagate {
+                let n_props = d.stats().into_iter().find(|(k,_)| *k == "theory_propagations");
+                assert_eq!(Some(("theory_propagations", 0)), n_props);
+            }
+        }
+    }
+}
+
+// only `p` and `(not p)` are needed for the refutation
+#[test]
+fn test_unsat_assertions() {
+    let mut d = Driver::new(DriverConfig{explain_unsat: true, ..Default::default()});
+    let res = d.execute_script(r#"
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (declare-fun r () Bool)
+        (assert (or q r))
+        (assert p)
+        (assert (or (not q) r))
+        (assert
+          (not p))
+        (assert (or q (not r)))
+        (check-sat)
+    "#.as_bytes());
+    assert_eq!(Some(&Response::Unsat), res.last());
+
+    let used = d.unsat_assertions();
+    assert!(used.contains(&1) && used.contains(&3), "used: {:?}", used);
+    assert!(used.len() < 5, "used: {:?}", used);
+
+    let (_, span) = d.assertion(3);
+    let span = span.expect("span");
+    assert_eq!((span.start_line, span.end_line), (8, 9));
+}
+
+// `p ∨ q ∨ r` has 7 models over `p, q, r`
+#[test]
+fn test_enumerate_models() {
+    let mut d = Driver::new(DriverConfig{lenient: true, ..Default::default()});
+    let res = d.execute_script(r#"
+        (set-option :produce-models true)
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (declare-fun r () Bool)
+        (assert (or p q r))
+        (check-sat)
+    "#.as_bytes());
+    assert_eq!(Some(&Response::Sat), res.last());
+
+    let mut models = vec!();
+    loop {
+        match d.execute_script("(get-model)".as_bytes()).pop() {
+            Some(Response::Model(m)) => {
+                let m = m.bools();
+                assert_eq!(m.len(), 3, "partial model {:?}", m);
+                assert!(!models.contains(&m), "duplicate model {:?}", m);
+                models.push(m);
+            },
+            r => panic!("expected a model, got {:?}", r),
+        }
+        match d.execute_script("(get-next-model)".as_bytes()).pop() {
+            Some(Response::Sat) => (),
+            Some(Response::Unsat) => break,
+            r => panic!("unexpected answer {:?}", r),
+        }
+        assert!(models.len() <= 7);
+    }
+    assert_eq!(models.len(), 7);
+    assert!(models.iter().all(|m| m.iter().any(|(_, b)| *b)));
+}
+
+#[test]
+fn test_next_model_needs_lenient() {
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script("(check-sat) (get-next-model)".as_bytes());
+    assert_eq!(Some(&Response::Unsupported), res.last());
+}
+
+// `x_0 = x_1 = … = x_n`, along with boolean constants depending on it
+#[test]
+fn test_preprocess_chain() {
+    let n = 1_000;
+    let mut s = String::from("(set-option :produce-models true)\n(declare-sort U 0)\n");
+    for i in 0 ..= n {
+        s.push_str(&format!("(declare-fun x_{} () U)\n", i));
+    }
+    s.push_str("(declare-fun p () Bool)\n(declare-fun q () Bool)\n");
+    for i in 0 .. n {
+        s.push_str(&format!("(assert (= x_{} x_{}))\n", i, i+1));
+    }
+    s.push_str(&format!("(assert (or p (not (= x_0 x_{}))))\n", n));
+    s.push_str("(assert (= q p))\n(check-sat)\n(get-model)\n");
+
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script(s.as_bytes());
+    assert_eq!(Some(&Response::Sat), res.get(res.len()-2));
+
+    let stats = *d.preprocessor().stats();
+    assert!(stats.eliminated >= n, "stats: {}", stats);
+    assert!(stats.dropped >= n, "stats: {}", stats);
+    assert!(!stats.unsat);
+    // `q` was eliminated, its value comes from `p`
+    match res.last() {
+        Some(Response::Model(m)) => {
+            let mut m = m.bools();
+            m.sort();
+            assert_eq!(m, vec![("p".to_string(), true), ("q".to_string(), true)]);
+        },
+        r => panic!("expected a model, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_preprocess_unsat() {
+    let pb = r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun c () U)
+        (declare-fun f (U) U)
+        (assert (= a b))
+        (assert (and (= b c) (not (= (f a) (f c)))))
+        (check-sat)
+    "#;
+    for &preprocess in &[true, false] {
+        let mut d = Driver::new(DriverConfig{preprocess, ..Default::default()});
+        let res = d.execute_script(pb.as_bytes());
+        assert_eq!(Some(&Response::Unsat), res.last());
+        assert_eq!(preprocess, d.preprocessor().stats().unsat);
+    }
+}
+
+#[test]
+fn test_memory_report() {
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script(pigeon_hole(6).as_bytes());
+    assert_eq!(Some(&Response::Unsat), res.last());
+    let r = d.memory_report();
+    for name in &[
+        "manager/nodes", "manager/symbols", "tseitin/caches", "solver/theory/cc/union-find",
+        "solver/lit-map", "solver/clause-db/clauses", "solver/clause-db/vars",
+    ] {
+        assert!(r.get(name).map_or(0, |t| t.total()) > 0, "{}: empty\n{}", name, r);
+    }
+}
+
+// the limit is checked before running the solver
+#[test]
+fn test_memory_limit() {
+    let pb = pigeon_hole(4);
+    let mut d = Driver::new(DriverConfig{memory_limit: Some(1 << 10), ..Default::default()});
+    let res = d.execute_script(pb.as_bytes());
+    assert_eq!(Some(&Response::Unknown(ReasonUnknown::ResourceOut)), res.last());
+
+    let mut d = Driver::new(DriverConfig{memory_limit: Some(1 << 30), ..Default::default()});
+    let res = d.execute_script(pb.as_bytes());
+    assert_eq!(Some(&Response::Unsat), res.last());
+}
+
+#[cfg(feature="simp-trace")]
+#[test]
+fn test_simp_trace() {
+    let mut d = Driver::new(DriverConfig{trace_simp: true, ..Default::default()});
+    let res = d.execute_script(r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun c () U)
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (assert (=> p (and q (or p q))))
+        (assert (or (and p (not p)) (distinct a b c)))
+        (check-sat)
+    "#.as_bytes());
+    assert_eq!(Some(&Response::Sat), res.last(), "{:?}", res);
+    let mut out = vec!();
+    d.write_simp_trace(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("(imply-to-or "), "{}", out);
+    assert!(out.contains("(distinct-expand "), "{}", out);
+    assert!(out.ends_with(" too large to check\n"), "{}", out);
+}
+
+// each assertion uses its own symbols, so nothing is shared
+#[test]
+fn test_encoding_stats() {
+    let mut s = String::from(r#"
+        (declare-sort U 0)
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (declare-fun a () U)
+        (declare-fun b () U)
+    "#);
+    let n = 20;
+    for i in 0 .. n {
+        s.push_str(&format!("(declare-fun x_{} () Bool)\n(declare-fun y_{} () Bool)\n", i, i));
+    }
+    let big: Vec<_> = (0 .. n).map(|i| format!("(and x_{} y_{})", i, i)).collect();
+    s.push_str(&format!("(assert (or {}))\n", big.join(" ")));
+    s.push_str("(assert (! (or p q) :named small))\n");
+    s.push_str("(assert (and (or (= a b) p) (or (not (= a b)) q)))\n");
+    for &preprocess in &[true, false] {
+        let mut d = Driver::new(DriverConfig{stats_encoding: true, preprocess, ..Default::default()});
+        let mut res = d.execute_script(s.as_bytes());
+        if preprocess {
+            // assertions are only encoded by `check-sat`
+            res = d.execute_script("(check-sat)".as_bytes());
+        }
+        assert!(!res.iter().any(|r| matches!(r, Response::Error(_))), "{:?}", res);
+
+        let st = d.encoding_stats();
+        assert_eq!(st.iter().map(|s| s.idx).collect::<Vec<_>>(), vec![0, 2, 1]);
+        assert!(st[0].clauses > 5 * st[1].clauses, "{:?}", st);
+        // `or(p,q)`: 2 clauses `p => or`, `q => or`, one `or => p ∨ q`, and the unit
+        let small = &st[2];
+        assert_eq!(small.name.as_deref(), Some("small"));
+        assert_eq!((small.def_lits, small.clauses, small.max_clause_len, small.atoms), (1, 4, 3, 2));
+    }
+}
+
+#[test]
+fn test_unused_symbols() {
+    let pb = r#"
+        (declare-sort U 0)
+        (declare-sort V 0)
+        (declare-fun f (U) U)
+        (declare-fun a () U)
+        (declare-fun g (V) Bool)
+        (declare-fun v () V)
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (assert (= (f a) a))
+        (check-sat-assumptions q)
+    "#;
+    let mut d = Driver::new(DriverConfig{warn_unused: true, ..Default::default()});
+    let res = d.execute_script(pb.as_bytes());
+    assert!(!res.iter().any(|r| matches!(r, Response::Error(_))), "{:?}", res);
+    let unused: Vec<_> =
+        d.unused_symbols().into_iter().map(|(kind, name)| format!("{} {}", kind, name)).collect();
+    assert_eq!(unused, vec!["sort V", "function g", "function p", "function v"]);
+
+    // symbols declared by the first call
+    let res = d.execute_script("(assert (or p (g v)))".as_bytes());
+    assert_eq!(vec![Response::Success], res);
+    assert_eq!(d.unused_symbols(), vec!());
+}
+
+// `p∧q` under `f` gets the value of its literal in the congruence closure
+#[test]
+fn test_bool_arg_unsat() {
+    let pb = r#"
+        (declare-sort U 0)
+        (declare-fun f (Bool) U)
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (declare-fun r () Bool)
+        (assert (and p q))
+        (assert r)
+        (assert (not (= (f (and p q)) (f r))))
+        (check-sat)
+    "#;
+    for &preprocess in &[true, false] {
+        let mut d = Driver::new(DriverConfig{preprocess, ..Default::default()});
+        let res = d.execute_script(pb.as_bytes());
+        assert_eq!(Some(&Response::Unsat), res.last(), "preprocess: {}", preprocess);
+    }
+}
+
+// same with a boolean `ite`, which is not otherwise used as a formula
+#[test]
+fn test_bool_ite_arg_unsat() {
+    let pb = r#"
+        (declare-sort U 0)
+        (declare-fun f (Bool) U)
+        (declare-fun c () Bool)
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (declare-fun r () Bool)
+        (assert p)
+        (assert q)
+        (assert r)
+        (assert (not (= (f (ite c p q)) (f r))))
+        (check-sat)
+    "#;
+    for &preprocess in &[true, false] {
+        let mut d = Driver::new(DriverConfig{preprocess, ..Default::default()});
+        let res = d.execute_script(pb.as_bytes());
+        assert_eq!(Some(&Response::Unsat), res.last(), "preprocess: {}", preprocess);
+    }
+}
+
+// declarations outlive the call that made them
+#[test]
+fn test_declarations_across_calls() {
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script("(declare-sort U 0) (declare-fun a () U) (declare-fun f (U) U)".as_bytes());
+    assert_eq!(vec![Response::Success; 3], res);
+    let res = d.execute_script(r#"
+        (declare-fun b () U)
+        (assert (= a b))
+        (assert (not (= (f a) (f b))))
+        (check-sat)
+    "#.as_bytes());
+    assert_eq!(Some(&Response::Unsat), res.last(), "{:?}", res);
+    // they cannot be declared again
+    let res = d.execute_script("(declare-fun a () U)".as_bytes());
+    match &res[..] {
+        [Response::Error(msg)] => assert!(msg.contains("function a is already declared"), "{}", msg),
+        r => panic!("expected an error, got {:?}", r),
+    }
+    let res = d.execute_script("(declare-sort U 0)".as_bytes());
+    match &res[..] {
+        [Response::Error(msg)] => assert!(msg.contains("sort U is already declared"), "{}", msg),
+        r => panic!("expected an error, got {:?}", r),
+    }
+}
+
+mod scopes {
+    use super::*;
+
+    // execute `pb`, and return its only assertion
+    fn assert_one(d: &mut Driver, pb: &str) -> AST {
+        let mut res = None;
+        for st in d.parse(pb.as_bytes()).unwrap() {
+            if let Statement::Assert(t) = st { res = Some(t) }
+            assert_eq!(Response::Success, d.execute(st));
+        }
+        res.expect("no assertion")
+    }
+
+    // head symbol of `t`
+    fn head(d: &Driver, t: AST) -> AST {
+        match d.ctx().m.view(&t) {
+            AstView::App{f, ..} => *f,
+            _ => t,
+        }
+    }
+
+    #[test]
+    fn test_redeclare_after_pop() {
+        let mut d = Driver::new(DriverConfig::default());
+        d.execute_script("(declare-sort A 0) (declare-sort B 0)".as_bytes());
+        d.push_scope();
+        let t1 = assert_one(&mut d, "(declare-fun f (A) Bool) (declare-fun c () A) (assert (f c))");
+        assert!(d.pop_scope());
+        assert!(!d.pop_scope());
+        // `f` is not visible anymore
+        let res = d.execute_script("(assert (f c))".as_bytes());
+        match res.last() {
+            Some(Response::Error(msg)) => assert!(msg.contains("f is not a known function"), "{}", msg),
+            r => panic!("expected an error, got {:?}", r),
+        }
+
+        d.push_scope();
+        let t2 = assert_one(&mut d, "(declare-fun f (B) Bool) (declare-fun c () B) (assert (not (f c)))");
+        let t2 = match d.ctx().m.view(&t2) {
+            AstView::App{args, ..} => args[0],
+            _ => panic!("expected a negation"),
+        };
+        assert_ne!(head(&d, t1), head(&d, t2));
+        assert_ne!(t1, t2);
+        // `(f c)` and `(not (f c))` are about different `f` and `c`
+        let res = d.execute_script("(check-sat)".as_bytes());
+        assert_eq!(vec![Response::Sat], res);
+        // declarations of the outer scope are kept
+        let res = d.execute_script("(declare-sort A 0)".as_bytes());
+        assert!(matches!(res.last(), Some(Response::Error(_))), "{:?}", res);
+    }
+}
+
+mod skip_satisfied {
+    use super::*;
+
+    fn driver() -> Driver {
+        Driver::new(DriverConfig{skip_satisfied: true, ..Default::default()})
+    }
+
+    const PREFIX: &str = r#"
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (declare-fun r () Bool)
+        (assert (or p r))
+        (check-sat)
+    "#;
+
+    #[test]
+    fn test_no_new_clauses() {
+        let mut d = driver();
+        let res = d.execute_script(PREFIX.as_bytes());
+        assert_eq!(Some(&Response::Sat), res.last());
+        let before = d.solver().clause_counts().input;
+        let res = d.execute_script("(assert (or p q r))".as_bytes());
+        assert_eq!(vec![Response::Success], res);
+        assert_eq!(before, d.solver().clause_counts().input);
+    }
+
+    // whatever the model, some of these assertions are deferred;
+    // they must still be clausified once they stop holding
+    #[test]
+    fn test_deferred() {
+        let mut d = driver();
+        let res = d.execute_script(PREFIX.as_bytes());
+        assert_eq!(Some(&Response::Sat), res.last());
+        let res = d.execute_script("(assert (or p q)) (assert (or (not p) q)) (check-sat)".as_bytes());
+        assert_eq!(vec![Response::Success, Response::Success, Response::Sat], res);
+        let res = d.execute_script("(assert (not q)) (check-sat)".as_bytes());
+        assert_eq!(vec![Response::Success, Response::Unsat], res);
+    }
+}
+
+mod trivial {
+    use super::*;
+
+    fn check(pb: &str, expected: Response) {
+        for &preprocess in &[true, false] {
+            let mut d = Driver::new(DriverConfig{preprocess, ..Default::default()});
+            let res = d.execute_script(pb.as_bytes());
+            assert_eq!(Some(&expected), res.last(), "preprocess: {}\n{}", preprocess, pb);
+        }
+    }
+
+    #[test]
+    fn test_no_assertion() {
+        check("(declare-fun p () Bool) (check-sat)", Response::Sat);
+
+        let mut d = Driver::new(DriverConfig::default());
+        let res = d.execute_script("(set-option :produce-models true) (check-sat) (get-model)".as_bytes());
+        assert_eq!(Some(&Response::Sat), res.get(1));
+        match res.last() {
+            Some(Response::Model(m)) => assert!(m.defs().is_empty(), "{}", m),
+            r => panic!("expected a model, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_assert_true() {
+        check("(assert true) (check-sat)", Response::Sat);
+    }
+
+    #[test]
+    fn test_assert_false() {
+        check("(declare-fun p () Bool) (assert p) (assert false) (check-sat)", Response::Unsat);
+    }
+
+    #[test]
+    fn test_empty_and() {
+        check("(assert (and)) (check-sat)", Response::Sat);
+    }
+
+    #[test]
+    fn test_empty_or() {
+        check("(assert (or)) (check-sat)", Response::Unsat);
+    }
+}
+
+mod lemmas {
+    use super::*;
+
+    // sat, but the lemma `(not p)` contradicts `p ∨ q` with `¬q`
+    const PB: &str = r#"
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (assert (or p q))
+        (assert (not q))
+        (assert-lemma (not p))
+        (check-sat)
+    "#;
+
+    #[test]
+    fn test_flagged() {
+        let mut d = Driver::new(DriverConfig{lenient: true, ..Default::default()});
+        let res = d.execute_script(PB.as_bytes());
+        assert_eq!(Some(&Response::UnsatWithLemmas), res.last());
+        assert_eq!("unsat (with injected lemmas)", res.last().unwrap().to_string());
+
+        let mut d = Driver::new(DriverConfig{lenient: true, trust_lemmas: true, ..Default::default()});
+        let res = d.execute_script(PB.as_bytes());
+        assert_eq!(Some(&Response::Unsat), res.last());
+    }
+
+    #[test]
+    fn test_needs_lenient() {
+        let mut d = Driver::new(DriverConfig::default());
+        let res = d.execute_script(PB.as_bytes());
+        assert_eq!(Some(&Response::Unsupported), res.get(res.len()-2));
+        assert_eq!(Some(&Response::Sat), res.last());
+    }
+
+    #[test]
+    fn test_not_in_core() {
+        let cfg = DriverConfig{lenient: true, explain_unsat: true, ..Default::default()};
+        let mut d = Driver::new(cfg);
+        let res = d.execute_script(r#"
+            (declare-fun p () Bool)
+            (declare-fun q () Bool)
+            (assert-lemma (or p q))
+            (assert p)
+            (assert-lemma (not q))
+            (assert (not p))
+            (check-sat)
+        "#.as_bytes());
+        assert_eq!(Some(&Response::UnsatWithLemmas), res.last());
+        // indices are those of the assertions, lemmas are not counted
+        let used = d.unsat_assertions();
+        assert_eq!(used, vec![0, 1]);
+    }
+}
+
+mod reset {
+    use super::*;
+
+    const PB: &str = r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun f (U) U)
+        (assert (= a b))
+        (assert (not (= (f a) (f b))))
+        (check-sat)
+    "#;
+
+    // the manager is collected at each reset, so memory does not grow
+    #[test]
+    fn test_memory_is_flat() {
+        let mut d = Driver::new(DriverConfig::default());
+        let mut bytes = None;
+        for i in 0 .. 100 {
+            d.reset();
+            let res = d.execute_script(PB.as_bytes());
+            assert_eq!(Some(&Response::Unsat), res.last());
+            let total = d.memory_report().total();
+            // the first cycles allocate free lists and the like
+            if i == 2 { bytes = Some(total) }
+            if let Some(n) = bytes {
+                assert!(total <= n, "memory grew from {} to {} at cycle {}", n, total, i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_forget_declarations() {
+        let mut d = Driver::new(DriverConfig::default());
+        let res = d.execute_script(PB.as_bytes());
+        assert_eq!(Some(&Response::Unsat), res.last());
+        // without a reset, the declarations of `PB` are still visible
+        let res = d.execute_script("(assert (= a b))".as_bytes());
+        assert_eq!(vec![Response::Success], res);
+        d.reset();
+        let res = d.execute_script("(assert (= a b))".as_bytes());
+        match res.last() {
+            Some(Response::Error(msg)) if msg.contains("a is not a known function") => (),
+            r => panic!("`a` is not declared anymore, got {:?}", r),
+        }
+        // the same declarations are accepted again
+        let res = d.execute_script(PB.as_bytes());
+        assert_eq!(Some(&Response::Unsat), res.last());
+    }
+
+    // `(reset)` in a script: what follows is parsed after the reset is done
+    #[test]
+    fn test_reset_in_script() {
+        let mut d = Driver::new(DriverConfig::default());
+        // `a` is declared again, with another sort
+        let script = format!("{} (reset) {} (reset) (declare-fun a () Bool) (assert a) (check-sat)", PB, PB);
+        let res = d.execute_script(script.as_bytes());
+        assert_eq!(res.len(), 7 + 1 + 7 + 1 + 3, "{:?}", res);
+        assert_eq!(Some(&Response::Unsat), res.get(6));
+        assert_eq!(Some(&Response::Success), res.get(7));
+        assert_eq!(Some(&Response::Unsat), res.get(14));
+        assert_eq!(Some(&Response::Sat), res.last());
+
+        // statements before `(reset)` run even if the rest does not parse
+        let mut d = Driver::new(DriverConfig::default());
+        let res = d.execute_script(r#"
+            (declare-fun p () Bool)
+            (assert p)
+            (check-sat)
+            (reset)
+            (assert p)
+        "#.as_bytes());
+        assert_eq!(&res[..4], &[Response::Success, Response::Success, Response::Sat, Response::Success]);
+        match res.last() {
+            Some(Response::Error(msg)) if msg.contains("p is not a known function") => (),
+            r => panic!("`p` is not declared anymore, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_reset_assertions() {
+        let mut d = Driver::new(DriverConfig::default());
+        let res = d.execute_script(r#"
+            (set-option :produce-models true)
+            (declare-fun p () Bool)
+            (assert p)
+            (assert (not p))
+            (check-sat)
+            (reset-assertions)
+            (assert p)
+            (check-sat)
+            (get-model)
+        "#.as_bytes());
+        assert_eq!(Some(&Response::Unsat), res.get(4));
+        assert_eq!(Some(&Response::Success), res.get(5));
+        assert_eq!(Some(&Response::Sat), res.get(7));
+        match res.last() {
+            Some(Response::Model(m)) => assert!(m.to_string().contains("p"), "{}", m),
+            r => panic!("models are still produced, got {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+
+use {
+    batsmt_run::{Driver, DriverConfig, Response},
+};
+
+// run `pb`, and return the message of its last response, which must be an error
+fn last_error(d: &mut Driver, pb: &str) -> String {
+    match d.execute_script(pb.as_bytes()).pop() {
+        Some(r @ Response::Error(_)) => {
+            // printed as a single SMT-LIB response
+            let s = r.to_string();
+            assert!(s.starts_with("(error \"") && s.ends_with("\")"), "bad response {}", s);
+            assert!(!s.contains('\n'), "bad response {:?}", s);
+            match r { Response::Error(msg) => msg, _ => unreachable!() }
+        },
+        r => panic!("expected an error, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_parse_error() {
+    let mut d = Driver::new(DriverConfig::default());
+    let msg = last_error(&mut d, r#"
+        (declare-fun p () Bool)
+        (assert (p)))
+        (check-sat)
+    "#);
+    assert!(msg.starts_with("parse error:"), "bad message {:?}", msg);
+    assert!(msg.contains("line 3"), "bad message {:?}", msg);
+}
+
+// declarations outlive the script, declaring them again is an error
+#[test]
+fn test_redeclaration() {
+    let mut d = Driver::new(DriverConfig::default());
+    d.execute_script("(declare-sort U 0) (declare-fun p () Bool)".as_bytes());
+    let msg = last_error(&mut d, "\n(declare-fun p () Bool)");
+    assert!(msg.starts_with("parse error: function p is already declared"), "bad message {:?}", msg);
+    assert!(msg.contains("line 2"), "bad message {:?}", msg);
+    let msg = last_error(&mut d, "(declare-sort U 1)");
+    assert!(msg.starts_with("parse error: sort U is already declared"), "bad message {:?}", msg);
+}
+
+#[test]
+fn test_sort_error() {
+    let pb = r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun p () Bool)
+        (assert (or p a))
+        (check-sat)
+    "#;
+    for &preprocess in &[true, false] {
+        let mut d = Driver::new(DriverConfig{preprocess, ..Default::default()});
+        let res = d.execute_script(pb.as_bytes());
+        let msg = match &res[3] {
+            Response::Error(msg) => msg.clone(),
+            r => panic!("expected an error, got {:?}", r),
+        };
+        assert!(msg.contains("`a` has sort `U`, expected `Bool`"), "bad message {:?}", msg);
+        assert!(msg.contains("line 5"), "bad message {:?}", msg);
+        // the assertion was rejected
+        assert_eq!(Some(&Response::Sat), res.last());
+    }
+
+    // a toplevel atom is rejected directly
+    let mut d = Driver::new(DriverConfig::default());
+    let msg = last_error(&mut d, r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (assert a)
+    "#);
+    assert!(msg.contains("`a` has sort `U`"), "bad message {:?}", msg);
+}
+
+#[test]
+fn test_unsupported() {
+    let mut d = Driver::new(DriverConfig::default());
+    let msg = last_error(&mut d, r#"
+        (declare-fun p () Bool)
+        (push 1)
+        (assert p)
+    "#);
+    assert!(msg.starts_with("unsupported: directive \"push\""), "bad message {:?}", msg);
+    assert!(msg.contains("line 3"), "bad message {:?}", msg);
+
+    let msg = last_error(&mut d, r#"
+        (declare-sort U 0)
+        (assert (forall ((x U)) (= x x)))
+    "#);
+    assert!(msg.starts_with("unsupported: quantifiers"), "bad message {:?}", msg);
+}
+
+#[test]
+fn test_validation() {
+    let pb = r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun p () Bool)
+        (declare-fun f (U) U)
+        (assert (= (f a b) b))
+        (assert (or p (= f a)))
+        (assert (distinct a))
+        (assert (= (ite a b a) b))
+        (assert (= (f a) (f a b)))
+        (check-sat)
+    "#;
+    // all the problems are reported, and nothing is executed
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script(pb.as_bytes());
+    let msgs: Vec<_> = res.iter().map(|r| match r {
+        Response::Error(msg) => msg.clone(),
+        r => panic!("expected an error, got {:?}", r),
+    }).collect();
+    assert_eq!(4, msgs.len(), "bad messages {:?}", msgs);
+    let expected = [
+        ("`f` expects 1 argument, got 2", "line 7"),
+        ("`f` expects 1 argument, got 0", "line 8"),
+        ("`distinct` expects at least 2 arguments, got 1", "line 9"),
+        ("has sort `U`, expected `Bool`", "line 10"),
+    ];
+    for (msg, (what, line)) in msgs.iter().zip(expected.iter()) {
+        assert!(msg.contains(what) && msg.contains(line), "bad message {:?}", msg);
+    }
+    assert!(msgs[3].starts_with("parse error: the condition of `"), "bad message {:?}", msgs[3]);
+
+    // the position is part of the error, not only of its message
+    let mut d = Driver::new(DriverConfig::default());
+    let stmts = d.parse_with_spans(pb.as_bytes()).unwrap();
+    let lines: Vec<_> = d.validate(&stmts).iter().map(|e| e.pos().map(|p| p.line)).collect();
+    assert_eq!(vec![Some(7), Some(8), Some(9), Some(10)], lines);
+
+    let mut d = Driver::new(DriverConfig::default());
+    let msg = last_error(&mut d, r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (assert (= a))
+    "#);
+    assert!(msg.contains("`=` expects at least 2 arguments, got 1"), "bad message {:?}", msg);
+    assert!(msg.contains("line 4"), "bad message {:?}", msg);
+}
+
+#[test]
+fn test_no_validation() {
+    // `f` is used with two arities, which is accepted without validation
+    let pb = r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun f (U) U)
+        (assert (not (= (f a) (f a b))))
+        (check-sat)
+    "#;
+    let mut d = Driver::new(DriverConfig::default());
+    let msg = last_error(&mut d, pb);
+    assert!(msg.contains("`f` expects 1 argument, got 2") && msg.contains("line 6"), "bad message {:?}", msg);
+
+    let mut d = Driver::new(DriverConfig{validate: false, ..Default::default()});
+    let res = d.execute_script(pb.as_bytes());
+    assert_eq!(6, res.len());
+    assert_eq!(Some(&Response::Sat), res.last());
+}
@@ -0,0 +1,116 @@
+
+use {
+    std::{env, fs, path::PathBuf, process::Command},
+};
+
+// write `content` into a fresh file named `name`
+fn tmp_file(name: &str, content: &[u8]) -> PathBuf {
+    let path = env::temp_dir().join(format!("batsmt-{}-{}", std::process::id(), name));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+#[cfg(feature="flate2")]
+#[test]
+fn test_several_files() {
+    use {std::io::Write, flate2::{write::GzEncoder, Compression}};
+
+    let pb1 = tmp_file("pb1.smt2", br#"
+        (declare-fun p () Bool)
+        (assert p)
+        (assert (not p))
+        (check-sat)
+    "#);
+    // `p` is declared again, with another sort
+    let pb2 = {
+        let mut enc = GzEncoder::new(vec!(), Compression::default());
+        enc.write_all(br#"
+            (declare-sort U 0)
+            (declare-fun p () U)
+            (declare-fun a () U)
+            (assert (= p a))
+            (check-sat)
+        "#).unwrap();
+        tmp_file("pb2.smt2.gz", &enc.finish().unwrap())
+    };
+    let out = Command::new(env!("CARGO_BIN_EXE_batsmt-run"))
+        .arg(&pb1).arg(&pb2)
+        .output().expect("cannot run batsmt-run");
+    let _ = (fs::remove_file(&pb1), fs::remove_file(&pb2));
+    assert!(out.status.success(), "{:?}", out);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let expected = format!(
+        "; file: {}\nUNSAT\n; file: {}\nSAT\n", pb1.display(), pb2.display());
+    assert_eq!(stdout, expected);
+}
+
+#[cfg(not(feature="flate2"))]
+#[test]
+fn test_gz_without_feature() {
+    let pb = tmp_file("pb.smt2.gz", b"");
+    let err = match batsmt_run::input::open(pb.to_str().unwrap()) {
+        Ok(_) => panic!("opened a compressed file"),
+        Err(e) => e.to_string(),
+    };
+    let _ = fs::remove_file(&pb);
+    assert!(err.contains("flate2"), "bad message {:?}", err);
+}
+
+#[test]
+fn test_parse_only() {
+    let ok = tmp_file("ok.smt2", br#"
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (assert (or p q))
+        (assert (not p))
+        (check-sat)
+    "#);
+    let bad = tmp_file("bad.smt2", br#"
+        (declare-fun p () Bool)
+        (assert (p)))
+    "#);
+    let run = |path: &PathBuf| {
+        Command::new(env!("CARGO_BIN_EXE_batsmt-run"))
+            .arg("--parse-only").arg(path)
+            .output().expect("cannot run batsmt-run")
+    };
+    let (out_ok, out_bad) = (run(&ok), run(&bad));
+    let _ = (fs::remove_file(&ok), fs::remove_file(&bad));
+
+    assert!(out_ok.status.success(), "{:?}", out_ok);
+    let stdout = String::from_utf8(out_ok.stdout).unwrap();
+    assert_eq!(stdout, "; 5 statements (2 declare-fun, 2 assert, 1 check-sat)\n");
+
+    assert!(!out_bad.status.success(), "{:?}", out_bad);
+    let stdout = String::from_utf8(out_bad.stdout).unwrap();
+    assert!(stdout.contains("parse error") && stdout.contains("line 3"), "bad message {:?}", stdout);
+}
+
+#[test]
+fn test_no_validate() {
+    let pb = tmp_file("arities.smt2", br#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun f (U) U)
+        (assert (not (= (f a) (f a a))))
+        (assert (= (f a a a) a))
+        (check-sat)
+    "#);
+    let run = |flags: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_batsmt-run"))
+            .args(flags).arg(&pb)
+            .output().expect("cannot run batsmt-run")
+    };
+    let (out, out_no_validate) = (run(&[]), run(&["--no-validate"]));
+    let _ = fs::remove_file(&pb);
+
+    // both problems are reported, and nothing is solved
+    assert!(!out.status.success(), "{:?}", out);
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("line 5") && stdout.contains("line 6"), "bad output {:?}", stdout);
+    assert!(stdout.contains("expects 1 argument") && !stdout.contains("sat"), "bad output {:?}", stdout);
+
+    assert!(out_no_validate.status.success(), "{:?}", out_no_validate);
+    let stdout = String::from_utf8(out_no_validate.stdout).unwrap();
+    assert!(stdout.contains("sat") && !stdout.contains("error"), "bad output {:?}", stdout);
+}
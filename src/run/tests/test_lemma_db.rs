@@ -0,0 +1,203 @@
+
+use {
+    std::collections::HashSet,
+    batsmt_core::{ast_u32::AST, backtrack::Backtrackable},
+    batsmt_theory::{self as theory, Actions, ClauseKind, LitMap, Theory},
+    batsmt_solver::{BLit, Res, Solver, WarmStart, solver::lbool},
+    batsmt_run::Ctx,
+};
+
+/// Knows that pigeons `0` and `1` have a hole, and tells the SAT solver lazily.
+///
+/// The clause for pigeon `0` is a permanent lemma, given only once.
+/// The one for pigeon `1` is deletable, and given again whenever it is violated.
+struct HasHole {
+    perm: Vec<BLit>,
+    del: Vec<BLit>,
+    perm_added: bool,
+    n_del_added: usize,
+    levels: usize,
+}
+
+// is the clause `c` false in `model`?
+fn is_false(model: &HashSet<BLit>, c: &[BLit]) -> bool {
+    c.iter().all(|&lit| model.contains(&!lit))
+}
+
+impl Backtrackable<Ctx> for HasHole {
+    fn push_level(&mut self, _c: &mut Ctx) { self.levels += 1 }
+    fn pop_levels(&mut self, _c: &mut Ctx, n: usize) { self.levels -= n }
+    fn n_levels(&self) -> usize { self.levels }
+}
+
+impl Theory<Ctx> for HasHole {
+    fn final_check<A>(&mut self, _c: &mut Ctx, acts: &mut A, trail: &theory::Trail<Ctx>)
+        where A: Actions<Ctx>
+    {
+        let model: HashSet<BLit> = trail.iter().map(|(_, _, lit)| lit).collect();
+        if is_false(&model, &self.perm) {
+            assert!(!self.perm_added, "permanent lemma was lost");
+            self.perm_added = true;
+            acts.add_lemma_with_kind(&self.perm, ClauseKind::TheoryLemmaPermanent);
+        }
+        if is_false(&model, &self.del) {
+            self.n_del_added += 1;
+            acts.add_lemma_with_kind(&self.del, ClauseKind::TheoryLemmaDeletable);
+        }
+    }
+
+    fn explain_propagation(&mut self, _: &mut Ctx, _: AST, _: bool, _: BLit) -> &[BLit] {
+        unreachable!("no propagation")
+    }
+}
+
+// pigeon-hole with `n+1` pigeons, where the theory knows that pigeons
+// `0` and `1` have a hole.
+fn pigeon_hole(c: &mut Ctx, n: usize, max_learnts: Option<usize>) -> Solver<Ctx, HasHole> {
+    pigeons(c, n+1, n, max_learnts, None)
+}
+
+// `n_pigeons` pigeons in `n_holes` holes, with the state tracked.
+// `w` is imported before the problem is added.
+fn pigeons(
+    c: &mut Ctx, n_pigeons: usize, n_holes: usize, max_learnts: Option<usize>, w: Option<&WarmStart>
+) -> Solver<Ctx, HasHole> {
+    let th = HasHole{perm: vec!(), del: vec!(), perm_added: false, n_del_added: 0, levels: 0};
+    let mut s = Solver::new(c.builtins(), th);
+    s.set_track_state(true);
+    if let Some(k) = max_learnts {
+        s.set_max_learnts(k);
+    }
+    if let Some(w) = w {
+        s.import_state(w);
+    }
+
+    let (n, bool_) = (n_holes, c.b.bool_);
+    let p: Vec<Vec<BLit>> = (0 .. n_pigeons).map(|i| {
+        (0 .. n).map(|j| {
+            let t = c.m.mk_str(&format!("p_{}_{}", i, j), Some(bool_));
+            s.new_term_lit(c, t)
+        }).collect()
+    }).collect();
+
+    for i in 2 .. n_pigeons {
+        s.add_bool_clause_reuse(&mut p[i].iter().map(|lit| lit.0).collect());
+    }
+    for j in 0 .. n {
+        for i1 in 0 .. n_pigeons {
+            for i2 in i1+1 .. n_pigeons {
+                s.add_bool_clause_reuse(&mut vec![(!p[i1][j]).0, (!p[i2][j]).0]);
+            }
+        }
+    }
+    {
+        let th = s.theory_mut();
+        th.perm = p[0].clone();
+        th.del = p[1].clone();
+    }
+    s
+}
+
+#[test]
+fn test_permanent_lemma_survives_reductions() {
+    let mut c = Ctx::new();
+    let mut s = pigeon_hole(&mut c, 7, Some(20));
+    assert_eq!(Ok(Res::UNSAT), s.solve(&mut c).map_err(|e| e.to_string()));
+
+    let counts = s.clause_counts();
+    assert_eq!(1, counts.lemmas_permanent, "counts: {:?}", counts);
+    assert!(counts.lemmas_deleted >= 1, "counts: {:?}", counts);
+    // the deletable lemma was given again after being deleted
+    assert!(s.theory().n_del_added >= 2, "counts: {:?}", counts);
+}
+
+// an empty lemma is never deleted, and makes the problem UNSAT at once
+#[test]
+fn test_empty_deletable_lemma() {
+    let mut c = Ctx::new();
+    let mut s = pigeon_hole(&mut c, 2, None);
+    let mut cs = theory::TheoryClauseSet::new();
+    cs.push::<theory::TheoryLit<Ctx>>(&[]);
+    s.add_clause_with_kind(&c, cs.iter().next().unwrap(), ClauseKind::TheoryLemmaDeletable);
+    assert!(s.has_empty_clause());
+    assert_eq!(Ok(Res::UNSAT), s.solve(&mut c).map_err(|e| e.to_string()));
+    assert_eq!(1, s.clause_counts().lemmas_permanent);
+    assert_eq!(0, s.n_conflicts());
+    assert_eq!(0, s.theory().n_del_added);
+}
+
+#[test]
+fn test_learned_clauses() {
+    let mut c = Ctx::new();
+    let mut s = pigeon_hole(&mut c, 5, None);
+    assert!(s.learned_clauses().is_empty());
+    assert_eq!(Ok(Res::UNSAT), s.solve(&mut c).map_err(|e| e.to_string()));
+    let learned = s.learned_clauses();
+    assert!(learned.iter().any(|c| !c.is_empty()), "learned: {:?}", learned);
+    assert_eq!(learned.len(), s.clause_counts().learned);
+}
+
+#[test]
+fn test_learned_clauses_untracked() {
+    let mut c = Ctx::new();
+    let mut s = pigeon_hole(&mut c, 5, None);
+    s.set_track_state(false);
+    assert_eq!(Ok(Res::UNSAT), s.solve(&mut c).map_err(|e| e.to_string()));
+    assert!(s.clause_counts().learned > 0);
+    assert!(s.learned_clauses().is_empty());
+    let w = s.export_state();
+    assert!(w.clauses().iter().all(|c| c.len() == 1), "only units");
+    assert!(w.phases().is_empty() && w.activities().is_empty());
+}
+
+#[test]
+fn test_warm_start() {
+    let mut c = Ctx::new();
+    let mut s1 = pigeon_hole(&mut c, 5, None);
+    assert_eq!(Ok(Res::UNSAT), s1.solve(&mut c).map_err(|e| e.to_string()));
+    let w = s1.export_state();
+    assert!(!w.clauses().is_empty());
+
+    // same problem, same manager: the atoms are the same terms
+    let mut s2 = pigeon_hole(&mut c, 5, None);
+    s2.import_state(&w);
+    assert_eq!(Ok(Res::UNSAT), s2.solve(&mut c).map_err(|e| e.to_string()));
+    assert!(s2.n_conflicts() < s1.n_conflicts(),
+        "{} conflicts, {} when cold", s2.n_conflicts(), s1.n_conflicts());
+}
+
+// a satisfiable problem: the saved phases are the model, and
+// the other solver finds it again without any conflict
+#[test]
+fn test_warm_start_phases() {
+    let mut c = Ctx::new();
+    let mut s1 = pigeons(&mut c, 6, 6, None, None);
+    assert_eq!(Ok(Res::SAT), s1.solve(&mut c).map_err(|e| e.to_string()));
+    let w = s1.export_state();
+    assert_eq!(36, w.phases().len());
+    let value = |s: &Solver<Ctx, HasHole>, c: &Ctx, t: AST, sign: bool| {
+        s.value_in_model(s.lit_map().get_term(c, &t, sign).unwrap())
+    };
+    for &(t, sign) in w.phases() {
+        assert_eq!(lbool::TRUE, value(&s1, &c, t, sign));
+    }
+
+    let mut s2 = pigeons(&mut c, 6, 6, None, Some(&w));
+    assert_eq!(Ok(Res::SAT), s2.solve(&mut c).map_err(|e| e.to_string()));
+    assert_eq!(0, s2.n_conflicts());
+    for &(t, sign) in w.phases() {
+        assert_eq!(lbool::TRUE, value(&s2, &c, t, sign));
+    }
+}
+
+#[test]
+fn test_warm_start_no_assumptions() {
+    let mut c = Ctx::new();
+    let mut s = pigeon_hole(&mut c, 5, None);
+    let t = c.m.mk_str("p_2_0", Some(c.b.bool_));
+    let lit = s.new_term_lit(&mut c, t);
+    assert_eq!(Ok(Res::UNSAT), s.solve_with(&mut c, &[lit.0]).map_err(|e| e.to_string()));
+    assert!(!s.learned_clauses().is_empty());
+    // the clauses were learnt under an assumption
+    assert!(s.export_state().clauses().is_empty());
+}
@@ -0,0 +1,308 @@
+
+use {
+    batsmt_core::{ast_u32::AST, backtrack::Backtrackable, Manager},
+    batsmt_run::{Ctx, Driver, DriverConfig, Response, Model, Eval, model::{Def, Value}},
+};
+
+/// Run `pb`, which ends with `(check-sat) (get-model)`, and check that
+/// the first `n_assertions` assertions are true in the model.
+fn get_model(d: &mut Driver, pb: &str, n_assertions: usize) -> Model {
+    let res = d.execute_script(pb.as_bytes());
+    assert_eq!(Some(&Response::Sat), res.get(res.len()-2), "{:?}", res);
+    let m = match res.last() {
+        Some(Response::Model(m)) => m.clone(),
+        r => panic!("expected a model, got {:?}", r),
+    };
+    for i in 0 .. n_assertions {
+        let (t, _) = d.assertion(i);
+        assert_eq!(m.eval(d.ctx(), &t), Some(Value::Bool(true)),
+            "assertion #{} is false in\n{}", i, m);
+    }
+    m
+}
+
+fn abstract_value(m: &Model, name: &str) -> usize {
+    match m.value(name) {
+        Some(Value::Abstract{sort, idx}) => {
+            assert_eq!(sort, "U");
+            *idx
+        },
+        v => panic!("expected an abstract value for {}, got {:?}", name, v),
+    }
+}
+
+const PB_SWAP: &str = r#"
+    (set-option :produce-models true)
+    (declare-sort U 0)
+    (declare-fun a () U)
+    (declare-fun b () U)
+    (declare-fun f (U) U)
+    (assert (not (= a b)))
+    (assert (= (f a) b))
+    (assert (= (f b) a))
+    (check-sat)
+    (get-model)
+"#;
+
+// `a` and `b` are in different classes, and `f` swaps them
+#[test]
+fn test_distinct_witnesses() {
+    for preprocess in vec![true, false] {
+        let mut d = Driver::new(DriverConfig{preprocess, ..Default::default()});
+        let m = get_model(&mut d, PB_SWAP, 3);
+        let (a, b) = (abstract_value(&m, "a"), abstract_value(&m, "b"));
+        assert_eq!((a, b), (0, 1));
+        let s = m.to_string();
+        assert!(s.contains("(define-fun a () U (as @U!0 U))"), "{}", s);
+        assert!(s.contains("(define-fun b () U (as @U!1 U))"), "{}", s);
+        assert_eq!(m.get("f").map(|d| &d.args[..]), Some(&["U".to_string()][..]));
+    }
+}
+
+// the class of `c` contains `(f a)`: tables print `c` rather than its value
+#[test]
+fn test_constant_as_witness() {
+    let mut d = Driver::new(DriverConfig{preprocess: false, ..Default::default()});
+    let m = get_model(&mut d, r#"
+        (set-option :produce-models true)
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun c () U)
+        (declare-fun f (U) U)
+        (assert (= c (f a)))
+        (assert (not (= a c)))
+        (assert (= (f c) c))
+        (check-sat)
+        (get-model)
+    "#, 3);
+    assert_ne!(m.value("a"), m.value("c"));
+    // every application of `f` is `c`: the table is just the default case
+    let f = m.get("f").expect("f has a definition");
+    assert_eq!(&f.default, m.value("c").unwrap());
+    assert!(m.to_string().contains("(define-fun f ((x!0 U)) U c)"), "{}", m);
+}
+
+// boolean classes are `true` or `false`, not abstract values
+#[test]
+fn test_bool_classes() {
+    let mut d = Driver::new(DriverConfig{preprocess: false, ..Default::default()});
+    let m = get_model(&mut d, r#"
+        (set-option :produce-models true)
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun p () Bool)
+        (declare-fun q (U) Bool)
+        (declare-fun g (Bool) U)
+        (assert (= p (= a b)))
+        (assert (not p))
+        (assert (q a))
+        (assert (not (q b)))
+        (assert (= (g (q a)) b))
+        (check-sat)
+        (get-model)
+    "#, 5);
+    assert_eq!(m.value("p"), Some(&Value::Bool(false)));
+    let q = m.get("q").unwrap();
+    assert!(q.table.iter().chain(Some(&(vec!(), q.default.clone())))
+        .all(|(_, v)| matches!(v, Value::Bool(_))), "{}", m);
+    let g = m.get("g").unwrap();
+    assert_eq!(g.apply(&[Value::Bool(true)]), m.value("b").unwrap());
+    assert!(m.to_string().contains("(define-fun p () Bool false)"), "{}", m);
+}
+
+// eliminated constants get the value of their representative
+#[test]
+fn test_preprocessed_consts() {
+    let mut d = Driver::new(DriverConfig::default());
+    let m = get_model(&mut d, r#"
+        (set-option :produce-models true)
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun c () U)
+        (declare-fun f (U) U)
+        (assert (= a b))
+        (assert (= c (f b)))
+        (assert (not (= (f a) a)))
+        (check-sat)
+        (get-model)
+    "#, 3);
+    assert_eq!(m.value("a"), m.value("b"));
+    assert_ne!(m.value("a"), m.value("c"));
+}
+
+// the evaluator on hand-built models
+mod eval {
+    use super::*;
+
+    fn u(idx: usize) -> Value { Value::Abstract{sort: "U".to_string(), idx} }
+
+    // `a = U!0`, `b = U!1`, `f(U!0) = U!1`, `f` is `U!0` elsewhere
+    fn model(c: &mut Ctx) -> (Model, [AST; 4]) {
+        let ty = c.m.mk_str("U", None);
+        let a = c.m.mk_str("a", Some(ty));
+        let b = c.m.mk_str("b", Some(ty));
+        let f = c.m.mk_str("f", None);
+        let mut m = Model::new();
+        m.define(a, Def::constant("a", "U", u(0)));
+        m.define(b, Def::constant("b", "U", u(1)));
+        m.define(f, Def {
+            name: "f".to_string(), args: vec!("U".to_string()), ret: "U".to_string(),
+            table: vec!((vec!(u(0)), u(1))), default: u(0),
+        });
+        (m, [ty, a, b, f])
+    }
+
+    #[test]
+    fn test_strict() {
+        let mut c = Ctx::new();
+        let (m, [ty, a, b, f]) = model(&mut c);
+        let fa = c.m.mk_app(f, &[a], Some(ty));
+        let fb = c.m.mk_app(f, &[b], Some(ty));
+        let eq = c.m.mk_app(c.b.eq, &[fa, b], Some(c.b.bool_));
+        let mut ev = Eval::new(&m);
+        assert_eq!(ev.eval_term(&c, &fa), Some(u(1)));
+        assert_eq!(ev.eval_term(&c, &fb), Some(u(0)));
+        assert_eq!(ev.eval_formula(&c, &eq), Some(true));
+        assert_eq!(ev.eval_formula(&c, &fa), None, "not a formula");
+        let mut ev = Eval::new(&m).strict(true);
+        assert_eq!(ev.eval_term(&c, &fa), Some(u(1)));
+        assert_eq!(ev.eval_term(&c, &fb), None, "(f b) is not in the table");
+    }
+
+    #[test]
+    fn test_connectives() {
+        let mut c = Ctx::new();
+        let (m, [ty, a, b, f]) = model(&mut c);
+        let bo = c.b.clone();
+        let fa = c.m.mk_app(f, &[a], Some(ty));
+        let ab = c.m.mk_app(bo.eq, &[a, b], Some(bo.bool_));
+        let fa_b = c.m.mk_app(bo.eq, &[fa, b], Some(bo.bool_));
+        let not_ab = c.m.mk_app(bo.not_, &[ab], Some(bo.bool_));
+        let and_ = c.m.mk_app(bo.and_, &[not_ab, fa_b], Some(bo.bool_));
+        let imp = c.m.mk_app(bo.imply_, &[ab, bo.false_], Some(bo.bool_));
+        let ite = c.m.mk_app(bo.ite, &[ab, a, fa], Some(ty));
+        let dist = c.m.mk_app(bo.distinct, &[a, b, fa], Some(bo.bool_));
+        let unknown = c.m.mk_str("d", Some(ty));
+        let mut ev = Eval::new(&m);
+        assert_eq!(ev.eval_formula(&c, &and_), Some(true));
+        assert_eq!(ev.eval_formula(&c, &imp), Some(true));
+        assert_eq!(ev.eval_term(&c, &ite), Some(u(1)));
+        assert_eq!(ev.eval_formula(&c, &dist), Some(false));
+        assert_eq!(ev.eval_term(&c, &unknown), None);
+        assert_eq!(m.eval(&c, &and_), Some(Value::Bool(true)));
+    }
+
+    // `f(f(…f(a)…))`, too deep for a recursive evaluator
+    #[test]
+    fn test_deep() {
+        let mut c = Ctx::new();
+        let (m, [ty, a, _, f]) = model(&mut c);
+        let mut t = a;
+        for _ in 0 .. 200_000 {
+            t = c.m.mk_app(f, &[t], Some(ty));
+        }
+        let mut ev = Eval::new(&m);
+        assert_eq!(ev.eval_term(&c, &t), Some(u(0)));
+    }
+
+    // the evaluator agrees with the models of the solver
+    #[test]
+    fn test_agrees_with_solver() {
+        let mut d = Driver::new(DriverConfig::default());
+        let m = get_model(&mut d, PB_SWAP, 0);
+        let mut ev = Eval::new(&m).strict(true);
+        for i in 0 .. 3 {
+            let (t, _) = d.assertion(i);
+            assert_eq!(ev.eval_formula(d.ctx(), &t), Some(true), "assertion #{}", i);
+        }
+    }
+}
+
+// models are only built if `:produce-models` is set
+#[test]
+fn test_models_not_enabled() {
+    let pb = PB_SWAP.replace("(set-option :produce-models true)", "");
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script(pb.as_bytes());
+    assert_eq!(Some(&Response::Sat), res.get(res.len()-2), "{:?}", res);
+    assert_eq!(Some(&Response::Error("model generation is not enabled".to_string())), res.last());
+    assert_eq!("(error \"model generation is not enabled\")", res.last().unwrap().to_string());
+
+    let pb = PB_SWAP.replace(":produce-models true", ":produce-models false");
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script(pb.as_bytes());
+    assert_eq!(Some(&Response::Error("model generation is not enabled".to_string())), res.last());
+}
+
+// `a=b` and `f(b)=c` only hold once `q` is decided, so the theory has
+// backtracked from them when `get-value` is asked
+const PB_CONGRUENT: &str = r#"
+    (set-option :produce-models true)
+    (declare-sort U 0)
+    (declare-fun a () U)
+    (declare-fun b () U)
+    (declare-fun c () U)
+    (declare-fun f (U) U)
+    (declare-fun q () Bool)
+    (assert (or q (= a b)))
+    (assert (or (not q) (= a b)))
+    (assert (or q (= (f b) c)))
+    (assert (or (not q) (= (f b) c)))
+    (check-sat)
+"#;
+
+fn get_values(d: &mut Driver, terms: &str) -> Vec<(String, Value)> {
+    let q = format!("(get-value ({}))", terms);
+    match d.execute_script(q.as_bytes()).pop() {
+        Some(Response::Values(v)) => v,
+        r => panic!("expected values, got {:?}", r),
+    }
+}
+
+// `f(a)` never occurs in the input, and gets the value of `f(b)`
+#[test]
+fn test_get_value_congruent() {
+    for preprocess in vec![true, false] {
+        let mut d = Driver::new(DriverConfig{preprocess, ..Default::default()});
+        let res = d.execute_script(PB_CONGRUENT.as_bytes());
+        assert_eq!(Some(&Response::Sat), res.last(), "{:?}", res);
+        let v = get_values(&mut d, "(f a) c a b (f c) (= (f a) c)");
+        let names: Vec<&str> = v.iter().map(|(t, _)| &t[..]).collect();
+        assert_eq!(names, vec!["(f a)", "c", "a", "b", "(f c)", "(= (f a) c)"]);
+        assert_eq!(v[0].1, v[1].1, "f(a) = f(b) = c");
+        assert_eq!(v[2].1, v[3].1, "a = b");
+        assert!(matches!(v[4].1, Value::Abstract{..}), "{:?}", v[4]);
+        assert_eq!(v[5].1, Value::Bool(true));
+        let s = Response::Values(v[2..4].to_vec()).to_string();
+        assert!(s.starts_with("((a (as @U!") && s.ends_with(" U)))"), "{}", s);
+    }
+}
+
+// queries leave the theory as it was
+#[test]
+fn test_get_value_state_unchanged() {
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script(PB_CONGRUENT.as_bytes());
+    assert_eq!(Some(&Response::Sat), res.last(), "{:?}", res);
+    let n_levels = d.solver().theory().n_levels();
+    let stats = format!("{:?}", d.solver().theory().stats());
+    let v = get_values(&mut d, "(f a) (f (f a)) (= (f a) c)");
+    for _ in 0 .. 50 {
+        assert_eq!(v, get_values(&mut d, "(f a) (f (f a)) (= (f a) c)"));
+    }
+    assert_eq!(n_levels, d.solver().theory().n_levels());
+    assert_eq!(stats, format!("{:?}", d.solver().theory().stats()));
+}
+
+#[test]
+fn test_get_value_needs_model() {
+    let mut d = Driver::new(DriverConfig::default());
+    let res = d.execute_script("(declare-fun p () Bool) (get-value (p))".as_bytes());
+    assert_eq!(Some(&Response::Error("model generation is not enabled".to_string())), res.last());
+
+    let pb = PB_CONGRUENT.replace("(check-sat)", "(get-value (a))");
+    let res = d.execute_script(pb.as_bytes());
+    assert_eq!(Some(&Response::Error("no model available".to_string())), res.last());
+}
@@ -0,0 +1,154 @@
+
+use {
+    std::time::Duration,
+    serde_json::{json, Value},
+    batsmt_solver::CancelToken,
+    batsmt_run::{Driver, DriverConfig, Response, ResponseWriter, JsonWriter, Smt2Writer, Model, model},
+};
+
+// execute `script` in `d`, and parse each line of the JSON output
+fn run_json(d: &mut Driver, script: &str) -> Vec<Value> {
+    let stmts = d.parse_with_spans(script.as_bytes()).expect("parse error");
+    let mut w = JsonWriter(vec!());
+    d.execute_all(stmts, &mut w).unwrap();
+    String::from_utf8(w.0).unwrap().lines()
+        .map(|l| serde_json::from_str(l).unwrap_or_else(|e| panic!("bad JSON {:?}: {}", l, e)))
+        .collect()
+}
+
+// remove the fields that vary from one run to another
+fn strip(mut v: Value) -> Value {
+    let obj = v.as_object_mut().expect("object");
+    assert!(obj.remove("time_ms").map_or(false, |t| t.is_u64()), "time_ms: {:?}", obj);
+    if let Some(stats) = obj.get_mut("stats") {
+        let stats = stats.as_object_mut().expect("stats");
+        assert!(stats.values().all(|n| n.is_u64()), "stats: {:?}", stats);
+        assert!(stats.contains_key("conflicts"));
+        *stats = Default::default();
+    }
+    v
+}
+
+#[test]
+fn test_json_every_response() {
+    let mut d = Driver::new(DriverConfig::default());
+    let res = run_json(&mut d, r#"
+        (set-option :produce-models true)
+        (set-option :produce-unsat-cores true)
+        (set-option :produce-models yes)
+        (set-option :verbosity true)
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (assert (or p q))
+        (get-unsat-core)
+        (check-sat-assumptions (not p))
+        (get-model)
+        (check-sat-assumptions (not p) (not q))
+        (get-unsat-core)
+    "#);
+    let res: Vec<Value> = res.into_iter().map(strip).collect();
+    let expected = vec![
+        json!({"cmd": "set-option", "result": "success"}),
+        json!({"cmd": "set-option", "result": "success"}),
+        json!({"cmd": "set-option", "result": "error",
+               "message": "expected boolean for :produce-models, got \"yes\""}),
+        json!({"cmd": "set-option", "result": "unsupported"}),
+        json!({"cmd": "declare-fun", "result": "success"}),
+        json!({"cmd": "declare-fun", "result": "success"}),
+        json!({"cmd": "assert", "result": "success"}),
+        json!({"cmd": "get-unsat-core", "result": "error", "message": "no unsat core available"}),
+        json!({"cmd": "check-sat-assumptions", "result": "sat", "stats": {}}),
+        json!({"cmd": "get-model", "result": "model",
+               "model": [{"name": "p", "value": false}, {"name": "q", "value": true}]}),
+        json!({"cmd": "check-sat-assumptions", "result": "unsat", "stats": {}}),
+        json!({"cmd": "get-unsat-core", "result": "core", "core": ["(not p)", "(not q)"]}),
+    ];
+    assert_eq!(expected, res);
+
+    // interrupted before it starts
+    let tok = CancelToken::new();
+    d.set_cancel_token(tok.clone());
+    tok.cancel();
+    let res = run_json(&mut d, r#"
+        (check-sat)
+        (get-info :reason-unknown)
+        (exit)
+        (check-sat)
+    "#);
+    let res: Vec<Value> = res.into_iter().map(strip).collect();
+    let expected = vec![
+        json!({"cmd": "check-sat", "result": "unknown", "reason": "canceled", "stats": {}}),
+        json!({"cmd": "get-info", "result": "info", "key": ":reason-unknown", "value": "canceled"}),
+        json!({"cmd": "exit", "result": "success"}),
+    ];
+    assert_eq!(expected, res);
+}
+
+#[test]
+fn test_json_used_assertions() {
+    let mut d = Driver::new(DriverConfig{explain_unsat: true, ..Default::default()});
+    let res = run_json(&mut d, r#"
+        (declare-fun p () Bool)
+        (declare-fun q () Bool)
+        (assert q)
+        (assert p)
+        (assert (not p))
+        (check-sat)
+    "#);
+    let res: Vec<Value> = res.into_iter().map(strip).collect();
+    assert_eq!(json!({"cmd": "check-sat", "result": "unsat", "stats": {}}), res[5]);
+    let used: Vec<(u64, &str)> = res[6..].iter().map(|v| {
+        assert_eq!(v["result"], "used-assertion");
+        assert!(v["span"].is_string());
+        (v["index"].as_u64().unwrap(), v["term"].as_str().unwrap())
+    }).collect();
+    // the set of used assertions is an over-approximation
+    assert!(used.contains(&(1, "p")) && used.contains(&(2, "(not p)")), "{:?}", used);
+}
+
+// strings are escaped, whatever characters they contain
+#[test]
+fn test_json_escape() {
+    let tricky = "a \"quoted\" \\ line\nnext\ttab \u{1} é".to_string();
+    let mut w = JsonWriter(vec!());
+    let mut m = Model::new();
+    m.push(model::Def::constant(tricky.clone(), "Bool", model::Value::Bool(true)));
+    w.response("get-model", &Response::Model(m), Duration::from_millis(3), None).unwrap();
+    w.response("get-unsat-core", &Response::Core(vec![tricky.clone()]), Duration::from_millis(0), None).unwrap();
+    let values = vec![
+        (tricky.clone(), model::Value::Bool(false)),
+        ("a".to_string(), model::Value::Abstract{sort: "U".to_string(), idx: 1}),
+    ];
+    w.response("get-value", &Response::Values(values), Duration::from_millis(0), None).unwrap();
+    w.response("check-sat", &Response::Error(tricky.clone()), Duration::from_millis(1),
+        Some(&[("conflicts", 42)])).unwrap();
+    let out = String::from_utf8(w.0).unwrap();
+    let lines: Vec<Value> = out.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(4, lines.len(), "{}", out);
+    assert_eq!(lines[0], json!({"cmd": "get-model", "result": "model", "time_ms": 3,
+                                "model": [{"name": tricky, "value": true}]}));
+    assert_eq!(lines[1]["core"], json!([tricky]));
+    assert_eq!(lines[2]["values"], json!([{"term": tricky, "value": false},
+                                          {"term": "a", "value": "(as @U!1 U)"}]));
+    assert_eq!(lines[3], json!({"cmd": "check-sat", "result": "error", "message": tricky,
+                                "time_ms": 1, "stats": {"conflicts": 42}}));
+}
+
+// the SMT-LIB writer prints what `Response` displays, except for successes
+#[test]
+fn test_smt2_output() {
+    let mut d = Driver::new(DriverConfig::default());
+    let stmts = d.parse_with_spans(r#"
+        (set-option :produce-models true)
+        (declare-fun p () Bool)
+        (assert p)
+        (check-sat)
+        (get-model)
+        (get-unsat-core)
+    "#.as_bytes()).unwrap();
+    let mut w = Smt2Writer(vec!());
+    d.execute_all(stmts, &mut w).unwrap();
+    assert_eq!(String::from_utf8(w.0).unwrap(),
+        "SAT\n(model\n  (define-fun p () Bool true)\n)\n\
+         (error \"unsat core generation is not enabled\")\n");
+}
@@ -0,0 +1,136 @@
+
+use {
+    std::collections::HashMap,
+    batsmt_core::{ast_u32::AST, Manager},
+    batsmt_run::{Ctx, PpDistinct},
+    batsmt_pretty as pp,
+    batsmt_tseitin::Tseitin,
+};
+
+#[test]
+fn test_infix_eq() {
+    let mut c = Ctx::new();
+    let u = c.m.mk_str("U", None);
+    let a = c.m.mk_str("a", Some(u));
+    let b = c.m.mk_str("b", Some(u));
+    let f = c.m.mk_str("f", Some(u));
+    let fa = c.m.mk_app(f, &[a], Some(u));
+    let t = c.m.mk_app(c.b.eq, &[fa, b], Some(c.b.bool_));
+    assert_eq!("(= (f a) b)", pp::pp1(&c, &t).to_string());
+
+    let eq = c.b.eq;
+    c.set_infix(&eq);
+    assert_eq!("((f a) = b)", pp::pp1(&c, &t).to_string());
+
+    // only binary applications are printed infix
+    let t3 = c.m.mk_app(c.b.eq, &[a, b, fa], Some(c.b.bool_));
+    assert_eq!("(= a b (f a))", pp::pp1(&c, &t3).to_string());
+}
+
+// the width of a symbol is its number of characters, not of bytes
+#[test]
+fn test_unicode_width() {
+    let mut c = Ctx::new();
+    let u = c.m.mk_str("U", None);
+    let f = c.m.mk_str("f", Some(u));
+    let names = ["αβγδεζηθικλμνξοπ", "ρστυφχψωαβγδεζηθ", "ικλμνξοπρστυφχψω", "ΑΒΓΔΕΖΗΘΙΚΛΜΝΞ"];
+    let args: Vec<AST> = names.iter().map(|s| c.m.mk_str(s, Some(u))).collect();
+    let t = c.m.mk_app(f, &args, Some(u));
+    // fits in the default width, counting characters
+    let expected = format!("(f {})", names.join(" "));
+    assert!(expected.chars().count() <= pp::WIDTH && expected.len() > pp::WIDTH);
+    assert_eq!(expected, pp::pp1(&c, &t).to_string());
+
+    // with one more argument, each argument gets its own line
+    let mut args2 = args.clone();
+    args2.push(args[0]);
+    let t2 = c.m.mk_app(f, &args2, Some(u));
+    let s2 = pp::pp1(&c, &t2).to_string();
+    assert_eq!(6, s2.lines().count(), "{}", s2);
+}
+
+// rebuild a term from its JSON rendering, with constants from `syms`
+fn of_json(c: &mut Ctx, syms: &HashMap<String, AST>, v: &serde_json::Value) -> AST {
+    if let Some(v) = v.get("const") {
+        let name = v["name"].as_str().expect("name");
+        let t = *syms.get(name).expect("unknown constant");
+        assert_eq!(c.m.ty(&t), v.get("sort").map(|ty| of_json(c, syms, ty)));
+        t
+    } else if let Some(v) = v.get("app") {
+        let f = of_json(c, syms, &v["f"]);
+        let args: Vec<_> =
+            v["args"].as_array().expect("args").iter().map(|u| of_json(c, syms, u)).collect();
+        let ty = v.get("sort").map(|ty| of_json(c, syms, ty));
+        c.m.mk_app(f, &args, ty)
+    } else {
+        panic!("bad JSON term {}", v)
+    }
+}
+
+#[test]
+fn test_json_roundtrip() {
+    let mut c = Ctx::new();
+    let mut syms = HashMap::new();
+    let u = c.m.mk_str("U", None);
+    let a = c.m.mk_str("a", Some(u));
+    let b_name = "|b \"quoted\"\\\n\t|";
+    let b = c.m.mk_str(b_name, Some(u));
+    let f = c.m.mk_str("f", Some(u));
+    for (name, t) in [("U", u), ("a", a), (b_name, b), ("f", f),
+                      ("=", c.b.eq), ("Bool", c.b.bool_)].iter() {
+        syms.insert(name.to_string(), *t);
+    }
+    let fab = c.m.mk_app(f, &[a, b], Some(u));
+    let t = c.m.mk_app(c.b.eq, &[fab, a], Some(c.b.bool_));
+
+    let s = c.to_json(&a);
+    assert_eq!(r#"{"const":{"name":"a","sort":{"const":{"name":"U"}}}}"#, s);
+
+    let s = c.to_json(&t);
+    assert!(s.starts_with(r#"{"app":{"f":{"const":{"name":"="}},"args":["#), "bad JSON {}", s);
+    let v: serde_json::Value = serde_json::from_str(&s).expect("valid JSON");
+    assert_eq!(t, of_json(&mut c, &syms, &v));
+}
+
+// `(distinct x0 … x{n-1})`, on sort `U`
+fn mk_distinct(c: &mut Ctx, n: usize) -> AST {
+    let u = c.m.mk_str("U", None);
+    let args: Vec<_> = (0 .. n).map(|i| c.m.mk_string(format!("x{}", i), Some(u))).collect();
+    c.m.mk_app(c.b.distinct, &args, Some(c.b.bool_))
+}
+
+#[test]
+fn test_refold_distinct() {
+    let mut c = Ctx::new();
+    let t = mk_distinct(&mut c, 4);
+    let expanded = Tseitin::new().simplify(&mut c, t);
+    let s = pp::pp1(&c, &expanded).to_string();
+    assert!(s.starts_with("(and\n (not (= x0 x1))"), "{}", s);
+
+    c.set_pp_distinct(PpDistinct{refold: true, ..PpDistinct::default()});
+    assert_eq!(pp::pp1(&c, &t).to_string(), pp::pp1(&c, &expanded).to_string());
+
+    // a missing disequation, or a single one, is printed as is
+    let args: Vec<_> = match c.m.view(&expanded) {
+        batsmt_core::AstView::App{args, ..} => args.to_vec(),
+        _ => panic!("expected a conjunction"),
+    };
+    let partial = c.m.mk_app(c.b.and_, &args[1..], Some(c.b.bool_));
+    assert!(pp::pp1(&c, &partial).to_string().starts_with("(and"));
+    assert!(pp::pp1(&c, &args[0]).to_string().starts_with("(not "));
+}
+
+#[test]
+fn test_compact_distinct() {
+    let mut c = Ctx::new();
+    let t = mk_distinct(&mut c, 40);
+    let normalize = |s: String| s.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let s = pp::pp1(&c, &t).to_string();
+    assert_eq!(41, s.lines().count(), "{}", s);
+
+    c.set_pp_distinct(PpDistinct{compact: true, ..PpDistinct::default()});
+    let s2 = pp::pp1(&c, &t).to_string();
+    assert!(s2.lines().count() < 5, "{}", s2);
+    assert_eq!(normalize(s), normalize(s2));
+}
@@ -0,0 +1,126 @@
+
+use {
+    std::{env, fs, process},
+    batsmt_core::{ast_u32::AST, backtrack::Backtrackable, Manager},
+    batsmt_cc as cc,
+    batsmt_theory::{self as theory, Actions, Theory},
+    batsmt_solver::{BLit, Res, Solver},
+    batsmt_run::{driver::Th, sat_dump, Ctx, Driver, DriverConfig, Response},
+};
+
+#[test]
+fn test_dump_after_each_sat() {
+    let path = env::temp_dir().join(format!("batsmt-sat-dump-{}.smt2", process::id()));
+    let mut d = Driver::new(DriverConfig{
+        debug_sat_dump: Some(path.clone()), preprocess: false, ..Default::default()
+    });
+    let res = d.execute_script(r#"
+        (declare-sort U 0)
+        (declare-fun a () U)
+        (declare-fun b () U)
+        (declare-fun f (U) U)
+        (assert (= (f a) b))
+        (assert (or (= (f a) a) (= (f (f a)) a)))
+        (check-sat)
+        (assert (not (= (f a) a)))
+        (check-sat)
+    "#.as_bytes());
+    assert_eq!(Some(&Response::Sat), res.last(), "{:?}", res);
+    let dump = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+
+    assert!(dump.starts_with("; sat answer #1\n"), "{}", dump);
+    assert!(dump.contains("; sat answer #2\n"), "{}", dump);
+    assert!(!dump.contains("; sat answer #3"), "{}", dump);
+    assert_eq!(2, dump.matches("inconsistent with the partition").count(), "{}", dump);
+    assert_eq!(2, dump.matches(", 0 inconsistent").count(), "{}", dump);
+    // `(f a)` occurs in several atoms, so it is named
+    assert!(dump.contains("(define-term _t0 (f a))"), "{}", dump);
+    assert!(dump.contains("(class "), "{}", dump);
+    assert!(!dump.contains(":inconsistent") && !dump.contains(":unchecked"), "{}", dump);
+}
+
+/// The driver's theory, except that it never sees the literal of `forget`,
+/// as if a rule of the congruence closure were disabled.
+struct Forgetful {
+    th: Th,
+    forget: AST,
+    trail: Vec<(AST, bool, BLit)>,
+}
+
+impl Forgetful {
+    fn filter(&mut self, trail: &theory::Trail<Ctx>) {
+        self.trail.clear();
+        let forget = self.forget;
+        self.trail.extend(trail.as_slice().iter().filter(|(t, _, _)| *t != forget).cloned());
+    }
+}
+
+impl Backtrackable<Ctx> for Forgetful {
+    fn push_level(&mut self, c: &mut Ctx) { self.th.push_level(c) }
+    fn pop_levels(&mut self, c: &mut Ctx, n: usize) { self.th.pop_levels(c, n) }
+    fn n_levels(&self) -> usize { self.th.n_levels() }
+}
+
+impl Theory<Ctx> for Forgetful {
+    fn final_check<A>(&mut self, c: &mut Ctx, acts: &mut A, trail: &theory::Trail<Ctx>)
+        where A: Actions<Ctx>
+    {
+        self.filter(trail);
+        self.th.final_check(c, acts, &theory::Trail::from_slice(&self.trail))
+    }
+
+    fn partial_check<A>(&mut self, c: &mut Ctx, acts: &mut A, trail: &theory::Trail<Ctx>)
+        where A: Actions<Ctx>
+    {
+        self.filter(trail);
+        self.th.partial_check(c, acts, &theory::Trail::from_slice(&self.trail))
+    }
+
+    fn has_partial_check() -> bool { Th::has_partial_check() }
+
+    fn add_literal(&mut self, c: &mut Ctx, t: AST, lit: BLit) { self.th.add_literal(c, t, lit) }
+
+    fn explain_propagation(&mut self, c: &mut Ctx, t: AST, sign: bool, p: BLit) -> &[BLit] {
+        self.th.explain_propagation(c, t, sign, p)
+    }
+}
+
+// `a=b`, `b=c`, `a!=c` is unsat, but the theory does not see `a!=c`:
+// the dump shows that this atom contradicts the partition.
+#[test]
+fn test_dump_pinpoints_wrong_atom() {
+    let mut c = Ctx::new();
+    let mut th: Th = cc::CCTheory::new(&mut c);
+    th.set_record_final_state(true);
+    let u = c.m.mk_str("U", None);
+    let a = c.m.mk_str("a", Some(u));
+    let b = c.m.mk_str("b", Some(u));
+    let d = c.m.mk_str("c", Some(u));
+    let bool_ = c.b.bool_;
+    let eq_ab = c.m.mk_app(c.b.eq, &[a, b], Some(bool_));
+    let eq_bc = c.m.mk_app(c.b.eq, &[b, d], Some(bool_));
+    let eq_ac = c.m.mk_app(c.b.eq, &[a, d], Some(bool_));
+
+    let mut s = Solver::new(c.builtins(), Forgetful{th, forget: eq_ac, trail: vec!()});
+    s.enable_th_propagation(false);
+    let lit_ab = s.new_term_lit(&mut c, eq_ab);
+    let lit_bc = s.new_term_lit(&mut c, eq_bc);
+    let lit_ac = s.new_term_lit(&mut c, eq_ac);
+    s.add_bool_clause_reuse(&mut vec![lit_ab.0]);
+    s.add_bool_clause_reuse(&mut vec![lit_bc.0]);
+    s.add_bool_clause_reuse(&mut vec![(!lit_ac).0]);
+    assert_eq!(Ok(Res::SAT), s.solve(&mut c).map_err(|e| e.to_string()));
+
+    let mut out = vec!();
+    sat_dump::write(&c, &s, s.theory().th.final_state(), &mut out).unwrap();
+    let dump = String::from_utf8(out).unwrap();
+    assert!(dump.starts_with("; 3 theory atoms, 1 inconsistent with the partition\n"), "{}", dump);
+    let bad: Vec<&str> = dump.lines().filter(|l| l.contains(":inconsistent")).collect();
+    assert_eq!(1, bad.len(), "{}", dump);
+    assert!(bad[0].starts_with("(atom (= a c) :value false"), "{}", dump);
+    assert!(dump.contains("(atom (= a b) :value true"), "{}", dump);
+    // `a`, `b` and `c` are in the same class
+    let class = dump.lines().find(|l| l.starts_with("(class") && l.contains(" a")).expect("class of a");
+    assert!(class.contains(" b") && class.contains(" c"), "{}", dump);
+}
@@ -7,6 +7,7 @@ pub mod blit;
 
 pub use crate::{
   lit_map::SatLitMap,
-  solver::Solver,
+  solver::{Solver, Res, ReasonUnknown, RestartPolicy, TheoryError, ClauseKind, ClauseCounts, CancelToken,
+    WarmStart, WARM_START_MAX_LEN, },
   blit::BLit,
 };
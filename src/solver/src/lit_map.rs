@@ -5,9 +5,10 @@ use {
     batsmt_core::{
         ast::{self, AstMap},
         ast_u32::{AST, ManagerU32, },
+        mem::{self, MemTree, ReportMemory},
     },
     batsmt_theory::{self as theory, BoolLit, },
-    batsat::{LMap, },
+    batsat::{LMap, intmap::AsIndex, },
     crate::BLit,
 };
 
@@ -22,6 +23,7 @@ pub struct SatLitMap {
     term_to_lit: ast::HashMap<AST,BLit>,
     new_theory_lits: Vec<(AST,BLit)>, // only bidir terms
     lit_to_term: LMap<(AST,bool)>,
+    lit_to_term_len: usize, // slots in `lit_to_term`, for memory reports
 }
 
 impl theory::LitMap<BLit> for SatLitMap {
@@ -31,6 +33,7 @@ impl theory::LitMap<BLit> for SatLitMap {
             b,
             term_to_lit: ast::HashMap::new(),
             lit_to_term: LMap::new(),
+            lit_to_term_len: 0,
             new_theory_lits: vec!(),
         }
     }
@@ -88,13 +91,31 @@ impl SatLitMap {
             let pad = (AST::SENTINEL, true); // used to fill the map
             self.lit_to_term.insert(lit.0, (t, true), pad);
             self.lit_to_term.insert(! lit.0, (t, false), pad);
+            let len = lit.0.as_index().max((! lit.0).as_index()) + 1;
+            self.lit_to_term_len = self.lit_to_term_len.max(len);
             self.new_theory_lits.push((t, lit));
         }
         self.term_to_lit.insert(t, lit);
     }
 
+    /// Theory atoms, i.e. terms mapped to a literal in both directions,
+    /// along with their literal. The order is not specified.
+    pub fn iter_theory_atoms<'a>(&'a self) -> impl Iterator<Item=(AST,BLit)> + 'a {
+        self.term_to_lit.iter()
+            .filter(move |(t, lit)| theory::LitMap::map_lit(self, **lit) == Some((**t, true)))
+            .map(|(t, lit)| (*t, *lit))
+    }
+
     /// Iterate over new theory literals, and remove them.
     pub fn drain_new_theory_lits<'a>(&'a mut self) -> impl Iterator<Item=(AST,BLit)> + 'a {
         self.new_theory_lits.drain(..)
     }
 }
+
+impl ReportMemory for SatLitMap {
+    fn memory_breakdown(&self, out: &mut MemTree) {
+        out.add("term-to-lit", &self.term_to_lit);
+        out.leaf("lit-to-term", self.lit_to_term_len * std::mem::size_of::<(AST,bool)>());
+        out.leaf("new-theory-lits", mem::vec_bytes(&self.new_theory_lits));
+    }
+}
@@ -2,16 +2,20 @@
 //! Main SMT solver
 
 use {
-    std::{fmt, marker::PhantomData, },
+    std::{fmt, marker::PhantomData, rc::Rc, cell::{Cell, RefCell}, },
     batsat as sat,
     batsmt_theory::{ self as theory,
         Ctx, Theory, TheoryLit, TheoryClauseRef, Trail, LitMap},
-    batsmt_core::{ backtrack, ast_u32::{AST, }, },
+    batsmt_tseitin::{self as tseitin, Tseitin, ClauseBatch},
+    batsmt_core::{
+        backtrack, ast_u32::{AST, }, Error, mem::{self, MemTree, ReportMemory}, hash::HashMap,
+        debug_assert_levels_in_sync,
+    },
     crate::{ lit_map::{SatLitMap}, },
 };
 
 pub use {
-    batsmt_theory::LitMapBuiltins as Builtins,
+    batsmt_theory::{LitMapBuiltins as Builtins, ReasonUnknown, TheoryError, ClauseKind, CancelToken, },
     batsat::lbool,
     crate::blit::BLit,
 };
@@ -22,11 +26,81 @@ struct CoreTheory<C: Ctx<B=BLit>, Th: Theory<C>> {
     lit_map: SatLitMap,
     lits: Vec<sat::Lit>,
     trail_offset: backtrack::Ref<usize>, // current offset in the trail for the theory
+    phases: Option<Vec<lbool>>, // last value of each variable in a check, see `set_track_state`
+    phase_hints: HashMap<AST,bool>, // default polarity of new atoms, see `import_state`
     th_trail: Vec<(AST,bool,BLit)>, // temporary for trail slices
     th_stats: theory::Stats,
+    incomplete: Option<&'static str>, // approximation used in the current `solve`
+    error: Option<TheoryError<BLit>>, // error raised in the current `solve`
+    stop: Rc<Cell<bool>>, // shared with the SAT solver's callbacks
+    cancel: CancelToken, // shared with the SAT solver's callbacks, and the user
+    lemma_db: Rc<RefCell<LemmaDb>>, // shared with the SAT solver's callbacks
     _m: PhantomData<C>,
 }
 
+/// Theory lemmas given to the SAT solver.
+///
+/// The SAT solver stores theory lemmas along with its learnt clauses, and
+/// deletes some of them when reducing its database. Permanent lemmas it
+/// deletes are given back to it at the next theory check.
+#[derive(Default)]
+struct LemmaDb {
+    permanent: HashMap<(usize,u64), Vec<sat::Lit>>, // permanent lemmas, by key
+    to_push: Vec<Vec<sat::Lit>>, // lemmas to give to the SAT solver at the next check
+}
+
+/// Number of clauses in the SAT solver, by kind.
+#[derive(Clone,Copy,Debug,Default,Eq,PartialEq)]
+pub struct ClauseCounts {
+    /// Input clauses added so far.
+    pub input: usize,
+    /// Learnt clauses currently in the SAT solver.
+    pub learned: usize,
+    /// Distinct permanent lemmas added so far. They are never lost.
+    pub lemmas_permanent: usize,
+    /// Deletable lemmas currently in the SAT solver.
+    pub lemmas_deletable: usize,
+    /// Deletable lemmas deleted by the SAT solver.
+    pub lemmas_deleted: usize,
+    /// Permanent lemmas deleted by the SAT solver, and given back to it.
+    pub lemmas_restored: usize,
+}
+
+/// Clauses learnt by a solver, and the phase and activity of its atoms,
+/// to warm-start another solver on the same problem.
+///
+/// See `Solver::export_state` and `Solver::import_state`.
+#[derive(Clone,Debug,Default)]
+pub struct WarmStart {
+    clauses: Vec<Vec<(AST,bool)>>,
+    phases: Vec<(AST,bool)>,
+    activities: Vec<(AST,f64)>,
+}
+
+impl WarmStart {
+    /// The clauses, as signed atoms.
+    pub fn clauses(&self) -> &[Vec<(AST,bool)>] { &self.clauses }
+
+    /// The saved phase of each atom: the last value it had in a check of the theory.
+    pub fn phases(&self) -> &[(AST,bool)] { &self.phases }
+
+    /// The activity of each atom that occurs in learnt clauses.
+    ///
+    /// The SAT solver does not give access to its own activities: each
+    /// learnt clause bumps its atoms, with the same decay as the SAT solver.
+    pub fn activities(&self) -> &[(AST,f64)] { &self.activities }
+}
+
+/// Longest learnt clause kept by `Solver::export_state`.
+pub const WARM_START_MAX_LEN: usize = 12;
+
+// Decay of the activities of `WarmStart`, the SAT solver's default.
+const VAR_DECAY: f64 = 0.95;
+
+// Estimates of the SAT solver's memory layout, whose internals are private.
+const SAT_CLAUSE_BYTES: usize = 8 + 2 * 8; // header and activity, two watchers
+const SAT_VAR_BYTES: usize = 48; // value, reason, level, activity, heap, polarity, watch lists
+
 /// Temporary bundle of theory + context, to be passed to the SAT solver.
 struct TheoryTmp<'a, C: Ctx<B=BLit>, Th: Theory<C>>(&'a mut CoreTheory<C,Th>, &'a mut C);
 
@@ -37,6 +111,11 @@ struct TmpAct<'a, 'b: 'a> {
     acts: &'a mut sat::theory::TheoryArg<'b>,
     lits: &'a mut Vec<sat::Lit>,
     lit_map: &'a mut SatLitMap,
+    incomplete: &'a mut Option<&'static str>,
+    error: &'a mut Option<TheoryError<BLit>>,
+    stop: &'a Cell<bool>,
+    cancel: &'a CancelToken,
+    lemma_db: &'a RefCell<LemmaDb>,
 }
 
 /// A SMT solver.
@@ -45,7 +124,32 @@ struct TmpAct<'a, 'b: 'a> {
 /// a theory to interpret boolean terms.
 pub struct Solver<C: Ctx<B=BLit>, Th: Theory<C>> {
     s0: Solver0<C,Th>,
+    sat_cfg: SatConfig, // options the SAT solver was built with
     lits: Vec<sat::Lit>, // temporary for clause
+    origins: Vec<sat::Lit>, // selector of each assertion, see `add_clause_from`
+    tseitin: Tseitin<C>, // for `assert_formula`
+    has_model: bool, // did the last `solve` return `SAT`, with no blocking clause since?
+    empty_clause: bool, // was an empty clause added? then UNSAT, without search
+    warm_clauses: Vec<Vec<(AST,bool)>>, // from `import_state`, added at the next `solve`
+    last_error: Option<TheoryError<BLit>>, // error raised by the theory in the last `solve`
+}
+
+/// When the SAT solver restarts its search.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum RestartPolicy {
+    /// Restart after `base * luby(i)` conflicts, where `luby` is the Luby sequence.
+    Luby { base: u32 },
+    /// Restart after `base` conflicts, then multiply the interval by `factor`.
+    Geometric { base: u32, factor: f64 },
+    /// Never restart.
+    None,
+}
+
+/// Options used to build the SAT solver.
+#[derive(Clone,Copy,Debug)]
+struct SatConfig {
+    max_learnts: Option<usize>,
+    restarts: RestartPolicy,
 }
 
 struct Solver0<C: Ctx<B=BLit>, Th: Theory<C>> {
@@ -53,11 +157,16 @@ struct Solver0<C: Ctx<B=BLit>, Th: Theory<C>> {
     sat: batsat::Solver<solver::Cb>,
 }
 
+impl Default for RestartPolicy {
+    fn default() -> Self { RestartPolicy::Geometric{base: 1000, factor: 15.} }
+}
+
 /// Result of a call to `solve`
 #[derive(Clone,Debug,Eq,PartialEq)]
 pub enum Res {
     SAT,
     UNSAT,
+    Unknown(ReasonUnknown),
 }
 
 /// Map theory literals into boolean literals.
@@ -84,7 +193,7 @@ fn get_or_create_lit_<C, F>(
 
 mod solver {
     use {
-        super::*, batsat::SolverInterface,
+        super::*, batsat::{SolverInterface, intmap::AsIndex},
         batsmt_pretty::{Pretty1},
         batsmt_theory::LitMap,
     };
@@ -101,21 +210,31 @@ mod solver {
                 th,
                 _m: PhantomData,
                 th_stats: theory::Stats::new(),
+                incomplete: None,
+                error: None,
+                stop: Rc::new(Cell::new(false)),
+                cancel: CancelToken::new(),
+                lemma_db: Rc::new(RefCell::new(LemmaDb::default())),
                 lit_map,
                 trail_offset: backtrack::Ref::new(0),
+                phases: None,
+                phase_hints: HashMap::default(),
                 th_trail: Vec::new(),
             };
-            let cb = Cb::new();
-            let mut opts = batsat::SolverOpts::default();
-            opts.luby_restart = false;
-            opts.restart_first = 1000;
-            opts.restart_inc = 15.;
-            opts.min_learnts_lim = 1_200; // min number of learnt clauses
             // create SAT solver
-            let sat = batsat::Solver::new_with(opts, cb);
+            let sat_cfg = SatConfig{max_learnts: None, restarts: RestartPolicy::default()};
+            let cb = Cb::new(c.stop.clone(), c.cancel.clone(), c.lemma_db.clone());
+            let sat = batsat::Solver::new_with(sat_cfg.opts(), cb);
             let mut s = Solver {
                 s0: Solver0 { sat, c, },
+                sat_cfg,
                 lits: Vec::new(),
+                origins: Vec::new(),
+                tseitin: Tseitin::new(),
+                has_model: false,
+                empty_clause: false,
+                warm_clauses: Vec::new(),
+                last_error: None,
             };
             s.init_logic();
             s
@@ -126,9 +245,78 @@ mod solver {
             trace!("solver.init-logic")
         }
 
+        /// Bound the number of learnt clauses kept by the SAT solver.
+        ///
+        /// When the database exceeds `n` clauses, it is reduced by removing
+        /// the learnt clauses with the lowest activity (roughly half of them).
+        /// Theory lemmas count towards `n`, including permanent ones, which
+        /// are given back to the SAT solver if it removes them.
+        /// By default the bound starts at 1200 and grows with the number
+        /// of clauses and restarts.
+        ///
+        /// Must be called before any literal or clause is added.
+        pub fn set_max_learnts(&mut self, n: usize) {
+            debug!("solver.set-max-learnts {}", n);
+            self.sat_cfg.max_learnts = Some(n);
+            self.rebuild_sat("set_max_learnts");
+        }
+
+        /// Choose when the SAT solver restarts.
+        ///
+        /// The default is `RestartPolicy::Geometric{base: 1000, factor: 15.}`.
+        ///
+        /// Must be called before any literal or clause is added.
+        pub fn set_restart_policy(&mut self, p: RestartPolicy) {
+            debug!("solver.set-restart-policy {:?}", p);
+            self.sat_cfg.restarts = p;
+            self.rebuild_sat("set_restart_policy");
+        }
+
+        // the SAT solver takes its options at creation, build a new one
+        fn rebuild_sat(&mut self, what: &str) {
+            assert!(self.n_lits() == 0 && self.n_clauses() == 0,
+                    "{}: solver is not empty", what);
+            let c = &self.s0.c;
+            let mut cb = Cb::new(c.stop.clone(), c.cancel.clone(), c.lemma_db.clone());
+            cb.track_learnts = self.s0.sat.cb().track_learnts;
+            self.s0.sat = batsat::Solver::new_with(self.sat_cfg.opts(), cb);
+        }
+
+        /// Track what `learned_clauses` and `export_state` need: a copy of each
+        /// learnt clause, and the phase and activity of each variable.
+        ///
+        /// The copies are removed when the SAT solver deletes the clauses.
+        /// Only what happens while it is enabled is tracked. Disabled by default.
+        pub fn set_track_state(&mut self, b: bool) {
+            debug!("solver.set-track-state {}", b);
+            self.s0.sat.cb_mut().track_learnts = b;
+            let phases = &mut self.s0.c.phases;
+            *phases = if b { Some(phases.take().unwrap_or_default()) } else { None };
+        }
+
+        /// Use `tok` to interrupt `solve`, possibly from another thread.
+        ///
+        /// The SAT solver polls the token between conflicts, and the theory
+        /// between steps of its checks (see `Actions::is_canceled`), and the
+        /// simplification of asserted formulas stops early (see
+        /// `Tseitin::set_cancel_token`). Once canceled, `solve` returns `Res::Unknown(ReasonUnknown::Canceled)`
+        /// and the solver can be used again, after `tok.reset()`.
+        pub fn set_cancel_token(&mut self, tok: CancelToken) {
+            debug!("solver.set-cancel-token");
+            self.s0.c.cancel = tok.clone();
+            self.tseitin.set_cancel_token(tok.clone());
+            self.s0.sat.cb_mut().cancel = tok;
+        }
+
         /// Access statistics.
         pub fn th_stats(&self) -> &theory::Stats { &self.s0.c.th_stats }
 
+        /// Access the theory.
+        pub fn theory(&self) -> &Th { &self.s0.c.th }
+
+        /// Access the theory, mutably.
+        pub fn theory_mut(&mut self) -> &mut Th { &mut self.s0.c.th }
+
         /// Access literal map of this solver.
         #[inline(always)]
         pub fn lit_map(&self) -> &SatLitMap { & self.s0.c.lit_map }
@@ -146,6 +334,10 @@ mod solver {
         #[inline]
         pub fn add_bool_clause_reuse(&mut self, c: &mut Vec<sat::Lit>) {
             trace!("solver.add-bool-clause {:?}", c);
+            let cb = self.s0.sat.cb_mut();
+            cb.counts.input += 1;
+            cb.n_lits += c.len();
+            self.empty_clause |= c.is_empty();
             self.s0.sat.add_clause_reuse(c);
         }
 
@@ -163,12 +355,15 @@ mod solver {
         }
 
         /// Get or allocate new boolean variable with default polarity `false`, mapped to given term.
+        ///
+        /// The polarity is the phase of `t` given to `import_state`, if any.
         pub fn new_term_lit(&mut self, ctx: &mut C, t: AST) -> BLit {
             let Solver0{sat, c, ..} = &mut self.s0;
+            let pol = c.default_polarity(ctx, &t);
             let lm = &mut c.lit_map;
 
             let f = || {
-                BLit::from_var(sat.new_var_default(), true)
+                BLit::from_var(new_var(sat, pol), true)
             };
 
             let bidir = true; // theory lit
@@ -183,7 +378,16 @@ mod solver {
 
         /// Add a clause made from signed terms.
         pub fn add_clause(&mut self, m: &C, c: TheoryClauseRef<C>) {
-            trace!("solver.add-clause\n{}", c.pp(m));
+            self.add_clause_with_kind(m, c, ClauseKind::Input)
+        }
+
+        /// Add a clause made from signed terms, of the given kind.
+        ///
+        /// Input clauses and permanent lemmas are never deleted. A deletable
+        /// lemma is given to the SAT solver at its next theory check, and
+        /// may be deleted afterwards. Learnt clauses only come from the SAT solver.
+        pub fn add_clause_with_kind(&mut self, m: &C, c: TheoryClauseRef<C>, kind: ClauseKind) {
+            trace!("solver.add-clause ({:?})\n{}", kind, c.pp(m));
             // use `self.lits` as temporary storage
             self.lits.clear();
             let s0 = &mut self.s0;
@@ -193,9 +397,110 @@ mod solver {
                     let lit = s0.get_or_create_lit(m, lit);
                     lit.0
                 }));
+            // the empty clause is UNSAT whatever its kind, and cannot be deleted
+            self.empty_clause |= self.lits.is_empty();
+            let kind = match kind {
+                ClauseKind::TheoryLemmaDeletable if self.lits.is_empty() =>
+                    ClauseKind::TheoryLemmaPermanent,
+                k => k,
+            };
+            let cb = self.s0.sat.cb_mut();
+            match kind {
+                ClauseKind::Input => cb.counts.input += 1,
+                ClauseKind::TheoryLemmaPermanent => cb.counts.lemmas_permanent += 1,
+                ClauseKind::TheoryLemmaDeletable => (), // counted once the SAT solver has it
+                ClauseKind::Learned => panic!("solver.add-clause: cannot add a learnt clause"),
+            }
+            if kind == ClauseKind::TheoryLemmaDeletable {
+                // given to the SAT solver at its next theory check, like the
+                // lemmas of the theory: its literals are counted in `Cb::on_new_clause`
+                self.s0.c.lemma_db.borrow_mut().to_push.push(self.lits.clone());
+            } else {
+                cb.n_lits += self.lits.len();
+                self.s0.sat.add_clause_reuse(&mut self.lits);
+            }
+        }
+
+        /// Was the empty clause added? If so, `solve` answers `UNSAT` at once.
+        ///
+        /// Clauses added with `add_clause_from` are never empty, since they
+        /// contain the selector of their assertion.
+        pub fn has_empty_clause(&self) -> bool { self.empty_clause }
+
+        /// The Tseitin transformation used by `assert_formula`.
+        pub fn tseitin(&self) -> &Tseitin<C> { &self.tseitin }
+
+        /// Mutable access to the Tseitin transformation, e.g. to configure it.
+        pub fn tseitin_mut(&mut self) -> &mut Tseitin<C> { &mut self.tseitin }
+
+        /// Add the clauses of `batch`, after declaring its literals.
+        ///
+        /// If the batch comes from an assertion (see `ClauseBatch::origin`),
+        /// its clauses are added with `add_clause_from`.
+        /// The batch is then given back to the Tseitin transformation.
+        pub fn add_batch(&mut self, m: &C, batch: ClauseBatch<C>) {
+            for lit in batch.lits() {
+                self.add_lit(m, lit.clone());
+            }
+            for c in batch.iter() {
+                match batch.origin() {
+                    Some(i) => self.add_clause_from(m, c, i),
+                    None => self.add_clause(m, c),
+                }
+            }
+            self.tseitin.recycle(batch);
+        }
+
+        /// Same as `add_batch`, with clauses of the given kind (see `add_clause_with_kind`).
+        ///
+        /// The origin of `batch` is ignored: the clauses are not tracked.
+        pub fn add_batch_with_kind(&mut self, m: &C, batch: ClauseBatch<C>, kind: ClauseKind) {
+            for lit in batch.lits() {
+                self.add_lit(m, lit.clone());
+            }
+            for c in batch.iter() {
+                self.add_clause_with_kind(m, c, kind);
+            }
+            self.tseitin.recycle(batch);
+        }
+
+        /// Add a clause coming from the assertion number `origin`.
+        ///
+        /// The clause is guarded by a selector literal for `origin`, which is
+        /// assumed by each call to `solve`; after `UNSAT`, `unsat_core_indices`
+        /// tells which assertions were used.
+        pub fn add_clause_from(&mut self, m: &C, c: TheoryClauseRef<C>, origin: usize) {
+            trace!("solver.add-clause (from #{})\n{}", origin, c.pp(m));
+            while self.origins.len() <= origin {
+                let sel = self.new_bool_lit_with(true);
+                self.origins.push(sel);
+            }
+            self.lits.clear();
+            let s0 = &mut self.s0;
+            self.lits.extend(c.iter().map(|lit| s0.get_or_create_lit(m, lit).0));
+            self.lits.push(! self.origins[origin]);
+            let cb = self.s0.sat.cb_mut();
+            cb.counts.input += 1;
+            cb.n_lits += self.lits.len();
             self.s0.sat.add_clause_reuse(&mut self.lits);
         }
 
+        /// Indices of the assertions used to prove `UNSAT`.
+        ///
+        /// Only clauses added with `add_clause_from` are tracked. This is an
+        /// over-approximation: the SAT solver's final conflict analysis does not
+        /// minimize the set of assumptions it returns, and the clauses that
+        /// define a subterm are attributed to the first assertion containing it.
+        /// Only meaningful if the last call to `solve` returned `Res::UNSAT`.
+        pub fn unsat_core_indices(&mut self) -> Vec<usize> {
+            if self.empty_clause { return vec!() } // no assertion needed
+            let Solver{s0, origins, ..} = self;
+            origins.iter().enumerate()
+                .filter(|(_, sel)| s0.sat.unsat_core_contains_lit(**sel))
+                .map(|(i, _)| i)
+                .collect()
+        }
+
         // add new theory literals to the theory.
         fn add_initial_literals(&mut self, m: &mut C) {
             debug!("solver.theory.add-lits");
@@ -206,12 +511,37 @@ mod solver {
         }
 
         /// Solve the set of constraints added with `add_clause` until now
-        pub fn solve_with(&mut self, m: &mut C, assumptions: &[sat::Lit]) -> Res {
+        ///
+        /// Fails if the theory could not handle the problem; see `last_error`
+        /// for the literal that caused it.
+        pub fn solve_with(&mut self, m: &mut C, assumptions: &[sat::Lit]) -> Result<Res, Error> {
             info!("solver.sat.solve ({} assumptions)", assumptions.len());
 
             self.add_initial_literals(m);
+            self.add_warm_clauses(m);
+
+            if self.empty_clause {
+                info!("solver: empty clause, unsat");
+                self.has_model = false;
+                self.last_error = None;
+                return Ok(Res::UNSAT)
+            }
 
             trace!("assumptions: {:?}", assumptions);
+            // tag the clauses learnt from now on, see `export_state`. Selectors
+            // do not count, they only occur in the clauses they guard.
+            self.s0.sat.cb_mut().tainted |= !assumptions.is_empty();
+            self.s0.c.incomplete = None;
+            self.s0.c.error = None;
+            self.s0.c.stop.set(false);
+            // selectors of tracked assertions are assumed too
+            let with_origins: Vec<sat::Lit>;
+            let assumptions = if self.origins.is_empty() {
+                assumptions
+            } else {
+                with_origins = assumptions.iter().chain(self.origins.iter()).cloned().collect();
+                &with_origins[..]
+            };
             let sat = &mut self.s0.sat;
             let r = {
                 // temporary theory, pass it to SAT
@@ -222,15 +552,40 @@ mod solver {
                   self.s0.c.th_stats,
                   sat.num_conflicts(), sat.num_decisions(),
                   sat.num_propagations(), sat.cb().stats());
+            // a model found while canceled may come from an unfinished check
+            let canceled = self.s0.c.cancel.is_canceled();
+            self.has_model = r == lbool::TRUE && self.s0.c.error.is_none() && !canceled;
+            self.last_error = self.s0.c.error.take();
+            if let Some(err) = &self.last_error {
+                info!("solver: theory error: {}", err);
+                return Err(err.err.clone())
+            }
             // convert result
-            if r == lbool::TRUE {
-                Res::SAT
-            } else {
-                assert_eq!(r, lbool::FALSE);
+            let res = if canceled && r != lbool::FALSE {
+                info!("solver: canceled");
+                Res::Unknown(ReasonUnknown::Canceled)
+            } else if r == lbool::TRUE {
+                match self.s0.c.incomplete {
+                    None => Res::SAT,
+                    Some(feature) => {
+                        info!("solver: sat, but {} was approximated", feature);
+                        Res::Unknown(ReasonUnknown::Incomplete{feature})
+                    },
+                }
+            } else if r == lbool::FALSE {
                 Res::UNSAT
-            }
+            } else {
+                Res::Unknown(ReasonUnknown::ResourceOut)
+            };
+            Ok(res)
         }
 
+        /// Error raised by the theory during the last call to `solve`, if any.
+        ///
+        /// Unlike the `Error` returned by `solve`, it carries the literal
+        /// the theory choked on.
+        pub fn last_error(&self) -> Option<&TheoryError<BLit>> { self.last_error.as_ref() }
+
         /* TODO
         pub fn get_model(&self) -> &[(BLit, lbool)] {
             let mut v = vec!();
@@ -244,6 +599,7 @@ mod solver {
 
         /// Unsat core
         pub fn get_unsat_core(&mut self) -> &[sat::Lit] {
+            if self.empty_clause { return &[] }
             self.s0.sat.unsat_core()
         }
 
@@ -265,16 +621,70 @@ mod solver {
             self.s0.sat.value_lit(lit.0)
         }
 
+        /// Value of `lit` in the model found by the last call to `solve`.
+        ///
+        /// Only meaningful if this call returned `Res::SAT`.
+        pub fn value_in_model(&self, lit: BLit) -> lbool {
+            let model = self.s0.sat.get_model();
+            let i = lit.0.var().as_index();
+            if i >= model.len() {
+                lbool::UNDEF
+            } else if lit.0.sign() {
+                model[i]
+            } else {
+                ! model[i]
+            }
+        }
+
         #[inline]
         pub fn unsat_core_contains_lit(&mut self, lit: sat::Lit) -> bool {
             self.s0.sat.unsat_core_contains_lit(lit)
         }
 
         /// Solve without assumptions.
-        pub fn solve(&mut self, m: &mut C) -> Res {
+        pub fn solve(&mut self, m: &mut C) -> Result<Res, Error> {
             self.solve_with(m, &[])
         }
 
+        /// Add a clause that excludes the current model, projected on `projection`.
+        ///
+        /// Future models differ from the current one on at least one of the
+        /// atoms of `projection`. Atoms that have no literal (they do not occur
+        /// in the problem) or no value are ignored. The solver has no scopes,
+        /// so the clause is permanent.
+        ///
+        /// Must be called after `solve` returned `Res::SAT`.
+        pub fn block_current_model(&mut self, m: &C, projection: &[AST]) {
+            assert!(self.has_model, "block_current_model: no current model");
+            self.lits.clear();
+            for t in projection {
+                let lit = match self.s0.c.lit_map.get_term(m, t, true) {
+                    Some(lit) => lit,
+                    None => continue,
+                };
+                let v = self.value_in_model(lit);
+                if v == lbool::TRUE {
+                    self.lits.push(! lit.0)
+                } else if v == lbool::FALSE {
+                    self.lits.push(lit.0)
+                }
+            }
+            debug!("solver.block-model ({} lits)", self.lits.len());
+            self.empty_clause |= self.lits.is_empty();
+            self.s0.sat.add_clause_reuse(&mut self.lits);
+            self.s0.sat.cb_mut().tainted = true;
+            self.has_model = false;
+        }
+
+        /// Block the current model (see `block_current_model`) and solve again.
+        ///
+        /// Calling it until it stops returning `Res::SAT` enumerates the
+        /// assignments of `block_on`'s atoms that can be extended into a model.
+        pub fn next_model(&mut self, m: &mut C, block_on: &[AST]) -> Result<Res, Error> {
+            self.block_current_model(m, block_on);
+            self.solve(m)
+        }
+
         /// Simplify boolean clauses.
         pub fn sat_simplify(&mut self) -> Res {
             let b = self.s0.sat.simplify();
@@ -286,6 +696,189 @@ mod solver {
         pub fn n_conflicts(&self) -> usize { self.s0.sat.num_conflicts() as usize }
         pub fn n_props(&self) -> usize { self.s0.sat.num_propagations() as usize }
         pub fn n_decisions(&self) -> usize { self.s0.sat.num_decisions() as usize }
+        pub fn n_restarts(&self) -> usize { self.s0.sat.cb().n_restarts as usize }
+        /// Number of learnt clauses currently in the SAT solver, including theory lemmas.
+        pub fn n_learnts(&self) -> usize { self.s0.sat.cb().n_learnts() }
+        /// Largest number of learnt clauses kept at once so far, including theory lemmas.
+        pub fn n_learnts_peak(&self) -> usize { self.s0.sat.cb().learnts_peak }
+
+        /// Learnt clauses currently in the SAT solver, not including theory lemmas.
+        ///
+        /// They are consequences of the clauses and lemmas added so far,
+        /// and are lost when the SAT solver is rebuilt (e.g. by `set_max_learnts`).
+        /// Empty unless `set_track_state` was enabled before solving.
+        pub fn learned_clauses(&self) -> Vec<Vec<C::B>> {
+            self.s0.sat.cb().learnts()
+                .map(|(c, _)| c.iter().map(|lit| BLit(*lit)).collect())
+                .collect()
+        }
+
+        /// Learnt clauses and literals proved at level 0, to warm-start another solver.
+        ///
+        /// Only learnt clauses with at most `WARM_START_MAX_LEN` literals, all
+        /// of them mapped to atoms, are kept. Clauses learnt during a `solve_with`
+        /// with assumptions, or after a call to `block_current_model`, are not
+        /// consequences of the problem alone: they are never exported, and
+        /// neither are the literals proved at level 0 once that happened.
+        ///
+        /// Learnt clauses, phases and activities are only exported if
+        /// `set_track_state` is enabled.
+        pub fn export_state(&self) -> WarmStart {
+            let cb = self.s0.sat.cb();
+            let lit_map = &self.s0.c.lit_map;
+            let to_atoms = |c: &[sat::Lit]| -> Option<Vec<(AST,bool)>> {
+                c.iter().map(|lit| lit_map.map_lit(BLit(*lit))).collect()
+            };
+            let mut clauses: Vec<_> = cb.learnts()
+                .filter(|&(c, tainted)| !tainted && c.len() <= WARM_START_MAX_LEN)
+                .filter_map(|(c, _)| to_atoms(c))
+                .collect();
+            if !cb.tainted {
+                clauses.extend(self.proved_at_lvl_0().iter().filter_map(|lit| to_atoms(&[*lit])));
+            }
+            let var = |i: usize| sat::Lit::new(sat::Var::from_index(i), true);
+            let phases: Vec<_> = self.s0.c.phases.iter().flatten().enumerate()
+                .filter(|&(_, v)| *v != lbool::UNDEF)
+                .filter_map(|(i, v)| {
+                    let lit = if *v == lbool::TRUE { var(i) } else { !var(i) };
+                    lit_map.map_lit(BLit(lit))
+                })
+                .collect();
+            let activities: Vec<_> = cb.activity.iter().enumerate()
+                .filter(|&(_, a)| *a > 0.)
+                .filter_map(|(i, a)| lit_map.map_lit(BLit(var(i))).map(|(t, _)| (t, *a)))
+                .collect();
+            debug!("solver.export-state ({} clauses, {} phases, {} activities)",
+                   clauses.len(), phases.len(), activities.len());
+            WarmStart{clauses, phases, activities}
+        }
+
+        /// Use `w`, exported by a solver for the same problem over the same manager.
+        ///
+        /// The saved phase of an atom becomes the default polarity of its
+        /// variable (see `new_bool_lit_with`) when the variable is created, so
+        /// this should be called before adding the problem. The clauses are
+        /// added at the next `solve`. Literals are found through their atoms,
+        /// so the problem may have been encoded differently; clauses with an
+        /// atom that has no literal in this solver by then are dropped.
+        ///
+        /// The activities are not imported, as the SAT solver has no way to set them.
+        pub fn import_state(&mut self, w: &WarmStart) {
+            debug!("solver.import-state ({} clauses, {} phases)", w.clauses.len(), w.phases.len());
+            self.s0.c.phase_hints.extend(w.phases.iter().cloned());
+            self.warm_clauses.extend(w.clauses.iter().cloned());
+        }
+
+        // add the clauses given to `import_state`
+        fn add_warm_clauses(&mut self, m: &C) {
+            if self.warm_clauses.is_empty() { return }
+            let Solver{s0, lits, empty_clause, warm_clauses, ..} = self;
+            let mut n = 0;
+            'clauses: for c in warm_clauses.iter() {
+                lits.clear();
+                for (t, sign) in c.iter() {
+                    match s0.c.lit_map.get_term(m, t, *sign) {
+                        Some(lit) => lits.push(lit.0),
+                        None => continue 'clauses, // the atom no longer exists
+                    }
+                }
+                n += 1;
+                let cb = s0.sat.cb_mut();
+                cb.counts.input += 1;
+                cb.n_lits += lits.len();
+                *empty_clause |= lits.is_empty();
+                s0.sat.add_clause_reuse(lits);
+            }
+            debug!("solver.add-warm-clauses ({} clauses out of {})", n, warm_clauses.len());
+            warm_clauses.clear();
+        }
+
+        /// Number of clauses, by kind.
+        pub fn clause_counts(&self) -> ClauseCounts {
+            let mut counts = self.s0.sat.cb().counts;
+            counts.lemmas_permanent += self.s0.c.lemma_db.borrow().permanent.len();
+            counts
+        }
+
+        /// Estimated memory usage of the solver: theory, literal map,
+        /// and clause database.
+        ///
+        /// The SAT solver's own structures are not visible, their size
+        /// is estimated from the number of variables, clauses and literals.
+        /// The AST manager belongs to the caller, which can add it to the
+        /// returned tree, along with `tseitin()`, which is not included.
+        pub fn memory_report(&self) -> MemTree where Th: ReportMemory {
+            let Solver0{sat, c} = &self.s0;
+            let cb = sat.cb();
+            let mut out = MemTree::new("solver");
+            out.add("theory", &c.th);
+            out.add("lit-map", &c.lit_map);
+            {
+                let out = out.child("clause-db");
+                let n_clauses = cb.counts.input + cb.counts.lemmas_permanent + cb.n_learnts;
+                out.leaf("clauses",
+                    n_clauses * SAT_CLAUSE_BYTES + cb.n_lits * std::mem::size_of::<sat::Lit>());
+                out.leaf("vars", sat.num_vars() as usize * SAT_VAR_BYTES);
+                let learnts: usize =
+                    cb.learnts.values().map(|cs| mem::vec_bytes(cs)
+                        + cs.iter().map(|(c, _)| mem::vec_bytes(c)).sum::<usize>()).sum();
+                out.leaf("tracking",
+                    mem::hashmap_bytes(&cb.learnt_keys) + mem::hashmap_bytes(&cb.learnts) + learnts
+                    + mem::hashmap_bytes(&cb.lemmas) + mem::hashmap_bytes(&cb.permanent)
+                    + mem::vec_bytes(&cb.activity) + c.phases.as_ref().map_or(0, mem::vec_bytes)
+                    + mem::hashmap_bytes(&c.phase_hints));
+                let db = c.lemma_db.borrow();
+                let lemmas: usize =
+                    db.permanent.values().chain(db.to_push.iter()).map(mem::vec_bytes).sum();
+                out.leaf("lemma-db",
+                    mem::hashmap_bytes(&db.permanent) + mem::vec_bytes(&db.to_push) + lemmas);
+            }
+            out.leaf("buffers",
+                mem::vec_bytes(&self.lits) + mem::vec_bytes(&self.origins)
+                + mem::vec_bytes(&c.lits) + mem::vec_bytes(&c.th_trail));
+            out
+        }
+    }
+
+    // asserting formulas
+    impl<C,Th> Solver<C,Th>
+        where C: tseitin::Ctx<B=BLit>, Th: Theory<C>
+    {
+        /// Assert the boolean formula `t`.
+        ///
+        /// `t` is simplified and turned into clauses by the solver's
+        /// Tseitin transformation, which remembers the subformulas it has
+        /// already defined: asserting `t` again only adds its top clause.
+        /// There are no scopes, the clauses are never removed.
+        pub fn assert_formula(&mut self, m: &mut C, t: AST) -> Result<(), Error> {
+            let batch = self.clausify(m, t, None)?;
+            self.add_batch(m, batch);
+            Ok(())
+        }
+
+        /// Same as `assert_formula`, for the assertion number `name`.
+        ///
+        /// The clauses are added with `add_clause_from`, so that
+        /// `unsat_core_indices` can tell whether the assertion was used.
+        pub fn assert_formula_named(&mut self, m: &mut C, t: AST, name: usize) -> Result<(), Error> {
+            let batch = self.clausify(m, t, Some(name))?;
+            self.add_batch(m, batch);
+            Ok(())
+        }
+
+        /// Turn `t` into clauses, without adding them; see `add_batch`.
+        ///
+        /// `origin` is the index of the assertion `t` comes from, if any.
+        pub fn clausify(
+            &mut self, m: &mut C, t: AST, origin: Option<usize>
+        ) -> Result<ClauseBatch<C>, Error> {
+            let Solver{s0, tseitin, ..} = self;
+            let lit_map = &mut s0.c.lit_map;
+            match origin {
+                Some(i) => tseitin.clauses_owned_from(m, lit_map, t, i),
+                None => tseitin.clauses_owned(m, lit_map, t),
+            }
+        }
     }
 
     impl<C,Th> CoreTheory<C, Th>
@@ -296,12 +889,36 @@ mod solver {
             self.lit_map.map_lit(lit)
         }
 
+        // the theory must have as many levels as the solver
+        #[inline(always)]
+        fn debug_assert_levels(&self) {
+            debug_assert_levels_in_sync!(
+                "solver" => self.trail_offset.n_levels(), "theory" => self.th.n_levels());
+        }
+
         // internal checking
         fn check<'a>(&mut self, m: &mut C, partial: bool, a: &mut sat::theory::TheoryArg<'a>)
         {
+            self.debug_assert_levels();
+            // lemmas added before solving, or deleted by the SAT solver
+            for c in self.lemma_db.borrow_mut().to_push.drain(..) {
+                trace!("solver.push-lemma {:?}", c);
+                a.add_theory_lemma(&c);
+            }
+
+            if let Some(phases) = &mut self.phases {
+                // the part of the trail not seen yet
+                for lit in a.model()[*self.trail_offset ..].iter() {
+                    let i = lit.var().as_index();
+                    if i >= phases.len() { phases.resize(i+1, lbool::UNDEF) }
+                    phases[i] = if lit.sign() { lbool::TRUE } else { lbool::FALSE };
+                }
+            }
+
             // no need to parse the trail or do anything, if the theory doesn't support partial
             // checks
             if partial && ! Th::has_partial_check() {
+                *self.trail_offset = a.model().len(); // only used for `phases`
                 return;
             }
 
@@ -343,8 +960,12 @@ mod solver {
                 return; // trivial
             }
 
-            let CoreTheory{lits, th, lit_map, th_trail, th_stats: stats, ..} = self;
-            let mut acts = TmpAct{ok: true, acts: a, lits, lit_map, stats};
+            let CoreTheory{
+                lits, th, lit_map, th_trail, th_stats: stats, incomplete, error,
+                stop, cancel, lemma_db, ..} = self;
+            let mut acts = TmpAct{
+                ok: true, acts: a, lits, lit_map, stats, incomplete, error, stop, cancel, lemma_db,
+            };
             if partial {
                 th.partial_check(m, &mut acts, &Trail::from_slice(&th_trail));
             } else {
@@ -360,10 +981,12 @@ mod solver {
         fn create_level(&mut self) {
             self.0.trail_offset.push_level();
             self.0.th.push_level(self.1);
+            self.0.debug_assert_levels();
         }
         fn pop_levels(&mut self, n: usize) {
             self.0.trail_offset.pop_levels(n);
             self.0.th.pop_levels(self.1, n);
+            self.0.debug_assert_levels();
         }
         fn n_levels(&self) -> usize {
             self.0.trail_offset.n_levels()
@@ -401,31 +1024,182 @@ mod solver {
         #[inline]
         fn get_or_create_lit(&mut self, ctx: &C, l: TheoryLit<C>) -> BLit {
             let sat = &mut self.sat;
+            let pol = match l {
+                TheoryLit::B(_) => lbool::UNDEF,
+                TheoryLit::BLazy(t,_) | TheoryLit::T(t,_) => self.c.default_polarity(ctx, &t),
+            };
             let f = || {
-                BLit::from_var(sat.new_var_default(), true)
+                BLit::from_var(new_var(sat, pol), true)
             };
             get_or_create_lit_(ctx, &mut self.c.lit_map, l, f)
         }
     }
 
+    // new variable, with the default polarity `pol` unless it is `UNDEF`
+    fn new_var(sat: &mut batsat::Solver<Cb>, pol: lbool) -> sat::Var {
+        if pol == lbool::UNDEF { sat.new_var_default() } else { sat.new_var(pol, true) }
+    }
+
+    impl<C, Th> CoreTheory<C,Th>
+        where C: Ctx<B=BLit>, Th: Theory<C>
+    {
+        // polarity of a new variable for `t`, from the phases given to `import_state`
+        fn default_polarity(&self, m: &C, t: &AST) -> lbool {
+            if self.phase_hints.is_empty() { return lbool::UNDEF }
+            let (t, sign) = self.lit_map.unfold_not(m, t, true);
+            match self.phase_hints.get(&t) {
+                Some(&b) if b == sign => lbool::TRUE,
+                Some(_) => lbool::FALSE,
+                None => lbool::UNDEF,
+            }
+        }
+    }
+
+    impl SatConfig {
+        /// Options for the SAT solver.
+        pub(super) fn opts(&self) -> batsat::SolverOpts {
+            let mut opts = batsat::SolverOpts::default();
+            match self.restarts {
+                RestartPolicy::Luby{base} => {
+                    opts.luby_restart = true;
+                    opts.restart_first = base as i32;
+                    opts.restart_inc = 2.;
+                },
+                RestartPolicy::Geometric{base, factor} => {
+                    opts.luby_restart = false;
+                    opts.restart_first = base as i32;
+                    opts.restart_inc = factor;
+                },
+                RestartPolicy::None => {
+                    opts.luby_restart = false;
+                    opts.restart_first = i32::max_value(); // never reached
+                    opts.restart_inc = 1.;
+                },
+            }
+            opts.min_learnts_lim = 1_200; // min number of learnt clauses
+            if let Some(n) = self.max_learnts {
+                // the limit is `max(n_clauses * factor, min_learnts_lim)`;
+                // do not let it depend on the problem size, nor grow.
+                opts.min_learnts_lim = n as i32;
+                opts.learntsize_factor = 0.;
+                opts.learntsize_inc = 1.;
+            }
+            opts
+        }
+    }
+
     /// Used for callbacks in the SAT solver.
     pub(super) struct Cb {
         n_restarts: u32,
         n_gc_calls: u32,
+        learnt_keys: HashMap<(usize,u64), u32>, // multiset of live learnt clauses
+        learnts: HashMap<(usize,u64), Vec<(Vec<sat::Lit>, bool)>>, // copies, by key, and their tag
+        pub(super) track_learnts: bool, // fill `learnts` and `activity`? see `set_track_state`
+        pub(super) activity: Vec<f64>, // of each variable, bumped by learnt clauses
+        act_inc: f64, // current bump of `activity`
+        pub(super) tainted: bool, // tag of new learnt clauses, see `export_state`
+        lemmas: HashMap<(usize,u64), u32>, // multiset of live deletable lemmas
+        permanent: HashMap<(usize,u64), u32>, // multiset of live permanent lemmas
+        n_learnts: usize, // learnt clauses and lemmas
+        pub(super) n_lits: usize, // literals in the clauses above, and in input clauses
+        pub(super) learnts_peak: usize,
+        pub(super) counts: ClauseCounts,
+        stop: Rc<Cell<bool>>, // set when the theory raises an error
+        pub(super) cancel: CancelToken, // set by the user
+        lemma_db: Rc<RefCell<LemmaDb>>, // to give back deleted permanent lemmas
     }
 
     impl Cb {
-        fn new() -> Self {
-            Cb { n_restarts: 0, n_gc_calls: 0, }
+        fn new(stop: Rc<Cell<bool>>, cancel: CancelToken, lemma_db: Rc<RefCell<LemmaDb>>) -> Self {
+            Cb {
+                n_restarts: 0, n_gc_calls: 0,
+                learnt_keys: HashMap::default(), learnts: HashMap::default(), track_learnts: false,
+                activity: Vec::new(), act_inc: 1.,
+                lemmas: HashMap::default(), permanent: HashMap::default(),
+                n_learnts: 0, n_lits: 0, learnts_peak: 0, counts: ClauseCounts::default(),
+                tainted: false, stop, cancel, lemma_db,
+            }
+        }
+
+        pub(super) fn n_learnts(&self) -> usize { self.n_learnts }
+
+        // sorted by key, so the order does not depend on the hasher
+        pub(super) fn learnts(&self) -> impl Iterator<Item=(&[sat::Lit], bool)> {
+            let mut by_key: Vec<_> = self.learnts.iter().collect();
+            by_key.sort_unstable_by_key(|(key, _)| **key);
+            by_key.into_iter().flat_map(|(_, cs)| cs.iter().map(|(c, tainted)| (&c[..], *tainted)))
+        }
+
+        // remove the copy of the learnt clause `c`, if present
+        fn remove_learnt(&mut self, c: &[sat::Lit], key: &(usize,u64)) -> bool {
+            let cs = match self.learnts.get_mut(key) {
+                None => return false,
+                Some(cs) => cs,
+            };
+            // same literals, maybe in another order; another clause can have the same key.
+            // Clauses have no duplicate literals, so the same length means the same set.
+            let same = |c2: &[sat::Lit]| c2.len() == c.len() && c.iter().all(|lit| c2.contains(lit));
+            let i = match cs.iter().position(|(c2, _)| same(c2)) {
+                None => return false,
+                Some(i) => i,
+            };
+            cs.swap_remove(i);
+            if cs.is_empty() { self.learnts.remove(key); }
+            true
+        }
+
+        // bump the variables of a learnt clause, then decay all activities
+        fn bump_activity(&mut self, c: &[sat::Lit]) {
+            for lit in c {
+                let i = lit.var().as_index();
+                if i >= self.activity.len() { self.activity.resize(i+1, 0.) }
+                self.activity[i] += self.act_inc;
+                if self.activity[i] > 1e100 {
+                    for a in self.activity.iter_mut() { *a *= 1e-100 }
+                    self.act_inc *= 1e-100;
+                }
+            }
+            self.act_inc /= VAR_DECAY;
         }
 
         fn stats<'a>(&'a self) -> impl fmt::Display+'a { self }
     }
 
+    impl LemmaDb {
+        // `c` is a permanent lemma, given to the SAT solver during search
+        pub(super) fn add_permanent(&mut self, c: &[sat::Lit]) {
+            self.permanent.entry(clause_key(c)).or_insert_with(|| c.to_vec());
+        }
+    }
+
+    // remove one occurrence of `key` from the multiset `m`, if present
+    fn remove_one(m: &mut HashMap<(usize,u64), u32>, key: &(usize,u64)) -> bool {
+        let remove = match m.get_mut(key) {
+            None => return false,
+            Some(n) => { *n -= 1; *n == 0 },
+        };
+        if remove { m.remove(key); }
+        true
+    }
+
+    /// Key for a clause, independent of the order of its literals
+    /// (the SAT solver moves watched literals around).
+    fn clause_key(c: &[sat::Lit]) -> (usize, u64) {
+        let mut h = 0u64;
+        for lit in c {
+            let x = (lit.as_index() as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            h = h.wrapping_add(x ^ (x >> 29));
+        }
+        (c.len(), h)
+    }
+
     impl fmt::Display for Cb {
         fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
-            write!(out, "sat.restarts: {}, sat.gc: {}",
-                   self.n_restarts, self.n_gc_calls)
+            let c = &self.counts;
+            write!(out, "sat.restarts: {}, sat.gc: {}, sat.learnts: {} (peak {}), \
+                   sat.lemmas.deletable: {} (deleted {}), sat.lemmas.restored: {}",
+                   self.n_restarts, self.n_gc_calls, self.n_learnts, self.learnts_peak,
+                   c.lemmas_deletable, c.lemmas_deleted, c.lemmas_restored)
         }
     }
 
@@ -434,6 +1208,95 @@ mod solver {
         fn on_restart(&mut self) { self.n_restarts += 1 }
         #[inline(always)]
         fn on_gc(&mut self, _: usize, _: usize) { self.n_gc_calls += 1; }
+        #[inline(always)]
+        fn stop(&self) -> bool { self.stop.get() || self.cancel.is_canceled() }
+
+        fn on_new_clause(&mut self, c: &[sat::Lit], k: batsat::ClauseKind) {
+            let key = clause_key(c);
+            match k {
+                batsat::ClauseKind::Axiom => return, // counted when added
+                batsat::ClauseKind::Learnt => {
+                    *self.learnt_keys.entry(key).or_insert(0) += 1;
+                    if self.track_learnts {
+                        self.learnts.entry(key).or_insert_with(Vec::new).push((c.to_vec(), self.tainted));
+                        self.bump_activity(c);
+                    }
+                    self.counts.learned += 1;
+                },
+                batsat::ClauseKind::Theory if self.lemma_db.borrow().permanent.contains_key(&key) => {
+                    *self.permanent.entry(key).or_insert(0) += 1;
+                },
+                batsat::ClauseKind::Theory => {
+                    *self.lemmas.entry(key).or_insert(0) += 1;
+                    self.counts.lemmas_deletable += 1;
+                },
+            }
+            self.n_learnts += 1;
+            self.n_lits += c.len();
+            self.learnts_peak = self.learnts_peak.max(self.n_learnts);
+        }
+
+        fn on_delete_clause(&mut self, c: &[sat::Lit]) {
+            // input clauses are not tracked
+            let key = clause_key(c);
+            if remove_one(&mut self.learnt_keys, &key) {
+                self.remove_learnt(c, &key);
+                self.counts.learned -= 1;
+            } else if remove_one(&mut self.lemmas, &key) {
+                self.counts.lemmas_deleted += 1;
+                self.counts.lemmas_deletable -= 1;
+            } else if remove_one(&mut self.permanent, &key) {
+                // give it back at the next theory check
+                self.counts.lemmas_restored += 1;
+                self.lemma_db.borrow_mut().to_push.push(c.to_vec());
+            } else {
+                return
+            }
+            self.n_learnts -= 1;
+            self.n_lits -= c.len();
+        }
+    }
+
+    // clauses whose keys collide are told apart by their literals
+    #[test]
+    fn test_remove_learnt_same_key() {
+        let mut cb = Cb::new(Rc::new(Cell::new(false)), CancelToken::new(), Rc::default());
+        let c = |i: usize| vec![sat::Lit::new(sat::Var::from_index(i), true)];
+        let (c1, c2, c3) = (c(0), c(1), c(2));
+        // force `c1` and `c2` into the same key
+        let key = clause_key(&c1);
+        cb.learnts.insert(key, vec![(c1.clone(), false), (c2.clone(), false)]);
+
+        assert!(!cb.remove_learnt(&c3, &key), "c3 is not learnt");
+        assert_eq!(2, cb.learnts[&key].len());
+        assert!(cb.remove_learnt(&c2, &key));
+        assert_eq!(vec![(c1.clone(), false)], cb.learnts[&key]);
+        assert!(!cb.remove_learnt(&c2, &key), "c2 is already removed");
+        assert!(cb.remove_learnt(&c1, &key));
+        assert!(cb.learnts.is_empty());
+
+        // a clause is not removed by one of its subsets, nor supersets
+        let c12 = vec![c1[0], c2[0]];
+        cb.learnts.insert(key, vec![(c12.clone(), false)]);
+        assert!(!cb.remove_learnt(&c1, &key));
+        assert!(!cb.remove_learnt(&[c1[0], c2[0], c3[0]], &key));
+        assert!(cb.remove_learnt(&[c2[0], c1[0]], &key));
+    }
+
+    // learnt clauses are only copied if tracked, but always counted
+    #[test]
+    fn test_track_learnts() {
+        use batsat::Callbacks;
+        let mut cb = Cb::new(Rc::new(Cell::new(false)), CancelToken::new(), Rc::default());
+        let c = vec![sat::Lit::new(sat::Var::from_index(0), true)];
+        cb.on_new_clause(&c, batsat::ClauseKind::Learnt);
+        assert_eq!((1, 0), (cb.counts.learned, cb.learnts().count()));
+        cb.track_learnts = true;
+        cb.on_new_clause(&c, batsat::ClauseKind::Learnt);
+        assert_eq!((2, 1), (cb.counts.learned, cb.learnts().count()));
+        cb.on_delete_clause(&c);
+        cb.on_delete_clause(&c);
+        assert_eq!((0, 0, 0), (cb.counts.learned, cb.learnts().count(), cb.n_learnts()));
     }
 }
 
@@ -451,13 +1314,20 @@ impl<'a,'b,C> theory::Actions<C> for TmpAct<'a,'b>
     }
 
     #[inline]
-    fn add_lemma(&mut self, c: &[C::B]) {
+    fn add_lemma_with_kind(&mut self, c: &[C::B], kind: ClauseKind) {
         if self.ok {
             self.stats.lemmas += 1;
             self.lits.clear();
             self.lits.reserve(c.len());
             // convert `BLit -> sat::Lit`
             for BLit(a) in c.iter() { self.lits.push(*a) }
+            match kind {
+                ClauseKind::TheoryLemmaDeletable => (),
+                ClauseKind::TheoryLemmaPermanent | ClauseKind::Input => {
+                    self.lemma_db.borrow_mut().add_permanent(&self.lits);
+                },
+                ClauseKind::Learned => panic!("theory.add-lemma: cannot add a learnt clause"),
+            }
             self.acts.add_theory_lemma(&self.lits)
         }
     }
@@ -486,4 +1356,21 @@ impl<'a,'b,C> theory::Actions<C> for TmpAct<'a,'b>
     }
 
     fn has_conflict(&self) -> bool { !self.ok }
+
+    fn mark_incomplete(&mut self, feature: &'static str) {
+        trace!("theory.mark-incomplete {}", feature);
+        *self.incomplete = Some(feature);
+    }
+
+    fn raise_error(&mut self, err: TheoryError<BLit>) {
+        debug!("theory.raise-error {}", err);
+        self.ok = false; // ignore further actions
+        self.stop.set(true);
+        if self.error.is_none() {
+            *self.error = Some(err);
+        }
+    }
+
+    #[inline]
+    fn is_canceled(&self) -> bool { self.cancel.is_canceled() }
 }
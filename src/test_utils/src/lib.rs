@@ -0,0 +1,227 @@
+
+//! Random terms and formulas, for tests.
+//!
+//! An `AstGen` wraps a manager whose symbols are strings, such as
+//! `HManager<StrSymbolManager>`, and gives the same constant to each name.
+//! `gen_term` and `gen_formula` are proptest strategies over such a manager;
+//! `sample` draws values from a strategy with a fixed seed, outside of `proptest!`.
+//!
+//! ```
+//! use batsmt_hast::{HManager, StrSymbolManager};
+//! use batsmt_test_utils::{AstGen, GenConfig, gen_term, sample};
+//!
+//! let g = AstGen::new(HManager::<StrSymbolManager>::new());
+//! let ts = sample(&gen_term(&g, &GenConfig::default()), 42, 10);
+//! assert_eq!(ts.len(), 10);
+//! ```
+
+use {
+    std::{fmt, rc::Rc, cell::RefCell, borrow::Borrow},
+    fxhash::FxHashMap,
+    batsmt_core::{ast::{HasManager, Manager}, ast_u32::AST},
+    proptest::{
+        prelude::*,
+        strategy::ValueTree,
+        test_runner::{TestRunner, TestRng, RngAlgorithm, Config},
+    },
+};
+
+/// Managers in which `AstGen` can build terms.
+pub trait GenManager : Manager<AST=AST> {
+    /// The constant named `s`.
+    fn mk_name(&mut self, s: String, ty: Option<AST>) -> AST;
+}
+
+impl<C> GenManager for C
+    where C: Manager<AST=AST>, String: Borrow<C::SymView> + Into<C::SymBuilder>
+{
+    fn mk_name(&mut self, s: String, ty: Option<AST>) -> AST { self.mk_const(s, ty) }
+}
+
+/// What terms and formulas look like.
+#[derive(Clone,Debug)]
+pub struct GenConfig {
+    /// Names of the constants, which are also used as function symbols.
+    pub symbols: Vec<String>,
+    /// Sort of the terms.
+    pub sort: String,
+    /// Names of the boolean constants of formulas.
+    pub bool_consts: Vec<String>,
+    /// Maximum depth of terms and formulas.
+    pub depth: u32,
+    /// Maximum number of arguments of an application.
+    pub max_args: usize,
+    /// If present, terms also contain curried applications `apply(t,u)`
+    /// of this symbol.
+    pub apply: Option<String>,
+}
+
+/// Context for generating terms, shared by the strategies that use it.
+pub struct AstGen<C>(pub Rc<RefCell<AstGenCell<C>>>);
+
+/// Content of an `AstGen`.
+pub struct AstGenCell<C> {
+    /// The context in which terms are built.
+    pub m: C,
+    consts: FxHashMap<String, AST>,
+}
+
+impl Default for GenConfig {
+    fn default() -> Self {
+        let strs = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        GenConfig {
+            symbols: strs(&["f", "g", "a", "b", "c", "d"]),
+            sort: "ty_u".to_string(),
+            bool_consts: strs(&["p", "q", "r"]),
+            depth: 8,
+            max_args: 5,
+            apply: None,
+        }
+    }
+}
+
+impl<C:GenManager> AstGenCell<C> {
+    /// The constant named `s`, with sort `ty` the first time it is built.
+    pub fn string(&mut self, s: String, ty: Option<AST>) -> AST {
+        match self.consts.get(&s) {
+            Some(t) => *t,
+            None => {
+                let t = self.m.mk_name(s.clone(), ty);
+                self.consts.insert(s, t);
+                t
+            }
+        }
+    }
+
+    /// Same as `string`.
+    pub fn str(&mut self, s: &str, ty: Option<AST>) -> AST { self.string(s.to_string(), ty) }
+}
+
+impl<C:GenManager> AstGen<C> {
+    /// New generator, building terms in `m`.
+    pub fn new(m: C) -> Self {
+        let cell = AstGenCell { m, consts: FxHashMap::default() };
+        AstGen(Rc::new(RefCell::new(cell)))
+    }
+
+    /// The constant named `s`, see `AstGenCell::string`.
+    pub fn str(&self, s: &str, ty: Option<AST>) -> AST { self.0.borrow_mut().str(s, ty) }
+
+    /// The application `f(args)`, of sort `ty`.
+    pub fn app(&self, f: AST, args: &[AST], ty: Option<AST>) -> AST {
+        self.0.borrow_mut().m.mk_app(f, args, ty)
+    }
+}
+
+impl<C:Manager> HasManager for AstGenCell<C> {
+    type M = C;
+    fn m(&self) -> &C { &self.m }
+    fn m_mut(&mut self) -> &mut C { &mut self.m }
+}
+
+impl<C> Clone for AstGen<C> {
+    fn clone(&self) -> Self { AstGen(self.0.clone()) }
+}
+
+// just so we can `prop_map` on it
+impl<C> fmt::Debug for AstGen<C> {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result { write!(out, "astgen") }
+}
+
+/// Pair the values of the strategy built by `f` with the generator `g` they use.
+pub fn with_astgen<C,F,T>(g: AstGen<C>, mut f: F) -> BoxedStrategy<(AstGen<C>,T)>
+    where F: FnMut(&AstGen<C>) -> BoxedStrategy<T>, T: 'static+fmt::Debug, C: 'static
+{
+    f(&g)
+        .prop_map(move |t| (g.clone(), t))
+        .boxed()
+}
+
+/// Random terms, applications of the symbols of `cfg` to each other.
+///
+/// All terms have the sort `cfg.sort`.
+pub fn gen_term<C>(g: &AstGen<C>, cfg: &GenConfig) -> BoxedStrategy<AST>
+    where C: GenManager + 'static
+{
+    let g = g.clone();
+    let ty = g.str(&cfg.sort, None);
+    let apply_f = cfg.apply.as_ref().map(|s| g.str(s, Some(ty)));
+    let leaf = {
+        let g2 = g.clone();
+        prop::sample::select(cfg.symbols.clone()).prop_map(move |s| g2.str(&s, Some(ty)))
+    };
+    let max_args = cfg.max_args;
+    // see https://docs.rs/proptest/*/proptest/#generating-recursive-data
+    leaf.prop_recursive(
+        cfg.depth, 512, 10,
+        move |inner| {
+            let g2 = g.clone();
+            let app =
+                (inner.clone(), prop::collection::vec(inner.clone(), 0..max_args+1))
+                .prop_map(move |(f,args)| g2.app(f, &args, Some(ty)));
+            match apply_f {
+                Some(apply_f) => {
+                    let g3 = g.clone();
+                    prop_oneof![
+                        1 => app,
+                        2 => (inner.clone(), inner)
+                            .prop_map(move |(f,x)| g3.app(apply_f, &[f,x], Some(ty))),
+                    ].boxed()
+                },
+                None => app.boxed(),
+            }
+        }).boxed()
+}
+
+/// Random formulas over equations between terms of `gen_term`,
+/// and the boolean constants of `cfg`.
+///
+/// Formulas use the constants `Bool`, `not`, `and`, `or` and `=`;
+/// the context can build its builtins from them with `AstGen::str`.
+pub fn gen_formula<C>(g: &AstGen<C>, cfg: &GenConfig) -> BoxedStrategy<AST>
+    where C: GenManager + 'static
+{
+    let g = g.clone();
+    let bool_ = g.str("Bool", None);
+    let (not_, and_, or_, eq) = (g.str("not", None), g.str("and", None), g.str("or", None), g.str("=", None));
+    let eqn = {
+        let g2 = g.clone();
+        (gen_term(&g, cfg), gen_term(&g, cfg))
+            .prop_map(move |(t,u)| g2.app(eq, &[t,u], Some(bool_)))
+            .boxed()
+    };
+    let atom = if cfg.bool_consts.is_empty() {
+        eqn
+    } else {
+        let g2 = g.clone();
+        let consts =
+            prop::sample::select(cfg.bool_consts.clone())
+            .prop_map(move |s| g2.str(&s, Some(bool_)));
+        prop_oneof![1 => consts, 1 => eqn].boxed()
+    };
+    atom.prop_recursive(
+        cfg.depth, 256, 4,
+        move |inner| {
+            let (g1, g2, g3) = (g.clone(), g.clone(), g.clone());
+            prop_oneof![
+                inner.clone().prop_map(move |a| g1.app(not_, &[a], Some(bool_))),
+                prop::collection::vec(inner.clone(), 2..4)
+                    .prop_map(move |args| g2.app(and_, &args, Some(bool_))),
+                prop::collection::vec(inner, 2..4)
+                    .prop_map(move |args| g3.app(or_, &args, Some(bool_))),
+            ]
+        }).boxed()
+}
+
+/// Draw `n` values from `s`, deterministically from `seed`.
+///
+/// The values are built in the order they are returned, so the same
+/// seed gives the same values in a fresh generator.
+pub fn sample<T:fmt::Debug>(s: &BoxedStrategy<T>, seed: u64, n: usize) -> Vec<T> {
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&seed.to_le_bytes());
+    bytes[8..].copy_from_slice(&(!seed).to_le_bytes());
+    let rng = TestRng::from_seed(RngAlgorithm::XorShift, &bytes);
+    let mut runner = TestRunner::new_with_rng(Config::default(), rng);
+    (0 .. n).map(|_| s.new_tree(&mut runner).expect("cannot generate value").current()).collect()
+}
@@ -0,0 +1,51 @@
+
+use {
+    batsmt_core::ast::Manager,
+    batsmt_hast::{HManager, StrSymbolManager},
+    batsmt_pretty as pp,
+    batsmt_test_utils::{AstGen, GenConfig, gen_term, gen_formula, sample},
+};
+
+type M = HManager<StrSymbolManager>;
+
+/// Print `n` terms drawn from `seed` in a fresh generator.
+fn print_terms(cfg: &GenConfig, seed: u64, n: usize) -> Vec<String> {
+    let g = AstGen::new(M::new());
+    let ts = sample(&gen_term(&g, cfg), seed, n);
+    let m = &g.0.borrow().m;
+    ts.iter().map(|t| pp::pp1(m, t).to_string()).collect()
+}
+
+#[test]
+fn test_terms_deterministic() {
+    let cfg = GenConfig::default();
+    let ts1 = print_terms(&cfg, 42, 1_000);
+    let ts2 = print_terms(&cfg, 42, 1_000);
+    assert_eq!(ts1.len(), 1_000);
+    assert_eq!(ts1, ts2);
+    // not all the same leaf
+    assert!(ts1.iter().any(|t| t.starts_with('(')), "{:?}", &ts1[..10]);
+    assert_ne!(ts1, print_terms(&cfg, 43, 1_000));
+}
+
+#[test]
+fn test_config() {
+    let cfg = GenConfig{
+        symbols: vec!("x".to_string()), depth: 2, max_args: 1,
+        apply: Some("@".to_string()), ..GenConfig::default()
+    };
+    for t in print_terms(&cfg, 0, 200) {
+        assert!(t.chars().all(|c| "x@() ".contains(c)), "{}", t);
+    }
+}
+
+#[test]
+fn test_formulas_are_bool() {
+    let g = AstGen::new(M::new());
+    let fs = sample(&gen_formula(&g, &GenConfig::default()), 7, 200);
+    let bool_ = g.str("Bool", None);
+    let m = &g.0.borrow().m;
+    for f in &fs {
+        assert_eq!(m.ty(f), Some(bool_), "{}", pp::pp1(m, f));
+    }
+}
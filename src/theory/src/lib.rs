@@ -10,12 +10,14 @@
 //! For this reason, it abstracts over the type of boolean literals (`BoolLit`).
 
 use {
-    std::{ops::{Deref,Not}, hash::Hash, fmt},
-    batsmt_core::{ backtrack::Backtrackable, gc, ast_u32, },
+    std::{ops::{Deref,Not}, hash::Hash, fmt, sync::{Arc, atomic::{AtomicBool, Ordering}}},
+    batsmt_core::{ backtrack::Backtrackable, gc, ast_u32, Error, mem::{MemTree, ReportMemory}, },
     batsmt_pretty as pp,
 };
 
 pub mod lit_map;
+#[cfg(feature="replay")]
+pub mod replay;
 
 // re-exports for litmap
 pub use {
@@ -105,10 +107,18 @@ pub struct TheoryClauseSet<C:Ctx> {
 /// literals can be propagated, or that the current set of literals
 /// is T-inconsistent.
 pub trait Actions<C:Ctx> {
-    /// Add a lemma-on-demand.
+    /// Add a lemma-on-demand, that the boolean solver may delete later.
     ///
     /// NOTE: this is not well supported yet.
-    fn add_lemma(&mut self, c: &[C::B]);
+    fn add_lemma(&mut self, c: &[C::B]) {
+        self.add_lemma_with_kind(c, ClauseKind::TheoryLemmaDeletable)
+    }
+
+    /// Add a lemma-on-demand of the given kind.
+    ///
+    /// `kind` should be `TheoryLemmaPermanent` for lemmas that are costly
+    /// to derive again, and `TheoryLemmaDeletable` otherwise.
+    fn add_lemma_with_kind(&mut self, c: &[C::B], kind: ClauseKind);
 
     /// Propagate the given boolean literal.
     ///
@@ -137,6 +147,73 @@ pub trait Actions<C:Ctx> {
     ///
     /// This is useful to interrupt work early.
     fn has_conflict(&self) -> bool;
+
+    /// Signal that the theory approximated `feature` to check the current trail.
+    ///
+    /// Call this only when the approximation was actually used, not merely
+    /// because it is enabled: a "sat" answer obtained afterwards
+    /// is downgraded to "unknown".
+    ///
+    /// Does nothing by default, for callers that cannot answer "unknown".
+    fn mark_incomplete(&mut self, _feature: &'static str) {}
+
+    /// Signal that the trail cannot be checked because of `err`.
+    ///
+    /// The solver stops as soon as possible and reports the error
+    /// instead of a result. The theory should not do anything else
+    /// in the current check.
+    fn raise_error(&mut self, err: TheoryError<C::B>);
+
+    /// Has the caller asked to stop?
+    ///
+    /// Theories doing a lot of work in one check should poll this
+    /// between steps, and return early (without a conflict) if it is true.
+    /// The current check can then be left unfinished; the caller will not
+    /// trust its result.
+    fn is_canceled(&self) -> bool { false }
+}
+
+/// Kind of a clause in the boolean solver, which decides whether it can be deleted.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Hash)]
+pub enum ClauseKind {
+    /// Part of the problem, e.g. a clause from the Tseitin transformation.
+    Input,
+    /// Theory lemma that is kept for good.
+    TheoryLemmaPermanent,
+    /// Theory lemma that can be deleted like a learnt clause, then derived again.
+    TheoryLemmaDeletable,
+    /// Clause learnt by the boolean solver from a conflict.
+    Learned,
+}
+
+/// Reason why satisfiability could not be decided.
+#[derive(Clone,Copy,Debug,Eq,PartialEq,Hash)]
+pub enum ReasonUnknown {
+    /// A resource limit (conflicts, time, etc.) was reached.
+    ResourceOut,
+    /// Some approximation was used, so a model cannot be trusted.
+    Incomplete { feature: &'static str },
+    /// Solving was interrupted from the outside.
+    Canceled,
+}
+
+/// Flag used to interrupt a solver, possibly from another thread.
+///
+/// Clones share the same flag. Once canceled, a token stays canceled
+/// until `reset` is called.
+#[derive(Clone,Debug,Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+/// An error that prevents a theory from checking the trail.
+///
+/// Unlike a conflict, this comes from the input itself (e.g. an equation
+/// between terms of distinct sorts), not from the current model.
+#[derive(Clone,Debug)]
+pub struct TheoryError<B> {
+    /// The error itself.
+    pub err: Error,
+    /// Literal of the offending atom, if any.
+    pub lit: Option<B>,
 }
 
 /// The theory subset of the (partial) model picked by the SAT solver.
@@ -227,6 +304,46 @@ mod stats {
     }
 }
 
+mod reason_unknown {
+    use {std::fmt, super::*};
+
+    // printed as the value of `(get-info :reason-unknown)`
+    impl fmt::Display for ReasonUnknown {
+        fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                ReasonUnknown::ResourceOut => write!(out, "resourceout"),
+                ReasonUnknown::Incomplete{feature} => write!(out, "(incomplete {})", feature),
+                ReasonUnknown::Canceled => write!(out, "canceled"),
+            }
+        }
+    }
+
+    impl<B> fmt::Display for TheoryError<B> {
+        fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+            write!(out, "{}", self.err)
+        }
+    }
+}
+
+mod cancel_token {
+    use super::*;
+
+    impl CancelToken {
+        /// New token, not canceled.
+        pub fn new() -> Self { CancelToken(Arc::new(AtomicBool::new(false))) }
+
+        /// Ask the solver(s) using this token to stop.
+        pub fn cancel(&self) { self.0.store(true, Ordering::Relaxed) }
+
+        /// Has `cancel` been called since the last `reset`?
+        #[inline]
+        pub fn is_canceled(&self) -> bool { self.0.load(Ordering::Relaxed) }
+
+        /// Clear the flag, so the token can be used for another call.
+        pub fn reset(&self) { self.0.store(false, Ordering::Relaxed) }
+    }
+}
+
 mod theory_lit {
     use super::*;
 
@@ -372,7 +489,8 @@ impl<C:Ctx> TheoryClauseSet<C> {
 
     /// Remove all clauses internally.
     ///
-    /// Internal storage is kept.
+    /// Internal storage is kept, use `reclaim_unused_memory`
+    /// afterwards to free it.
     pub fn clear(&mut self) {
         self.offsets.clear();
         self.lits.clear();
@@ -404,6 +522,14 @@ impl<C:Ctx> TheoryClauseSet<C> {
     pub fn iter<'a>(&'a self) -> impl Iterator<Item=TheoryClauseRef<'a,C>> {
         CSIter{cs: &self, idx: 0}
     }
+
+    /// Number of clauses.
+    #[inline(always)]
+    pub fn len(&self) -> usize { self.offsets.len() }
+
+    /// No clause at all?
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool { self.offsets.is_empty() }
 }
 
 // iterator over clauses
@@ -412,6 +538,12 @@ struct CSIter<'a, C:Ctx> {
     idx: usize, // in `cs.offsets`
 }
 
+/// Owning iterator over the clauses of a `TheoryClauseSet`.
+pub struct ClauseSetIntoIter<C:Ctx> {
+    cs: TheoryClauseSet<C>,
+    idx: usize, // in `cs.offsets`
+}
+
 mod theory_clause_set {
     use super::*;
 
@@ -441,12 +573,40 @@ mod theory_clause_set {
         }
     }
 
+    impl<C:Ctx> Iterator for ClauseSetIntoIter<C> {
+        type Item = Vec<TheoryLit<C>>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let cs = &self.cs;
+            if self.idx >= cs.offsets.len() {
+                None
+            } else {
+                let (off,len) = cs.offsets[self.idx];
+                self.idx += 1;
+                Some(cs.lits[off..off+len].to_vec())
+            }
+        }
+    }
+
+    impl<C:Ctx> IntoIterator for TheoryClauseSet<C> {
+        type Item = Vec<TheoryLit<C>>;
+        type IntoIter = ClauseSetIntoIter<C>;
+        fn into_iter(self) -> Self::IntoIter { ClauseSetIntoIter{cs: self, idx: 0} }
+    }
+
     impl<C:Ctx> gc::HasInternalMemory for TheoryClauseSet<C> {
         fn reclaim_unused_memory(&mut self) {
             self.lits.shrink_to_fit();
             self.offsets.shrink_to_fit();
         }
     }
+
+    impl<C:Ctx> ReportMemory for TheoryClauseSet<C> {
+        fn memory_breakdown(&self, out: &mut MemTree) {
+            self.lits.memory_breakdown(out);
+            self.offsets.memory_breakdown(out);
+        }
+    }
 }
 
 impl<'a, C:Ctx> Trail<'a, C> {
@@ -505,6 +665,14 @@ pub mod int_lit {
     #[derive(Copy,Clone,Eq,PartialEq,Ord,PartialOrd,Hash,Debug)]
     pub struct Lit(i32);
 
+    impl Lit {
+        /// Literal number `i`, negated if `i < 0`.
+        pub fn new(i: i32) -> Self { assert_ne!(i, 0); Lit(i) }
+
+        /// The number of this literal.
+        pub fn to_int(&self) -> i32 { self.0 }
+    }
+
     impl std::ops::Not for Lit {
         type Output = Lit;
         fn not(self) -> Self { debug_assert_ne!(self.0, 0); Lit(-self.0) }
@@ -524,6 +692,10 @@ pub struct SimpleActions<C:Ctx> {
     costly: bool,
     props: Vec<C::B>,
     lemmas: Vec<Vec<C::B>>,
+    lemma_kinds: Vec<ClauseKind>, // kind of each lemma
+    incomplete: Option<&'static str>,
+    error: Option<TheoryError<C::B>>,
+    cancel: Option<CancelToken>,
     #[allow(unused)]
     mk_lit: Box<Fn() -> C::B>, // FIXME: actual litmap or something?
 }
@@ -540,9 +712,10 @@ impl<C:Ctx> Actions<C> for SimpleActions<C> {
             true
         }
     }
-    fn add_lemma(&mut self, c: &[C::B]) {
+    fn add_lemma_with_kind(&mut self, c: &[C::B], kind: ClauseKind) {
         if !self.has_conflict() {
-            self.lemmas.push(c.iter().cloned().collect())
+            self.lemmas.push(c.iter().cloned().collect());
+            self.lemma_kinds.push(kind);
         }
     }
     fn raise_conflict(&mut self, c: &[C::B], costly: bool) {
@@ -555,6 +728,17 @@ impl<C:Ctx> Actions<C> for SimpleActions<C> {
     fn map_lit(&mut self, _m: &C, _lit: TheoryLit<C>) -> C::B {
         unimplemented!("map-lit")
     }
+    fn mark_incomplete(&mut self, feature: &'static str) {
+        self.incomplete = Some(feature);
+    }
+    fn raise_error(&mut self, err: TheoryError<C::B>) {
+        if self.error.is_none() {
+            self.error = Some(err);
+        }
+    }
+    fn is_canceled(&self) -> bool {
+        self.cancel.as_ref().map_or(false, |c| c.is_canceled())
+    }
 }
 
 impl<C:Ctx> SimpleActions<C> {
@@ -564,16 +748,45 @@ impl<C:Ctx> SimpleActions<C> {
     {
         let mk_lit = Box::new(f);
         SimpleActions {
-            mk_lit, lemmas: vec!(), props: vec!(),
-            confl: None, costly: false
+            mk_lit, lemmas: vec!(), lemma_kinds: vec!(), props: vec!(),
+            confl: None, costly: false, incomplete: None, error: None, cancel: None,
         }
     }
 
+    /// Theories using these actions are canceled along with `tok`.
+    pub fn set_cancel_token(&mut self, tok: CancelToken) { self.cancel = Some(tok) }
+
     /// Reset internal state.
     pub fn clear(&mut self) {
         self.confl = None;
         self.props.clear();
         self.lemmas.clear();
+        self.lemma_kinds.clear();
+        self.incomplete = None;
+        self.error = None;
+    }
+
+    /// The last approximated feature, if the theory approximated anything.
+    pub fn incomplete(&self) -> Option<&'static str> { self.incomplete }
+
+    /// Move the propagations out, in the order they were made.
+    ///
+    /// This empties the set of propagations; the other results are kept.
+    pub fn drain_props(&mut self) -> impl Iterator<Item=C::B> + '_ { self.props.drain(..) }
+
+    /// Kind of each lemma returned by `get`, in the same order.
+    pub fn lemma_kinds(&self) -> &[ClauseKind] { &self.lemma_kinds }
+
+    /// The first error raised by the theory, if any.
+    pub fn error(&self) -> Option<&TheoryError<C::B>> { self.error.as_ref() }
+
+    /// Negation of each literal of the conflict, if any.
+    ///
+    /// The conflict is a clause that is false in the current trail, so this
+    /// yields the trail literals that are inconsistent together.
+    /// Yields nothing if there is no conflict.
+    pub fn negated_conflict(&self) -> impl Iterator<Item=C::B> + '_ {
+        self.confl.iter().flat_map(|c| c.iter().map(|lit| ! *lit))
     }
 
     /// Get results.
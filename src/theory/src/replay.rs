@@ -0,0 +1,768 @@
+
+//! Record and replay of the calls into a theory.
+//!
+//! `Recorder` wraps a theory and logs every call into it (new literals,
+//! backtracking, checks, explanations), along with what the theory did
+//! in return. `run` executes such a log again against a fresh theory, and
+//! stops at the first call whose answer differs. This makes bugs that
+//! depend on the exact interleaving of checks and backtracking reproducible
+//! without the SAT solver.
+//!
+//! A log is a text file. Its prologue lists the terms, each after its
+//! subterms, then come the calls, one per line. Terms and literals are
+//! numbered in the order they are met; literal `-i` is the negation of `i`.
+//!
+//! ```text
+//! batsmt-theory-log 1
+//! t 0 _ c Bool
+//! t 1 0 c p
+//! lit 1 1
+//! push
+//! final +1/1
+//! > propagate -2
+//! explain -3 -2 : 1
+//! pop 1
+//! ```
+//!
+//! Lines starting with `;` are comments.
+//! Lines starting with `>` are the outcome of the check above them. In an
+//! `unchecked` log (e.g. one built from a test case rather than recorded),
+//! calls carry no outcome and replaying only executes them.
+
+use {
+    std::{fmt, io},
+    batsmt_core::{AstView, backtrack::Backtrackable, ast_u32::{AST, ManagerU32},
+        error::Pos, Error, hash::HashMap},
+    crate::{Actions, BoolLit, ClauseKind, Ctx, Theory, TheoryError, TheoryLit, Trail},
+};
+
+const HEADER: &str = "batsmt-theory-log 1";
+
+/// A term of the log. Subterms and types refer to earlier terms.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum LogTerm {
+    Const { name: String, ty: Option<u32> },
+    App { f: u32, args: Vec<u32>, ty: Option<u32> },
+    Index(u32),
+}
+
+/// Items of a trail: term, sign, literal.
+pub type LogTrail = Vec<(u32, bool, i32)>;
+
+/// A call into the theory.
+#[derive(Clone,Debug,Eq,PartialEq)]
+pub enum Event {
+    AddLiteral(u32, i32),
+    PushLevel,
+    PopLevels(usize),
+    EnablePropagation(bool),
+    PartialCheck(LogTrail, Outcome),
+    FinalCheck(LogTrail, Outcome),
+    /// Explanation of the propagation of `p`, the literal of `(t,sign)`.
+    Explain { t: u32, sign: bool, p: i32, expl: Vec<i32> },
+}
+
+/// What the theory did during a check.
+#[derive(Clone,Debug,Default,Eq,PartialEq)]
+pub struct Outcome {
+    pub props: Vec<i32>,
+    pub lemmas: Vec<(Vec<i32>, ClauseKind)>,
+    /// Conflict clause, and whether it is costly.
+    pub conflict: Option<(Vec<i32>, bool)>,
+    /// Results of `map_lit`: lazy, term, sign, literal.
+    pub lits: Vec<(bool, u32, bool, i32)>,
+    pub incomplete: Option<String>,
+    pub error: Option<String>,
+}
+
+/// A log of calls into a theory, see the module documentation.
+#[derive(Clone,Debug,Default,Eq,PartialEq)]
+pub struct Log {
+    pub terms: Vec<LogTerm>,
+    pub events: Vec<Event>,
+    /// Are outcomes recorded? If not, replaying does not compare them.
+    pub checked: bool,
+}
+
+/// Numbering of the terms and literals met so far, to build a log.
+pub struct LogBuilder<B> {
+    log: Log,
+    terms: HashMap<AST, u32>,
+    lits: HashMap<B, i32>, // positive literals only
+}
+
+/// A theory whose calls are recorded into a log.
+pub struct Recorder<C:Ctx, Th> {
+    th: Th,
+    b: LogBuilder<C::B>,
+}
+
+/// A context into which a log can be replayed.
+pub trait ReplayCtx : Ctx {
+    /// The constant `name`, of type `ty`.
+    ///
+    /// Contexts with builtin constants (e.g. `true` or `=`) should return
+    /// them, rather than fresh constants with the same name.
+    fn replay_const(&mut self, name: &str, ty: Option<AST>) -> AST;
+
+    /// The literal numbered `i` (positive) in the log.
+    fn replay_lit(&self, i: i32) -> Self::B;
+}
+
+/// Why a log could not be replayed.
+#[derive(Clone,Debug)]
+pub enum ReplayError {
+    /// The log itself cannot be replayed in this context.
+    Log(Error),
+    /// At event number `event`, the theory did `found` instead of `expected`.
+    Divergence { event: usize, expected: String, found: String },
+}
+
+impl<B:BoolLit> LogBuilder<B> {
+    /// New builder. `checked` logs record the outcome of each call.
+    pub fn new(checked: bool) -> Self {
+        LogBuilder{
+            log: Log{terms: vec!(), events: vec!(), checked},
+            terms: HashMap::default(), lits: HashMap::default(),
+        }
+    }
+
+    /// Number of `t`, adding it and its subterms to the prologue if needed.
+    pub fn term<M:ManagerU32>(&mut self, m: &M, t: AST) -> u32 {
+        // add subterms and types first, without recursion
+        let mut st = vec![(t, false)];
+        while let Some((u, ready)) = st.pop() {
+            if self.terms.contains_key(&u) { continue }
+            let ty = m.ty(&u);
+            let subs: Vec<AST> = match m.view(&u) {
+                AstView::App{f, args} => Some(*f).into_iter().chain(args.iter().cloned()).collect(),
+                _ => vec!(),
+            };
+            let missing = ty.iter().chain(subs.iter()).any(|v| !self.terms.contains_key(v));
+            if !ready && missing {
+                st.push((u, true));
+                st.extend(ty.into_iter().chain(subs).map(|v| (v, false)));
+                continue
+            }
+            let ty = ty.map(|v| self.terms[&v]);
+            let lt = match m.view(&u) {
+                AstView::Const(s) => LogTerm::Const{name: s.to_string(), ty},
+                AstView::Index(i) => LogTerm::Index(i),
+                AstView::App{f, args} => {
+                    LogTerm::App{f: self.terms[f], args: args.iter().map(|a| self.terms[a]).collect(), ty}
+                },
+            };
+            let n = self.log.terms.len() as u32;
+            self.log.terms.push(lt);
+            self.terms.insert(u, n);
+        }
+        self.terms[&t]
+    }
+
+    /// Number of the literal `b`.
+    pub fn lit(&mut self, b: B) -> i32 {
+        let n = self.lits.len() as i32 + 1;
+        let i = *self.lits.entry(b.abs()).or_insert(n);
+        if b == b.abs() { i } else { -i }
+    }
+
+    /// Log version of `trail`.
+    pub fn trail<M:ManagerU32>(&mut self, m: &M, trail: &[(AST,bool,B)]) -> LogTrail {
+        trail.iter().map(|(t,sign,b)| (self.term(m, *t), *sign, self.lit(*b))).collect()
+    }
+
+    /// Add `ev` at the end of the log.
+    pub fn push(&mut self, ev: Event) { self.log.events.push(ev) }
+
+    /// The log so far.
+    pub fn log(&self) -> &Log { &self.log }
+
+    pub fn into_log(self) -> Log { self.log }
+}
+
+impl<C:Ctx, Th:Theory<C>> Recorder<C, Th> {
+    /// Record the calls into `th`.
+    pub fn new(th: Th) -> Self { Recorder{th, b: LogBuilder::new(true)} }
+
+    /// The wrapped theory.
+    pub fn theory(&self) -> &Th { &self.th }
+
+    /// The calls recorded so far.
+    pub fn log(&self) -> &Log { self.b.log() }
+
+    pub fn into_log(self) -> Log { self.b.into_log() }
+
+    fn check<A>(&mut self, c: &mut C, acts: &mut A, trail: &Trail<C>, is_final: bool)
+        where A: Actions<C>
+    {
+        let log_trail = self.b.trail(c, trail.as_slice());
+        let mut racts = RecordActions{acts, b: &mut self.b, out: Outcome::default()};
+        if is_final {
+            self.th.final_check(c, &mut racts, trail);
+        } else {
+            self.th.partial_check(c, &mut racts, trail);
+        }
+        let out = racts.out;
+        self.b.push(if is_final { Event::FinalCheck(log_trail, out) } else { Event::PartialCheck(log_trail, out) });
+    }
+}
+
+impl<C:Ctx, Th:Theory<C>> Backtrackable<C> for Recorder<C, Th> {
+    fn push_level(&mut self, c: &mut C) {
+        self.b.push(Event::PushLevel);
+        self.th.push_level(c)
+    }
+
+    fn pop_levels(&mut self, c: &mut C, n: usize) {
+        self.b.push(Event::PopLevels(n));
+        self.th.pop_levels(c, n)
+    }
+
+    fn n_levels(&self) -> usize { self.th.n_levels() }
+}
+
+impl<C:Ctx, Th:Theory<C>> Theory<C> for Recorder<C, Th> {
+    fn final_check<A:Actions<C>>(&mut self, c: &mut C, acts: &mut A, trail: &Trail<C>) {
+        self.check(c, acts, trail, true)
+    }
+
+    fn partial_check<A:Actions<C>>(&mut self, c: &mut C, acts: &mut A, trail: &Trail<C>) {
+        self.check(c, acts, trail, false)
+    }
+
+    fn has_partial_check() -> bool { Th::has_partial_check() }
+
+    fn add_literal(&mut self, c: &mut C, t: AST, lit: C::B) {
+        let ev = Event::AddLiteral(self.b.term(c, t), self.b.lit(lit));
+        self.b.push(ev);
+        self.th.add_literal(c, t, lit)
+    }
+
+    fn explain_propagation(&mut self, c: &mut C, t: AST, sign: bool, p: C::B) -> &[C::B] {
+        let ev_t = self.b.term(c, t);
+        let ev_p = self.b.lit(p);
+        let b = &mut self.b;
+        let expl = self.th.explain_propagation(c, t, sign, p);
+        let ev_expl = expl.iter().map(|u| b.lit(*u)).collect();
+        b.push(Event::Explain{t: ev_t, sign, p: ev_p, expl: ev_expl});
+        expl
+    }
+
+    fn enable_propagation(&mut self, on: bool) {
+        self.b.push(Event::EnablePropagation(on));
+        self.th.enable_propagation(on)
+    }
+}
+
+// forward to `acts`, keeping track of what the theory did
+struct RecordActions<'a, C:Ctx, A> {
+    acts: &'a mut A,
+    b: &'a mut LogBuilder<C::B>,
+    out: Outcome,
+}
+
+impl<'a, C:Ctx, A:Actions<C>> Actions<C> for RecordActions<'a, C, A> {
+    fn add_lemma_with_kind(&mut self, c: &[C::B], kind: ClauseKind) {
+        let b = &mut self.b;
+        self.out.lemmas.push((c.iter().map(|u| b.lit(*u)).collect(), kind));
+        self.acts.add_lemma_with_kind(c, kind)
+    }
+
+    fn propagate(&mut self, p: C::B) -> bool {
+        self.out.props.push(self.b.lit(p));
+        self.acts.propagate(p)
+    }
+
+    fn raise_conflict(&mut self, c: &[C::B], costly: bool) {
+        if self.out.conflict.is_none() {
+            let b = &mut self.b;
+            self.out.conflict = Some((c.iter().map(|u| b.lit(*u)).collect(), costly));
+        }
+        self.acts.raise_conflict(c, costly)
+    }
+
+    fn map_lit(&mut self, m: &C, lit: TheoryLit<C>) -> C::B {
+        let res = match lit {
+            TheoryLit::T(t, sign) | TheoryLit::BLazy(t, sign) => {
+                Some((lit.is_pure_bool(), self.b.term(m, t), sign))
+            },
+            TheoryLit::B(_) => None,
+        };
+        let b = self.acts.map_lit(m, lit);
+        if let Some((lazy, t, sign)) = res {
+            self.out.lits.push((lazy, t, sign, self.b.lit(b)));
+        }
+        b
+    }
+
+    fn has_conflict(&self) -> bool { self.acts.has_conflict() }
+
+    fn mark_incomplete(&mut self, feature: &'static str) {
+        self.out.incomplete = Some(feature.to_string());
+        self.acts.mark_incomplete(feature)
+    }
+
+    fn raise_error(&mut self, err: TheoryError<C::B>) {
+        if self.out.error.is_none() {
+            self.out.error = Some(err.to_string());
+        }
+        self.acts.raise_error(err)
+    }
+
+    fn is_canceled(&self) -> bool { self.acts.is_canceled() }
+}
+
+/// Replay `log` into `c`, against the theory built by `theory_factory`.
+///
+/// The terms of the prologue are rebuilt in `c` first. If the log is
+/// checked, stops at the first call whose outcome differs from the log.
+pub fn run<C, Th, F>(log: &Log, c: &mut C, theory_factory: F) -> Result<(), ReplayError>
+    where C: ReplayCtx, Th: Theory<C>, F: FnOnce(&mut C) -> Th
+{
+    let terms = rebuild_terms(log, c)?;
+    let mut st = ReplayState::new(log, &terms);
+    let terms = &terms;
+    let mut th = theory_factory(c);
+
+    for (i, ev) in log.events.iter().enumerate() {
+        let diverge = |expected: String, found: String| ReplayError::Divergence{event: i, expected, found};
+        match ev {
+            Event::AddLiteral(t, lit) => {
+                let b = st.lit(c, *lit);
+                th.add_literal(c, terms[*t as usize], b)
+            },
+            Event::PushLevel => th.push_level(c),
+            Event::PopLevels(n) => th.pop_levels(c, *n),
+            Event::EnablePropagation(on) => th.enable_propagation(*on),
+            Event::PartialCheck(trail, out) | Event::FinalCheck(trail, out) => {
+                let trail: Vec<(AST,bool,C::B)> =
+                    trail.iter().map(|(t,sign,lit)| (terms[*t as usize], *sign, st.lit(c, *lit))).collect();
+                let found = {
+                    let mut acts = ReplayActions{st: &mut st, out: Outcome::default()};
+                    let trail = Trail::from_slice(&trail);
+                    if let Event::FinalCheck(..) = ev {
+                        th.final_check(c, &mut acts, &trail)
+                    } else {
+                        th.partial_check(c, &mut acts, &trail)
+                    };
+                    acts.out
+                };
+                if log.checked && out.normalize() != found.normalize() {
+                    return Err(diverge(out.to_string(), found.to_string()))
+                }
+            },
+            Event::Explain{t, sign, p, expl} => {
+                let b = st.lit(c, *p);
+                let found: Vec<C::B> = th.explain_propagation(c, terms[*t as usize], *sign, b).to_vec();
+                let found: Vec<i32> = found.into_iter().map(|b| st.lit_number(b)).collect();
+                if log.checked && sorted(expl) != sorted(&found) {
+                    return Err(diverge(format!("explanation {:?}", expl), format!("explanation {:?}", found)))
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+fn sorted(c: &[i32]) -> Vec<i32> {
+    let mut c = c.to_vec();
+    c.sort();
+    c
+}
+
+// build the terms of `log` in `c`
+fn rebuild_terms<C:ReplayCtx>(log: &Log, c: &mut C) -> Result<Vec<AST>, ReplayError> {
+    let mut terms = Vec::with_capacity(log.terms.len());
+    for lt in log.terms.iter() {
+        let t = match lt {
+            LogTerm::Const{name, ty} => {
+                let ty = ty.map(|u| terms[u as usize]);
+                c.replay_const(name, ty)
+            },
+            LogTerm::App{f, args, ty} => {
+                let args: Vec<AST> = args.iter().map(|u| terms[*u as usize]).collect();
+                let ty = ty.map(|u| terms[u as usize]);
+                c.mk_app(terms[*f as usize], &args, ty)
+            },
+            LogTerm::Index(_) => {
+                return Err(ReplayError::Log(Error::unsupported("replay of index terms")))
+            },
+        };
+        terms.push(t);
+    }
+    Ok(terms)
+}
+
+// literals of the replay, and their numbers in the log
+struct ReplayState<B> {
+    term_numbers: HashMap<AST, u32>,
+    lits: HashMap<i32, B>,
+    numbers: HashMap<B, i32>,
+    mapped: HashMap<(bool, AST), i32>, // results of `map_lit`, for positive terms
+    next: i32, // next unused number
+}
+
+impl<B:BoolLit> ReplayState<B> {
+    fn new(log: &Log, terms: &[AST]) -> Self {
+        let mut mapped = HashMap::default();
+        let mut next = 1;
+        let mut see = |i: i32| next = next.max(i.abs() + 1);
+        for ev in log.events.iter() {
+            match ev {
+                Event::AddLiteral(_, i) => see(*i),
+                Event::PartialCheck(trail, out) | Event::FinalCheck(trail, out) => {
+                    trail.iter().for_each(|(_,_,i)| see(*i));
+                    for (lazy, t, sign, i) in out.lits.iter() {
+                        see(*i);
+                        let i = if *sign { *i } else { -i };
+                        mapped.insert((*lazy, terms[*t as usize]), i);
+                    }
+                },
+                Event::Explain{p, ..} => see(*p),
+                Event::PushLevel | Event::PopLevels(..) | Event::EnablePropagation(..) => (),
+            }
+        }
+        let term_numbers = terms.iter().enumerate().map(|(i, t)| (*t, i as u32)).collect();
+        ReplayState{term_numbers, lits: HashMap::default(), numbers: HashMap::default(), mapped, next}
+    }
+
+    fn lit<C:ReplayCtx<B=B>>(&mut self, c: &C, i: i32) -> B {
+        if let Some(b) = self.lits.get(&i) { return *b }
+        let b = c.replay_lit(i.abs());
+        self.lits.insert(i.abs(), b);
+        self.lits.insert(-i.abs(), !b);
+        self.numbers.insert(b, i.abs());
+        self.numbers.insert(!b, -i.abs());
+        self.lits[&i]
+    }
+
+    // number of a literal of the replay, `0` if it was never met
+    fn lit_number(&self, b: B) -> i32 { self.numbers.get(&b).cloned().unwrap_or(0) }
+}
+
+// answer `map_lit` like in the log, and keep track of what the theory did
+struct ReplayActions<'a, B> {
+    st: &'a mut ReplayState<B>,
+    out: Outcome,
+}
+
+impl<'a, C:ReplayCtx> Actions<C> for ReplayActions<'a, C::B> {
+    fn add_lemma_with_kind(&mut self, c: &[C::B], kind: ClauseKind) {
+        if self.out.conflict.is_none() {
+            let st = &self.st;
+            self.out.lemmas.push((c.iter().map(|b| st.lit_number(*b)).collect(), kind));
+        }
+    }
+
+    fn propagate(&mut self, p: C::B) -> bool {
+        if self.out.conflict.is_some() { return false }
+        self.out.props.push(self.st.lit_number(p));
+        true
+    }
+
+    fn raise_conflict(&mut self, c: &[C::B], costly: bool) {
+        if self.out.conflict.is_none() {
+            let st = &self.st;
+            self.out.conflict = Some((c.iter().map(|b| st.lit_number(*b)).collect(), costly));
+        }
+    }
+
+    fn map_lit(&mut self, m: &C, lit: TheoryLit<C>) -> C::B {
+        let (lazy, t, sign) = match lit {
+            TheoryLit::B(b) => return b,
+            TheoryLit::T(t, sign) => (false, t, sign),
+            TheoryLit::BLazy(t, sign) => (true, t, sign),
+        };
+        let st = &mut *self.st;
+        let i = match st.mapped.get(&(lazy, t)) {
+            Some(i) => *i,
+            None => {
+                // not in the log: a fresh literal
+                let i = st.next;
+                st.next += 1;
+                st.mapped.insert((lazy, t), i);
+                i
+            },
+        };
+        let i = if sign { i } else { -i };
+        // a term that is not in the log has no number
+        let t = st.term_numbers.get(&t).cloned().unwrap_or(u32::MAX);
+        self.out.lits.push((lazy, t, sign, i));
+        st.lit(m, i)
+    }
+
+    fn has_conflict(&self) -> bool { self.out.conflict.is_some() }
+
+    fn mark_incomplete(&mut self, feature: &'static str) {
+        self.out.incomplete = Some(feature.to_string())
+    }
+
+    fn raise_error(&mut self, err: TheoryError<C::B>) {
+        if self.out.error.is_none() {
+            self.out.error = Some(err.to_string());
+        }
+    }
+}
+
+mod outcome {
+    use super::*;
+
+    impl Outcome {
+        // clauses as sets, so the order of their literals does not matter
+        pub(super) fn normalize(&self) -> Outcome {
+            let mut out = self.clone();
+            for (c, _) in out.lemmas.iter_mut() { c.sort() }
+            if let Some((c, _)) = &mut out.conflict { c.sort() }
+            out
+        }
+    }
+
+    // one `>` line per item
+    impl fmt::Display for Outcome {
+        fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+            for p in self.props.iter() { writeln!(out, "> propagate {}", p)? }
+            for (c, kind) in self.lemmas.iter() {
+                writeln!(out, "> lemma {}{}", kind_str(*kind), Lits(c))?
+            }
+            if let Some((c, costly)) = &self.conflict {
+                writeln!(out, "> conflict {}{}", if *costly {"costly"} else {"cheap"}, Lits(c))?
+            }
+            for (lazy, t, sign, i) in self.lits.iter() {
+                writeln!(out, "> lit {} {}{} {}", if *lazy {"b"} else {"t"}, sign_str(*sign), t, i)?
+            }
+            if let Some(f) = &self.incomplete { writeln!(out, "> incomplete {}", f)? }
+            if let Some(e) = &self.error { writeln!(out, "> error {}", one_line(e))? }
+            Ok(())
+        }
+    }
+
+    impl fmt::Display for ReplayError {
+        fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+            let or_nothing = |s: &str| if s.is_empty() { "(nothing)\n".to_string() } else { s.to_string() };
+            match self {
+                ReplayError::Log(e) => write!(out, "cannot replay: {}", e),
+                ReplayError::Divergence{event, expected, found} => {
+                    write!(out, "replay diverges at event {}\nexpected:\n{}found:\n{}",
+                        event, or_nothing(expected), or_nothing(found))
+                },
+            }
+        }
+    }
+}
+
+// ` 1 -2 3`
+struct Lits<'a>(&'a [i32]);
+
+impl<'a> fmt::Display for Lits<'a> {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        for i in self.0.iter() { write!(out, " {}", i)? }
+        Ok(())
+    }
+}
+
+fn sign_str(sign: bool) -> &'static str { if sign { "+" } else { "-" } }
+
+fn one_line(s: &str) -> String { s.replace(&['\n', '\r'][..], " ") }
+
+fn kind_str(kind: ClauseKind) -> &'static str {
+    match kind {
+        ClauseKind::Input => "input",
+        ClauseKind::TheoryLemmaPermanent => "permanent",
+        ClauseKind::TheoryLemmaDeletable => "deletable",
+        ClauseKind::Learned => "learned",
+    }
+}
+
+mod log {
+    use super::*;
+
+    impl Log {
+        /// Write the log in its text format.
+        pub fn write<W:io::Write>(&self, out: &mut W) -> io::Result<()> {
+            writeln!(out, "{}{}", HEADER, if self.checked {""} else {" unchecked"})?;
+            let ty_str = |ty: &Option<u32>| ty.map_or("_".to_string(), |u| u.to_string());
+            for (i, t) in self.terms.iter().enumerate() {
+                match t {
+                    LogTerm::Const{name, ty} => writeln!(out, "t {} {} c {}", i, ty_str(ty), one_line(name))?,
+                    LogTerm::App{f, args, ty} => {
+                        let args: Vec<i32> = args.iter().map(|u| *u as i32).collect();
+                        writeln!(out, "t {} {} a {}{}", i, ty_str(ty), f, Lits(&args))?
+                    },
+                    LogTerm::Index(n) => writeln!(out, "t {} _ i {}", i, n)?,
+                }
+            }
+            let trail = |trail: &LogTrail| -> String {
+                trail.iter().map(|(t,sign,i)| format!(" {}{}/{}", sign_str(*sign), t, i)).collect()
+            };
+            for ev in self.events.iter() {
+                match ev {
+                    Event::AddLiteral(t, i) => writeln!(out, "lit {} {}", t, i)?,
+                    Event::PushLevel => writeln!(out, "push")?,
+                    Event::PopLevels(n) => writeln!(out, "pop {}", n)?,
+                    Event::EnablePropagation(on) => writeln!(out, "propagation {}", if *on {"on"} else {"off"})?,
+                    Event::PartialCheck(tr, o) => write!(out, "partial{}\n{}", trail(tr), o)?,
+                    Event::FinalCheck(tr, o) => write!(out, "final{}\n{}", trail(tr), o)?,
+                    Event::Explain{t, sign, p, expl} => {
+                        writeln!(out, "explain {}{} {} :{}", sign_str(*sign), t, p, Lits(expl))?
+                    },
+                }
+            }
+            Ok(())
+        }
+
+        /// Parse a log written by `write`.
+        pub fn parse(s: &str) -> Result<Log, Error> {
+            let mut log = Log::default();
+            let mut lines = s.lines().enumerate();
+            match lines.next() {
+                Some((_, l)) if l.trim() == HEADER => log.checked = true,
+                Some((_, l)) if l.trim() == format!("{} unchecked", HEADER) => (),
+                _ => return Err(Error::Parse{pos: Some(Pos{line: 1, col: 1}), msg: "expected a theory log".to_string()}),
+            }
+            for (i, line) in lines {
+                let err = |msg: String| Error::Parse{pos: Some(Pos{line: i as u32 + 1, col: 1}), msg};
+                parse_line(&mut log, line).map_err(err)?
+            }
+            Ok(log)
+        }
+    }
+
+    fn int<T:std::str::FromStr>(s: Option<&str>) -> Result<T, String> {
+        let s = s.ok_or_else(|| "missing number".to_string())?;
+        s.parse().map_err(|_| format!("expected a number, not `{}`", s))
+    }
+
+    fn lit(s: Option<&str>) -> Result<i32, String> {
+        match int(s)? {
+            0 => Err("literal 0".to_string()),
+            i => Ok(i),
+        }
+    }
+
+    // `+t` or `-t`
+    fn signed_term(n_terms: usize, s: Option<&str>) -> Result<(u32, bool), String> {
+        let s = s.ok_or_else(|| "missing term".to_string())?;
+        let sign = match s.chars().next() {
+            Some('+') => true,
+            Some('-') => false,
+            _ => return Err(format!("expected a signed term, not `{}`", s)),
+        };
+        let t = term(n_terms, Some(&s[1..]))?;
+        Ok((t, sign))
+    }
+
+    // `u32::MAX` stands for a term that is not in the log
+    fn term(n_terms: usize, s: Option<&str>) -> Result<u32, String> {
+        let t = int(s)?;
+        if t as usize >= n_terms && t != u32::MAX {
+            return Err(format!("unknown term {}", t))
+        }
+        Ok(t)
+    }
+
+    fn parse_line(log: &mut Log, line: &str) -> Result<(), String> {
+        let mut toks = line.split_whitespace();
+        let n = log.terms.len();
+        let ev = match toks.next() {
+            None => return Ok(()),
+            Some(tok) if tok.starts_with(';') => return Ok(()),
+            Some("t") => {
+                let mut parts = line.trim_start().splitn(5, ' ').skip(1);
+                let id: usize = int(parts.next())?;
+                if id != n { return Err(format!("expected term {}", n)) }
+                let ty = match parts.next() {
+                    Some("_") => None,
+                    s => Some(term(n, s)?),
+                };
+                let t = match (parts.next(), parts.next()) {
+                    (Some("c"), Some(name)) => LogTerm::Const{name: name.to_string(), ty},
+                    (Some("i"), n) => LogTerm::Index(int(n)?),
+                    (Some("a"), Some(rest)) => {
+                        let mut args = vec!();
+                        for a in rest.split_whitespace() { args.push(term(n, Some(a))?) }
+                        if args.is_empty() { return Err("application without head".to_string()) }
+                        let f = args.remove(0);
+                        LogTerm::App{f, args, ty}
+                    },
+                    _ => return Err("ill-formed term".to_string()),
+                };
+                log.terms.push(t);
+                return Ok(())
+            },
+            Some("lit") => Event::AddLiteral(term(n, toks.next())?, lit(toks.next())?),
+            Some("push") => Event::PushLevel,
+            Some("pop") => Event::PopLevels(int(toks.next())?),
+            Some("propagation") => match toks.next() {
+                Some("on") => Event::EnablePropagation(true),
+                Some("off") => Event::EnablePropagation(false),
+                _ => return Err("expected `on` or `off`".to_string()),
+            },
+            Some(kw @ "partial") | Some(kw @ "final") => {
+                let mut trail = vec!();
+                for item in toks.by_ref() {
+                    let mut it = item.splitn(2, '/');
+                    let (t, sign) = signed_term(n, it.next())?;
+                    trail.push((t, sign, lit(it.next())?));
+                }
+                if kw == "final" {
+                    Event::FinalCheck(trail, Outcome::default())
+                } else {
+                    Event::PartialCheck(trail, Outcome::default())
+                }
+            },
+            Some("explain") => {
+                let (t, sign) = signed_term(n, toks.next())?;
+                let p = lit(toks.next())?;
+                if toks.next() != Some(":") { return Err("expected `:`".to_string()) }
+                let expl = toks.by_ref().map(|s| lit(Some(s))).collect::<Result<_,_>>()?;
+                Event::Explain{t, sign, p, expl}
+            },
+            Some(">") => {
+                let out = match log.events.last_mut() {
+                    Some(Event::PartialCheck(_, out)) | Some(Event::FinalCheck(_, out)) => out,
+                    _ => return Err("outcome without a check".to_string()),
+                };
+                let rest = |toks: std::str::SplitWhitespace| toks.collect::<Vec<_>>().join(" ");
+                match toks.next() {
+                    Some("propagate") => out.props.push(lit(toks.next())?),
+                    Some("lemma") => {
+                        let kind = match toks.next() {
+                            Some("input") => ClauseKind::Input,
+                            Some("permanent") => ClauseKind::TheoryLemmaPermanent,
+                            Some("deletable") => ClauseKind::TheoryLemmaDeletable,
+                            Some("learned") => ClauseKind::Learned,
+                            _ => return Err("unknown kind of lemma".to_string()),
+                        };
+                        let c = toks.map(|s| lit(Some(s))).collect::<Result<_,_>>()?;
+                        out.lemmas.push((c, kind))
+                    },
+                    Some("conflict") => {
+                        let costly = match toks.next() {
+                            Some("costly") => true,
+                            Some("cheap") => false,
+                            _ => return Err("expected `costly` or `cheap`".to_string()),
+                        };
+                        let c = toks.map(|s| lit(Some(s))).collect::<Result<_,_>>()?;
+                        out.conflict = Some((c, costly))
+                    },
+                    Some("lit") => {
+                        let lazy = match toks.next() {
+                            Some("b") => true,
+                            Some("t") => false,
+                            _ => return Err("expected `t` or `b`".to_string()),
+                        };
+                        let (t, sign) = signed_term(n, toks.next())?;
+                        out.lits.push((lazy, t, sign, lit(toks.next())?))
+                    },
+                    Some("incomplete") => out.incomplete = Some(rest(toks)),
+                    Some("error") => out.error = Some(rest(toks)),
+                    _ => return Err("unknown outcome".to_string()),
+                }
+                return Ok(())
+            },
+            Some(kw) => return Err(format!("unknown event `{}`", kw)),
+        };
+        log.events.push(ev);
+        Ok(())
+    }
+}
@@ -6,15 +6,20 @@
 
 #[macro_use] extern crate log;
 
+#[cfg(feature="simp-trace")]
+pub mod simp_trace;
+
 use {
+    std::{fmt, sync::Arc, time::{Duration, Instant}},
     batsmt_core::{
-        ast_u32::{self, AST, }, gc, AstView,
-        ast::{self, AstMap, iter_dag::State as AstIter},
+        ast_u32::{self, AST, AstDenseMap, }, gc, AstView, Error, hash,
+        mem::{self, MemTree, ReportMemory},
+        ast::{self, AstMap, algo::AlgoCache, iter_dag::{State as AstIter, Order}},
     },
-    fxhash::FxHashSet,
+    fxhash::FxHashMap,
     batsmt_theory::{
-        self as theory, TheoryLit, TheoryClauseSet, TheoryClauseRef,
-        LitMap, pp_ast, },
+        self as theory, TheoryLit, TheoryClauseSet, TheoryClauseRef, ClauseSetIntoIter,
+        LitMap, CancelToken, pp_ast, },
 };
 
 /// A boolean-centric view of formulas.
@@ -31,8 +36,49 @@ pub enum View<'a, AST> {
     Ite(AST,AST,AST),
 }
 
+/// The kind of a formula: its `View` without the arguments.
+///
+/// This is cheap to copy and to cache, see `Ctx::formula_kind`.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum FormulaKind {
+    TyBool,
+    Bool(bool),
+    Not,
+    And,
+    Or,
+    Imply,
+    Eq,
+    Distinct,
+    Atom,
+    Ite,
+}
+
+/// Inline capacity of `SVec`, used for flattened connectives.
+///
+/// This is a temporary buffer, so the extra room is cheap: on `benchs/`,
+/// 8 keeps all but 817 of 5673 connectives inline, and 16 barely does
+/// better (see `benchs/svec_report.md`).
+/// The `svec-large` feature raises it for wide conjunctions/disjunctions.
+#[cfg(not(feature="svec-large"))]
+pub const SVEC_SIZE: usize = 8;
+#[cfg(feature="svec-large")]
+pub const SVEC_SIZE: usize = 16;
+
 /// A relatively big small-vec
-type SVec<T> = smallvec::SmallVec<[T; 6]>;
+type SVec<T> = smallvec::SmallVec<[T; SVEC_SIZE]>;
+
+/// Spill counters for the small vectors of this crate.
+///
+/// Only counted with the `svec-stats` feature.
+pub mod svec_sites {
+    use batsmt_core::svec_stats::Site;
+
+    /// Arguments of a flattened `and`/`or`.
+    pub static FLATTEN: Site = Site::new("tseitin.flatten", crate::SVEC_SIZE);
+
+    /// All the sites of this crate.
+    pub static ALL: [&Site; 1] = [&FLATTEN];
+}
 
 pub trait Ctx : theory::Ctx {
     /// How to view an AST.
@@ -41,6 +87,14 @@ pub trait Ctx : theory::Ctx {
     /// How to build an AST.
     fn mk_formula(&mut self, v: View<AST>) -> AST;
 
+    /// Kind of the formula `t`, i.e. `view_as_formula(t)` without arguments.
+    ///
+    /// The Tseitin transformation caches the result for each term, so
+    /// this is called at most once per term (until memory is reclaimed).
+    fn formula_kind(&self, t: AST) -> FormulaKind {
+        self.view_as_formula(t).kind()
+    }
+
     fn is_bool(&self, t: AST) -> bool {
         self.view_as_formula(t).is_bool()
     }
@@ -65,26 +119,252 @@ impl<'a, AST> View<'a, AST> {
     pub fn is_false(&self) -> bool {
         match self { View::Bool(false) => true, _ => false }
     }
+
+    /// Kind of this view.
+    pub fn kind(&self) -> FormulaKind {
+        match self {
+            View::TyBool => FormulaKind::TyBool,
+            View::Bool(b) => FormulaKind::Bool(*b),
+            View::Not(..) => FormulaKind::Not,
+            View::And(..) => FormulaKind::And,
+            View::Or(..) => FormulaKind::Or,
+            View::Imply(..) => FormulaKind::Imply,
+            View::Eq(..) => FormulaKind::Eq,
+            View::Distinct(..) => FormulaKind::Distinct,
+            View::Atom(..) => FormulaKind::Atom,
+            View::Ite(..) => FormulaKind::Ite,
+        }
+    }
+}
+
+/// A term known to be a formula: a connective, or a term of sort `Bool`.
+///
+/// The sort is checked once by `BoolTerm::new`; APIs taking a `BoolTerm`,
+/// such as `Tseitin::clauses_bool`, do not check it again. Subterms are
+/// not checked.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Ord,PartialOrd,Hash)]
+pub struct BoolTerm(AST);
+
+impl BoolTerm {
+    /// `Some(t)` if `t` can be used as a formula.
+    pub fn new<C:Ctx>(c: &C, t: AST) -> Option<Self> {
+        let ok = match c.formula_kind(t) {
+            FormulaKind::TyBool => false,
+            FormulaKind::Atom => c.ty(&t).map_or(false, |ty| c.formula_kind(ty) == FormulaKind::TyBool),
+            _ => true,
+        };
+        if ok { Some(BoolTerm(t)) } else { None }
+    }
+
+    /// The term itself.
+    #[inline]
+    pub fn term(&self) -> AST { self.0 }
+}
+
+impl From<BoolTerm> for AST {
+    fn from(t: BoolTerm) -> AST { t.0 }
+}
+
+/// Cache for `Ctx::formula_kind`.
+#[derive(Clone)]
+struct KindCache(AstDenseMap<FormulaKind>);
+
+impl KindCache {
+    fn new() -> Self { KindCache(AstDenseMap::new()) }
+
+    /// Kind of `t`, computed at most once.
+    #[inline]
+    fn get<C:Ctx>(&mut self, m: &C, t: AST) -> FormulaKind {
+        match self.0.get(&t) {
+            Some(k) => *k,
+            None => {
+                let k = m.formula_kind(t);
+                self.0.insert(t, k);
+                k
+            }
+        }
+    }
 }
 
 /// Main state for the Tseitin transformation.
 ///
 /// The state remembers which formulas have been translated to clauses already.
 #[derive(Clone)]
-pub struct Tseitin<C:Ctx> {
-    simp_map: ast::HashMap<AST, AST>, // for simplify
-    iter: AstIter<AST, ast_u32::HashSet>, // to traverse subterms
+pub struct Tseitin<C:theory::Ctx> {
+    simp_map: SimpCache, // for simplify
+    iter: AstIter<AST, ast_u32::EpochSet>, // to traverse subterms
+    check_iter: AstIter<AST, ast_u32::EpochSet>, // to traverse subterms in `check_formula`
     tmp: Vec<TheoryLit<C>>, // temp clause
     tmp2: Vec<TheoryLit<C>>, // temp clause
     tmp_ast: Vec<AST>, // for arguments
     cs: TheoryClauseSet<C>, // clauses
-    lits: FxHashSet<TheoryLit<C>>, // lits
+    lits: hash::IndexSet<TheoryLit<C>>, // literals of the clauses so far, in order of appearance
+    bool_args: hash::HashSet<AST>, // connectives used as arguments, see `connect_bool_args`
+    kinds: KindCache, // kind of each formula
+    sizes: AstDenseMap<u64>, // size of the tree expansion of each subterm
+    tmp_stack: Vec<(AST,bool)>, // for `tree_size`
+    spare: Vec<ClauseBatch<C>>, // recycled batches
+    max_new_nodes: usize, // budget for one call to `simplify`
+    simplify_fixpoint: bool, // see `set_simplify_fixpoint`
+    simplify_time_limit: Option<Duration>, // see `set_simplify_time_limit`
+    cancel: Option<CancelToken>, // see `set_cancel_token`
+    interrupted: bool, // was the last call to `simplify` interrupted?
+    #[cfg(feature="simp-trace")]
+    simp_trace: Option<simp_trace::Trace>, // see `set_simp_trace`
+    propagate_units: bool, // see `set_propagate_units`
+    definition_order: DefinitionOrder<C>, // see `set_definition_order`
+    units: Units, // for `clauses_many`
+    subst: ast::HashMap<AST,AST>, // for `clauses_many`
+    algo: AlgoCache<AST>, // for `clauses_many`
+    stats: Stats,
+}
+
+/// Order in which `clauses` defines the subterms of a formula.
+///
+/// The clauses are the same in every order, only their sequence changes,
+/// and with it the numbering of the SAT variables allocated for them.
+/// The encoding of a subterm only refers to the literals of its
+/// arguments, so it does not need them to be defined first.
+#[derive(Clone)]
+pub enum DefinitionOrder<C> {
+    /// Visit the subterms in the given order (`Order::PreOrder` by default).
+    Traversal(Order),
+    /// Visit the subterms by increasing key, ties in `Order::PostOrder`.
+    Key(Arc<dyn Fn(&C, &AST) -> u64 + Send + Sync>),
+}
+
+impl<C> Default for DefinitionOrder<C> {
+    fn default() -> Self { DefinitionOrder::Traversal(Order::PreOrder) }
+}
+
+impl<C> fmt::Debug for DefinitionOrder<C> {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DefinitionOrder::Traversal(o) => write!(out, "traversal({:?})", o),
+            DefinitionOrder::Key(_) => write!(out, "key"),
+        }
+    }
+}
+
+/// Default budget of `simplify`, see `Tseitin::set_max_new_nodes`.
+pub const DEFAULT_MAX_NEW_NODES: usize = 1_000_000;
+
+/// Maximum number of passes of `simplify`, see `Tseitin::set_simplify_fixpoint`.
+pub const MAX_SIMPLIFY_PASSES: usize = 16;
+
+/// `simplify` checks its time limit and cancel token once every
+/// `SIMPLIFY_CHECK_PERIOD` results put in its cache.
+pub const SIMPLIFY_CHECK_PERIOD: usize = 16;
+
+/// Statistics of the Tseitin transformation.
+#[derive(Clone,Debug,Default)]
+pub struct Stats {
+    /// Calls to `simplify`.
+    pub simplify: u64,
+    /// Nodes built by `simplify`.
+    pub new_nodes: u64,
+    /// Calls to `simplify` that ran out of budget and returned a partially simplified term.
+    pub partial_simplify: u64,
+    /// Calls to `simplify` stopped by their time limit or cancel token.
+    /// They are counted in `partial_simplify` too.
+    pub interrupted_simplify: u64,
+    /// Passes of `simplify` beyond the first one, in fixpoint mode.
+    pub simplify_extra_passes: u64,
+    /// Results of `simplify` evicted from the cache (see `Tseitin::set_simplify_cache_cap`).
+    pub simplify_evicted: u64,
+    /// Distinct subterms traversed by the encoding.
+    pub encoded_subterms: u64,
+    /// Subterm occurrences in the tree expansion of the encoded formulas.
+    pub subterm_occurrences: u64,
+    /// Top-level units substituted into the other assertions by `clauses_many`.
+    pub units: u64,
+    /// Batches that `clauses_many` found unsat before encoding them.
+    pub unsat_batches: u64,
+    /// Literals defining a connective (`and`, `or`, `=>`) introduced by the encoding.
+    pub def_lits: u64,
+    /// Clauses emitted by the encoding, including the top-level unit clauses.
+    pub clauses: u64,
+    /// Theory atoms (boolean atoms and equations) met for the first time by the encoding.
+    pub atoms: u64,
+}
+
+impl Stats {
+    /// `encoded_subterms / subterm_occurrences`, or 1 if nothing was encoded.
+    ///
+    /// The lower it is, the more work the DAG encoding saved compared
+    /// to encoding formulas as trees.
+    pub fn sharing_ratio(&self) -> f64 {
+        if self.subterm_occurrences == 0 { return 1. }
+        self.encoded_subterms as f64 / self.subterm_occurrences as f64
+    }
+
+    /// Copy of the current statistics, to be compared later using `diff`.
+    pub fn snapshot(&self) -> Stats { self.clone() }
+
+    /// What was counted since `before`, an earlier snapshot of these statistics.
+    ///
+    /// ```
+    /// # use batsmt_tseitin::Stats;
+    /// let mut st = Stats::default();
+    /// st.clauses = 3;
+    /// let before = st.snapshot();
+    /// st.clauses += 2;
+    /// assert_eq!(st.diff(&before).clauses, 2);
+    /// ```
+    pub fn diff(&self, before: &Stats) -> Stats {
+        let d = |x: u64, y: u64| x.saturating_sub(y);
+        Stats {
+            simplify: d(self.simplify, before.simplify),
+            new_nodes: d(self.new_nodes, before.new_nodes),
+            partial_simplify: d(self.partial_simplify, before.partial_simplify),
+            interrupted_simplify: d(self.interrupted_simplify, before.interrupted_simplify),
+            simplify_extra_passes: d(self.simplify_extra_passes, before.simplify_extra_passes),
+            simplify_evicted: d(self.simplify_evicted, before.simplify_evicted),
+            encoded_subterms: d(self.encoded_subterms, before.encoded_subterms),
+            subterm_occurrences: d(self.subterm_occurrences, before.subterm_occurrences),
+            units: d(self.units, before.units),
+            unsat_batches: d(self.unsat_batches, before.unsat_batches),
+            def_lits: d(self.def_lits, before.def_lits),
+            clauses: d(self.clauses, before.clauses),
+            atoms: d(self.atoms, before.atoms),
+        }
+    }
+}
+
+mod stats {
+    use {std::fmt, super::*};
+    impl fmt::Display for Stats {
+        fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+            write!(out, "tseitin.simplify {}, tseitin.new_nodes {}, tseitin.partial_simplify {}, \
+                   tseitin.interrupted_simplify {}, tseitin.simplify_extra_passes {}, \
+                   tseitin.simplify_evicted {}, tseitin.sharing_ratio {:.3}, \
+                   tseitin.units {}, tseitin.unsat_batches {}, \
+                   tseitin.def_lits {}, tseitin.clauses {}, tseitin.atoms {}",
+                   self.simplify, self.new_nodes, self.partial_simplify,
+                   self.interrupted_simplify, self.simplify_extra_passes, self.simplify_evicted,
+                   self.sharing_ratio(), self.units, self.unsat_batches,
+                   self.def_lits, self.clauses, self.atoms)
+        }
+    }
+}
+
+/// Clauses and literals obtained from `Tseitin::clauses_owned`.
+///
+/// Unlike the result of `Tseitin::clauses`, this doesn't borrow the encoder.
+/// Give it back with `Tseitin::recycle` once done, so its storage can
+/// be reused.
+#[derive(Clone)]
+pub struct ClauseBatch<C:theory::Ctx> {
+    cs: TheoryClauseSet<C>,
+    lits: Vec<TheoryLit<C>>,
+    origin: Option<usize>,
 }
 
 /// Temporary structure
 struct LitMapB<'a, C:Ctx, LM: LitMap<C::B>> {
     m: &'a mut C,
     lit_map: &'a mut LM,
+    kinds: &'a mut KindCache,
 }
 
 impl<'a,C,LM> LitMapB<'a,C,LM>
@@ -93,28 +373,26 @@ impl<'a,C,LM> LitMapB<'a,C,LM>
     /// Map `t,sign` to either a theory literal, or a lazy pure boolean literal
     fn term_to_lit(&mut self, t: &AST) -> TheoryLit<C> {
         let (t,sign) = self.lit_map.unfold_not(self.m, t, true);
-        let view_t = self.m.view_as_formula(t);
-        match view_t {
-            View::Bool(..) => {
+        match self.kinds.get(self.m, t) {
+            FormulaKind::Bool(..) => {
                 TheoryLit::new_b(t, sign)
             },
-            View::And(..) | View::Or(..) | View::Imply(..) => {
+            FormulaKind::And | FormulaKind::Or | FormulaKind::Imply => {
                 TheoryLit::new_t(t, sign)
             },
-            View::Distinct(args) if args.len() == 2 => {
-                // turn `distinct(a,b)` into `!(a=b)`
-                let t0 = args[0];
-                let t1 = args[1];
-                drop(view_t);
-                let eqn = self.m.mk_formula(View::Eq(t0, t1));
-                ! TheoryLit::new_t(eqn, sign)
-            },
-            View::Distinct(..) => {
-                TheoryLit::new_b(t,sign) // encoded away
+            FormulaKind::Distinct => {
+                match self.m.view_as_formula(t) {
+                    View::Distinct(&[t0, t1]) => {
+                        // turn `distinct(a,b)` into `!(a=b)`
+                        let eqn = self.m.mk_formula(View::Eq(t0, t1));
+                        ! TheoryLit::new_t(eqn, sign)
+                    },
+                    _ => TheoryLit::new_b(t,sign), // encoded away
+                }
             },
-            View::TyBool => panic!("type bool doesn't map to a literal"),
-            View::Not(..) => panic!("should not have a negation"), // unfold-not
-            View::Atom(..) | View::Eq(..) | View::Ite(..) => {
+            FormulaKind::TyBool => panic!("type bool doesn't map to a literal"),
+            FormulaKind::Not => panic!("should not have a negation"), // unfold-not
+            FormulaKind::Atom | FormulaKind::Eq | FormulaKind::Ite => {
                 // theory literal
                 TheoryLit::new_t(t, sign)
             },
@@ -125,23 +403,180 @@ impl<'a,C,LM> LitMapB<'a,C,LM>
 #[derive(Copy,Clone,Debug,PartialEq)]
 enum Conn { And, Or, }
 
+/// Rewrite rules applied by `Tseitin::simplify`, see `simp_trace`.
+#[derive(Copy,Clone,Debug,Eq,PartialEq,Hash)]
+pub enum SimpRule {
+    /// Simplify the function and arguments of an application.
+    AppArgs,
+    /// `distinct(a) --> true`
+    DistinctUnit,
+    /// `distinct(a,b) --> not (a=b)`
+    DistinctPair,
+    /// `distinct(t1…tn) --> and_{i<j} not (t_i=t_j)`
+    DistinctExpand,
+    /// `a=a --> true`
+    EqRefl,
+    /// `not b --> ¬b`, for a boolean constant `b`
+    NotBool,
+    /// `not (not a) --> a`
+    NotNot,
+    /// Simplify the argument of a negation.
+    NotArg,
+    /// Flatten, sort and deduplicate a conjunction, simplify its arguments.
+    FlattenAnd,
+    /// `and(…, false, …) --> false`
+    AndFalse,
+    /// `and(…, a, not a, …) --> false`
+    AndComplementary,
+    /// `and() --> true`, once flattened
+    AndTrue,
+    /// Flatten, sort and deduplicate a disjunction, simplify its arguments.
+    FlattenOr,
+    /// `or(…, true, …) --> true`
+    OrTrue,
+    /// `or(…, a, not a, …) --> true`
+    OrComplementary,
+    /// `or() --> false`, once flattened
+    OrFalse,
+    /// `a1 => … => b --> or(not a1, …, b)`, simplified
+    ImplyToOr,
+    /// `ite(true, a, b) --> a` and `ite(false, a, b) --> b`
+    FoldIte,
+    /// `ite(c, a, a) --> a`
+    IteSame,
+    /// Simplify the arguments of an `ite`.
+    IteArgs,
+    /// Result of all the passes, in fixpoint mode.
+    Fixpoint,
+}
+
+impl SimpRule {
+    /// Identifier of the rule in traces.
+    pub fn name(&self) -> &'static str {
+        use SimpRule::*;
+        match self {
+            AppArgs => "app-args",
+            DistinctUnit => "distinct-unit",
+            DistinctPair => "distinct-pair",
+            DistinctExpand => "distinct-expand",
+            EqRefl => "eq-refl",
+            NotBool => "not-bool",
+            NotNot => "not-not",
+            NotArg => "not-arg",
+            FlattenAnd => "flatten-and",
+            AndFalse => "and-false",
+            AndComplementary => "and-complementary",
+            AndTrue => "and-true",
+            FlattenOr => "flatten-or",
+            OrTrue => "or-true",
+            OrComplementary => "or-complementary",
+            OrFalse => "or-false",
+            ImplyToOr => "imply-to-or",
+            FoldIte => "fold-ite",
+            IteSame => "ite-same",
+            IteArgs => "ite-args",
+            Fixpoint => "fixpoint",
+        }
+    }
+}
+
+impl std::fmt::Display for SimpRule {
+    fn fmt(&self, out: &mut std::fmt::Formatter) -> std::fmt::Result {
+        out.write_str(self.name())
+    }
+}
+
+/// Cache of `simplify`, optionally bounded.
+///
+/// Once there are more than `cap` entries, the least recently used
+/// ones are evicted, keeping only `cap/2` of them.
+#[derive(Clone)]
+struct SimpCache {
+    map: hash::HashMap<AST, (AST, u64)>, // result, and time of last use
+    clock: u64,
+    cap: Option<usize>,
+    evicted: u64,
+}
+
+impl SimpCache {
+    fn new() -> Self {
+        SimpCache { map: hash::HashMap::default(), clock: 0, cap: None, evicted: 0, }
+    }
+
+    fn get(&mut self, t: &AST) -> Option<AST> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.map.get_mut(t).map(|(u, last)| { *last = clock; *u })
+    }
+
+    fn insert(&mut self, t: AST, u: AST) {
+        self.clock += 1;
+        self.map.insert(t, (u, self.clock));
+        self.check_cap();
+    }
+
+    fn set_cap(&mut self, cap: Option<usize>) {
+        self.cap = cap;
+        self.check_cap();
+    }
+
+    fn check_cap(&mut self) {
+        match self.cap {
+            Some(cap) if self.map.len() > cap => self.evict(cap / 2),
+            _ => (),
+        }
+    }
+
+    // keep only the `keep` most recently used entries
+    fn evict(&mut self, keep: usize) {
+        let n = self.map.len();
+        if keep == 0 {
+            self.map.clear();
+        } else {
+            let mut times: Vec<u64> = self.map.values().map(|(_, last)| *last).collect();
+            times.sort_unstable();
+            let oldest_kept = times[n - keep]; // times are unique
+            self.map.retain(|_, (_, last)| *last >= oldest_kept);
+        }
+        debug!("tseitin.simplify: evicted {} cached results", n - self.map.len());
+        self.evicted += (n - self.map.len()) as u64;
+    }
+}
+
 struct SimpStruct<'a, C:Ctx> {
     m: &'a mut C,
-    map: &'a mut ast::HashMap<AST, AST>,
+    map: &'a mut SimpCache,
+    kinds: &'a mut KindCache,
+    n_new: usize, // nodes built so far
+    max_new_nodes: usize,
+    partial: bool, // did we stop rewriting somewhere?
+    cancel: Option<&'a CancelToken>,
+    deadline: Option<Instant>,
+    n_inserts: usize, // results put in the cache so far
+    interrupted: bool, // stopped by `deadline` or `cancel`?
+    rule: SimpRule, // rule of the last rewrite done by `simplify_view`/`simplify_app`
+    #[cfg(feature="simp-trace")]
+    trace: Option<&'a mut simp_trace::Trace>,
 }
 
 /// Push each element `t` of `args` into `v`, but if `t=conn(u1…un)` then flatten `u1…un` into `v`
-fn flatten_conn_rec<C:Ctx>(m: &C, conn: Conn, v: &mut SVec<AST>, args: &[AST]) {
+fn flatten_conn_rec<C:Ctx>(
+    m: &C, kinds: &mut KindCache, conn: Conn, v: &mut SVec<AST>, args: &[AST]
+) {
     for t in args.iter() {
-        match m.view_as_formula(*t) {
-            View::And(args2) if conn == Conn::And => {
-                flatten_conn_rec(m, conn, v, args2)
+        match kinds.get(m, *t) {
+            FormulaKind::And if conn == Conn::And => {
+                if let View::And(args2) = m.view_as_formula(*t) {
+                    flatten_conn_rec(m, kinds, conn, v, args2)
+                }
             },
-            View::Or(args2) if conn == Conn::Or => {
-                flatten_conn_rec(m, conn, v, args2)
+            FormulaKind::Or if conn == Conn::Or => {
+                if let View::Or(args2) = m.view_as_formula(*t) {
+                    flatten_conn_rec(m, kinds, conn, v, args2)
+                }
             },
-            View::Bool(true) if conn == Conn::And => (), // skip
-            View::Bool(false) if conn == Conn::Or => (), // skip
+            FormulaKind::Bool(true) if conn == Conn::And => (), // skip
+            FormulaKind::Bool(false) if conn == Conn::Or => (), // skip
             _ => {
                 v.push(*t)
             }
@@ -150,166 +585,637 @@ fn flatten_conn_rec<C:Ctx>(m: &C, conn: Conn, v: &mut SVec<AST>, args: &[AST]) {
 }
 
 /// Push each element `t` of `args` into `v`, but if `t=conn(u1…un)` then flatten `u1…un` into `v`
-fn flatten_conn<C:Ctx>(m: &C, conn: Conn, v: &mut SVec<AST>, args: &[AST]) {
-    flatten_conn_rec(m, conn, v, args);
+fn flatten_conn<C:Ctx>(
+    m: &C, kinds: &mut KindCache, conn: Conn, v: &mut SVec<AST>, args: &[AST]
+) {
+    flatten_conn_rec(m, kinds, conn, v, args);
     v.sort_unstable();
     v.dedup();
+    svec_sites::FLATTEN.record(v.len());
+}
+
+/// Does the sorted slice `args` contain both some `u` and `not u`?
+fn has_complementary<C:Ctx>(m: &C, kinds: &mut KindCache, args: &[AST]) -> bool {
+    args.iter().any(|t| {
+        kinds.get(m, *t) == FormulaKind::Not && match m.view_as_formula(*t) {
+            View::Not(u) => args.binary_search(&u).is_ok(),
+            _ => false,
+        }
+    })
 }
 
 impl<'a, C:Ctx> SimpStruct<'a, C> {
     fn simplify_rec(&mut self, t: AST) -> AST {
         if let Some(u) = self.map.get(&t) {
-            *u // in cache
+            u // in cache
+        } else if self.n_new >= self.max_new_nodes || self.interrupted {
+            self.partial = true;
+            t // out of budget or time, keep `t` as is
         } else {
             //trace!("simplify-rec {}", pp::pp1(self.m, &t));
-            let view_t = self.m.view_as_formula(t);
-            let u = match view_t {
-                View::Bool(..) | View::TyBool => t,
-                View::Distinct(&[_]) => {
-                    self.m.mk_formula(View::Bool(true))
-                },
-                View::Distinct(&[a,b]) => {
-                    let a = self.simplify_rec(a);
-                    let b = self.simplify_rec(b);
-                    let eq = self.m.mk_formula(View::Eq(a,b));
-                    self.m.mk_formula(View::Not(eq))
-                },
-                View::Distinct(args) => {
-                    // distinct(t1…tn) --> and_{i<j} t_i != t_j
-                    // simplify each subterm first
-                    let mut args: Vec<AST> = args.iter().cloned().collect();
-                    drop(view_t);
-
-                    for u in args.iter_mut() { *u = self.simplify_rec(*u) }
-                    let mut conj = vec!();
-
-                    for i in 0 .. args.len()-1 {
-                        let t_i = args[i];
-                        for j in i+1 .. args.len() {
-                            let t_j = args[j];
-                            let eqn_i_j = self.m.mk_formula(View::Eq(t_i, t_j));
-                            conj.push(self.m.mk_formula(View::Not(eqn_i_j)));
-                        }
-                    }
+            let u = match self.kinds.get(self.m, t) {
+                FormulaKind::Bool(..) | FormulaKind::TyBool => t,
+                FormulaKind::Atom => self.simplify_app(t),
+                _ => self.simplify_view(t),
+            };
+            if t != u {
+                trace!("(simp_rec :from {} :to {})", pp_ast(self.m,&t), pp_ast(self.m,&u));
+                self.record(self.rule, t, u);
+            }
+            // do not cache partial results, a later call might do better
+            if !self.partial {
+                self.map.insert(t, u);
+                self.n_inserts += 1;
+                if self.n_inserts % SIMPLIFY_CHECK_PERIOD == 0 {
+                    self.check_interrupt();
+                }
+            }
+            u
+        }
+    }
 
-                    self.m.mk_formula(View::And(&conj))
-                },
-                View::Eq(t, u) if t==u => {
-                    self.m.mk_formula(View::Bool(true))
+    // stop rewriting if the deadline is past or the token is canceled
+    fn check_interrupt(&mut self) {
+        let canceled = self.cancel.map_or(false, |tok| tok.is_canceled());
+        if canceled || self.deadline.map_or(false, |d| Instant::now() >= d) {
+            debug!("tseitin.simplify: interrupted after {} cached results (canceled: {})",
+                self.n_inserts, canceled);
+            self.interrupted = true;
+            self.partial = true;
+        }
+    }
+
+    /// `t` was rewritten into `u` by `rule`.
+    #[cfg(feature="simp-trace")]
+    fn record(&mut self, rule: SimpRule, t: AST, u: AST) {
+        if let Some(tr) = &mut self.trace { tr.push(rule, t, u) }
+    }
+
+    #[cfg(not(feature="simp-trace"))]
+    #[inline(always)]
+    fn record(&mut self, _: SimpRule, _: AST, _: AST) {}
+
+    /// Result `u` of `simplify_view` or `simplify_app`, obtained by `rule`.
+    ///
+    /// Must be called after the recursive calls, which set `self.rule` too.
+    #[inline]
+    fn rewrite(&mut self, rule: SimpRule, u: AST) -> AST {
+        self.rule = rule;
+        u
+    }
+
+    /// Build a formula, counting it against the budget.
+    fn mk_formula(&mut self, v: View<AST>) -> AST {
+        self.n_new += 1;
+        self.m.mk_formula(v)
+    }
+
+    // simplify immediate subterms of `t`
+    fn simplify_app(&mut self, t: AST) -> AST {
+        match self.m.view(&t) {
+            AstView::Const(_) | AstView::Index(..) => t,
+            AstView::App{f, args} => {
+                let mut args: SVec<AST> = args.iter().cloned().collect();
+                let f0 = *f;
+                let f = self.simplify_rec(f0);
+                let mut changed = f != f0;
+                for u in args.iter_mut() {
+                    let u2 = self.simplify_rec(*u);
+                    changed = changed || u2 != *u;
+                    *u = u2;
                 }
-                View::Eq(..) | View::Atom(..) => {
-                    // just map one level.
-                    drop(view_t);
-                    match self.m.view(&t) {
-                        AstView::Const(_) | AstView::Index(..) => t,
-                        AstView::App{f, args} => {
-                            let mut args: SVec<AST> = args.iter().cloned().collect();
-                            let f = self.simplify_rec(*f);
-                            for u in args.iter_mut() { *u = self.simplify_rec(*u) }
-                            self.m.mk_app(f, &args[..], self.m.ty(&t))
-                        }
-                    }
-                },
-                View::Not(u) => {
-                    let u = self.simplify_rec(u);
-                    match self.m.view_as_formula(u) {
-                        View::Bool(b) => self.m.mk_formula(View::Bool(!b)),
-                        View::Not(v) => v,
-                        _ => self.m.mk_formula(View::Not(u))
-                    }
+                if !changed {
+                    t // share `t` rather than rebuilding it
+                } else {
+                    self.n_new += 1;
+                    let ty = self.m.ty(&t);
+                    let u = self.m.mk_app(f, &args[..], ty);
+                    self.rewrite(SimpRule::AppArgs, u)
                 }
-                View::And(args0) => {
-                    let mut args = SVec::new();
-                    flatten_conn(self.m, Conn::And, &mut args, args0);
-                    for u in args.iter_mut() { *u = self.simplify_rec(*u) }
-                    if args.iter().any(|u| self.m.is_false(*u)) {
-                        self.m.mk_formula(View::Bool(false)) // shortcut
-                    } else {
-                        self.m.mk_formula(View::And(&args))
+            }
+        }
+    }
+
+    // simplify `t`, a connective
+    fn simplify_view(&mut self, t: AST) -> AST {
+        let view_t = self.m.view_as_formula(t);
+        match view_t {
+            View::Bool(..) | View::TyBool => t,
+            View::Distinct(&[_]) => {
+                let u = self.m.mk_formula(View::Bool(true));
+                self.rewrite(SimpRule::DistinctUnit, u)
+            },
+            View::Distinct(&[a,b]) => {
+                let a = self.simplify_rec(a);
+                let b = self.simplify_rec(b);
+                let eq = self.mk_formula(View::Eq(a,b));
+                let u = self.mk_formula(View::Not(eq));
+                self.rewrite(SimpRule::DistinctPair, u)
+            },
+            View::Distinct(args) => {
+                // distinct(t1…tn) --> and_{i<j} t_i != t_j
+                // simplify each subterm first
+                let mut args: Vec<AST> = args.iter().cloned().collect();
+
+                for u in args.iter_mut() { *u = self.simplify_rec(*u) }
+                let mut conj = vec!();
+
+                for i in 0 .. args.len()-1 {
+                    let t_i = args[i];
+                    for j in i+1 .. args.len() {
+                        let t_j = args[j];
+                        let eqn_i_j = self.mk_formula(View::Eq(t_i, t_j));
+                        conj.push(self.mk_formula(View::Not(eqn_i_j)));
                     }
                 }
-                View::Or(args0) => {
-                    let mut args = SVec::new();
-                    flatten_conn(self.m, Conn::Or, &mut args, args0);
-                    for u in args.iter_mut() { *u = self.simplify_rec(*u) }
-                    if args.iter().any(|u| self.m.is_true(*u)) {
-                        self.m.mk_formula(View::Bool(true)) // shortcut
-                    } else {
-                        self.m.mk_formula(View::Or(&args))
-                    }
-                },
-                View::Imply(args0) => {
-                    // transform into `or`
-                    let n = args0.len();
-                    assert!(n >= 2);
-                    let mut disj: Vec<AST> = args0.iter().cloned().collect();
-                    drop(view_t);
-
-                    for u in disj[.. n-1].iter_mut() {
-                        *u = self.m.mk_formula(View::Not(*u))
+
+                let u = self.mk_formula(View::And(&conj));
+                self.rewrite(SimpRule::DistinctExpand, u)
+            },
+            View::Eq(t, u) if t==u => {
+                let u = self.m.mk_formula(View::Bool(true));
+                self.rewrite(SimpRule::EqRefl, u)
+            }
+            View::Eq(..) | View::Atom(..) => {
+                // just map one level.
+                self.simplify_app(t)
+            },
+            View::Not(u0) => {
+                let u = self.simplify_rec(u0);
+                match self.kinds.get(self.m, u) {
+                    FormulaKind::Bool(b) => {
+                        let u = self.m.mk_formula(View::Bool(!b));
+                        self.rewrite(SimpRule::NotBool, u)
+                    },
+                    FormulaKind::Not => match self.m.view_as_formula(u) {
+                        View::Not(v) => self.rewrite(SimpRule::NotNot, v),
+                        _ => unreachable!(),
+                    },
+                    _ if u == u0 => t,
+                    _ => {
+                        let u = self.mk_formula(View::Not(u));
+                        self.rewrite(SimpRule::NotArg, u)
                     }
+                }
+            }
+            View::And(args0) => {
+                let mut args = SVec::new();
+                flatten_conn(self.m, self.kinds, Conn::And, &mut args, args0);
+                let flat = args.len() < 2 || args[..] != args0[..];
+                let changed = self.simplify_args(&mut args) || flat;
+                let SimpStruct{m, kinds, ..} = self;
+                let (rule, u) = if args.iter().any(|u| kinds.get(*m, *u) == FormulaKind::Bool(false)) {
+                    (SimpRule::AndFalse, self.m.mk_formula(View::Bool(false))) // shortcut
+                } else if has_complementary(*m, kinds, &args) {
+                    // `and(…, u, not u, …)`
+                    (SimpRule::AndComplementary, self.m.mk_formula(View::Bool(false)))
+                } else if args.is_empty() {
+                    // all arguments were `true`
+                    (SimpRule::AndTrue, self.m.mk_formula(View::Bool(true)))
+                } else if !changed {
+                    return t
+                } else {
+                    (SimpRule::FlattenAnd, self.mk_formula(View::And(&args)))
+                };
+                self.rewrite(rule, u)
+            }
+            View::Or(args0) => {
+                let mut args = SVec::new();
+                flatten_conn(self.m, self.kinds, Conn::Or, &mut args, args0);
+                let flat = args.len() < 2 || args[..] != args0[..];
+                let changed = self.simplify_args(&mut args) || flat;
+                let SimpStruct{m, kinds, ..} = self;
+                let (rule, u) = if args.iter().any(|u| kinds.get(*m, *u) == FormulaKind::Bool(true)) {
+                    (SimpRule::OrTrue, self.m.mk_formula(View::Bool(true))) // shortcut
+                } else if has_complementary(*m, kinds, &args) {
+                    // `or(…, u, not u, …)`
+                    (SimpRule::OrComplementary, self.m.mk_formula(View::Bool(true)))
+                } else if args.is_empty() {
+                    // all arguments were `false`
+                    (SimpRule::OrFalse, self.m.mk_formula(View::Bool(false)))
+                } else if !changed {
+                    return t
+                } else {
+                    (SimpRule::FlattenOr, self.mk_formula(View::Or(&args)))
+                };
+                self.rewrite(rule, u)
+            },
+            View::Imply(args0) => {
+                // transform into `or`
+                let n = args0.len();
+                assert!(n >= 2);
+                let mut disj: Vec<AST> = args0.iter().cloned().collect();
 
-                    let u = self.m.mk_formula(View::Or(&disj));
-                    // and simplify the disjunction as is, including flattening
-                    self.simplify_rec(u)
-                },
-                View::Ite(a,b,c) => {
-                    let a = self.simplify_rec(a);
-                    let b = self.simplify_rec(b);
-                    let c = self.simplify_rec(c);
-                    match self.m.view_as_formula(a) {
-                        View::Bool(true) => b,
-                        View::Bool(false) => c,
-                        _ if b == c => b,
-                        _ => self.m.mk_formula(View::Ite(a,b,c))
+                for u in disj[.. n-1].iter_mut() {
+                    *u = self.mk_formula(View::Not(*u))
+                }
+
+                let u = self.mk_formula(View::Or(&disj));
+                // and simplify the disjunction as is, including flattening
+                let u = self.simplify_rec(u);
+                self.rewrite(SimpRule::ImplyToOr, u)
+            },
+            View::Ite(a0,b0,c0) => {
+                let a = self.simplify_rec(a0);
+                let b = self.simplify_rec(b0);
+                let c = self.simplify_rec(c0);
+                match self.kinds.get(self.m, a) {
+                    FormulaKind::Bool(true) => self.rewrite(SimpRule::FoldIte, b),
+                    FormulaKind::Bool(false) => self.rewrite(SimpRule::FoldIte, c),
+                    _ if b == c => self.rewrite(SimpRule::IteSame, b),
+                    _ if (a,b,c) == (a0,b0,c0) => t,
+                    _ => {
+                        let u = self.mk_formula(View::Ite(a,b,c));
+                        self.rewrite(SimpRule::IteArgs, u)
                     }
-                },
-            };
-            if t != u { trace!("(simp_rec :from {} :to {})", pp_ast(self.m,&t), pp_ast(self.m,&u)); }
-            self.map.insert(t, u);
-            u
+                }
+            },
+        }
+    }
+
+    /// Simplify each element of the sorted slice `args`, returns `true` if any of them changed.
+    fn simplify_args(&mut self, args: &mut SVec<AST>) -> bool {
+        let mut changed = false;
+        for u in args.iter_mut() {
+            let u2 = self.simplify_rec(*u);
+            changed = changed || u2 != *u;
+            *u = u2;
+        }
+        if changed {
+            // keep arguments sorted, so that simplifying again is a no-op
+            args.sort_unstable();
+            args.dedup();
         }
+        changed
     }
 }
 
 /// Boolean-typed term?
-fn has_ty_bool<C:Ctx>(c: &C, t: &AST) -> bool {
+fn has_ty_bool<C:Ctx>(c: &C, kinds: &mut KindCache, t: &AST) -> bool {
     match c.ty(t) {
-        Some(b) =>
-            match c.view_as_formula(b) {
-                View::TyBool => true,
-                _ => false
-            },
+        Some(b) => kinds.get(c, b) == FormulaKind::TyBool,
         None => false
     }
 }
 
-impl<C> Tseitin<C> where C: Ctx {
+// `t` is used as a formula: fail if it has a sort other than `Bool`
+fn check_formula<C:Ctx>(c: &C, kinds: &mut KindCache, t: &AST) -> Result<(), Error> {
+    if kinds.get(c, *t) != FormulaKind::Atom || has_ty_bool(c, kinds, t) {
+        return Ok(())
+    }
+    match c.ty(t) {
+        Some(ty) => Err(Error::Sort{
+            expected: "Bool".to_string(), found: pp_ast(c, &ty).to_string(),
+            term: pp_ast(c, t).to_string(),
+        }),
+        None => Ok(()), // not checked
+    }
+}
+
+// number of subterm occurrences in the tree expansion of `t`, memoized in `sizes`
+fn tree_size<C:Ctx>(
+    m: &C, sizes: &mut AstDenseMap<u64>, st: &mut Vec<(AST,bool)>, t: AST
+) -> u64 {
+    st.clear();
+    st.push((t, false));
+    // postfix traversal: `(u, true)` once the subterms of `u` are sized
+    while let Some((u, expanded)) = st.pop() {
+        if sizes.contains(&u) { continue }
+        match m.view(&u) {
+            AstView::Const(_) | AstView::Index(..) => sizes.insert(u, 1),
+            AstView::App{f, args} if expanded => {
+                let n = args.iter().chain(std::iter::once(f))
+                    .fold(1u64, |n, v| n.saturating_add(*sizes.get(v).unwrap()));
+                sizes.insert(u, n)
+            },
+            AstView::App{f, args} => {
+                st.push((u, true));
+                st.push((*f, false));
+                st.extend(args.iter().map(|v| (*v, false)));
+            },
+        }
+    }
+    *sizes.get(&t).unwrap()
+}
+
+/// Top-level units of a batch, see `Tseitin::clauses_many`.
+///
+/// Classes of terms asserted to be equal, as a union-find. Boolean
+/// atoms are in the class of `true` or `false`, which are always
+/// representatives of their class.
+#[derive(Clone)]
+struct Units {
+    parent: FxHashMap<AST, AST>,
+    lits: Vec<AST>, // the units, still asserted as is
+    unsat: bool, // some class contains both `true` and `false`
+}
+
+impl Units {
+    fn new() -> Self { Units { parent: FxHashMap::default(), lits: vec!(), unsat: false } }
+
+    fn clear(&mut self) {
+        self.parent.clear();
+        self.lits.clear();
+        self.unsat = false;
+    }
+
+    fn find(&self, mut t: AST) -> AST {
+        while let Some(u) = self.parent.get(&t) { t = *u }
+        t
+    }
+
+    // merge the classes of `t` and `u`; the representative of `u` is kept,
+    // unless the one of `t` is a boolean constant
+    fn union<C:Ctx>(&mut self, m: &C, kinds: &mut KindCache, t: AST, u: AST) {
+        let (rt, ru) = (self.find(t), self.find(u));
+        if rt == ru { return }
+        let mut is_bool = |r| match kinds.get(m, r) { FormulaKind::Bool(_) => true, _ => false };
+        match (is_bool(rt), is_bool(ru)) {
+            (true, true) => self.unsat = true,
+            (true, false) => { self.parent.insert(ru, rt); },
+            (false, _) => { self.parent.insert(rt, ru); },
+        }
+    }
+
+    // collect the units of the assertion `t`, looking through conjunctions
+    fn collect<C:Ctx>(&mut self, m: &C, kinds: &mut KindCache, t: AST, b: [AST; 2]) {
+        let is_atom = |kinds: &mut KindCache, u: AST| {
+            kinds.get(m, u) == FormulaKind::Atom && m.is_const(&u) && has_ty_bool(m, kinds, &u)
+        };
+        match m.view_as_formula(t) {
+            View::Bool(false) => self.unsat = true,
+            View::And(args) => {
+                for &u in args.iter() { self.collect(m, kinds, u, b) }
+            },
+            View::Atom(u) if is_atom(kinds, u) => {
+                self.lits.push(t);
+                self.union(m, kinds, u, b[1]);
+            },
+            View::Not(u) if is_atom(kinds, u) => {
+                self.lits.push(t);
+                self.union(m, kinds, u, b[0]);
+            },
+            View::Eq(u1, u2) if m.is_const(&u1) && m.is_const(&u2) && m.ty(&u1) == m.ty(&u2) => {
+                self.lits.push(t);
+                self.union(m, kinds, u1, u2);
+            },
+            _ => (),
+        }
+    }
+}
+
+// `lit(b) => b=true` and `¬lit(b) => b=false`, for each connective `b`
+// of `args` (the arguments of an application, or of an equation).
+//
+// The value of a connective is only known to the SAT solver: without
+// these clauses, the congruence closure sees `b` as an opaque argument,
+// and cannot tell that `f(and(p,q))` and `f(r)` are equal when `p∧q` and `r` are.
+// Atoms and equations need none, the congruence closure gets their value.
+// A boolean `ite` is a theory literal, but nothing assigns it unless
+// it is also used as a formula.
+fn connect_bool_args<C, LM>(
+    lmb: &mut LitMapB<C, LM>, cs: &mut TheoryClauseSet<C>,
+    lits: &mut hash::IndexSet<TheoryLit<C>>, done: &mut hash::HashSet<AST>, args: &[AST],
+) where C: Ctx, LM: LitMap<C::B> {
+    for &b in args {
+        let is_ite = match lmb.kinds.get(lmb.m, b) {
+            FormulaKind::And | FormulaKind::Or | FormulaKind::Imply => false,
+            FormulaKind::Ite if has_ty_bool(lmb.m, lmb.kinds, &b) => true,
+            _ => continue,
+        };
+        if !done.insert(b) { continue }
+        let lit = lmb.term_to_lit(&b);
+        if is_ite {
+            lits.insert(lit);
+        }
+        let true_ = lmb.m.mk_formula(View::Bool(true));
+        let false_ = lmb.m.mk_formula(View::Bool(false));
+        let b_true = lmb.m.mk_formula(View::Eq(b, true_));
+        let b_false = lmb.m.mk_formula(View::Eq(b, false_));
+        let (lit_true, lit_false) = (TheoryLit::new_t(b_true, true), TheoryLit::new_t(b_false, true));
+        lits.insert(lit_true);
+        lits.insert(lit_false);
+        cs.push(&[!lit, lit_true]);
+        cs.push(&[lit, lit_false]);
+    }
+}
+
+// the arguments of the connective `u` are used as formulas
+fn check_args<C:Ctx>(c: &C, kinds: &mut KindCache, u: &AST) -> Result<(), Error> {
+    match c.view_as_formula(*u) {
+        View::Not(a) | View::Ite(a,_,_) => check_formula(c, kinds, &a),
+        View::And(args) | View::Or(args) | View::Imply(args) => {
+            args.iter().try_for_each(|a| check_formula(c, kinds, a))
+        },
+        _ => Ok(()),
+    }
+}
+
+impl<C> Tseitin<C> where C: theory::Ctx {
     /// Create a new Tseitin transformation
     pub fn new() -> Self {
         Self {
             tmp: Vec::new(),
             tmp2: Vec::new(),
             tmp_ast: vec!(),
-            lits: FxHashSet::default(),
-            iter: ast::iter_dag::new(),
-            simp_map: ast::HashMap::new(),
+            lits: hash::IndexSet::new(),
+            bool_args: hash::HashSet::default(),
+            iter: ast::iter_dag::new_with(ast_u32::EpochSet::new()),
+            check_iter: ast::iter_dag::new_with(ast_u32::EpochSet::new()),
+            simp_map: SimpCache::new(),
             cs: TheoryClauseSet::new(),
+            kinds: KindCache::new(),
+            sizes: AstDenseMap::new(),
+            tmp_stack: vec!(),
+            spare: vec!(),
+            max_new_nodes: DEFAULT_MAX_NEW_NODES,
+            simplify_fixpoint: false,
+            simplify_time_limit: None,
+            cancel: None,
+            interrupted: false,
+            #[cfg(feature="simp-trace")]
+            simp_trace: None,
+            propagate_units: false,
+            definition_order: DefinitionOrder::default(),
+            units: Units::new(),
+            subst: ast::HashMap::new(),
+            algo: AlgoCache::new(),
+            stats: Stats::default(),
         }
     }
 
+    /// Give back a batch obtained from `clauses_owned`, to reuse its storage.
+    pub fn recycle(&mut self, mut batch: ClauseBatch<C>) {
+        batch.cs.clear();
+        batch.lits.clear();
+        batch.origin = None;
+        self.spare.push(batch);
+    }
+
+    /// Interrupt `simplify` once `tok` is canceled, like its time limit does.
+    pub fn set_cancel_token(&mut self, tok: CancelToken) {
+        self.cancel = Some(tok);
+    }
+}
+
+impl<C> Tseitin<C> where C: Ctx {
     /// Clear internal caches.
     ///
     /// This means that formulas already defined in previous calls to
     /// `self.clauses(t)` will be re-defined if we meet them again.
+    /// This is cheap: the set of visited terms is cleared by starting a new epoch.
     pub fn clear(&mut self) {
         self.iter.clear();
+        self.bool_args.clear();
+    }
+
+    /// Forget every term, after the manager collected some of them.
+    ///
+    /// The IDs of collected terms are reused by new terms, so the caches
+    /// keyed by terms (kinds, `simplify`, the defined subterms, etc.) would
+    /// give wrong answers for them. Configuration and statistics are kept.
+    /// Must be called every time the manager collects terms.
+    pub fn on_gc(&mut self) {
+        self.clear();
+        self.check_iter.clear();
+        self.kinds.0.clear();
+        self.sizes.clear();
+        self.simp_map.map.clear();
+        self.cs.clear();
+        self.lits.clear();
+        self.units.clear();
+        self.subst.clear();
+    }
+
+    /// Bound the number of nodes built by a single call to `simplify`.
+    ///
+    /// Once the budget is exhausted, remaining subterms are kept as is;
+    /// the result is still equivalent to the input, but only partially
+    /// simplified. Defaults to `DEFAULT_MAX_NEW_NODES`.
+    pub fn set_max_new_nodes(&mut self, n: usize) {
+        self.max_new_nodes = n;
+    }
+
+    /// Bound the number of results of `simplify` kept in cache.
+    ///
+    /// When the bound is exceeded, the least recently used results are
+    /// evicted, and will be recomputed if needed. By default the cache
+    /// is not bounded.
+    pub fn set_simplify_cache_cap(&mut self, n: usize) {
+        self.simp_map.set_cap(Some(n));
+        self.stats.simplify_evicted = self.simp_map.evicted;
+    }
+
+    /// Make `simplify` run again on its own result, until nothing changes.
+    ///
+    /// A single bottom-up pass can miss rewrites enabled by the simplification
+    /// of arguments, e.g. `or(and(a, not a), b)` becomes `or(false, b)`, not `b`.
+    /// In fixpoint mode, `simplify` stops after `MAX_SIMPLIFY_PASSES` passes,
+    /// or once the budget of `set_max_new_nodes` (shared by all the passes)
+    /// is exhausted. Disabled by default.
+    pub fn set_simplify_fixpoint(&mut self, b: bool) {
+        self.simplify_fixpoint = b;
+    }
+
+    /// Bound the time spent in a single call to `simplify`.
+    ///
+    /// The limit is checked periodically (see `SIMPLIFY_CHECK_PERIOD`); once it
+    /// is reached, `simplify` stops rewriting and returns the partially
+    /// simplified term, as with `set_max_new_nodes`. `None`, the default,
+    /// removes the limit.
+    pub fn set_simplify_time_limit(&mut self, d: Option<Duration>) {
+        self.simplify_time_limit = d;
     }
 
+    /// Was the last call to `simplify` stopped by its time limit or cancel token?
+    ///
+    /// Its result is still equivalent to its input, like any partial result.
+    pub fn simplify_interrupted(&self) -> bool { self.interrupted }
+
+    /// Record the rewrites done by `simplify` into a trace, see `simp_trace`.
+    ///
+    /// Enabling it empties the cache of `simplify`, so that the rewrites
+    /// of subterms are in the trace before the rewrites that use them.
+    /// Disabling it drops the trace. Disabled by default.
+    #[cfg(feature="simp-trace")]
+    pub fn set_simp_trace(&mut self, b: bool) {
+        if b && self.simp_trace.is_none() {
+            self.simp_map.map.clear();
+            self.simp_trace = Some(simp_trace::Trace::new());
+        } else if !b {
+            self.simp_trace = None;
+        }
+    }
+
+    /// Rewrites done by `simplify` since `set_simp_trace(true)`.
+    #[cfg(feature="simp-trace")]
+    pub fn simp_trace(&self) -> Option<&simp_trace::Trace> { self.simp_trace.as_ref() }
+
+    /// Propagate the top-level units of a batch in `clauses_many`.
+    ///
+    /// Disabled by default.
+    pub fn set_propagate_units(&mut self, b: bool) {
+        self.propagate_units = b;
+    }
+
+    /// Choose the order in which subterms are defined, see `DefinitionOrder`.
+    ///
+    /// Subterms defined by earlier calls are not defined again.
+    pub fn set_definition_order(&mut self, o: DefinitionOrder<C>) {
+        self.definition_order = o;
+    }
+
+    /// Number of results of `simplify` currently in cache.
+    pub fn simplify_cache_len(&self) -> usize { self.simp_map.map.len() }
+
+    /// Statistics.
+    pub fn stats(&self) -> &Stats { &self.stats }
+
     /// Simplify boolean expressions.
     pub fn simplify(&mut self, m: &mut C, t: AST) -> AST {
-        let mut simp = SimpStruct{m, map: &mut self.simp_map};
-        let u = simp.simplify_rec(t);
+        let mut simp = SimpStruct{
+            m, map: &mut self.simp_map, kinds: &mut self.kinds,
+            n_new: 0, max_new_nodes: self.max_new_nodes, partial: false,
+            cancel: self.cancel.as_ref(),
+            deadline: self.simplify_time_limit.map(|d| Instant::now() + d),
+            n_inserts: 0, interrupted: false,
+            rule: SimpRule::AppArgs,
+            #[cfg(feature="simp-trace")]
+            trace: self.simp_trace.as_mut(),
+        };
+        let u1 = simp.simplify_rec(t);
+        let mut u = u1;
+        let mut n_passes = 1;
+        if self.simplify_fixpoint {
+            while !simp.partial {
+                if n_passes == MAX_SIMPLIFY_PASSES {
+                    debug!("tseitin.simplify: no fixpoint after {} passes", n_passes);
+                    break
+                }
+                let u2 = simp.simplify_rec(u);
+                n_passes += 1;
+                if u2 == u { break }
+                u = u2;
+            }
+            // next time, get the final result directly
+            if !simp.partial {
+                simp.map.insert(t, u);
+                if u != u1 { simp.record(SimpRule::Fixpoint, t, u) }
+            }
+        }
+        let (n_new, partial, interrupted) = (simp.n_new, simp.partial, simp.interrupted);
+        self.interrupted = interrupted;
+        self.stats.simplify += 1;
+        self.stats.simplify_extra_passes += (n_passes - 1) as u64;
+        self.stats.new_nodes += n_new as u64;
+        self.stats.simplify_evicted = self.simp_map.evicted;
+        if partial {
+            debug!("tseitin.simplify: out of budget after {} new nodes", n_new);
+            self.stats.partial_simplify += 1;
+        }
+        if interrupted {
+            self.stats.interrupted_simplify += 1;
+        }
         if t != u {
             debug!("tseitin.simplify\nfrom {}\nto {}", pp_ast(m,&t), pp_ast(m,&u));
         }
@@ -319,63 +1225,166 @@ impl<C> Tseitin<C> where C: Ctx {
     /// `tseitin.clauses(t)` turns the boolean term `t` into a set of clauses and literals.
     ///
     /// The clauses define boolean connectives occurring inside `t`.
+    /// If `t` simplifies to `true`, there are no clauses at all; if it
+    /// simplifies to `false`, the only clause is the empty clause.
+    ///
+    /// The literals are those of all the clauses produced so far, in the
+    /// order in which they first appeared: for each formula, the order in
+    /// which its simplified form is traversed (see `DefinitionOrder`).
+    /// This order does not depend on hashing.
     /// ## params
     /// - `t` is the formula to normalize
+    ///
+    /// Panics if `t` cannot be turned into clauses, see `try_clauses`.
     pub fn clauses<LM>(
         &mut self, m: &mut C, lit_map: &mut LM, t: AST
     ) -> (impl Iterator<Item=TheoryClauseRef<C>>, impl Iterator<Item=&TheoryLit<C>>)
         where LM: LitMap<C::B>
+    {
+        if let Err(e) = self.encode(m, lit_map, t, false) {
+            panic!("tseitin.clauses: {}", e)
+        }
+        (self.cs.iter(), self.lits.iter())
+    }
+
+    /// Check that `t` can be used as a formula, without encoding it.
+    ///
+    /// `try_clauses` fails with the same error on `t`, if any.
+    pub fn check_formula(&mut self, m: &C, t: &AST) -> Result<(), Error> {
+        let Tseitin{check_iter, kinds, ..} = self;
+        check_formula(m, kinds, t)?;
+        let mut res = Ok(());
+        check_iter.clear();
+        check_iter.iter(m, t, |m, u| {
+            if res.is_ok() {
+                res = check_args(m, kinds, u);
+            }
+        });
+        res
+    }
+
+    /// Same as `clauses`, but fails on ill-formed formulas.
+    ///
+    /// Fails with `Error::Sort` if a non-boolean term is used as a formula,
+    /// and with `Error::ResourceOut` if `simplify` ran out of budget before
+    /// removing all the `distinct`.
+    pub fn try_clauses<LM>(
+        &mut self, m: &mut C, lit_map: &mut LM, t: AST
+    ) -> Result<(impl Iterator<Item=TheoryClauseRef<C>>, impl Iterator<Item=&TheoryLit<C>>), Error>
+        where LM: LitMap<C::B>
+    {
+        self.encode(m, lit_map, t, false)?;
+        Ok((self.cs.iter(), self.lits.iter()))
+    }
+
+    /// Same as `try_clauses`, for a term whose sort was already checked.
+    ///
+    /// Only the subterms of `t` are checked; it fails like `try_clauses` on them.
+    pub fn clauses_bool<LM>(
+        &mut self, m: &mut C, lit_map: &mut LM, t: BoolTerm
+    ) -> Result<(impl Iterator<Item=TheoryClauseRef<C>>, impl Iterator<Item=&TheoryLit<C>>), Error>
+        where LM: LitMap<C::B>
+    {
+        self.encode(m, lit_map, t.term(), true)?;
+        Ok((self.cs.iter(), self.lits.iter()))
+    }
+
+    // fill `self.cs` with the clauses of `t`, and `self.lits` with its literals.
+    // If `checked`, `t` is known to be a formula.
+    fn encode<LM>(&mut self, m: &mut C, lit_map: &mut LM, t: AST, checked: bool) -> Result<(), Error>
+        where LM: LitMap<C::B>
     {
         // first, simplify to flatten connectives and remove `distinct`
         let t = self.simplify(m, t);
 
-        let Tseitin { tmp_ast: args, cs, lits: all_lits, tmp, tmp2, ..} = self;
+        let Tseitin { tmp_ast: args, cs, lits: all_lits, tmp, tmp2, kinds, bool_args, ..} = self;
         cs.clear();
+        match kinds.get(m, t) {
+            FormulaKind::Bool(true) => return Ok(()), // trivially true, no clause
+            FormulaKind::Bool(false) => {
+                cs.push::<TheoryLit<C>>(&[]); // the empty clause
+                self.stats.clauses += 1;
+                return Ok(())
+            },
+            _ => (),
+        }
+        if !checked {
+            check_formula(m, kinds, &t)?;
+        }
+        let mut err = None;
+        let (mut n_encoded, mut n_def_lits, mut n_atoms) = (0, 0, 0);
 
         // traverse `t` as a DAG
-        self.iter.iter_mut(m, &t, |m, u| {
+        let visit = |m: &mut C, u: &AST| {
             // `u` is a subterm that has never been processed.
-            let view_u = m.view_as_formula(*u);
+            n_encoded += 1;
             args.clear();
             tmp.clear();
-            match view_u {
-                View::TyBool => (),
-                View::Atom(_) => {
-                    drop(view_u);
-                    if has_ty_bool(m, u) {
+            // leaves do not need a full view
+            match kinds.get(m, *u) {
+                FormulaKind::TyBool => return,
+                FormulaKind::Atom => {
+                    if has_ty_bool(m, kinds, u) {
                         // map to a literal
                         let lit = TheoryLit::new_t(*u, true);
                         all_lits.insert(lit);
+                        n_atoms += 1;
                     }
-                }, 
-                View::Not(a) => {
-                    let lit = TheoryLit::new_t(a, true);
-                    all_lits.insert(lit);
+                    if let AstView::App{args: args2, ..} = m.view(u) {
+                        args.extend_from_slice(args2);
+                        let mut lmb = LitMapB{lit_map, m, kinds};
+                        connect_bool_args(&mut lmb, cs, all_lits, bool_args, args);
+                    }
+                    return
                 },
-                View::Eq(..) => {
+                FormulaKind::Eq => {
                     let lit = TheoryLit::new_t(*u, true);
                     all_lits.insert(lit);
+                    n_atoms += 1;
+                    if let View::Eq(a, b) = m.view_as_formula(*u) {
+                        args.extend_from_slice(&[a, b]);
+                        let mut lmb = LitMapB{lit_map, m, kinds};
+                        connect_bool_args(&mut lmb, cs, all_lits, bool_args, args);
+                    }
+                    return
                 },
-                View::Bool(true) => {
-                    cs.push(&[TheoryLit::new_b(*u, true)]) // clause [true]
+                FormulaKind::Bool(true) => {
+                    cs.push(&[TheoryLit::new_b(*u, true)]); // clause [true]
+                    return
                 },
-                View::Bool(false) => {
-                    // TODO: is this needed? `u` maps to `not true` anyway?
-                    cs.push(&[TheoryLit::new_b(*u, false)]) // clause [¬false]
+                FormulaKind::Bool(false) => {
+                    // `false` has its own literal, distinct from `¬true`
+                    cs.push(&[TheoryLit::new_b(*u, false)]); // clause [¬false]
+                    return
+                },
+                _ => (),
+            }
+            if err.is_some() { return }
+            if let Err(e) = check_args(m, kinds, u) {
+                err = Some(e);
+                return
+            }
+            let view_u = m.view_as_formula(*u);
+            match view_u {
+                View::TyBool | View::Atom(..) | View::Eq(..) | View::Bool(..) => unreachable!(),
+                View::Not(a) => {
+                    let lit = TheoryLit::new_t(a, true);
+                    all_lits.insert(lit);
                 },
                 View::Ite(a,_,_) => {
-                    let mut lmb = LitMapB{lit_map, m};
+                    let mut lmb = LitMapB{lit_map, m, kinds};
                     let lit = lmb.term_to_lit(&a);
                     all_lits.insert(lit);
                 },
                 View::And(args2) => {
                     args.extend_from_slice(args2);
                     drop(view_u);
-                    let mut lmb = LitMapB{lit_map, m};
+                    let mut lmb = LitMapB{lit_map, m, kinds};
                     for t in args.iter() {
                         tmp.push(lmb.term_to_lit(t));
                     }
                     let lit_and = lmb.term_to_lit(u); // pure bool
+                    n_def_lits += 1;
 
                     // `lit_and => args[i]`
                     for &sub in tmp.iter() {
@@ -394,11 +1403,12 @@ impl<C> Tseitin<C> where C: Ctx {
                 View::Or(args2) => {
                     args.extend_from_slice(args2);
                     drop(view_u);
-                    let mut lmb = LitMapB{lit_map, m};
+                    let mut lmb = LitMapB{lit_map, m, kinds};
                     for t in args.iter() {
                         tmp.push(lmb.term_to_lit(t));
                     }
                     let lit_or = lmb.term_to_lit(u); // pure bool
+                    n_def_lits += 1;
 
                     // `args[i] => lit_or`
                     for &sub in tmp.iter() {
@@ -416,7 +1426,7 @@ impl<C> Tseitin<C> where C: Ctx {
                     // same as `or`, but all literals but the last are negated
                     args.extend_from_slice(args2);
                     drop(view_u);
-                    let mut lmb = LitMapB{lit_map, m};
+                    let mut lmb = LitMapB{lit_map, m, kinds};
                     {
                         let t_last = args[args.len()-1];
                         tmp.push(lmb.term_to_lit(&t_last));
@@ -426,6 +1436,7 @@ impl<C> Tseitin<C> where C: Ctx {
                         tmp.push(! lmb.term_to_lit(t));
                     }
                     let lit_or = lmb.term_to_lit(u); // pure bool
+                    n_def_lits += 1;
                     debug_assert!(lit_or.is_pure_bool());
 
                     // `args[i] => lit_or`
@@ -440,30 +1451,225 @@ impl<C> Tseitin<C> where C: Ctx {
                         cs.push(&tmp2);
                     }
                 },
-                View::Distinct(_) => panic!("distinct post-simplification"),
+                View::Distinct(_) => {
+                    // only left if `simplify` ran out of budget
+                    err = Some(Error::ResourceOut);
+                },
             }
-        });
+        };
+        match &self.definition_order {
+            DefinitionOrder::Traversal(Order::PreOrder) => self.iter.iter_mut(m, &t, visit),
+            DefinitionOrder::Traversal(o) => self.iter.iter_mut_ordered(m, &t, *o, visit),
+            DefinitionOrder::Key(key) => self.iter.iter_mut_by_key(m, &t, |m, u| key(m, u), visit),
+        }
+
+        if let Some(e) = err {
+            debug!("tseitin: cannot encode formula: {}", e);
+            // forget visited subterms, as their clauses are dropped
+            self.iter.clear();
+            self.bool_args.clear();
+            self.cs.clear();
+            return Err(e)
+        }
+        self.stats.encoded_subterms += n_encoded;
+        self.stats.def_lits += n_def_lits;
+        self.stats.atoms += n_atoms;
+        self.stats.subterm_occurrences = self.stats.subterm_occurrences
+            .saturating_add(tree_size(m, &mut self.sizes, &mut self.tmp_stack, t));
 
         {
             // unit clause asserting that `t` is true
-            let mut lmb = LitMapB{lit_map, m};
+            let mut lmb = LitMapB{lit_map, m, kinds};
             let top_lit = lmb.term_to_lit(&t);
             self.cs.push(&[top_lit]);
         }
+        self.stats.clauses += self.cs.len() as u64;
+        Ok(())
+    }
 
-        (self.cs.iter(), self.lits.iter())
+    /// Same as `try_clauses`, but returns an owned batch of clauses.
+    ///
+    /// The clause storage is moved out of the encoder (not copied);
+    /// literals are copied into the batch.
+    pub fn clauses_owned<LM>(
+        &mut self, m: &mut C, lit_map: &mut LM, t: AST
+    ) -> Result<ClauseBatch<C>, Error>
+        where LM: LitMap<C::B>
+    {
+        self.encode(m, lit_map, t, false)?;
+        let mut batch = self.spare.pop().unwrap_or_else(ClauseBatch::new);
+        std::mem::swap(&mut batch.cs, &mut self.cs);
+        batch.lits.extend(self.lits.iter().cloned());
+        Ok(batch)
+    }
+
+    /// Same as `clauses_owned`, for the assertion number `origin`.
+    ///
+    /// All the clauses of the batch come from this assertion: the top-level
+    /// unit clause, and the definitions of the subterms that no previous
+    /// assertion defined (since `clear` was last called). Subterms shared with
+    /// previous assertions are not defined again, so their clauses keep the
+    /// origin of the assertion that first defined them.
+    pub fn clauses_owned_from<LM>(
+        &mut self, m: &mut C, lit_map: &mut LM, t: AST, origin: usize
+    ) -> Result<ClauseBatch<C>, Error>
+        where LM: LitMap<C::B>
+    {
+        let mut batch = self.clauses_owned(m, lit_map, t)?;
+        batch.origin = Some(origin);
+        Ok(batch)
+    }
+
+    /// Encode the conjunction of `ts` into a single batch.
+    ///
+    /// If unit propagation is enabled (see `set_propagate_units`), the
+    /// assertions are first scanned for top-level units: boolean constants,
+    /// their negation, and equalities between constants, possibly under
+    /// conjunctions. Each constant is replaced by the representative of its
+    /// class in the assertions, which are simplified again before being encoded.
+    /// The units themselves are still encoded as is, so that eliminated
+    /// constants get their forced value in models.
+    ///
+    /// If the units are contradictory, or make some assertion `false`, the
+    /// batch is only the empty clause and nothing is encoded.
+    pub fn clauses_many<LM>(
+        &mut self, m: &mut C, lit_map: &mut LM, ts: &[AST]
+    ) -> Result<ClauseBatch<C>, Error>
+        where LM: LitMap<C::B>
+    {
+        let mut batch = self.spare.pop().unwrap_or_else(ClauseBatch::new);
+        let mut ts: Vec<AST> = ts.to_vec();
+        if self.propagate_units && !self.propagate(m, &mut ts) {
+            debug!("tseitin.clauses-many: unsat by unit propagation");
+            self.stats.unsat_batches += 1;
+            batch.cs.push::<TheoryLit<C>>(&[]);
+            return Ok(batch)
+        }
+
+        for &t in ts.iter() {
+            if let Err(e) = self.encode(m, lit_map, t, false) {
+                self.recycle(batch);
+                return Err(e)
+            }
+            for c in self.cs.iter() {
+                batch.cs.push(&c);
+            }
+        }
+        batch.lits.extend(self.lits.iter().cloned());
+        Ok(batch)
+    }
+
+    // substitute the units of `ts` into `ts`, and add the units at the end.
+    // Returns `false` if `ts` is found unsat.
+    fn propagate(&mut self, m: &mut C, ts: &mut Vec<AST>) -> bool {
+        let b = [m.mk_formula(View::Bool(false)), m.mk_formula(View::Bool(true))];
+        let Tseitin{units, kinds, subst, ..} = self;
+        units.clear();
+        for &t in ts.iter() {
+            units.collect(m, kinds, t, b);
+        }
+        if units.unsat { return false }
+        if units.lits.is_empty() { return true }
+
+        subst.clear();
+        for &t in units.parent.keys() {
+            subst.insert(t, units.find(t));
+        }
+        self.stats.units += units.lits.len() as u64;
+        trace!("tseitin.propagate: {} unit(s), {} constant(s) replaced",
+            units.lits.len(), subst.len());
+
+        for t in ts.iter_mut() {
+            let u = self.algo.substitute(m, t, &self.subst);
+            *t = self.simplify(m, u);
+            if self.kinds.get(m, *t) == FormulaKind::Bool(false) {
+                return false
+            }
+        }
+        // the units now simplify to `true`, assert them as is
+        ts.retain(|t| *t != b[1]);
+        ts.extend_from_slice(&self.units.lits);
+        true
     }
+}
+
+impl<C:theory::Ctx> ClauseBatch<C> {
+    fn new() -> Self {
+        ClauseBatch { cs: TheoryClauseSet::new(), lits: vec!(), origin: None }
+    }
+
+    /// Iterate over the clauses.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item=TheoryClauseRef<'a,C>> { self.cs.iter() }
+
+    /// Literals to declare to the SAT solver.
+    pub fn lits(&self) -> &[TheoryLit<C>] { &self.lits }
+
+    /// Number of clauses.
+    pub fn len(&self) -> usize { self.cs.len() }
 
+    /// Index of the assertion these clauses come from, if known.
+    ///
+    /// See `Tseitin::clauses_owned_from`.
+    pub fn origin(&self) -> Option<usize> { self.origin }
+}
+
+impl<C:Ctx> IntoIterator for ClauseBatch<C> {
+    type Item = Vec<TheoryLit<C>>;
+    type IntoIter = ClauseSetIntoIter<C>;
+    fn into_iter(self) -> Self::IntoIter { self.cs.into_iter() }
+}
+
+/// Caches, clauses (including recycled batches) and temporary buffers.
+impl<C> ReportMemory for Tseitin<C> where C: theory::Ctx {
+    fn memory_breakdown(&self, out: &mut MemTree) {
+        {
+            let out = out.child("caches");
+            out.leaf("simplify", mem::hashmap_bytes(&self.simp_map.map));
+            out.add("kinds", &self.kinds.0);
+            out.add("sizes", &self.sizes);
+            out.add("iter", &self.iter);
+            out.add("check-iter", &self.check_iter);
+            out.add("algo", &self.algo);
+            out.add("subst", &self.subst);
+            out.leaf("units", mem::hashmap_bytes(&self.units.parent) + mem::vec_bytes(&self.units.lits));
+        }
+        {
+            let out = out.child("clauses");
+            out.add("current", &self.cs);
+            out.add("lits", &self.lits);
+            out.leaf("bool-args", mem::hashset_bytes(&self.bool_args));
+            let spare: usize = self.spare.iter()
+                .map(|b| mem::report("", &b.cs).total() + mem::vec_bytes(&b.lits))
+                .sum();
+            out.leaf("spare", mem::vec_bytes(&self.spare) + spare);
+        }
+        out.leaf("buffers",
+            mem::vec_bytes(&self.tmp) + mem::vec_bytes(&self.tmp2)
+            + mem::vec_bytes(&self.tmp_ast) + mem::vec_bytes(&self.tmp_stack));
+    }
 }
 
-impl<C> gc::HasInternalMemory for Tseitin<C> where C: Ctx {
+impl<C> gc::HasInternalMemory for Tseitin<C> where C: theory::Ctx {
     fn reclaim_unused_memory(&mut self) {
         self.tmp.shrink_to_fit();
         self.tmp2.shrink_to_fit();
         self.tmp_ast.shrink_to_fit();
+        // the clauses of the last formula, and the literals so far, are kept
         self.cs.reclaim_unused_memory();
+        self.lits.reclaim_unused_memory();
         self.iter.reclaim_unused_memory();
-        self.simp_map.reclaim_unused_memory();
+        self.check_iter.reclaim_unused_memory();
+        self.simp_map.map.shrink_to_fit();
+        self.spare.clear();
+        // caches keyed by terms are kept, only shrunk. They are only stale
+        // once the manager collects terms, see `on_gc`.
+        self.kinds.0.reclaim_unused_memory();
+        self.bool_args.shrink_to_fit();
+        self.sizes.reclaim_unused_memory();
+        self.tmp_stack.shrink_to_fit();
+        self.units.clear();
+        self.subst.clear();
+        self.subst.reclaim_unused_memory();
     }
 }
 
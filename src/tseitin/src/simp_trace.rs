@@ -0,0 +1,368 @@
+
+//! Trace of the rewrites done by `Tseitin::simplify`, and its checker.
+//!
+//! Each step `from --> to` is tagged with the `SimpRule` that justifies it.
+//! Steps that rebuild a term (flattening, simplified arguments…) are checked
+//! structurally, boolean shortcuts by evaluating both sides under every
+//! assignment of their atoms. Either way, a step can rely on the steps
+//! before it: an argument `a` can be replaced by what `a` was rewritten into.
+
+use {
+    std::fmt,
+    fxhash::FxHashMap,
+    batsmt_core::{ast_u32::AST, AstView},
+    batsmt_pretty as pp,
+    batsmt_theory::pp_ast,
+    crate::{Ctx, View, SimpRule, MAX_SIMPLIFY_PASSES},
+};
+
+/// Steps with more atoms than this are not checked by evaluation.
+pub const MAX_ORACLE_ATOMS: usize = 12;
+
+/// Evaluation gives up on steps with more nodes than this (counted with repetitions).
+const MAX_ORACLE_NODES: usize = 100_000;
+
+/// One rewrite of `simplify`.
+#[derive(Copy,Clone,Debug,Eq,PartialEq)]
+pub struct Step {
+    pub rule: SimpRule,
+    pub from: AST,
+    pub to: AST,
+}
+
+/// Rewrites of `simplify`, in the order in which they were done.
+#[derive(Clone,Debug,Default)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+}
+
+/// A step of a trace that could not be justified.
+#[derive(Clone,Debug)]
+pub struct TraceError {
+    /// Index of the step in `Trace::steps`.
+    pub step: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "invalid simplification step {}: {}", self.step, self.msg)
+    }
+}
+
+impl Trace {
+    /// New empty trace.
+    pub fn new() -> Self { Trace{ steps: vec!() } }
+
+    pub(crate) fn push(&mut self, rule: SimpRule, from: AST, to: AST) {
+        self.steps.push(Step{rule, from, to})
+    }
+
+    /// Number of steps.
+    pub fn len(&self) -> usize { self.steps.len() }
+
+    pub fn is_empty(&self) -> bool { self.steps.is_empty() }
+
+    /// Check each step in order, assuming the previous ones.
+    ///
+    /// Returns the number of steps that were not checked, because
+    /// they have more than `MAX_ORACLE_ATOMS` atoms.
+    pub fn check<C:Ctx>(&self, m: &mut C) -> Result<usize, TraceError> {
+        let mut ch = Checker{ rw: FxHashMap::default(), n_unchecked: 0 };
+        for (i, st) in self.steps.iter().enumerate() {
+            if let Err(msg) = ch.check_step(m, st) {
+                let msg = format!("{} {} --> {}: {}",
+                                  st.rule, pp_ast(m, &st.from), pp_ast(m, &st.to), msg);
+                return Err(TraceError{step: i, msg})
+            }
+            ch.rw.insert(st.from, st.to);
+        }
+        Ok(ch.n_unchecked)
+    }
+}
+
+struct Checker {
+    rw: FxHashMap<AST, AST>, // latest rewrite of each term
+    n_unchecked: usize,
+}
+
+fn expect(b: bool, msg: &str) -> Result<(), String> {
+    if b { Ok(()) } else { Err(msg.to_string()) }
+}
+
+fn bool_of<C:Ctx>(m: &C, t: AST) -> Option<bool> {
+    match m.view_as_formula(t) { View::Bool(b) => Some(b), _ => None }
+}
+
+fn not_of<C:Ctx>(m: &C, t: AST) -> Option<AST> {
+    match m.view_as_formula(t) { View::Not(u) => Some(u), _ => None }
+}
+
+fn eq_of<C:Ctx>(m: &C, t: AST) -> Option<(AST,AST)> {
+    match m.view_as_formula(t) { View::Eq(a, b) => Some((a, b)), _ => None }
+}
+
+fn ite_of<C:Ctx>(m: &C, t: AST) -> Option<(AST,AST,AST)> {
+    match m.view_as_formula(t) { View::Ite(a, b, c) => Some((a, b, c)), _ => None }
+}
+
+/// Arguments of `t`, if it is a conjunction (resp. disjunction).
+fn conn_args<C:Ctx>(m: &C, and: bool, t: AST) -> Option<Vec<AST>> {
+    match m.view_as_formula(t) {
+        View::And(args) if and => Some(args.to_vec()),
+        View::Or(args) if !and => Some(args.to_vec()),
+        _ => None,
+    }
+}
+
+/// Same as `flatten_conn_rec`, without sorting.
+fn flatten<C:Ctx>(m: &C, and: bool, args: &[AST], out: &mut Vec<AST>) {
+    for &t in args {
+        match m.view_as_formula(t) {
+            View::And(args2) if and => flatten(m, and, args2, out),
+            View::Or(args2) if !and => flatten(m, and, args2, out),
+            View::Bool(b) if b == and => (), // neutral element
+            _ => out.push(t),
+        }
+    }
+}
+
+impl Checker {
+    /// `t` itself, or what it was rewritten into.
+    fn cands(&self, t: AST) -> impl Iterator<Item=AST> {
+        std::iter::once(t).chain(self.rw.get(&t).cloned())
+    }
+
+    /// Can `t` be replaced by `u`?
+    fn ok(&self, t: AST, u: AST) -> bool { self.cands(t).any(|t| t == u) }
+
+    fn all_ok(&self, ts: &[AST], us: &[AST]) -> bool {
+        ts.len() == us.len() && ts.iter().zip(us.iter()).all(|(t,u)| self.ok(*t, *u))
+    }
+
+    /// Is `and(us)` the same as `and(ts)`, up to replacements and duplicates?
+    fn covers(&self, ts: &[AST], us: &[AST]) -> bool {
+        ts.iter().all(|t| self.cands(*t).any(|t| us.contains(&t))) &&
+            us.iter().all(|u| ts.iter().any(|t| self.ok(*t, *u)))
+    }
+
+    /// `not (a=b)`, built from the candidates for `a` and `b`, as `simplify` does.
+    fn mk_diseqs<C:Ctx>(&self, m: &mut C, a: AST, b: AST) -> Vec<AST> {
+        let mut res = vec!();
+        for a in self.cands(a) {
+            for b in self.cands(b) {
+                let eq = m.mk_formula(View::Eq(a, b));
+                res.push(m.mk_formula(View::Not(eq)));
+            }
+        }
+        res
+    }
+
+    fn check_step<C:Ctx>(&mut self, m: &mut C, st: &Step) -> Result<(), String> {
+        use SimpRule::*;
+        let (t, u) = (st.from, st.to);
+        match st.rule {
+            AppArgs => match (m.view(&t), m.view(&u)) {
+                (AstView::App{f: f1, args: args1}, AstView::App{f: f2, args: args2}) => {
+                    expect(self.ok(*f1, *f2) && self.all_ok(args1, args2) && m.ty(&t) == m.ty(&u),
+                           "arguments do not match")
+                },
+                _ => Err("expected applications".to_string()),
+            },
+            DistinctUnit => match m.view_as_formula(t) {
+                View::Distinct(args) if args.len() == 1 =>
+                    expect(bool_of(m, u) == Some(true), "expected `true`"),
+                _ => Err("expected a unary `distinct`".to_string()),
+            },
+            DistinctPair | DistinctExpand => {
+                let args = match m.view_as_formula(t) {
+                    View::Distinct(args) if args.len() == 2 && st.rule == DistinctPair => args.to_vec(),
+                    View::Distinct(args) if args.len() > 2 && st.rule == DistinctExpand => args.to_vec(),
+                    _ => return Err("wrong number of arguments".to_string()),
+                };
+                let diseqs = if st.rule == DistinctPair {
+                    vec![u]
+                } else {
+                    conn_args(m, true, u).ok_or_else(|| "expected a conjunction".to_string())?
+                };
+                // the ways `simplify` could have built `a_i != a_j`
+                let n = args.len();
+                let mut found = vec!();
+                for i in 0 .. n {
+                    for j in i+1 .. n {
+                        let built = self.mk_diseqs(m, args[i], args[j]);
+                        match diseqs.iter().find(|d| built.contains(d)) {
+                            Some(d) => found.push(*d),
+                            None => return Err(format!("missing disequation for arguments {} and {}", i, j)),
+                        }
+                    }
+                }
+                expect(diseqs.iter().all(|d| found.contains(d)), "extra disequation")
+            },
+            EqRefl => match eq_of(m, t) {
+                Some((a, b)) if a == b => expect(bool_of(m, u) == Some(true), "expected `true`"),
+                _ => Err("expected `a=a`".to_string()),
+            },
+            NotBool => match (not_of(m, t), bool_of(m, u)) {
+                (Some(a), Some(b)) =>
+                    expect(self.cands(a).any(|a| bool_of(m, a) == Some(!b)), "wrong negation"),
+                _ => Err("expected a negation and a boolean".to_string()),
+            },
+            NotNot => match not_of(m, t) {
+                Some(a) => expect(self.cands(a).any(|a| not_of(m, a) == Some(u)), "not a double negation"),
+                None => Err("expected a negation".to_string()),
+            },
+            NotArg => match (not_of(m, t), not_of(m, u)) {
+                (Some(a), Some(b)) => expect(self.ok(a, b), "arguments do not match"),
+                _ => Err("expected negations".to_string()),
+            },
+            FlattenAnd | FlattenOr => {
+                let and = st.rule == FlattenAnd;
+                let args = conn_args(m, and, t).ok_or_else(|| "wrong connective".to_string())?;
+                let mut flat = vec!();
+                flatten(m, and, &args, &mut flat);
+                // `and(a)` can be built as `a`
+                let ok = conn_args(m, and, u).map_or(false, |us| self.covers(&flat, &us))
+                    || self.covers(&flat, &[u]);
+                expect(ok, "arguments do not match")
+            },
+            AndFalse | AndComplementary | AndTrue | OrTrue | OrComplementary | OrFalse => {
+                self.check_by_eval(m, t, u)
+            },
+            ImplyToOr => {
+                let args = match m.view_as_formula(t) {
+                    View::Imply(args) if args.len() >= 2 => args.to_vec(),
+                    _ => return Err("expected an implication".to_string()),
+                };
+                // rebuild the disjunction, as `simplify` did
+                let n = args.len();
+                let mut disj = args;
+                for a in disj[.. n-1].iter_mut() {
+                    *a = m.mk_formula(View::Not(*a))
+                }
+                let v = m.mk_formula(View::Or(&disj));
+                expect(self.ok(v, u), "not the disjunction")
+            },
+            FoldIte => match ite_of(m, t) {
+                Some((a, b, c)) => {
+                    let ok = self.cands(a).any(|a| match bool_of(m, a) {
+                        Some(true) => self.ok(b, u),
+                        Some(false) => self.ok(c, u),
+                        None => false,
+                    });
+                    expect(ok, "condition is not a boolean, or wrong branch")
+                },
+                None => Err("expected an `ite`".to_string()),
+            },
+            IteSame => match ite_of(m, t) {
+                Some((_, b, c)) => expect(self.ok(b, u) && self.ok(c, u), "branches differ"),
+                None => Err("expected an `ite`".to_string()),
+            },
+            IteArgs => match (ite_of(m, t), ite_of(m, u)) {
+                (Some((a, b, c)), Some((x, y, z))) => {
+                    expect(self.all_ok(&[a, b, c], &[x, y, z]), "arguments do not match")
+                },
+                _ => Err("expected `ite`s".to_string()),
+            },
+            Fixpoint => {
+                // follow the rewrites of the successive passes
+                let mut v = t;
+                for _ in 0 ..= MAX_SIMPLIFY_PASSES {
+                    if v == u { return Ok(()) }
+                    match self.rw.get(&v) {
+                        Some(&w) if w != v => v = w,
+                        _ => break,
+                    }
+                }
+                expect(v == u, "not the result of the passes")
+            },
+        }
+    }
+
+    /// Check that `t` and `u` agree under all the assignments of their atoms.
+    fn check_by_eval<C:Ctx>(&mut self, m: &C, t: AST, u: AST) -> Result<(), String> {
+        let mut ev = Eval{ m, rw: &self.rw, atoms: FxHashMap::default(), bits: 0, n_nodes: 0 };
+        ev.eval(t, 0);
+        ev.eval(u, 0);
+        let n = ev.atoms.len();
+        if n > MAX_ORACLE_ATOMS || ev.n_nodes > MAX_ORACLE_NODES {
+            self.n_unchecked += 1;
+            return Ok(())
+        }
+        for bits in 0 .. (1u64 << n) {
+            ev.bits = bits;
+            let (vt, vu) = (ev.eval(t, 0), ev.eval(u, 0));
+            if vt != vu {
+                return Err(format!("evaluates to {} and {} in some model", vt, vu))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Propositional evaluation, where atoms are numbered the first time they are met.
+struct Eval<'a, C:Ctx> {
+    m: &'a C,
+    rw: &'a FxHashMap<AST, AST>,
+    atoms: FxHashMap<AST, usize>,
+    bits: u64, // value of each atom
+    n_nodes: usize,
+}
+
+impl<'a, C:Ctx> Eval<'a, C> {
+    // `depth=0` for the two sides of the step, which must not be rewritten
+    fn eval(&mut self, t: AST, depth: usize) -> bool {
+        self.n_nodes += 1;
+        if self.n_nodes > MAX_ORACLE_NODES { return false }
+        if depth > 0 {
+            // `t` might have been simplified before being used
+            if let Some(&u) = self.rw.get(&t) {
+                if u != t && depth <= MAX_SIMPLIFY_PASSES { return self.eval(u, depth+1) }
+            }
+        }
+        let d = depth + 1;
+        match self.m.view_as_formula(t) {
+            View::Bool(b) => b,
+            View::Not(a) => !self.eval(a, d),
+            // no shortcut, so that every atom is numbered
+            View::And(args) => args.iter().fold(true, |acc, a| self.eval(*a, d) && acc),
+            View::Or(args) => args.iter().fold(false, |acc, a| self.eval(*a, d) || acc),
+            View::Imply(args) => {
+                let n = args.len();
+                let hyps = args[.. n-1].iter().fold(true, |acc, a| self.eval(*a, d) && acc);
+                let concl = self.eval(args[n-1], d);
+                !hyps || concl
+            },
+            View::Ite(a, b, c) => {
+                let (a, b, c) = (self.eval(a, d), self.eval(b, d), self.eval(c, d));
+                if a { b } else { c }
+            },
+            View::Eq(a, b) if a == b => true,
+            _ => {
+                let n = self.atoms.len();
+                let i = *self.atoms.entry(t).or_insert(n);
+                i < 64 && self.bits & (1 << i) != 0
+            },
+        }
+    }
+}
+
+impl<C:Ctx> pp::Pretty1<C> for Step {
+    fn pp1_into(&self, c: &C, ctx: &mut pp::Ctx) {
+        ctx.sexp(|ctx| {
+            ctx.str(self.rule.name()).space();
+            c.pp_ast(&self.from, ctx);
+            ctx.space();
+            c.pp_ast(&self.to, ctx);
+        });
+    }
+}
+
+/// One step per line.
+impl<C:Ctx> pp::Pretty1<C> for Trace {
+    fn pp1_into(&self, c: &C, ctx: &mut pp::Ctx) {
+        for st in self.steps.iter() {
+            ctx.pp1(st, c).newline();
+        }
+    }
+}
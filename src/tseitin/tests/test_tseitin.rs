@@ -0,0 +1,1168 @@
+
+use {
+    std::cell::RefCell,
+    fxhash::FxHashMap,
+    batsmt_core::{ast::{HasManager, Manager}, AstView, ast_u32::AST},
+    batsmt_hast::*,
+    batsmt_pretty as pp,
+    batsmt_theory::{self as theory, LitMap, LitMapBuiltins, IntLit},
+    batsmt_tseitin::{self as tseitin, Tseitin, View, FormulaKind, BoolTerm},
+};
+
+type M = HManager<StrSymbolManager>;
+
+#[derive(Clone)]
+struct Builtins {
+    bool_: AST,
+    true_: AST,
+    false_: AST,
+    not_: AST,
+    and_: AST,
+    or_: AST,
+    ite: AST,
+    eq: AST,
+    distinct: AST,
+    imply: AST,
+}
+
+/// A context that counts calls to `formula_kind`, per term.
+struct Ctx {
+    m: M,
+    b: Builtins,
+    n_kind: RefCell<FxHashMap<AST, usize>>,
+}
+
+mod ctx {
+    use super::*;
+
+    impl HasManager for Ctx {
+        type M = M;
+        fn m(&self) -> &M { &self.m }
+        fn m_mut(&mut self) -> &mut M { &mut self.m }
+    }
+
+    impl theory::BoolLitCtx for Ctx {
+        type B = IntLit;
+    }
+
+    impl theory::Ctx for Ctx {
+        fn pp_ast(&self, t: &AST, ctx: &mut pp::Ctx) {
+            ctx.pp1(&self.m, t);
+        }
+    }
+
+    impl tseitin::Ctx for Ctx {
+        fn view_as_formula(&self, t: AST) -> View<'_, AST> {
+            let b = &self.b;
+            if t == b.true_ { View::Bool(true) }
+            else if t == b.false_ { View::Bool(false) }
+            else if t == b.bool_ { View::TyBool }
+            else {
+                match self.m.view(&t) {
+                    AstView::App{f, args} if *f == b.not_ => View::Not(args[0]),
+                    AstView::App{f, args} if *f == b.and_ => View::And(args),
+                    AstView::App{f, args} if *f == b.or_ => View::Or(args),
+                    AstView::App{f, args} if *f == b.ite => View::Ite(args[0], args[1], args[2]),
+                    AstView::App{f, args} if *f == b.eq => View::Eq(args[0], args[1]),
+                    AstView::App{f, args} if *f == b.distinct => View::Distinct(args),
+                    AstView::App{f, args} if *f == b.imply => View::Imply(args),
+                    _ => View::Atom(t),
+                }
+            }
+        }
+
+        fn mk_formula(&mut self, v: View<AST>) -> AST {
+            let sb = Some(self.b.bool_);
+            match v {
+                View::Atom(t) => t,
+                View::Bool(true) => self.b.true_,
+                View::Bool(false) => self.b.false_,
+                View::Not(t) => self.m.mk_app(self.b.not_, &[t], sb),
+                View::And(args) if args.len() == 1 => args[0],
+                View::And(args) => self.m.mk_app(self.b.and_, args, sb),
+                View::Or(args) if args.len() == 1 => args[0],
+                View::Or(args) => self.m.mk_app(self.b.or_, args, sb),
+                View::Ite(a, b, c) => self.m.mk_app(self.b.ite, &[a, b, c], sb),
+                View::Eq(a, b) => self.m.mk_app(self.b.eq, &[a, b], sb),
+                View::Distinct(args) => self.m.mk_app(self.b.distinct, args, sb),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn formula_kind(&self, t: AST) -> FormulaKind {
+            *self.n_kind.borrow_mut().entry(t).or_insert(0) += 1;
+            self.view_as_formula(t).kind()
+        }
+    }
+
+    impl Ctx {
+        pub fn new() -> Self {
+            let mut m = M::new();
+            let bool_ = m.mk_str("Bool", None);
+            let b = Builtins {
+                true_: m.mk_str("true", Some(bool_)),
+                false_: m.mk_str("false", Some(bool_)),
+                not_: m.mk_str("not", None),
+                and_: m.mk_str("and", None),
+                or_: m.mk_str("or", None),
+                ite: m.mk_str("ite", None),
+                eq: m.mk_str("=", None),
+                distinct: m.mk_str("distinct", None),
+                imply: m.mk_str("=>", None),
+                bool_,
+            };
+            Ctx { m, b, n_kind: RefCell::new(FxHashMap::default()) }
+        }
+    }
+}
+
+/// Only needs to unfold negations.
+struct NoLitMap(LitMapBuiltins);
+
+impl LitMap<IntLit> for NoLitMap {
+    fn new(b: LitMapBuiltins) -> Self { NoLitMap(b) }
+    fn b(&self) -> &LitMapBuiltins { &self.0 }
+    fn get_term<M>(&self, _: &M, _: &AST, _: bool) -> Option<IntLit> { unimplemented!() }
+    fn get_term_or_else<M, F>(&mut self, _: &M, _: &AST, _: bool, _: bool, _: F) -> IntLit
+        where F: FnOnce() -> IntLit
+    { unimplemented!() }
+    fn map_lit(&self, _: IntLit) -> Option<(AST, bool)> { unimplemented!() }
+}
+
+// `t_{i+1} = or(and(t_i, x_{i+1}), not(t_i))`: each `t_i` is shared
+#[test]
+fn test_kind_computed_once() {
+    use batsmt_core::gc::HasInternalMemory;
+
+    let n = 50;
+    let mut c = Ctx::new();
+    let b = c.b.clone();
+    let sb = Some(b.bool_);
+    let mut t = c.m.mk_str("x0", sb);
+    for i in 1 .. n {
+        let x = c.m.mk_str(&format!("x{}", i), sb);
+        let a = c.m.mk_app(b.and_, &[t, x], sb);
+        let not_t = c.m.mk_app(b.not_, &[t], sb);
+        t = c.m.mk_app(b.or_, &[a, not_t], sb);
+    }
+
+    let mut lm = NoLitMap::new(LitMapBuiltins{
+        bool_: b.bool_, true_: b.true_, false_: b.false_, not_: b.not_});
+    let mut ts = Tseitin::new();
+    let u = ts.simplify(&mut c, t);
+    let n_clauses = ts.clauses(&mut c, &mut lm, t).0.count();
+    assert!(n_clauses > n);
+    assert_eq!(u, ts.simplify(&mut c, t));
+    // the cache survives reclaiming memory, e.g. before each `check-sat`,
+    // and `clear`, after which the subterms are defined again
+    ts.reclaim_unused_memory();
+    ts.clear();
+    assert_eq!(n_clauses, ts.clauses(&mut c, &mut lm, t).0.count());
+
+    let counts = c.n_kind.borrow();
+    assert!(counts.len() >= 3 * n, "only {} terms classified", counts.len());
+    for (t, k) in counts.iter() {
+        assert_eq!(*k, 1, "{} classified {} times", pp::pp1(&c.m, t), k);
+    }
+}
+
+// a term collected by the manager has its ID reused by a new term
+#[test]
+fn test_kind_after_gc() {
+    use batsmt_core::gc::GC;
+
+    let mut c = Ctx::new();
+    let b = c.b.clone();
+    let sb = Some(b.bool_);
+    let p = c.m.mk_str("p", sb);
+    let t = c.m.mk_app(b.not_, &[p], sb);
+
+    let mut lm = NoLitMap::new(LitMapBuiltins{
+        bool_: b.bool_, true_: b.true_, false_: b.false_, not_: b.not_});
+    let mut ts = Tseitin::new();
+    assert_eq!(t, ts.simplify(&mut c, t));
+    assert_eq!(1, c.n_kind.borrow()[&t]);
+
+    let Builtins{bool_, true_, false_, not_, and_, or_, ite, eq, distinct, imply} = b;
+    for u in &[bool_, true_, false_, not_, and_, or_, ite, eq, distinct, imply, p] {
+        c.m.mark_root(u);
+    }
+    assert_eq!(1, c.m.collect());
+    ts.on_gc();
+
+    // `q` is an atom, not a negation
+    let f = c.m.mk_str("f", None);
+    let q = c.m.mk_app(f, &[p], sb);
+    assert_eq!(t, q, "the ID of `not p` is not reused");
+    assert_eq!(q, ts.simplify(&mut c, q));
+    assert_eq!(2, c.n_kind.borrow()[&q]);
+    let cs: Vec<_> = ts.clauses(&mut c, &mut lm, q).0.map(|c| c.to_vec()).collect();
+    assert_eq!(vec![vec![theory::TheoryLit::new_t(q, true)]], cs);
+}
+
+mod clause_batch {
+    use {super::*, batsmt_tseitin::ClauseBatch, batsmt_theory::TheoryLit};
+
+    fn lm(b: &Builtins) -> NoLitMap {
+        NoLitMap::new(LitMapBuiltins{
+            bool_: b.bool_, true_: b.true_, false_: b.false_, not_: b.not_})
+    }
+
+    // `(a ∧ b) ∨ c`, `(a ∨ d) ∧ ¬c`
+    fn formulas(c: &mut Ctx) -> (AST, AST) {
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let (a, b_, c_, d) =
+            (c.m.mk_str("a", sb), c.m.mk_str("b", sb), c.m.mk_str("c", sb), c.m.mk_str("d", sb));
+        let ab = c.m.mk_app(b.and_, &[a, b_], sb);
+        let f1 = c.m.mk_app(b.or_, &[ab, c_], sb);
+        let ad = c.m.mk_app(b.or_, &[a, d], sb);
+        let not_c = c.m.mk_app(b.not_, &[c_], sb);
+        let f2 = c.m.mk_app(b.and_, &[ad, not_c], sb);
+        (f1, f2)
+    }
+
+    fn to_vecs(batch: &ClauseBatch<Ctx>) -> Vec<Vec<TheoryLit<Ctx>>> {
+        batch.iter().map(|c| c.to_vec()).collect()
+    }
+
+    #[test]
+    fn test_send() {
+        fn is_send<T: Send>() {}
+        is_send::<ClauseBatch<Ctx>>();
+    }
+
+    #[test]
+    fn test_same_as_borrowed() {
+        let mut c = Ctx::new();
+        let mut lm = lm(&c.b);
+        let (f1, _) = formulas(&mut c);
+        let expected: Vec<Vec<_>> = {
+            let mut ts = Tseitin::new();
+            let cs = ts.clauses(&mut c, &mut lm, f1).0;
+            cs.map(|c| c.to_vec()).collect()
+        };
+        let mut ts = Tseitin::new();
+        let batch = ts.clauses_owned(&mut c, &mut lm, f1).unwrap();
+        assert_eq!(batch.len(), expected.len());
+        assert_eq!(batch.into_iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_recycle_round_trip() {
+        let mut c = Ctx::new();
+        let mut lm = lm(&c.b);
+        let (f1, _) = formulas(&mut c);
+        let mut ts = Tseitin::new();
+        let batch = ts.clauses_owned(&mut c, &mut lm, f1).unwrap();
+        let cs1 = to_vecs(&batch);
+        ts.recycle(batch);
+
+        // encode again from scratch, reusing the batch's storage
+        ts.clear();
+        let batch = ts.clauses_owned(&mut c, &mut lm, f1).unwrap();
+        assert_eq!(to_vecs(&batch), cs1);
+        ts.recycle(batch);
+    }
+
+    #[test]
+    fn test_back_to_back() {
+        let mut c = Ctx::new();
+        let mut lm = lm(&c.b);
+        let (f1, f2) = formulas(&mut c);
+        let expected2: Vec<Vec<_>> = {
+            let mut ts = Tseitin::new();
+            let _ = ts.clauses(&mut c, &mut lm, f1);
+            let cs = ts.clauses(&mut c, &mut lm, f2).0;
+            cs.map(|c| c.to_vec()).collect()
+        };
+
+        let mut ts = Tseitin::new();
+        let b1 = ts.clauses_owned(&mut c, &mut lm, f1).unwrap();
+        let cs1 = to_vecs(&b1);
+        let b2 = ts.clauses_owned(&mut c, &mut lm, f2).unwrap();
+        // `b1` is still alive and untouched
+        assert_eq!(to_vecs(&b1), cs1);
+        assert_eq!(to_vecs(&b2), expected2);
+        assert!(b1.len() > 0 && b2.len() > 0);
+        ts.recycle(b1);
+        ts.recycle(b2);
+    }
+
+    // `true` needs no clause, `false` is the empty clause, even after simplification
+    #[test]
+    fn test_constant_formulas() {
+        let mut c = Ctx::new();
+        let mut lm = lm(&c.b);
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let not_true = c.m.mk_app(b.not_, &[b.true_], sb);
+        let true2 = c.m.mk_app(b.and_, &[b.true_, b.true_], sb);
+        let false2 = c.m.mk_app(b.or_, &[b.false_, not_true], sb);
+
+        let mut ts = Tseitin::new();
+        for &t in &[b.true_, true2] {
+            assert_eq!(ts.clauses(&mut c, &mut lm, t).0.count(), 0);
+            let batch = ts.clauses_owned(&mut c, &mut lm, t).unwrap();
+            assert_eq!(batch.len(), 0);
+            ts.recycle(batch);
+        }
+        for &t in &[b.false_, not_true, false2] {
+            let batch = ts.clauses_owned(&mut c, &mut lm, t).unwrap();
+            assert_eq!(to_vecs(&batch), vec![vec![]]);
+            ts.recycle(batch);
+        }
+    }
+
+    // `(a ∧ b)` is shared by both disjunctions
+    #[test]
+    fn test_sharing_ratio() {
+        let mut c = Ctx::new();
+        let mut lm = lm(&c.b);
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let atoms: Vec<AST> = ["a", "b", "c", "d"].iter().map(|s| c.m.mk_str(s, sb)).collect();
+        let g = c.m.mk_app(b.and_, &atoms[0..2], sb);
+        let h = c.m.mk_app(b.or_, &[g, atoms[2]], sb);
+        let k = c.m.mk_app(b.or_, &[g, atoms[3]], sb);
+        let f = c.m.mk_app(b.and_, &[h, k], sb);
+
+        let mut ts = Tseitin::new();
+        assert_eq!(ts.stats().sharing_ratio(), 1.);
+        let _ = ts.clauses(&mut c, &mut lm, f);
+        // `and, or, a, b, c, d, g, h, k, f`; `g` and `h` (resp. `k`) have 4 and 7
+        // occurrences as trees, including the function symbol.
+        assert_eq!(ts.stats().encoded_subterms, 10);
+        assert_eq!(ts.stats().subterm_occurrences, 1 + 1 + 7 + 7);
+        assert_eq!(ts.stats().sharing_ratio(), 10. / 16.);
+
+        // nothing new to encode the second time
+        let _ = ts.clauses(&mut c, &mut lm, f);
+        assert_eq!(ts.stats().sharing_ratio(), 10. / 32.);
+    }
+
+    // `x` has sort `U`, it cannot be used as a formula
+    #[test]
+    fn test_ill_sorted() {
+        let mut c = Ctx::new();
+        let mut lm = lm(&c.b);
+        let (f1, _) = formulas(&mut c);
+        let ty_u = c.m.mk_str("U", None);
+        let x = c.m.mk_str("x", Some(ty_u));
+        let sb = Some(c.b.bool_);
+        let bad = c.m.mk_app(c.b.or_, &[f1, x], sb);
+
+        let mut ts = Tseitin::new();
+        for &t in &[x, bad] {
+            let msg = "`x` has sort `U`, expected `Bool`";
+            assert_eq!(ts.check_formula(&c, &t).map_err(|e| e.to_string()), Err(msg.to_string()));
+            match ts.clauses_owned(&mut c, &mut lm, t) {
+                Err(e) => assert_eq!(e.to_string(), msg),
+                Ok(_) => panic!("{} should be rejected", pp::pp1(&c.m, &t)),
+            }
+        }
+        assert!(ts.check_formula(&c, &f1).is_ok());
+    }
+
+    #[test]
+    fn test_bool_term() {
+        let mut c = Ctx::new();
+        let mut lm = lm(&c.b);
+        let (f1, _) = formulas(&mut c);
+        let ty_u = c.m.mk_str("U", None);
+        let x = c.m.mk_str("x", Some(ty_u));
+        let sb = Some(c.b.bool_);
+        let bad = c.m.mk_app(c.b.or_, &[f1, x], sb);
+
+        assert_eq!(BoolTerm::new(&c, x), None);
+        assert_eq!(BoolTerm::new(&c, c.b.bool_), None);
+        let t1 = BoolTerm::new(&c, f1).expect("f1 is a formula");
+        assert_eq!(t1.term(), f1);
+
+        let expected: Vec<Vec<_>> =
+            Tseitin::new().clauses(&mut c, &mut lm, f1).0.map(|cl| cl.to_vec()).collect();
+        let mut ts = Tseitin::new();
+        let cs: Vec<Vec<_>> =
+            ts.clauses_bool(&mut c, &mut lm, t1).unwrap().0.map(|cl| cl.to_vec()).collect();
+        assert_eq!(expected, cs);
+
+        // only the top-level sort is checked
+        let t_bad = BoolTerm::new(&c, bad).expect("bad is a disjunction");
+        assert!(ts.clauses_bool(&mut c, &mut lm, t_bad).is_err());
+    }
+
+    // `p`, `x = five`, and `(¬p ∧ a_1 ∧ … ∧ a_n) ∨ ¬(x = five) ∨ r`, which is just `r`
+    #[test]
+    fn test_units_collapse_sibling() {
+        let mut c = Ctx::new();
+        let mut lm = lm(&c.b);
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let ty_u = c.m.mk_str("U", None);
+        let (x, five) = (c.m.mk_str("x", Some(ty_u)), c.m.mk_str("five", Some(ty_u)));
+        let (p, r) = (c.m.mk_str("p", sb), c.m.mk_str("r", sb));
+        let mut conj = vec![c.m.mk_app(b.not_, &[p], sb)];
+        conj.extend((0 .. 50).map(|i| c.m.mk_str(&format!("a_{}", i), sb)));
+        let big_and = c.m.mk_app(b.and_, &conj, sb);
+        let x_five = c.m.mk_app(b.eq, &[x, five], sb);
+        let not_x_five = c.m.mk_app(b.not_, &[x_five], sb);
+        let big = c.m.mk_app(b.or_, &[big_and, not_x_five, r], sb);
+        let pb = [p, x_five, big];
+
+        let n_plain = {
+            let mut ts = Tseitin::new();
+            ts.clauses_many(&mut c, &mut lm, &pb).unwrap().len()
+        };
+        assert!(n_plain > 50, "{} clauses", n_plain);
+
+        let mut ts = Tseitin::new();
+        ts.set_propagate_units(true);
+        let batch = ts.clauses_many(&mut c, &mut lm, &pb).unwrap();
+        assert_eq!(ts.stats().units, 2);
+        let mut cs = to_vecs(&batch);
+        cs.sort_by_key(|c| format!("{:?}", c));
+        let mut expected = vec![
+            vec![TheoryLit::new_t(r, true)],
+            vec![TheoryLit::new_t(p, true)],
+            vec![TheoryLit::new_t(x_five, true)],
+        ];
+        expected.sort_by_key(|c| format!("{:?}", c));
+        assert_eq!(cs, expected);
+    }
+
+    // `p` and `¬p` are found unsat without encoding anything
+    #[test]
+    fn test_units_unsat() {
+        let mut c = Ctx::new();
+        let mut lm = lm(&c.b);
+        let (f1, f2) = formulas(&mut c);
+        let sb = Some(c.b.bool_);
+        let p = c.m.mk_str("p", sb);
+        let not_p = c.m.mk_app(c.b.not_, &[p], sb);
+        let p_f1 = c.m.mk_app(c.b.and_, &[p, f1], sb);
+
+        let mut ts = Tseitin::new();
+        ts.set_propagate_units(true);
+        let batch = ts.clauses_many(&mut c, &mut lm, &[p_f1, f2, not_p]).unwrap();
+        assert_eq!(to_vecs(&batch), vec![vec![]]);
+        assert_eq!(ts.stats().unsat_batches, 1);
+        assert_eq!(ts.stats().encoded_subterms, 0);
+    }
+
+    // the subterms of `f2` shared with `f1` are not counted again
+    #[test]
+    fn test_stats_diff() {
+        let mut c = Ctx::new();
+        let mut lm = lm(&c.b);
+        let (f1, f2) = formulas(&mut c);
+        let mut ts = Tseitin::new();
+        let batch = ts.clauses_owned(&mut c, &mut lm, f1).unwrap();
+        let st1 = ts.stats().snapshot();
+        assert_eq!((st1.def_lits, st1.clauses, st1.atoms), (2, batch.len() as u64, 3));
+        ts.recycle(batch);
+
+        let batch = ts.clauses_owned(&mut c, &mut lm, f2).unwrap();
+        let d = ts.stats().diff(&st1);
+        assert_eq!((d.def_lits, d.clauses, d.atoms), (2, batch.len() as u64, 1));
+        assert_eq!(d.clauses, 7);
+        assert_eq!(ts.stats().def_lits, 4);
+    }
+}
+
+// connectives under applications are connected to `true` and `false`
+mod bool_args {
+    use {super::*, batsmt_theory::TheoryLit};
+
+    #[test]
+    fn test_connect_once() {
+        let mut c = Ctx::new();
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let mut lm = NoLitMap::new(LitMapBuiltins{
+            bool_: b.bool_, true_: b.true_, false_: b.false_, not_: b.not_});
+        let u = c.m.mk_str("U", None);
+        let (f, g) = (c.m.mk_str("f", None), c.m.mk_str("g", None));
+        let (p, q, r) = (c.m.mk_str("p", sb), c.m.mk_str("q", sb), c.m.mk_str("r", sb));
+        let pq = c.m.mk_app(b.and_, &[p, q], sb);
+        let (f_pq, f_r) = (c.m.mk_app(f, &[pq], Some(u)), c.m.mk_app(f, &[r], Some(u)));
+        let eq = c.m.mk_app(b.eq, &[f_pq, f_r], sb);
+        let t1 = c.m.mk_app(b.not_, &[eq], sb);
+        let t2 = c.m.mk_app(g, &[pq], sb); // `p∧q` again, under another symbol
+
+        let pq_true = c.m.mk_app(b.eq, &[pq, b.true_], sb);
+        let pq_false = c.m.mk_app(b.eq, &[pq, b.false_], sb);
+        let lit_pq = TheoryLit::<Ctx>::new_t(pq, true);
+        let expected = vec![
+            vec![!lit_pq, TheoryLit::new_t(pq_true, true)],
+            vec![lit_pq, TheoryLit::new_t(pq_false, true)],
+        ];
+
+        let mut ts = Tseitin::new();
+        let cs1: Vec<Vec<_>> = ts.clauses(&mut c, &mut lm, t1).0.map(|c| c.to_vec()).collect();
+        for cl in expected.iter() {
+            assert!(cs1.contains(cl), "{:?} not in {:?}", cl, cs1);
+        }
+        // `r` is an atom, the congruence closure knows its value
+        let r_true = c.m.mk_app(b.eq, &[r, b.true_], sb);
+        assert!(!cs1.iter().flatten().any(|lit| matches!(lit, TheoryLit::T(t, _) if *t == r_true)), "{:?}", cs1);
+
+        let cs2: Vec<Vec<_>> = ts.clauses(&mut c, &mut lm, t2).0.map(|c| c.to_vec()).collect();
+        assert!(!expected.iter().any(|cl| cs2.contains(cl)), "{:?}", cs2);
+    }
+
+    #[test]
+    fn test_connect_ite() {
+        let mut c = Ctx::new();
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let mut lm = NoLitMap::new(LitMapBuiltins{
+            bool_: b.bool_, true_: b.true_, false_: b.false_, not_: b.not_});
+        let u = c.m.mk_str("U", None);
+        let f = c.m.mk_str("f", None);
+        let (p, q, r) = (c.m.mk_str("p", sb), c.m.mk_str("q", sb), c.m.mk_str("r", sb));
+        let cond = c.m.mk_str("c", sb);
+        let ite = c.m.mk_app(b.ite, &[cond, p, q], sb);
+        let (f_ite, f_r) = (c.m.mk_app(f, &[ite], Some(u)), c.m.mk_app(f, &[r], Some(u)));
+        let eq = c.m.mk_app(b.eq, &[f_ite, f_r], sb);
+        let t = c.m.mk_app(b.not_, &[eq], sb);
+
+        let ite_true = c.m.mk_app(b.eq, &[ite, b.true_], sb);
+        let ite_false = c.m.mk_app(b.eq, &[ite, b.false_], sb);
+        let lit_ite = TheoryLit::<Ctx>::new_t(ite, true);
+        let mut ts = Tseitin::new();
+        let (cs, lits) = ts.clauses(&mut c, &mut lm, t);
+        let cs: Vec<Vec<_>> = cs.map(|c| c.to_vec()).collect();
+        let lits: Vec<_> = lits.cloned().collect();
+        for cl in &[vec![!lit_ite, TheoryLit::new_t(ite_true, true)],
+                    vec![lit_ite, TheoryLit::new_t(ite_false, true)]] {
+            assert!(cs.contains(cl), "{:?} not in {:?}", cl, cs);
+        }
+        assert!(lits.contains(&lit_ite), "{:?}", lits);
+
+        // an `ite` on another sort is not a formula
+        let (a1, a2) = (c.m.mk_str("a1", Some(u)), c.m.mk_str("a2", Some(u)));
+        let ite_u = c.m.mk_app(b.ite, &[cond, a1, a2], Some(u));
+        let g = c.m.mk_str("g", None);
+        let t2 = c.m.mk_app(g, &[ite_u], sb);
+        let cs2: Vec<Vec<_>> = ts.clauses(&mut c, &mut lm, t2).0.map(|c| c.to_vec()).collect();
+        let ite_u_true = c.m.mk_app(b.eq, &[ite_u, b.true_], sb);
+        assert!(!cs2.iter().flatten().any(|lit| matches!(lit, TheoryLit::T(t, _) if *t == ite_u_true)), "{:?}", cs2);
+    }
+}
+
+mod budget {
+    use {
+        super::*, std::time::Duration, batsmt_theory::CancelToken,
+        batsmt_tseitin::{DEFAULT_MAX_NEW_NODES, SIMPLIFY_CHECK_PERIOD},
+    };
+
+    // `t_{i+1} = ite(c_i, and(t_i, true), or(false, t_i, x_i))`: both branches
+    // contain `t_i`, and every level needs rewriting.
+    fn doubling_ite(c: &mut Ctx, n: usize) -> (AST, Vec<AST>) {
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let mut atoms = vec!();
+        let mut t = c.m.mk_str("x0", sb);
+        atoms.push(t);
+        for i in 1 .. n {
+            let ci = c.m.mk_str(&format!("c{}", i), sb);
+            let xi = c.m.mk_str(&format!("x{}", i), sb);
+            atoms.push(ci);
+            atoms.push(xi);
+            let t1 = c.m.mk_app(b.and_, &[t, b.true_], sb);
+            let t2 = c.m.mk_app(b.or_, &[b.false_, t, xi], sb);
+            t = c.m.mk_app(b.ite, &[ci, t1, t2], sb);
+        }
+        (t, atoms)
+    }
+
+    /// Propositional oracle: evaluate `t` under `model`.
+    fn eval(c: &Ctx, model: &FxHashMap<AST, bool>, t: AST) -> bool {
+        use tseitin::Ctx as _;
+        match c.view_as_formula(t) {
+            View::Bool(b) => b,
+            View::Not(u) => !eval(c, model, u),
+            View::And(args) => args.iter().all(|u| eval(c, model, *u)),
+            View::Or(args) => args.iter().any(|u| eval(c, model, *u)),
+            View::Ite(a, b, c_) => {
+                if eval(c, model, a) { eval(c, model, b) } else { eval(c, model, c_) }
+            },
+            View::Atom(u) => model[&u],
+            _ => unimplemented!(),
+        }
+    }
+
+    /// Check that `t` and `u` agree on all the models over `atoms`.
+    fn check_equiv(c: &Ctx, atoms: &[AST], t: AST, u: AST) {
+        assert!(atoms.len() < 16);
+        for bits in 0 .. (1u32 << atoms.len()) {
+            let model: FxHashMap<_,_> =
+                atoms.iter().enumerate().map(|(i,a)| (*a, bits & (1 << i) != 0)).collect();
+            assert_eq!(eval(c, &model, t), eval(c, &model, u),
+                       "{} and {} differ", pp::pp1(&c.m, &t), pp::pp1(&c.m, &u));
+        }
+    }
+
+    #[test]
+    fn test_budget_caps_work() {
+        let n = 7;
+        let mut c = Ctx::new();
+        let (t, atoms) = doubling_ite(&mut c, n);
+
+        let mut ts_full = Tseitin::new();
+        let u_full = ts_full.simplify(&mut c, t);
+        let full = ts_full.stats().clone();
+        assert_eq!(full.partial_simplify, 0);
+        check_equiv(&c, &atoms, t, u_full);
+
+        let max = 5;
+        let mut ts = Tseitin::new();
+        ts.set_max_new_nodes(max);
+        let u = ts.simplify(&mut c, t);
+        let st = ts.stats().clone();
+        assert_eq!(st.partial_simplify, 1);
+        assert!(st.new_nodes < full.new_nodes, "{} vs {}", st, full);
+        // once out of budget, only the nodes being rebuilt on the stack are finished
+        assert!(st.new_nodes as usize <= max + 2 * n, "{}", st);
+        assert_ne!(u, u_full);
+        check_equiv(&c, &atoms, t, u);
+
+        // partial results are not cached: with a larger budget, we get the full result
+        ts.set_max_new_nodes(DEFAULT_MAX_NEW_NODES);
+        assert_eq!(u_full, ts.simplify(&mut c, t));
+        assert_eq!(ts.stats().partial_simplify, 1);
+    }
+
+    // `and(or(false, x_1), …, or(false, x_n))`: each argument needs rewriting
+    fn wide_and(c: &mut Ctx, n: usize) -> AST {
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let args: Vec<AST> = (0 .. n).map(|i| {
+            let x = c.m.mk_str(&format!("x{}", i), sb);
+            c.m.mk_app(b.or_, &[b.false_, x], sb)
+        }).collect();
+        c.m.mk_app(b.and_, &args, sb)
+    }
+
+    #[test]
+    fn test_time_limit_partial() {
+        let n = 8;
+        let mut c = Ctx::new();
+        let (t, atoms) = doubling_ite(&mut c, n);
+        let u_full = Tseitin::new().simplify(&mut c, t);
+
+        let mut ts = Tseitin::new();
+        ts.set_simplify_time_limit(Some(Duration::from_secs(0)));
+        let u = ts.simplify(&mut c, t);
+        assert!(ts.simplify_interrupted());
+        assert_eq!(ts.stats().interrupted_simplify, 1);
+        assert_eq!(ts.stats().partial_simplify, 1);
+        assert_ne!(u, u_full);
+        check_equiv(&c, &atoms, t, u);
+    }
+
+    #[test]
+    fn test_canceled_stops_early() {
+        let n = 10_000;
+        let mut c = Ctx::new();
+        let t = wide_and(&mut c, n);
+
+        let tok = CancelToken::new();
+        tok.cancel();
+        let mut ts = Tseitin::new();
+        ts.set_cancel_token(tok.clone());
+        ts.simplify(&mut c, t);
+        assert!(ts.simplify_interrupted());
+        // only the arguments before the first check, and the conjunction, are rebuilt
+        assert!(ts.stats().new_nodes as usize <= SIMPLIFY_CHECK_PERIOD + 1, "{}", ts.stats());
+
+        // once the token is reset, the cached results are reused, and the rest is done
+        tok.reset();
+        let u = ts.simplify(&mut c, t);
+        assert!(!ts.simplify_interrupted());
+        assert_eq!(u, Tseitin::new().simplify(&mut c, t));
+    }
+
+    // a time limit that is not reached changes nothing
+    #[test]
+    fn test_time_limit_not_reached() {
+        let mut c = Ctx::new();
+        let (t, _) = doubling_ite(&mut c, 10);
+        let mut ts_ref = Tseitin::new();
+        let u_ref = ts_ref.simplify(&mut c, t);
+
+        let mut ts = Tseitin::new();
+        ts.set_simplify_time_limit(Some(Duration::from_secs(3600)));
+        ts.set_cancel_token(CancelToken::new());
+        assert_eq!(u_ref, ts.simplify(&mut c, t));
+        assert!(!ts.simplify_interrupted());
+        assert_eq!(ts.stats().interrupted_simplify, 0);
+        assert_eq!(ts.stats().new_nodes, ts_ref.stats().new_nodes);
+    }
+
+    // simplifying an already simplified formula does not rebuild anything
+    #[test]
+    fn test_simplified_is_shared() {
+        let mut c = Ctx::new();
+        let (t, _) = doubling_ite(&mut c, 10);
+        let u = Tseitin::new().simplify(&mut c, t);
+        assert_ne!(t, u);
+
+        let mut ts = Tseitin::new();
+        assert_eq!(u, ts.simplify(&mut c, u));
+        assert_eq!(ts.stats().new_nodes, 0, "{}", ts.stats());
+    }
+}
+
+mod simplify_cache {
+    use super::*;
+
+    // `or(and(x_i, true), not(not(y_i)), false)`
+    fn formula(c: &mut Ctx, i: usize) -> AST {
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let x = c.m.mk_str(&format!("x{}", i), sb);
+        let y = c.m.mk_str(&format!("y{}", i), sb);
+        let t1 = c.m.mk_app(b.and_, &[x, b.true_], sb);
+        let ny = c.m.mk_app(b.not_, &[y], sb);
+        let t2 = c.m.mk_app(b.not_, &[ny], sb);
+        c.m.mk_app(b.or_, &[t1, t2, b.false_], sb)
+    }
+
+    #[test]
+    fn test_cache_stays_under_cap() {
+        let n = 1_000;
+        let cap = 50;
+        let mut c = Ctx::new();
+        let ts: Vec<AST> = (0 .. n).map(|i| formula(&mut c, i)).collect();
+
+        let mut full = Tseitin::new();
+        let mut capped = Tseitin::new();
+        capped.set_simplify_cache_cap(cap);
+        for (i, &t) in ts.iter().enumerate() {
+            let u = capped.simplify(&mut c, t);
+            assert!(capped.simplify_cache_len() <= cap, "cache has {} entries", capped.simplify_cache_len());
+            assert_eq!(full.simplify(&mut c, t), u);
+            // recently simplified formulas are still cached
+            if i > 0 {
+                let new_nodes = capped.stats().new_nodes;
+                assert_eq!(u, capped.simplify(&mut c, t));
+                assert_eq!(new_nodes, capped.stats().new_nodes);
+            }
+        }
+        assert!(full.simplify_cache_len() > n);
+        assert!(capped.stats().simplify_evicted > 0, "{}", capped.stats());
+
+        // evicted results are recomputed, identically
+        for &t in ts.iter() {
+            assert_eq!(full.simplify(&mut c, t), capped.simplify(&mut c, t));
+        }
+
+        // lowering the cap evicts immediately
+        capped.set_simplify_cache_cap(10);
+        assert!(capped.simplify_cache_len() <= 10);
+    }
+}
+
+mod simplify_fixpoint {
+    use super::*;
+
+    // `or(and(a, not a), b)`: the first pass turns the `and` into `false`,
+    // the `or` is only reduced by the second one.
+    #[test]
+    fn test_two_passes() {
+        let mut c = Ctx::new();
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let a = c.m.mk_str("a", sb);
+        let bb = c.m.mk_str("b", sb);
+        let not_a = c.m.mk_app(b.not_, &[a], sb);
+        let and_ = c.m.mk_app(b.and_, &[a, not_a], sb);
+        let t = c.m.mk_app(b.or_, &[and_, bb], sb);
+
+        let mut ts = Tseitin::new();
+        let u = ts.simplify(&mut c, t);
+        assert_ne!(u, bb, "single pass: {}", pp::pp1(&c.m, &u));
+        assert_eq!(ts.stats().simplify_extra_passes, 0);
+
+        let mut ts = Tseitin::new();
+        ts.set_simplify_fixpoint(true);
+        assert_eq!(bb, ts.simplify(&mut c, t));
+        let extra = ts.stats().simplify_extra_passes;
+        assert_eq!(extra, 2, "{}", ts.stats()); // the last pass changes nothing
+
+        // the final result is cached
+        let new_nodes = ts.stats().new_nodes;
+        assert_eq!(bb, ts.simplify(&mut c, t));
+        assert_eq!(ts.stats().simplify_extra_passes, extra + 1);
+        assert_eq!(ts.stats().new_nodes, new_nodes);
+    }
+}
+
+#[cfg(feature="simp-trace")]
+mod simp_trace {
+    use {super::*, batsmt_tseitin::SimpRule};
+
+    const ALL_RULES: [SimpRule; 21] = {
+        use SimpRule::*;
+        [AppArgs, DistinctUnit, DistinctPair, DistinctExpand, EqRefl, NotBool, NotNot, NotArg,
+         FlattenAnd, AndFalse, AndComplementary, AndTrue, FlattenOr, OrTrue, OrComplementary,
+         OrFalse, ImplyToOr, FoldIte, IteSame, IteArgs, Fixpoint]
+    };
+
+    // one formula per rule, at least
+    fn formulas(c: &mut Ctx) -> Vec<AST> {
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let u = c.m.mk_str("U", None);
+        let (x, y, z) = (c.m.mk_str("x", Some(u)), c.m.mk_str("y", Some(u)), c.m.mk_str("z", Some(u)));
+        let (p, q, r) = (c.m.mk_str("p", sb), c.m.mk_str("q", sb), c.m.mk_str("r", sb));
+        let f = c.m.mk_str("f", None);
+        let m = &mut c.m;
+        let mut app = |f: AST, args: &[AST]| m.mk_app(f, args, sb);
+
+        let p_true = app(b.and_, &[p, b.true_]);
+        let not_p = app(b.not_, &[p]);
+        let q_or_r = app(b.or_, &[q, r]);
+        let q_and_r = app(b.and_, &[q, r]);
+        let p_and_not_p = app(b.and_, &[p, not_p]);
+        vec![
+            app(f, &[p_true]),
+            app(b.distinct, &[x]),
+            app(b.distinct, &[x, y]),
+            app(b.distinct, &[x, y, z]),
+            app(b.eq, &[x, x]),
+            app(b.not_, &[b.true_]),
+            app(b.not_, &[not_p]),
+            app(b.not_, &[p_true]),
+            app(b.and_, &[p, q_and_r]),
+            app(b.and_, &[p, b.false_]),
+            app(b.and_, &[q, p, not_p]),
+            app(b.and_, &[b.true_, b.true_]),
+            app(b.or_, &[p, q_or_r]),
+            app(b.or_, &[p, b.true_]),
+            app(b.or_, &[not_p, q, p]),
+            app(b.or_, &[b.false_, b.false_]),
+            app(b.imply, &[p, q, r]),
+            app(b.ite, &[b.true_, p, q]),
+            app(b.ite, &[b.false_, p, q]),
+            app(b.ite, &[p, q_or_r, q_or_r]),
+            app(b.ite, &[p, p_true, q]),
+            app(b.or_, &[p_and_not_p, q]), // needs two passes
+        ]
+    }
+
+    fn traced(c: &mut Ctx) -> tseitin::simp_trace::Trace {
+        let ts = formulas(c);
+        let mut tseitin = Tseitin::new();
+        tseitin.set_simplify_fixpoint(true);
+        tseitin.set_simp_trace(true);
+        for t in ts {
+            tseitin.simplify(c, t);
+        }
+        tseitin.simp_trace().unwrap().clone()
+    }
+
+    #[test]
+    fn test_all_rules_checked() {
+        let mut c = Ctx::new();
+        let tr = traced(&mut c);
+        for rule in ALL_RULES.iter() {
+            assert!(tr.steps.iter().any(|st| st.rule == *rule), "{} never fired", rule);
+        }
+        match tr.check(&mut c) {
+            Ok(n_unchecked) => assert_eq!(0, n_unchecked),
+            Err(e) => panic!("{}\n{}", e, pp::pp1(&tr, &c)),
+        }
+
+        let s = pp::pp1(&tr, &c).to_string();
+        // long steps are broken over several, indented lines
+        assert_eq!(tr.len(), s.lines().filter(|l| l.starts_with('(')).count(), "{}", s);
+        assert!(s.contains("(fold-ite (ite true p q) p)"), "{}", s);
+    }
+
+    #[test]
+    fn test_corrupted_step_rejected() {
+        let mut c = Ctx::new();
+        let tr = traced(&mut c);
+        let find = |rule| tr.steps.iter().position(|st| st.rule == rule).unwrap();
+        let (p, q) = (c.m.mk_str("p", Some(c.b.bool_)), c.m.mk_str("q", Some(c.b.bool_)));
+
+        // structural: the wrong branch
+        let i = find(SimpRule::FoldIte);
+        let mut bad = tr.clone();
+        bad.steps[i].to = if bad.steps[i].to == p { q } else { p };
+        assert_eq!(i, bad.check(&mut c).unwrap_err().step);
+
+        // by evaluation: `and(q, p, not p)` is not `true`
+        let i = find(SimpRule::AndComplementary);
+        let mut bad = tr.clone();
+        bad.steps[i].to = c.b.true_;
+        let err = bad.check(&mut c).unwrap_err();
+        assert_eq!(i, err.step, "{}", err);
+
+        // a step under the wrong rule
+        let i = find(SimpRule::NotNot);
+        let mut bad = tr.clone();
+        bad.steps[i].rule = SimpRule::IteSame;
+        assert_eq!(i, bad.check(&mut c).unwrap_err().step);
+    }
+}
+
+mod memory {
+    use {super::*, batsmt_core::{gc::HasInternalMemory, mem}};
+
+    // `(x_i ∧ y_i) ∨ ¬z_i ∨ (x_{i+1} ∧ ¬y_i)`
+    fn formula(c: &mut Ctx, i: usize) -> AST {
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let mut atom = |s: &str, i: usize| c.m.mk_string(format!("{}{}", s, i), sb);
+        let (x, y, z, x2) = (atom("x", i), atom("y", i), atom("z", i), atom("x", i+1));
+        let xy = c.m.mk_app(b.and_, &[x, y], sb);
+        let nz = c.m.mk_app(b.not_, &[z], sb);
+        let ny = c.m.mk_app(b.not_, &[y], sb);
+        let xny = c.m.mk_app(b.and_, &[x2, ny], sb);
+        c.m.mk_app(b.or_, &[xy, nz, xny], sb)
+    }
+
+    #[test]
+    fn test_reclaim_reduces_report() {
+        let mut c = Ctx::new();
+        let b = c.b.clone();
+        let mut lm = NoLitMap::new(LitMapBuiltins{
+            bool_: b.bool_, true_: b.true_, false_: b.false_, not_: b.not_});
+        let ts: Vec<AST> = (0 .. 2_000).map(|i| formula(&mut c, i)).collect();
+
+        let mut tseitin = Tseitin::new();
+        for &t in ts.iter() {
+            let t = tseitin.simplify(&mut c, t);
+            let batch = tseitin.clauses_owned(&mut c, &mut lm, t).unwrap();
+            tseitin.recycle(batch);
+        }
+        let r1 = mem::report("tseitin", &tseitin);
+        for name in &["caches/simplify", "caches/kinds", "caches/iter", "clauses/spare"] {
+            assert!(r1.get(name).unwrap().total() > 0, "{}: empty\n{}", name, r1);
+        }
+
+        tseitin.reclaim_unused_memory();
+        let r2 = mem::report("tseitin", &tseitin);
+        assert!(r2.total() < r1.total(), "before:\n{}after:\n{}", r1, r2);
+        // kinds are still valid, they are kept
+        assert!(r2.get("caches/kinds").unwrap().total() > 0, "{}", r2);
+        let spare = |r: &mem::MemTree| r.get("clauses/spare").unwrap().total();
+        assert!(spare(&r2) < spare(&r1), "before:\n{}after:\n{}", r1, r2);
+    }
+
+    // the storage of the clauses shrinks to those of the last formula,
+    // the clauses and literals themselves are kept
+    #[test]
+    fn test_reclaim_shrinks_clauses() {
+        let mut c = Ctx::new();
+        let b = c.b.clone();
+        let mut lm = NoLitMap::new(LitMapBuiltins{
+            bool_: b.bool_, true_: b.true_, false_: b.false_, not_: b.not_});
+        let ts: Vec<AST> = (0 .. 2_000).map(|i| formula(&mut c, i)).collect();
+        let t = c.m.mk_app(b.and_, &ts, Some(b.bool_));
+
+        let mut tseitin = Tseitin::new();
+        let n = tseitin.clauses(&mut c, &mut lm, t).0.count();
+        assert!(n > 2_000);
+        // already defined, only the unit clause is left
+        let lits_of = |tseitin: &mut Tseitin<Ctx>, c: &mut Ctx, lm: &mut NoLitMap| {
+            let (cs, lits) = tseitin.clauses(c, lm, ts[0]);
+            assert_eq!(cs.count(), 1);
+            lits.cloned().collect::<Vec<_>>()
+        };
+        let lits = lits_of(&mut tseitin, &mut c, &mut lm);
+        assert!(lits.len() > 2_000);
+
+        let current = |r: &mem::MemTree| r.get("clauses/current").unwrap().total();
+        let r1 = mem::report("tseitin", &tseitin);
+        tseitin.reclaim_unused_memory();
+        let r2 = mem::report("tseitin", &tseitin);
+        assert!(current(&r2) * 100 < current(&r1), "before:\n{}after:\n{}", r1, r2);
+        assert_eq!(lits, lits_of(&mut tseitin, &mut c, &mut lm));
+    }
+}
+
+mod definition_order {
+    use {
+        super::*, std::sync::Arc,
+        batsmt_core::ast::iter_dag::{self, Order},
+        batsmt_tseitin::DefinitionOrder, batsmt_theory::TheoryLit,
+    };
+
+    // `(x ∧ y) ∨ ¬(z ∨ (x ∧ y)) ∨ (y ∧ ¬z)`, with `x ∧ y` shared
+    fn formula(c: &mut Ctx) -> AST {
+        let b = c.b.clone();
+        let sb = Some(b.bool_);
+        let (x, y, z) = (c.m.mk_str("x", sb), c.m.mk_str("y", sb), c.m.mk_str("z", sb));
+        let xy = c.m.mk_app(b.and_, &[x, y], sb);
+        let z_xy = c.m.mk_app(b.or_, &[z, xy], sb);
+        let n = c.m.mk_app(b.not_, &[z_xy], sb);
+        let nz = c.m.mk_app(b.not_, &[z], sb);
+        let ynz = c.m.mk_app(b.and_, &[y, nz], sb);
+        c.m.mk_app(b.or_, &[xy, n, ynz], sb)
+    }
+
+    // the literals follow the definition order, not the hash of the atoms
+    #[test]
+    fn test_lits_in_definition_order() {
+        for o in vec![Order::PreOrder, Order::PostOrder] {
+            let mut c = Ctx::new();
+            let b = c.b.clone();
+            let mut lm = NoLitMap::new(LitMapBuiltins{
+                bool_: b.bool_, true_: b.true_, false_: b.false_, not_: b.not_});
+            let sb = Some(b.bool_);
+            let ps: Vec<AST> = (0 .. 40).map(|i| c.m.mk_string(format!("p{}", i), sb)).collect();
+            let mut ts = Tseitin::new();
+            ts.set_definition_order(DefinitionOrder::Traversal(o));
+
+            // atoms of the simplified formulas, in the order of the traversal
+            let mut expected = vec!();
+            let mut iter = iter_dag::new();
+            for chunk in ps.chunks(10).rev() {
+                let args: Vec<AST> = chunk.chunks(3)
+                    .map(|sub| c.m.mk_app(b.and_, sub, sb)).collect();
+                let t = c.m.mk_app(b.or_, &args, sb);
+                let s = ts.simplify(&mut c, t);
+                iter.iter_ordered(&c.m, &s, o, |_, u| {
+                    if ps.contains(u) { expected.push(*u) }
+                });
+
+                let lits: Vec<AST> = ts.clauses(&mut c, &mut lm, t).1.map(|lit| match lit {
+                    TheoryLit::T(t, true) => *t,
+                    lit => panic!("unexpected literal {:?}", lit),
+                }).collect();
+                assert_eq!(expected, lits, "order {:?}", o);
+            }
+        }
+    }
+
+    // clauses of `formula`, in the order of the encoding
+    fn encode(o: DefinitionOrder<Ctx>) -> Vec<String> {
+        let mut c = Ctx::new();
+        let b = c.b.clone();
+        let mut lm = NoLitMap::new(LitMapBuiltins{
+            bool_: b.bool_, true_: b.true_, false_: b.false_, not_: b.not_});
+        let t = formula(&mut c);
+        let mut ts = Tseitin::new();
+        ts.set_definition_order(o);
+        let batch = ts.clauses_owned(&mut c, &mut lm, t).unwrap();
+        batch.iter().map(|cl| format!("{:?}", cl.to_vec())).collect()
+    }
+
+    #[test]
+    fn test_same_clauses() {
+        let pre = encode(DefinitionOrder::default());
+        let mut sorted_pre = pre.clone();
+        sorted_pre.sort();
+        let orders = vec![
+            DefinitionOrder::Traversal(Order::PostOrder),
+            DefinitionOrder::Traversal(Order::BreadthFirst),
+            DefinitionOrder::Key(Arc::new(|_: &Ctx, t: &AST| u64::MAX - t.idx() as u64)),
+        ];
+        let mut n_reordered = 0;
+        for o in orders {
+            let name = format!("{:?}", o);
+            let cs = encode(o);
+            if cs != pre { n_reordered += 1 }
+            // same clauses, hence equisatisfiable
+            let mut sorted = cs;
+            sorted.sort();
+            assert_eq!(sorted, sorted_pre, "order: {}", name);
+        }
+        assert!(n_reordered > 0);
+    }
+}
+
+// Run with `cargo test --release --features svec-stats -- --ignored --nocapture`.
+//
+// Each assertion of each problem in `benchs/` is turned into clauses.
+#[cfg(feature="svec-stats")]
+mod svec_report {
+    use {
+        super::*, std::{fs, collections::HashMap},
+        batsmt_parser::{Statement, simple_ast::{self, TermCell}},
+    };
+
+    // convert terms into `Ctx`, sharing them by address
+    struct Conv<'a> {
+        c: &'a mut Ctx,
+        terms: HashMap<*const TermCell, AST>,
+    }
+
+    impl<'a> Conv<'a> {
+        fn term(&mut self, t: &simple_ast::Term) -> AST {
+            let key = t.view() as *const TermCell;
+            if let Some(u) = self.terms.get(&key) { return *u }
+            let b = self.c.b.clone();
+            let u = match t.view() {
+                TermCell::App(f, args) => {
+                    let args: Vec<AST> = args.iter().map(|u| self.term(u)).collect();
+                    let ret = f.ret();
+                    let ty = Some(if ret.name() == "Bool" { b.bool_ } else { self.c.m.mk_str(ret.name(), None) });
+                    let f = match f.name() {
+                        "true" => return b.true_,
+                        "false" => return b.false_,
+                        "not" => b.not_,
+                        "and" => b.and_,
+                        "or" => b.or_,
+                        "=>" => b.imply,
+                        "=" => b.eq,
+                        "distinct" => b.distinct,
+                        name if args.is_empty() => return self.c.m.mk_str(name, ty),
+                        name => self.c.m.mk_str(name, None),
+                    };
+                    self.c.m.mk_app(f, &args, ty)
+                },
+                TermCell::Ite(a, b_, c_) => {
+                    let args = [self.term(a), self.term(b_), self.term(c_)];
+                    self.c.m.mk_app(b.ite, &args, None)
+                },
+                TermCell::Rational(num, den) => self.c.m.mk_str(&format!("{}/{}", num, den), None),
+            };
+            self.terms.insert(key, u);
+            u
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn report_benchs() {
+        let site = &tseitin::svec_sites::FLATTEN;
+        site.reset();
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../../benchs");
+        let mut files: Vec<_> = ["sat", "unsat"].iter()
+            .flat_map(|d| fs::read_dir(format!("{}/{}", dir, d)).unwrap())
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().map_or(false, |e| e == "smt2"))
+            .collect();
+        files.sort();
+        for file in files.iter() {
+            let s = fs::read_to_string(file).unwrap();
+            let stmts = simple_ast::parse_str(&s)
+                .unwrap_or_else(|e| panic!("cannot parse {:?}: {}", file, e));
+            let mut c = Ctx::new();
+            let b = c.b.clone();
+            let mut lm = NoLitMap::new(LitMapBuiltins{
+                bool_: b.bool_, true_: b.true_, false_: b.false_, not_: b.not_});
+            let mut ts = Tseitin::new();
+            let mut conv = Conv{c: &mut c, terms: HashMap::new()};
+            let asserts: Vec<AST> = stmts.iter().filter_map(|st| match st {
+                Statement::Assert(t) => Some(conv.term(t)),
+                _ => None,
+            }).collect();
+            let mut n_clauses = 0;
+            for t in asserts {
+                n_clauses += ts.clauses(&mut c, &mut lm, t).0.count();
+            }
+            println!("{}: {} clauses", file.file_name().unwrap().to_string_lossy(), n_clauses);
+        }
+        let caps = [1, 2, 3, 4, 5, 6, 8, 16];
+        println!("\n{}: {} uses, max {}, cap {}", site.name(), site.uses(), site.max_len(), site.inline_cap());
+        for &cap in caps.iter() {
+            println!("spills at cap {:>2}: {}", cap, site.spills_at(cap));
+        }
+    }
+}